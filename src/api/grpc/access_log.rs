@@ -0,0 +1,169 @@
+use std::net::SocketAddr;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use futures::future::BoxFuture;
+use tonic::codegen::http::{Request, Response};
+use tonic::transport::server::TcpConnectInfo;
+use tonic::Code;
+use tower::{Layer, Service};
+use tracing::{info, warn};
+
+use crate::observability::metrics::Metrics;
+
+/// Logs one line per RPC with its path, peer address, `grpc-status` and
+/// latency, and records the same in `grpc_requests_total` and
+/// `grpc_request_duration_seconds`. The status is the one known when the
+/// response starts, so a stream that fails after sending messages still
+/// counts as `Ok`.
+#[derive(Clone)]
+pub struct AccessLogLayer {
+    metrics: Metrics,
+}
+
+impl AccessLogLayer {
+    pub fn new(metrics: Metrics) -> Self {
+        Self { metrics }
+    }
+}
+
+impl<S> Layer<S> for AccessLogLayer {
+    type Service = AccessLog<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AccessLog {
+            inner,
+            metrics: self.metrics.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct AccessLog<S> {
+    inner: S,
+    metrics: Metrics,
+}
+
+impl<S, B, ResBody> Service<Request<B>> for AccessLog<S>
+where
+    S: Service<Request<B>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let rpc = request.uri().path().to_string();
+        let client: Option<SocketAddr> = request
+            .extensions()
+            .get::<TcpConnectInfo>()
+            .and_then(TcpConnectInfo::remote_addr);
+        let metrics = self.metrics.clone();
+        let started = Instant::now();
+        let response = self.inner.call(request);
+
+        Box::pin(async move {
+            let result = response.await;
+            let elapsed = started.elapsed().as_secs_f64();
+            let latency_ms = elapsed * 1000.0;
+
+            let code = match &result {
+                // Successful unary responses only carry grpc-status in the
+                // trailers; a status in the headers is an error.
+                Ok(response) => response
+                    .headers()
+                    .get("grpc-status")
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.parse::<i32>().ok())
+                    .map_or(Code::Ok, Code::from_i32),
+                Err(_) => Code::Unknown,
+            };
+            let grpc_status = code as i32;
+            match &result {
+                Ok(_) => info!(
+                    rpc,
+                    client = client.map(display),
+                    grpc_status,
+                    latency_ms,
+                    "grpc request"
+                ),
+                Err(_) => warn!(
+                    rpc,
+                    client = client.map(display),
+                    latency_ms,
+                    "grpc request failed"
+                ),
+            }
+
+            // Paths that match no RPC would otherwise each get their own series.
+            let rpc_label = if code == Code::Unimplemented {
+                "unknown"
+            } else {
+                rpc.as_str()
+            };
+            metrics.record_grpc_request(rpc_label, &format!("{code:?}"), elapsed);
+
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use tonic::codegen::http::{Request, Response};
+    use tower::{service_fn, Layer, ServiceExt};
+
+    use super::AccessLogLayer;
+    use crate::observability::metrics::Metrics;
+
+    async fn call(metrics: &Metrics, path: &str, grpc_status: Option<&str>) {
+        let status = grpc_status.map(str::to_string);
+        let service =
+            AccessLogLayer::new(metrics.clone()).layer(service_fn(move |_request: Request<()>| {
+                let status = status.clone();
+                async move {
+                    let mut response = Response::builder();
+                    if let Some(status) = status {
+                        response = response.header("grpc-status", status);
+                    }
+                    Ok::<_, Infallible>(response.body(()).unwrap())
+                }
+            }));
+        let request = Request::builder().uri(path).body(()).unwrap();
+        service.oneshot(request).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn counts_requests_by_rpc_and_code() {
+        let metrics = Metrics::new();
+        let get_order = "/dispatch.DispatchService/GetOrder";
+
+        call(&metrics, get_order, None).await;
+        call(&metrics, get_order, Some("5")).await;
+        call(&metrics, "/dispatch.DispatchService/Nope", Some("12")).await;
+
+        let count = |rpc: &str, code: &str| {
+            metrics
+                .grpc_requests_total
+                .with_label_values(&[rpc, code])
+                .get()
+        };
+        assert_eq!(count(get_order, "Ok"), 1);
+        assert_eq!(count(get_order, "NotFound"), 1);
+        assert_eq!(count("unknown", "Unimplemented"), 1);
+        assert_eq!(
+            metrics
+                .grpc_request_duration_seconds
+                .with_label_values(&[get_order])
+                .get_sample_count(),
+            2
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use tonic::service::Interceptor;
+use tonic::{Request, Status};
+
+use crate::auth::{constant_time_eq, JwtVerifier};
+
+/// Rejects RPCs that don't carry one of the configured API keys, either as
+/// `x-api-key: <key>` or `authorization: Bearer <key>`. With no keys
+/// configured every request is let through. Alongside JWT auth the key has to
+/// go in `x-api-key`, since `authorization` carries the token.
+#[derive(Clone, Default)]
+pub struct ApiKeyInterceptor {
+    keys: Arc<HashSet<String>>,
+}
+
+impl ApiKeyInterceptor {
+    pub fn new(keys: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            keys: Arc::new(keys.into_iter().collect()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.keys.is_empty()
+    }
+
+    fn is_valid(&self, presented: &str) -> bool {
+        self.keys
+            .iter()
+            .fold(false, |found, key| found | constant_time_eq(key, presented))
+    }
+}
+
+impl Interceptor for ApiKeyInterceptor {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        if !self.is_enabled() {
+            return Ok(request);
+        }
+
+        let metadata = request.metadata();
+        let presented = metadata
+            .get("x-api-key")
+            .and_then(|value| value.to_str().ok())
+            .or_else(|| {
+                metadata
+                    .get("authorization")
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.strip_prefix("Bearer "))
+            });
+
+        match presented {
+            Some(key) if self.is_valid(key.trim()) => Ok(request),
+            Some(_) => Err(Status::unauthenticated("invalid api key")),
+            None => Err(Status::unauthenticated("missing api key")),
+        }
+    }
+}
+
+/// Validates `authorization: Bearer <jwt>` and attaches the caller's
+/// [`Principal`](crate::auth::Principal) to the request, where handlers check
+/// roles against it. Without a verifier every request is let through.
+#[derive(Clone, Default)]
+pub struct JwtInterceptor {
+    verifier: Option<Arc<JwtVerifier>>,
+}
+
+impl JwtInterceptor {
+    pub fn new(verifier: Option<Arc<JwtVerifier>>) -> Self {
+        Self { verifier }
+    }
+}
+
+impl Interceptor for JwtInterceptor {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        let Some(verifier) = &self.verifier else {
+            return Ok(request);
+        };
+
+        let authorization = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok());
+        let principal = verifier.verify_bearer(authorization)?;
+        request.extensions_mut().insert(principal);
+        Ok(request)
+    }
+}
+
+/// Both checks, in the order the service needs them: the API key first, then
+/// the caller's token.
+#[derive(Clone, Default)]
+pub struct GrpcAuth {
+    pub api_keys: ApiKeyInterceptor,
+    pub jwt: JwtInterceptor,
+}
+
+impl Interceptor for GrpcAuth {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        let request = self.api_keys.call(request)?;
+        self.jwt.call(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tonic::service::Interceptor;
+    use tonic::{Code, Request};
+
+    use super::ApiKeyInterceptor;
+
+    fn request_with(header: &'static str, value: &'static str) -> Request<()> {
+        let mut request = Request::new(());
+        request
+            .metadata_mut()
+            .insert(header, value.parse().unwrap());
+        request
+    }
+
+    #[test]
+    fn disabled_without_keys() {
+        let mut auth = ApiKeyInterceptor::new(Vec::new());
+        assert!(auth.call(Request::new(())).is_ok());
+    }
+
+    #[test]
+    fn accepts_api_key_header_and_bearer_token() {
+        let mut auth = ApiKeyInterceptor::new(vec!["s3cret".to_string()]);
+        assert!(auth.call(request_with("x-api-key", "s3cret")).is_ok());
+        assert!(auth
+            .call(request_with("authorization", "Bearer s3cret"))
+            .is_ok());
+    }
+
+    #[test]
+    fn rejects_missing_or_wrong_key() {
+        let mut auth = ApiKeyInterceptor::new(vec!["s3cret".to_string()]);
+
+        let missing = auth.call(Request::new(())).unwrap_err();
+        assert_eq!(missing.code(), Code::Unauthenticated);
+
+        let wrong = auth.call(request_with("x-api-key", "guess")).unwrap_err();
+        assert_eq!(wrong.code(), Code::Unauthenticated);
+    }
+}
@@ -0,0 +1,450 @@
+// The deprecated `*_name` string fields are still written on responses and
+// read as a fallback on requests; everything touching them lives here.
+#![allow(deprecated)]
+
+use chrono::{DateTime, Utc};
+use tonic::Status;
+use uuid::Uuid;
+
+use crate::api::grpc::pb;
+use crate::api::grpc::pb::change_event::Entity;
+use crate::geo::zone_of;
+use crate::models::assignment::Assignment;
+use crate::models::courier::{Courier, CourierLocation, CourierStatus, GeoPoint, VehicleType};
+use crate::models::event::EntityChange;
+use crate::models::order::{
+    DeliveryOrder, Handling, OrderEvent, OrderItem, OrderStatus, Priority, WaitingReason,
+};
+
+pub fn geo_to_proto(p: &GeoPoint) -> pb::GeoPoint {
+    pb::GeoPoint {
+        lat: p.lat,
+        lng: p.lng,
+    }
+}
+
+pub fn geo_from_proto(p: pb::GeoPoint) -> GeoPoint {
+    GeoPoint {
+        lat: p.lat,
+        lng: p.lng,
+    }
+}
+
+pub fn courier_to_proto(c: &Courier) -> pb::CourierResponse {
+    pb::CourierResponse {
+        id: c.id.to_string(),
+        name: c.name.clone(),
+        location: Some(geo_to_proto(&c.location)),
+        capacity: c.capacity as u32,
+        current_load: c.current_load as u32,
+        capacity_units: c.capacity,
+        load_units: c.current_load,
+        status_name: format!("{:?}", c.status),
+        rating: c.rating,
+        status: courier_status_to_proto(&c.status) as i32,
+        token: String::new(),
+        token_expires_at: String::new(),
+        last_seen_at: c.last_seen_at.to_rfc3339(),
+        vehicle_type: vehicle_type_to_proto(c.vehicle_type) as i32,
+        skills: c.skills.clone(),
+        preferred_zones: c.preferred_zones.clone(),
+        reliability: c.offers.reliability(),
+        last_assigned_at: c
+            .last_assigned_at
+            .map(|at| at.to_rfc3339())
+            .unwrap_or_default(),
+        accepts_cod: c.accepts_cod,
+        cod_limit: c.cod_limit.unwrap_or_default(),
+        equipment: handling_to_proto(&c.equipment),
+        break_until: c
+            .break_until
+            .map(|until| until.to_rfc3339())
+            .unwrap_or_default(),
+    }
+}
+
+pub fn order_to_proto(o: &DeliveryOrder) -> pb::OrderResponse {
+    pb::OrderResponse {
+        id: o.id.to_string(),
+        pickup: Some(geo_to_proto(&o.pickup)),
+        dropoff: Some(geo_to_proto(&o.dropoff)),
+        priority_name: format!("{:?}", o.priority),
+        status_name: format!("{:?}", o.status),
+        assigned_courier: o
+            .assigned_courier
+            .map(|id| id.to_string())
+            .unwrap_or_default(),
+        created_at: o.created_at.to_rfc3339(),
+        priority: priority_to_proto(&o.priority) as i32,
+        status: order_status_to_proto(&o.status) as i32,
+        required_vehicle: o
+            .required_vehicle
+            .map_or(pb::VehicleType::Unspecified, vehicle_type_to_proto)
+            as i32,
+        size: o.size,
+        items: o.items.iter().map(order_item_to_proto).collect(),
+        extra_pickups: o.extra_pickups.iter().map(geo_to_proto).collect(),
+        cash_on_delivery: o.cash_on_delivery.unwrap_or_default(),
+        required_skills: o.required_skills.clone(),
+        handling: handling_to_proto(&o.handling),
+        waiting_reason: o
+            .waiting_reason
+            .map_or(pb::WaitingReason::Unspecified, waiting_reason_to_proto)
+            as i32,
+    }
+}
+
+pub fn order_event_to_proto(e: &OrderEvent) -> pb::OrderEvent {
+    pb::OrderEvent {
+        order_id: e.order_id.to_string(),
+        status_name: format!("{:?}", e.status),
+        courier_id: e.courier_id.map(|id| id.to_string()).unwrap_or_default(),
+        pickup: Some(geo_to_proto(&e.pickup)),
+        zone: zone_of(&e.pickup),
+        occurred_at: e.occurred_at.to_rfc3339(),
+        status: order_status_to_proto(&e.status) as i32,
+        seq: 0,
+        items: e.items.iter().map(order_item_to_proto).collect(),
+    }
+}
+
+pub fn order_item_to_proto(item: &OrderItem) -> pb::OrderItem {
+    pb::OrderItem {
+        description: item.description.clone(),
+        weight: item.weight,
+        quantity: item.quantity,
+    }
+}
+
+pub fn order_item_from_proto(item: &pb::OrderItem) -> OrderItem {
+    OrderItem {
+        description: item.description.clone(),
+        weight: item.weight,
+        quantity: item.quantity.max(1),
+    }
+}
+
+pub fn courier_location_to_proto(l: &CourierLocation) -> pb::CourierLocationEvent {
+    pb::CourierLocationEvent {
+        courier_id: l.courier_id.to_string(),
+        location: Some(geo_to_proto(&l.location)),
+        status: courier_status_to_proto(&l.status) as i32,
+        zone: zone_of(&l.location),
+        updated_at: l.updated_at.to_rfc3339(),
+    }
+}
+
+/// The inverse of `courier_location_to_proto`, for standbys.
+#[allow(clippy::result_large_err)]
+pub fn courier_location_from_proto(l: pb::CourierLocationEvent) -> Result<CourierLocation, Status> {
+    let status = pb::CourierStatus::try_from(l.status)
+        .map_err(|_| unknown_enum_value("status", l.status))
+        .and_then(courier_status_from_proto)?;
+    Ok(CourierLocation {
+        courier_id: parse_id("courier_id", &l.courier_id)?,
+        location: geo_from_proto(
+            l.location
+                .ok_or_else(|| Status::invalid_argument("location is required"))?,
+        ),
+        status,
+        updated_at: parse_time("updated_at", &l.updated_at)?
+            .ok_or_else(|| Status::invalid_argument("updated_at is required"))?,
+        relayed: false,
+    })
+}
+
+#[allow(clippy::result_large_err)]
+pub fn change_to_proto(seq: u64, change: &EntityChange) -> Result<pb::ChangeEvent, Status> {
+    let entity = match change {
+        EntityChange::Courier(courier) => serde_json::to_string(courier).map(Entity::CourierJson),
+        EntityChange::Order(order) => serde_json::to_string(order).map(Entity::OrderJson),
+    }
+    .map_err(|err| Status::internal(format!("failed to serialize change: {err}")))?;
+    Ok(pb::ChangeEvent {
+        seq,
+        entity: Some(entity),
+    })
+}
+
+#[allow(clippy::result_large_err)]
+pub fn change_from_proto(change: pb::ChangeEvent) -> Result<EntityChange, Status> {
+    match change.entity {
+        Some(Entity::CourierJson(json)) => serde_json::from_str(&json).map(EntityChange::Courier),
+        Some(Entity::OrderJson(json)) => serde_json::from_str(&json).map(EntityChange::Order),
+        None => return Err(Status::invalid_argument("entity is required")),
+    }
+    .map_err(|err| Status::invalid_argument(format!("invalid entity: {err}")))
+}
+
+pub fn assignment_to_proto(a: &Assignment) -> pb::AssignmentEvent {
+    pb::AssignmentEvent {
+        id: a.id.to_string(),
+        order_id: a.order_id.to_string(),
+        courier_id: a.courier_id.to_string(),
+        score: a.score,
+        score_breakdown: Some(pb::ScoreBreakdown {
+            distance_score: a.score_breakdown.distance_score,
+            load_score: a.score_breakdown.load_score,
+            rating_score: a.score_breakdown.rating_score,
+            priority_score: a.score_breakdown.priority_score,
+            zone_score: a.score_breakdown.zone_score,
+            reliability_score: a.score_breakdown.reliability_score,
+            idle_score: a.score_breakdown.idle_score,
+            earnings_score: a.score_breakdown.earnings_score,
+        }),
+        assigned_at: a.assigned_at.to_rfc3339(),
+        seq: 0,
+        due_at: a.due_at.to_rfc3339(),
+        delivered_at: a.delivered_at.map(|at| at.to_rfc3339()).unwrap_or_default(),
+        stacked_with: a.stacked_with.iter().map(Uuid::to_string).collect(),
+        cash_on_delivery: a.cash_on_delivery.unwrap_or_default(),
+    }
+}
+
+#[allow(clippy::result_large_err)]
+pub fn parse_id(field: &str, s: &str) -> Result<Uuid, Status> {
+    Uuid::parse_str(s).map_err(|err| Status::invalid_argument(format!("invalid {field}: {err}")))
+}
+
+/// An optional RFC 3339 timestamp; empty means not set.
+#[allow(clippy::result_large_err)]
+pub fn parse_time(field: &str, s: &str) -> Result<Option<DateTime<Utc>>, Status> {
+    if s.is_empty() {
+        return Ok(None);
+    }
+    DateTime::parse_from_rfc3339(s)
+        .map(|time| Some(time.with_timezone(&Utc)))
+        .map_err(|err| Status::invalid_argument(format!("invalid {field}: {err}")))
+}
+
+/// Proto3 strings cannot be absent; empty means not set.
+pub fn non_empty(s: String) -> Option<String> {
+    (!s.is_empty()).then_some(s)
+}
+
+/// proto3 cannot tell an unset number from zero, so zero means not set.
+pub fn non_zero(n: f64) -> Option<f64> {
+    (n != 0.0).then_some(n)
+}
+
+#[allow(clippy::result_large_err)]
+pub fn requested_priority(req: &pb::CreateOrderRequest) -> Result<Priority, Status> {
+    match pb::Priority::try_from(req.priority) {
+        Ok(pb::Priority::Unspecified) if !req.priority_name.is_empty() => {
+            parse_priority(&req.priority_name)
+        }
+        Ok(pb::Priority::Unspecified) => Err(Status::invalid_argument("priority is required")),
+        Ok(priority) => priority_from_proto(priority),
+        Err(_) => Err(unknown_enum_value("priority", req.priority)),
+    }
+}
+
+#[allow(clippy::result_large_err)]
+pub fn requested_courier_status(
+    req: &pb::UpdateCourierStatusRequest,
+) -> Result<CourierStatus, Status> {
+    match pb::CourierStatus::try_from(req.status) {
+        Ok(pb::CourierStatus::Unspecified) if !req.status_name.is_empty() => {
+            parse_courier_status(&req.status_name)
+        }
+        Ok(pb::CourierStatus::Unspecified) => Err(Status::invalid_argument("status is required")),
+        Ok(status) => courier_status_from_proto(status),
+        Err(_) => Err(unknown_enum_value("status", req.status)),
+    }
+}
+
+#[allow(clippy::result_large_err)]
+pub fn requested_order_status(req: &pb::UpdateOrderStatusRequest) -> Result<OrderStatus, Status> {
+    match pb::OrderStatus::try_from(req.status) {
+        Ok(pb::OrderStatus::Unspecified) if !req.status_name.is_empty() => {
+            parse_order_status(&req.status_name)
+        }
+        Ok(pb::OrderStatus::Unspecified) => Err(Status::invalid_argument("status is required")),
+        Ok(status) => order_status_from_proto(status),
+        Err(_) => Err(unknown_enum_value("status", req.status)),
+    }
+}
+
+#[allow(clippy::result_large_err)]
+pub fn requested_order_status_filter(
+    req: &pb::WatchOrdersRequest,
+) -> Result<Vec<OrderStatus>, Status> {
+    let typed = req.statuses.iter().map(|raw| {
+        pb::OrderStatus::try_from(*raw)
+            .map_err(|_| unknown_enum_value("statuses", *raw))
+            .and_then(order_status_from_proto)
+    });
+    let legacy = req.status_names.iter().map(|name| parse_order_status(name));
+
+    typed.chain(legacy).collect()
+}
+
+/// `capacity_units`, falling back to the whole-slot `capacity` from before
+/// sizes.
+pub fn requested_capacity(req: &pb::CreateCourierRequest) -> f64 {
+    if req.capacity_units != 0.0 {
+        req.capacity_units
+    } else {
+        f64::from(req.capacity)
+    }
+}
+
+/// Unspecified, for both a courier's vehicle and an order's requirement, is
+/// `None`.
+#[allow(clippy::result_large_err)]
+pub fn requested_vehicle(field: &str, raw: i32) -> Result<Option<VehicleType>, Status> {
+    match pb::VehicleType::try_from(raw) {
+        Ok(pb::VehicleType::Unspecified) => Ok(None),
+        Ok(pb::VehicleType::Bike) => Ok(Some(VehicleType::Bike)),
+        Ok(pb::VehicleType::Scooter) => Ok(Some(VehicleType::Scooter)),
+        Ok(pb::VehicleType::Car) => Ok(Some(VehicleType::Car)),
+        Ok(pb::VehicleType::Van) => Ok(Some(VehicleType::Van)),
+        Err(_) => Err(unknown_enum_value(field, raw)),
+    }
+}
+
+/// Unspecified is rejected: a list of requirements has no use for it.
+#[allow(clippy::result_large_err)]
+pub fn requested_handling(field: &str, raw: &[i32]) -> Result<Vec<Handling>, Status> {
+    raw.iter()
+        .map(|raw| match pb::Handling::try_from(*raw) {
+            Ok(pb::Handling::Fragile) => Ok(Handling::Fragile),
+            Ok(pb::Handling::Chilled) => Ok(Handling::Chilled),
+            Ok(pb::Handling::Frozen) => Ok(Handling::Frozen),
+            Ok(pb::Handling::Unspecified) | Err(_) => Err(unknown_enum_value(field, *raw)),
+        })
+        .collect()
+}
+
+fn handling_to_proto(handling: &[Handling]) -> Vec<i32> {
+    handling
+        .iter()
+        .map(|handling| match handling {
+            Handling::Fragile => pb::Handling::Fragile,
+            Handling::Chilled => pb::Handling::Chilled,
+            Handling::Frozen => pb::Handling::Frozen,
+        } as i32)
+        .collect()
+}
+
+fn vehicle_type_to_proto(v: VehicleType) -> pb::VehicleType {
+    match v {
+        VehicleType::Bike => pb::VehicleType::Bike,
+        VehicleType::Scooter => pb::VehicleType::Scooter,
+        VehicleType::Car => pb::VehicleType::Car,
+        VehicleType::Van => pb::VehicleType::Van,
+    }
+}
+
+fn waiting_reason_to_proto(r: WaitingReason) -> pb::WaitingReason {
+    match r {
+        WaitingReason::NoCourierAvailable => pb::WaitingReason::NoCourierAvailable,
+        WaitingReason::NoQualifiedCourier => pb::WaitingReason::NoQualifiedCourier,
+    }
+}
+
+fn priority_to_proto(p: &Priority) -> pb::Priority {
+    match p {
+        Priority::Low => pb::Priority::Low,
+        Priority::Normal => pb::Priority::Normal,
+        Priority::High => pb::Priority::High,
+        Priority::Urgent => pb::Priority::Urgent,
+    }
+}
+
+#[allow(clippy::result_large_err)]
+fn priority_from_proto(p: pb::Priority) -> Result<Priority, Status> {
+    match p {
+        pb::Priority::Low => Ok(Priority::Low),
+        pb::Priority::Normal => Ok(Priority::Normal),
+        pb::Priority::High => Ok(Priority::High),
+        pb::Priority::Urgent => Ok(Priority::Urgent),
+        pb::Priority::Unspecified => Err(Status::invalid_argument("priority is required")),
+    }
+}
+
+fn courier_status_to_proto(s: &CourierStatus) -> pb::CourierStatus {
+    match s {
+        CourierStatus::Available => pb::CourierStatus::Available,
+        CourierStatus::Busy => pb::CourierStatus::Busy,
+        CourierStatus::OnBreak => pb::CourierStatus::OnBreak,
+        CourierStatus::Offline => pb::CourierStatus::Offline,
+    }
+}
+
+#[allow(clippy::result_large_err)]
+fn courier_status_from_proto(s: pb::CourierStatus) -> Result<CourierStatus, Status> {
+    match s {
+        pb::CourierStatus::Available => Ok(CourierStatus::Available),
+        pb::CourierStatus::Busy => Ok(CourierStatus::Busy),
+        pb::CourierStatus::OnBreak => Ok(CourierStatus::OnBreak),
+        pb::CourierStatus::Offline => Ok(CourierStatus::Offline),
+        pb::CourierStatus::Unspecified => Err(Status::invalid_argument("status is required")),
+    }
+}
+
+fn order_status_to_proto(s: &OrderStatus) -> pb::OrderStatus {
+    match s {
+        OrderStatus::Pending => pb::OrderStatus::Pending,
+        OrderStatus::Assigned => pb::OrderStatus::Assigned,
+        OrderStatus::InTransit => pb::OrderStatus::InTransit,
+        OrderStatus::Delivered => pb::OrderStatus::Delivered,
+        OrderStatus::Rejected => pb::OrderStatus::Rejected,
+    }
+}
+
+#[allow(clippy::result_large_err)]
+fn order_status_from_proto(s: pb::OrderStatus) -> Result<OrderStatus, Status> {
+    match s {
+        pb::OrderStatus::Pending => Ok(OrderStatus::Pending),
+        pb::OrderStatus::Assigned => Ok(OrderStatus::Assigned),
+        pb::OrderStatus::InTransit => Ok(OrderStatus::InTransit),
+        pb::OrderStatus::Delivered => Ok(OrderStatus::Delivered),
+        pb::OrderStatus::Rejected => Ok(OrderStatus::Rejected),
+        pb::OrderStatus::Unspecified => Err(Status::invalid_argument("status is required")),
+    }
+}
+
+fn unknown_enum_value(field: &str, raw: i32) -> Status {
+    Status::invalid_argument(format!("unknown {field} value: {raw}"))
+}
+
+#[allow(clippy::result_large_err)]
+fn parse_priority(s: &str) -> Result<Priority, Status> {
+    match s {
+        "Low" => Ok(Priority::Low),
+        "Normal" => Ok(Priority::Normal),
+        "High" => Ok(Priority::High),
+        "Urgent" => Ok(Priority::Urgent),
+        other => Err(Status::invalid_argument(format!(
+            "unknown priority: {other}, expected Low/Normal/High/Urgent"
+        ))),
+    }
+}
+
+#[allow(clippy::result_large_err)]
+fn parse_courier_status(s: &str) -> Result<CourierStatus, Status> {
+    match s {
+        "Available" => Ok(CourierStatus::Available),
+        "Busy" => Ok(CourierStatus::Busy),
+        "OnBreak" => Ok(CourierStatus::OnBreak),
+        "Offline" => Ok(CourierStatus::Offline),
+        other => Err(Status::invalid_argument(format!(
+            "unknown courier status: {other}, expected Available/Busy/OnBreak/Offline"
+        ))),
+    }
+}
+
+#[allow(clippy::result_large_err)]
+fn parse_order_status(s: &str) -> Result<OrderStatus, Status> {
+    match s {
+        "Pending" => Ok(OrderStatus::Pending),
+        "Assigned" => Ok(OrderStatus::Assigned),
+        "InTransit" => Ok(OrderStatus::InTransit),
+        "Delivered" => Ok(OrderStatus::Delivered),
+        "Rejected" => Ok(OrderStatus::Rejected),
+        other => Err(Status::invalid_argument(format!(
+            "unknown order status: {other}, expected Pending/Assigned/InTransit/Delivered/Rejected"
+        ))),
+    }
+}
@@ -0,0 +1,35 @@
+use tokio::task::JoinHandle;
+use tonic_health::server::HealthReporter;
+use tonic_health::ServingStatus;
+use tracing::error;
+
+use crate::api::grpc::pb::dispatch_service_server::DispatchServiceServer;
+use crate::api::grpc::GrpcDispatchService;
+
+/// Service name under which the assignment engine's liveness is reported.
+pub const ENGINE_SERVICE_NAME: &str = "dispatch.AssignmentEngine";
+
+pub async fn report_serving(reporter: &mut HealthReporter) {
+    reporter
+        .set_serving::<DispatchServiceServer<GrpcDispatchService>>()
+        .await;
+    reporter
+        .set_service_status(ENGINE_SERVICE_NAME, ServingStatus::Serving)
+        .await;
+}
+
+/// Waits for the engine task to finish and then reports the engine, and the
+/// server as a whole, as `NOT_SERVING`; without the engine, orders are
+/// accepted but never assigned.
+pub async fn watch_engine(mut reporter: HealthReporter, engine: JoinHandle<()>) {
+    if let Err(err) = engine.await {
+        error!(error = %err, "assignment engine task failed");
+    }
+
+    reporter
+        .set_service_status(ENGINE_SERVICE_NAME, ServingStatus::NotServing)
+        .await;
+    reporter
+        .set_service_status("", ServingStatus::NotServing)
+        .await;
+}
@@ -1,27 +1,61 @@
+use std::collections::HashSet;
 use std::pin::Pin;
 use std::sync::Arc;
 
 use chrono::Utc;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
 use tokio_stream::wrappers::BroadcastStream;
 use tokio_stream::Stream;
 use tokio_stream::StreamExt;
-use tonic::{Request, Response, Status};
+use tonic::metadata::MetadataValue;
+use tonic::{Request, Response, Status, Streaming};
+use tracing::warn;
 use uuid::Uuid;
 
-use crate::engine::queue::enqueue_order;
-use crate::models::courier::{Courier, CourierStatus};
-use crate::models::order::{DeliveryOrder, OrderStatus, Priority};
-use crate::state::AppState;
+use crate::api::REQUEST_ID_HEADER;
+use crate::auth::{Principal, Role};
+use crate::engine::fleet::{register_courier, set_courier_status};
+use crate::engine::lifecycle::transition_order;
+use crate::engine::location::move_courier;
+use crate::engine::queue::submit_order;
+use crate::events::{Lagged, RecordedEvent};
+use crate::geo::{in_zone, CachedLocation};
+use crate::models::courier::{Courier, CourierStatus, OfferHistory};
+use crate::models::event::{DispatchEvent, EntityChange};
+use crate::models::order::NewOrder;
+use crate::state::{AppState, AssignmentFilter};
+use crate::validation::{
+    normalize_skills, normalize_zones, validate_amount, validate_cash, validate_point,
+    validate_rating,
+};
+
+pub mod access_log;
+pub mod auth;
+pub(crate) mod convert;
+pub mod health;
+pub mod tenants;
 
 pub mod pb {
     tonic::include_proto!("dispatch");
 }
 
 use pb::dispatch_service_server::DispatchService;
+use pb::get_assignment_request::Lookup;
 use pb::{
-    AssignmentEvent, CourierResponse, CreateCourierRequest, CreateOrderRequest, GeoPoint,
-    GetAssignmentsRequest, GetAssignmentsResponse, GetCouriersRequest, GetCouriersResponse,
-    OrderResponse, ScoreBreakdown, WatchAssignmentsRequest,
+    AssignmentEvent, ChangeEvent, CourierLocationEvent, CourierResponse, CreateCourierRequest,
+    CreateOrderRequest, GeoPoint, GetAssignmentRequest, GetAssignmentsRequest,
+    GetAssignmentsResponse, GetCouriersRequest, GetCouriersResponse, GetOrderRequest, LocationAck,
+    LocationPing, OrderEvent, OrderResponse, UpdateCourierLocationRequest,
+    UpdateCourierStatusRequest, UpdateOrderStatusRequest, WatchAssignmentsRequest,
+    WatchChangesRequest, WatchCourierLocationsRequest, WatchOrdersRequest,
+};
+
+use convert::{
+    assignment_to_proto, change_to_proto, courier_location_to_proto, courier_to_proto,
+    geo_from_proto, non_empty, non_zero, order_event_to_proto, order_item_from_proto,
+    order_to_proto, parse_id, parse_time, requested_capacity, requested_courier_status,
+    requested_handling, requested_order_status, requested_order_status_filter, requested_priority,
+    requested_vehicle,
 };
 
 pub struct GrpcDispatchService {
@@ -32,49 +66,43 @@ impl GrpcDispatchService {
     pub fn new(state: Arc<AppState>) -> Self {
         Self { state }
     }
-}
 
-fn courier_to_proto(c: &Courier) -> CourierResponse {
-    CourierResponse {
-        id: c.id.to_string(),
-        name: c.name.clone(),
-        location: Some(GeoPoint {
-            lat: c.location.lat,
-            lng: c.location.lng,
-        }),
-        capacity: c.capacity as u32,
-        current_load: c.current_load as u32,
-        status: format!("{:?}", c.status),
-        rating: c.rating,
+    /// The caller [`JwtInterceptor`](auth::JwtInterceptor) attached, or an
+    /// unrestricted one when JWT auth is off.
+    #[allow(clippy::result_large_err)]
+    fn principal<T>(&self, request: &Request<T>) -> Result<Principal, Status> {
+        match request.extensions().get::<Principal>() {
+            Some(principal) => {
+                principal.require_tenant(&self.state.tenant)?;
+                Ok(principal.clone())
+            }
+            None if self.state.jwt.is_none() => Ok(Principal::unrestricted("grpc")),
+            None => Err(Status::unauthenticated("missing bearer token")),
+        }
     }
-}
 
-fn assignment_to_proto(a: &crate::models::assignment::Assignment) -> AssignmentEvent {
-    AssignmentEvent {
-        id: a.id.to_string(),
-        order_id: a.order_id.to_string(),
-        courier_id: a.courier_id.to_string(),
-        score: a.score,
-        score_breakdown: Some(ScoreBreakdown {
-            distance_score: a.score_breakdown.distance_score,
-            load_score: a.score_breakdown.load_score,
-            rating_score: a.score_breakdown.rating_score,
-            priority_score: a.score_breakdown.priority_score,
-        }),
-        assigned_at: a.assigned_at.to_rfc3339(),
+    #[allow(clippy::result_large_err)]
+    fn apply_location(
+        &self,
+        principal: &Principal,
+        courier_id: &str,
+        location: Option<GeoPoint>,
+    ) -> Result<Arc<Courier>, Status> {
+        let id = parse_id("courier_id", courier_id)?;
+        principal.require_courier(id)?;
+        let location = location.ok_or_else(|| Status::invalid_argument("location is required"))?;
+
+        Ok(move_courier(&self.state, id, geo_from_proto(location))?)
     }
 }
 
-fn parse_priority(s: &str) -> Result<Priority, Status> {
-    match s {
-        "Low" => Ok(Priority::Low),
-        "Normal" => Ok(Priority::Normal),
-        "High" => Ok(Priority::High),
-        "Urgent" => Ok(Priority::Urgent),
-        other => Err(Status::invalid_argument(format!(
-            "unknown priority: {other}, expected Low/Normal/High/Urgent"
-        ))),
-    }
+/// Ends a watch stream whose consumer fell behind. The client should
+/// reconnect with `resume_from` set to the last `seq` it received.
+fn lagged_status(state: &AppState, dropped: u64) -> Status {
+    state.metrics.record_dropped_events("grpc", dropped);
+    Status::data_loss(format!(
+        "events_dropped: {dropped}; resume from the last seq received"
+    ))
 }
 
 #[tonic::async_trait]
@@ -83,35 +111,66 @@ impl DispatchService for GrpcDispatchService {
         &self,
         request: Request<CreateCourierRequest>,
     ) -> Result<Response<CourierResponse>, Status> {
+        let principal = self.principal(&request)?;
+        principal.require(Role::Admin)?;
         let req = request.into_inner();
 
         if req.name.trim().is_empty() {
             return Err(Status::invalid_argument("name cannot be empty"));
         }
-        if req.capacity == 0 {
-            return Err(Status::invalid_argument("capacity must be > 0"));
-        }
+        let capacity = requested_capacity(&req);
+        validate_amount("capacity", capacity, self.state.capacity_unit)?;
 
         let location = req
             .location
+            .map(geo_from_proto)
             .ok_or_else(|| Status::invalid_argument("location is required"))?;
+        validate_point("location", &location)?;
+        validate_rating(req.rating)?;
+        let vehicle_type = requested_vehicle("vehicle_type", req.vehicle_type)?.unwrap_or_default();
+        let skills = normalize_skills("skills", req.skills)?;
+        let preferred_zones = normalize_zones("preferred_zones", req.preferred_zones)?;
+        let cod_limit = non_zero(req.cod_limit);
+        if let Some(limit) = cod_limit {
+            validate_cash("cod_limit", limit)?;
+        }
+        let equipment = requested_handling("equipment", &req.equipment)?;
 
         let courier = Courier {
-            id: Uuid::new_v4(),
+            id: Uuid::now_v7(),
             name: req.name,
-            location: crate::models::courier::GeoPoint {
-                lat: location.lat,
-                lng: location.lng,
-            },
-            capacity: req.capacity.min(255) as u8,
-            current_load: 0,
+            location_cache: CachedLocation::new(&location),
+            location,
+            capacity,
+            current_load: 0.0,
             status: CourierStatus::Available,
+            break_until: None,
             rating: req.rating.clamp(0.0, 5.0),
+            offers: OfferHistory::default(),
+            last_assigned_at: None,
+            vehicle_type,
             updated_at: Utc::now(),
+            last_seen_at: Utc::now(),
+            shifts: Vec::new(),
+            skills,
+            preferred_zones,
+            accepts_cod: req.accepts_cod,
+            cod_limit,
+            equipment,
         };
 
-        self.state.couriers.insert(courier.id, courier.clone());
-        Ok(Response::new(courier_to_proto(&courier)))
+        let courier = register_courier(&self.state, courier, &principal.subject);
+        let mut response = courier_to_proto(&courier);
+        if let Some(issued) = self
+            .state
+            .jwt
+            .as_ref()
+            .and_then(|jwt| jwt.issue_courier_token(&self.state.tenant, courier.id))
+        {
+            response.token = issued.token;
+            response.token_expires_at = issued.expires_at.to_rfc3339();
+        }
+        Ok(Response::new(response))
     }
 
     async fn get_couriers(
@@ -128,12 +187,76 @@ impl DispatchService for GrpcDispatchService {
         Ok(Response::new(GetCouriersResponse { couriers }))
     }
 
+    async fn update_courier_location(
+        &self,
+        request: Request<UpdateCourierLocationRequest>,
+    ) -> Result<Response<CourierResponse>, Status> {
+        let principal = self.principal(&request)?;
+        let req = request.into_inner();
+        let courier = self.apply_location(&principal, &req.courier_id, req.location)?;
+
+        Ok(Response::new(courier_to_proto(&courier)))
+    }
+
+    async fn update_courier_status(
+        &self,
+        request: Request<UpdateCourierStatusRequest>,
+    ) -> Result<Response<CourierResponse>, Status> {
+        let principal = self.principal(&request)?;
+        let req = request.into_inner();
+
+        let id = parse_id("courier_id", &req.courier_id)?;
+        principal.require_courier(id)?;
+        let status = requested_courier_status(&req)?;
+        let break_until = parse_time("break_until", &req.break_until)?;
+
+        let courier = set_courier_status(&self.state, id, status, break_until, &principal.subject)?;
+        Ok(Response::new(courier_to_proto(&courier)))
+    }
+
+    async fn stream_locations(
+        &self,
+        request: Request<Streaming<LocationPing>>,
+    ) -> Result<Response<LocationAck>, Status> {
+        let principal = self.principal(&request)?;
+        let mut pings = request.into_inner();
+        let mut ack = LocationAck {
+            accepted: 0,
+            rejected: 0,
+        };
+
+        // A bad ping (unknown courier, missing location) is counted and skipped
+        // rather than tearing down the device's stream.
+        while let Some(ping) = pings.message().await? {
+            match self.apply_location(&principal, &ping.courier_id, ping.location) {
+                Ok(_) => ack.accepted += 1,
+                Err(status) => {
+                    ack.rejected += 1;
+                    warn!(courier_id = %ping.courier_id, error = %status.message(), "rejected location ping");
+                }
+            }
+        }
+
+        Ok(Response::new(ack))
+    }
+
     async fn create_order(
         &self,
         request: Request<CreateOrderRequest>,
     ) -> Result<Response<OrderResponse>, Status> {
+        let principal = self.principal(&request)?;
+        principal.require(Role::Dispatcher)?;
+        let request_id = request
+            .metadata()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
         let req = request.into_inner();
 
+        let priority = requested_priority(&req)?;
+        let required_vehicle = requested_vehicle("required_vehicle", req.required_vehicle)?;
+        let handling = requested_handling("handling", &req.handling)?;
         let pickup = req
             .pickup
             .ok_or_else(|| Status::invalid_argument("pickup is required"))?;
@@ -141,53 +264,129 @@ impl DispatchService for GrpcDispatchService {
             .dropoff
             .ok_or_else(|| Status::invalid_argument("dropoff is required"))?;
 
-        let priority = parse_priority(&req.priority)?;
-
-        let order = DeliveryOrder {
-            id: Uuid::new_v4(),
-            pickup: crate::models::courier::GeoPoint {
-                lat: pickup.lat,
-                lng: pickup.lng,
+        let order = submit_order(
+            &self.state,
+            NewOrder {
+                pickup: geo_from_proto(pickup),
+                dropoff: geo_from_proto(dropoff),
+                extra_pickups: req.extra_pickups.into_iter().map(geo_from_proto).collect(),
+                priority,
+                size: non_zero(req.size),
+                items: req.items.iter().map(order_item_from_proto).collect(),
+                cash_on_delivery: non_zero(req.cash_on_delivery),
+                required_vehicle,
+                required_skills: req.required_skills,
+                handling,
+                callback_url: non_empty(req.callback_url),
+                customer_contact: non_empty(req.customer_contact),
+                request_id: Some(request_id.clone()),
             },
-            dropoff: crate::models::courier::GeoPoint {
-                lat: dropoff.lat,
-                lng: dropoff.lng,
-            },
-            priority: priority.clone(),
-            status: OrderStatus::Pending,
-            assigned_courier: None,
-            created_at: Utc::now(),
+            &principal.subject,
+        )
+        .await?;
+
+        let mut response = Response::new(order_to_proto(&order));
+        if let Ok(value) = MetadataValue::try_from(request_id) {
+            response.metadata_mut().insert(REQUEST_ID_HEADER, value);
+        }
+        Ok(response)
+    }
+
+    async fn get_order(
+        &self,
+        request: Request<GetOrderRequest>,
+    ) -> Result<Response<OrderResponse>, Status> {
+        let principal = self.principal(&request)?;
+        let id = parse_id("order_id", &request.into_inner().order_id)?;
+
+        let order = self
+            .state
+            .orders
+            .get(&id)
+            .ok_or_else(|| Status::not_found(format!("order {id} not found")))?;
+        principal.require_order_read(order.assigned_courier)?;
+
+        Ok(Response::new(order_to_proto(order.value())))
+    }
+
+    async fn update_order_status(
+        &self,
+        request: Request<UpdateOrderStatusRequest>,
+    ) -> Result<Response<OrderResponse>, Status> {
+        let principal = self.principal(&request)?;
+        let req = request.into_inner();
+
+        let id = parse_id("order_id", &req.order_id)?;
+        let status = requested_order_status(&req)?;
+        let assigned_courier = self
+            .state
+            .orders
+            .get(&id)
+            .ok_or_else(|| Status::not_found(format!("order {id} not found")))?
+            .assigned_courier;
+        principal.require_order_update(assigned_courier)?;
+        let order = transition_order(&self.state, id, status, &principal.subject)?;
+
+        Ok(Response::new(order_to_proto(&order)))
+    }
+
+    async fn get_assignment(
+        &self,
+        request: Request<GetAssignmentRequest>,
+    ) -> Result<Response<AssignmentEvent>, Status> {
+        let principal = self.principal(&request)?;
+        let assignment = match request.into_inner().lookup {
+            Some(Lookup::AssignmentId(raw)) => {
+                let id = parse_id("assignment_id", &raw)?;
+                self.state
+                    .assignments
+                    .get(&id)
+                    .map(|entry| entry.value().clone())
+                    .ok_or_else(|| Status::not_found(format!("assignment {id} not found")))?
+            }
+            Some(Lookup::OrderId(raw)) => {
+                let id = parse_id("order_id", &raw)?;
+                self.state
+                    .latest_assignment_for_order(id)
+                    .ok_or_else(|| Status::not_found(format!("order {id} has no assignment")))?
+            }
+            None => {
+                return Err(Status::invalid_argument(
+                    "assignment_id or order_id is required",
+                ));
+            }
         };
+        principal.require_order_read(Some(assignment.courier_id))?;
 
-        self.state.orders.insert(order.id, order.clone());
-        enqueue_order(&self.state, order.clone())
-            .await
-            .map_err(|err| Status::internal(format!("enqueue failed: {err}")))?;
-
-        Ok(Response::new(OrderResponse {
-            id: order.id.to_string(),
-            pickup: Some(GeoPoint {
-                lat: pickup.lat,
-                lng: pickup.lng,
-            }),
-            dropoff: Some(GeoPoint {
-                lat: dropoff.lat,
-                lng: dropoff.lng,
-            }),
-            priority: format!("{:?}", order.priority),
-            status: format!("{:?}", order.status),
-        }))
+        Ok(Response::new(assignment_to_proto(&assignment)))
     }
 
+    #[allow(clippy::result_large_err)]
     async fn get_assignments(
         &self,
-        _request: Request<GetAssignmentsRequest>,
+        request: Request<GetAssignmentsRequest>,
     ) -> Result<Response<GetAssignmentsResponse>, Status> {
+        let courier_id = self.principal(&request)?.courier_scope(None)?;
+        let req = request.into_inner();
+        let after = (!req.after.is_empty())
+            .then(|| parse_id("after", &req.after))
+            .transpose()?;
+        let limit = match req.limit {
+            0 => usize::MAX,
+            limit => limit as usize,
+        };
         let assignments: Vec<AssignmentEvent> = self
             .state
-            .assignments
+            .find_assignments(
+                &AssignmentFilter {
+                    after,
+                    courier_id,
+                    ..AssignmentFilter::default()
+                },
+                limit,
+            )
             .iter()
-            .map(|entry| assignment_to_proto(entry.value()))
+            .map(assignment_to_proto)
             .collect();
 
         Ok(Response::new(GetAssignmentsResponse { assignments }))
@@ -198,14 +397,175 @@ impl DispatchService for GrpcDispatchService {
 
     async fn watch_assignments(
         &self,
-        _request: Request<WatchAssignmentsRequest>,
+        request: Request<WatchAssignmentsRequest>,
     ) -> Result<Response<Self::WatchAssignmentsStream>, Status> {
-        let rx = self.state.assignment_events_tx.subscribe();
-        let stream = BroadcastStream::new(rx).filter_map(|result| match result {
-            Ok(assignment) => Some(Ok(assignment_to_proto(&assignment))),
-            Err(_) => None,
+        let courier_id = self.principal(&request)?.courier_scope(None)?;
+        let resume_from = request.into_inner().resume_from;
+        let state = self.state.clone();
+        let stream = self
+            .state
+            .events
+            .stream((resume_from > 0).then_some(resume_from), None)
+            .filter_map(move |item| match item {
+                Ok(RecordedEvent {
+                    seq,
+                    event: DispatchEvent::Assignment(assignment),
+                    ..
+                }) if courier_id.is_none_or(|id| id == assignment.courier_id) => {
+                    Some(Ok(AssignmentEvent {
+                        seq,
+                        ..assignment_to_proto(&assignment)
+                    }))
+                }
+                Ok(_) => None,
+                Err(Lagged(dropped)) => Some(Err(lagged_status(&state, dropped))),
+            });
+
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    type WatchOrdersStream = Pin<Box<dyn Stream<Item = Result<OrderEvent, Status>> + Send>>;
+
+    #[allow(clippy::result_large_err)]
+    async fn watch_orders(
+        &self,
+        request: Request<WatchOrdersRequest>,
+    ) -> Result<Response<Self::WatchOrdersStream>, Status> {
+        let courier_id = self.principal(&request)?.courier_scope(None)?;
+        let req = request.into_inner();
+        let statuses = requested_order_status_filter(&req)?;
+        let zone = req.zone;
+
+        let state = self.state.clone();
+        let stream = self
+            .state
+            .events
+            .stream((req.resume_from > 0).then_some(req.resume_from), None)
+            .filter_map(move |item| match item {
+                Ok(RecordedEvent {
+                    seq,
+                    event: DispatchEvent::Order(event),
+                    ..
+                }) => {
+                    let courier_matches = courier_id.is_none_or(|id| event.courier_id == Some(id));
+                    let status_matches = statuses.is_empty() || statuses.contains(&event.status);
+                    let zone_matches = zone.is_empty() || in_zone(&event.pickup, &zone);
+                    (courier_matches && status_matches && zone_matches).then(|| {
+                        Ok(OrderEvent {
+                            seq,
+                            ..order_event_to_proto(&event)
+                        })
+                    })
+                }
+                Ok(_) => None,
+                Err(Lagged(dropped)) => Some(Err(lagged_status(&state, dropped))),
+            });
+
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    type WatchCourierLocationsStream =
+        Pin<Box<dyn Stream<Item = Result<CourierLocationEvent, Status>> + Send>>;
+
+    #[allow(clippy::result_large_err)]
+    async fn watch_courier_locations(
+        &self,
+        request: Request<WatchCourierLocationsRequest>,
+    ) -> Result<Response<Self::WatchCourierLocationsStream>, Status> {
+        let principal = self.principal(&request)?;
+        let req = request.into_inner();
+        let mut courier_ids = req
+            .courier_ids
+            .iter()
+            .map(|raw| parse_id("courier_ids", raw))
+            .collect::<Result<HashSet<Uuid>, Status>>()?;
+        // Courier tokens only follow their own courier.
+        if !principal.has_role(Role::Dispatcher) {
+            for id in &courier_ids {
+                principal.courier_scope(Some(*id))?;
+            }
+            courier_ids = principal.courier_scope(None)?.into_iter().collect();
+        }
+        let zone = req.zone;
+
+        let state = self.state.clone();
+        let rx = self.state.courier_locations_tx.subscribe();
+        // Positions supersede each other, so falling behind is only counted;
+        // the next ping brings the map up to date.
+        let stream = BroadcastStream::new(rx).filter_map(move |result| {
+            let update = match result {
+                Ok(update) => update,
+                Err(BroadcastStreamRecvError::Lagged(dropped)) => {
+                    state.metrics.record_dropped_events("grpc", dropped);
+                    return None;
+                }
+            };
+            let courier_matches =
+                courier_ids.is_empty() || courier_ids.contains(&update.courier_id);
+            let zone_matches = zone.is_empty() || in_zone(&update.location, &zone);
+            (courier_matches && zone_matches).then(|| Ok(courier_location_to_proto(&update)))
         });
 
         Ok(Response::new(Box::pin(stream)))
     }
+
+    type WatchChangesStream = Pin<Box<dyn Stream<Item = Result<ChangeEvent, Status>> + Send>>;
+
+    #[allow(clippy::result_large_err)]
+    async fn watch_changes(
+        &self,
+        request: Request<WatchChangesRequest>,
+    ) -> Result<Response<Self::WatchChangesStream>, Status> {
+        self.principal(&request)?.require(Role::Admin)?;
+        if !self.state.publish_changes {
+            return Err(Status::failed_precondition(
+                "PUBLISH_CHANGES is off on this instance",
+            ));
+        }
+        let resume_from = request.into_inner().resume_from;
+
+        let (copy, after, live) = if resume_from > 0 && self.state.events.can_resume(resume_from) {
+            let live = self.state.events.stream(Some(resume_from), None);
+            (Vec::new(), resume_from, live)
+        } else {
+            // Subscribed before copying so nothing changed meanwhile is
+            // missed; what the copy already covers is skipped.
+            let live = self.state.events.stream(None, None);
+            let seq = self.state.events.last_seq();
+            (copy_of_state(&self.state, seq), seq, live)
+        };
+
+        let state = self.state.clone();
+        let live = live.filter_map(move |item| match item {
+            Ok(RecordedEvent {
+                seq,
+                event: DispatchEvent::Change(change),
+                ..
+            }) if seq > after => Some(change_to_proto(seq, &change)),
+            Ok(_) => None,
+            Err(Lagged(dropped)) => Some(Err(lagged_status(&state, dropped))),
+        });
+
+        Ok(Response::new(Box::pin(
+            tokio_stream::iter(copy).chain(live),
+        )))
+    }
+}
+
+/// Every courier and order, as changes carrying `seq`.
+#[allow(clippy::result_large_err)]
+fn copy_of_state(state: &AppState, seq: u64) -> Vec<Result<ChangeEvent, Status>> {
+    let _snapshot = state.snapshots.read();
+    let couriers = state
+        .couriers
+        .iter()
+        .map(|entry| EntityChange::Courier(Arc::clone(entry.value())));
+    let orders = state
+        .orders
+        .iter()
+        .map(|entry| EntityChange::Order(Box::new(entry.value().clone())));
+    couriers
+        .chain(orders)
+        .map(|change| change_to_proto(seq, &change))
+        .collect()
 }
@@ -0,0 +1,71 @@
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::task::{Context, Poll};
+
+use futures::future::BoxFuture;
+use tonic::body::BoxBody;
+use tonic::codegen::http::{Request, Response};
+use tonic::server::NamedService;
+use tonic::Status;
+use tower::{Service, ServiceExt};
+
+use crate::tenants::{DEFAULT_TENANT, TENANT_HEADER};
+
+/// One gRPC service per tenant, behind a single name. Each call goes to
+/// the service of the tenant named in its `x-tenant-id` metadata, or of the
+/// default tenant when it names none; an unknown tenant is
+/// `INVALID_ARGUMENT`.
+#[derive(Clone)]
+pub struct TenantServices<S> {
+    services: BTreeMap<String, S>,
+}
+
+impl<S> TenantServices<S> {
+    /// `services` must include the default tenant's.
+    pub fn new(services: impl IntoIterator<Item = (String, S)>) -> Self {
+        let services: BTreeMap<_, _> = services.into_iter().collect();
+        assert!(
+            services.contains_key(DEFAULT_TENANT),
+            "the default tenant is missing"
+        );
+        Self { services }
+    }
+}
+
+impl<S: NamedService> NamedService for TenantServices<S> {
+    const NAME: &'static str = S::NAME;
+}
+
+impl<S, B> Service<Request<B>> for TenantServices<S>
+where
+    S: Service<Request<B>, Response = Response<BoxBody>, Error = Infallible>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send + 'static,
+    B: Send + 'static,
+{
+    type Response = Response<BoxBody>;
+    type Error = Infallible;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    /// Readiness is checked per call, once the tenant is known.
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let tenant = request
+            .headers()
+            .get(TENANT_HEADER)
+            .map(|value| value.to_str().unwrap_or_default())
+            .unwrap_or(DEFAULT_TENANT);
+        match self.services.get(tenant) {
+            Some(service) => Box::pin(service.clone().oneshot(request)),
+            None => {
+                let status = Status::invalid_argument(format!("unknown tenant {tenant:?}"));
+                Box::pin(async move { Ok(status.to_http()) })
+            }
+        }
+    }
+}
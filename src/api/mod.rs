@@ -1,2 +1,6 @@
 pub mod grpc;
 pub mod rest;
+
+/// Header (or gRPC metadata key) correlating a request with the work it
+/// causes. Generated when the client does not send one.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
@@ -0,0 +1,48 @@
+use std::sync::Arc;
+
+use axum::extract::{Query, State};
+use axum::routing::get;
+use axum::Json;
+use axum::Router;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use utoipa::IntoParams;
+use uuid::Uuid;
+
+use crate::auth::{Principal, Role};
+use crate::error::AppError;
+use crate::models::audit::{AuditEntity, AuditEntry};
+use crate::state::AppState;
+
+pub fn router() -> Router<Arc<AppState>> {
+    Router::new().route("/audit", get(list_audit_entries))
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct AuditQuery {
+    /// `courier` or `order`.
+    pub entity: Option<AuditEntity>,
+    pub entity_id: Option<Uuid>,
+    /// Only entries recorded after this RFC 3339 time.
+    pub since: Option<DateTime<Utc>>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/audit",
+    tag = "audit",
+    params(AuditQuery),
+    responses((status = 200, description = "Matching changes, oldest first", body = [AuditEntry]))
+)]
+async fn list_audit_entries(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<Vec<AuditEntry>>, AppError> {
+    principal.require(Role::Admin)?;
+    Ok(Json(state.audit.query(
+        query.entity,
+        query.entity_id,
+        query.since,
+    )))
+}
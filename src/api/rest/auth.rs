@@ -0,0 +1,34 @@
+use std::sync::Arc;
+
+use axum::async_trait;
+use axum::extract::FromRequestParts;
+use axum::http::header::AUTHORIZATION;
+use axum::http::request::Parts;
+
+use crate::auth::Principal;
+use crate::error::AppError;
+use crate::state::AppState;
+
+/// Authenticates the request from its `authorization: Bearer <jwt>` header.
+/// With JWT auth off every request is [`Principal::unrestricted`].
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for Principal {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let Some(verifier) = &state.jwt else {
+            return Ok(Principal::unrestricted("rest"));
+        };
+
+        let authorization = parts
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok());
+        let principal = verifier.verify_bearer(authorization)?;
+        principal.require_tenant(&state.tenant)?;
+        Ok(principal)
+    }
+}
@@ -0,0 +1,60 @@
+use std::time::Duration;
+
+use axum::http::header::{AUTHORIZATION, CONTENT_TYPE, RETRY_AFTER};
+use axum::http::{HeaderName, HeaderValue, Method};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+
+use crate::api::REQUEST_ID_HEADER;
+use crate::error::AppError;
+
+#[derive(Debug, Clone)]
+pub struct CorsSettings {
+    /// Origins allowed to call the API, e.g. `https://ops.example.com`; `*`
+    /// allows any.
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    pub max_age: Duration,
+}
+
+/// Builds the CORS layer for browser dashboards served from other origins.
+/// Requests carry credentials as bearer tokens or API keys, never cookies,
+/// so credentialed CORS is not enabled.
+pub fn layer(settings: &CorsSettings) -> Result<CorsLayer, AppError> {
+    let origins = if settings.allowed_origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::from(Any)
+    } else {
+        let origins = settings
+            .allowed_origins
+            .iter()
+            .map(|origin| {
+                HeaderValue::from_str(origin.trim_end_matches('/')).map_err(|err| {
+                    AppError::Internal(format!("invalid CORS_ALLOWED_ORIGINS: {origin}: {err}"))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        AllowOrigin::list(origins)
+    };
+
+    let methods = settings
+        .allowed_methods
+        .iter()
+        .map(|method| {
+            method.to_uppercase().parse::<Method>().map_err(|err| {
+                AppError::Internal(format!("invalid CORS_ALLOWED_METHODS: {method}: {err}"))
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let request_id = HeaderName::from_static(REQUEST_ID_HEADER);
+    Ok(CorsLayer::new()
+        .allow_origin(origins)
+        .allow_methods(methods)
+        .allow_headers([
+            CONTENT_TYPE,
+            AUTHORIZATION,
+            HeaderName::from_static("x-api-key"),
+            request_id.clone(),
+        ])
+        .expose_headers([request_id, RETRY_AFTER])
+        .max_age(settings.max_age))
+}
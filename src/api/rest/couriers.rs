@@ -1,106 +1,925 @@
 use std::sync::Arc;
 
-use axum::extract::{Path, State};
-use axum::routing::{patch, post};
+use axum::body::Bytes;
+use axum::extract::{Path, Query, State};
+use axum::http::header::CONTENT_TYPE;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::Response;
+use axum::routing::{delete, get, patch, post, put};
 use axum::Json;
 use axum::Router;
-use chrono::Utc;
-use serde::Deserialize;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 
+use crate::api::rest::json_stream::json_array;
+use crate::auth::{CourierToken, Principal, Role};
+use crate::engine::feedback::courier_feedback;
+use crate::engine::fleet::{
+    record_heartbeat, register_courier, set_courier_shifts, set_courier_skills, set_courier_status,
+    set_courier_zones,
+};
+use crate::engine::leaderboard::leaderboard;
+use crate::engine::location::move_courier;
+use crate::engine::route::courier_route;
 use crate::error::AppError;
-use crate::models::courier::{Courier, CourierStatus, GeoPoint};
+use crate::geo::region::REGION_PRECISION;
+use crate::geo::{is_geohash, CachedLocation, MAX_GEOHASH_PRECISION};
+use crate::models::assignment::Assignment;
+use crate::models::courier::{
+    CapacityUnit, Courier, CourierStatus, GeoPoint, OfferHistory, VehicleType,
+};
+use crate::models::device::{CourierDevice, PushPlatform};
+use crate::models::feedback::Feedback;
+use crate::models::order::{Handling, OrderStatus};
+use crate::models::route::CourierRoute;
+use crate::models::shift::{Shift, ShiftWindow};
+use crate::models::stats::{
+    CourierStats, DeliveryStats, FleetStats, Leaderboard, LeaderboardMetric, LeaderboardPeriod,
+};
 use crate::state::AppState;
+use crate::validation::{
+    normalize_skills, normalize_zones, validate_amount, validate_cash, validate_point,
+    validate_rating, validate_shifts,
+};
 
 pub fn router() -> Router<Arc<AppState>> {
     Router::new()
         .route("/couriers", post(create_courier).get(list_couriers))
+        .route("/couriers/bulk", post(bulk_import_couriers))
+        .route("/couriers/:id/token", post(issue_courier_token))
         .route("/couriers/:id/status", patch(update_courier_status))
         .route("/couriers/:id/location", patch(update_courier_location))
+        .route("/couriers/:id/heartbeat", post(courier_heartbeat))
+        .route(
+            "/couriers/:id/shifts",
+            get(get_courier_shifts).put(update_courier_shifts),
+        )
+        .route("/couriers/:id/skills", put(update_courier_skills))
+        .route("/couriers/:id/zones", put(update_courier_zones))
+        .route("/couriers/:id/stats", get(get_courier_stats))
+        .route("/fleet/stats", get(get_fleet_stats))
+        .route("/couriers/leaderboard", get(get_leaderboard))
+        .route("/couriers/:id/assignments", get(list_courier_assignments))
+        .route("/couriers/:id/route", get(get_courier_route))
+        .route("/couriers/:id/feedback", get(get_courier_feedback))
+        .route(
+            "/couriers/:id/devices",
+            post(register_device).get(list_devices),
+        )
+        .route("/couriers/:id/devices/:token", delete(unregister_device))
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct CreateCourierRequest {
     pub name: String,
     pub location: GeoPoint,
-    pub capacity: u8,
+    /// In the deployment's `CAPACITY_UNIT`.
+    pub capacity: f64,
     pub rating: f64,
+    /// Defaults to `Bike`, which is only offered orders with no vehicle
+    /// requirement or one that a bike meets.
+    #[serde(default)]
+    pub vehicle_type: VehicleType,
+    /// Working hours; without any the courier can be assigned at any time.
+    #[serde(default)]
+    pub shifts: Vec<Shift>,
+    /// Skill tags such as `alcohol-certified`, `heavy-lift` or `pharmacy`.
+    #[serde(default)]
+    pub skills: Vec<String>,
+    /// Geohash zones the courier would rather work in.
+    #[serde(default)]
+    pub preferred_zones: Vec<String>,
+    /// Whether the courier takes cash-on-delivery orders.
+    #[serde(default)]
+    pub accepts_cod: bool,
+    /// Most cash the courier may be due to collect at once; no limit if left out.
+    #[serde(default)]
+    pub cod_limit: Option<f64>,
+    /// Handling the courier is equipped for: `Fragile`, `Chilled`, `Frozen`.
+    #[serde(default)]
+    pub equipment: Vec<Handling>,
 }
 
-#[derive(Deserialize)]
+#[derive(Serialize, ToSchema)]
+pub struct CreateCourierResponse {
+    #[serde(flatten)]
+    pub courier: Arc<Courier>,
+    /// Token for the courier's own app, present when `COURIER_TOKEN_SECRET`
+    /// is set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<CourierToken>,
+}
+
+#[derive(Deserialize, ToSchema)]
 pub struct UpdateStatusRequest {
     pub status: CourierStatus,
+    /// With `OnBreak`: when the courier goes back to work by itself.
+    #[serde(default)]
+    pub break_until: Option<DateTime<Utc>>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct UpdateLocationRequest {
     pub location: GeoPoint,
 }
 
+#[derive(Deserialize, ToSchema)]
+pub struct UpdateShiftsRequest {
+    pub shifts: Vec<Shift>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct UpdateSkillsRequest {
+    /// Replaces the courier's tags; matched case-insensitively.
+    pub skills: Vec<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct UpdateZonesRequest {
+    /// Replaces the courier's preferred zones; an empty list clears them.
+    #[schema(example = json!(["u33d", "u33e"]))]
+    pub preferred_zones: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CourierShiftsResponse {
+    pub shifts: Vec<Shift>,
+    /// Whether the engine will currently offer the courier orders.
+    pub on_shift: bool,
+    /// The shift running now, if any.
+    pub current: Option<ShiftWindow>,
+    /// The next shift to start within the coming week.
+    pub next: Option<ShiftWindow>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct RegisterDeviceRequest {
+    pub platform: PushPlatform,
+    /// FCM registration token or APNs device token.
+    pub token: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BulkImportItemResult {
+    /// Zero-based position of the item in the submitted array or CSV data rows.
+    pub index: usize,
+    pub courier: Option<Arc<Courier>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<CourierToken>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BulkImportResponse {
+    pub created: usize,
+    pub failed: usize,
+    pub results: Vec<BulkImportItemResult>,
+}
+
+#[derive(Deserialize)]
+struct CsvCourierRow {
+    name: String,
+    lat: f64,
+    lng: f64,
+    capacity: f64,
+    rating: f64,
+    #[serde(default)]
+    vehicle_type: VehicleType,
+}
+
+impl From<CsvCourierRow> for CreateCourierRequest {
+    fn from(row: CsvCourierRow) -> Self {
+        Self {
+            name: row.name,
+            location: GeoPoint {
+                lat: row.lat,
+                lng: row.lng,
+            },
+            capacity: row.capacity,
+            rating: row.rating,
+            vehicle_type: row.vehicle_type,
+            shifts: Vec::new(),
+            skills: Vec::new(),
+            preferred_zones: Vec::new(),
+            accepts_cod: false,
+            cod_limit: None,
+            equipment: Vec::new(),
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct LeaderboardQuery {
+    /// `deliveries` (default), `rating` or `on_time_rate`.
+    #[serde(default)]
+    #[param(value_type = Option<String>)]
+    pub metric: LeaderboardMetric,
+    /// `day` (default, the last 24 hours) or `week` (the last 7 days).
+    #[serde(default)]
+    #[param(value_type = Option<String>)]
+    pub period: LeaderboardPeriod,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct CourierListQuery {
+    /// Only couriers inside this geohash cell, of at least 3 characters.
+    /// Only the shard holding that region is read.
+    pub region: Option<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct CourierAssignmentsQuery {
+    /// `true` for in-flight assignments only, `false` for completed or superseded ones.
+    pub active: Option<bool>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/couriers",
+    tag = "couriers",
+    request_body = CreateCourierRequest,
+    responses(
+        (status = 200, description = "Courier registered", body = CreateCourierResponse),
+        (status = 400, description = "Invalid courier", body = ErrorResponse)
+    )
+)]
 async fn create_courier(
     State(state): State<Arc<AppState>>,
+    principal: Principal,
     Json(payload): Json<CreateCourierRequest>,
-) -> Result<Json<Courier>, AppError> {
+) -> Result<Json<CreateCourierResponse>, AppError> {
+    principal.require(Role::Admin)?;
+    let courier = register_courier(
+        &state,
+        build_courier(payload, state.capacity_unit)?,
+        &principal.subject,
+    );
+    let token = courier_token(&state, courier.id);
+    Ok(Json(CreateCourierResponse { courier, token }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/couriers/{id}/token",
+    tag = "couriers",
+    params(("id" = Uuid, Path, description = "Courier ID")),
+    responses(
+        (status = 200, description = "New token for the courier; earlier tokens stay valid until they expire", body = CourierToken),
+        (status = 400, description = "Courier tokens are not enabled", body = ErrorResponse),
+        (status = 404, description = "Courier not found", body = ErrorResponse)
+    )
+)]
+async fn issue_courier_token(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(id): Path<Uuid>,
+) -> Result<Json<CourierToken>, AppError> {
+    principal.require(Role::Admin)?;
+    if !state.couriers.contains_key(&id) {
+        return Err(AppError::NotFound(format!("courier {id} not found")));
+    }
+    courier_token(&state, id).map(Json).ok_or_else(|| {
+        AppError::BadRequest("courier tokens are not enabled (COURIER_TOKEN_SECRET)".to_string())
+    })
+}
+
+fn courier_token(state: &AppState, courier_id: Uuid) -> Option<CourierToken> {
+    state
+        .jwt
+        .as_ref()?
+        .issue_courier_token(&state.tenant, courier_id)
+}
+
+#[utoipa::path(
+    post,
+    path = "/couriers/bulk",
+    tag = "couriers",
+    request_body(
+        content = [CreateCourierRequest],
+        description = "JSON array of couriers, or `text/csv` with header `name,lat,lng,capacity,rating` and optionally `vehicle_type`"
+    ),
+    responses(
+        (status = 200, description = "Per-item import results", body = BulkImportResponse),
+        (status = 400, description = "Body is not a JSON array or valid CSV", body = ErrorResponse)
+    )
+)]
+async fn bulk_import_couriers(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<BulkImportResponse>, AppError> {
+    principal.require(Role::Admin)?;
+    let is_csv = headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/csv"));
+
+    let items = if is_csv {
+        parse_csv_couriers(&body)
+    } else {
+        serde_json::from_slice::<Vec<serde_json::Value>>(&body)
+            .map_err(|err| AppError::BadRequest(format!("expected a JSON array: {err}")))?
+            .into_iter()
+            .map(|value| {
+                serde_json::from_value::<CreateCourierRequest>(value)
+                    .map_err(|err| format!("invalid courier: {err}"))
+            })
+            .collect()
+    };
+
+    let mut response = BulkImportResponse {
+        created: 0,
+        failed: 0,
+        results: Vec::with_capacity(items.len()),
+    };
+
+    for (index, item) in items.into_iter().enumerate() {
+        let outcome = item.and_then(|payload| {
+            build_courier(payload, state.capacity_unit).map_err(|err| err.to_string())
+        });
+
+        match outcome {
+            Ok(courier) => {
+                let courier = register_courier(&state, courier, &principal.subject);
+                response.created += 1;
+                response.results.push(BulkImportItemResult {
+                    index,
+                    token: courier_token(&state, courier.id),
+                    courier: Some(courier),
+                    error: None,
+                });
+            }
+            Err(error) => {
+                response.failed += 1;
+                response.results.push(BulkImportItemResult {
+                    index,
+                    courier: None,
+                    token: None,
+                    error: Some(error),
+                });
+            }
+        }
+    }
+
+    Ok(Json(response))
+}
+
+fn parse_csv_couriers(body: &[u8]) -> Vec<Result<CreateCourierRequest, String>> {
+    csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(body)
+        .deserialize::<CsvCourierRow>()
+        .map(|row| {
+            row.map(CreateCourierRequest::from)
+                .map_err(|err| format!("invalid csv row: {err}"))
+        })
+        .collect()
+}
+
+pub(crate) fn build_courier(
+    payload: CreateCourierRequest,
+    unit: CapacityUnit,
+) -> Result<Courier, AppError> {
     if payload.name.trim().is_empty() {
         return Err(AppError::BadRequest("name cannot be empty".to_string()));
     }
 
-    if payload.capacity == 0 {
-        return Err(AppError::BadRequest("capacity must be > 0".to_string()));
+    validate_amount("capacity", payload.capacity, unit)?;
+    validate_point("location", &payload.location)?;
+    validate_rating(payload.rating)?;
+    validate_shifts(&payload.shifts)?;
+    let skills = normalize_skills("skills", payload.skills)?;
+    let preferred_zones = normalize_zones("preferred_zones", payload.preferred_zones)?;
+    if let Some(limit) = payload.cod_limit {
+        validate_cash("cod_limit", limit)?;
     }
 
-    let courier = Courier {
-        id: Uuid::new_v4(),
+    Ok(Courier {
+        id: Uuid::now_v7(),
         name: payload.name,
+        location_cache: CachedLocation::new(&payload.location),
         location: payload.location,
         capacity: payload.capacity,
-        current_load: 0,
+        current_load: 0.0,
         status: CourierStatus::Available,
+        break_until: None,
         rating: payload.rating.clamp(0.0, 5.0),
+        offers: OfferHistory::default(),
+        last_assigned_at: None,
+        vehicle_type: payload.vehicle_type,
         updated_at: Utc::now(),
+        last_seen_at: Utc::now(),
+        shifts: payload.shifts,
+        skills,
+        preferred_zones,
+        accepts_cod: payload.accepts_cod,
+        cod_limit: payload.cod_limit,
+        equipment: payload.equipment,
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/couriers",
+    tag = "couriers",
+    params(CourierListQuery),
+    responses(
+        (status = 200, description = "All couriers, or those in the region", body = [Courier]),
+        (status = 400, description = "region is not a geohash of 3 to 12 characters", body = ErrorResponse)
+    )
+)]
+async fn list_couriers(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Query(query): Query<CourierListQuery>,
+) -> Result<Response, AppError> {
+    principal.require(Role::Dispatcher)?;
+    let region = query.region.map(|region| region.trim().to_lowercase());
+    if let Some(region) = &region
+        && (region.len() < REGION_PRECISION || !is_geohash(region))
+    {
+        return Err(AppError::BadRequest(format!(
+            "region must be a geohash of {REGION_PRECISION} to {MAX_GEOHASH_PRECISION} characters"
+        )));
+    }
+
+    let couriers: Vec<Arc<Courier>> = {
+        let _snapshot = state.snapshots.read();
+        match &region {
+            Some(region) => state
+                .couriers
+                .within(region)
+                .map(|entry| Arc::clone(entry.value()))
+                .collect(),
+            None => state
+                .couriers
+                .iter()
+                .map(|entry| Arc::clone(entry.value()))
+                .collect(),
+        }
     };
+    Ok(json_array(couriers))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/couriers/{id}/status",
+    tag = "couriers",
+    params(("id" = Uuid, Path, description = "Courier ID")),
+    request_body = UpdateStatusRequest,
+    responses(
+        (status = 200, description = "Updated courier", body = Courier),
+        (status = 400, description = "break_until without OnBreak or in the past", body = ErrorResponse),
+        (status = 404, description = "Courier not found", body = ErrorResponse)
+    )
+)]
+async fn update_courier_status(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<UpdateStatusRequest>,
+) -> Result<Json<Arc<Courier>>, AppError> {
+    principal.require_courier(id)?;
+    let courier = set_courier_status(
+        &state,
+        id,
+        payload.status,
+        payload.break_until,
+        &principal.subject,
+    )?;
+    Ok(Json(courier))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/couriers/{id}/location",
+    tag = "couriers",
+    params(("id" = Uuid, Path, description = "Courier ID")),
+    request_body = UpdateLocationRequest,
+    responses(
+        (status = 200, description = "Updated courier", body = Courier),
+        (status = 404, description = "Courier not found", body = ErrorResponse)
+    )
+)]
+async fn update_courier_location(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<UpdateLocationRequest>,
+) -> Result<Json<Arc<Courier>>, AppError> {
+    principal.require_courier(id)?;
+    let courier = move_courier(&state, id, payload.location)?;
+    Ok(Json(courier))
+}
 
-    state.couriers.insert(courier.id, courier.clone());
+/// Keeps a courier from being taken offline by `COURIER_HEARTBEAT_TIMEOUT_SECS`
+/// while it has nothing else to report.
+#[utoipa::path(
+    post,
+    path = "/couriers/{id}/heartbeat",
+    tag = "couriers",
+    params(("id" = Uuid, Path, description = "Courier ID")),
+    responses(
+        (status = 200, description = "Courier with the new last_seen_at", body = Courier),
+        (status = 404, description = "Courier not found", body = ErrorResponse)
+    )
+)]
+async fn courier_heartbeat(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Arc<Courier>>, AppError> {
+    principal.require_courier(id)?;
+    let courier = record_heartbeat(&state, id)?;
     Ok(Json(courier))
 }
 
-async fn list_couriers(State(state): State<Arc<AppState>>) -> Json<Vec<Courier>> {
-    let couriers = state
+#[utoipa::path(
+    get,
+    path = "/couriers/{id}/shifts",
+    tag = "couriers",
+    params(("id" = Uuid, Path, description = "Courier ID")),
+    responses(
+        (status = 200, description = "Working hours with the running and next shift", body = CourierShiftsResponse),
+        (status = 404, description = "Courier not found", body = ErrorResponse)
+    )
+)]
+async fn get_courier_shifts(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(id): Path<Uuid>,
+) -> Result<Json<CourierShiftsResponse>, AppError> {
+    principal.require_courier_read(id)?;
+    let shifts = state
         .couriers
+        .get(&id)
+        .map(|courier| shifts_response(&courier, state.clock.now()))
+        .ok_or_else(|| AppError::NotFound(format!("courier {} not found", id)))?;
+    Ok(Json(shifts))
+}
+
+#[utoipa::path(
+    put,
+    path = "/couriers/{id}/shifts",
+    tag = "couriers",
+    params(("id" = Uuid, Path, description = "Courier ID")),
+    request_body = UpdateShiftsRequest,
+    responses(
+        (status = 200, description = "Working hours replaced", body = CourierShiftsResponse),
+        (status = 400, description = "Invalid shift", body = ErrorResponse),
+        (status = 404, description = "Courier not found", body = ErrorResponse)
+    )
+)]
+async fn update_courier_shifts(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<UpdateShiftsRequest>,
+) -> Result<Json<CourierShiftsResponse>, AppError> {
+    principal.require(Role::Admin)?;
+    validate_shifts(&payload.shifts)?;
+    let courier = set_courier_shifts(&state, id, payload.shifts, &principal.subject)?;
+    Ok(Json(shifts_response(&courier, state.clock.now())))
+}
+
+#[utoipa::path(
+    put,
+    path = "/couriers/{id}/skills",
+    tag = "couriers",
+    params(("id" = Uuid, Path, description = "Courier ID")),
+    request_body = UpdateSkillsRequest,
+    responses(
+        (status = 200, description = "Skills replaced", body = Courier),
+        (status = 400, description = "Empty skill tag", body = ErrorResponse),
+        (status = 404, description = "Courier not found", body = ErrorResponse)
+    )
+)]
+async fn update_courier_skills(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<UpdateSkillsRequest>,
+) -> Result<Json<Arc<Courier>>, AppError> {
+    principal.require(Role::Admin)?;
+    let skills = normalize_skills("skills", payload.skills)?;
+    let courier = set_courier_skills(&state, id, skills, &principal.subject)?;
+    Ok(Json(courier))
+}
+
+/// Couriers set their own preferred zones; see `ZONE_MODE` for how they are
+/// used.
+#[utoipa::path(
+    put,
+    path = "/couriers/{id}/zones",
+    tag = "couriers",
+    params(("id" = Uuid, Path, description = "Courier ID")),
+    request_body = UpdateZonesRequest,
+    responses(
+        (status = 200, description = "Preferred zones replaced", body = Courier),
+        (status = 400, description = "Not a geohash", body = ErrorResponse),
+        (status = 404, description = "Courier not found", body = ErrorResponse)
+    )
+)]
+async fn update_courier_zones(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<UpdateZonesRequest>,
+) -> Result<Json<Arc<Courier>>, AppError> {
+    principal.require_courier(id)?;
+    let zones = normalize_zones("preferred_zones", payload.preferred_zones)?;
+    let courier = set_courier_zones(&state, id, zones, &principal.subject)?;
+    Ok(Json(courier))
+}
+
+fn shifts_response(courier: &Courier, now: DateTime<Utc>) -> CourierShiftsResponse {
+    let (current, next) = courier.shift_windows(now);
+    CourierShiftsResponse {
+        on_shift: courier.is_on_shift(now),
+        shifts: courier.shifts.clone(),
+        current,
+        next,
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/couriers/{id}/stats",
+    tag = "couriers",
+    params(("id" = Uuid, Path, description = "Courier ID")),
+    responses(
+        (status = 200, description = "Deliveries, distance and earnings so far", body = CourierStats),
+        (status = 404, description = "Courier not found", body = ErrorResponse)
+    )
+)]
+async fn get_courier_stats(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(id): Path<Uuid>,
+) -> Result<Json<CourierStats>, AppError> {
+    principal.require_courier_read(id)?;
+    if !state.couriers.contains_key(&id) {
+        return Err(AppError::NotFound(format!("courier {} not found", id)));
+    }
+
+    let active_orders = state
+        .orders
         .iter()
+        .filter(|entry| {
+            entry.assigned_courier == Some(id)
+                && matches!(entry.status, OrderStatus::Assigned | OrderStatus::InTransit)
+        })
+        .count();
+    Ok(Json(CourierStats {
+        courier_id: id,
+        totals: state
+            .courier_stats
+            .get(&id)
+            .map(|stats| *stats)
+            .unwrap_or_default(),
+        active_orders,
+        earnings_today: state.earnings.today(state.clock.now()).of(id),
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/fleet/stats",
+    tag = "couriers",
+    responses(
+        (status = 200, description = "Delivery totals over all couriers", body = FleetStats),
+        (status = 403, description = "Dispatcher role required", body = ErrorResponse)
+    )
+)]
+async fn get_fleet_stats(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+) -> Result<Json<FleetStats>, AppError> {
+    principal.require(Role::Dispatcher)?;
+    let _snapshot = state.snapshots.read();
+    let mut totals = DeliveryStats::default();
+    let mut active_couriers = 0;
+    for stats in state.courier_stats.iter() {
+        totals.add(stats.value());
+        if stats.deliveries > 0 {
+            active_couriers += 1;
+        }
+    }
+    Ok(Json(FleetStats {
+        couriers: state.couriers.len(),
+        active_couriers,
+        totals,
+        median_earnings_today: state.earnings.today(state.clock.now()).median(),
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/couriers/leaderboard",
+    tag = "couriers",
+    params(LeaderboardQuery),
+    responses(
+        (status = 200, description = "Couriers ranked by the metric over the period", body = Leaderboard),
+        (status = 400, description = "Unknown metric or period", body = ErrorResponse),
+        (status = 403, description = "Dispatcher role required", body = ErrorResponse)
+    )
+)]
+async fn get_leaderboard(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Query(query): Query<LeaderboardQuery>,
+) -> Result<Json<Leaderboard>, AppError> {
+    principal.require(Role::Dispatcher)?;
+    Ok(Json(leaderboard(
+        &state,
+        query.metric,
+        query.period,
+        state.clock.now(),
+    )))
+}
+
+#[utoipa::path(
+    get,
+    path = "/couriers/{id}/assignments",
+    tag = "couriers",
+    params(("id" = Uuid, Path, description = "Courier ID"), CourierAssignmentsQuery),
+    responses(
+        (status = 200, description = "Assignments for the courier, oldest first", body = [Assignment]),
+        (status = 404, description = "Courier not found", body = ErrorResponse)
+    )
+)]
+async fn list_courier_assignments(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(id): Path<Uuid>,
+    Query(query): Query<CourierAssignmentsQuery>,
+) -> Result<Json<Vec<Assignment>>, AppError> {
+    principal.require_courier_read(id)?;
+    let _snapshot = state.snapshots.read();
+    if !state.couriers.contains_key(&id) {
+        return Err(AppError::NotFound(format!("courier {} not found", id)));
+    }
+
+    let mut assignments: Vec<Assignment> = state
+        .assignments
+        .iter()
+        .filter(|entry| entry.value().courier_id == id)
+        .filter(|entry| match query.active {
+            Some(active) => is_active(&state, entry.value()) == active,
+            None => true,
+        })
         .map(|entry| entry.value().clone())
         .collect();
-    Json(couriers)
+
+    assignments.sort_by_key(|assignment| assignment.assigned_at);
+    Ok(Json(assignments))
 }
 
-async fn update_courier_status(
+#[utoipa::path(
+    get,
+    path = "/couriers/{id}/route",
+    tag = "couriers",
+    params(("id" = Uuid, Path, description = "Courier ID")),
+    responses(
+        (status = 200, description = "Pickups and dropoffs left across the courier's orders, in visiting order", body = CourierRoute),
+        (status = 404, description = "Courier not found", body = ErrorResponse)
+    )
+)]
+async fn get_courier_route(
     State(state): State<Arc<AppState>>,
+    principal: Principal,
     Path(id): Path<Uuid>,
-    Json(payload): Json<UpdateStatusRequest>,
-) -> Result<Json<Courier>, AppError> {
-    let mut courier = state
-        .couriers
-        .get_mut(&id)
-        .ok_or_else(|| AppError::NotFound(format!("courier {} not found", id)))?;
+) -> Result<Json<CourierRoute>, AppError> {
+    principal.require_courier_read(id)?;
+    Ok(Json(courier_route(&state, id)?))
+}
 
-    courier.status = payload.status;
-    courier.updated_at = Utc::now();
+#[utoipa::path(
+    get,
+    path = "/couriers/{id}/feedback",
+    tag = "couriers",
+    params(("id" = Uuid, Path, description = "Courier ID")),
+    responses(
+        (status = 200, description = "Customer feedback on the courier's deliveries, newest first", body = [Feedback]),
+        (status = 404, description = "Courier not found", body = ErrorResponse)
+    )
+)]
+async fn get_courier_feedback(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<Feedback>>, AppError> {
+    principal.require_courier_read(id)?;
+    Ok(Json(courier_feedback(&state, id)?))
+}
 
-    Ok(Json(courier.clone()))
+fn is_active(state: &AppState, assignment: &Assignment) -> bool {
+    state
+        .orders
+        .get(&assignment.order_id)
+        .map(|order| {
+            order.assigned_courier == Some(assignment.courier_id)
+                && matches!(order.status, OrderStatus::Assigned | OrderStatus::InTransit)
+        })
+        .unwrap_or(false)
 }
 
-async fn update_courier_location(
+#[utoipa::path(
+    post,
+    path = "/couriers/{id}/devices",
+    tag = "couriers",
+    params(("id" = Uuid, Path, description = "Courier ID")),
+    request_body = RegisterDeviceRequest,
+    responses(
+        (status = 200, description = "Device registered; re-registering a token refreshes it", body = CourierDevice),
+        (status = 400, description = "Empty token", body = ErrorResponse),
+        (status = 404, description = "Courier not found", body = ErrorResponse)
+    )
+)]
+async fn register_device(
     State(state): State<Arc<AppState>>,
+    principal: Principal,
     Path(id): Path<Uuid>,
-    Json(payload): Json<UpdateLocationRequest>,
-) -> Result<Json<Courier>, AppError> {
-    let mut courier = state
-        .couriers
-        .get_mut(&id)
-        .ok_or_else(|| AppError::NotFound(format!("courier {} not found", id)))?;
+    Json(payload): Json<RegisterDeviceRequest>,
+) -> Result<Json<CourierDevice>, AppError> {
+    principal.require_courier(id)?;
+    if !state.couriers.contains_key(&id) {
+        return Err(AppError::NotFound(format!("courier {} not found", id)));
+    }
+    if payload.token.trim().is_empty() {
+        return Err(AppError::BadRequest("token cannot be empty".to_string()));
+    }
 
-    courier.location = payload.location;
-    courier.updated_at = Utc::now();
+    let device = CourierDevice {
+        courier_id: id,
+        platform: payload.platform,
+        token: payload.token,
+        registered_at: Utc::now(),
+    };
+
+    let mut devices = state.courier_devices.entry(id).or_default();
+    devices.retain(|existing| existing.token != device.token);
+    devices.push(device.clone());
+
+    Ok(Json(device))
+}
 
-    Ok(Json(courier.clone()))
+#[utoipa::path(
+    get,
+    path = "/couriers/{id}/devices",
+    tag = "couriers",
+    params(("id" = Uuid, Path, description = "Courier ID")),
+    responses(
+        (status = 200, description = "Devices registered for the courier", body = [CourierDevice]),
+        (status = 404, description = "Courier not found", body = ErrorResponse)
+    )
+)]
+async fn list_devices(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<CourierDevice>>, AppError> {
+    principal.require_courier(id)?;
+    if !state.couriers.contains_key(&id) {
+        return Err(AppError::NotFound(format!("courier {} not found", id)));
+    }
+
+    let devices = state
+        .courier_devices
+        .get(&id)
+        .map(|devices| devices.clone())
+        .unwrap_or_default();
+    Ok(Json(devices))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/couriers/{id}/devices/{token}",
+    tag = "couriers",
+    params(
+        ("id" = Uuid, Path, description = "Courier ID"),
+        ("token" = String, Path, description = "Device token")
+    ),
+    responses(
+        (status = 204, description = "Device removed"),
+        (status = 404, description = "Device not found", body = ErrorResponse)
+    )
+)]
+async fn unregister_device(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path((id, token)): Path<(Uuid, String)>,
+) -> Result<StatusCode, AppError> {
+    principal.require_courier(id)?;
+    if state.remove_courier_device(id, &token) {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(AppError::NotFound(format!(
+            "device not registered for courier {}",
+            id
+        )))
+    }
 }
@@ -0,0 +1,226 @@
+use std::sync::Arc;
+
+use axum::response::Html;
+use axum::routing::get;
+use axum::Json;
+use axum::Router;
+use serde::Serialize;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi, ToSchema};
+
+use crate::api::rest::{
+    audit, couriers, orders, sse, stats, webhooks, HealthResponse, LiveResponse,
+};
+use crate::auth::CourierToken;
+use crate::engine::status::{EngineHealth, Readiness};
+use crate::models::assignment::{Assignment, AssignmentPreview, PreviewCandidate, ScoreBreakdown};
+use crate::models::audit::{AuditEntity, AuditEntry};
+use crate::models::courier::{Courier, CourierStatus, GeoPoint, OfferHistory, VehicleType};
+use crate::models::device::{CourierDevice, PushPlatform};
+use crate::models::event::Topic;
+use crate::models::feedback::Feedback;
+use crate::models::history::{OrderHistoryEntry, OrderHistoryEvent};
+use crate::models::order::{
+    DeliveryOrder, Handling, OrderDeadLetter, OrderItem, OrderStatus, OrderTracking, Priority,
+    WaitingReason,
+};
+use crate::models::report::{DailyReport, ReportTotals, ZoneReport};
+use crate::models::route::{CourierRoute, RouteStop, StopKind};
+use crate::models::shift::{Shift, ShiftWindow};
+use crate::models::stats::{
+    CourierStats, DeliveryStats, DispatchSummary, FleetStats, Leaderboard, LeaderboardEntry,
+    LeaderboardMetric, LeaderboardPeriod, TimeSeries, TimeSeriesPoint,
+};
+use crate::models::surge::SurgeEvent;
+use crate::models::webhook::{DeadLetter, Webhook};
+use crate::state::AppState;
+
+const SWAGGER_UI_HTML: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="UTF-8" />
+  <title>Dispatch Router API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css" />
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+  <script>
+    window.ui = SwaggerUIBundle({ url: "/openapi.json", dom_id: "#swagger-ui" });
+  </script>
+</body>
+</html>
+"##;
+
+/// Body returned by every failing REST handler (see `AppError::into_response`).
+#[derive(Serialize, ToSchema)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+/// Declares the `bearer` JWT scheme that JWT auth (`JWT_JWKS_URL`) checks.
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme(
+                "bearer",
+                SecurityScheme::Http(
+                    HttpBuilder::new()
+                        .scheme(HttpAuthScheme::Bearer)
+                        .bearer_format("JWT")
+                        .build(),
+                ),
+            );
+        }
+    }
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "dispatch-router", description = "Real-time delivery assignment service"),
+    modifiers(&BearerAuth),
+    security(("bearer" = [])),
+    paths(
+        couriers::create_courier,
+        couriers::bulk_import_couriers,
+        couriers::issue_courier_token,
+        couriers::list_couriers,
+        couriers::update_courier_status,
+        couriers::update_courier_location,
+        couriers::courier_heartbeat,
+        couriers::get_courier_shifts,
+        couriers::update_courier_shifts,
+        couriers::update_courier_skills,
+        couriers::update_courier_zones,
+        couriers::get_courier_stats,
+        couriers::get_fleet_stats,
+        couriers::get_leaderboard,
+        couriers::list_courier_assignments,
+        couriers::get_courier_route,
+        couriers::get_courier_feedback,
+        couriers::register_device,
+        couriers::list_devices,
+        couriers::unregister_device,
+        orders::create_order,
+        orders::preview_order,
+        orders::get_order,
+        orders::list_order_dead_letters,
+        orders::search_orders,
+        orders::update_order_status,
+        orders::decline_assigned_order,
+        orders::create_order_feedback,
+        orders::get_order_assignment,
+        orders::get_order_history,
+        orders::track_order,
+        orders::list_assignments,
+        sse::event_stream,
+        webhooks::create_webhook,
+        webhooks::list_webhooks,
+        webhooks::delete_webhook,
+        webhooks::list_dead_letters,
+        audit::list_audit_entries,
+        stats::get_dispatch_stats,
+        stats::get_dispatch_timeseries,
+        stats::get_daily_report,
+        stats::list_surges,
+        crate::api::rest::health,
+        crate::api::rest::engine_health,
+        crate::api::rest::live,
+        crate::api::rest::ready,
+        crate::api::rest::metrics,
+    ),
+    components(schemas(
+        GeoPoint,
+        Courier,
+        CourierStatus,
+        VehicleType,
+        OfferHistory,
+        Shift,
+        ShiftWindow,
+        DeliveryStats,
+        CourierStats,
+        FleetStats,
+        DispatchSummary,
+        TimeSeries,
+        TimeSeriesPoint,
+        DailyReport,
+        ReportTotals,
+        ZoneReport,
+        SurgeEvent,
+        Leaderboard,
+        LeaderboardEntry,
+        LeaderboardMetric,
+        LeaderboardPeriod,
+        DeliveryOrder,
+        OrderItem,
+        Handling,
+        OrderDeadLetter,
+        OrderStatus,
+        Priority,
+        WaitingReason,
+        OrderTracking,
+        CourierRoute,
+        Feedback,
+        OrderHistoryEntry,
+        OrderHistoryEvent,
+        RouteStop,
+        StopKind,
+        Assignment,
+        ScoreBreakdown,
+        AssignmentPreview,
+        PreviewCandidate,
+        couriers::CreateCourierRequest,
+        couriers::CreateCourierResponse,
+        CourierToken,
+        couriers::BulkImportItemResult,
+        couriers::BulkImportResponse,
+        couriers::UpdateStatusRequest,
+        couriers::UpdateLocationRequest,
+        couriers::UpdateShiftsRequest,
+        couriers::CourierShiftsResponse,
+        couriers::UpdateSkillsRequest,
+        couriers::UpdateZonesRequest,
+        couriers::RegisterDeviceRequest,
+        CourierDevice,
+        PushPlatform,
+        orders::CreateOrderRequest,
+        orders::UpdateOrderStatusRequest,
+        orders::FeedbackRequest,
+        webhooks::CreateWebhookRequest,
+        Webhook,
+        DeadLetter,
+        Topic,
+        AuditEntity,
+        AuditEntry,
+        HealthResponse,
+        EngineHealth,
+        LiveResponse,
+        Readiness,
+        ErrorResponse,
+    )),
+    tags(
+        (name = "couriers", description = "Courier fleet management"),
+        (name = "orders", description = "Order intake and lookup"),
+        (name = "assignments", description = "Assignment history"),
+        (name = "webhooks", description = "Outbound event delivery"),
+        (name = "audit", description = "Change history for couriers and orders"),
+        (name = "system", description = "Health and metrics")
+    )
+)]
+pub struct ApiDoc;
+
+pub fn router() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/openapi.json", get(openapi_json))
+        .route("/docs", get(swagger_ui))
+}
+
+async fn openapi_json() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+async fn swagger_ui() -> Html<&'static str> {
+    Html(SWAGGER_UI_HTML)
+}
@@ -0,0 +1,63 @@
+use axum::body::{Body, Bytes};
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+use futures::stream::{self, StreamExt};
+use serde::Serialize;
+
+/// Items encoded per body chunk.
+const CHUNK_ITEMS: usize = 256;
+
+/// Streams `items` as a JSON array, encoding them a chunk at a time as the
+/// body is sent, so the whole encoded body is never held at once. Callers
+/// copy `items` out under [`crate::snapshot::Snapshots::read`] and hold no
+/// map lock while the client reads.
+pub fn json_array<T>(items: Vec<T>) -> Response
+where
+    T: Serialize + Send + 'static,
+{
+    let mut first = true;
+    let items = stream::iter(items).chunks(CHUNK_ITEMS).map(move |chunk| {
+        let mut buffer = Vec::new();
+        for item in &chunk {
+            if !first {
+                buffer.push(b',');
+            }
+            first = false;
+            serde_json::to_writer(&mut buffer, item)?;
+        }
+        Ok::<_, serde_json::Error>(Bytes::from(buffer))
+    });
+    let body = stream::once(async { Ok(Bytes::from_static(b"[")) })
+        .chain(items)
+        .chain(stream::once(async { Ok(Bytes::from_static(b"]")) }));
+
+    (
+        [(header::CONTENT_TYPE, "application/json")],
+        Body::from_stream(body),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::to_bytes;
+    use serde_json::{json, Value};
+
+    use super::json_array;
+
+    #[tokio::test]
+    async fn streams_a_valid_array_across_chunks() {
+        let items: Vec<Value> = (0..600).map(|n| json!({ "n": n })).collect();
+        let res = json_array(items);
+        let body: Value =
+            serde_json::from_slice(&to_bytes(res.into_body(), usize::MAX).await.unwrap()).unwrap();
+        let items = body.as_array().unwrap();
+        assert_eq!(items.len(), 600);
+        assert_eq!(items[0]["n"], 0);
+        assert_eq!(items[599]["n"], 599);
+
+        let res = json_array(Vec::<u32>::new());
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&body[..], b"[]");
+    }
+}
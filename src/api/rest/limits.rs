@@ -0,0 +1,56 @@
+use std::time::Duration;
+
+use axum::error_handling::HandleErrorLayer;
+use axum::extract::DefaultBodyLimit;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::{BoxError, Json, Router};
+use serde_json::json;
+use tower::limit::GlobalConcurrencyLimitLayer;
+use tower::ServiceBuilder;
+use tower_http::limit::RequestBodyLimitLayer;
+use tower_http::timeout::TimeoutLayer;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RequestLimits {
+    /// Larger bodies get `413`, checked against `content-length` up front and
+    /// while reading chunked bodies.
+    pub max_body_bytes: usize,
+    /// Time allowed until the response starts, reading the body included;
+    /// slower requests get `408`. `None` disables. Streams (WebSocket, SSE)
+    /// start their response right away, so they are not cut off.
+    pub timeout: Option<Duration>,
+    /// Requests handled at once across all clients; more get `503` instead of
+    /// queueing. Zero means unlimited.
+    pub max_concurrent: usize,
+}
+
+/// Wraps the REST router in the body size, timeout and concurrency limits.
+pub fn apply(router: Router, limits: &RequestLimits) -> Router {
+    let mut router = router
+        .layer(RequestBodyLimitLayer::new(limits.max_body_bytes))
+        // Extractors have their own 2 MB default; the layer above replaces it.
+        .layer(DefaultBodyLimit::disable());
+
+    if let Some(timeout) = limits.timeout {
+        router = router.layer(TimeoutLayer::new(timeout));
+    }
+
+    if limits.max_concurrent > 0 {
+        router = router.layer(
+            ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(overloaded))
+                .load_shed()
+                .layer(GlobalConcurrencyLimitLayer::new(limits.max_concurrent)),
+        );
+    }
+
+    router
+}
+
+async fn overloaded(_: BoxError) -> impl IntoResponse {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(json!({ "error": "too many concurrent requests" })),
+    )
+}
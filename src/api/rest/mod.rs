@@ -1,50 +1,278 @@
+pub mod audit;
+pub mod auth;
+pub mod cors;
 pub mod couriers;
+pub mod docs;
+pub mod json_stream;
+pub mod limits;
 pub mod orders;
+pub mod rate_limit;
+pub mod sse;
+pub mod stats;
+pub mod tenants;
+pub mod webhooks;
 pub mod ws;
 
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 
-use axum::extract::State;
-use axum::http::StatusCode;
-use axum::response::IntoResponse;
+use axum::body::Body;
+use axum::extract::{ConnectInfo, State};
+use axum::http::{header, HeaderMap, HeaderName, Request, StatusCode};
+use axum::middleware;
+use axum::response::{IntoResponse, Response};
 use axum::routing::get;
 use axum::Json;
 use axum::Router;
 use serde::Serialize;
+use tower::ServiceBuilder;
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
 use tower_http::services::ServeDir;
+use tower_http::trace::TraceLayer;
+use tracing::Span;
+use utoipa::ToSchema;
 
+use crate::api::REQUEST_ID_HEADER;
+use crate::engine::status::{EngineHealth, Readiness};
+use crate::observability::metrics::Metrics;
 use crate::state::AppState;
+use crate::tenants::Tenants;
 
 pub fn router(state: Arc<AppState>) -> Router {
-    Router::new()
+    let serve_metrics = !state.metrics_access.separate_port;
+    routes(state, serve_metrics)
+}
+
+/// The API of one tenant; `/metrics` only when `serve_metrics`.
+pub(crate) fn routes(state: Arc<AppState>, serve_metrics: bool) -> Router {
+    let api = if serve_metrics {
+        Router::new().route("/metrics", get(metrics))
+    } else {
+        Router::new()
+    };
+
+    api.merge(audit::router())
         .merge(couriers::router())
         .merge(orders::router())
+        .merge(docs::router())
+        .merge(sse::router())
+        .merge(stats::router())
+        .merge(webhooks::router())
         .route("/health", get(health))
-        .route("/metrics", get(metrics))
+        .route("/health/engine", get(engine_health))
+        .route("/live", get(live))
+        .route("/ready", get(ready))
         .route("/ws", get(ws::ws_handler))
-        .with_state(state)
+        .with_state(state.clone())
         .fallback_service(ServeDir::new("static"))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            rate_limit::limit,
+        ))
+        .layer(middleware::from_fn_with_state(state, chaos_latency))
+        .layer(
+            ServiceBuilder::new()
+                .layer(SetRequestIdLayer::new(
+                    HeaderName::from_static(REQUEST_ID_HEADER),
+                    MakeRequestUuid,
+                ))
+                .layer(
+                    TraceLayer::new_for_http()
+                        .make_span_with(http_span)
+                        .on_response(log_response),
+                )
+                .layer(PropagateRequestIdLayer::new(HeaderName::from_static(
+                    REQUEST_ID_HEADER,
+                ))),
+        )
+}
+
+/// Holds up requests at the `CHAOS_LATENCY_RATE`.
+async fn chaos_latency(
+    State(state): State<Arc<AppState>>,
+    request: Request<Body>,
+    next: middleware::Next,
+) -> Response {
+    if let Some(latency) = state.chaos.latency() {
+        tokio::time::sleep(latency).await;
+    }
+    next.run(request).await
+}
+
+/// Just `/metrics`, covering every tenant, for serving on an internal port
+/// (`METRICS_PORT`).
+pub fn metrics_router(tenants: Arc<Tenants>) -> Router {
+    Router::new()
+        .route("/metrics", get(tenants::metrics))
+        .with_state(tenants)
+}
+
+fn http_span(request: &Request<Body>) -> Span {
+    tracing::info_span!(
+        "http",
+        method = %request.method(),
+        uri = %request.uri(),
+        request_id = request_id(request.headers()),
+        client = request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| display(addr)),
+        user_agent = request
+            .headers()
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok()),
+    )
+}
+
+/// The access log line; the span supplies method, URI and client.
+fn log_response<B>(response: &Response<B>, latency: Duration, _span: &Span) {
+    tracing::info!(
+        status = response.status().as_u16(),
+        latency_ms = latency.as_secs_f64() * 1000.0,
+        "http request"
+    );
+}
+
+/// The request's `x-request-id`, which the router sets when the client
+/// didn't.
+pub fn request_id(headers: &axum::http::HeaderMap) -> Option<&str> {
+    headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
 }
 
-#[derive(Serialize)]
-struct HealthResponse {
+#[derive(Serialize, ToSchema)]
+pub struct HealthResponse {
     status: &'static str,
     couriers: usize,
     orders: usize,
     assignments: usize,
+    ws_connections: i64,
 }
 
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "system",
+    security(()),
+    responses((status = 200, description = "Service is up", body = HealthResponse))
+)]
 async fn health(State(state): State<Arc<AppState>>) -> Json<HealthResponse> {
+    let _snapshot = state.snapshots.read();
     Json(HealthResponse {
         status: "ok",
         couriers: state.couriers.len(),
         orders: state.orders.len(),
         assignments: state.assignments.len(),
+        ws_connections: state.metrics.ws_connections_active.get(),
     })
 }
 
-async fn metrics(State(state): State<Arc<AppState>>) -> impl IntoResponse {
-    match state.metrics.encode() {
+/// Assignment engine detail: `200` while it runs and keeps up with the
+/// queue, `503` once it has stopped or stalled.
+#[utoipa::path(
+    get,
+    path = "/health/engine",
+    tag = "system",
+    security(()),
+    responses(
+        (status = 200, description = "Engine is running", body = EngineHealth),
+        (status = 503, description = "Engine stopped or stalled", body = EngineHealth)
+    )
+)]
+async fn engine_health(State(state): State<Arc<AppState>>) -> (StatusCode, Json<EngineHealth>) {
+    let health = state.engine.health(
+        state.queue_depth(),
+        state.clock.now(),
+        state.tunables().requeue_delay,
+    );
+    let status = if health.status == "ok" {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(health))
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct LiveResponse {
+    status: &'static str,
+}
+
+/// Liveness: answers `200` whenever the process can serve HTTP at all. Says
+/// nothing about the engine; restart on this, route traffic on `/ready`.
+#[utoipa::path(
+    get,
+    path = "/live",
+    tag = "system",
+    security(()),
+    responses((status = 200, description = "Process is up", body = LiveResponse))
+)]
+async fn live() -> Json<LiveResponse> {
+    Json(LiveResponse { status: "ok" })
+}
+
+/// Readiness: `503` while the engine is stopped or stalled, during
+/// shutdown, or with the order queue close to full.
+#[utoipa::path(
+    get,
+    path = "/ready",
+    tag = "system",
+    security(()),
+    responses(
+        (status = 200, description = "Ready for traffic", body = Readiness),
+        (status = 503, description = "Not ready; `reasons` says why", body = Readiness)
+    )
+)]
+async fn ready(State(state): State<Arc<AppState>>) -> (StatusCode, Json<Readiness>) {
+    let readiness = state.readiness();
+    let status = if readiness.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(readiness))
+}
+
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "system",
+    security(()),
+    responses(
+        (status = 200, description = "Prometheus text exposition", content_type = "text/plain"),
+        (status = 401, description = "METRICS_BEARER_TOKEN or METRICS_BASIC_AUTH is set and the request lacks it")
+    )
+)]
+async fn metrics(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Response {
+    render_metrics(&headers, [&state])
+}
+
+/// The metrics of `states` in one exposition, once the request passed the
+/// first state's `metrics_access`.
+fn render_metrics<'a>(
+    headers: &HeaderMap,
+    states: impl IntoIterator<Item = &'a Arc<AppState>>,
+) -> Response {
+    let states: Vec<_> = states.into_iter().collect();
+    let authorization = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    if !states[0].metrics_access.allows(authorization) {
+        return (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Basic realm=\"metrics\"")],
+        )
+            .into_response();
+    }
+
+    let runtime = tokio::runtime::Handle::current().metrics();
+    for state in &states {
+        state.metrics.refresh_fleet(&state.couriers);
+        state.metrics.refresh_runtime(&runtime);
+    }
+    match Metrics::encode_all(states.iter().map(|state| &state.metrics)) {
         Ok(body) => (
             StatusCode::OK,
             [("content-type", "text/plain; version=0.0.4; charset=utf-8")],
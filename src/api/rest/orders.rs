@@ -1,72 +1,562 @@
+use std::cmp::Reverse;
+use std::convert::Infallible;
+use std::str::FromStr;
 use std::sync::Arc;
 
-use axum::extract::{Path, State};
-use axum::routing::{get, post};
+use axum::extract::{Path, Query, State};
+use axum::http::HeaderMap;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::Response;
+use axum::routing::{get, patch, post};
 use axum::Json;
 use axum::Router;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
+use futures::stream::{self, Stream, StreamExt};
 use serde::Deserialize;
+use tokio_stream::wrappers::BroadcastStream;
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 
-use crate::engine::queue::enqueue_order;
+use crate::api::rest::json_stream::json_array;
+use crate::api::rest::request_id;
+use crate::auth::{Principal, Role};
+use crate::engine::assignment::preview_assignment;
+use crate::engine::feedback::submit_feedback;
+use crate::engine::lifecycle::{decline_order, transition_order};
+use crate::engine::queue::{build_order, submit_order};
+use crate::engine::tracking::{affects_tracking, order_tracking};
 use crate::error::AppError;
-use crate::models::assignment::Assignment;
-use crate::models::courier::GeoPoint;
-use crate::models::order::{DeliveryOrder, OrderStatus, Priority};
-use crate::state::AppState;
+use crate::geo::haversine_km;
+use crate::geo::index::BoundingBox;
+use crate::models::assignment::{Assignment, AssignmentPreview};
+use crate::models::courier::{GeoPoint, VehicleType};
+use crate::models::event::DispatchEvent;
+use crate::models::feedback::Feedback;
+use crate::models::history::OrderHistoryEntry;
+use crate::models::order::{
+    DeliveryOrder, Handling, NewOrder, OrderDeadLetter, OrderItem, OrderStatus, OrderTracking,
+    Priority,
+};
+use crate::state::{AppState, AssignmentFilter};
+use crate::validation::validate_point;
+
+const DEFAULT_PREVIEW_LIMIT: usize = 10;
 
 pub fn router() -> Router<Arc<AppState>> {
     Router::new()
         .route("/orders", post(create_order))
+        .route("/orders/preview", post(preview_order))
+        .route("/orders/dead-letters", get(list_order_dead_letters))
+        .route("/orders/search", get(search_orders))
         .route("/orders/:id", get(get_order))
+        .route("/orders/:id/status", patch(update_order_status))
+        .route("/orders/:id/decline", post(decline_assigned_order))
+        .route("/orders/:id/feedback", post(create_order_feedback))
+        .route("/orders/:id/assignment", get(get_order_assignment))
+        .route("/orders/:id/track", get(track_order))
+        .route("/orders/:id/history", get(get_order_history))
         .route("/assignments", get(list_assignments))
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct CreateOrderRequest {
     pub pickup: GeoPoint,
     pub dropoff: GeoPoint,
+    /// Further pickups, in the order the courier should visit them after
+    /// `pickup`.
+    #[serde(default)]
+    pub extra_pickups: Vec<GeoPoint>,
     pub priority: Priority,
+    /// Capacity the order takes up, in the deployment's `CAPACITY_UNIT`.
+    /// Defaults to the items' total weight, or 1 without items; giving both
+    /// is rejected.
+    #[serde(default)]
+    pub size: Option<f64>,
+    #[serde(default)]
+    pub items: Vec<OrderItem>,
+    /// Cash to collect at the dropoff; leave out for prepaid orders.
+    #[serde(default)]
+    pub cash_on_delivery: Option<f64>,
+    /// Smallest vehicle that can carry the order; bigger ones qualify too.
+    #[serde(default)]
+    pub required_vehicle: Option<VehicleType>,
+    /// Skill tags such as `alcohol-certified`; only couriers with all of
+    /// them are offered the order.
+    #[serde(default)]
+    pub required_skills: Vec<String>,
+    /// `Fragile`, `Chilled` or `Frozen`; only couriers equipped for all of
+    /// them are offered the order.
+    #[serde(default)]
+    pub handling: Vec<Handling>,
+    /// Receives the order's tracking snapshot on customer-facing transitions.
+    #[serde(default)]
+    pub callback_url: Option<String>,
+    /// Phone number or email passed to the customer notification gateway.
+    #[serde(default)]
+    pub customer_contact: Option<String>,
+}
+
+impl From<CreateOrderRequest> for NewOrder {
+    fn from(request: CreateOrderRequest) -> Self {
+        Self {
+            pickup: request.pickup,
+            dropoff: request.dropoff,
+            extra_pickups: request.extra_pickups,
+            priority: request.priority,
+            size: request.size,
+            items: request.items,
+            cash_on_delivery: request.cash_on_delivery,
+            required_vehicle: request.required_vehicle,
+            required_skills: request.required_skills,
+            handling: request.handling,
+            callback_url: request.callback_url,
+            customer_contact: request.customer_contact,
+            request_id: None,
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct OrderSearchQuery {
+    /// `min_lng,min_lat,max_lng,max_lat`.
+    pub bbox: Option<String>,
+    /// Centre of a radius search, with `lng` and `radius_km`.
+    pub lat: Option<f64>,
+    pub lng: Option<f64>,
+    pub radius_km: Option<f64>,
 }
 
+/// Ids are time-ordered, so the last one a client has seen marks where to
+/// carry on from.
+#[derive(Deserialize, IntoParams)]
+pub struct AssignmentPageQuery {
+    /// Only assignments made after the one with this id.
+    pub after: Option<Uuid>,
+    /// Only assignments made at or after this RFC 3339 time.
+    pub from: Option<DateTime<Utc>>,
+    /// Only assignments made before this RFC 3339 time.
+    pub to: Option<DateTime<Utc>>,
+    /// Only assignments to this courier.
+    pub courier_id: Option<Uuid>,
+    /// At most this many.
+    pub limit: Option<usize>,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct PreviewQuery {
+    /// Candidates to return, best first; 10 by default.
+    pub limit: Option<usize>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct FeedbackRequest {
+    /// 1 to 5.
+    pub stars: u8,
+    #[serde(default)]
+    pub comment: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct UpdateOrderStatusRequest {
+    pub status: OrderStatus,
+}
+
+#[utoipa::path(
+    post,
+    path = "/orders",
+    tag = "orders",
+    request_body = CreateOrderRequest,
+    responses(
+        (status = 200, description = "Order accepted and queued for assignment", body = DeliveryOrder),
+        (status = 400, description = "Invalid callback URL, size or items", body = ErrorResponse),
+        (status = 503, description = "Shutting down; new orders are refused", body = ErrorResponse)
+    )
+)]
 async fn create_order(
     State(state): State<Arc<AppState>>,
+    principal: Principal,
+    headers: HeaderMap,
     Json(payload): Json<CreateOrderRequest>,
 ) -> Result<Json<DeliveryOrder>, AppError> {
-    let order = DeliveryOrder {
-        id: Uuid::new_v4(),
-        pickup: payload.pickup,
-        dropoff: payload.dropoff,
-        priority: payload.priority,
-        status: OrderStatus::Pending,
-        assigned_courier: None,
-        created_at: Utc::now(),
-    };
-
-    state.orders.insert(order.id, order.clone());
-    enqueue_order(&state, order.clone()).await?;
-
+    principal.require(Role::Dispatcher)?;
+    let mut new = NewOrder::from(payload);
+    new.request_id = request_id(&headers).map(str::to_string);
+    let order = submit_order(&state, new, &principal.subject).await?;
     Ok(Json(order))
 }
 
+#[utoipa::path(
+    post,
+    path = "/orders/preview",
+    tag = "orders",
+    params(PreviewQuery),
+    request_body = CreateOrderRequest,
+    responses(
+        (status = 200, description = "Couriers the engine could assign the order to, best first; no order is created", body = AssignmentPreview),
+        (status = 400, description = "Invalid callback URL, size or items", body = ErrorResponse)
+    )
+)]
+async fn preview_order(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Query(query): Query<PreviewQuery>,
+    Json(payload): Json<CreateOrderRequest>,
+) -> Result<Json<AssignmentPreview>, AppError> {
+    principal.require(Role::Dispatcher)?;
+    let order = build_order(&state, NewOrder::from(payload))?;
+    Ok(Json(preview_assignment(
+        &state,
+        &order,
+        query.limit.unwrap_or(DEFAULT_PREVIEW_LIMIT),
+    )))
+}
+
+#[utoipa::path(
+    get,
+    path = "/orders/{id}",
+    tag = "orders",
+    params(("id" = Uuid, Path, description = "Order ID")),
+    responses(
+        (status = 200, description = "Order", body = DeliveryOrder),
+        (status = 404, description = "Order not found", body = ErrorResponse)
+    )
+)]
 async fn get_order(
     State(state): State<Arc<AppState>>,
+    principal: Principal,
     Path(id): Path<Uuid>,
 ) -> Result<Json<DeliveryOrder>, AppError> {
     let order = state
         .orders
         .get(&id)
         .ok_or_else(|| AppError::NotFound(format!("order {} not found", id)))?;
+    principal.require_order_read(order.assigned_courier)?;
 
     Ok(Json(order.value().clone()))
 }
 
-async fn list_assignments(State(state): State<Arc<AppState>>) -> Json<Vec<Assignment>> {
-    let assignments = state
-        .assignments
+#[utoipa::path(
+    patch,
+    path = "/orders/{id}/status",
+    tag = "orders",
+    params(("id" = Uuid, Path, description = "Order ID")),
+    request_body = UpdateOrderStatusRequest,
+    responses(
+        (status = 200, description = "Updated order", body = DeliveryOrder),
+        (status = 404, description = "Order not found", body = ErrorResponse),
+        (status = 409, description = "Transition not allowed from the current status", body = ErrorResponse)
+    )
+)]
+async fn update_order_status(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<UpdateOrderStatusRequest>,
+) -> Result<Json<DeliveryOrder>, AppError> {
+    let assigned_courier = state
+        .orders
+        .get(&id)
+        .ok_or_else(|| AppError::NotFound(format!("order {} not found", id)))?
+        .assigned_courier;
+    principal.require_order_update(assigned_courier)?;
+    let order = transition_order(&state, id, payload.status, &principal.subject)?;
+    Ok(Json(order))
+}
+
+/// The assigned courier turns the order down before picking it up.
+#[utoipa::path(
+    post,
+    path = "/orders/{id}/decline",
+    tag = "orders",
+    params(("id" = Uuid, Path, description = "Order ID")),
+    responses(
+        (status = 200, description = "Order back in the queue", body = DeliveryOrder),
+        (status = 404, description = "Order not found", body = ErrorResponse),
+        (status = 409, description = "Order is not Assigned", body = ErrorResponse)
+    )
+)]
+async fn decline_assigned_order(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(id): Path<Uuid>,
+) -> Result<Json<DeliveryOrder>, AppError> {
+    let assigned_courier = state
+        .orders
+        .get(&id)
+        .ok_or_else(|| AppError::NotFound(format!("order {} not found", id)))?
+        .assigned_courier;
+    principal.require_order_update(assigned_courier)?;
+    let order = decline_order(&state, id, &principal.subject).await?;
+    Ok(Json(order))
+}
+
+/// The customer rates a delivered order, which feeds the courier's rating.
+#[utoipa::path(
+    post,
+    path = "/orders/{id}/feedback",
+    tag = "orders",
+    params(("id" = Uuid, Path, description = "Order ID")),
+    request_body = FeedbackRequest,
+    responses(
+        (status = 200, description = "Feedback recorded", body = Feedback),
+        (status = 400, description = "Stars outside 1-5 or comment too long", body = ErrorResponse),
+        (status = 404, description = "Order not found", body = ErrorResponse),
+        (status = 409, description = "Order not Delivered or already rated", body = ErrorResponse)
+    )
+)]
+async fn create_order_feedback(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<FeedbackRequest>,
+) -> Result<Json<Feedback>, AppError> {
+    principal.require(Role::Dispatcher)?;
+    let feedback = submit_feedback(
+        &state,
+        id,
+        payload.stars,
+        payload.comment,
+        &principal.subject,
+    )?;
+    Ok(Json(feedback))
+}
+
+/// Pending orders picked up inside a box, a circle, or both, oldest first.
+#[utoipa::path(
+    get,
+    path = "/orders/search",
+    tag = "orders",
+    params(OrderSearchQuery),
+    responses(
+        (status = 200, description = "Pending orders whose first pickup is in the area", body = [DeliveryOrder]),
+        (status = 400, description = "No area given, or a malformed one", body = ErrorResponse)
+    )
+)]
+async fn search_orders(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Query(query): Query<OrderSearchQuery>,
+) -> Result<Json<Vec<DeliveryOrder>>, AppError> {
+    principal.require(Role::Dispatcher)?;
+    let bbox = query
+        .bbox
+        .as_deref()
+        .map(BoundingBox::from_str)
+        .transpose()
+        .map_err(AppError::BadRequest)?;
+    let circle = match (query.lat, query.lng, query.radius_km) {
+        (None, None, None) => None,
+        (Some(lat), Some(lng), Some(radius_km)) => {
+            let center = GeoPoint { lat, lng };
+            validate_point("center", &center)?;
+            if !radius_km.is_finite() || radius_km <= 0.0 {
+                return Err(AppError::BadRequest(
+                    "radius_km must be a positive number".to_string(),
+                ));
+            }
+            Some((center, radius_km))
+        }
+        _ => {
+            return Err(AppError::BadRequest(
+                "a radius search needs lat, lng and radius_km".to_string(),
+            ));
+        }
+    };
+    let area = match (bbox, &circle) {
+        (Some(bbox), Some((center, radius_km))) => {
+            bbox.intersect(&BoundingBox::around(center, *radius_km))
+        }
+        (Some(bbox), None) => Some(bbox),
+        (None, Some((center, radius_km))) => Some(BoundingBox::around(center, *radius_km)),
+        (None, None) => {
+            return Err(AppError::BadRequest(
+                "give a bbox or lat, lng and radius_km".to_string(),
+            ));
+        }
+    };
+    let Some(area) = area else {
+        return Ok(Json(Vec::new()));
+    };
+
+    let mut orders: Vec<DeliveryOrder> = state
+        .pickup_index
+        .candidates(&area)
+        .into_iter()
+        .filter_map(|id| state.orders.get(&id).map(|order| order.value().clone()))
+        .filter(|order| {
+            order.status == OrderStatus::Pending
+                && area.contains(&order.pickup)
+                && circle.as_ref().is_none_or(|(center, radius_km)| {
+                    haversine_km(center, &order.pickup) <= *radius_km
+                })
+        })
+        .collect();
+    orders.sort_by_key(|order| order.created_at);
+    Ok(Json(orders))
+}
+
+#[utoipa::path(
+    get,
+    path = "/orders/dead-letters",
+    tag = "orders",
+    responses((status = 200, description = "Orders the engine rejected, newest first", body = [OrderDeadLetter]))
+)]
+async fn list_order_dead_letters(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+) -> Result<Json<Vec<OrderDeadLetter>>, AppError> {
+    principal.require(Role::Dispatcher)?;
+    let mut dead_letters: Vec<OrderDeadLetter> = state
+        .order_dead_letters
         .iter()
         .map(|entry| entry.value().clone())
         .collect();
+    dead_letters.sort_by_key(|dead_letter| Reverse(dead_letter.rejected_at));
+
+    Ok(Json(dead_letters))
+}
+
+#[utoipa::path(
+    get,
+    path = "/orders/{id}/history",
+    tag = "orders",
+    params(("id" = Uuid, Path, description = "Order ID")),
+    responses(
+        (status = 200, description = "What happened to the order, oldest first", body = [OrderHistoryEntry]),
+        (status = 404, description = "Order not found", body = ErrorResponse)
+    )
+)]
+async fn get_order_history(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<OrderHistoryEntry>>, AppError> {
+    principal.require(Role::Dispatcher)?;
+    if !state.orders.contains_key(&id) {
+        return Err(AppError::NotFound(format!("order {} not found", id)));
+    }
+    let history = state
+        .order_history
+        .get(&id)
+        .map(|entries| entries.value().clone())
+        .unwrap_or_default();
+    Ok(Json(history))
+}
+
+#[utoipa::path(
+    get,
+    path = "/orders/{id}/assignment",
+    tag = "orders",
+    params(("id" = Uuid, Path, description = "Order ID")),
+    responses(
+        (status = 200, description = "Latest assignment for the order", body = Assignment),
+        (status = 404, description = "Order not found or not yet assigned", body = ErrorResponse)
+    )
+)]
+async fn get_order_assignment(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Assignment>, AppError> {
+    if !state.orders.contains_key(&id) {
+        return Err(AppError::NotFound(format!("order {} not found", id)));
+    }
+
+    let assignment = state
+        .latest_assignment_for_order(id)
+        .ok_or_else(|| AppError::NotFound(format!("order {} has no assignment", id)))?;
+    principal.require_order_read(Some(assignment.courier_id))?;
+
+    Ok(Json(assignment))
+}
+
+#[utoipa::path(
+    get,
+    path = "/assignments",
+    tag = "assignments",
+    params(AssignmentPageQuery),
+    responses(
+        (status = 200, description = "Assignments, oldest first", body = [Assignment]),
+        (status = 400, description = "`from` is after `to`", body = ErrorResponse)
+    )
+)]
+async fn list_assignments(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Query(query): Query<AssignmentPageQuery>,
+) -> Result<Response, AppError> {
+    if let (Some(from), Some(to)) = (query.from, query.to)
+        && from > to
+    {
+        return Err(AppError::BadRequest(
+            "from must not be after to".to_string(),
+        ));
+    }
+    let filter = AssignmentFilter {
+        after: query.after,
+        from: query.from,
+        to: query.to,
+        courier_id: principal.courier_scope(query.courier_id)?,
+    };
+    Ok(json_array(state.find_assignments(
+        &filter,
+        query.limit.unwrap_or(usize::MAX),
+    )))
+}
+
+/// Streams an `OrderTracking` snapshot as SSE `tracking` events: one on
+/// connect, then one whenever the order moves or its courier does. The
+/// stream ends after the `Delivered` snapshot.
+#[utoipa::path(
+    get,
+    path = "/orders/{id}/track",
+    tag = "orders",
+    security(()),
+    params(("id" = Uuid, Path, description = "Order ID")),
+    responses(
+        (status = 200, description = "Stream of OrderTracking snapshots", content_type = "text/event-stream"),
+        (status = 404, description = "Order not found", body = ErrorResponse)
+    )
+)]
+pub async fn track_order(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, AppError> {
+    // `None` marks a lagged receiver: the snapshot is rebuilt from current
+    // state, so refreshing is enough to catch up.
+    let events = BroadcastStream::new(state.events.subscribe())
+        .map(|result| result.ok().map(|recorded| recorded.event));
+    let locations = BroadcastStream::new(state.courier_locations_tx.subscribe())
+        .map(|result| result.ok().map(DispatchEvent::CourierLocation));
+    let initial = order_tracking(&state, id)?;
+    let keepalive = state.keepalive.ping_interval;
+
+    let changes = stream::select(events, locations).filter_map(move |event| {
+        let snapshot = event
+            .as_ref()
+            .is_none_or(|event| affects_tracking(&state, id, event))
+            .then(|| order_tracking(&state, id).ok())
+            .flatten();
+        async move { snapshot }
+    });
+
+    let snapshots = stream::once(async move { initial })
+        .chain(changes)
+        .scan(false, |delivered, snapshot| {
+            let next = (!*delivered).then(|| {
+                *delivered = snapshot.status == OrderStatus::Delivered;
+                snapshot
+            });
+            async move { next }
+        })
+        .map(|snapshot| Ok(tracking_event(&snapshot)));
+
+    Ok(Sse::new(snapshots).keep_alive(KeepAlive::new().interval(keepalive)))
+}
 
-    Json(assignments)
+fn tracking_event(snapshot: &OrderTracking) -> Event {
+    Event::default()
+        .event("tracking")
+        .json_data(snapshot)
+        .unwrap_or_else(|err| Event::default().comment(format!("serialization failed: {err}")))
 }
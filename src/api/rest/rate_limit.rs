@@ -0,0 +1,49 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::header::RETRY_AFTER;
+use axum::http::HeaderValue;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+
+use crate::error::AppError;
+use crate::rate_limit::RouteGroup;
+use crate::state::AppState;
+
+/// Rejects requests past the client's budget for the route group with `429`
+/// and a `Retry-After` in whole seconds.
+pub async fn limit(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let Some(group) = RouteGroup::of(request.method(), request.uri().path()) else {
+        return next.run(request).await;
+    };
+
+    match state.rate_limiter.check(group, &client_key(&request)) {
+        Ok(()) => next.run(request).await,
+        Err(retry_after) => {
+            state.metrics.record_rate_limited(group.as_str());
+            let seconds = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+            let mut response =
+                AppError::TooManyRequests("rate limit exceeded".to_string()).into_response();
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(seconds));
+            response
+        }
+    }
+}
+
+/// The caller's `x-api-key` when it sends one, otherwise its address.
+fn client_key(request: &Request) -> String {
+    if let Some(key) = request
+        .headers()
+        .get("x-api-key")
+        .and_then(|value| value.to_str().ok())
+    {
+        return format!("key:{key}");
+    }
+    match request.extensions().get::<ConnectInfo<SocketAddr>>() {
+        Some(ConnectInfo(addr)) => format!("ip:{}", addr.ip()),
+        None => "unknown".to_string(),
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use axum::extract::{Query, State};
+use axum::http::HeaderMap;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::routing::get;
+use axum::Router;
+use serde::Serialize;
+use serde_json::json;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{Stream, StreamExt};
+
+use crate::api::rest::ws::ConnectParams;
+use crate::auth::Principal;
+use crate::error::AppError;
+use crate::events::Lagged;
+use crate::models::event::{DispatchEvent, Topic};
+use crate::state::AppState;
+
+pub fn router() -> Router<Arc<AppState>> {
+    Router::new().route("/events/stream", get(event_stream))
+}
+
+/// Server-Sent Events version of `/ws`: same query parameters and the same
+/// JSON envelopes, with `seq` as the SSE event id so browsers resume through
+/// `Last-Event-ID` on their own. Without `topics`, assignments and orders are
+/// streamed.
+#[utoipa::path(
+    get,
+    path = "/events/stream",
+    tag = "assignments",
+    params(
+        ("topics" = Option<String>, Query, description = "Comma-separated topics"),
+        ("courier_id" = Option<uuid::Uuid>, Query, description = "Only events for this courier"),
+        ("zone" = Option<String>, Query, description = "Geohash prefix"),
+        ("resume_from" = Option<u64>, Query, description = "Replay events after this seq"),
+        ("since" = Option<String>, Query, description = "Replay events after this RFC 3339 time"),
+        ("Last-Event-ID" = Option<u64>, Header, description = "Takes precedence over resume_from"),
+    ),
+    responses(
+        (status = 200, description = "Event stream", content_type = "text/event-stream"),
+        (status = 400, description = "Invalid topic or Last-Event-ID", body = ErrorResponse)
+    )
+)]
+pub async fn event_stream(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    headers: HeaderMap,
+    Query(params): Query<ConnectParams>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, AppError> {
+    let mut subscription = params.subscription()?;
+    if params.topics.is_none() {
+        subscription.topics = HashSet::from([Topic::Assignments, Topic::Orders]);
+    }
+    subscription.restrict_to(&principal)?;
+
+    let last_event_id = match headers.get("last-event-id") {
+        Some(value) => Some(
+            value
+                .to_str()
+                .ok()
+                .and_then(|raw| raw.trim().parse::<u64>().ok())
+                .ok_or_else(|| AppError::BadRequest("invalid Last-Event-ID".to_string()))?,
+        ),
+        None => None,
+    };
+    let after = last_event_id.or(params.resume_from).unwrap_or(0);
+
+    let keepalive = state.keepalive.ping_interval;
+    let sequenced = {
+        let state = state.clone();
+        let subscription = subscription.clone();
+        state
+            .events
+            .stream(Some(after), params.since)
+            .filter_map(move |item| match item {
+                Ok(recorded) => subscription.matches(&state, &recorded.event).then(|| {
+                    Ok(sse_event(recorded.event.topic().as_str(), &recorded)
+                        .id(recorded.seq.to_string()))
+                }),
+                // The browser's Last-Event-ID is still the last event it got,
+                // so reconnecting after this replays the gap if it is buffered.
+                Err(Lagged(dropped)) => {
+                    state.metrics.record_dropped_events("sse", dropped);
+                    Some(Ok(sse_event(
+                        "events_dropped",
+                        &json!({ "count": dropped }),
+                    )))
+                }
+            })
+    };
+    // Locations carry no `seq`, so they are sent without an id and are not
+    // replayed on reconnect.
+    let locations = BroadcastStream::new(state.courier_locations_tx.subscribe())
+        .filter_map(|result| result.ok().map(DispatchEvent::CourierLocation))
+        .filter(move |event| subscription.matches(&state, event))
+        .map(|event| Ok(sse_event(event.topic().as_str(), &event)));
+
+    Ok(Sse::new(sequenced.merge(locations)).keep_alive(KeepAlive::new().interval(keepalive)))
+}
+
+fn sse_event<T: Serialize>(name: &str, payload: &T) -> Event {
+    Event::default()
+        .event(name)
+        .json_data(payload)
+        .unwrap_or_else(|err| Event::default().comment(format!("serialization failed: {err}")))
+}
@@ -0,0 +1,103 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+use axum::routing::get;
+use axum::Json;
+use axum::Router;
+use chrono::NaiveDate;
+
+use crate::auth::{Principal, Role};
+use crate::error::AppError;
+use crate::models::report::DailyReport;
+use crate::models::stats::{DispatchSummary, TimeSeries};
+use crate::models::surge::SurgeEvent;
+use crate::state::AppState;
+
+pub fn router() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/stats", get(get_dispatch_stats))
+        .route("/stats/timeseries", get(get_dispatch_timeseries))
+        .route("/reports/:date", get(get_daily_report))
+        .route("/surges", get(list_surges))
+}
+
+/// Kept up to date as orders move, so it costs the same however large the
+/// fleet and the order book.
+#[utoipa::path(
+    get,
+    path = "/stats",
+    tag = "system",
+    responses(
+        (status = 200, description = "Orders by status and how dispatch went over STATS_WINDOW_SECS", body = DispatchSummary),
+        (status = 403, description = "Dispatcher role required", body = ErrorResponse)
+    )
+)]
+async fn get_dispatch_stats(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+) -> Result<Json<DispatchSummary>, AppError> {
+    principal.require(Role::Dispatcher)?;
+    Ok(Json(state.stats.summary(state.clock.now())))
+}
+
+/// Kept in memory, so the dashboard can chart recent dispatch without a
+/// Prometheus to query.
+#[utoipa::path(
+    get,
+    path = "/stats/timeseries",
+    tag = "system",
+    responses(
+        (status = 200, description = "Orders, assignments and mean score per minute over the last STATS_TIMESERIES_MINUTES", body = TimeSeries),
+        (status = 403, description = "Dispatcher role required", body = ErrorResponse)
+    )
+)]
+async fn get_dispatch_timeseries(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+) -> Result<Json<TimeSeries>, AppError> {
+    principal.require(Role::Dispatcher)?;
+    Ok(Json(state.stats.timeseries(state.clock.now())))
+}
+
+/// Reports are generated shortly after each day ends, UTC, so the day
+/// under way has none yet.
+#[utoipa::path(
+    get,
+    path = "/reports/{date}",
+    tag = "system",
+    params(("date" = NaiveDate, Path, description = "Day reported on, e.g. 2026-10-16")),
+    responses(
+        (status = 200, description = "Volumes and on-time rate for the day, in total and per pickup zone", body = DailyReport),
+        (status = 403, description = "Dispatcher role required", body = ErrorResponse),
+        (status = 404, description = "No report for that day", body = ErrorResponse)
+    )
+)]
+async fn get_daily_report(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(date): Path<NaiveDate>,
+) -> Result<Json<DailyReport>, AppError> {
+    principal.require(Role::Dispatcher)?;
+    state
+        .reports
+        .get(date)
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound(format!("no report for {date}")))
+}
+
+#[utoipa::path(
+    get,
+    path = "/surges",
+    tag = "system",
+    responses(
+        (status = 200, description = "Zones in surge as of the last check, busiest first", body = [SurgeEvent]),
+        (status = 403, description = "Dispatcher role required", body = ErrorResponse)
+    )
+)]
+async fn list_surges(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+) -> Result<Json<Vec<SurgeEvent>>, AppError> {
+    principal.require(Role::Dispatcher)?;
+    Ok(Json(state.surge.surging()))
+}
@@ -0,0 +1,63 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::{HeaderMap, Request};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use tower::ServiceExt;
+
+use crate::error::AppError;
+use crate::tenants::{Tenants, DEFAULT_TENANT, TENANT_HEADER};
+
+/// The REST API of every tenant. Each request is served by the router of
+/// the tenant its `x-tenant-id` header names, or of the default tenant when
+/// it names none; an unknown tenant is a 400. `/metrics` covers all of
+/// them at once. With only the default tenant this is just [`super::router`].
+pub fn tenant_router(tenants: Arc<Tenants>) -> Router {
+    let default = tenants.default_tenant().clone();
+    if !tenants.is_multi_tenant() {
+        return super::router(default);
+    }
+
+    let routers: Arc<BTreeMap<String, Router>> = Arc::new(
+        tenants
+            .iter()
+            .map(|state| (state.tenant.clone(), super::routes(state.clone(), false)))
+            .collect(),
+    );
+    let api = if default.metrics_access.separate_port {
+        Router::new()
+    } else {
+        Router::new().route("/metrics", get(metrics))
+    };
+    api.with_state(tenants)
+        .fallback(move |request: Request<Body>| route(routers.clone(), request))
+}
+
+async fn route(routers: Arc<BTreeMap<String, Router>>, request: Request<Body>) -> Response {
+    let tenant = match request.headers().get(TENANT_HEADER) {
+        None => DEFAULT_TENANT,
+        Some(value) => match value.to_str() {
+            Ok(tenant) => tenant,
+            Err(_) => {
+                return AppError::BadRequest(format!("{TENANT_HEADER} is not valid text"))
+                    .into_response();
+            }
+        },
+    };
+    let Some(router) = routers.get(tenant).cloned() else {
+        return AppError::BadRequest(format!("unknown tenant {tenant:?}")).into_response();
+    };
+    router
+        .oneshot(request)
+        .await
+        .unwrap_or_else(|never| match never {})
+}
+
+/// Every tenant's metrics, each series labelled with its tenant.
+pub(super) async fn metrics(State(tenants): State<Arc<Tenants>>, headers: HeaderMap) -> Response {
+    super::render_metrics(&headers, tenants.iter())
+}
@@ -0,0 +1,151 @@
+use std::cmp::Reverse;
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::{delete, get, post};
+use axum::Json;
+use axum::Router;
+use chrono::Utc;
+use serde::Deserialize;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::auth::{Principal, Role};
+use crate::error::AppError;
+use crate::models::event::Topic;
+use crate::models::webhook::{DeadLetter, Webhook};
+use crate::state::AppState;
+
+pub fn router() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/webhooks", post(create_webhook).get(list_webhooks))
+        .route("/webhooks/dead-letters", get(list_dead_letters))
+        .route("/webhooks/:id", delete(delete_webhook))
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreateWebhookRequest {
+    pub url: String,
+    pub secret: String,
+    /// `assignments` and/or `orders`.
+    pub event_types: Vec<Topic>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/webhooks",
+    tag = "webhooks",
+    request_body = CreateWebhookRequest,
+    responses(
+        (status = 200, description = "Webhook registered", body = Webhook),
+        (status = 400, description = "Invalid webhook", body = ErrorResponse)
+    )
+)]
+async fn create_webhook(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Json(payload): Json<CreateWebhookRequest>,
+) -> Result<Json<Webhook>, AppError> {
+    principal.require(Role::Admin)?;
+    let url = reqwest::Url::parse(&payload.url)
+        .map_err(|err| AppError::BadRequest(format!("invalid url: {err}")))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(AppError::BadRequest(
+            "url must be http or https".to_string(),
+        ));
+    }
+    if payload.secret.is_empty() {
+        return Err(AppError::BadRequest("secret cannot be empty".to_string()));
+    }
+    if payload.event_types.is_empty() {
+        return Err(AppError::BadRequest(
+            "event_types cannot be empty".to_string(),
+        ));
+    }
+    if payload.event_types.contains(&Topic::CourierLocations) {
+        return Err(AppError::BadRequest(
+            "courier_locations cannot be delivered by webhook".to_string(),
+        ));
+    }
+    if payload.event_types.contains(&Topic::Changes) {
+        return Err(AppError::BadRequest(
+            "changes cannot be delivered by webhook".to_string(),
+        ));
+    }
+
+    let webhook = Webhook {
+        id: Uuid::now_v7(),
+        url: url.to_string(),
+        secret: payload.secret,
+        event_types: payload.event_types,
+        created_at: Utc::now(),
+    };
+
+    state.webhooks.insert(webhook.id, webhook.clone());
+    Ok(Json(webhook))
+}
+
+#[utoipa::path(
+    get,
+    path = "/webhooks",
+    tag = "webhooks",
+    responses((status = 200, description = "Registered webhooks", body = [Webhook]))
+)]
+async fn list_webhooks(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+) -> Result<Json<Vec<Webhook>>, AppError> {
+    principal.require(Role::Admin)?;
+    let webhooks = state
+        .webhooks
+        .iter()
+        .map(|entry| entry.value().clone())
+        .collect();
+
+    Ok(Json(webhooks))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/webhooks/{id}",
+    tag = "webhooks",
+    params(("id" = Uuid, Path, description = "Webhook ID")),
+    responses(
+        (status = 204, description = "Webhook removed"),
+        (status = 404, description = "Webhook not found", body = ErrorResponse)
+    )
+)]
+async fn delete_webhook(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    principal.require(Role::Admin)?;
+    state
+        .webhooks
+        .remove(&id)
+        .map(|_| StatusCode::NO_CONTENT)
+        .ok_or_else(|| AppError::NotFound(format!("webhook {} not found", id)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/webhooks/dead-letters",
+    tag = "webhooks",
+    responses((status = 200, description = "Deliveries that exhausted their retries, newest first", body = [DeadLetter]))
+)]
+async fn list_dead_letters(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+) -> Result<Json<Vec<DeadLetter>>, AppError> {
+    principal.require(Role::Admin)?;
+    let mut dead_letters: Vec<DeadLetter> = state
+        .webhook_dead_letters
+        .iter()
+        .map(|entry| entry.value().clone())
+        .collect();
+    dead_letters.sort_by_key(|dead_letter| Reverse(dead_letter.failed_at));
+
+    Ok(Json(dead_letters))
+}
@@ -1,51 +1,567 @@
+use std::collections::HashSet;
 use std::sync::Arc;
 
 use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
-use axum::extract::State;
+use std::net::SocketAddr;
+
+use axum::extract::{ConnectInfo, Query, State};
 use axum::response::IntoResponse;
+use chrono::{DateTime, Utc};
+use futures::stream::SplitSink;
 use futures::SinkExt;
 use futures::StreamExt;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::{interval_at, Instant};
 use tracing::{info, warn};
+use uuid::Uuid;
 
+use crate::auth::{Principal, Role};
+use crate::connections::ConnectionPermit;
+use crate::error::AppError;
+use crate::geo::in_zone;
+use crate::models::event::{DispatchEvent, Topic};
 use crate::state::AppState;
 
+/// Which events a client receives. New connections get every assignment;
+/// clients narrow or widen that by sending
+/// `{"type":"subscribe","topics":[...],"courier_id":"...","zone":"..."}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Subscription {
+    pub topics: HashSet<Topic>,
+    #[serde(default)]
+    pub courier_id: Option<Uuid>,
+    /// Geohash prefix; matched against order pickups and courier positions.
+    #[serde(default)]
+    pub zone: Option<String>,
+}
+
+impl Default for Subscription {
+    fn default() -> Self {
+        Self {
+            topics: HashSet::from([Topic::Assignments]),
+            courier_id: None,
+            zone: None,
+        }
+    }
+}
+
+impl Subscription {
+    pub fn matches(&self, state: &AppState, event: &DispatchEvent) -> bool {
+        if !self.topics.contains(&event.topic()) {
+            return false;
+        }
+
+        match event {
+            DispatchEvent::Assignment(assignment) => {
+                self.courier_matches(Some(assignment.courier_id))
+                    && self.zone.as_deref().is_none_or(|zone| {
+                        state
+                            .orders
+                            .get(&assignment.order_id)
+                            .is_some_and(|order| in_zone(&order.pickup, zone))
+                    })
+            }
+            DispatchEvent::Order(order) => {
+                self.courier_matches(order.courier_id)
+                    && self
+                        .zone
+                        .as_deref()
+                        .is_none_or(|zone| in_zone(&order.pickup, zone))
+            }
+            DispatchEvent::CourierLocation(update) => {
+                self.courier_matches(Some(update.courier_id))
+                    && self
+                        .zone
+                        .as_deref()
+                        .is_none_or(|zone| in_zone(&update.location, zone))
+            }
+            DispatchEvent::Report(_) => true,
+            DispatchEvent::Surge(surge) => self
+                .zone
+                .as_deref()
+                .is_none_or(|zone| surge.zone.starts_with(zone) || zone.starts_with(&surge.zone)),
+            // Whole couriers and orders are for standbys, over gRPC.
+            DispatchEvent::Change(_) => false,
+        }
+    }
+
+    /// Narrows the subscription to what `principal` may see: courier tokens
+    /// only get events about their own courier, and no fleet-wide reports.
+    pub fn restrict_to(&mut self, principal: &Principal) -> Result<(), AppError> {
+        self.courier_id = principal.courier_scope(self.courier_id)?;
+        if !principal.has_role(Role::Dispatcher) {
+            self.topics.remove(&Topic::Reports);
+        }
+        Ok(())
+    }
+
+    fn courier_matches(&self, courier_id: Option<Uuid>) -> bool {
+        self.courier_id.is_none() || self.courier_id == courier_id
+    }
+}
+
+/// Wire format for `/ws` frames. JSON goes out as text frames; MessagePack
+/// and CBOR as binary frames with the same field names as the JSON form.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    #[default]
+    Json,
+    Msgpack,
+    Cbor,
+}
+
+impl Encoding {
+    fn encode<T: Serialize>(&self, message: &T) -> Result<Message, String> {
+        match self {
+            Encoding::Json => serde_json::to_string(message)
+                .map(Message::Text)
+                .map_err(|err| err.to_string()),
+            // Human-readable so UUIDs and timestamps stay strings, as in JSON.
+            Encoding::Msgpack => {
+                let mut buffer = Vec::new();
+                let mut serializer = rmp_serde::Serializer::new(&mut buffer)
+                    .with_struct_map()
+                    .with_human_readable();
+                message
+                    .serialize(&mut serializer)
+                    .map(|()| Message::Binary(buffer))
+                    .map_err(|err| err.to_string())
+            }
+            // ciborium has no human-readable mode, so go through a JSON value.
+            Encoding::Cbor => {
+                let value = serde_json::to_value(message).map_err(|err| err.to_string())?;
+                let mut buffer = Vec::new();
+                ciborium::into_writer(&value, &mut buffer)
+                    .map(|()| Message::Binary(buffer))
+                    .map_err(|err| err.to_string())
+            }
+        }
+    }
+
+    /// Text frames are always read as JSON so clients in a binary mode can
+    /// still send hand-written control messages.
+    fn decode<T: DeserializeOwned>(&self, frame: Message) -> Result<T, String> {
+        match (frame, self) {
+            (Message::Text(text), _) => serde_json::from_str(&text).map_err(|err| err.to_string()),
+            (Message::Binary(bytes), Encoding::Json) => {
+                serde_json::from_slice(&bytes).map_err(|err| err.to_string())
+            }
+            (Message::Binary(bytes), Encoding::Msgpack) => {
+                let mut deserializer =
+                    rmp_serde::Deserializer::from_read_ref(&bytes).with_human_readable();
+                T::deserialize(&mut deserializer).map_err(|err| err.to_string())
+            }
+            (Message::Binary(bytes), Encoding::Cbor) => {
+                ciborium::from_reader::<serde_json::Value, _>(bytes.as_slice())
+                    .map_err(|err| err.to_string())
+                    .and_then(|value| serde_json::from_value(value).map_err(|err| err.to_string()))
+            }
+            _ => Err("unsupported frame type".to_string()),
+        }
+    }
+}
+
+/// Query string accepted on `/ws`. `topics` (comma separated), `courier_id`
+/// and `zone` set the initial subscription; buffered events matching it are
+/// replayed before live ones, limited to those after `resume_from` (the last
+/// `seq` the client saw) and `since` when given.
+#[derive(Debug, Default, Deserialize)]
+pub struct ConnectParams {
+    /// `json` (default), `msgpack` or `cbor`; ignored by `/events/stream`.
+    #[serde(default)]
+    pub encoding: Encoding,
+    pub resume_from: Option<u64>,
+    pub since: Option<DateTime<Utc>>,
+    pub topics: Option<String>,
+    pub courier_id: Option<Uuid>,
+    pub zone: Option<String>,
+}
+
+impl ConnectParams {
+    pub(crate) fn subscription(&self) -> Result<Subscription, AppError> {
+        let mut subscription = Subscription {
+            courier_id: self.courier_id,
+            zone: self.zone.clone(),
+            ..Subscription::default()
+        };
+
+        if let Some(topics) = &self.topics {
+            subscription.topics = topics
+                .split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(|name| {
+                    serde_json::from_value(serde_json::Value::String(name.to_string()))
+                        .map_err(|_| AppError::BadRequest(format!("unknown topic: {name}")))
+                })
+                .collect::<Result<_, _>>()?;
+        }
+
+        Ok(subscription)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    Subscribe(Subscription),
+}
+
+#[derive(Serialize)]
+#[serde(tag = "topic", content = "data", rename_all = "snake_case")]
+enum ControlMessage<'a> {
+    Subscribed(&'a Subscription),
+    /// The client fell behind the broadcast buffer and missed `count`
+    /// events; it should reconnect with `resume_from` or refetch state.
+    EventsDropped {
+        count: u64,
+    },
+    Error {
+        message: String,
+    },
+}
+
 pub async fn ws_handler(
     ws: WebSocketUpgrade,
     State(state): State<Arc<AppState>>,
-) -> impl IntoResponse {
-    ws.on_upgrade(|socket| handle_socket(socket, state))
+    principal: Principal,
+    Query(params): Query<ConnectParams>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+) -> Result<impl IntoResponse, AppError> {
+    let mut subscription = params.subscription()?;
+    subscription.restrict_to(&principal)?;
+
+    let ip = connect_info.map(|ConnectInfo(addr)| addr.ip());
+    let permit = state
+        .ws_connections
+        .try_acquire(ip)
+        .inspect_err(|rejection| {
+            state
+                .metrics
+                .ws_connections_rejected_total
+                .with_label_values(&[rejection.as_str()])
+                .inc();
+            warn!(
+                ?ip,
+                reason = rejection.as_str(),
+                "websocket connection rejected"
+            );
+        })?;
+
+    Ok(ws.on_upgrade(move |socket| {
+        handle_socket(socket, state, principal, subscription, params, permit)
+    }))
 }
 
-async fn handle_socket(socket: WebSocket, state: Arc<AppState>) {
-    let (mut sender, mut receiver) = socket.split();
-    let mut rx = state.assignment_events_tx.subscribe();
+async fn handle_socket(
+    socket: WebSocket,
+    state: Arc<AppState>,
+    principal: Principal,
+    mut subscription: Subscription,
+    params: ConnectParams,
+    _permit: ConnectionPermit,
+) {
+    let (sink, mut receiver) = socket.split();
+    let mut sender = Outbound {
+        sink,
+        encoding: params.encoding,
+    };
+    // Subscribe before reading the history so nothing published in between
+    // is lost; anything already replayed is then skipped by `seq`.
+    let mut events = state.events.subscribe();
+    let mut locations = state.courier_locations_tx.subscribe();
+    let resume_from = state.events.resume_cursor(params.resume_from);
+    let mut last_seq = resume_from.unwrap_or(0);
+
+    let keepalive = state.keepalive;
+    let mut ping = interval_at(
+        Instant::now() + keepalive.ping_interval,
+        keepalive.ping_interval,
+    );
+    let mut last_heard = Instant::now();
 
     info!("websocket client connected");
 
-    let send_task = tokio::spawn(async move {
-        while let Ok(assignment) = rx.recv().await {
-            let json = match serde_json::to_string(&assignment) {
-                Ok(json) => json,
-                Err(err) => {
-                    warn!(error = %err, "failed to serialize assignment for ws");
+    for recorded in state.events.replay(resume_from, params.since) {
+        last_seq = recorded.seq;
+        if subscription.matches(&state, &recorded.event)
+            && sender
+                .send_event(&state, recorded.event.topic(), &recorded)
+                .await
+                .is_err()
+        {
+            return;
+        }
+    }
+
+    loop {
+        let received = tokio::select! {
+            _ = ping.tick() => {
+                if last_heard.elapsed() > keepalive.idle_timeout {
+                    info!("websocket client missed pongs, disconnecting");
+                    let _ = sender.sink.send(Message::Close(None)).await;
+                    break;
+                }
+                if sender.sink.send(Message::Ping(Vec::new())).await.is_err() {
+                    break;
+                }
+                continue;
+            }
+            incoming = receiver.next() => match incoming {
+                Some(Ok(frame @ (Message::Text(_) | Message::Binary(_)))) => {
+                    last_heard = Instant::now();
+                    let reply = match sender.encoding.decode::<ClientMessage>(frame) {
+                        Ok(ClientMessage::Subscribe(mut next)) => {
+                            match next.restrict_to(&principal) {
+                                Ok(()) => {
+                                    subscription = next;
+                                    ControlMessage::Subscribed(&subscription)
+                                }
+                                Err(err) => ControlMessage::Error {
+                                    message: err.to_string(),
+                                },
+                            }
+                        }
+                        Err(err) => ControlMessage::Error {
+                            message: format!("invalid message: {err}"),
+                        },
+                    };
+
+                    if sender.send_message(&reply).await.is_err() {
+                        break;
+                    }
                     continue;
                 }
-            };
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {
+                    last_heard = Instant::now();
+                    continue;
+                }
+            },
+            result = events.recv() => result,
+            // Locations are not sequenced or replayed; they go out as bare
+            // `{"topic":"courier_locations","data":...}` envelopes.
+            result = locations.recv() => {
+                match result {
+                    Ok(update) => {
+                        let event = DispatchEvent::CourierLocation(update);
+                        if subscription.matches(&state, &event)
+                            && sender.send_event(&state, event.topic(), &event)
+                                .await
+                                .is_err()
+                        {
+                            break;
+                        }
+                    }
+                    Err(RecvError::Lagged(dropped)) => {
+                        state.metrics.record_dropped_events("ws", dropped);
+                        let notice = ControlMessage::EventsDropped { count: dropped };
+                        if sender.send_message(&notice).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+                continue;
+            }
+        };
 
-            if sender.send(Message::Text(json.into())).await.is_err() {
-                break;
+        let recorded = match received {
+            Ok(recorded) if recorded.seq <= last_seq => continue,
+            Ok(recorded) => recorded,
+            Err(RecvError::Lagged(dropped)) => {
+                state.metrics.record_dropped_events("ws", dropped);
+                let notice = ControlMessage::EventsDropped { count: dropped };
+                if sender.send_message(&notice).await.is_err() {
+                    break;
+                }
+                continue;
             }
+            Err(RecvError::Closed) => break,
+        };
+        last_seq = recorded.seq;
+
+        if subscription.matches(&state, &recorded.event)
+            && sender
+                .send_event(&state, recorded.event.topic(), &recorded)
+                .await
+                .is_err()
+        {
+            break;
         }
-    });
+    }
 
-    let recv_task = tokio::spawn(async move {
-        while let Some(Ok(_msg)) = receiver.next().await {}
-    });
+    info!("websocket client disconnected");
+}
+
+/// Sending half of a socket, serializing in the encoding the client chose.
+struct Outbound {
+    sink: SplitSink<WebSocket, Message>,
+    encoding: Encoding,
+}
 
-    tokio::select! {
-        _ = send_task => {},
-        _ = recv_task => {},
+impl Outbound {
+    async fn send_event<T: Serialize>(
+        &mut self,
+        state: &AppState,
+        topic: Topic,
+        message: &T,
+    ) -> Result<(), axum::Error> {
+        self.send_message(message).await?;
+        state
+            .metrics
+            .ws_messages_sent_total
+            .with_label_values(&[topic.as_str()])
+            .inc();
+        Ok(())
     }
 
-    info!("websocket client disconnected");
+    async fn send_message<T: Serialize>(&mut self, message: &T) -> Result<(), axum::Error> {
+        let frame = match self.encoding.encode(message) {
+            Ok(frame) => frame,
+            Err(err) => {
+                warn!(error = %err, encoding = ?self.encoding, "failed to serialize ws message");
+                return Ok(());
+            }
+        };
+
+        self.sink.send(frame).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    use axum::extract::ws::Message;
+    use serde_json::{json, Value};
+
+    use super::{ClientMessage, ConnectParams, Encoding, Subscription};
+    use crate::models::courier::{CourierLocation, CourierStatus, GeoPoint};
+    use crate::models::event::{DispatchEvent, Topic};
+    use crate::models::order::{OrderEvent, OrderStatus};
+    use crate::state::AppState;
+
+    fn location_event(courier_id: Uuid, lat: f64, lng: f64) -> DispatchEvent {
+        DispatchEvent::CourierLocation(CourierLocation {
+            courier_id,
+            location: GeoPoint { lat, lng },
+            status: CourierStatus::Available,
+            updated_at: Utc::now(),
+            relayed: false,
+        })
+    }
+
+    #[test]
+    fn default_subscription_only_receives_assignments() {
+        let (state, _rx) = AppState::new(16, 16);
+        let subscription = Subscription::default();
+
+        let order = DispatchEvent::Order(OrderEvent {
+            order_id: Uuid::new_v4(),
+            status: OrderStatus::Pending,
+            courier_id: None,
+            pickup: GeoPoint {
+                lat: 52.51,
+                lng: 13.39,
+            },
+            occurred_at: Utc::now(),
+            items: Vec::new(),
+        });
+
+        assert!(!subscription.matches(&state, &order));
+    }
+
+    #[test]
+    fn courier_and_zone_filters_narrow_locations() {
+        let (state, _rx) = AppState::new(16, 16);
+        let tracked = Uuid::new_v4();
+        let subscription = Subscription {
+            topics: HashSet::from([Topic::CourierLocations]),
+            courier_id: Some(tracked),
+            zone: Some("u33d".to_string()),
+        };
+
+        assert!(subscription.matches(&state, &location_event(tracked, 52.51, 13.39)));
+        assert!(!subscription.matches(&state, &location_event(tracked, 48.85, 2.35)));
+        assert!(!subscription.matches(&state, &location_event(Uuid::new_v4(), 52.51, 13.39)));
+    }
+
+    #[test]
+    fn parses_subscribe_message() {
+        let message: ClientMessage = serde_json::from_str(
+            r#"{"type":"subscribe","topics":["orders","courier_locations"],"zone":"u33"}"#,
+        )
+        .unwrap();
+
+        let ClientMessage::Subscribe(subscription) = message;
+        assert!(subscription.topics.contains(&Topic::Orders));
+        assert!(subscription.topics.contains(&Topic::CourierLocations));
+        assert_eq!(subscription.zone.as_deref(), Some("u33"));
+        assert!(subscription.courier_id.is_none());
+    }
+
+    #[test]
+    fn connect_params_set_initial_subscription() {
+        let params = ConnectParams {
+            topics: Some("orders, courier_locations".to_string()),
+            zone: Some("u33".to_string()),
+            ..ConnectParams::default()
+        };
+
+        let subscription = params.subscription().unwrap();
+        assert_eq!(
+            subscription.topics,
+            HashSet::from([Topic::Orders, Topic::CourierLocations])
+        );
+        assert_eq!(subscription.zone.as_deref(), Some("u33"));
+
+        let invalid = ConnectParams {
+            topics: Some("parcels".to_string()),
+            ..ConnectParams::default()
+        };
+        assert!(invalid.subscription().is_err());
+    }
+
+    #[test]
+    fn binary_encodings_keep_json_field_names() {
+        let event = location_event(Uuid::new_v4(), 52.51, 13.39);
+
+        let Ok(Message::Binary(bytes)) = Encoding::Msgpack.encode(&event) else {
+            panic!("msgpack should produce a binary frame");
+        };
+        let decoded: Value = rmp_serde::from_slice(&bytes).unwrap();
+        assert_eq!(decoded["topic"], "courier_locations");
+        assert_eq!(decoded["data"]["location"]["lat"], 52.51);
+        assert!(decoded["data"]["courier_id"].is_string());
+
+        let Ok(Message::Binary(bytes)) = Encoding::Cbor.encode(&event) else {
+            panic!("cbor should produce a binary frame");
+        };
+        let decoded: Value = ciborium::from_reader(bytes.as_slice()).unwrap();
+        assert_eq!(decoded["topic"], "courier_locations");
+        assert!(decoded["data"]["courier_id"].is_string());
+    }
+
+    #[test]
+    fn decodes_binary_and_text_client_messages() {
+        let subscribe = json!({ "type": "subscribe", "topics": ["orders"] });
+
+        let mut cbor = Vec::new();
+        ciborium::into_writer(&subscribe, &mut cbor).unwrap();
+        let ClientMessage::Subscribe(subscription) =
+            Encoding::Cbor.decode(Message::Binary(cbor)).unwrap();
+        assert!(subscription.topics.contains(&Topic::Orders));
+
+        let ClientMessage::Subscribe(subscription) = Encoding::Msgpack
+            .decode(Message::Text(subscribe.to_string()))
+            .unwrap();
+        assert!(subscription.topics.contains(&Topic::Orders));
+    }
 }
@@ -0,0 +1,138 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::models::audit::{AuditEntity, AuditEntry};
+
+struct Entries {
+    next_seq: u64,
+    entries: VecDeque<AuditEntry>,
+}
+
+/// In-memory record of changes to couriers and orders, keeping the most
+/// recent `capacity` entries. Location updates are not recorded: they are
+/// telemetry, and at ping rates they would push everything else out.
+pub struct AuditLog {
+    capacity: usize,
+    entries: Mutex<Entries>,
+}
+
+impl AuditLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(Entries {
+                next_seq: 1,
+                entries: VecDeque::with_capacity(capacity),
+            }),
+        }
+    }
+
+    pub fn record<T: Serialize>(
+        &self,
+        entity: AuditEntity,
+        entity_id: Uuid,
+        action: &str,
+        actor: &str,
+        old: Option<&T>,
+        new: Option<&T>,
+    ) {
+        if self.capacity == 0 {
+            return;
+        }
+        let snapshot = |value: Option<&T>| value.and_then(|v| serde_json::to_value(v).ok());
+
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = AuditEntry {
+            seq: entries.next_seq,
+            entity,
+            entity_id,
+            action: action.to_string(),
+            actor: actor.to_string(),
+            old: snapshot(old),
+            new: snapshot(new),
+            recorded_at: Utc::now(),
+        };
+        entries.next_seq += 1;
+        if entries.entries.len() == self.capacity {
+            entries.entries.pop_front();
+        }
+        entries.entries.push_back(entry);
+    }
+
+    /// Entries in the order they were recorded, narrowed by whichever
+    /// filters are given.
+    pub fn query(
+        &self,
+        entity: Option<AuditEntity>,
+        entity_id: Option<Uuid>,
+        since: Option<DateTime<Utc>>,
+    ) -> Vec<AuditEntry> {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries
+            .entries
+            .iter()
+            .filter(|entry| entity.is_none_or(|entity| entry.entity == entity))
+            .filter(|entry| entity_id.is_none_or(|id| entry.entity_id == id))
+            .filter(|entry| since.is_none_or(|since| entry.recorded_at > since))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    use super::AuditLog;
+    use crate::models::audit::AuditEntity;
+
+    #[test]
+    fn keeps_the_most_recent_entries_and_filters_them() {
+        let log = AuditLog::new(2);
+        let courier_id = Uuid::new_v4();
+        let order_id = Uuid::new_v4();
+
+        log.record(
+            AuditEntity::Courier,
+            courier_id,
+            "created",
+            "rest",
+            None,
+            Some(&1),
+        );
+        log.record(
+            AuditEntity::Order,
+            order_id,
+            "created",
+            "rest",
+            None,
+            Some(&2),
+        );
+        std::thread::sleep(Duration::from_millis(5));
+        let cutoff = Utc::now();
+        log.record(
+            AuditEntity::Order,
+            order_id,
+            "assigned",
+            "engine",
+            Some(&2),
+            Some(&3),
+        );
+
+        let all = log.query(None, None, None);
+        assert_eq!(all.iter().map(|e| e.seq).collect::<Vec<_>>(), [2, 3]);
+        assert!(log.query(Some(AuditEntity::Courier), None, None).is_empty());
+
+        let assigned = log.query(None, Some(order_id), Some(cutoff));
+        assert_eq!(assigned.len(), 1);
+        assert_eq!(assigned[0].action, "assigned");
+        assert_eq!(assigned[0].old, Some(serde_json::json!(2)));
+    }
+}
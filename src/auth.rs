@@ -0,0 +1,558 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use jsonwebtoken::jwk::{AlgorithmParameters, EllipticCurve, Jwk, JwkSet};
+use jsonwebtoken::{
+    decode, decode_header, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::tenants::DEFAULT_TENANT;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// `kid` and `iss` of the tokens this service issues to couriers, which are
+/// verified with the local secret instead of the JWKS.
+const COURIER_TOKEN_KID: &str = "dispatch-router-courier";
+const COURIER_TOKEN_ISSUER: &str = "dispatch-router";
+
+/// What a token's `roles` claim can grant. Admins can do everything the
+/// other roles can.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// Registers couriers and changes them, manages webhooks, reads the audit log.
+    Admin,
+    /// Creates orders and moves them through their lifecycle.
+    Dispatcher,
+    /// Acts only on the courier named by the token's `courier_id` claim and
+    /// the orders assigned to it.
+    Courier,
+}
+
+impl Role {
+    pub const ALL: [Role; 3] = [Role::Admin, Role::Dispatcher, Role::Courier];
+
+    /// The name used in the `roles` claim.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Admin => "admin",
+            Role::Dispatcher => "dispatcher",
+            Role::Courier => "courier",
+        }
+    }
+
+    fn parse(raw: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|role| role.as_str() == raw)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Claims {
+    sub: String,
+    /// Unknown roles are ignored rather than rejecting the token.
+    #[serde(default)]
+    roles: Vec<String>,
+    #[serde(default)]
+    courier_id: Option<Uuid>,
+    /// The tenant the token is good for; the default tenant when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tenant: Option<String>,
+    /// Only read on courier tokens; the JWKS path leaves it to `Validation`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    iss: Option<String>,
+    #[serde(default)]
+    exp: u64,
+}
+
+/// A token scoped to one courier, handed out when the courier is registered.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct CourierToken {
+    /// Send as `authorization: Bearer <token>`.
+    pub token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Who a request acts as.
+#[derive(Debug, Clone)]
+pub struct Principal {
+    /// The token's `sub`, or the channel name when JWT auth is off. Recorded
+    /// as the actor in the audit log.
+    pub subject: String,
+    pub roles: Vec<Role>,
+    pub courier_id: Option<Uuid>,
+    /// The token's `tenant` claim; `None` for the default tenant.
+    pub tenant: Option<String>,
+}
+
+impl Principal {
+    /// The caller when JWT auth is off: allowed everything, audited under the
+    /// channel it came in on.
+    pub fn unrestricted(channel: &str) -> Self {
+        Self {
+            subject: channel.to_string(),
+            roles: vec![Role::Admin],
+            courier_id: None,
+            tenant: None,
+        }
+    }
+
+    pub fn has_role(&self, role: Role) -> bool {
+        self.roles.contains(&Role::Admin) || self.roles.contains(&role)
+    }
+
+    pub fn require(&self, role: Role) -> Result<(), AppError> {
+        if self.has_role(role) {
+            Ok(())
+        } else {
+            Err(AppError::Forbidden(format!(
+                "{} role required",
+                role.as_str()
+            )))
+        }
+    }
+
+    /// Admins, or the courier the token was issued to.
+    pub fn require_courier(&self, courier_id: Uuid) -> Result<(), AppError> {
+        if self.has_role(Role::Admin) || self.acts_as(courier_id) {
+            Ok(())
+        } else {
+            Err(AppError::Forbidden(format!(
+                "not allowed to act on courier {courier_id}"
+            )))
+        }
+    }
+
+    /// Like [`Principal::require_courier`], but dispatchers may also read.
+    pub fn require_courier_read(&self, courier_id: Uuid) -> Result<(), AppError> {
+        if self.has_role(Role::Dispatcher) {
+            return Ok(());
+        }
+        self.require_courier(courier_id)
+    }
+
+    /// Dispatchers, or the courier the order is assigned to.
+    pub fn require_order_update(&self, assigned_courier: Option<Uuid>) -> Result<(), AppError> {
+        if self.has_role(Role::Dispatcher) || assigned_courier.is_some_and(|id| self.acts_as(id)) {
+            Ok(())
+        } else {
+            Err(AppError::Forbidden(
+                "only dispatchers or the assigned courier can update this order".to_string(),
+            ))
+        }
+    }
+
+    /// Dispatchers, or the courier the order or assignment is for.
+    pub fn require_order_read(&self, courier_id: Option<Uuid>) -> Result<(), AppError> {
+        if self.has_role(Role::Dispatcher) || courier_id.is_some_and(|id| self.acts_as(id)) {
+            Ok(())
+        } else {
+            Err(AppError::Forbidden(
+                "only dispatchers or the assigned courier can read this order".to_string(),
+            ))
+        }
+    }
+
+    /// The courier a listing or event stream is limited to: `requested`
+    /// for dispatchers, and the token's own courier for courier tokens,
+    /// which may not ask for another.
+    pub fn courier_scope(&self, requested: Option<Uuid>) -> Result<Option<Uuid>, AppError> {
+        if self.has_role(Role::Dispatcher) {
+            return Ok(requested);
+        }
+        match self.courier_id {
+            Some(own) if self.acts_as(own) => {
+                if requested.is_none_or(|id| id == own) {
+                    Ok(Some(own))
+                } else {
+                    Err(AppError::Forbidden(
+                        "courier tokens only see their own courier".to_string(),
+                    ))
+                }
+            }
+            _ => self.require(Role::Dispatcher).map(|()| requested),
+        }
+    }
+
+    /// Tokens only act within the tenant they were issued for.
+    pub fn require_tenant(&self, tenant: &str) -> Result<(), AppError> {
+        if self.tenant.as_deref().unwrap_or(DEFAULT_TENANT) == tenant {
+            Ok(())
+        } else {
+            Err(AppError::Forbidden(format!(
+                "token is not valid for tenant {tenant:?}"
+            )))
+        }
+    }
+
+    fn acts_as(&self, courier_id: Uuid) -> bool {
+        self.roles.contains(&Role::Courier) && self.courier_id == Some(courier_id)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct JwtSettings {
+    /// Where the signing keys are published.
+    pub jwks_url: String,
+    /// Required `iss`; unchecked when `None`.
+    pub issuer: Option<String>,
+    /// Required `aud`; unchecked when `None`.
+    pub audience: Option<String>,
+    pub refresh_interval: Duration,
+    /// HS256 secret for signing courier tokens; couriers get no token when
+    /// `None`.
+    pub courier_token_secret: Option<String>,
+    pub courier_token_ttl: Duration,
+}
+
+/// Validates bearer tokens against the keys published at the JWKS URL.
+/// Keys are looked up by the token's `kid`, so rotating in a new key only
+/// needs the set to be refreshed. Also issues and validates courier tokens
+/// when a courier token secret is set.
+pub struct JwtVerifier {
+    settings: JwtSettings,
+    keys: RwLock<HashMap<String, (Algorithm, DecodingKey)>>,
+    courier_keys: Option<(EncodingKey, DecodingKey)>,
+}
+
+impl JwtVerifier {
+    pub fn new(settings: JwtSettings) -> Self {
+        let courier_keys = settings.courier_token_secret.as_ref().map(|secret| {
+            (
+                EncodingKey::from_secret(secret.as_bytes()),
+                DecodingKey::from_secret(secret.as_bytes()),
+            )
+        });
+        Self {
+            settings,
+            keys: RwLock::new(HashMap::new()),
+            courier_keys,
+        }
+    }
+
+    /// Signs a token that can act only as `courier_id` of `tenant`, or `None`
+    /// when courier tokens are off. Tokens are not stored; they expire after
+    /// the TTL.
+    pub fn issue_courier_token(&self, tenant: &str, courier_id: Uuid) -> Option<CourierToken> {
+        let (key, _) = self.courier_keys.as_ref()?;
+        let expires_at = Utc::now()
+            + chrono::Duration::from_std(self.settings.courier_token_ttl).unwrap_or_default();
+        let claims = Claims {
+            sub: format!("courier:{courier_id}"),
+            roles: vec![Role::Courier.as_str().to_string()],
+            courier_id: Some(courier_id),
+            tenant: (tenant != DEFAULT_TENANT).then(|| tenant.to_string()),
+            iss: Some(COURIER_TOKEN_ISSUER.to_string()),
+            exp: expires_at.timestamp().max(0) as u64,
+        };
+        let header = Header {
+            kid: Some(COURIER_TOKEN_KID.to_string()),
+            ..Header::new(Algorithm::HS256)
+        };
+        match encode(&header, &claims, key) {
+            Ok(token) => Some(CourierToken { token, expires_at }),
+            Err(err) => {
+                warn!(error = %err, %courier_id, "failed to sign courier token");
+                None
+            }
+        }
+    }
+
+    /// Replaces the key set. Keys without a `kid` are stored under an empty
+    /// one, which tokens without a `kid` use. Returns how many keys are usable.
+    pub fn set_keys(&self, jwks: &JwkSet) -> usize {
+        let keys: HashMap<_, _> = jwks
+            .keys
+            .iter()
+            .filter_map(|jwk| {
+                let algorithm = key_algorithm(jwk)?;
+                let key = DecodingKey::from_jwk(jwk).ok()?;
+                Some((
+                    jwk.common.key_id.clone().unwrap_or_default(),
+                    (algorithm, key),
+                ))
+            })
+            .collect();
+        let usable = keys.len();
+        *self.keys.write().unwrap_or_else(|e| e.into_inner()) = keys;
+        usable
+    }
+
+    /// Fetches the JWKS and swaps it in.
+    pub async fn refresh(&self, client: &reqwest::Client) -> Result<usize, AppError> {
+        let jwks: JwkSet = client
+            .get(&self.settings.jwks_url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| AppError::Internal(format!("failed to fetch jwks: {err}")))?
+            .json()
+            .await
+            .map_err(|err| AppError::Internal(format!("invalid jwks: {err}")))?;
+        Ok(self.set_keys(&jwks))
+    }
+
+    /// Validates a token's signature, expiry and, when configured, issuer
+    /// and audience.
+    pub fn verify(&self, token: &str) -> Result<Principal, AppError> {
+        let header = decode_header(token)
+            .map_err(|err| AppError::Unauthorized(format!("invalid token: {err}")))?;
+        let kid = header.kid.unwrap_or_default();
+        if kid == COURIER_TOKEN_KID
+            && let Some((_, key)) = &self.courier_keys
+        {
+            return verify_courier_token(token, key);
+        }
+
+        let keys = self.keys.read().unwrap_or_else(|e| e.into_inner());
+        let (algorithm, key) = keys
+            .get(&kid)
+            .ok_or_else(|| AppError::Unauthorized(format!("unknown signing key {kid:?}")))?;
+
+        let mut validation = Validation::new(*algorithm);
+        validation.set_required_spec_claims(&["exp", "sub"]);
+        match &self.settings.issuer {
+            Some(issuer) => validation.set_issuer(&[issuer]),
+            None => validation.iss = None,
+        }
+        match &self.settings.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+
+        let claims = decode::<Claims>(token, key, &validation)
+            .map_err(|err| AppError::Unauthorized(format!("invalid token: {err}")))?
+            .claims;
+
+        Ok(Principal {
+            subject: claims.sub,
+            roles: claims
+                .roles
+                .iter()
+                .filter_map(|role| Role::parse(role))
+                .collect(),
+            courier_id: claims.courier_id,
+            tenant: claims.tenant,
+        })
+    }
+
+    /// Validates the token from an `authorization: Bearer <token>` value.
+    pub fn verify_bearer(&self, authorization: Option<&str>) -> Result<Principal, AppError> {
+        let token = authorization
+            .ok_or_else(|| AppError::Unauthorized("missing bearer token".to_string()))?
+            .strip_prefix("Bearer ")
+            .ok_or_else(|| AppError::Unauthorized("expected a bearer token".to_string()))?;
+        self.verify(token.trim())
+    }
+}
+
+/// Courier tokens carry exactly one courier and nothing else, whatever else
+/// their claims say.
+fn verify_courier_token(token: &str, key: &DecodingKey) -> Result<Principal, AppError> {
+    let mut validation = Validation::new(Algorithm::HS256);
+    validation.set_required_spec_claims(&["exp", "sub", "iss"]);
+    validation.set_issuer(&[COURIER_TOKEN_ISSUER]);
+    validation.validate_aud = false;
+
+    let claims = decode::<Claims>(token, key, &validation)
+        .map_err(|err| AppError::Unauthorized(format!("invalid courier token: {err}")))?
+        .claims;
+    let courier_id = claims
+        .courier_id
+        .ok_or_else(|| AppError::Unauthorized("courier token without courier_id".to_string()))?;
+
+    Ok(Principal {
+        subject: claims.sub,
+        roles: vec![Role::Courier],
+        courier_id: Some(courier_id),
+        tenant: claims.tenant,
+    })
+}
+
+/// Compares secrets without returning early on the first mismatch.
+pub(crate) fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0u8, |diff, (x, y)| diff | (x ^ y))
+            == 0
+}
+
+/// The algorithm a JWK is used with: its `alg`, or what its key type implies
+/// when the publisher left `alg` out.
+fn key_algorithm(jwk: &Jwk) -> Option<Algorithm> {
+    if let Some(algorithm) = jwk.common.key_algorithm {
+        return Algorithm::from_str(&algorithm.to_string()).ok();
+    }
+    match &jwk.algorithm {
+        AlgorithmParameters::RSA(_) => Some(Algorithm::RS256),
+        AlgorithmParameters::EllipticCurve(params) => match params.curve {
+            EllipticCurve::P256 => Some(Algorithm::ES256),
+            EllipticCurve::P384 => Some(Algorithm::ES384),
+            _ => None,
+        },
+        AlgorithmParameters::OctetKey(_) => Some(Algorithm::HS256),
+        AlgorithmParameters::OctetKeyPair(_) => Some(Algorithm::EdDSA),
+    }
+}
+
+/// Re-fetches the JWKS every `refresh_interval` so rotated keys are picked
+/// up. A failed fetch keeps the previous keys.
+pub async fn run_jwks_refresh(verifier: Arc<JwtVerifier>) {
+    let client = match reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(err) => {
+            warn!(error = %err, "failed to build jwks client, keys will not be refreshed");
+            return;
+        }
+    };
+
+    let mut interval = tokio::time::interval(verifier.settings.refresh_interval);
+    loop {
+        interval.tick().await;
+        match verifier.refresh(&client).await {
+            Ok(keys) => info!(keys, "jwks refreshed"),
+            Err(err) => warn!(error = %err, "jwks refresh failed, keeping previous keys"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use jsonwebtoken::jwk::JwkSet;
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use super::{JwtSettings, JwtVerifier, Role};
+    use crate::tenants::DEFAULT_TENANT;
+
+    fn verifier() -> JwtVerifier {
+        let verifier = JwtVerifier::new(JwtSettings {
+            jwks_url: "http://idp.test/jwks".to_string(),
+            issuer: Some("https://idp.test".to_string()),
+            audience: None,
+            refresh_interval: Duration::from_secs(300),
+            courier_token_secret: Some("courier-token-secret-for-tests-only".to_string()),
+            courier_token_ttl: Duration::from_secs(3600),
+        });
+        let jwks: JwkSet = serde_json::from_value(json!({
+            "keys": [{ "kty": "oct", "kid": "k1", "alg": "HS256", "k": "c2VjcmV0" }]
+        }))
+        .unwrap();
+        assert_eq!(verifier.set_keys(&jwks), 1);
+        verifier
+    }
+
+    fn token(kid: &str, claims: serde_json::Value) -> String {
+        let header = Header {
+            kid: Some(kid.to_string()),
+            ..Default::default()
+        };
+        encode(&header, &claims, &EncodingKey::from_secret(b"secret")).unwrap()
+    }
+
+    #[test]
+    fn verifies_tokens_and_extracts_roles() {
+        let courier_id = Uuid::new_v4();
+        let principal = verifier()
+            .verify(&token(
+                "k1",
+                json!({
+                    "sub": "rider-7",
+                    "iss": "https://idp.test",
+                    "exp": 4_102_444_800u64,
+                    "roles": ["courier", "unknown"],
+                    "courier_id": courier_id,
+                }),
+            ))
+            .unwrap();
+
+        assert_eq!(principal.subject, "rider-7");
+        assert_eq!(principal.roles, vec![Role::Courier]);
+        assert!(principal.require_courier(courier_id).is_ok());
+        assert!(principal.require_courier(Uuid::new_v4()).is_err());
+        assert!(principal.require(Role::Dispatcher).is_err());
+    }
+
+    #[test]
+    fn rejects_wrong_issuer_expired_and_unknown_keys() {
+        let verifier = verifier();
+        let claims = |iss: &str, exp: u64| json!({ "sub": "x", "iss": iss, "exp": exp });
+
+        assert!(verifier
+            .verify(&token("k1", claims("https://other.test", 4_102_444_800)))
+            .is_err());
+        assert!(verifier
+            .verify(&token("k1", claims("https://idp.test", 1)))
+            .is_err());
+        assert!(verifier
+            .verify(&token("k2", claims("https://idp.test", 4_102_444_800)))
+            .is_err());
+    }
+
+    #[test]
+    fn courier_tokens_act_only_as_their_courier() {
+        let verifier = verifier();
+        let courier_id = Uuid::new_v4();
+        let issued = verifier
+            .issue_courier_token(DEFAULT_TENANT, courier_id)
+            .unwrap();
+
+        let principal = verifier.verify(&issued.token).unwrap();
+        assert_eq!(principal.roles, vec![Role::Courier]);
+        assert!(principal.require_courier(courier_id).is_ok());
+        assert!(principal.require_courier(Uuid::new_v4()).is_err());
+        assert!(principal.require(Role::Dispatcher).is_err());
+
+        // Signed with the JWKS key under the courier kid: not a courier token.
+        let forged = token(
+            "dispatch-router-courier",
+            json!({
+                "sub": "x",
+                "iss": "dispatch-router",
+                "exp": 4_102_444_800u64,
+                "roles": ["admin"],
+                "courier_id": courier_id,
+            }),
+        );
+        assert!(verifier.verify(&forged).is_err());
+    }
+
+    #[test]
+    fn tokens_act_only_within_their_tenant() {
+        let verifier = verifier();
+        let courier_id = Uuid::new_v4();
+
+        let default = verifier
+            .verify(
+                &verifier
+                    .issue_courier_token(DEFAULT_TENANT, courier_id)
+                    .unwrap()
+                    .token,
+            )
+            .unwrap();
+        assert!(default.require_tenant(DEFAULT_TENANT).is_ok());
+        assert!(default.require_tenant("acme").is_err());
+
+        let acme = verifier
+            .verify(
+                &verifier
+                    .issue_courier_token("acme", courier_id)
+                    .unwrap()
+                    .token,
+            )
+            .unwrap();
+        assert_eq!(acme.tenant.as_deref(), Some("acme"));
+        assert!(acme.require_tenant("acme").is_ok());
+        assert!(acme.require_tenant(DEFAULT_TENANT).is_err());
+    }
+}
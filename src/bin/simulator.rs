@@ -0,0 +1,396 @@
+//! Load simulator: registers synthetic couriers with a running instance,
+//! submits orders as a Poisson process around a few hotspots, walks each
+//! assigned order through to `Delivered`, and reports how long orders waited
+//! for an assignment.
+//!
+//! ```text
+//! cargo run --bin simulator -- --couriers 200 --rate 20 --duration-secs 120
+//! ```
+
+use std::collections::HashMap;
+use std::f64::consts::TAU;
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use clap::Parser;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use reqwest::{Client, RequestBuilder};
+use serde_json::{json, Value};
+
+/// Kilometres per degree of latitude.
+const KM_PER_DEGREE: f64 = 111.195;
+
+#[derive(Parser)]
+#[command(about = "Generate synthetic load against a running dispatch-router")]
+struct Args {
+    /// Base URL of the REST API.
+    #[arg(long, default_value = "http://localhost:3000")]
+    url: String,
+    /// Bearer token with the admin role, when JWT auth is on.
+    #[arg(long)]
+    token: Option<String>,
+    /// Couriers to register before submitting orders.
+    #[arg(long, default_value_t = 50)]
+    couriers: usize,
+    /// Capacity of each courier.
+    #[arg(long, default_value_t = 3.0)]
+    courier_capacity: f64,
+    /// Mean orders per second; gaps between orders are exponential.
+    #[arg(long, default_value_t = 5.0)]
+    rate: f64,
+    /// How long to keep submitting orders.
+    #[arg(long, default_value_t = 60)]
+    duration_secs: u64,
+    /// How long to wait afterwards for outstanding assignments.
+    #[arg(long, default_value_t = 10)]
+    drain_secs: u64,
+    /// Centre of the simulated city, as `lat,lng`.
+    #[arg(long, default_value = "52.52,13.405", value_parser = parse_point)]
+    center: (f64, f64),
+    /// Radius of the simulated city.
+    #[arg(long, default_value_t = 5.0)]
+    radius_km: f64,
+    /// Busy spots, e.g. restaurant streets, scattered over the city.
+    #[arg(long, default_value_t = 3)]
+    hotspots: usize,
+    /// Share of orders picked up at a hotspot rather than anywhere.
+    #[arg(long, default_value_t = 0.7)]
+    hotspot_share: f64,
+    /// How far from its hotspot an order may be picked up.
+    #[arg(long, default_value_t = 0.5)]
+    hotspot_radius_km: f64,
+    /// Time from assignment to delivery, freeing the courier again.
+    #[arg(long, default_value_t = 5)]
+    delivery_secs: u64,
+    /// Seed for reproducible runs.
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+fn parse_point(raw: &str) -> Result<(f64, f64), String> {
+    let (lat, lng) = raw
+        .split_once(',')
+        .ok_or_else(|| "expected lat,lng".to_string())?;
+    let lat: f64 = lat.trim().parse().map_err(|_| "invalid latitude")?;
+    let lng: f64 = lng.trim().parse().map_err(|_| "invalid longitude")?;
+    if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lng) {
+        return Err("latitude must be within ±90 and longitude within ±180".to_string());
+    }
+    Ok((lat, lng))
+}
+
+/// A uniformly random point within `radius_km` of `center`.
+fn point_near(rng: &mut impl Rng, center: (f64, f64), radius_km: f64) -> (f64, f64) {
+    let distance = radius_km * rng.r#gen::<f64>().sqrt();
+    let bearing = TAU * rng.r#gen::<f64>();
+    let lat = center.0 + distance * bearing.cos() / KM_PER_DEGREE;
+    let lng = center.1 + distance * bearing.sin() / (KM_PER_DEGREE * center.0.to_radians().cos());
+    (lat.clamp(-90.0, 90.0), lng.clamp(-180.0, 180.0))
+}
+
+/// Where an order stands between submitting it and seeing its assignment,
+/// which can arrive before the response to the submit does.
+enum Pending {
+    Submitted(Instant),
+    Assigned(Instant),
+}
+
+#[derive(Default)]
+struct Counters {
+    submitted: AtomicU64,
+    refused: AtomicU64,
+    failed: AtomicU64,
+    delivered: AtomicU64,
+}
+
+struct Simulation {
+    client: Client,
+    url: String,
+    token: Option<String>,
+    delivery: Duration,
+    pending: Mutex<HashMap<String, Pending>>,
+    latencies: Mutex<Vec<Duration>>,
+    counters: Counters,
+}
+
+impl Simulation {
+    fn request(&self, method: reqwest::Method, path: &str) -> RequestBuilder {
+        let request = self.client.request(method, format!("{}{}", self.url, path));
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    /// Notes one side of an order's submit/assign pair, recording the
+    /// latency once both are in.
+    fn observe(&self, order_id: String, side: Pending) {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        let latency = match (pending.remove(&order_id), side) {
+            (Some(Pending::Submitted(at)), Pending::Assigned(assigned)) => {
+                assigned.saturating_duration_since(at)
+            }
+            (Some(Pending::Assigned(assigned)), Pending::Submitted(at)) => {
+                assigned.saturating_duration_since(at)
+            }
+            (_, side) => {
+                pending.insert(order_id, side);
+                return;
+            }
+        };
+        drop(pending);
+        self.latencies
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(latency);
+    }
+
+    async fn register_couriers(&self, args: &Args, rng: &mut StdRng) -> Result<(), String> {
+        for n in 0..args.couriers {
+            let (lat, lng) = point_near(rng, args.center, args.radius_km);
+            let rating = rng.gen_range(3.5..=5.0);
+            let res = self
+                .request(reqwest::Method::POST, "/couriers")
+                .json(&json!({
+                    "name": format!("sim-{n}"),
+                    "location": { "lat": lat, "lng": lng },
+                    "capacity": args.courier_capacity,
+                    "rating": rating,
+                }))
+                .send()
+                .await
+                .map_err(|err| format!("registering courier: {err}"))?;
+            if !res.status().is_success() {
+                let status = res.status();
+                let body = res.text().await.unwrap_or_default();
+                return Err(format!("registering courier: {status} {body}"));
+            }
+        }
+        Ok(())
+    }
+
+    async fn submit_order(self: Arc<Self>, pickup: (f64, f64), dropoff: (f64, f64)) {
+        self.counters.submitted.fetch_add(1, Ordering::Relaxed);
+        let sent = Instant::now();
+        let res = self
+            .request(reqwest::Method::POST, "/orders")
+            .json(&json!({
+                "pickup": { "lat": pickup.0, "lng": pickup.1 },
+                "dropoff": { "lat": dropoff.0, "lng": dropoff.1 },
+                "priority": "Normal",
+            }))
+            .send()
+            .await;
+        let order_id = match res {
+            Ok(res) if res.status().is_success() => res
+                .json::<Value>()
+                .await
+                .ok()
+                .and_then(|body| body["id"].as_str().map(str::to_string)),
+            Ok(_) => {
+                self.counters.refused.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+            Err(_) => None,
+        };
+        match order_id {
+            Some(order_id) => self.observe(order_id, Pending::Submitted(sent)),
+            None => {
+                self.counters.failed.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Picks the order up and delivers it after `delivery`, so the courier
+    /// takes work again.
+    async fn deliver(self: Arc<Self>, order_id: String) {
+        tokio::time::sleep(self.delivery).await;
+        for status in ["InTransit", "Delivered"] {
+            let res = self
+                .request(
+                    reqwest::Method::PATCH,
+                    &format!("/orders/{order_id}/status"),
+                )
+                .json(&json!({ "status": status }))
+                .send()
+                .await;
+            if !res.is_ok_and(|res| res.status().is_success()) {
+                return;
+            }
+        }
+        self.counters.delivered.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Reads assignments off `/events/stream` until the stream ends.
+    async fn watch_assignments(self: Arc<Self>) -> Result<(), String> {
+        let mut res = self
+            .request(reqwest::Method::GET, "/events/stream?topics=assignments")
+            .send()
+            .await
+            .map_err(|err| format!("opening event stream: {err}"))?;
+        if !res.status().is_success() {
+            return Err(format!("opening event stream: {}", res.status()));
+        }
+
+        let mut buffer = String::new();
+        while let Some(chunk) = res
+            .chunk()
+            .await
+            .map_err(|err| format!("reading event stream: {err}"))?
+        {
+            buffer.push_str(&String::from_utf8_lossy(&chunk));
+            while let Some(end) = buffer.find('\n') {
+                let line: String = buffer.drain(..=end).collect();
+                let Some(data) = line.trim_end().strip_prefix("data:") else {
+                    continue;
+                };
+                let Ok(event) = serde_json::from_str::<Value>(data.trim()) else {
+                    continue;
+                };
+                if event["topic"] != "assignments" {
+                    continue;
+                }
+                if let Some(order_id) = event["data"]["order_id"].as_str() {
+                    self.observe(order_id.to_string(), Pending::Assigned(Instant::now()));
+                    tokio::spawn(self.clone().deliver(order_id.to_string()));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The value below which `share` of the sorted `values` fall.
+fn percentile(sorted: &[Duration], share: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = ((share * sorted.len() as f64).ceil() as usize).clamp(1, sorted.len());
+    sorted[rank - 1]
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = Args::parse();
+    if args.rate <= 0.0 || !args.rate.is_finite() {
+        eprintln!("--rate must be a positive number");
+        return ExitCode::FAILURE;
+    }
+    if !(0.0..=1.0).contains(&args.hotspot_share) {
+        eprintln!("--hotspot-share must be between 0 and 1");
+        return ExitCode::FAILURE;
+    }
+
+    let mut rng = match args.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let simulation = Arc::new(Simulation {
+        client: Client::new(),
+        url: args.url.trim_end_matches('/').to_string(),
+        token: args.token.clone(),
+        delivery: Duration::from_secs(args.delivery_secs),
+        pending: Mutex::new(HashMap::new()),
+        latencies: Mutex::new(Vec::new()),
+        counters: Counters::default(),
+    });
+
+    println!("registering {} couriers", args.couriers);
+    if let Err(err) = simulation.register_couriers(&args, &mut rng).await {
+        eprintln!("{err}");
+        return ExitCode::FAILURE;
+    }
+    let watcher = tokio::spawn(simulation.clone().watch_assignments());
+
+    let hotspots: Vec<(f64, f64)> = (0..args.hotspots)
+        .map(|_| point_near(&mut rng, args.center, args.radius_km))
+        .collect();
+    println!(
+        "submitting ~{} orders/s for {}s around {} hotspots",
+        args.rate,
+        args.duration_secs,
+        hotspots.len()
+    );
+
+    let started = Instant::now();
+    let until = started + Duration::from_secs(args.duration_secs);
+    let mut next = started;
+    while next < until {
+        let gap = -(1.0 - rng.r#gen::<f64>()).ln() / args.rate;
+        next += Duration::from_secs_f64(gap);
+        tokio::time::sleep_until(next.into()).await;
+
+        let pickup = if !hotspots.is_empty() && rng.gen_bool(args.hotspot_share) {
+            let hotspot = hotspots[rng.gen_range(0..hotspots.len())];
+            point_near(&mut rng, hotspot, args.hotspot_radius_km)
+        } else {
+            point_near(&mut rng, args.center, args.radius_km)
+        };
+        let dropoff = point_near(&mut rng, args.center, args.radius_km);
+        tokio::spawn(simulation.clone().submit_order(pickup, dropoff));
+    }
+    let elapsed = started.elapsed();
+
+    tokio::time::sleep(Duration::from_secs(args.drain_secs)).await;
+    if watcher.is_finished() {
+        if let Ok(Err(err)) = watcher.await {
+            eprintln!("{err}");
+            return ExitCode::FAILURE;
+        }
+    } else {
+        watcher.abort();
+    }
+
+    let mut latencies = simulation
+        .latencies
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone();
+    latencies.sort();
+    let counters = &simulation.counters;
+    let submitted = counters.submitted.load(Ordering::Relaxed);
+    let unassigned = simulation
+        .pending
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .values()
+        .filter(|side| matches!(side, Pending::Submitted(_)))
+        .count();
+
+    println!();
+    println!(
+        "orders submitted  {submitted} ({:.1}/s)",
+        submitted as f64 / elapsed.as_secs_f64()
+    );
+    println!(
+        "refused / failed  {} / {}",
+        counters.refused.load(Ordering::Relaxed),
+        counters.failed.load(Ordering::Relaxed)
+    );
+    println!("assigned          {}", latencies.len());
+    println!("still unassigned  {unassigned}");
+    println!(
+        "delivered         {}",
+        counters.delivered.load(Ordering::Relaxed)
+    );
+    println!();
+    println!("assignment latency (ms, from submit to the assignment event)");
+    for (label, share) in [("p50", 0.5), ("p90", 0.9), ("p95", 0.95), ("p99", 0.99)] {
+        println!(
+            "  {label:<4} {:>9.1}",
+            millis(percentile(&latencies, share))
+        );
+    }
+    println!(
+        "  max  {:>9.1}",
+        millis(latencies.last().copied().unwrap_or_default())
+    );
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,101 @@
+use std::time::Duration;
+
+use prometheus::IntCounterVec;
+use rand::Rng;
+
+/// How often to misbehave on purpose. Every rate is a probability from 0
+/// to 1 and defaults to 0, which leaves that kind of fault out.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ChaosSettings {
+    /// Share of REST requests held up by `latency` before they are handled.
+    pub latency_rate: f64,
+    pub latency: Duration,
+    /// Share of events that are given a `seq` but never reach the stream,
+    /// webhooks or the message buses, leaving a gap for consumers to notice.
+    pub drop_event_rate: f64,
+    /// Share of orders the engine panics on after putting them back in the
+    /// queue, for the supervisor to restart it.
+    pub engine_panic_rate: f64,
+}
+
+impl ChaosSettings {
+    pub fn is_enabled(&self) -> bool {
+        self.latency_rate > 0.0 || self.drop_event_rate > 0.0 || self.engine_panic_rate > 0.0
+    }
+}
+
+/// Injects the faults of [`ChaosSettings`] at random, counting each in
+/// `chaos_injections_total`, so retries and alerting can be tried against
+/// a misbehaving instance. Off unless configured; never turn it on in
+/// production.
+pub struct Chaos {
+    pub settings: ChaosSettings,
+    injections: IntCounterVec,
+}
+
+impl Chaos {
+    pub fn new(settings: ChaosSettings, injections: IntCounterVec) -> Self {
+        Self {
+            settings,
+            injections,
+        }
+    }
+
+    /// How long to hold up the next request, if at all.
+    pub fn latency(&self) -> Option<Duration> {
+        self.roll(self.settings.latency_rate, "latency")
+            .then_some(self.settings.latency)
+    }
+
+    pub fn drop_event(&self) -> bool {
+        self.roll(self.settings.drop_event_rate, "dropped_event")
+    }
+
+    pub fn engine_panic(&self) -> bool {
+        self.roll(self.settings.engine_panic_rate, "engine_panic")
+    }
+
+    fn roll(&self, rate: f64, kind: &str) -> bool {
+        let injected = rate > 0.0 && rand::thread_rng().gen_bool(rate.min(1.0));
+        if injected {
+            self.injections.with_label_values(&[kind]).inc();
+        }
+        injected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Chaos, ChaosSettings};
+    use crate::observability::metrics::Metrics;
+
+    #[test]
+    fn injects_at_the_configured_rates() {
+        let metrics = Metrics::default();
+        let chaos = Chaos::new(
+            ChaosSettings {
+                latency_rate: 1.0,
+                latency: Duration::from_millis(250),
+                drop_event_rate: 0.0,
+                engine_panic_rate: 1.0,
+            },
+            metrics.chaos_injections_total.clone(),
+        );
+        assert_eq!(chaos.latency(), Some(Duration::from_millis(250)));
+        assert!(!chaos.drop_event());
+        assert!(chaos.engine_panic());
+
+        let counted = |kind: &str| {
+            metrics
+                .chaos_injections_total
+                .with_label_values(&[kind])
+                .get()
+        };
+        assert_eq!(counted("latency"), 1);
+        assert_eq!(counted("dropped_event"), 0);
+        assert_eq!(counted("engine_panic"), 1);
+        assert!(!ChaosSettings::default().is_enabled());
+    }
+}
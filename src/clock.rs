@@ -0,0 +1,76 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+use futures::future::BoxFuture;
+use tokio::sync::watch;
+
+/// Where the engine reads the time from. Stamps, break and heartbeat
+/// expiry, the dispatch stats, stall detection and the engine
+/// supervisor's backoff all go through `AppState::clock`, so a simulation
+/// or test can swap in a [`ManualClock`] and move time on instead of
+/// sleeping through it.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+
+    /// Resolves once `duration` has passed on this clock.
+    fn sleep(&self, duration: std::time::Duration) -> BoxFuture<'static, ()>;
+}
+
+/// The wall clock; what every instance runs on.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    fn sleep(&self, duration: std::time::Duration) -> BoxFuture<'static, ()> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/// A clock that stands still until it is set or advanced. Clones share
+/// their time, so a test keeps one and hands another to the state. Its
+/// sleeps end when the clock is moved past them.
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    now: Arc<watch::Sender<DateTime<Utc>>>,
+}
+
+impl ManualClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            now: Arc::new(watch::Sender::new(now)),
+        }
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        self.now.send_replace(now);
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.now.send_modify(|now| *now += by);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.borrow()
+    }
+
+    fn sleep(&self, duration: std::time::Duration) -> BoxFuture<'static, ()> {
+        let mut now = self.now.subscribe();
+        let until = Duration::from_std(duration)
+            .ok()
+            .and_then(|duration| now.borrow().checked_add_signed(duration));
+        Box::pin(async move {
+            match until {
+                Some(until) => {
+                    let _ = now.wait_for(|now| *now >= until).await;
+                }
+                None => std::future::pending().await,
+            }
+        })
+    }
+}
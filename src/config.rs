@@ -1,39 +1,907 @@
+use std::collections::HashMap;
 use std::env;
+use std::fmt::Display;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
 
+use tracing_subscriber::EnvFilter;
+use uuid::Uuid;
+
+use crate::chaos::ChaosSettings;
+use crate::engine::lifecycle::PayoutRates;
+use crate::engine::regions::RegionSettings;
+use crate::engine::scoring::ScoringWeights;
+use crate::engine::surge::SurgeSettings;
 use crate::error::AppError;
+use crate::geo::is_geohash;
+use crate::listen::ListenAddr;
+use crate::models::courier::{CapacityUnit, ZoneMode};
+use crate::models::order::OrderStatus;
+use crate::tenants::{is_tenant_id, DEFAULT_TENANT};
 
 #[derive(Debug, Clone)]
 pub struct Config {
     pub http_port: u16,
+    /// Every address the REST API is served on; `0.0.0.0:HTTP_PORT` unless
+    /// `HTTP_LISTEN` says otherwise.
+    pub http_listen: Vec<ListenAddr>,
+    pub tls_cert_path: String,
+    pub tls_key_path: String,
+    pub tls_reload_interval_secs: u64,
     pub grpc_port: u16,
-    pub log_level: String,
+    pub grpc_listen: Vec<ListenAddr>,
+    pub metrics_port: u16,
+    pub metrics_bearer_token: Option<String>,
+    pub metrics_basic_auth: Option<String>,
+    pub log_json: bool,
+    /// The dotenv file loaded at startup, re-read on reload.
+    pub config_file: Option<PathBuf>,
+    pub config_reload_interval_secs: u64,
+    pub tunables: Tunables,
     pub order_queue_size: usize,
     pub event_buffer_size: usize,
+    pub event_replay_size: usize,
+    pub audit_log_size: usize,
+    pub distance_cache_size: usize,
+    pub parallel_scoring_threshold: usize,
+    pub ws_ping_interval_secs: u64,
+    pub ws_idle_timeout_secs: u64,
+    pub ws_max_connections: usize,
+    pub ws_max_connections_per_ip: usize,
+    pub grpc_api_keys: Vec<String>,
+    /// Tenants served besides the default one.
+    pub tenants: Vec<String>,
+    /// Areas dispatched by engines of their own.
+    pub regions: Vec<RegionSettings>,
+    /// How far back `GET /stats` looks.
+    pub stats_window_secs: u64,
+    /// How many minutes `GET /stats/timeseries` keeps.
+    pub stats_timeseries_minutes: usize,
+    /// Where daily reports are written; `None` keeps them in memory only.
+    pub reports_dir: Option<PathBuf>,
+    /// When a zone surges and how its orders are boosted.
+    pub surge: SurgeSettings,
+    pub surge_check_interval_secs: u64,
+    /// Faults to inject for resilience testing; all off by default.
+    pub chaos: ChaosSettings,
+    /// Engines orders outside every region are hashed onto by pickup area.
+    pub engine_shards: usize,
+    pub jwt_jwks_url: String,
+    pub jwt_issuer: Option<String>,
+    pub jwt_audience: Option<String>,
+    pub jwt_jwks_refresh_secs: u64,
+    pub courier_token_secret: Option<String>,
+    pub courier_token_ttl_secs: u64,
+    pub cors_allowed_origins: Vec<String>,
+    pub cors_allowed_methods: Vec<String>,
+    pub cors_max_age_secs: u64,
+    pub rate_limit_order_create_per_min: u32,
+    pub rate_limit_write_per_min: u32,
+    pub rate_limit_read_per_min: u32,
+    pub max_request_body_bytes: usize,
+    pub request_timeout_secs: u64,
+    pub max_concurrent_requests: usize,
+    pub shutdown_drain_secs: u64,
+    pub courier_heartbeat_timeout_secs: u64,
+    pub capacity_unit: CapacityUnit,
+    pub zone_mode: ZoneMode,
+    pub payouts: PayoutRates,
+    pub webhook_max_attempts: u32,
+    pub webhook_retry_base_ms: u64,
+    pub webhook_timeout_secs: u64,
+    pub webhook_dead_letter_limit: usize,
+    pub kafka_brokers: String,
+    pub kafka_assignments_topic: String,
+    pub kafka_orders_topic: String,
+    pub nats_url: String,
+    pub nats_subject_prefix: String,
+    pub nats_jetstream: bool,
+    pub nats_orders_subject: String,
+    pub mqtt_host: String,
+    pub mqtt_port: u16,
+    pub mqtt_client_id: String,
+    pub mqtt_username: Option<String>,
+    pub mqtt_password: Option<String>,
+    pub mqtt_topic: String,
+    pub mqtt_device_map: HashMap<String, Uuid>,
+    pub redis_url: String,
+    pub redis_channel: String,
+    /// Redis key replicas compete for so that only one runs the engines;
+    /// `None` runs them everywhere.
+    pub leader_election_key: Option<String>,
+    pub leader_lease_secs: u64,
+    /// Publish whole couriers and orders as they change, for standbys.
+    pub publish_changes: bool,
+    /// Bearer token a standby presents to its primary.
+    pub standby_token: Option<String>,
+    pub push_gateway_url: String,
+    pub push_gateway_token: Option<String>,
+    pub customer_notify_statuses: Vec<OrderStatus>,
+    pub customer_callback_secret: Option<String>,
+    pub customer_gateway_url: String,
+    pub customer_template_assigned: Option<String>,
+    pub customer_template_in_transit: Option<String>,
+    pub customer_template_delivered: Option<String>,
+    pub otel_exporter_endpoint: String,
+    pub otel_service_name: String,
+    /// Where `tokio-console` connects (`console` feature only).
+    pub console_bind: SocketAddr,
+    /// Variables that were found only under their old, unprefixed name.
+    pub legacy_env_vars: Vec<String>,
+}
+
+/// Settings that can be changed without a restart; see [`crate::reload`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tunables {
+    pub log_level: String,
+    pub scoring: ScoringWeights,
+    /// How long the engine waits before re-queueing an order no courier
+    /// could take.
+    pub requeue_delay: Duration,
+    /// Longest detour, in km, the engine adds to a courier's route to stack
+    /// another order on it; 0 turns stacking off.
+    pub stacking_max_detour_km: f64,
+    /// Farthest, in km, a courier may be from an order's pickup to be
+    /// offered it; 0 for no limit.
+    pub max_pickup_km: f64,
+    /// Share of a courier's rating each new piece of feedback makes up;
+    /// older feedback fades by the rest.
+    pub feedback_rating_weight: f64,
+    /// gRPC address of the instance this one mirrors as a warm standby;
+    /// empty for none. Clearing it on reload promotes the standby.
+    pub standby_primary_url: String,
+}
+
+impl Default for Tunables {
+    fn default() -> Self {
+        Self {
+            log_level: "info".to_string(),
+            scoring: ScoringWeights::default(),
+            requeue_delay: Duration::from_millis(250),
+            stacking_max_detour_km: 0.0,
+            max_pickup_km: 0.0,
+            feedback_rating_weight: 0.1,
+            standby_primary_url: String::new(),
+        }
+    }
+}
+
+impl Tunables {
+    /// Reads the tunables through `lookup`, which returns a variable's value
+    /// if it is set.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Self, AppError> {
+        let mut reader = Reader::new(lookup);
+        let tunables = Self::read(&mut reader);
+        reader.finish(tunables)
+    }
+
+    fn read<F: Fn(&str) -> Option<String>>(r: &mut Reader<F>) -> Self {
+        let defaults = Self::default();
+
+        let log_level = r.string("LOG_LEVEL", &defaults.log_level);
+        if let Err(err) = EnvFilter::try_new(&log_level) {
+            r.invalid("LOG_LEVEL", err);
+        }
+
+        let mut weight = |key: &str, default: f64| {
+            let weight: f64 = r.parse(key, default);
+            if !weight.is_finite() || weight < 0.0 {
+                r.invalid(key, "must be a number >= 0");
+            }
+            weight
+        };
+        let scoring = ScoringWeights {
+            distance: weight("SCORING_DISTANCE_WEIGHT", defaults.scoring.distance),
+            load: weight("SCORING_LOAD_WEIGHT", defaults.scoring.load),
+            rating: weight("SCORING_RATING_WEIGHT", defaults.scoring.rating),
+            priority: weight("SCORING_PRIORITY_WEIGHT", defaults.scoring.priority),
+            zone: weight("SCORING_ZONE_WEIGHT", defaults.scoring.zone),
+            reliability: weight("SCORING_RELIABILITY_WEIGHT", defaults.scoring.reliability),
+            idle: weight("SCORING_IDLE_WEIGHT", defaults.scoring.idle),
+            earnings: weight("SCORING_EARNINGS_WEIGHT", defaults.scoring.earnings),
+        };
+        let total = scoring.distance
+            + scoring.load
+            + scoring.rating
+            + scoring.priority
+            + scoring.zone
+            + scoring.reliability
+            + scoring.idle
+            + scoring.earnings;
+        if total == 0.0 {
+            r.invalid("SCORING_DISTANCE_WEIGHT", "at least one weight must be > 0");
+        }
+
+        let stacking_max_detour_km: f64 =
+            r.parse("STACKING_MAX_DETOUR_KM", defaults.stacking_max_detour_km);
+        if !stacking_max_detour_km.is_finite() || stacking_max_detour_km < 0.0 {
+            r.invalid("STACKING_MAX_DETOUR_KM", "must be a number >= 0");
+        }
+        let max_pickup_km: f64 = r.parse("MAX_PICKUP_KM", defaults.max_pickup_km);
+        if !max_pickup_km.is_finite() || max_pickup_km < 0.0 {
+            r.invalid("MAX_PICKUP_KM", "must be a number >= 0");
+        }
+        let feedback_rating_weight: f64 =
+            r.parse("FEEDBACK_RATING_WEIGHT", defaults.feedback_rating_weight);
+        if !(feedback_rating_weight > 0.0 && feedback_rating_weight <= 1.0) {
+            r.invalid("FEEDBACK_RATING_WEIGHT", "must be a number > 0 and <= 1");
+        }
+        let standby_primary_url = r.string("STANDBY_PRIMARY_URL", "");
+        if !standby_primary_url.is_empty()
+            && !reqwest::Url::parse(&standby_primary_url)
+                .is_ok_and(|url| matches!(url.scheme(), "http" | "https"))
+        {
+            r.invalid("STANDBY_PRIMARY_URL", "expected an http or https URL");
+        }
+
+        Self {
+            log_level,
+            scoring,
+            requeue_delay: Duration::from_millis(r.parse(
+                "ENGINE_REQUEUE_DELAY_MS",
+                defaults.requeue_delay.as_millis() as u64,
+            )),
+            stacking_max_detour_km,
+            max_pickup_km,
+            feedback_rating_weight,
+            standby_primary_url,
+        }
+    }
+}
+
+/// `REGIONS`, as `name=geohash` pairs (a name repeats for each cell it
+/// covers), and the `REGION_<NAME>_*` settings of each region.
+fn read_regions<F: Fn(&str) -> Option<String>>(
+    r: &mut Reader<F>,
+    defaults: &Tunables,
+) -> Vec<RegionSettings> {
+    let mut regions: Vec<RegionSettings> = Vec::new();
+    for entry in r.list("REGIONS") {
+        let Some((name, cell)) = entry.split_once('=') else {
+            r.invalid("REGIONS", format!("{entry:?} is not name=geohash"));
+            continue;
+        };
+        let (name, cell) = (name.trim(), cell.trim().to_lowercase());
+        if name.is_empty()
+            || !name
+                .bytes()
+                .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_')
+        {
+            r.invalid(
+                "REGIONS",
+                format!("{name:?} is not lowercase letters, digits or '_'"),
+            );
+        } else if !is_geohash(&cell) {
+            r.invalid(
+                "REGIONS",
+                format!("{cell:?} is not a geohash of 1 to 12 characters"),
+            );
+        } else {
+            match regions.iter_mut().find(|region| region.name == name) {
+                Some(region) => region.cells.push(cell),
+                None => regions.push(RegionSettings {
+                    name: name.to_string(),
+                    cells: vec![cell],
+                    scoring: None,
+                    max_pickup_km: None,
+                    stacking_max_detour_km: None,
+                }),
+            }
+        }
+    }
+
+    for region in &mut regions {
+        let prefix = format!("REGION_{}_", region.name.to_uppercase());
+        let non_negative = |r: &mut Reader<F>, name: &str| {
+            let key = format!("{prefix}{name}");
+            let value: Option<f64> = r.optional(&key);
+            if value.is_some_and(|value| !value.is_finite() || value < 0.0) {
+                r.invalid(&key, "must be a number >= 0");
+            }
+            value
+        };
+        region.max_pickup_km = non_negative(r, "MAX_PICKUP_KM");
+        region.stacking_max_detour_km = non_negative(r, "STACKING_MAX_DETOUR_KM");
+        let weights = [
+            "DISTANCE",
+            "LOAD",
+            "RATING",
+            "PRIORITY",
+            "ZONE",
+            "RELIABILITY",
+            "IDLE",
+            "EARNINGS",
+        ]
+        .map(|factor| non_negative(r, &format!("SCORING_{factor}_WEIGHT")));
+        if weights.iter().any(Option::is_some) {
+            let [distance, load, rating, priority, zone, reliability, idle, earnings] = weights;
+            let global = defaults.scoring;
+            let scoring = ScoringWeights {
+                distance: distance.unwrap_or(global.distance),
+                load: load.unwrap_or(global.load),
+                rating: rating.unwrap_or(global.rating),
+                priority: priority.unwrap_or(global.priority),
+                zone: zone.unwrap_or(global.zone),
+                reliability: reliability.unwrap_or(global.reliability),
+                idle: idle.unwrap_or(global.idle),
+                earnings: earnings.unwrap_or(global.earnings),
+            };
+            let total = scoring.distance
+                + scoring.load
+                + scoring.rating
+                + scoring.priority
+                + scoring.zone
+                + scoring.reliability
+                + scoring.idle
+                + scoring.earnings;
+            if total == 0.0 {
+                r.invalid(
+                    &format!("{prefix}SCORING_DISTANCE_WEIGHT"),
+                    "at least one weight must be > 0",
+                );
+            }
+            region.scoring = Some(scoring);
+        }
+    }
+    regions
 }
 
 impl Config {
+    /// Loads `CONFIG_FILE` (or `.env`) into the environment, then reads the
+    /// configuration from it.
     pub fn from_env() -> Result<Self, AppError> {
-        let _ = dotenvy::dotenv();
-
-        Ok(Self {
-            http_port: parse_or_default("HTTP_PORT", 3000)?,
-            grpc_port: parse_or_default("GRPC_PORT", 50051)?,
-            log_level: env::var("LOG_LEVEL").unwrap_or_else(|_| "info".to_string()),
-            order_queue_size: parse_or_default("ORDER_QUEUE_SIZE", 1024)?,
-            event_buffer_size: parse_or_default("EVENT_BUFFER_SIZE", 1024)?,
+        let config_file = match Reader::new(|key| env::var(key).ok()).non_empty("CONFIG_FILE") {
+            Some(path) => {
+                dotenvy::from_path(&path).map_err(|err| {
+                    AppError::Internal(format!("failed to load CONFIG_FILE {path}: {err}"))
+                })?;
+                Some(PathBuf::from(path))
+            }
+            None => dotenvy::dotenv().ok(),
+        };
+
+        let mut config = Self::from_lookup(|key| env::var(key).ok())?;
+        config.config_file = config_file;
+        Ok(config)
+    }
+
+    /// Reads every setting through `lookup` and reports all invalid ones at
+    /// once, so a deployment can be fixed in one go.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Self, AppError> {
+        let mut r = Reader::new(lookup);
+
+        let ws_ping_interval_secs = r.nonzero("WS_PING_INTERVAL_SECS", 30);
+        let webhook_max_attempts = r.nonzero("WEBHOOK_MAX_ATTEMPTS", 5);
+        let webhook_dead_letter_limit = r.nonzero("WEBHOOK_DEAD_LETTER_LIMIT", 1000);
+        let jwt_jwks_url = r.string("JWT_JWKS_URL", "");
+        let jwt_jwks_refresh_secs = r.nonzero("JWT_JWKS_REFRESH_SECS", 300);
+
+        let courier_token_secret = r.non_empty("COURIER_TOKEN_SECRET");
+        if let Some(secret) = &courier_token_secret {
+            if jwt_jwks_url.is_empty() {
+                r.invalid("COURIER_TOKEN_SECRET", "requires JWT_JWKS_URL");
+            }
+            if secret.len() < 32 {
+                r.invalid("COURIER_TOKEN_SECRET", "must be at least 32 bytes");
+            }
+        }
+        let courier_token_ttl_secs = r.nonzero("COURIER_TOKEN_TTL_SECS", 2_592_000);
+        let max_request_body_bytes = r.nonzero("MAX_REQUEST_BODY_BYTES", 2 * 1024 * 1024);
+
+        let redis_url = r.string("REDIS_URL", "");
+        let leader_election_key = r.non_empty("LEADER_ELECTION_KEY");
+        if leader_election_key.is_some() && redis_url.is_empty() {
+            r.invalid("LEADER_ELECTION_KEY", "requires REDIS_URL");
+        }
+        let leader_lease_secs = r.nonzero("LEADER_LEASE_SECS", 15);
+
+        let metrics_basic_auth = r.non_empty("METRICS_BASIC_AUTH");
+        if metrics_basic_auth
+            .as_ref()
+            .is_some_and(|credentials| !credentials.contains(':'))
+        {
+            r.invalid("METRICS_BASIC_AUTH", "expected user:password");
+        }
+
+        let tls_cert_path = r.string("TLS_CERT_PATH", "");
+        let tls_key_path = r.string("TLS_KEY_PATH", "");
+        if tls_cert_path.is_empty() != tls_key_path.is_empty() {
+            r.invalid(
+                "TLS_CERT_PATH",
+                "TLS_CERT_PATH and TLS_KEY_PATH must be set together",
+            );
+        }
+        let tls_reload_interval_secs = r.nonzero("TLS_RELOAD_INTERVAL_SECS", 60);
+
+        let mut rate = |key: &str| {
+            let rate: f64 = r.parse(key, 0.0);
+            if !rate.is_finite() || rate < 0.0 {
+                r.invalid(key, "must be a number >= 0");
+            }
+            rate
+        };
+        let payouts = PayoutRates {
+            per_delivery: rate("PAYOUT_PER_DELIVERY"),
+            per_km: rate("PAYOUT_PER_KM"),
+        };
+
+        let tenants = r.list("TENANTS");
+        for (index, tenant) in tenants.iter().enumerate() {
+            if !is_tenant_id(tenant) {
+                r.invalid(
+                    "TENANTS",
+                    format!("{tenant:?} is not 1 to 64 lowercase letters, digits, '-' or '_'"),
+                );
+            } else if tenant == DEFAULT_TENANT {
+                r.invalid("TENANTS", format!("{DEFAULT_TENANT:?} is always served"));
+            } else if tenants[..index].contains(tenant) {
+                r.invalid("TENANTS", format!("{tenant:?} is listed twice"));
+            }
+        }
+
+        let tunables = Tunables::read(&mut r);
+        let regions = read_regions(&mut r, &tunables);
+
+        let surge_defaults = SurgeSettings::default();
+        let surge = SurgeSettings {
+            threshold: r.parse("SURGE_THRESHOLD", surge_defaults.threshold),
+            priority_boost: r.parse("SURGE_PRIORITY_BOOST", surge_defaults.priority_boost),
+        };
+        if !surge.threshold.is_finite() || surge.threshold < 0.0 {
+            r.invalid("SURGE_THRESHOLD", "must be a number >= 0");
+        }
+        if !(0.0..=1.0).contains(&surge.priority_boost) {
+            r.invalid("SURGE_PRIORITY_BOOST", "must be a number from 0 to 1");
+        }
+
+        let chaos = ChaosSettings {
+            latency_rate: r.parse("CHAOS_LATENCY_RATE", 0.0),
+            latency: Duration::from_millis(r.parse("CHAOS_LATENCY_MS", 500)),
+            drop_event_rate: r.parse("CHAOS_DROP_EVENT_RATE", 0.0),
+            engine_panic_rate: r.parse("CHAOS_ENGINE_PANIC_RATE", 0.0),
+        };
+        for (key, rate) in [
+            ("CHAOS_LATENCY_RATE", chaos.latency_rate),
+            ("CHAOS_DROP_EVENT_RATE", chaos.drop_event_rate),
+            ("CHAOS_ENGINE_PANIC_RATE", chaos.engine_panic_rate),
+        ] {
+            if !(0.0..=1.0).contains(&rate) {
+                r.invalid(key, "must be a number from 0 to 1");
+            }
+        }
+
+        let http_port = r.parse("HTTP_PORT", 3000);
+        let http_listen = parse_listen_addrs(&mut r, "HTTP_LISTEN", http_port);
+        let grpc_port = r.parse("GRPC_PORT", 50051);
+        let grpc_listen = parse_listen_addrs(&mut r, "GRPC_LISTEN", grpc_port);
+
+        let config = Self {
+            http_port,
+            http_listen,
+            tls_cert_path,
+            tls_key_path,
+            tls_reload_interval_secs,
+            grpc_port,
+            grpc_listen,
+            metrics_port: r.parse("METRICS_PORT", 0),
+            metrics_bearer_token: r.non_empty("METRICS_BEARER_TOKEN"),
+            metrics_basic_auth,
+            log_json: r.parse("LOG_JSON", false),
+            config_file: None,
+            config_reload_interval_secs: r.parse("CONFIG_RELOAD_INTERVAL_SECS", 10),
+            tunables,
+            order_queue_size: r.nonzero("ORDER_QUEUE_SIZE", 1024),
+            event_buffer_size: r.nonzero("EVENT_BUFFER_SIZE", 1024),
+            event_replay_size: r.parse("EVENT_REPLAY_SIZE", 256),
+            audit_log_size: r.parse("AUDIT_LOG_SIZE", 10_000),
+            distance_cache_size: r.parse("DISTANCE_CACHE_SIZE", 4096),
+            parallel_scoring_threshold: r.parse("PARALLEL_SCORING_THRESHOLD", 10_000),
+            ws_ping_interval_secs,
+            ws_idle_timeout_secs: r.parse("WS_IDLE_TIMEOUT_SECS", 90),
+            ws_max_connections: r.parse("WS_MAX_CONNECTIONS", 10_000),
+            ws_max_connections_per_ip: r.parse("WS_MAX_CONNECTIONS_PER_IP", 20),
+            grpc_api_keys: r.list("GRPC_API_KEYS"),
+            tenants,
+            regions,
+            engine_shards: r.nonzero("ENGINE_SHARDS", 1),
+            stats_window_secs: r.nonzero("STATS_WINDOW_SECS", 900),
+            stats_timeseries_minutes: r.nonzero("STATS_TIMESERIES_MINUTES", 60),
+            reports_dir: r.non_empty("REPORTS_DIR").map(PathBuf::from),
+            surge,
+            surge_check_interval_secs: r.nonzero("SURGE_CHECK_INTERVAL_SECS", 10),
+            chaos,
+            jwt_jwks_url,
+            jwt_issuer: r.non_empty("JWT_ISSUER"),
+            jwt_audience: r.non_empty("JWT_AUDIENCE"),
+            jwt_jwks_refresh_secs,
+            courier_token_secret,
+            courier_token_ttl_secs,
+            cors_allowed_origins: r.list("CORS_ALLOWED_ORIGINS"),
+            cors_allowed_methods: match r.list("CORS_ALLOWED_METHODS") {
+                methods if methods.is_empty() => ["GET", "POST", "PATCH", "DELETE"]
+                    .map(str::to_string)
+                    .to_vec(),
+                methods => methods,
+            },
+            cors_max_age_secs: r.parse("CORS_MAX_AGE_SECS", 600),
+            rate_limit_order_create_per_min: r.parse("RATE_LIMIT_ORDER_CREATE_PER_MIN", 0),
+            rate_limit_write_per_min: r.parse("RATE_LIMIT_WRITE_PER_MIN", 0),
+            rate_limit_read_per_min: r.parse("RATE_LIMIT_READ_PER_MIN", 0),
+            max_request_body_bytes,
+            request_timeout_secs: r.parse("REQUEST_TIMEOUT_SECS", 30),
+            max_concurrent_requests: r.parse("MAX_CONCURRENT_REQUESTS", 1024),
+            shutdown_drain_secs: r.parse("SHUTDOWN_DRAIN_SECS", 30),
+            courier_heartbeat_timeout_secs: r.parse("COURIER_HEARTBEAT_TIMEOUT_SECS", 0),
+            capacity_unit: r.parse("CAPACITY_UNIT", CapacityUnit::Slots),
+            zone_mode: r.parse("ZONE_MODE", ZoneMode::Prefer),
+            payouts,
+            webhook_max_attempts,
+            webhook_retry_base_ms: r.parse("WEBHOOK_RETRY_BASE_MS", 500),
+            webhook_timeout_secs: r.parse("WEBHOOK_TIMEOUT_SECS", 10),
+            webhook_dead_letter_limit,
+            kafka_brokers: r.string("KAFKA_BROKERS", ""),
+            kafka_assignments_topic: r.string("KAFKA_ASSIGNMENTS_TOPIC", "dispatch.assignments"),
+            kafka_orders_topic: r.string("KAFKA_ORDERS_TOPIC", "dispatch.orders"),
+            nats_url: r.string("NATS_URL", ""),
+            nats_subject_prefix: r.string("NATS_SUBJECT_PREFIX", "dispatch"),
+            nats_jetstream: r.parse("NATS_JETSTREAM", false),
+            nats_orders_subject: r.string("NATS_ORDERS_SUBJECT", ""),
+            mqtt_host: r.string("MQTT_HOST", ""),
+            mqtt_port: r.parse("MQTT_PORT", 1883),
+            mqtt_client_id: r.string("MQTT_CLIENT_ID", "dispatch-router"),
+            mqtt_username: r.non_empty("MQTT_USERNAME"),
+            mqtt_password: r.non_empty("MQTT_PASSWORD"),
+            mqtt_topic: r.string("MQTT_TOPIC", "couriers/+/location"),
+            mqtt_device_map: parse_device_map(&mut r, "MQTT_DEVICE_MAP"),
+            redis_url,
+            redis_channel: r.string("REDIS_CHANNEL", "dispatch-router:events"),
+            leader_election_key,
+            leader_lease_secs,
+            publish_changes: r.parse("PUBLISH_CHANGES", false),
+            standby_token: r.non_empty("STANDBY_TOKEN"),
+            push_gateway_url: r.string("PUSH_GATEWAY_URL", ""),
+            push_gateway_token: r.non_empty("PUSH_GATEWAY_TOKEN"),
+            customer_notify_statuses: parse_order_statuses(&mut r, "CUSTOMER_NOTIFY_STATUSES"),
+            customer_callback_secret: r.non_empty("CUSTOMER_CALLBACK_SECRET"),
+            customer_gateway_url: r.string("CUSTOMER_GATEWAY_URL", ""),
+            customer_template_assigned: r.var("CUSTOMER_TEMPLATE_ASSIGNED"),
+            customer_template_in_transit: r.var("CUSTOMER_TEMPLATE_IN_TRANSIT"),
+            customer_template_delivered: r.var("CUSTOMER_TEMPLATE_DELIVERED"),
+            otel_exporter_endpoint: r.string("OTEL_EXPORTER_OTLP_ENDPOINT", ""),
+            otel_service_name: r.string("OTEL_SERVICE_NAME", "dispatch-router"),
+            console_bind: r.parse("CONSOLE_BIND", SocketAddr::from(([127, 0, 0, 1], 6669))),
+            legacy_env_vars: Vec::new(),
+        };
+
+        let legacy_env_vars = r.legacy.clone();
+        r.finish(Self {
+            legacy_env_vars,
+            ..config
         })
     }
 }
 
-fn parse_or_default<T>(key: &str, default: T) -> Result<T, AppError>
-where
-    T: std::str::FromStr,
-    T::Err: std::fmt::Display,
-{
-    match env::var(key) {
-        Ok(raw) => raw
-            .parse::<T>()
-            .map_err(|err| AppError::Internal(format!("invalid {key}: {err}"))),
-        Err(_) => Ok(default),
+/// Prefix every setting is read under, e.g. `DISPATCH_HTTP_PORT`.
+pub const ENV_PREFIX: &str = "DISPATCH_";
+
+/// Reads settings by their unprefixed key, e.g. `HTTP_PORT`, and keeps going
+/// past invalid values so they can all be reported together.
+struct Reader<F> {
+    lookup: F,
+    /// The variable each key was read from, for error messages.
+    sources: HashMap<String, String>,
+    legacy: Vec<String>,
+    errors: Vec<String>,
+}
+
+impl<F: Fn(&str) -> Option<String>> Reader<F> {
+    fn new(lookup: F) -> Self {
+        Self {
+            lookup,
+            sources: HashMap::new(),
+            legacy: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    /// `DISPATCH_<key>`, or `<key>` as deployments from before the prefix
+    /// set it.
+    fn var(&mut self, key: &str) -> Option<String> {
+        let prefixed = format!("{ENV_PREFIX}{key}");
+        if let Some(value) = (self.lookup)(&prefixed) {
+            self.sources.insert(key.to_string(), prefixed);
+            return Some(value);
+        }
+
+        let value = (self.lookup)(key)?;
+        self.sources.insert(key.to_string(), key.to_string());
+        if !self.legacy.iter().any(|legacy| legacy == key) {
+            self.legacy.push(key.to_string());
+        }
+        Some(value)
+    }
+
+    fn string(&mut self, key: &str, default: &str) -> String {
+        self.var(key).unwrap_or_else(|| default.to_string())
+    }
+
+    /// Unset and empty are the same: off.
+    fn non_empty(&mut self, key: &str) -> Option<String> {
+        self.var(key).filter(|value| !value.is_empty())
+    }
+
+    fn parse<T>(&mut self, key: &str, default: T) -> T
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        match self.var(key).map(|raw| raw.parse::<T>()) {
+            Some(Ok(value)) => value,
+            Some(Err(err)) => {
+                self.invalid(key, err);
+                default
+            }
+            None => default,
+        }
+    }
+
+    /// `None` when unset, so the caller can fall back to another setting.
+    fn optional<T>(&mut self, key: &str) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        match self.var(key)?.parse::<T>() {
+            Ok(value) => Some(value),
+            Err(err) => {
+                self.invalid(key, err);
+                None
+            }
+        }
+    }
+
+    /// Intervals and limits where zero would spin or reject everything.
+    fn nonzero<T>(&mut self, key: &str, default: T) -> T
+    where
+        T: FromStr + PartialEq + From<u8>,
+        T::Err: Display,
+    {
+        let value = self.parse(key, default);
+        if value == T::from(0) {
+            self.invalid(key, "must be > 0");
+        }
+        value
+    }
+
+    /// Comma-separated, with blanks dropped.
+    fn list(&mut self, key: &str) -> Vec<String> {
+        self.var(key)
+            .map(|raw| {
+                raw.split(',')
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn invalid(&mut self, key: &str, reason: impl Display) {
+        let name = self
+            .sources
+            .get(key)
+            .cloned()
+            .unwrap_or_else(|| format!("{ENV_PREFIX}{key}"));
+        self.errors.push(format!("{name}: {reason}"));
+    }
+
+    fn finish<T>(self, value: T) -> Result<T, AppError> {
+        if self.errors.is_empty() {
+            return Ok(value);
+        }
+        Err(AppError::Internal(format!(
+            "invalid configuration:\n  {}",
+            self.errors.join("\n  ")
+        )))
+    }
+}
+
+/// `device=courier-uuid` pairs, comma-separated.
+fn parse_device_map<F: Fn(&str) -> Option<String>>(
+    r: &mut Reader<F>,
+    key: &str,
+) -> HashMap<String, Uuid> {
+    let mut map = HashMap::new();
+    for entry in r.list(key) {
+        let Some((device, courier)) = entry.split_once('=') else {
+            r.invalid(key, format!("{entry} is not device=courier_id"));
+            continue;
+        };
+        match Uuid::parse_str(courier.trim()) {
+            Ok(courier) => {
+                map.insert(device.trim().to_string(), courier);
+            }
+            Err(err) => r.invalid(key, format!("{entry}: {err}")),
+        }
+    }
+    map
+}
+
+/// Comma-separated `host:port` and `unix:/path` addresses; unset means all
+/// interfaces on `port`.
+fn parse_listen_addrs<F: Fn(&str) -> Option<String>>(
+    r: &mut Reader<F>,
+    key: &str,
+    port: u16,
+) -> Vec<ListenAddr> {
+    let mut addrs = Vec::new();
+    for addr in r.list(key) {
+        match addr.parse() {
+            Ok(addr) => addrs.push(addr),
+            Err(err) => r.invalid(key, err),
+        }
+    }
+    if addrs.is_empty() {
+        addrs.push(ListenAddr::Tcp(([0, 0, 0, 0], port).into()));
+    }
+    addrs
+}
+
+/// Comma-separated order statuses (`Assigned,InTransit,Delivered`); unset
+/// means all three.
+fn parse_order_statuses<F: Fn(&str) -> Option<String>>(
+    r: &mut Reader<F>,
+    key: &str,
+) -> Vec<OrderStatus> {
+    if r.var(key).is_none() {
+        return vec![
+            OrderStatus::Assigned,
+            OrderStatus::InTransit,
+            OrderStatus::Delivered,
+        ];
+    }
+
+    let mut statuses = Vec::new();
+    for status in r.list(key) {
+        match serde_json::from_value(serde_json::Value::String(status.clone())) {
+            Ok(parsed) => statuses.push(parsed),
+            Err(_) => r.invalid(key, format!("unknown status {status}")),
+        }
+    }
+    statuses
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::Config;
+    use crate::listen::ListenAddr;
+
+    fn config(vars: &[(&str, &str)]) -> Result<Config, String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        Config::from_lookup(|key| vars.get(key).cloned()).map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn prefixed_names_win_and_bare_names_are_reported_as_legacy() {
+        let config = config(&[
+            ("DISPATCH_HTTP_PORT", "8080"),
+            ("HTTP_PORT", "9090"),
+            ("GRPC_PORT", "6000"),
+        ])
+        .unwrap();
+
+        assert_eq!(config.http_port, 8080);
+        assert_eq!(config.grpc_port, 6000);
+        assert_eq!(config.legacy_env_vars, vec!["GRPC_PORT".to_string()]);
+    }
+
+    #[test]
+    fn reports_every_invalid_setting_at_once() {
+        let err = config(&[
+            ("DISPATCH_HTTP_PORT", "http"),
+            ("WS_PING_INTERVAL_SECS", "0"),
+            ("DISPATCH_TLS_CERT_PATH", "/etc/tls/cert.pem"),
+            ("DISPATCH_CUSTOMER_NOTIFY_STATUSES", "Assigned,Lost"),
+            ("DISPATCH_TENANTS", "acme,Globex,acme"),
+            ("DISPATCH_LEADER_ELECTION_KEY", "dispatch-router:leader"),
+            ("DISPATCH_CHAOS_DROP_EVENT_RATE", "1.5"),
+            ("DISPATCH_WEBHOOK_DEAD_LETTER_LIMIT", "0"),
+            ("DISPATCH_ORDER_QUEUE_SIZE", "0"),
+            ("DISPATCH_EVENT_BUFFER_SIZE", "0"),
+            ("DISPATCH_STANDBY_PRIMARY_URL", "primary:50051"),
+        ])
+        .unwrap_err();
+
+        assert!(err.contains("DISPATCH_HTTP_PORT: invalid digit"), "{err}");
+        assert!(err.contains("WS_PING_INTERVAL_SECS: must be > 0"), "{err}");
+        assert!(err.contains("DISPATCH_TLS_CERT_PATH: "), "{err}");
+        assert!(err.contains("unknown status Lost"), "{err}");
+        assert!(err.contains("\"Globex\" is not 1 to 64"), "{err}");
+        assert!(err.contains("\"acme\" is listed twice"), "{err}");
+        assert!(
+            err.contains("DISPATCH_LEADER_ELECTION_KEY: requires REDIS_URL"),
+            "{err}"
+        );
+        assert!(
+            err.contains("DISPATCH_CHAOS_DROP_EVENT_RATE: must be a number from 0 to 1"),
+            "{err}"
+        );
+        assert!(
+            err.contains("DISPATCH_WEBHOOK_DEAD_LETTER_LIMIT: must be > 0"),
+            "{err}"
+        );
+        assert!(
+            err.contains("DISPATCH_ORDER_QUEUE_SIZE: must be > 0"),
+            "{err}"
+        );
+        assert!(
+            err.contains("DISPATCH_EVENT_BUFFER_SIZE: must be > 0"),
+            "{err}"
+        );
+        assert!(
+            err.contains("DISPATCH_STANDBY_PRIMARY_URL: expected an http or https URL"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn regions_override_only_what_they_set() {
+        let loaded = config(&[
+            ("DISPATCH_REGIONS", "berlin=u33,berlin=u32,paris=U09"),
+            ("DISPATCH_SCORING_LOAD_WEIGHT", "0.5"),
+            ("DISPATCH_REGION_BERLIN_SCORING_DISTANCE_WEIGHT", "0.9"),
+            ("DISPATCH_REGION_PARIS_MAX_PICKUP_KM", "3"),
+        ])
+        .unwrap();
+
+        let [berlin, paris] = &loaded.regions[..] else {
+            panic!("{:?}", loaded.regions);
+        };
+        assert_eq!(berlin.cells, vec!["u33", "u32"]);
+        let scoring = berlin.scoring.unwrap();
+        assert_eq!((scoring.distance, scoring.load), (0.9, 0.5));
+        assert_eq!(berlin.max_pickup_km, None);
+        assert_eq!(paris.cells, vec!["u09"]);
+        assert_eq!(paris.scoring, None);
+        assert_eq!(paris.max_pickup_km, Some(3.0));
+
+        let err = config(&[("DISPATCH_REGIONS", "berlin,Paris=u09,rome=zz!")]).unwrap_err();
+        assert!(err.contains("\"berlin\" is not name=geohash"), "{err}");
+        assert!(err.contains("\"Paris\" is not lowercase"), "{err}");
+        assert!(err.contains("\"zz!\" is not a geohash"), "{err}");
+    }
+
+    #[test]
+    fn listen_addresses_default_to_the_port_on_all_interfaces() {
+        let loaded = config(&[
+            ("DISPATCH_HTTP_PORT", "8080"),
+            (
+                "DISPATCH_GRPC_LISTEN",
+                "127.0.0.1:6000, unix:/run/dispatch/grpc.sock",
+            ),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            loaded.http_listen,
+            vec!["0.0.0.0:8080".parse::<ListenAddr>().unwrap()]
+        );
+        assert_eq!(
+            loaded.grpc_listen,
+            vec![
+                "127.0.0.1:6000".parse::<ListenAddr>().unwrap(),
+                "unix:/run/dispatch/grpc.sock".parse().unwrap(),
+            ]
+        );
+
+        let err = config(&[("DISPATCH_HTTP_LISTEN", "localhost:80")]).unwrap_err();
+        assert!(err.contains("DISPATCH_HTTP_LISTEN: localhost:80"), "{err}");
     }
 }
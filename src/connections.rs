@@ -0,0 +1,166 @@
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use dashmap::DashMap;
+use prometheus::IntGauge;
+
+use crate::error::AppError;
+
+/// Caps on concurrent stream connections; zero means unlimited.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConnectionLimits {
+    pub max_total: usize,
+    pub max_per_ip: usize,
+}
+
+/// Counts open stream connections, globally and per client IP, and refuses
+/// new ones past the configured limits. Each accepted connection holds a
+/// `ConnectionPermit` that gives its slot back on drop.
+#[derive(Clone)]
+pub struct ConnectionTracker {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    limits: ConnectionLimits,
+    total: AtomicUsize,
+    per_ip: DashMap<IpAddr, usize>,
+    gauge: IntGauge,
+}
+
+/// Why a connection was refused; used as the metric label.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejection {
+    GlobalLimit,
+    IpLimit,
+}
+
+impl Rejection {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Rejection::GlobalLimit => "global_limit",
+            Rejection::IpLimit => "ip_limit",
+        }
+    }
+}
+
+impl From<Rejection> for AppError {
+    fn from(rejection: Rejection) -> Self {
+        match rejection {
+            Rejection::GlobalLimit => {
+                AppError::TooManyRequests("too many open connections".to_string())
+            }
+            Rejection::IpLimit => {
+                AppError::TooManyRequests("too many open connections from this address".to_string())
+            }
+        }
+    }
+}
+
+impl ConnectionTracker {
+    pub fn new(limits: ConnectionLimits, gauge: IntGauge) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                limits,
+                total: AtomicUsize::new(0),
+                per_ip: DashMap::new(),
+                gauge,
+            }),
+        }
+    }
+
+    pub fn try_acquire(&self, ip: Option<IpAddr>) -> Result<ConnectionPermit, Rejection> {
+        let limits = self.inner.limits;
+
+        let total = self.inner.total.fetch_add(1, Ordering::SeqCst) + 1;
+        if limits.max_total > 0 && total > limits.max_total {
+            self.inner.total.fetch_sub(1, Ordering::SeqCst);
+            return Err(Rejection::GlobalLimit);
+        }
+
+        if let Some(ip) = ip {
+            let mut count = self.inner.per_ip.entry(ip).or_insert(0);
+            if limits.max_per_ip > 0 && *count >= limits.max_per_ip {
+                drop(count);
+                self.inner.total.fetch_sub(1, Ordering::SeqCst);
+                return Err(Rejection::IpLimit);
+            }
+            *count += 1;
+        }
+
+        self.inner.gauge.inc();
+        Ok(ConnectionPermit {
+            inner: self.inner.clone(),
+            ip,
+        })
+    }
+
+    pub fn active(&self) -> usize {
+        self.inner.total.load(Ordering::SeqCst)
+    }
+}
+
+pub struct ConnectionPermit {
+    inner: Arc<Inner>,
+    ip: Option<IpAddr>,
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        self.inner.total.fetch_sub(1, Ordering::SeqCst);
+        self.inner.gauge.dec();
+
+        if let Some(ip) = self.ip {
+            self.inner.per_ip.remove_if_mut(&ip, |_, count| {
+                *count -= 1;
+                *count == 0
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use prometheus::IntGauge;
+
+    use super::{ConnectionLimits, ConnectionTracker, Rejection};
+
+    fn tracker(max_total: usize, max_per_ip: usize) -> ConnectionTracker {
+        ConnectionTracker::new(
+            ConnectionLimits {
+                max_total,
+                max_per_ip,
+            },
+            IntGauge::new("test_connections", "test").unwrap(),
+        )
+    }
+
+    #[test]
+    fn enforces_per_ip_limit_and_releases_on_drop() {
+        let tracker = tracker(0, 1);
+        let a = Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)));
+        let b = Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)));
+
+        let permit = tracker.try_acquire(a).unwrap();
+        assert_eq!(tracker.try_acquire(a).err(), Some(Rejection::IpLimit));
+        let _other = tracker.try_acquire(b).unwrap();
+        assert_eq!(tracker.active(), 2);
+
+        drop(permit);
+        assert!(tracker.try_acquire(a).is_ok());
+    }
+
+    #[test]
+    fn enforces_global_limit() {
+        let tracker = tracker(1, 0);
+        let _permit = tracker.try_acquire(None).unwrap();
+        assert_eq!(
+            tracker.try_acquire(None).err(),
+            Some(Rejection::GlobalLimit)
+        );
+        assert_eq!(tracker.active(), 1);
+    }
+}
@@ -1,28 +1,95 @@
+use std::collections::BTreeMap;
+use std::num::NonZeroUsize;
+use std::panic::resume_unwind;
 use std::sync::Arc;
+use std::thread;
 use std::time::Instant;
 
-use chrono::Utc;
 use tokio::sync::mpsc;
-use tokio::time::{sleep, Duration};
-use tracing::{error, info, warn};
+use tokio::time::sleep;
+use tracing::{error, info, instrument, warn};
 use uuid::Uuid;
 
-use crate::engine::queue::enqueue_order;
-use crate::engine::scoring::compute_score;
+use crate::engine::constraints::{can_take, is_qualified, ConstraintContext};
+use crate::engine::queue::{requeue_order, QueuedOrder};
+use crate::engine::regions::DispatchSettings;
+use crate::engine::route::{active_orders, km_until_dropoff, plan_route, stacking_detour_km};
+use crate::engine::scoring::{score_against, ScoringTarget, ScoringWeights};
+use crate::engine::tracking::travel_seconds;
 use crate::error::AppError;
-use crate::models::assignment::Assignment;
-use crate::models::courier::{Courier, CourierStatus};
-use crate::models::order::{DeliveryOrder, OrderStatus};
+use crate::geo::haversine_km;
+use crate::models::assignment::{Assignment, AssignmentPreview, PreviewCandidate, ScoreBreakdown};
+use crate::models::audit::AuditEntity;
+use crate::models::courier::{Courier, CourierStatus, GeoPoint};
+use crate::models::history::OrderHistoryEvent;
+use crate::models::order::{DeliveryOrder, Handling, OrderDeadLetter, OrderStatus, WaitingReason};
 use crate::state::AppState;
 
-pub async fn run_assignment_engine(state: Arc<AppState>, mut order_rx: mpsc::Receiver<DeliveryOrder>) {
-    info!("assignment engine started");
+pub async fn run_assignment_engine(
+    state: Arc<AppState>,
+    mut order_rx: mpsc::Receiver<QueuedOrder>,
+) {
+    assign_queued_orders(state, &mut order_rx).await;
+}
+
+/// The engine loop. It borrows the queue so that, should it panic, the
+/// supervisor can hand the same queue to its replacement.
+pub async fn assign_queued_orders(
+    state: Arc<AppState>,
+    order_rx: &mut mpsc::Receiver<QueuedOrder>,
+) {
+    assign_region_orders(state, None, order_rx).await;
+}
 
-    while let Some(order) = order_rx.recv().await {
-        state.metrics.orders_in_queue.dec();
+/// The engine loop of `region` (an index into `AppState::regions`), or of
+/// orders outside every region for `None`. It takes orders only while this
+/// instance leads, waiting on standby otherwise.
+pub async fn assign_region_orders(
+    state: Arc<AppState>,
+    region: Option<usize>,
+    order_rx: &mut mpsc::Receiver<QueuedOrder>,
+) {
+    let engine = state.engine_of(region);
+    let _running = engine.start();
+    let region_name = region.map(|index| state.regions[index].settings.name.as_str());
+    info!(region = region_name, "assignment engine started");
+
+    loop {
+        if !state.leadership.is_leader() {
+            engine.set_standby(true);
+            info!(
+                region = region_name,
+                "assignment engine on standby until this instance leads"
+            );
+            state.leadership.acquired().await;
+            engine.set_standby(false);
+            info!(region = region_name, "assignment engine resumed as leader");
+        }
+        let queued = tokio::select! {
+            biased;
+            () = state.leadership.lost() => continue,
+            queued = order_rx.recv() => match queued {
+                Some(queued) => queued,
+                None => break,
+            },
+        };
+        engine.record_dequeue(state.clock.now());
+        state.stats.record_attempt(state.clock.now());
+        state
+            .metrics
+            .orders_in_queue
+            .with_label_values(&[queued.order.priority.as_str()])
+            .dec();
+        if state.chaos.engine_panic() {
+            // Back in the queue first, so the restarted engine takes it up.
+            if let Err(err) = requeue_order(&state, queued).await {
+                error!(error = %err, "failed to requeue order before injected panic");
+            }
+            panic!("chaos: injected engine panic");
+        }
 
         let start = Instant::now();
-        match process_order(state.clone(), order).await {
+        match process_order(state.clone(), region, queued).await {
             Ok(()) => {
                 let elapsed = start.elapsed().as_secs_f64();
                 state
@@ -48,85 +115,596 @@ pub async fn run_assignment_engine(state: Arc<AppState>, mut order_rx: mpsc::Rec
                     .assignments_total
                     .with_label_values(&["error"])
                     .inc();
+                engine.record_failure();
                 error!(error = %err, "failed to process order");
             }
         }
+        engine.record_processed();
     }
 
-    warn!("assignment engine stopped: queue channel closed");
+    warn!(
+        region = region_name,
+        "assignment engine stopped: queue channel closed"
+    );
 }
 
-async fn process_order(state: Arc<AppState>, order: DeliveryOrder) -> Result<(), AppError> {
-    let candidates: Vec<Courier> = state
-        .couriers
-        .iter()
-        .filter_map(|entry| {
-            let courier = entry.value();
-            let can_take_order = courier.status == CourierStatus::Available
-                && courier.current_load < courier.capacity;
-
-            if can_take_order {
-                Some(courier.clone())
-            } else {
-                None
-            }
-        })
-        .collect();
+#[instrument(
+    parent = &queued.span,
+    skip_all,
+    fields(order_id = %queued.order.id, request_id = queued.order.request_id.as_deref())
+)]
+async fn process_order(
+    state: Arc<AppState>,
+    region: Option<usize>,
+    queued: QueuedOrder,
+) -> Result<(), AppError> {
+    let order = &queued.order;
+    let engine = state.engine_of(region);
+    let region = region.map(|index| &state.regions[index].settings);
+    let ctx = ConstraintContext {
+        state: &state,
+        now: state.clock.now(),
+        region,
+    };
+    let settings = DispatchSettings::resolve(&state.tunables(), region);
+    let pick = pick_courier(
+        &ctx,
+        order,
+        &settings.scoring,
+        settings.stacking_max_detour_km,
+    );
 
-    if candidates.is_empty() {
-        warn!(order_id = %order.id, "no eligible couriers; re-queueing order");
-        sleep(Duration::from_millis(250)).await;
-        enqueue_order(&state, order).await?;
+    let Some(pick) = pick else {
+        if let Some(reason) = unmet_handling(&state, order) {
+            reject_order(&state, order, reason);
+            return Ok(());
+        }
+        let reason = if state
+            .couriers
+            .iter()
+            .any(|entry| is_qualified(&ctx, entry.value(), order))
+        {
+            WaitingReason::NoCourierAvailable
+        } else {
+            WaitingReason::NoQualifiedCourier
+        };
+        let newly_flagged = record_waiting_reason(&state, order, reason);
+        match reason {
+            WaitingReason::NoCourierAvailable => {
+                warn!(order_id = %order.id, "no eligible couriers; re-queueing order");
+            }
+            WaitingReason::NoQualifiedCourier => {
+                if newly_flagged {
+                    state.metrics.orders_without_qualified_courier_total.inc();
+                }
+                warn!(
+                    order_id = %order.id,
+                    required_skills = ?order.required_skills,
+                    required_vehicle = ?order.required_vehicle,
+                    size = order.size,
+                    "no qualified courier for order; re-queueing"
+                );
+            }
+        }
+        sleep(state.tunables().requeue_delay).await;
+        requeue_order(&state, queued).await?;
+        engine.record_requeue();
         return Ok(());
-    }
+    };
 
-    let (winning_courier, best_score, best_breakdown) = candidates
-        .iter()
-        .map(|courier| {
-            let (score, breakdown) = compute_score(courier, &order);
-            (courier, score, breakdown)
-        })
-        .max_by(|a, b| a.1.total_cmp(&b.1))
-        .ok_or_else(|| AppError::Internal("failed to score couriers".to_string()))?;
+    // Measured once per order: earlier attempts found no courier and
+    // assigned nothing.
+    let waited = (state.clock.now() - order.created_at)
+        .to_std()
+        .unwrap_or_default();
+    state
+        .metrics
+        .order_queue_wait_seconds
+        .with_label_values(&[order.priority.as_str()])
+        .observe(waited.as_secs_f64());
+
+    let Pick {
+        courier_id,
+        location,
+        score: best_score,
+        breakdown: best_breakdown,
+        stacked_onto: stacking,
+    } = pick;
 
     let mut updated_order = order.clone();
     updated_order.status = OrderStatus::Assigned;
-    updated_order.assigned_courier = Some(winning_courier.id);
-    state.orders.insert(updated_order.id, updated_order.clone());
+    updated_order.assigned_courier = Some(courier_id);
+    updated_order.waiting_reason = None;
 
-    if let Some(mut courier) = state.couriers.get_mut(&winning_courier.id) {
-        courier.current_load = courier.current_load.saturating_add(1);
-        if courier.current_load >= courier.capacity {
-            courier.status = CourierStatus::Busy;
+    // Same route the tracking ETA assumes: courier to the first pickup, then
+    // through the others to the dropoff. A stacked order waits its turn on
+    // the combined route.
+    let pickup_km = haversine_km(&location, &order.pickup);
+    let route_km = match &stacking {
+        Some(active) => {
+            let mut orders = active.clone();
+            orders.push(order.clone());
+            let stops = plan_route(&location, &orders);
+            km_until_dropoff(&location, &stops, order.id)
         }
-        courier.updated_at = Utc::now();
-
-        let utilization = courier.current_load as f64 / courier.capacity as f64;
-        state
-            .metrics
-            .courier_utilization
-            .with_label_values(&[&winning_courier.id.to_string()])
-            .set(utilization);
-    }
-
+        None => pickup_km + order.route_km(),
+    };
+    let eta_seconds = travel_seconds(route_km);
+    let assigned_at = state.clock.now();
     let assignment = Assignment {
-        id: Uuid::new_v4(),
+        id: Uuid::now_v7(),
         order_id: updated_order.id,
-        courier_id: winning_courier.id,
+        courier_id,
         score: best_score,
         score_breakdown: best_breakdown,
-        assigned_at: Utc::now(),
+        assigned_at,
+        due_at: assigned_at + chrono::Duration::milliseconds((eta_seconds * 1000.0) as i64),
+        delivered_at: None,
+        stacked_with: stacking
+            .as_ref()
+            .map(|active| active.iter().map(|order| order.id).collect())
+            .unwrap_or_default(),
+        cash_on_delivery: order.cash_on_delivery,
+    };
+
+    let committed = {
+        let _change = state.snapshots.change();
+        // Another region's engine may have filled the courier up since it
+        // was scored here; both commit under the guard, so this holds.
+        let courier = state
+            .couriers
+            .get(&courier_id)
+            .map(|entry| Arc::clone(&entry));
+        if !courier.is_some_and(|courier| can_take(&ctx, &courier, order)) {
+            None
+        } else {
+            state.orders.insert(updated_order.id, updated_order.clone());
+            if let Some(assigned) = state.orders.get(&updated_order.id) {
+                state.publish_order_change(&assigned);
+            }
+            let courier_change = state.couriers.get_mut(&courier_id).map(|mut entry| {
+                let old = Arc::clone(&entry);
+                let courier = Arc::make_mut(&mut entry);
+                courier.current_load += order.size;
+                if courier.is_full() {
+                    courier.status = CourierStatus::Busy;
+                }
+                courier.last_assigned_at = Some(assigned_at);
+                courier.updated_at = state.clock.now();
+
+                state
+                    .metrics
+                    .record_courier_load(courier.current_load, courier.capacity);
+                state.stats.record_courier_load(
+                    courier.current_load,
+                    courier.capacity,
+                    state.clock.now(),
+                );
+                state.publish_courier_change(&entry);
+                (old, Arc::clone(&entry))
+            });
+            state.assignments.insert(assignment.id, assignment.clone());
+            state
+                .assignment_timeline
+                .insert(assignment.id, assignment.assigned_at);
+            Some(courier_change)
+        }
     };
+    let Some(courier_change) = committed else {
+        warn!(order_id = %order.id, %courier_id, "courier taken meanwhile; re-queueing order");
+        requeue_order(&state, queued).await?;
+        engine.record_requeue();
+        return Ok(());
+    };
+
+    state.audit.record(
+        AuditEntity::Order,
+        updated_order.id,
+        "assigned",
+        "engine",
+        Some(order),
+        Some(&updated_order),
+    );
+    state.record_order_history(
+        updated_order.id,
+        OrderHistoryEvent::Assigned,
+        "engine",
+        Some(courier_id),
+        stacking
+            .as_ref()
+            .map(|active| format!("stacked onto {} other orders", active.len())),
+    );
+    state.publish_order_event(Some(&order.status), &updated_order);
+    if let Some((old, new)) = courier_change {
+        state.audit.record(
+            AuditEntity::Courier,
+            courier_id,
+            "assigned",
+            "engine",
+            Some(&old),
+            Some(&new),
+        );
+    }
+    if stacking.is_some() {
+        state.metrics.orders_stacked_total.inc();
+    }
 
-    state.assignments.insert(assignment.id, assignment.clone());
-    let _ = state.assignment_events_tx.send(assignment.clone());
+    state.publish_assignment(&assignment);
+    engine.record_assignment(state.clock.now());
+
+    state
+        .metrics
+        .assignment_pickup_distance_km
+        .observe(pickup_km);
+    state.metrics.assignment_eta_seconds.observe(eta_seconds);
+    state.stats.record_assignment(
+        (assigned_at - order.created_at)
+            .to_std()
+            .unwrap_or_default(),
+        pickup_km,
+        assignment.score,
+        state.clock.now(),
+    );
 
     info!(
         order_id = %updated_order.id,
-        courier_id = %winning_courier.id,
+        courier_id = %courier_id,
         score = best_score,
+        stacked_with = assignment.stacked_with.len(),
         "order assigned"
     );
 
     Ok(())
 }
+
+/// The courier an order goes to.
+struct Pick {
+    courier_id: Uuid,
+    location: GeoPoint,
+    score: f64,
+    breakdown: ScoreBreakdown,
+    /// The courier's active orders when the order is stacked onto them.
+    stacked_onto: Option<Vec<DeliveryOrder>>,
+}
+
+impl Pick {
+    fn new(courier: &Courier, score: f64, breakdown: ScoreBreakdown) -> Self {
+        Self {
+            courier_id: courier.id,
+            location: courier.location.clone(),
+            score,
+            breakdown,
+            stacked_onto: None,
+        }
+    }
+}
+
+/// Picks the courier for `order` in one pass over the fleet, scoring each
+/// candidate where it is stored rather than copying the fleet out first.
+/// The courier already on its way with other orders that can add `order`
+/// for the shortest detour within `max_detour_km` wins over scoring; a
+/// budget of 0 turns stacking off. `None` if no courier can take the order.
+///
+/// Fleets of at least `parallel_scoring_threshold` couriers are scored in
+/// slices on their own threads instead; see [`pick_courier_parallel`].
+fn pick_courier(
+    ctx: &ConstraintContext,
+    order: &DeliveryOrder,
+    weights: &ScoringWeights,
+    max_detour_km: f64,
+) -> Option<Pick> {
+    let mut target = ScoringTarget::new(order, ctx.now);
+    target.boost_priority(ctx.state.surge.priority_boost(&order.pickup));
+    target.set_earnings(ctx.state.earnings.today(ctx.now));
+    let scorer = Scorer {
+        ctx,
+        order,
+        target,
+        weights,
+        max_detour_km,
+    };
+    let threshold = ctx.state.parallel_scoring_threshold;
+    if threshold > 0 && ctx.state.couriers.len() >= threshold {
+        return pick_courier_parallel(&scorer);
+    }
+
+    let mut candidates = Candidates::default();
+    for entry in ctx.state.couriers.iter() {
+        let courier = entry.value();
+        if !can_take(ctx, courier, order) {
+            continue;
+        }
+        let (distance_km, hit) = scorer
+            .target
+            .cached_pickup_km(courier, &ctx.state.distances);
+        ctx.state
+            .metrics
+            .distance_cache_lookups_total
+            .with_label_values(&[if hit { "hit" } else { "miss" }])
+            .inc();
+        scorer.consider(&mut candidates, courier, distance_km);
+    }
+    candidates.into_pick()
+}
+
+/// `pick_courier` over a snapshot of the fleet cut into one slice per
+/// available core. Each thread works out its distances directly, as taking
+/// turns on the shared distance cache would undo the split, and the slices'
+/// picks are merged in fleet order so ties go the same way as on one thread.
+fn pick_courier_parallel(scorer: &Scorer) -> Option<Pick> {
+    let couriers: Vec<Arc<Courier>> = scorer
+        .ctx
+        .state
+        .couriers
+        .iter()
+        .map(|entry| Arc::clone(entry.value()))
+        .collect();
+    let threads = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    let slice_len = couriers.len().div_ceil(threads).max(1);
+
+    thread::scope(|scope| {
+        let slices: Vec<_> = couriers
+            .chunks(slice_len)
+            .map(|slice| {
+                scope.spawn(move || {
+                    let mut candidates = Candidates::default();
+                    for courier in slice {
+                        if can_take(scorer.ctx, courier, scorer.order) {
+                            let distance_km = scorer.target.pickup_km(courier);
+                            scorer.consider(&mut candidates, courier, distance_km);
+                        }
+                    }
+                    candidates
+                })
+            })
+            .collect();
+        slices
+            .into_iter()
+            .map(|slice| slice.join().unwrap_or_else(|panic| resume_unwind(panic)))
+            .reduce(Candidates::merge)
+            .and_then(Candidates::into_pick)
+    })
+}
+
+/// What every courier is scored against for one order.
+struct Scorer<'a> {
+    ctx: &'a ConstraintContext<'a>,
+    order: &'a DeliveryOrder,
+    target: ScoringTarget,
+    weights: &'a ScoringWeights,
+    max_detour_km: f64,
+}
+
+impl Scorer<'_> {
+    /// Scores `courier`, `distance_km` from the pickup, and keeps it in
+    /// `candidates` if it beats those there.
+    fn consider(&self, candidates: &mut Candidates, courier: &Courier, distance_km: f64) {
+        let (score, breakdown) = score_against(courier, &self.target, distance_km, self.weights);
+
+        if self.max_detour_km > 0.0 {
+            let active = active_orders(self.ctx.state, courier.id);
+            if !active.is_empty() {
+                let detour_km = stacking_detour_km(courier, &active, self.order);
+                if detour_km <= self.max_detour_km
+                    && candidates
+                        .stacked
+                        .as_ref()
+                        .is_none_or(|(_, shortest)| detour_km < *shortest)
+                {
+                    let mut pick = Pick::new(courier, score, breakdown);
+                    pick.stacked_onto = Some(active);
+                    candidates.stacked = Some((pick, detour_km));
+                }
+            }
+        }
+        if candidates
+            .best
+            .as_ref()
+            .is_none_or(|best| score.total_cmp(&best.score).is_gt())
+        {
+            candidates.best = Some(Pick::new(courier, score, breakdown));
+        }
+    }
+}
+
+/// The best-scoring courier and the one with the shortest stacking detour
+/// among those considered so far.
+#[derive(Default)]
+struct Candidates {
+    best: Option<Pick>,
+    stacked: Option<(Pick, f64)>,
+}
+
+impl Candidates {
+    /// Folds in the candidates of couriers that come after these, keeping
+    /// the earlier of two equal picks as `Scorer::consider` does.
+    fn merge(mut self, later: Self) -> Self {
+        if let Some(best) = later.best
+            && self
+                .best
+                .as_ref()
+                .is_none_or(|current| best.score.total_cmp(&current.score).is_gt())
+        {
+            self.best = Some(best);
+        }
+        if let Some((pick, detour_km)) = later.stacked
+            && self
+                .stacked
+                .as_ref()
+                .is_none_or(|(_, shortest)| detour_km < *shortest)
+        {
+            self.stacked = Some((pick, detour_km));
+        }
+        self
+    }
+
+    fn into_pick(self) -> Option<Pick> {
+        self.stacked.map(|(pick, _)| pick).or(self.best)
+    }
+}
+
+/// Scores every courier that could take `order` now, as the engine would,
+/// and names the one it would pick; nothing is assigned. Unlike the engine
+/// it leaves the distance cache and `candidates_rejected_total` alone, so
+/// previews don't skew them. Returns at most `limit` candidates.
+pub fn preview_assignment(
+    state: &AppState,
+    order: &DeliveryOrder,
+    limit: usize,
+) -> AssignmentPreview {
+    let region = state
+        .region_of(&order.pickup)
+        .map(|index| &state.regions[index].settings);
+    let ctx = ConstraintContext {
+        state,
+        now: state.clock.now(),
+        region,
+    };
+    let settings = DispatchSettings::resolve(&state.tunables(), region);
+    let mut target = ScoringTarget::new(order, ctx.now);
+    target.boost_priority(state.surge.priority_boost(&order.pickup));
+    target.set_earnings(state.earnings.today(ctx.now));
+
+    let mut rejected: BTreeMap<String, u64> = BTreeMap::new();
+    let mut candidates = Vec::new();
+    for entry in state.couriers.iter() {
+        let courier = entry.value();
+        if let Some(failed) = state
+            .constraints
+            .iter()
+            .find(|constraint| !constraint.allows(&ctx, courier, order))
+        {
+            *rejected.entry(failed.name().to_string()).or_default() += 1;
+            continue;
+        }
+        let pickup_km = target.pickup_km(courier);
+        let (score, breakdown) = score_against(courier, &target, pickup_km, &settings.scoring);
+        let stacking_detour_km = if settings.stacking_max_detour_km > 0.0 {
+            let active = active_orders(state, courier.id);
+            (!active.is_empty())
+                .then(|| stacking_detour_km(courier, &active, order))
+                .filter(|&detour_km| detour_km <= settings.stacking_max_detour_km)
+        } else {
+            None
+        };
+        candidates.push(PreviewCandidate {
+            courier_id: courier.id,
+            name: courier.name.clone(),
+            score,
+            score_breakdown: breakdown,
+            pickup_km,
+            stacking_detour_km,
+        });
+    }
+
+    // The engine's pick: the shortest detour, else the best score, the
+    // earlier courier winning ties either way.
+    let selected = candidates
+        .iter()
+        .filter_map(|candidate| candidate.stacking_detour_km.map(|km| (candidate, km)))
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(candidate, _)| candidate)
+        .or_else(|| candidates.iter().min_by(|a, b| b.score.total_cmp(&a.score)))
+        .map(|candidate| candidate.courier_id);
+    candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+    let eligible = candidates.len();
+    candidates.truncate(limit);
+
+    AssignmentPreview {
+        selected_courier_id: selected,
+        candidates,
+        eligible,
+        rejected,
+    }
+}
+
+/// Why no courier in the fleet is equipped for the order's handling, or
+/// `None` if one is. Unlike a missing skill this is not waited out: the
+/// order is rejected.
+fn unmet_handling(state: &AppState, order: &DeliveryOrder) -> Option<String> {
+    if order.handling.is_empty()
+        || state
+            .couriers
+            .iter()
+            .any(|entry| entry.missing_handling(&order.handling).is_empty())
+    {
+        return None;
+    }
+    let unmet: Vec<&str> = order
+        .handling
+        .iter()
+        .filter(|handling| {
+            !state
+                .couriers
+                .iter()
+                .any(|entry| handling.is_met_by(&entry.equipment))
+        })
+        .map(Handling::as_str)
+        .collect();
+    Some(if unmet.is_empty() {
+        let required: Vec<&str> = order.handling.iter().map(Handling::as_str).collect();
+        format!(
+            "no single courier is equipped for {} handling together",
+            required.join(", ")
+        )
+    } else {
+        format!("no courier is equipped for {} handling", unmet.join(", "))
+    })
+}
+
+/// Takes the order out of dispatch for good and files it in the order dead
+/// letters with `reason`.
+fn reject_order(state: &AppState, order: &DeliveryOrder, reason: String) {
+    let Some((old, updated)) = state.orders.get_mut(&order.id).and_then(|mut current| {
+        if current.status != OrderStatus::Pending {
+            return None;
+        }
+        let old = current.clone();
+        current.status = OrderStatus::Rejected;
+        current.waiting_reason = None;
+        state.publish_order_change(&current);
+        Some((old, current.clone()))
+    }) else {
+        return;
+    };
+
+    warn!(order_id = %order.id, reason, "order rejected, moved to dead letters");
+    state.audit.record(
+        AuditEntity::Order,
+        order.id,
+        "rejected",
+        "engine",
+        Some(&old),
+        Some(&updated),
+    );
+    state.record_order_history(
+        order.id,
+        OrderHistoryEvent::Rejected,
+        "engine",
+        None,
+        Some(reason.clone()),
+    );
+    state.order_dead_letters.insert(
+        order.id,
+        OrderDeadLetter {
+            order_id: order.id,
+            reason,
+            rejected_at: state.clock.now(),
+        },
+    );
+    state.metrics.orders_rejected_total.inc();
+    state.publish_order_event(Some(&old.status), &updated);
+}
+
+/// Notes on the stored order why it is still waiting. Returns whether the
+/// reason changed, so each order is counted once.
+fn record_waiting_reason(state: &AppState, order: &DeliveryOrder, reason: WaitingReason) -> bool {
+    state.orders.get_mut(&order.id).is_some_and(|mut current| {
+        let changed = current.status == OrderStatus::Pending
+            && current.waiting_reason.replace(reason) != Some(reason);
+        if changed {
+            state.publish_order_change(&current);
+        }
+        changed
+    })
+}
@@ -0,0 +1,433 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+
+use crate::engine::regions::RegionSettings;
+use crate::engine::route::active_orders;
+use crate::geo::haversine_km;
+use crate::models::courier::{Courier, CourierStatus, ZoneMode};
+use crate::models::order::DeliveryOrder;
+use crate::state::AppState;
+
+/// How lasting a constraint's verdict is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConstraintKind {
+    /// Says whether the courier could ever take the order. An order every
+    /// courier fails one of these for waits with `NoQualifiedCourier`.
+    Qualification,
+    /// Says whether the courier can take the order right now; it may pass
+    /// later without anything about the courier being changed.
+    Availability,
+}
+
+/// What constraints can look at beyond the courier and the order.
+pub struct ConstraintContext<'a> {
+    pub state: &'a AppState,
+    pub now: DateTime<Utc>,
+    /// The region the order is picked up in, if any.
+    pub region: Option<&'a RegionSettings>,
+}
+
+/// A rule a courier must pass to be offered an order. The engine checks
+/// every courier against each of `AppState::constraints` in turn and counts
+/// the first one it fails in `candidates_rejected_total{constraint}`.
+pub trait Constraint: Send + Sync {
+    /// The `constraint` label on `candidates_rejected_total`.
+    fn name(&self) -> &'static str;
+
+    fn kind(&self) -> ConstraintKind;
+
+    fn allows(&self, ctx: &ConstraintContext, courier: &Courier, order: &DeliveryOrder) -> bool;
+}
+
+/// The rules every deployment dispatches by.
+pub fn default_constraints() -> Vec<Arc<dyn Constraint>> {
+    vec![
+        Arc::new(VehicleConstraint),
+        Arc::new(SkillsConstraint),
+        Arc::new(HandlingConstraint),
+        Arc::new(CapacityConstraint),
+        Arc::new(CashConstraint),
+        Arc::new(ZoneConstraint),
+        Arc::new(StatusConstraint),
+        Arc::new(RoomConstraint),
+        Arc::new(ShiftConstraint),
+        Arc::new(DeclinedConstraint),
+        Arc::new(CashFloatConstraint),
+        Arc::new(RadiusConstraint),
+    ]
+}
+
+/// Whether the courier passes every constraint and can take the order now.
+/// Counts the constraint it fails, if any.
+pub fn can_take(ctx: &ConstraintContext, courier: &Courier, order: &DeliveryOrder) -> bool {
+    match ctx
+        .state
+        .constraints
+        .iter()
+        .find(|constraint| !constraint.allows(ctx, courier, order))
+    {
+        Some(failed) => {
+            ctx.state
+                .metrics
+                .candidates_rejected_total
+                .with_label_values(&[failed.name()])
+                .inc();
+            false
+        }
+        None => true,
+    }
+}
+
+/// Whether the courier could ever take the order, whether or not it is free
+/// now.
+pub fn is_qualified(ctx: &ConstraintContext, courier: &Courier, order: &DeliveryOrder) -> bool {
+    ctx.state
+        .constraints
+        .iter()
+        .filter(|constraint| constraint.kind() == ConstraintKind::Qualification)
+        .all(|constraint| constraint.allows(ctx, courier, order))
+}
+
+/// Couriers on anything smaller than the order's `required_vehicle`.
+pub struct VehicleConstraint;
+
+impl Constraint for VehicleConstraint {
+    fn name(&self) -> &'static str {
+        "vehicle"
+    }
+
+    fn kind(&self) -> ConstraintKind {
+        ConstraintKind::Qualification
+    }
+
+    fn allows(&self, _ctx: &ConstraintContext, courier: &Courier, order: &DeliveryOrder) -> bool {
+        courier.vehicle_type.can_carry(order.required_vehicle)
+    }
+}
+
+/// Couriers missing any of the order's `required_skills`.
+pub struct SkillsConstraint;
+
+impl Constraint for SkillsConstraint {
+    fn name(&self) -> &'static str {
+        "skills"
+    }
+
+    fn kind(&self) -> ConstraintKind {
+        ConstraintKind::Qualification
+    }
+
+    fn allows(&self, _ctx: &ConstraintContext, courier: &Courier, order: &DeliveryOrder) -> bool {
+        courier.missing_skills(&order.required_skills).is_empty()
+    }
+}
+
+/// Couriers without the equipment for the order's `handling`.
+pub struct HandlingConstraint;
+
+impl Constraint for HandlingConstraint {
+    fn name(&self) -> &'static str {
+        "handling"
+    }
+
+    fn kind(&self) -> ConstraintKind {
+        ConstraintKind::Qualification
+    }
+
+    fn allows(&self, _ctx: &ConstraintContext, courier: &Courier, order: &DeliveryOrder) -> bool {
+        courier.missing_handling(&order.handling).is_empty()
+    }
+}
+
+/// Couriers too small for the order even when empty.
+pub struct CapacityConstraint;
+
+impl Constraint for CapacityConstraint {
+    fn name(&self) -> &'static str {
+        "capacity"
+    }
+
+    fn kind(&self) -> ConstraintKind {
+        ConstraintKind::Qualification
+    }
+
+    fn allows(&self, _ctx: &ConstraintContext, courier: &Courier, order: &DeliveryOrder) -> bool {
+        order.size <= courier.capacity
+    }
+}
+
+/// For cash on delivery, couriers who take no cash or whose float is below
+/// the amount.
+pub struct CashConstraint;
+
+impl Constraint for CashConstraint {
+    fn name(&self) -> &'static str {
+        "cod"
+    }
+
+    fn kind(&self) -> ConstraintKind {
+        ConstraintKind::Qualification
+    }
+
+    fn allows(&self, _ctx: &ConstraintContext, courier: &Courier, order: &DeliveryOrder) -> bool {
+        order
+            .cash_on_delivery
+            .is_none_or(|amount| courier.can_collect(amount))
+    }
+}
+
+/// In strict zone mode, couriers with preferred zones that leave out the
+/// pickup's.
+pub struct ZoneConstraint;
+
+impl Constraint for ZoneConstraint {
+    fn name(&self) -> &'static str {
+        "zone"
+    }
+
+    fn kind(&self) -> ConstraintKind {
+        ConstraintKind::Qualification
+    }
+
+    fn allows(&self, ctx: &ConstraintContext, courier: &Courier, order: &DeliveryOrder) -> bool {
+        ctx.state.zone_mode == ZoneMode::Prefer
+            || courier.preferred_zones.is_empty()
+            || courier.prefers_zone_of(&order.pickup)
+    }
+}
+
+/// Couriers that are not `Available`.
+pub struct StatusConstraint;
+
+impl Constraint for StatusConstraint {
+    fn name(&self) -> &'static str {
+        "status"
+    }
+
+    fn kind(&self) -> ConstraintKind {
+        ConstraintKind::Availability
+    }
+
+    fn allows(&self, _ctx: &ConstraintContext, courier: &Courier, _order: &DeliveryOrder) -> bool {
+        courier.status == CourierStatus::Available
+    }
+}
+
+/// Couriers without room for the order next to what they carry.
+pub struct RoomConstraint;
+
+impl Constraint for RoomConstraint {
+    fn name(&self) -> &'static str {
+        "room"
+    }
+
+    fn kind(&self) -> ConstraintKind {
+        ConstraintKind::Availability
+    }
+
+    fn allows(&self, _ctx: &ConstraintContext, courier: &Courier, order: &DeliveryOrder) -> bool {
+        courier.has_room_for(order.size)
+    }
+}
+
+/// Couriers off shift.
+pub struct ShiftConstraint;
+
+impl Constraint for ShiftConstraint {
+    fn name(&self) -> &'static str {
+        "shift"
+    }
+
+    fn kind(&self) -> ConstraintKind {
+        ConstraintKind::Availability
+    }
+
+    fn allows(&self, ctx: &ConstraintContext, courier: &Courier, _order: &DeliveryOrder) -> bool {
+        courier.is_on_shift(ctx.now)
+    }
+}
+
+/// Couriers who handed the order back before.
+pub struct DeclinedConstraint;
+
+impl Constraint for DeclinedConstraint {
+    fn name(&self) -> &'static str {
+        "declined"
+    }
+
+    fn kind(&self) -> ConstraintKind {
+        ConstraintKind::Availability
+    }
+
+    fn allows(&self, _ctx: &ConstraintContext, courier: &Courier, order: &DeliveryOrder) -> bool {
+        !order.declined_by.contains(&courier.id)
+    }
+}
+
+/// Couriers whose `cod_limit` would not cover the order's cash on top of
+/// what their undelivered orders are due to collect.
+pub struct CashFloatConstraint;
+
+impl Constraint for CashFloatConstraint {
+    fn name(&self) -> &'static str {
+        "cod_float"
+    }
+
+    fn kind(&self) -> ConstraintKind {
+        ConstraintKind::Availability
+    }
+
+    fn allows(&self, ctx: &ConstraintContext, courier: &Courier, order: &DeliveryOrder) -> bool {
+        let (Some(amount), Some(limit)) = (order.cash_on_delivery, courier.cod_limit) else {
+            return true;
+        };
+        let due: f64 = active_orders(ctx.state, courier.id)
+            .iter()
+            .filter_map(|order| order.cash_on_delivery)
+            .sum();
+        due + amount <= limit
+    }
+}
+
+/// With `MAX_PICKUP_KM` (or the order's region's) set, couriers farther
+/// than that from the pickup.
+pub struct RadiusConstraint;
+
+impl Constraint for RadiusConstraint {
+    fn name(&self) -> &'static str {
+        "radius"
+    }
+
+    fn kind(&self) -> ConstraintKind {
+        ConstraintKind::Availability
+    }
+
+    fn allows(&self, ctx: &ConstraintContext, courier: &Courier, order: &DeliveryOrder) -> bool {
+        let max_km = ctx
+            .region
+            .and_then(|region| region.max_pickup_km)
+            .unwrap_or_else(|| ctx.state.tunables().max_pickup_km);
+        max_km <= 0.0 || haversine_km(&courier.location, &order.pickup) <= max_km
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    use super::{can_take, is_qualified, ConstraintContext};
+    use crate::config::Tunables;
+    use crate::models::courier::{Courier, CourierStatus, GeoPoint, OfferHistory, VehicleType};
+    use crate::models::order::{DeliveryOrder, OrderStatus, Priority};
+    use crate::state::AppState;
+
+    fn courier(lng: f64) -> Courier {
+        Courier {
+            id: Uuid::new_v4(),
+            name: "Max".to_string(),
+            location: GeoPoint { lat: 52.52, lng },
+            capacity: 3.0,
+            current_load: 0.0,
+            status: CourierStatus::Available,
+            break_until: None,
+            rating: 4.5,
+            offers: OfferHistory::default(),
+            last_assigned_at: None,
+            vehicle_type: VehicleType::Bike,
+            updated_at: Utc::now(),
+            last_seen_at: Utc::now(),
+            shifts: Vec::new(),
+            skills: Vec::new(),
+            preferred_zones: Vec::new(),
+            accepts_cod: false,
+            cod_limit: None,
+            equipment: Vec::new(),
+            location_cache: Default::default(),
+        }
+    }
+
+    fn order() -> DeliveryOrder {
+        DeliveryOrder {
+            id: Uuid::new_v4(),
+            pickup: GeoPoint {
+                lat: 52.52,
+                lng: 13.40,
+            },
+            dropoff: GeoPoint {
+                lat: 52.53,
+                lng: 13.41,
+            },
+            extra_pickups: Vec::new(),
+            priority: Priority::Normal,
+            status: OrderStatus::Pending,
+            assigned_courier: None,
+            created_at: Utc::now(),
+            size: 1.0,
+            items: Vec::new(),
+            cash_on_delivery: None,
+            required_vehicle: None,
+            required_skills: Vec::new(),
+            handling: Vec::new(),
+            waiting_reason: None,
+            declined_by: Vec::new(),
+            callback_url: None,
+            customer_contact: None,
+            request_id: None,
+        }
+    }
+
+    #[test]
+    fn counts_the_first_constraint_a_courier_fails() {
+        let (state, _rx) = AppState::new(16, 16);
+        let ctx = ConstraintContext {
+            state: &state,
+            now: Utc::now(),
+            region: None,
+        };
+        let mut order = order();
+        order.required_vehicle = Some(VehicleType::Van);
+
+        let mut busy_bike = courier(13.40);
+        busy_bike.status = CourierStatus::Busy;
+        assert!(!can_take(&ctx, &busy_bike, &order));
+        assert!(!is_qualified(&ctx, &busy_bike, &order));
+
+        let mut busy_van = courier(13.40);
+        busy_van.vehicle_type = VehicleType::Van;
+        busy_van.status = CourierStatus::Busy;
+        assert!(!can_take(&ctx, &busy_van, &order));
+        assert!(is_qualified(&ctx, &busy_van, &order));
+
+        let rejected = |name: &str| {
+            state
+                .metrics
+                .candidates_rejected_total
+                .with_label_values(&[name])
+                .get()
+        };
+        assert_eq!(rejected("vehicle"), 1);
+        assert_eq!(rejected("status"), 1);
+    }
+
+    #[test]
+    fn radius_only_applies_once_set() {
+        let (state, _rx) = AppState::new(16, 16);
+        let far = courier(13.50);
+        let order = order();
+        let ctx = ConstraintContext {
+            state: &state,
+            now: Utc::now(),
+            region: None,
+        };
+        assert!(can_take(&ctx, &far, &order));
+
+        state.set_tunables(Tunables {
+            max_pickup_km: 5.0,
+            ..Tunables::default()
+        });
+        assert!(!can_take(&ctx, &far, &order));
+        assert!(can_take(&ctx, &courier(13.42), &order));
+    }
+}
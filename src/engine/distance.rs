@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Marks the end of the recency list.
+const NIL: usize = usize::MAX;
+
+/// Courier-to-pickup distances by `(courier cell, pickup cell)`, both
+/// `cell_id`s at `DISTANCE_CELL_PRECISION`, keeping the most recently used
+/// `capacity` of them. Couriers scored against clustered pickups hit the
+/// same pairs order after order. A hit is the distance between the first
+/// two points seen in those cells, so it is off by at most a cell or so.
+pub struct DistanceCache {
+    capacity: usize,
+    lru: Mutex<Lru>,
+}
+
+impl DistanceCache {
+    /// A capacity of 0 turns caching off.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            lru: Mutex::new(Lru::default()),
+        }
+    }
+
+    /// The cached distance between the two cells, or `compute`'s, which is
+    /// then cached. The flag says whether it was a hit.
+    pub fn km(&self, from: u64, to: u64, compute: impl FnOnce() -> f64) -> (f64, bool) {
+        if self.capacity == 0 {
+            return (compute(), false);
+        }
+        let mut lru = self.lru.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(km) = lru.get((from, to)) {
+            return (km, true);
+        }
+        let km = compute();
+        lru.insert((from, to), km, self.capacity);
+        (km, false)
+    }
+
+    pub fn len(&self) -> usize {
+        self.lru.lock().unwrap_or_else(|e| e.into_inner()).map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+struct Node {
+    key: (u64, u64),
+    km: f64,
+    prev: usize,
+    next: usize,
+}
+
+/// A doubly linked recency list threaded through `nodes`, most recent at
+/// `head`, with `map` pointing into it.
+struct Lru {
+    map: HashMap<(u64, u64), usize>,
+    nodes: Vec<Node>,
+    head: usize,
+    tail: usize,
+}
+
+impl Default for Lru {
+    fn default() -> Self {
+        Self {
+            map: HashMap::new(),
+            nodes: Vec::new(),
+            head: NIL,
+            tail: NIL,
+        }
+    }
+}
+
+impl Lru {
+    fn get(&mut self, key: (u64, u64)) -> Option<f64> {
+        let index = *self.map.get(&key)?;
+        self.unlink(index);
+        self.push_front(index);
+        Some(self.nodes[index].km)
+    }
+
+    fn insert(&mut self, key: (u64, u64), km: f64, capacity: usize) {
+        let index = if self.nodes.len() < capacity {
+            self.nodes.push(Node {
+                key,
+                km,
+                prev: NIL,
+                next: NIL,
+            });
+            self.nodes.len() - 1
+        } else {
+            // Full: the least recently used node makes room.
+            let index = self.tail;
+            self.unlink(index);
+            self.map.remove(&self.nodes[index].key);
+            self.nodes[index].key = key;
+            self.nodes[index].km = km;
+            index
+        };
+        self.map.insert(key, index);
+        self.push_front(index);
+    }
+
+    fn unlink(&mut self, index: usize) {
+        let (prev, next) = (self.nodes[index].prev, self.nodes[index].next);
+        match prev {
+            NIL => self.head = next,
+            prev => self.nodes[prev].next = next,
+        }
+        match next {
+            NIL => self.tail = prev,
+            next => self.nodes[next].prev = prev,
+        }
+    }
+
+    fn push_front(&mut self, index: usize) {
+        self.nodes[index].prev = NIL;
+        self.nodes[index].next = self.head;
+        match self.head {
+            NIL => self.tail = index,
+            head => self.nodes[head].prev = index,
+        }
+        self.head = index;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DistanceCache;
+
+    #[test]
+    fn evicts_the_least_recently_used_pair() {
+        let cache = DistanceCache::new(2);
+        assert_eq!(cache.km(1, 2, || 1.5), (1.5, false));
+        assert_eq!(cache.km(3, 4, || 2.5), (2.5, false));
+        // Touching (1, 2) leaves (3, 4) as the one to go.
+        assert_eq!(cache.km(1, 2, || unreachable!()), (1.5, true));
+        assert_eq!(cache.km(5, 6, || 3.5), (3.5, false));
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.km(1, 2, || unreachable!()), (1.5, true));
+        assert_eq!(cache.km(3, 4, || 9.0), (9.0, false));
+
+        let off = DistanceCache::new(0);
+        assert_eq!(off.km(1, 2, || 1.5), (1.5, false));
+        assert_eq!(off.km(1, 2, || 1.5), (1.5, false));
+        assert!(off.is_empty());
+    }
+}
@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use uuid::Uuid;
+
+/// What couriers have been paid over one UTC day, for balancing work
+/// towards those earning least.
+#[derive(Debug, Default, Clone)]
+pub struct DailyEarnings {
+    day: Option<NaiveDate>,
+    by_courier: HashMap<Uuid, f64>,
+    /// Median over the couriers paid anything that day.
+    median: Option<f64>,
+}
+
+impl DailyEarnings {
+    pub fn of(&self, courier_id: Uuid) -> f64 {
+        self.by_courier
+            .get(&courier_id)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn median(&self) -> Option<f64> {
+        self.median
+    }
+
+    /// 1 for a courier that has earned nothing yet, 0.5 at the fleet median
+    /// and falling towards 0 the further above it the courier is.
+    pub fn score(&self, courier_id: Uuid) -> f64 {
+        let earned = self.of(courier_id);
+        match self.median {
+            Some(median) if earned > 0.0 => median / (median + earned),
+            _ => 1.0,
+        }
+    }
+
+    fn update_median(&mut self) {
+        let mut paid: Vec<f64> = self
+            .by_courier
+            .values()
+            .copied()
+            .filter(|earned| *earned > 0.0)
+            .collect();
+        if paid.is_empty() {
+            self.median = None;
+            return;
+        }
+        paid.sort_by(f64::total_cmp);
+        let middle = paid.len() / 2;
+        self.median = Some(if paid.len().is_multiple_of(2) {
+            (paid[middle - 1] + paid[middle]) / 2.0
+        } else {
+            paid[middle]
+        });
+    }
+}
+
+/// Couriers' earnings for the current UTC day, started afresh at
+/// midnight. Unlike `AppState::courier_stats`, which keeps totals since
+/// startup, this only feeds the earnings score.
+#[derive(Debug, Default)]
+pub struct EarningsLedger {
+    today: RwLock<Arc<DailyEarnings>>,
+}
+
+impl EarningsLedger {
+    /// Adds a delivery's `payout` to the courier's earnings for the day of
+    /// `at`.
+    pub fn record(&self, courier_id: Uuid, payout: f64, at: DateTime<Utc>) {
+        let day = at.date_naive();
+        let mut today = self.today.write().unwrap_or_else(|e| e.into_inner());
+        let earnings = Arc::make_mut(&mut today);
+        if earnings.day != Some(day) {
+            *earnings = DailyEarnings {
+                day: Some(day),
+                ..DailyEarnings::default()
+            };
+        }
+        *earnings.by_courier.entry(courier_id).or_default() += payout;
+        earnings.update_median();
+    }
+
+    /// The earnings of the day of `now`; empty until the first delivery of
+    /// the day. Cheap to take once per order.
+    pub fn today(&self, now: DateTime<Utc>) -> Arc<DailyEarnings> {
+        let today = self.today.read().unwrap_or_else(|e| e.into_inner());
+        if today.day == Some(now.date_naive()) {
+            Arc::clone(&today)
+        } else {
+            Arc::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+    use uuid::Uuid;
+
+    use super::EarningsLedger;
+
+    #[test]
+    fn scores_couriers_against_the_median_of_the_day() {
+        let ledger = EarningsLedger::default();
+        let morning = Utc.with_ymd_and_hms(2026, 10, 17, 9, 0, 0).unwrap();
+        let (low, mid, high, idle) = (
+            Uuid::now_v7(),
+            Uuid::now_v7(),
+            Uuid::now_v7(),
+            Uuid::now_v7(),
+        );
+        ledger.record(low, 10.0, morning);
+        ledger.record(mid, 20.0, morning);
+        ledger.record(high, 25.0, morning);
+        ledger.record(high, 35.0, morning);
+
+        let today = ledger.today(morning + Duration::hours(3));
+        assert_eq!(today.median(), Some(20.0));
+        assert_eq!(today.of(high), 60.0);
+        assert_eq!(today.score(idle), 1.0);
+        assert!((today.score(low) - 2.0 / 3.0).abs() < 1e-9);
+        assert_eq!(today.score(mid), 0.5);
+        assert_eq!(today.score(high), 0.25);
+
+        let tomorrow = morning + Duration::days(1);
+        assert_eq!(ledger.today(tomorrow).median(), None);
+        ledger.record(low, 5.0, tomorrow);
+        let today = ledger.today(tomorrow);
+        assert_eq!(today.of(low), 5.0);
+        assert_eq!(today.of(high), 0.0);
+    }
+}
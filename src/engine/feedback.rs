@@ -0,0 +1,228 @@
+use std::cmp::Reverse;
+use std::sync::Arc;
+
+use dashmap::mapref::entry::Entry;
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::models::audit::AuditEntity;
+use crate::models::feedback::Feedback;
+use crate::models::order::OrderStatus;
+use crate::state::AppState;
+
+/// Longest comment kept with feedback, in characters.
+pub const MAX_COMMENT_CHARS: usize = 1000;
+
+/// Records the customer's feedback on a delivered order and folds the stars
+/// into the rating of the courier who delivered it, as a moving average
+/// weighted by `FEEDBACK_RATING_WEIGHT`. Each order takes feedback once.
+pub fn submit_feedback(
+    state: &AppState,
+    order_id: Uuid,
+    stars: u8,
+    comment: Option<String>,
+    actor: &str,
+) -> Result<Feedback, AppError> {
+    if !(1..=5).contains(&stars) {
+        return Err(AppError::BadRequest(
+            "stars must be between 1 and 5".to_string(),
+        ));
+    }
+    let comment = comment
+        .map(|comment| comment.trim().to_string())
+        .filter(|comment| !comment.is_empty());
+    if comment
+        .as_ref()
+        .is_some_and(|comment| comment.chars().count() > MAX_COMMENT_CHARS)
+    {
+        return Err(AppError::BadRequest(format!(
+            "comment must be at most {MAX_COMMENT_CHARS} characters"
+        )));
+    }
+
+    let (status, assigned_courier) = state
+        .orders
+        .get(&order_id)
+        .map(|order| (order.status.clone(), order.assigned_courier))
+        .ok_or_else(|| AppError::NotFound(format!("order {} not found", order_id)))?;
+    let (OrderStatus::Delivered, Some(courier_id)) = (&status, assigned_courier) else {
+        return Err(AppError::Conflict(format!(
+            "feedback can only be given on Delivered orders, not {:?}",
+            status
+        )));
+    };
+
+    let Entry::Vacant(slot) = state.feedback.entry(order_id) else {
+        return Err(AppError::Conflict(format!(
+            "order {} already has feedback",
+            order_id
+        )));
+    };
+    let weight = state.tunables().feedback_rating_weight;
+    let (old, new) = {
+        let mut entry = state
+            .couriers
+            .get_mut(&courier_id)
+            .ok_or_else(|| AppError::NotFound(format!("courier {} not found", courier_id)))?;
+        let old = Arc::clone(&entry);
+        let courier = Arc::make_mut(&mut entry);
+        courier.rating += weight * (f64::from(stars) - courier.rating);
+        courier.updated_at = state.clock.now();
+        state.publish_courier_change(&entry);
+        (old, Arc::clone(&entry))
+    };
+    let feedback = Feedback {
+        order_id,
+        courier_id,
+        stars,
+        comment,
+        rating_after: new.rating,
+        created_at: state.clock.now(),
+    };
+    slot.insert(feedback.clone());
+
+    state.audit.record(
+        AuditEntity::Courier,
+        courier_id,
+        "rated",
+        actor,
+        Some(&old),
+        Some(&new),
+    );
+    Ok(feedback)
+}
+
+/// Feedback on the orders a courier delivered, newest first.
+pub fn courier_feedback(state: &AppState, courier_id: Uuid) -> Result<Vec<Feedback>, AppError> {
+    if !state.couriers.contains_key(&courier_id) {
+        return Err(AppError::NotFound(format!(
+            "courier {} not found",
+            courier_id
+        )));
+    }
+    let mut feedback: Vec<Feedback> = state
+        .feedback
+        .iter()
+        .filter(|entry| entry.courier_id == courier_id)
+        .map(|entry| entry.value().clone())
+        .collect();
+    feedback.sort_by_key(|feedback| Reverse(feedback.created_at));
+    Ok(feedback)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    use super::{courier_feedback, submit_feedback};
+    use crate::error::AppError;
+    use crate::models::courier::{Courier, CourierStatus, GeoPoint, OfferHistory, VehicleType};
+    use crate::models::order::{DeliveryOrder, OrderStatus, Priority};
+    use crate::state::AppState;
+
+    fn courier() -> Courier {
+        Courier {
+            id: Uuid::new_v4(),
+            name: "Max".to_string(),
+            location: GeoPoint {
+                lat: 52.52,
+                lng: 13.40,
+            },
+            capacity: 3.0,
+            current_load: 0.0,
+            status: CourierStatus::Available,
+            break_until: None,
+            rating: 4.0,
+            offers: OfferHistory::default(),
+            last_assigned_at: None,
+            vehicle_type: VehicleType::Bike,
+            updated_at: Utc::now(),
+            last_seen_at: Utc::now(),
+            shifts: Vec::new(),
+            skills: Vec::new(),
+            preferred_zones: Vec::new(),
+            accepts_cod: false,
+            cod_limit: None,
+            equipment: Vec::new(),
+            location_cache: Default::default(),
+        }
+    }
+
+    fn order(courier_id: Uuid, status: OrderStatus) -> DeliveryOrder {
+        DeliveryOrder {
+            id: Uuid::new_v4(),
+            pickup: GeoPoint {
+                lat: 52.52,
+                lng: 13.40,
+            },
+            dropoff: GeoPoint {
+                lat: 52.53,
+                lng: 13.41,
+            },
+            extra_pickups: Vec::new(),
+            priority: Priority::Normal,
+            status,
+            assigned_courier: Some(courier_id),
+            created_at: Utc::now(),
+            size: 1.0,
+            items: Vec::new(),
+            cash_on_delivery: None,
+            required_vehicle: None,
+            required_skills: Vec::new(),
+            handling: Vec::new(),
+            waiting_reason: None,
+            declined_by: Vec::new(),
+            callback_url: None,
+            customer_contact: None,
+            request_id: None,
+        }
+    }
+
+    #[test]
+    fn moves_the_rating_towards_the_stars_once_per_order() {
+        let (state, _rx) = AppState::new(16, 16);
+        let courier = courier();
+        let delivered = order(courier.id, OrderStatus::Delivered);
+        let in_transit = order(courier.id, OrderStatus::InTransit);
+        state.couriers.insert(courier.id, Arc::new(courier.clone()));
+        for order in [&delivered, &in_transit] {
+            state.orders.insert(order.id, order.clone());
+        }
+
+        let feedback = submit_feedback(
+            &state,
+            delivered.id,
+            5,
+            Some("  Quick and friendly ".to_string()),
+            "test",
+        )
+        .unwrap();
+        // 4.0 + 0.1 * (5 - 4.0)
+        assert!((feedback.rating_after - 4.1).abs() < 1e-9);
+        assert_eq!(feedback.comment.as_deref(), Some("Quick and friendly"));
+        assert_eq!(
+            state.couriers.get(&courier.id).unwrap().rating,
+            feedback.rating_after
+        );
+
+        assert!(matches!(
+            submit_feedback(&state, delivered.id, 1, None, "test"),
+            Err(AppError::Conflict(_))
+        ));
+        assert!(matches!(
+            submit_feedback(&state, in_transit.id, 1, None, "test"),
+            Err(AppError::Conflict(_))
+        ));
+        assert!(matches!(
+            submit_feedback(&state, delivered.id, 0, None, "test"),
+            Err(AppError::BadRequest(_))
+        ));
+
+        let history = courier_feedback(&state, courier.id).unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].stars, 5);
+    }
+}
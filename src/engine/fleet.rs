@@ -0,0 +1,247 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::models::audit::AuditEntity;
+use crate::models::courier::{Courier, CourierStatus, OfferOutcome};
+use crate::models::shift::Shift;
+use crate::state::AppState;
+
+/// Adds a validated courier to the fleet, as last seen now.
+pub fn register_courier(state: &AppState, mut courier: Courier, actor: &str) -> Arc<Courier> {
+    courier.updated_at = state.clock.now();
+    courier.last_seen_at = courier.updated_at;
+    let courier = Arc::new(courier);
+    state.couriers.insert(courier.id, Arc::clone(&courier));
+    if let Some(registered) = state.couriers.get(&courier.id) {
+        state.publish_courier_change(&registered);
+    }
+    state.audit.record(
+        AuditEntity::Courier,
+        courier.id,
+        "created",
+        actor,
+        None,
+        Some(&courier),
+    );
+    courier
+}
+
+/// Sets a courier's availability as reported by the courier or a dispatcher.
+/// `break_until` is only accepted with `OnBreak` and must lie ahead.
+pub fn set_courier_status(
+    state: &AppState,
+    courier_id: Uuid,
+    status: CourierStatus,
+    break_until: Option<DateTime<Utc>>,
+    actor: &str,
+) -> Result<Arc<Courier>, AppError> {
+    if let Some(break_until) = break_until {
+        if status != CourierStatus::OnBreak {
+            return Err(AppError::BadRequest(
+                "break_until is only allowed with status OnBreak".to_string(),
+            ));
+        }
+        if break_until <= state.clock.now() {
+            return Err(AppError::BadRequest(
+                "break_until must be in the future".to_string(),
+            ));
+        }
+    }
+
+    let (old, new) = {
+        let mut entry = state
+            .couriers
+            .get_mut(&courier_id)
+            .ok_or_else(|| AppError::NotFound(format!("courier {} not found", courier_id)))?;
+
+        let old = Arc::clone(&entry);
+        let courier = Arc::make_mut(&mut entry);
+        courier.status = status;
+        courier.break_until = break_until;
+        courier.updated_at = state.clock.now();
+        courier.last_seen_at = courier.updated_at;
+        state.publish_courier_change(&entry);
+        (old, Arc::clone(&entry))
+    };
+
+    state.audit.record(
+        AuditEntity::Courier,
+        courier_id,
+        "status_changed",
+        actor,
+        Some(&old),
+        Some(&new),
+    );
+    Ok(new)
+}
+
+/// Puts couriers whose `break_until` has passed back to work: `Available`,
+/// or `Busy` if the orders they kept still fill them. Returns how many
+/// resumed.
+pub fn resume_ended_breaks(state: &AppState, now: DateTime<Utc>, actor: &str) -> usize {
+    let ended = |courier: &Courier| {
+        courier.status == CourierStatus::OnBreak
+            && courier.break_until.is_some_and(|until| until <= now)
+    };
+    let due: Vec<Uuid> = state
+        .couriers
+        .iter()
+        .filter(|entry| ended(entry.value()))
+        .map(|entry| entry.id)
+        .collect();
+
+    let mut resumed = 0;
+    for courier_id in due {
+        // Checked again under the lock: the courier may have changed its
+        // status since the scan.
+        let Some((old, new)) = state.couriers.get_mut(&courier_id).and_then(|mut entry| {
+            if !ended(&entry) {
+                return None;
+            }
+            let old = Arc::clone(&entry);
+            let courier = Arc::make_mut(&mut entry);
+            courier.status = if courier.is_full() {
+                CourierStatus::Busy
+            } else {
+                CourierStatus::Available
+            };
+            courier.break_until = None;
+            courier.updated_at = now;
+            state.publish_courier_change(&entry);
+            Some((old, Arc::clone(&entry)))
+        }) else {
+            continue;
+        };
+
+        state.audit.record(
+            AuditEntity::Courier,
+            courier_id,
+            "break_ended",
+            actor,
+            Some(&old),
+            Some(&new),
+        );
+        resumed += 1;
+    }
+    resumed
+}
+
+/// Replaces a courier's working hours. The engine stops offering work to a
+/// courier outside them, but orders already assigned stay assigned.
+pub fn set_courier_shifts(
+    state: &AppState,
+    courier_id: Uuid,
+    shifts: Vec<Shift>,
+    actor: &str,
+) -> Result<Arc<Courier>, AppError> {
+    let (old, new) = {
+        let mut entry = state
+            .couriers
+            .get_mut(&courier_id)
+            .ok_or_else(|| AppError::NotFound(format!("courier {} not found", courier_id)))?;
+
+        let old = Arc::clone(&entry);
+        let courier = Arc::make_mut(&mut entry);
+        courier.shifts = shifts;
+        courier.updated_at = state.clock.now();
+        state.publish_courier_change(&entry);
+        (old, Arc::clone(&entry))
+    };
+
+    state.audit.record(
+        AuditEntity::Courier,
+        courier_id,
+        "shifts_changed",
+        actor,
+        Some(&old),
+        Some(&new),
+    );
+    Ok(new)
+}
+
+/// Replaces a courier's skill tags, already normalized. Orders already
+/// assigned stay assigned even if the courier no longer qualifies.
+pub fn set_courier_skills(
+    state: &AppState,
+    courier_id: Uuid,
+    skills: Vec<String>,
+    actor: &str,
+) -> Result<Arc<Courier>, AppError> {
+    let (old, new) = {
+        let mut entry = state
+            .couriers
+            .get_mut(&courier_id)
+            .ok_or_else(|| AppError::NotFound(format!("courier {} not found", courier_id)))?;
+
+        let old = Arc::clone(&entry);
+        let courier = Arc::make_mut(&mut entry);
+        courier.skills = skills;
+        courier.updated_at = state.clock.now();
+        state.publish_courier_change(&entry);
+        (old, Arc::clone(&entry))
+    };
+
+    state.audit.record(
+        AuditEntity::Courier,
+        courier_id,
+        "skills_changed",
+        actor,
+        Some(&old),
+        Some(&new),
+    );
+    Ok(new)
+}
+
+/// Replaces a courier's preferred zones, already normalized.
+pub fn set_courier_zones(
+    state: &AppState,
+    courier_id: Uuid,
+    preferred_zones: Vec<String>,
+    actor: &str,
+) -> Result<Arc<Courier>, AppError> {
+    let (old, new) = {
+        let mut entry = state
+            .couriers
+            .get_mut(&courier_id)
+            .ok_or_else(|| AppError::NotFound(format!("courier {} not found", courier_id)))?;
+
+        let old = Arc::clone(&entry);
+        let courier = Arc::make_mut(&mut entry);
+        courier.preferred_zones = preferred_zones;
+        courier.updated_at = state.clock.now();
+        state.publish_courier_change(&entry);
+        (old, Arc::clone(&entry))
+    };
+
+    state.audit.record(
+        AuditEntity::Courier,
+        courier_id,
+        "zones_changed",
+        actor,
+        Some(&old),
+        Some(&new),
+    );
+    Ok(new)
+}
+
+/// Counts what became of an order the engine gave the courier.
+pub fn record_offer_outcome(state: &AppState, courier_id: Uuid, outcome: OfferOutcome) {
+    if let Some(mut courier) = state.couriers.get_mut(&courier_id) {
+        Arc::make_mut(&mut courier).offers.record(outcome);
+        state.publish_courier_change(&courier);
+    }
+}
+
+/// Notes that the courier is still around without changing anything else.
+pub fn record_heartbeat(state: &AppState, courier_id: Uuid) -> Result<Arc<Courier>, AppError> {
+    let mut courier = state
+        .couriers
+        .get_mut(&courier_id)
+        .ok_or_else(|| AppError::NotFound(format!("courier {} not found", courier_id)))?;
+    Arc::make_mut(&mut courier).last_seen_at = state.clock.now();
+    state.publish_courier_change(&courier);
+    Ok(Arc::clone(&courier))
+}
@@ -0,0 +1,326 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::engine::fleet::{record_offer_outcome, resume_ended_breaks};
+use crate::engine::queue::enqueue_order;
+use crate::models::audit::AuditEntity;
+use crate::models::courier::{CourierStatus, OfferOutcome};
+use crate::models::history::OrderHistoryEvent;
+use crate::models::order::{DeliveryOrder, OrderStatus};
+use crate::state::AppState;
+
+const ACTOR: &str = "heartbeat";
+
+/// How often the sweeper runs; breaks end and silent couriers go offline at
+/// most this late.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy)]
+pub struct HeartbeatSettings {
+    /// A courier not heard from for this long is taken offline; `None`
+    /// leaves silent couriers alone.
+    pub timeout: Option<Duration>,
+}
+
+/// Ends breaks whose `break_until` has passed and, with a heartbeat timeout
+/// set, takes silent couriers offline.
+pub async fn run_courier_sweeper(state: Arc<AppState>, settings: HeartbeatSettings) {
+    info!(
+        timeout_secs = settings.timeout.map(|timeout| timeout.as_secs()),
+        "courier sweeper started"
+    );
+    let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+    loop {
+        interval.tick().await;
+        resume_ended_breaks(&state, state.clock.now(), ACTOR);
+        if let Some(timeout) = settings.timeout {
+            sweep_stale_couriers(&state, timeout).await;
+        }
+    }
+}
+
+/// Takes every courier not seen within `timeout` offline and puts the orders
+/// they had not picked up yet back in the queue. Orders already `InTransit`
+/// stay with the courier, who has the parcel. Couriers on a break are left
+/// alone until it ends. Returns how many couriers went offline.
+pub async fn sweep_stale_couriers(state: &AppState, timeout: Duration) -> usize {
+    let cutoff =
+        state.clock.now() - chrono::Duration::from_std(timeout).unwrap_or(chrono::Duration::MAX);
+    let stale: Vec<Uuid> = state
+        .couriers
+        .iter()
+        .filter(|entry| is_watched(&entry.status) && entry.last_seen_at < cutoff)
+        .map(|entry| entry.id)
+        .collect();
+
+    let mut taken_offline = 0;
+    for courier_id in stale {
+        if take_offline(state, courier_id, cutoff).await {
+            taken_offline += 1;
+        }
+    }
+    taken_offline
+}
+
+fn is_watched(status: &CourierStatus) -> bool {
+    !matches!(status, CourierStatus::Offline | CourierStatus::OnBreak)
+}
+
+async fn take_offline(state: &AppState, courier_id: Uuid, cutoff: DateTime<Utc>) -> bool {
+    let orders: Vec<DeliveryOrder> = state
+        .orders
+        .iter()
+        .filter(|entry| {
+            entry.assigned_courier == Some(courier_id) && entry.status == OrderStatus::Assigned
+        })
+        .map(|entry| entry.value().clone())
+        .collect();
+
+    let (old, new, unassigned) = {
+        let _change = state.snapshots.change();
+        // Checked again under the lock: a ping may have arrived since the scan.
+        let Some((old, new)) = state.couriers.get_mut(&courier_id).and_then(|mut entry| {
+            if !is_watched(&entry.status) || entry.last_seen_at >= cutoff {
+                return None;
+            }
+            let old = Arc::clone(&entry);
+            let courier = Arc::make_mut(&mut entry);
+            courier.status = CourierStatus::Offline;
+            courier.unload(orders.iter().map(|order| order.size).sum());
+            courier.updated_at = state.clock.now();
+            state.publish_courier_change(&entry);
+            Some((old, Arc::clone(&entry)))
+        }) else {
+            return false;
+        };
+        let unassigned: Vec<(DeliveryOrder, DeliveryOrder)> = orders
+            .into_iter()
+            .filter_map(|order| unassign(state, courier_id, &order).map(|updated| (order, updated)))
+            .collect();
+        (old, new, unassigned)
+    };
+
+    warn!(
+        %courier_id,
+        last_seen_at = %old.last_seen_at,
+        requeued_orders = unassigned.len(),
+        "courier missed its heartbeat; taken offline"
+    );
+    state.audit.record(
+        AuditEntity::Courier,
+        courier_id,
+        "timed_out",
+        ACTOR,
+        Some(&old),
+        Some(&new),
+    );
+    state.metrics.courier_timeouts_total.inc();
+
+    for (order, updated) in unassigned {
+        requeue(state, courier_id, order, updated).await;
+    }
+    true
+}
+
+/// Takes `order` off the courier, if it is still theirs and not picked up.
+fn unassign(state: &AppState, courier_id: Uuid, order: &DeliveryOrder) -> Option<DeliveryOrder> {
+    state.orders.get_mut(&order.id).and_then(|mut current| {
+        if current.assigned_courier != Some(courier_id) || current.status != OrderStatus::Assigned {
+            return None;
+        }
+        current.status = OrderStatus::Pending;
+        current.assigned_courier = None;
+        state.publish_order_change(&current);
+        Some(current.clone())
+    })
+}
+
+async fn requeue(state: &AppState, courier_id: Uuid, order: DeliveryOrder, updated: DeliveryOrder) {
+    state.audit.record(
+        AuditEntity::Order,
+        order.id,
+        "unassigned",
+        ACTOR,
+        Some(&order),
+        Some(&updated),
+    );
+    record_offer_outcome(state, courier_id, OfferOutcome::TimedOut);
+    state.record_order_history(
+        order.id,
+        OrderHistoryEvent::Unassigned,
+        ACTOR,
+        Some(courier_id),
+        Some("courier missed its heartbeat".to_string()),
+    );
+    state.publish_order_event(Some(&OrderStatus::Assigned), &updated);
+    if let Err(err) = enqueue_order(state, updated, ACTOR).await {
+        error!(order_id = %order.id, error = %err, "failed to requeue order of offline courier");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    use super::sweep_stale_couriers;
+    use crate::clock::{Clock, ManualClock};
+    use crate::engine::fleet::{record_heartbeat, register_courier, resume_ended_breaks};
+    use crate::models::courier::{Courier, CourierStatus, GeoPoint, OfferHistory, VehicleType};
+    use crate::models::order::{DeliveryOrder, OrderStatus, Priority};
+    use crate::state::AppState;
+
+    fn courier(last_seen_secs_ago: i64) -> Courier {
+        Courier {
+            id: Uuid::new_v4(),
+            name: "Max".to_string(),
+            location: GeoPoint {
+                lat: 52.52,
+                lng: 13.40,
+            },
+            capacity: 3.0,
+            current_load: 2.0,
+            status: CourierStatus::Available,
+            break_until: None,
+            rating: 4.5,
+            offers: OfferHistory::default(),
+            last_assigned_at: None,
+            vehicle_type: VehicleType::Bike,
+            updated_at: Utc::now(),
+            last_seen_at: Utc::now() - chrono::Duration::seconds(last_seen_secs_ago),
+            shifts: Vec::new(),
+            skills: Vec::new(),
+            preferred_zones: Vec::new(),
+            accepts_cod: false,
+            cod_limit: None,
+            equipment: Vec::new(),
+            location_cache: Default::default(),
+        }
+    }
+
+    fn order(courier_id: Uuid, status: OrderStatus) -> DeliveryOrder {
+        DeliveryOrder {
+            id: Uuid::new_v4(),
+            pickup: GeoPoint {
+                lat: 52.52,
+                lng: 13.40,
+            },
+            dropoff: GeoPoint {
+                lat: 52.53,
+                lng: 13.41,
+            },
+            extra_pickups: Vec::new(),
+            priority: Priority::Normal,
+            status,
+            assigned_courier: Some(courier_id),
+            created_at: Utc::now(),
+            size: 1.0,
+            items: Vec::new(),
+            cash_on_delivery: None,
+            required_vehicle: None,
+            required_skills: Vec::new(),
+            handling: Vec::new(),
+            waiting_reason: None,
+            declined_by: Vec::new(),
+            callback_url: None,
+            customer_contact: None,
+            request_id: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn takes_silent_couriers_offline_and_requeues_orders_not_picked_up() {
+        let (state, _rx) = AppState::new(16, 16);
+        let silent = courier(120);
+        let active = courier(10);
+        let assigned = order(silent.id, OrderStatus::Assigned);
+        let in_transit = order(silent.id, OrderStatus::InTransit);
+        for courier in [&silent, &active] {
+            state.couriers.insert(courier.id, Arc::new(courier.clone()));
+        }
+        for order in [&assigned, &in_transit] {
+            state.orders.insert(order.id, order.clone());
+        }
+        let snapshot = state.couriers.get(&silent.id).unwrap().clone();
+
+        assert_eq!(
+            sweep_stale_couriers(&state, Duration::from_secs(60)).await,
+            1
+        );
+
+        let silent = state.couriers.get(&silent.id).unwrap().clone();
+        assert_eq!(silent.status, CourierStatus::Offline);
+        // The update stored a new version; the old snapshot is untouched.
+        assert_eq!(snapshot.status, CourierStatus::Available);
+        assert_eq!(silent.current_load, 1.0);
+        assert_eq!(
+            state.couriers.get(&active.id).unwrap().status,
+            CourierStatus::Available
+        );
+
+        let requeued = state.orders.get(&assigned.id).unwrap().clone();
+        assert_eq!(requeued.status, OrderStatus::Pending);
+        assert_eq!(requeued.assigned_courier, None);
+        assert_eq!(
+            state.orders.get(&in_transit.id).unwrap().status,
+            OrderStatus::InTransit
+        );
+        assert_eq!(state.queue_depth(), 1);
+        assert_eq!(state.metrics.courier_timeouts_total.get(), 1);
+
+        // Already offline, so a second sweep leaves it alone.
+        assert_eq!(
+            sweep_stale_couriers(&state, Duration::from_secs(60)).await,
+            0
+        );
+    }
+
+    #[tokio::test]
+    async fn breaks_are_not_timeouts_and_end_on_time() {
+        let (state, _rx) = AppState::new(16, 16);
+        let mut resting = courier(120);
+        resting.status = CourierStatus::OnBreak;
+        resting.break_until = Some(Utc::now() + chrono::Duration::minutes(5));
+        state.couriers.insert(resting.id, Arc::new(resting.clone()));
+
+        assert_eq!(
+            sweep_stale_couriers(&state, Duration::from_secs(60)).await,
+            0
+        );
+        assert_eq!(resume_ended_breaks(&state, Utc::now(), "test"), 0);
+
+        let later = Utc::now() + chrono::Duration::minutes(6);
+        assert_eq!(resume_ended_breaks(&state, later, "test"), 1);
+        let resumed = state.couriers.get(&resting.id).unwrap().clone();
+        assert_eq!(resumed.status, CourierStatus::Available);
+        assert_eq!(resumed.break_until, None);
+    }
+
+    #[tokio::test]
+    async fn couriers_go_stale_as_the_clock_moves_on() {
+        let clock = ManualClock::new(Utc::now());
+        let (mut state, _rx) = AppState::new(16, 16);
+        state.clock = Arc::new(clock.clone());
+        let courier = register_courier(&state, courier(0), "test");
+        let timeout = Duration::from_secs(60);
+
+        clock.advance(chrono::Duration::seconds(50));
+        assert_eq!(sweep_stale_couriers(&state, timeout).await, 0);
+        record_heartbeat(&state, courier.id).unwrap();
+        clock.advance(chrono::Duration::seconds(50));
+        assert_eq!(sweep_stale_couriers(&state, timeout).await, 0);
+
+        clock.advance(chrono::Duration::seconds(11));
+        assert_eq!(sweep_stale_couriers(&state, timeout).await, 1);
+        let courier = state.couriers.get(&courier.id).unwrap().clone();
+        assert_eq!(courier.status, CourierStatus::Offline);
+        assert_eq!(courier.updated_at, clock.now());
+    }
+}
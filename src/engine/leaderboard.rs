@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::models::stats::{Leaderboard, LeaderboardEntry, LeaderboardMetric, LeaderboardPeriod};
+use crate::state::AppState;
+
+#[derive(Default)]
+struct Tally {
+    deliveries: u64,
+    on_time: u64,
+}
+
+/// Ranks couriers by `metric` over the assignment history of the period
+/// ending at `now`. Couriers with nothing assigned or delivered in the period
+/// are left out, as are couriers without deliveries when ranking by on-time
+/// rate.
+pub fn leaderboard(
+    state: &AppState,
+    metric: LeaderboardMetric,
+    period: LeaderboardPeriod,
+    now: DateTime<Utc>,
+) -> Leaderboard {
+    let since = now - period.duration();
+    let mut tallies: HashMap<Uuid, Tally> = HashMap::new();
+    for assignment in state.assignments.iter() {
+        let delivered_at = assignment.delivered_at.filter(|at| *at >= since);
+        if assignment.assigned_at < since && delivered_at.is_none() {
+            continue;
+        }
+        let tally = tallies.entry(assignment.courier_id).or_default();
+        if delivered_at.is_some() {
+            tally.deliveries += 1;
+            if assignment.delivered_on_time() {
+                tally.on_time += 1;
+            }
+        }
+    }
+
+    let mut entries: Vec<LeaderboardEntry> = tallies
+        .into_iter()
+        .filter_map(|(courier_id, tally)| {
+            let courier = state.couriers.get(&courier_id)?;
+            let value = match metric {
+                LeaderboardMetric::Deliveries => tally.deliveries as f64,
+                LeaderboardMetric::Rating => courier.rating,
+                LeaderboardMetric::OnTimeRate if tally.deliveries == 0 => return None,
+                LeaderboardMetric::OnTimeRate => tally.on_time as f64 / tally.deliveries as f64,
+            };
+            Some(LeaderboardEntry {
+                rank: 0,
+                courier_id,
+                name: courier.name.clone(),
+                value,
+                deliveries: tally.deliveries,
+            })
+        })
+        .collect();
+
+    entries.sort_by(|a, b| {
+        b.value
+            .total_cmp(&a.value)
+            .then(b.deliveries.cmp(&a.deliveries))
+            .then(a.name.cmp(&b.name))
+    });
+    let mut previous: Option<(f64, usize)> = None;
+    for (i, entry) in entries.iter_mut().enumerate() {
+        entry.rank = match previous {
+            Some((value, rank)) if value == entry.value => rank,
+            _ => i + 1,
+        };
+        previous = Some((entry.value, entry.rank));
+    }
+
+    Leaderboard {
+        metric,
+        period,
+        since,
+        entries,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::{DateTime, Duration, Utc};
+    use uuid::Uuid;
+
+    use super::leaderboard;
+    use crate::models::assignment::{Assignment, ScoreBreakdown};
+    use crate::models::courier::{Courier, CourierStatus, GeoPoint, OfferHistory, VehicleType};
+    use crate::models::stats::{LeaderboardMetric, LeaderboardPeriod};
+    use crate::state::AppState;
+
+    fn courier(name: &str, rating: f64) -> Courier {
+        Courier {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            location: GeoPoint {
+                lat: 52.52,
+                lng: 13.40,
+            },
+            capacity: 3.0,
+            current_load: 0.0,
+            status: CourierStatus::Available,
+            break_until: None,
+            rating,
+            offers: OfferHistory::default(),
+            last_assigned_at: None,
+            vehicle_type: VehicleType::Bike,
+            updated_at: Utc::now(),
+            last_seen_at: Utc::now(),
+            shifts: Vec::new(),
+            skills: Vec::new(),
+            preferred_zones: Vec::new(),
+            accepts_cod: false,
+            cod_limit: None,
+            equipment: Vec::new(),
+            location_cache: Default::default(),
+        }
+    }
+
+    /// An assignment made `hours_ago`, delivered `late_mins` after it was due.
+    fn delivered(
+        courier_id: Uuid,
+        now: DateTime<Utc>,
+        hours_ago: i64,
+        late_mins: i64,
+    ) -> Assignment {
+        let assigned_at = now - Duration::hours(hours_ago);
+        let due_at = assigned_at + Duration::minutes(30);
+        Assignment {
+            id: Uuid::new_v4(),
+            order_id: Uuid::new_v4(),
+            courier_id,
+            score: 0.8,
+            score_breakdown: ScoreBreakdown {
+                distance_score: 0.8,
+                load_score: 1.0,
+                rating_score: 0.9,
+                priority_score: 0.5,
+                zone_score: 0.0,
+                reliability_score: 1.0,
+                idle_score: 1.0,
+                earnings_score: 1.0,
+            },
+            assigned_at,
+            due_at,
+            delivered_at: Some(due_at + Duration::minutes(late_mins)),
+            stacked_with: Vec::new(),
+            cash_on_delivery: None,
+        }
+    }
+
+    #[test]
+    fn ranks_couriers_over_the_period() {
+        let (state, _rx) = AppState::new(16, 16);
+        let now = Utc::now();
+        let ada = courier("Ada", 4.2);
+        let bo = courier("Bo", 4.9);
+        let cy = courier("Cy", 4.5);
+        for courier in [&ada, &bo, &cy] {
+            state.couriers.insert(courier.id, Arc::new(courier.clone()));
+        }
+        for assignment in [
+            delivered(ada.id, now, 2, -5),
+            delivered(ada.id, now, 3, 10),
+            delivered(bo.id, now, 4, -1),
+            // Only inside the week.
+            delivered(cy.id, now, 48, 0),
+            delivered(cy.id, now, 50, 0),
+            delivered(cy.id, now, 52, 0),
+        ] {
+            state.assignments.insert(assignment.id, assignment);
+        }
+
+        let board = leaderboard(
+            &state,
+            LeaderboardMetric::Deliveries,
+            LeaderboardPeriod::Day,
+            now,
+        );
+        let names: Vec<&str> = board.entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["Ada", "Bo"]);
+        assert_eq!(board.entries[0].value, 2.0);
+
+        let board = leaderboard(
+            &state,
+            LeaderboardMetric::Deliveries,
+            LeaderboardPeriod::Week,
+            now,
+        );
+        assert_eq!(board.entries[0].name, "Cy");
+        assert_eq!(board.entries[0].deliveries, 3);
+
+        let board = leaderboard(
+            &state,
+            LeaderboardMetric::OnTimeRate,
+            LeaderboardPeriod::Week,
+            now,
+        );
+        let ranks: Vec<(&str, usize, f64)> = board
+            .entries
+            .iter()
+            .map(|e| (e.name.as_str(), e.rank, e.value))
+            .collect();
+        assert_eq!(ranks, [("Cy", 1, 1.0), ("Bo", 1, 1.0), ("Ada", 3, 0.5)]);
+
+        let board = leaderboard(
+            &state,
+            LeaderboardMetric::Rating,
+            LeaderboardPeriod::Day,
+            now,
+        );
+        assert_eq!(board.entries[0].name, "Bo");
+        assert_eq!(board.entries.len(), 2);
+    }
+}
@@ -0,0 +1,66 @@
+use tokio::sync::watch;
+
+/// Whether this instance may run its assignment engines. Always so unless
+/// leader election is on (`LEADER_ELECTION_KEY`); then only the instance
+/// holding the lease does, and the others keep their engines on standby,
+/// orders left queued, until they take it over. See `crate::leader`.
+#[derive(Debug)]
+pub struct Leadership {
+    leader: watch::Sender<bool>,
+}
+
+impl Default for Leadership {
+    fn default() -> Self {
+        Self {
+            leader: watch::channel(true).0,
+        }
+    }
+}
+
+impl Leadership {
+    pub fn is_leader(&self) -> bool {
+        *self.leader.borrow()
+    }
+
+    /// Returns whether that changed anything.
+    pub fn set(&self, leader: bool) -> bool {
+        self.leader.send_if_modified(|current| {
+            let changed = *current != leader;
+            *current = leader;
+            changed
+        })
+    }
+
+    /// Resolves once this instance leads.
+    pub async fn acquired(&self) {
+        let _ = self.leader.subscribe().wait_for(|leader| *leader).await;
+    }
+
+    /// Resolves once this instance no longer leads.
+    pub async fn lost(&self) {
+        let _ = self.leader.subscribe().wait_for(|leader| !*leader).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::Leadership;
+
+    #[tokio::test]
+    async fn waits_follow_the_lease() {
+        let leadership = Leadership::default();
+        assert!(leadership.is_leader());
+        leadership.acquired().await;
+
+        assert!(leadership.set(false));
+        assert!(!leadership.set(false));
+        leadership.lost().await;
+        let waiting = tokio::time::timeout(Duration::from_millis(20), leadership.acquired());
+        assert!(waiting.await.is_err());
+
+        assert!(leadership.set(true));
+        leadership.acquired().await;
+    }
+}
@@ -0,0 +1,209 @@
+use std::sync::Arc;
+
+use uuid::Uuid;
+
+use crate::engine::fleet::record_offer_outcome;
+use crate::engine::queue::{enqueue_reserved, reserve_queue_slot};
+use crate::error::AppError;
+use crate::models::audit::AuditEntity;
+use crate::models::courier::{CourierStatus, OfferOutcome};
+use crate::models::history::OrderHistoryEvent;
+use crate::models::order::{DeliveryOrder, OrderStatus};
+use crate::state::AppState;
+
+/// What a courier is paid per delivery, in any currency; the same for the
+/// whole deployment.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PayoutRates {
+    pub per_delivery: f64,
+    pub per_km: f64,
+}
+
+impl PayoutRates {
+    pub fn payout(&self, distance_km: f64) -> f64 {
+        self.per_delivery + self.per_km * distance_km
+    }
+}
+
+/// Moves an assigned order forward (`Assigned` -> `InTransit` -> `Delivered`)
+/// and publishes the transition. Delivering an order frees the capacity it
+/// took up, stamps its assignment as delivered and counts towards the
+/// courier's delivery stats.
+pub fn transition_order(
+    state: &AppState,
+    order_id: Uuid,
+    next: OrderStatus,
+    actor: &str,
+) -> Result<DeliveryOrder, AppError> {
+    let _change = state.snapshots.change();
+    let (old, updated) = {
+        let mut order = state
+            .orders
+            .get_mut(&order_id)
+            .ok_or_else(|| AppError::NotFound(format!("order {} not found", order_id)))?;
+
+        if !is_valid_transition(&order.status, &next) {
+            return Err(AppError::Conflict(format!(
+                "order {} cannot move from {:?} to {:?}",
+                order_id, order.status, next
+            )));
+        }
+
+        let old = order.clone();
+        order.status = next;
+        state.publish_order_change(&order);
+        (old, order.clone())
+    };
+    state.audit.record(
+        AuditEntity::Order,
+        order_id,
+        "status_changed",
+        actor,
+        Some(&old),
+        Some(&updated),
+    );
+
+    let event = match updated.status {
+        OrderStatus::InTransit => OrderHistoryEvent::PickedUp,
+        _ => OrderHistoryEvent::Delivered,
+    };
+    state.record_order_history(order_id, event, actor, updated.assigned_courier, None);
+
+    if let Some(courier_id) = updated.assigned_courier {
+        match updated.status {
+            OrderStatus::InTransit => {
+                record_offer_outcome(state, courier_id, OfferOutcome::Accepted);
+            }
+            OrderStatus::Delivered => {
+                release_courier(state, courier_id, updated.size, actor);
+                record_delivery(state, courier_id, &updated);
+            }
+            _ => {}
+        }
+    }
+
+    state.publish_order_event(Some(&old.status), &updated);
+    Ok(updated)
+}
+
+/// Hands an order back before pickup. It is freed from the courier, counted
+/// as declined in the courier's offer history and queued again for anyone
+/// but that courier. While its queue is full the order stays with the
+/// courier and the decline fails with `Unavailable`.
+pub async fn decline_order(
+    state: &AppState,
+    order_id: Uuid,
+    actor: &str,
+) -> Result<DeliveryOrder, AppError> {
+    let pickup = state
+        .orders
+        .get(&order_id)
+        .map(|order| order.pickup.clone())
+        .ok_or_else(|| AppError::NotFound(format!("order {} not found", order_id)))?;
+    let slot = reserve_queue_slot(state, &pickup)?;
+    let (old, updated, courier_id) = {
+        let _change = state.snapshots.change();
+        let (old, updated, courier_id) = {
+            let mut order = state
+                .orders
+                .get_mut(&order_id)
+                .ok_or_else(|| AppError::NotFound(format!("order {} not found", order_id)))?;
+
+            let (OrderStatus::Assigned, Some(courier_id)) = (&order.status, order.assigned_courier)
+            else {
+                return Err(AppError::Conflict(format!(
+                    "order {} can only be declined while Assigned, not {:?}",
+                    order_id, order.status
+                )));
+            };
+
+            let old = order.clone();
+            order.status = OrderStatus::Pending;
+            order.assigned_courier = None;
+            order.declined_by.push(courier_id);
+            state.publish_order_change(&order);
+            (old, order.clone(), courier_id)
+        };
+        release_courier(state, courier_id, updated.size, actor);
+        (old, updated, courier_id)
+    };
+    state.audit.record(
+        AuditEntity::Order,
+        order_id,
+        "declined",
+        actor,
+        Some(&old),
+        Some(&updated),
+    );
+
+    record_offer_outcome(state, courier_id, OfferOutcome::Declined);
+    state.record_order_history(
+        order_id,
+        OrderHistoryEvent::Declined,
+        actor,
+        Some(courier_id),
+        None,
+    );
+    state.publish_order_event(Some(&old.status), &updated);
+    enqueue_reserved(state, slot, updated.clone(), actor);
+    Ok(updated)
+}
+
+fn is_valid_transition(current: &OrderStatus, next: &OrderStatus) -> bool {
+    matches!(
+        (current, next),
+        (OrderStatus::Assigned, OrderStatus::InTransit)
+            | (OrderStatus::InTransit, OrderStatus::Delivered)
+    )
+}
+
+fn record_delivery(state: &AppState, courier_id: Uuid, order: &DeliveryOrder) {
+    if let Some(assignment) = state
+        .latest_assignment_for_order(order.id)
+        .filter(|assignment| assignment.courier_id == courier_id)
+        && let Some(mut stored) = state.assignments.get_mut(&assignment.id)
+    {
+        stored.delivered_at = Some(state.clock.now());
+    }
+
+    let distance_km = order.route_km();
+    let payout = state.payouts.payout(distance_km);
+    state
+        .courier_stats
+        .entry(courier_id)
+        .or_default()
+        .record(distance_km, payout);
+    state.earnings.record(courier_id, payout, state.clock.now());
+}
+
+fn release_courier(state: &AppState, courier_id: Uuid, size: f64, actor: &str) {
+    let Some((old, new)) = state.couriers.get_mut(&courier_id).map(|mut entry| {
+        let old = Arc::clone(&entry);
+        let courier = Arc::make_mut(&mut entry);
+        courier.unload(size);
+        if courier.status == CourierStatus::Busy && !courier.is_full() {
+            courier.status = CourierStatus::Available;
+        }
+        courier.updated_at = state.clock.now();
+
+        state
+            .metrics
+            .record_courier_load(courier.current_load, courier.capacity);
+        state
+            .stats
+            .record_courier_load(courier.current_load, courier.capacity, state.clock.now());
+        state.publish_courier_change(&entry);
+        (old, Arc::clone(&entry))
+    }) else {
+        return;
+    };
+
+    state.audit.record(
+        AuditEntity::Courier,
+        courier_id,
+        "released",
+        actor,
+        Some(&old),
+        Some(&new),
+    );
+}
@@ -0,0 +1,42 @@
+use std::sync::Arc;
+
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::geo::cell_id;
+use crate::geo::region::REGION_PRECISION;
+use crate::models::courier::{Courier, GeoPoint};
+use crate::state::AppState;
+use crate::validation::validate_point;
+
+/// Moves a courier and broadcasts the new position. Shared by every
+/// location source (REST, gRPC streams, MQTT devices).
+pub fn move_courier(
+    state: &AppState,
+    courier_id: Uuid,
+    location: GeoPoint,
+) -> Result<Arc<Courier>, AppError> {
+    validate_point("location", &location)?;
+    let (moved, from) = {
+        let mut entry = state
+            .couriers
+            .get_mut(&courier_id)
+            .ok_or_else(|| AppError::NotFound(format!("courier {} not found", courier_id)))?;
+
+        let courier = Arc::make_mut(&mut entry);
+        let from = cell_id(&courier.location, REGION_PRECISION);
+        courier.set_location(location);
+        courier.updated_at = state.clock.now();
+        courier.last_seen_at = courier.updated_at;
+        state.publish_courier_location(courier);
+        (Arc::clone(&entry), from)
+    };
+
+    // Couriers are filed by region; one that crossed into another is moved
+    // as one change, so list reads see it exactly once.
+    if cell_id(&moved.location, REGION_PRECISION) != from {
+        let _change = state.snapshots.change();
+        state.couriers.rehome(&courier_id);
+    }
+    Ok(moved)
+}
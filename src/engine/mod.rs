@@ -1,3 +1,20 @@
 pub mod assignment;
+pub mod constraints;
+pub mod distance;
+pub mod earnings;
+pub mod feedback;
+pub mod fleet;
+pub mod heartbeat;
+pub mod leaderboard;
+pub mod leadership;
+pub mod lifecycle;
+pub mod location;
 pub mod queue;
+pub mod regions;
+pub mod route;
 pub mod scoring;
+pub mod status;
+pub mod supervisor;
+pub mod surge;
+pub mod timeline;
+pub mod tracking;
@@ -1,14 +1,199 @@
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+use tracing::{instrument, Span};
+use uuid::Uuid;
+
 use crate::error::AppError;
-use crate::models::order::DeliveryOrder;
+use crate::models::audit::AuditEntity;
+use crate::models::courier::GeoPoint;
+use crate::models::history::OrderHistoryEvent;
+use crate::models::order::{default_size, DeliveryOrder, NewOrder, OrderItem, OrderStatus};
 use crate::state::AppState;
+use crate::validation::{
+    normalize_items, normalize_skills, validate_amount, validate_cash, validate_extra_pickups,
+    validate_route,
+};
 
-pub async fn enqueue_order(state: &AppState, order: DeliveryOrder) -> Result<(), AppError> {
-    state
-        .order_tx
-        .send(order)
+/// An order waiting for assignment, along with the span it was queued under
+/// so the engine's attempts show up in the submitter's trace.
+#[derive(Debug)]
+pub struct QueuedOrder {
+    pub order: DeliveryOrder,
+    pub span: Span,
+}
+
+/// Records a new order, announces it and queues it for assignment. Shared by
+/// every ingestion path so they create orders identically.
+#[instrument(skip_all, fields(order_id = tracing::field::Empty, request_id = new.request_id.as_deref()))]
+pub async fn submit_order(
+    state: &AppState,
+    new: NewOrder,
+    actor: &str,
+) -> Result<DeliveryOrder, AppError> {
+    if state.engine.is_draining() {
+        return Err(AppError::Unavailable(
+            "shutting down; not accepting new orders".to_string(),
+        ));
+    }
+    let order = build_order(state, new)?;
+    Span::current().record("order_id", tracing::field::display(order.id));
+
+    state.orders.insert(order.id, order.clone());
+    if let Some(created) = state.orders.get(&order.id) {
+        state.publish_order_change(&created);
+    }
+    state.pickup_index.insert(order.id, &order.pickup);
+    state.audit.record(
+        AuditEntity::Order,
+        order.id,
+        "created",
+        actor,
+        None,
+        Some(&order),
+    );
+    state.record_order_history(order.id, OrderHistoryEvent::Created, actor, None, None);
+    state.publish_order_event(None, &order);
+    enqueue_order(state, order.clone(), actor).await?;
+
+    Ok(order)
+}
+
+/// Validates `new` and builds the order it describes, without recording or
+/// queueing it.
+pub fn build_order(state: &AppState, new: NewOrder) -> Result<DeliveryOrder, AppError> {
+    validate_route(&new.pickup, &new.dropoff)?;
+    validate_extra_pickups(&new.extra_pickups)?;
+    let items = normalize_items(new.items, state.capacity_unit)?;
+    let size = match (new.size, items.is_empty()) {
+        (Some(_), false) => {
+            return Err(AppError::BadRequest(
+                "give either size or items, not both".to_string(),
+            ));
+        }
+        (Some(size), true) => size,
+        (None, true) => default_size(),
+        (None, false) => items.iter().map(OrderItem::total_weight).sum(),
+    };
+    validate_amount("size", size, state.capacity_unit)?;
+    if let Some(amount) = new.cash_on_delivery {
+        validate_cash("cash_on_delivery", amount)?;
+    }
+    let required_skills = normalize_skills("required_skills", new.required_skills)?;
+    let mut handling = Vec::new();
+    for required in new.handling {
+        if !handling.contains(&required) {
+            handling.push(required);
+        }
+    }
+    if let Some(callback_url) = &new.callback_url {
+        let url = reqwest::Url::parse(callback_url)
+            .map_err(|err| AppError::BadRequest(format!("invalid callback_url: {err}")))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(AppError::BadRequest(
+                "callback_url must be http or https".to_string(),
+            ));
+        }
+    }
+
+    Ok(DeliveryOrder {
+        id: Uuid::now_v7(),
+        pickup: new.pickup,
+        dropoff: new.dropoff,
+        extra_pickups: new.extra_pickups,
+        priority: new.priority,
+        status: OrderStatus::Pending,
+        assigned_courier: None,
+        created_at: state.clock.now(),
+        size,
+        items,
+        cash_on_delivery: new.cash_on_delivery,
+        required_vehicle: new.required_vehicle,
+        required_skills,
+        handling,
+        waiting_reason: None,
+        declined_by: Vec::new(),
+        callback_url: new.callback_url,
+        customer_contact: new
+            .customer_contact
+            .filter(|contact| !contact.trim().is_empty()),
+        request_id: new.request_id,
+    })
+}
+
+#[instrument(skip_all, fields(order_id = %order.id, request_id = order.request_id.as_deref()))]
+pub async fn enqueue_order(
+    state: &AppState,
+    order: DeliveryOrder,
+    actor: &str,
+) -> Result<(), AppError> {
+    state.record_order_history(order.id, OrderHistoryEvent::Queued, actor, None, None);
+    requeue_order(
+        state,
+        QueuedOrder {
+            order,
+            span: Span::current(),
+        },
+    )
+    .await
+}
+
+/// Puts an order back in the queue under its original span: that of the
+/// region it is picked up in, if any.
+pub(crate) async fn requeue_order(state: &AppState, queued: QueuedOrder) -> Result<(), AppError> {
+    let priority = queued.order.priority.as_str();
+    order_tx(state, &queued.order.pickup)
+        .send(queued)
         .await
         .map_err(|err| AppError::Internal(format!("order queue send failed: {err}")))?;
 
-    state.metrics.orders_in_queue.inc();
+    state
+        .metrics
+        .orders_in_queue
+        .with_label_values(&[priority])
+        .inc();
     Ok(())
 }
+
+/// Holds a place in the queue of orders picked up at `pickup`, for changes
+/// that must not go through unless the order can be queued afterwards.
+/// Fails with `Unavailable` while the queue is full.
+pub(crate) fn reserve_queue_slot<'a>(
+    state: &'a AppState,
+    pickup: &GeoPoint,
+) -> Result<mpsc::Permit<'a, QueuedOrder>, AppError> {
+    order_tx(state, pickup)
+        .try_reserve()
+        .map_err(|err| match err {
+            TrySendError::Full(()) => {
+                AppError::Unavailable("order queue is full; try again".to_string())
+            }
+            TrySendError::Closed(()) => AppError::Internal("order queue is closed".to_string()),
+        })
+}
+
+/// `enqueue_order` into a place held by `reserve_queue_slot`; cannot fail.
+pub(crate) fn enqueue_reserved(
+    state: &AppState,
+    slot: mpsc::Permit<'_, QueuedOrder>,
+    order: DeliveryOrder,
+    actor: &str,
+) {
+    state.record_order_history(order.id, OrderHistoryEvent::Queued, actor, None, None);
+    let priority = order.priority.as_str();
+    slot.send(QueuedOrder {
+        order,
+        span: Span::current(),
+    });
+    state
+        .metrics
+        .orders_in_queue
+        .with_label_values(&[priority])
+        .inc();
+}
+
+fn order_tx<'a>(state: &'a AppState, pickup: &GeoPoint) -> &'a mpsc::Sender<QueuedOrder> {
+    match state.region_of(pickup) {
+        Some(index) => &state.regions[index].order_tx,
+        None => &state.order_tx,
+    }
+}
@@ -0,0 +1,185 @@
+use std::sync::Arc;
+
+use tokio::sync::{mpsc, Mutex};
+
+use crate::config::Tunables;
+use crate::engine::queue::QueuedOrder;
+use crate::engine::scoring::ScoringWeights;
+use crate::engine::status::EngineStatus;
+use crate::geo::in_zone;
+use crate::models::courier::GeoPoint;
+
+/// A city or other area dispatched on its own, as set in `REGIONS`. Orders
+/// picked up in one of its cells go to its engine and are scored with its
+/// settings; anything it leaves unset follows the instance-wide tunables.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegionSettings {
+    pub name: String,
+    /// Geohash cells it covers. Where regions overlap, the one with the
+    /// longer matching cell takes the order.
+    pub cells: Vec<String>,
+    /// Replaces the `SCORING_*_WEIGHT`s.
+    pub scoring: Option<ScoringWeights>,
+    /// Replaces `MAX_PICKUP_KM`.
+    pub max_pickup_km: Option<f64>,
+    /// Replaces `STACKING_MAX_DETOUR_KM`.
+    pub stacking_max_detour_km: Option<f64>,
+}
+
+impl RegionSettings {
+    /// The length of this region's longest cell `point` is in, if any.
+    fn matching_cell_len(&self, point: &GeoPoint) -> Option<usize> {
+        self.cells
+            .iter()
+            .filter(|cell| in_zone(point, cell))
+            .map(String::len)
+            .max()
+    }
+}
+
+/// How the engine scores couriers for one order: a region's settings over
+/// the current tunables.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DispatchSettings {
+    pub scoring: ScoringWeights,
+    pub stacking_max_detour_km: f64,
+}
+
+impl DispatchSettings {
+    pub fn resolve(tunables: &Tunables, region: Option<&RegionSettings>) -> Self {
+        Self {
+            scoring: region
+                .and_then(|region| region.scoring)
+                .unwrap_or(tunables.scoring),
+            stacking_max_detour_km: region
+                .and_then(|region| region.stacking_max_detour_km)
+                .unwrap_or(tunables.stacking_max_detour_km),
+        }
+    }
+}
+
+/// A region with the queue and engine of its own, so a busy city never
+/// holds up another's orders. One without cells is an engine shard (see
+/// `ENGINE_SHARDS`), taking orders by [`shard_of_cell`] instead.
+pub struct DispatchRegion {
+    pub settings: RegionSettings,
+    pub order_tx: mpsc::Sender<QueuedOrder>,
+    pub engine: EngineStatus,
+    /// Held by the region's engine while it runs; see
+    /// [`crate::engine::supervisor`].
+    pub(crate) order_rx: Arc<Mutex<mpsc::Receiver<QueuedOrder>>>,
+}
+
+impl DispatchRegion {
+    pub fn new(settings: RegionSettings, order_queue_size: usize) -> Self {
+        let (order_tx, order_rx) = mpsc::channel(order_queue_size);
+        Self {
+            settings,
+            order_tx,
+            engine: EngineStatus::default(),
+            order_rx: Arc::new(Mutex::new(order_rx)),
+        }
+    }
+
+    /// Engine shard `index`, counting the instance-wide engine as 0.
+    pub fn shard(index: usize, order_queue_size: usize) -> Self {
+        Self::new(
+            RegionSettings {
+                name: format!("shard-{index}"),
+                cells: Vec::new(),
+                scoring: None,
+                max_pickup_km: None,
+                stacking_max_detour_km: None,
+            },
+            order_queue_size,
+        )
+    }
+
+    pub fn queue_depth(&self) -> usize {
+        self.order_tx.max_capacity() - self.order_tx.capacity()
+    }
+}
+
+/// Index of the region an order picked up at `point` belongs to, or `None`
+/// if it lies outside every region.
+pub fn region_of(regions: &[DispatchRegion], point: &GeoPoint) -> Option<usize> {
+    regions
+        .iter()
+        .enumerate()
+        .filter_map(|(index, region)| Some((index, region.settings.matching_cell_len(point)?)))
+        .max_by_key(|&(index, len)| (len, std::cmp::Reverse(index)))
+        .map(|(index, _)| index)
+}
+
+/// Which of `shards` engines takes orders picked up in `cell` (a
+/// `cell_id`), by jump consistent hashing: adding a shard moves only about
+/// one cell in `shards + 1`, each to the new shard, so orders from one
+/// area keep meeting in the same engine.
+pub fn shard_of_cell(cell: u64, shards: usize) -> usize {
+    let mut key = cell;
+    let (mut shard, mut next) = (0, 0);
+    while next < shards {
+        shard = next;
+        key = key.wrapping_mul(2_862_933_555_777_941_757).wrapping_add(1);
+        next = ((shard + 1) as f64 * ((1u64 << 31) as f64 / ((key >> 33) + 1) as f64)) as usize;
+    }
+    shard
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{region_of, shard_of_cell, DispatchRegion, RegionSettings};
+    use crate::geo::geohash;
+    use crate::models::courier::GeoPoint;
+
+    fn region(name: &str, cells: &[&str]) -> DispatchRegion {
+        DispatchRegion::new(
+            RegionSettings {
+                name: name.to_string(),
+                cells: cells.iter().map(|cell| cell.to_string()).collect(),
+                scoring: None,
+                max_pickup_km: None,
+                stacking_max_detour_km: None,
+            },
+            4,
+        )
+    }
+
+    #[test]
+    fn the_longest_matching_cell_picks_the_region() {
+        let berlin = GeoPoint {
+            lat: 52.52,
+            lng: 13.405,
+        };
+        let paris = GeoPoint {
+            lat: 48.8566,
+            lng: 2.3522,
+        };
+        let mitte = geohash(&berlin, 5);
+        let regions = [region("germany", &["u3", "u1"]), region("mitte", &[&mitte])];
+
+        assert_eq!(region_of(&regions, &berlin), Some(1));
+        let hamburg = GeoPoint {
+            lat: 53.55,
+            lng: 9.99,
+        };
+        assert_eq!(region_of(&regions, &hamburg), Some(0));
+        assert_eq!(region_of(&regions, &paris), None);
+    }
+
+    #[test]
+    fn a_new_shard_only_takes_cells_over() {
+        let mut per_shard = [0; 4];
+        for cell in 0..4000 {
+            let shard = shard_of_cell(cell, 4);
+            per_shard[shard] += 1;
+            let grown = shard_of_cell(cell, 5);
+            assert!(
+                grown == shard || grown == 4,
+                "{cell} moved {shard} -> {grown}"
+            );
+        }
+        assert!(per_shard.iter().all(|&count| count > 800), "{per_shard:?}");
+        assert_eq!(shard_of_cell(17, 1), 0);
+    }
+}
@@ -0,0 +1,212 @@
+use std::iter;
+
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::geo::haversine_km;
+use crate::models::courier::{Courier, GeoPoint};
+use crate::models::order::{DeliveryOrder, OrderStatus};
+use crate::models::route::{CourierRoute, RouteStop, StopKind};
+use crate::state::AppState;
+
+/// Orders the courier still has to pick up or deliver, oldest first.
+pub fn active_orders(state: &AppState, courier_id: Uuid) -> Vec<DeliveryOrder> {
+    let mut orders: Vec<DeliveryOrder> = state
+        .orders
+        .iter()
+        .filter(|entry| {
+            entry.assigned_courier == Some(courier_id)
+                && matches!(entry.status, OrderStatus::Assigned | OrderStatus::InTransit)
+        })
+        .map(|entry| entry.value().clone())
+        .collect();
+    orders.sort_by_key(|order| order.created_at);
+    orders
+}
+
+/// Plans a route through `orders`: every pickup not yet made, order by
+/// order, then the dropoffs, always heading for the nearest one next.
+/// Orders already `InTransit` only need their dropoff.
+pub fn plan_route(start: &GeoPoint, orders: &[DeliveryOrder]) -> Vec<RouteStop> {
+    let mut stops: Vec<RouteStop> = orders
+        .iter()
+        .filter(|order| order.status != OrderStatus::InTransit)
+        .flat_map(|order| {
+            iter::once(&order.pickup)
+                .chain(&order.extra_pickups)
+                .map(|location| RouteStop {
+                    order_id: order.id,
+                    kind: StopKind::Pickup,
+                    location: location.clone(),
+                })
+        })
+        .collect();
+
+    let mut at = stops.last().map_or(start, |stop| &stop.location).clone();
+    let mut dropoffs: Vec<&DeliveryOrder> = orders.iter().collect();
+    while !dropoffs.is_empty() {
+        let (nearest, _) = dropoffs
+            .iter()
+            .enumerate()
+            .map(|(index, order)| (index, haversine_km(&at, &order.dropoff)))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .expect("dropoffs is not empty");
+        let order = dropoffs.remove(nearest);
+        at = order.dropoff.clone();
+        stops.push(RouteStop {
+            order_id: order.id,
+            kind: StopKind::Dropoff,
+            location: order.dropoff.clone(),
+        });
+    }
+    stops
+}
+
+/// Length of the trip from `start` through `stops` in order.
+pub fn stops_km(start: &GeoPoint, stops: &[RouteStop]) -> f64 {
+    iter::once(start)
+        .chain(stops.iter().map(|stop| &stop.location))
+        .collect::<Vec<_>>()
+        .windows(2)
+        .map(|leg| haversine_km(leg[0], leg[1]))
+        .sum()
+}
+
+/// Distance from `start` along `stops` until `order_id` is dropped off.
+pub fn km_until_dropoff(start: &GeoPoint, stops: &[RouteStop], order_id: Uuid) -> f64 {
+    let end = stops
+        .iter()
+        .position(|stop| stop.order_id == order_id && stop.kind == StopKind::Dropoff)
+        .map_or(stops.len(), |index| index + 1);
+    stops_km(start, &stops[..end])
+}
+
+/// How much longer the courier's route gets with `order` stacked on its
+/// `active` ones.
+pub fn stacking_detour_km(
+    courier: &Courier,
+    active: &[DeliveryOrder],
+    order: &DeliveryOrder,
+) -> f64 {
+    let current = stops_km(&courier.location, &plan_route(&courier.location, active));
+    let mut stacked = active.to_vec();
+    stacked.push(order.clone());
+    stops_km(&courier.location, &plan_route(&courier.location, &stacked)) - current
+}
+
+/// The route a courier is on right now, from where it last reported.
+pub fn courier_route(state: &AppState, courier_id: Uuid) -> Result<CourierRoute, AppError> {
+    let location = state
+        .couriers
+        .get(&courier_id)
+        .map(|courier| courier.location.clone())
+        .ok_or_else(|| AppError::NotFound(format!("courier {} not found", courier_id)))?;
+    let stops = plan_route(&location, &active_orders(state, courier_id));
+    Ok(CourierRoute {
+        courier_id,
+        distance_km: stops_km(&location, &stops),
+        stops,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    use super::{plan_route, stacking_detour_km, stops_km};
+    use crate::models::courier::{Courier, CourierStatus, GeoPoint, OfferHistory, VehicleType};
+    use crate::models::order::{DeliveryOrder, OrderStatus, Priority};
+    use crate::models::route::StopKind;
+
+    fn point(lng: f64) -> GeoPoint {
+        GeoPoint { lat: 52.50, lng }
+    }
+
+    fn courier() -> Courier {
+        Courier {
+            id: Uuid::new_v4(),
+            name: "Max".to_string(),
+            location: point(13.40),
+            capacity: 3.0,
+            current_load: 1.0,
+            status: CourierStatus::Available,
+            break_until: None,
+            rating: 4.5,
+            offers: OfferHistory::default(),
+            last_assigned_at: None,
+            vehicle_type: VehicleType::Bike,
+            updated_at: Utc::now(),
+            last_seen_at: Utc::now(),
+            shifts: Vec::new(),
+            skills: Vec::new(),
+            preferred_zones: Vec::new(),
+            accepts_cod: false,
+            cod_limit: None,
+            equipment: Vec::new(),
+            location_cache: Default::default(),
+        }
+    }
+
+    fn order(status: OrderStatus, pickup: f64, dropoff: f64) -> DeliveryOrder {
+        DeliveryOrder {
+            id: Uuid::new_v4(),
+            pickup: point(pickup),
+            dropoff: point(dropoff),
+            extra_pickups: Vec::new(),
+            priority: Priority::Normal,
+            status,
+            assigned_courier: None,
+            created_at: Utc::now(),
+            size: 1.0,
+            items: Vec::new(),
+            cash_on_delivery: None,
+            required_vehicle: None,
+            required_skills: Vec::new(),
+            handling: Vec::new(),
+            waiting_reason: None,
+            declined_by: Vec::new(),
+            callback_url: None,
+            customer_contact: None,
+            request_id: None,
+        }
+    }
+
+    #[test]
+    fn picks_up_first_then_drops_off_nearest_first() {
+        let courier = courier();
+        let carried = order(OrderStatus::InTransit, 13.40, 13.50);
+        let next = order(OrderStatus::Pending, 13.45, 13.48);
+
+        let stops = plan_route(&courier.location, &[carried.clone(), next.clone()]);
+        let visits: Vec<(StopKind, f64)> = stops
+            .iter()
+            .map(|stop| (stop.kind, stop.location.lng))
+            .collect();
+        assert_eq!(
+            visits,
+            [
+                (StopKind::Pickup, 13.45),
+                (StopKind::Dropoff, 13.48),
+                (StopKind::Dropoff, 13.50)
+            ]
+        );
+        // Straight east all the way: ~6.8 km, the same as the carried order alone.
+        let km = stops_km(&courier.location, &stops);
+        assert!((6.7..6.9).contains(&km), "{km}");
+    }
+
+    #[test]
+    fn orders_going_the_other_way_cost_a_long_detour() {
+        let courier = courier();
+        let carried = [order(OrderStatus::InTransit, 13.40, 13.50)];
+
+        let on_the_way = order(OrderStatus::Pending, 13.45, 13.51);
+        let detour = stacking_detour_km(&courier, &carried, &on_the_way);
+        assert!(detour < 1.0, "{detour}");
+
+        let backwards = order(OrderStatus::Pending, 13.38, 13.30);
+        let detour = stacking_detour_km(&courier, &carried, &backwards);
+        assert!(detour > 10.0, "{detour}");
+    }
+}
@@ -1,44 +1,153 @@
-use crate::geo::haversine_km;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+
+use crate::engine::distance::DistanceCache;
+use crate::engine::earnings::DailyEarnings;
+use crate::geo::{
+    cell_id, geohash, haversine_radians_km, RadianPoint, DISTANCE_CELL_PRECISION,
+    MAX_GEOHASH_PRECISION,
+};
 use crate::models::assignment::ScoreBreakdown;
 use crate::models::courier::Courier;
 use crate::models::order::{DeliveryOrder, Priority};
 
-const DISTANCE_WEIGHT: f64 = 0.40;
-const LOAD_WEIGHT: f64 = 0.30;
-const RATING_WEIGHT: f64 = 0.20;
-const PRIORITY_WEIGHT: f64 = 0.10;
+/// How much each factor counts towards a courier's score. They don't have to
+/// add up to 1; only how they compare matters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScoringWeights {
+    pub distance: f64,
+    pub load: f64,
+    pub rating: f64,
+    pub priority: f64,
+    pub zone: f64,
+    pub reliability: f64,
+    pub idle: f64,
+    /// Off by default: favours couriers earning less than the fleet today.
+    pub earnings: f64,
+}
+
+impl Default for ScoringWeights {
+    fn default() -> Self {
+        Self {
+            distance: 0.40,
+            load: 0.30,
+            rating: 0.20,
+            priority: 0.10,
+            zone: 0.10,
+            reliability: 0.10,
+            idle: 0.10,
+            earnings: 0.0,
+        }
+    }
+}
+
+/// What scoring needs from an order, worked out once per order rather than
+/// once per courier it is scored against.
+pub struct ScoringTarget {
+    pickup: RadianPoint,
+    /// The pickup's `cell_id` at `DISTANCE_CELL_PRECISION`.
+    pickup_cell: u64,
+    /// Finest geohash of the pickup; it is in a zone if it starts with it.
+    pickup_geohash: String,
+    priority_score: f64,
+    /// When the order is scored; idle time is measured up to here.
+    now: DateTime<Utc>,
+    /// What couriers have earned today, against the fleet median.
+    earnings: Arc<DailyEarnings>,
+}
+
+impl ScoringTarget {
+    pub fn new(order: &DeliveryOrder, now: DateTime<Utc>) -> Self {
+        Self {
+            pickup: (&order.pickup).into(),
+            pickup_cell: cell_id(&order.pickup, DISTANCE_CELL_PRECISION),
+            pickup_geohash: geohash(&order.pickup, MAX_GEOHASH_PRECISION),
+            priority_score: priority_score(&order.priority),
+            now,
+            earnings: Arc::default(),
+        }
+    }
+
+    /// Raises the priority score by `boost`, up to 1, as for orders in a
+    /// surging zone.
+    pub fn boost_priority(&mut self, boost: f64) {
+        self.priority_score = (self.priority_score + boost).min(1.0);
+    }
+
+    /// Scores couriers' earnings against `earnings`; until this is called
+    /// every courier counts as having earned nothing yet.
+    pub fn set_earnings(&mut self, earnings: Arc<DailyEarnings>) {
+        self.earnings = earnings;
+    }
+
+    /// How far the courier is from the pickup.
+    pub fn pickup_km(&self, courier: &Courier) -> f64 {
+        haversine_radians_km(&courier.location_radians(), &self.pickup)
+    }
+
+    /// `pickup_km` through `cache`, and whether it was a hit.
+    pub fn cached_pickup_km(&self, courier: &Courier, cache: &DistanceCache) -> (f64, bool) {
+        cache.km(courier.location_cell(), self.pickup_cell, || {
+            self.pickup_km(courier)
+        })
+    }
+}
 
-pub fn compute_score(courier: &Courier, order: &DeliveryOrder) -> (f64, ScoreBreakdown) {
-    let distance_km = haversine_km(&courier.location, &order.pickup);
+pub fn compute_score(
+    courier: &Courier,
+    order: &DeliveryOrder,
+    weights: &ScoringWeights,
+) -> (f64, ScoreBreakdown) {
+    let target = ScoringTarget::new(order, Utc::now());
+    score_against(courier, &target, target.pickup_km(courier), weights)
+}
 
+/// `compute_score` for a target prepared up front and a courier
+/// `distance_km` from the pickup, for scoring one order against many
+/// couriers. Allocates nothing.
+pub fn score_against(
+    courier: &Courier,
+    target: &ScoringTarget,
+    distance_km: f64,
+    weights: &ScoringWeights,
+) -> (f64, ScoreBreakdown) {
     let breakdown = ScoreBreakdown {
         distance_score: distance_score(distance_km),
         load_score: load_score(courier.current_load, courier.capacity),
         rating_score: rating_score(courier.rating),
-        priority_score: priority_score(&order.priority),
+        priority_score: target.priority_score,
+        zone_score: zone_score(courier, target),
+        reliability_score: courier.offers.reliability(),
+        idle_score: idle_score(courier.last_assigned_at, target.now),
+        earnings_score: target.earnings.score(courier.id),
     };
 
-    let score = weighted_score(&breakdown);
+    let score = weighted_score(&breakdown, weights);
     (score, breakdown)
 }
 
-pub fn weighted_score(breakdown: &ScoreBreakdown) -> f64 {
-    (breakdown.distance_score * DISTANCE_WEIGHT)
-        + (breakdown.load_score * LOAD_WEIGHT)
-        + (breakdown.rating_score * RATING_WEIGHT)
-        + (breakdown.priority_score * PRIORITY_WEIGHT)
+pub fn weighted_score(breakdown: &ScoreBreakdown, weights: &ScoringWeights) -> f64 {
+    (breakdown.distance_score * weights.distance)
+        + (breakdown.load_score * weights.load)
+        + (breakdown.rating_score * weights.rating)
+        + (breakdown.priority_score * weights.priority)
+        + (breakdown.zone_score * weights.zone)
+        + (breakdown.reliability_score * weights.reliability)
+        + (breakdown.idle_score * weights.idle)
+        + (breakdown.earnings_score * weights.earnings)
 }
 
 fn distance_score(distance_km: f64) -> f64 {
     1.0 / (1.0 + distance_km.max(0.0))
 }
 
-fn load_score(current_load: u8, capacity: u8) -> f64 {
-    if capacity == 0 {
+fn load_score(current_load: f64, capacity: f64) -> f64 {
+    if capacity <= 0.0 {
         return 0.0;
     }
 
-    let utilization = current_load as f64 / capacity as f64;
+    let utilization = current_load / capacity;
     (1.0 - utilization).clamp(0.0, 1.0)
 }
 
@@ -55,16 +164,52 @@ fn priority_score(priority: &Priority) -> f64 {
     }
 }
 
+fn zone_score(courier: &Courier, target: &ScoringTarget) -> f64 {
+    if courier
+        .preferred_zones
+        .iter()
+        .any(|zone| target.pickup_geohash.starts_with(zone.as_str()))
+    {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+/// Minutes without an assignment after which a courier's idle score tops
+/// out at 1.
+const IDLE_SCORE_FULL_MINUTES: i64 = 30;
+
+/// Grows from 0 right after an assignment to 1 once the courier has gone
+/// `IDLE_SCORE_FULL_MINUTES` without one. A courier never assigned counts as
+/// idle all along.
+fn idle_score(last_assigned_at: Option<DateTime<Utc>>, now: DateTime<Utc>) -> f64 {
+    let Some(last_assigned_at) = last_assigned_at else {
+        return 1.0;
+    };
+    let idle_seconds = (now - last_assigned_at).num_seconds() as f64;
+    (idle_seconds / (IDLE_SCORE_FULL_MINUTES * 60) as f64).clamp(0.0, 1.0)
+}
+
 #[cfg(test)]
 mod tests {
-    use chrono::Utc;
+    use chrono::{Duration, Utc};
     use uuid::Uuid;
 
-    use super::compute_score;
-    use crate::models::courier::{Courier, CourierStatus, GeoPoint};
+    use super::{compute_score, score_against, ScoringTarget, ScoringWeights};
+    use crate::engine::earnings::EarningsLedger;
+    use crate::geo::zone_of;
+    use crate::models::courier::{Courier, CourierStatus, GeoPoint, OfferHistory, VehicleType};
     use crate::models::order::{DeliveryOrder, OrderStatus, Priority};
 
-    fn courier(id_seed: u128, lat: f64, lng: f64, load: u8, capacity: u8, rating: f64) -> Courier {
+    fn courier(
+        id_seed: u128,
+        lat: f64,
+        lng: f64,
+        load: f64,
+        capacity: f64,
+        rating: f64,
+    ) -> Courier {
         Courier {
             id: Uuid::from_u128(id_seed),
             name: "test-courier".to_string(),
@@ -72,8 +217,20 @@ mod tests {
             capacity,
             current_load: load,
             status: CourierStatus::Available,
+            break_until: None,
             rating,
+            offers: OfferHistory::default(),
+            last_assigned_at: None,
+            vehicle_type: VehicleType::Bike,
             updated_at: Utc::now(),
+            last_seen_at: Utc::now(),
+            shifts: Vec::new(),
+            skills: Vec::new(),
+            preferred_zones: Vec::new(),
+            accepts_cod: false,
+            cod_limit: None,
+            equipment: Vec::new(),
+            location_cache: Default::default(),
         }
     }
 
@@ -85,10 +242,22 @@ mod tests {
                 lat: lat + 0.01,
                 lng: lng + 0.01,
             },
+            extra_pickups: Vec::new(),
             priority,
             status: OrderStatus::Pending,
             assigned_courier: None,
             created_at: Utc::now(),
+            size: 1.0,
+            items: Vec::new(),
+            cash_on_delivery: None,
+            required_vehicle: None,
+            required_skills: Vec::new(),
+            handling: Vec::new(),
+            waiting_reason: None,
+            declined_by: Vec::new(),
+            callback_url: None,
+            customer_contact: None,
+            request_id: None,
         }
     }
 
@@ -96,11 +265,11 @@ mod tests {
     fn closer_courier_gets_higher_score_when_other_factors_match() {
         let pickup_order = order(Priority::Normal, 53.5511, 9.9937);
 
-        let near = courier(1, 53.5512, 9.9938, 0, 3, 4.5);
-        let far = courier(2, 53.7, 10.2, 0, 3, 4.5);
+        let near = courier(1, 53.5512, 9.9938, 0.0, 3.0, 4.5);
+        let far = courier(2, 53.7, 10.2, 0.0, 3.0, 4.5);
 
-        let (near_score, _) = compute_score(&near, &pickup_order);
-        let (far_score, _) = compute_score(&far, &pickup_order);
+        let (near_score, _) = compute_score(&near, &pickup_order, &ScoringWeights::default());
+        let (far_score, _) = compute_score(&far, &pickup_order, &ScoringWeights::default());
 
         assert!(near_score > far_score);
     }
@@ -109,25 +278,155 @@ mod tests {
     fn heavily_loaded_courier_is_penalized() {
         let pickup_order = order(Priority::Normal, 53.5511, 9.9937);
 
-        let light_load = courier(1, 53.5512, 9.9938, 0, 3, 4.5);
-        let heavy_load = courier(2, 53.5512, 9.9938, 2, 3, 4.5);
+        let light_load = courier(1, 53.5512, 9.9938, 0.0, 3.0, 4.5);
+        let heavy_load = courier(2, 53.5512, 9.9938, 2.0, 3.0, 4.5);
 
-        let (light_score, _) = compute_score(&light_load, &pickup_order);
-        let (heavy_score, _) = compute_score(&heavy_load, &pickup_order);
+        let (light_score, _) =
+            compute_score(&light_load, &pickup_order, &ScoringWeights::default());
+        let (heavy_score, _) =
+            compute_score(&heavy_load, &pickup_order, &ScoringWeights::default());
 
         assert!(light_score > heavy_score);
     }
 
     #[test]
     fn urgent_priority_increases_priority_component() {
-        let courier = courier(1, 53.5512, 9.9938, 0, 3, 4.5);
+        let courier = courier(1, 53.5512, 9.9938, 0.0, 3.0, 4.5);
 
         let normal_order = order(Priority::Normal, 53.5511, 9.9937);
         let urgent_order = order(Priority::Urgent, 53.5511, 9.9937);
 
-        let (_normal_total, normal_breakdown) = compute_score(&courier, &normal_order);
-        let (_urgent_total, urgent_breakdown) = compute_score(&courier, &urgent_order);
+        let (_normal_total, normal_breakdown) =
+            compute_score(&courier, &normal_order, &ScoringWeights::default());
+        let (_urgent_total, urgent_breakdown) =
+            compute_score(&courier, &urgent_order, &ScoringWeights::default());
 
         assert!(urgent_breakdown.priority_score > normal_breakdown.priority_score);
     }
+
+    #[test]
+    fn weights_decide_between_a_near_and_a_well_rated_courier() {
+        let pickup_order = order(Priority::Normal, 53.5511, 9.9937);
+
+        let near = courier(1, 53.5512, 9.9938, 0.0, 3.0, 3.0);
+        let rated = courier(2, 53.6, 10.05, 0.0, 3.0, 5.0);
+        let by_rating = ScoringWeights {
+            distance: 0.05,
+            load: 0.0,
+            rating: 0.95,
+            priority: 0.0,
+            zone: 0.0,
+            reliability: 0.0,
+            idle: 0.0,
+            earnings: 0.0,
+        };
+
+        let (near_score, _) = compute_score(&near, &pickup_order, &ScoringWeights::default());
+        let (rated_score, _) = compute_score(&rated, &pickup_order, &ScoringWeights::default());
+        assert!(near_score > rated_score);
+
+        let (near_score, _) = compute_score(&near, &pickup_order, &by_rating);
+        let (rated_score, _) = compute_score(&rated, &pickup_order, &by_rating);
+        assert!(rated_score > near_score);
+    }
+
+    #[test]
+    fn couriers_score_higher_for_pickups_in_their_preferred_zones() {
+        let pickup_order = order(Priority::Normal, 53.5511, 9.9937);
+
+        let mut local = courier(1, 53.56, 10.0, 0.0, 3.0, 4.5);
+        local.preferred_zones = vec![zone_of(&pickup_order.pickup)];
+        let mut elsewhere = courier(2, 53.56, 10.0, 0.0, 3.0, 4.5);
+        elsewhere.preferred_zones = vec!["u33d".to_string()];
+
+        let (local_score, local_breakdown) =
+            compute_score(&local, &pickup_order, &ScoringWeights::default());
+        let (elsewhere_score, elsewhere_breakdown) =
+            compute_score(&elsewhere, &pickup_order, &ScoringWeights::default());
+
+        assert_eq!(local_breakdown.zone_score, 1.0);
+        assert_eq!(elsewhere_breakdown.zone_score, 0.0);
+        assert!(local_score > elsewhere_score);
+    }
+
+    #[test]
+    fn declines_lower_the_reliability_score_but_not_the_rating() {
+        let pickup_order = order(Priority::Normal, 53.5511, 9.9937);
+
+        let steady = courier(1, 53.5512, 9.9938, 0.0, 3.0, 4.5);
+        let mut flaky = courier(2, 53.5512, 9.9938, 0.0, 3.0, 4.5);
+        flaky.offers = OfferHistory {
+            accepted: 3,
+            declined: 2,
+            timed_out: 1,
+        };
+
+        let (steady_score, steady_breakdown) =
+            compute_score(&steady, &pickup_order, &ScoringWeights::default());
+        let (flaky_score, flaky_breakdown) =
+            compute_score(&flaky, &pickup_order, &ScoringWeights::default());
+
+        assert_eq!(steady_breakdown.reliability_score, 1.0);
+        assert!((flaky_breakdown.reliability_score - 6.0 / 9.0).abs() < 1e-9);
+        assert_eq!(flaky_breakdown.rating_score, steady_breakdown.rating_score);
+        assert!(steady_score > flaky_score);
+    }
+
+    #[test]
+    fn couriers_left_idle_score_higher_than_those_just_assigned() {
+        let pickup_order = order(Priority::Normal, 53.5511, 9.9937);
+        let now = Utc::now();
+        let target = ScoringTarget::new(&pickup_order, now);
+
+        let mut busy = courier(1, 53.5512, 9.9938, 0.0, 3.0, 4.5);
+        busy.last_assigned_at = Some(now - Duration::minutes(3));
+        let mut waiting = courier(2, 53.5512, 9.9938, 0.0, 3.0, 4.5);
+        waiting.last_assigned_at = Some(now - Duration::hours(2));
+        let new = courier(3, 53.5512, 9.9938, 0.0, 3.0, 4.5);
+
+        let score = |courier: &Courier| {
+            score_against(
+                courier,
+                &target,
+                target.pickup_km(courier),
+                &ScoringWeights::default(),
+            )
+        };
+        let (busy_score, busy_breakdown) = score(&busy);
+        let (waiting_score, waiting_breakdown) = score(&waiting);
+        let (_, new_breakdown) = score(&new);
+
+        assert!((busy_breakdown.idle_score - 0.1).abs() < 1e-9);
+        assert_eq!(waiting_breakdown.idle_score, 1.0);
+        assert_eq!(new_breakdown.idle_score, 1.0);
+        assert!(waiting_score > busy_score);
+    }
+
+    #[test]
+    fn earnings_weight_favours_couriers_earning_below_the_median() {
+        let pickup_order = order(Priority::Normal, 53.5511, 9.9937);
+        let now = Utc::now();
+        let ledger = EarningsLedger::default();
+        let rich = courier(1, 53.5512, 9.9938, 0.0, 3.0, 4.5);
+        let poor = courier(2, 53.5512, 9.9938, 0.0, 3.0, 4.5);
+        ledger.record(rich.id, 90.0, now);
+        ledger.record(poor.id, 10.0, now);
+        ledger.record(Uuid::from_u128(3), 30.0, now);
+
+        let mut target = ScoringTarget::new(&pickup_order, now);
+        target.set_earnings(ledger.today(now));
+        let weights = ScoringWeights {
+            earnings: 0.2,
+            ..ScoringWeights::default()
+        };
+        let score = |courier: &Courier| {
+            score_against(courier, &target, target.pickup_km(courier), &weights)
+        };
+        let (rich_score, rich_breakdown) = score(&rich);
+        let (poor_score, poor_breakdown) = score(&poor);
+
+        assert_eq!(rich_breakdown.earnings_score, 0.25);
+        assert_eq!(poor_breakdown.earnings_score, 0.75);
+        assert!(poor_score > rich_score);
+    }
 }
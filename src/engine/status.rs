@@ -0,0 +1,230 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// With orders waiting, the engine takes one at least once per
+/// `ENGINE_REQUEUE_DELAY_MS`, which it sleeps through before requeueing an
+/// order no courier could take. Going this much longer than that without
+/// taking any means it is stuck.
+pub const STALL_AFTER: Duration = Duration::from_secs(30);
+
+/// Share of `ORDER_QUEUE_SIZE` in use at which the instance reports itself
+/// not ready, leaving headroom for orders already on their way in.
+pub const SATURATED_QUEUE_PERCENT: usize = 90;
+
+/// Liveness and progress counters the assignment engine keeps about itself.
+#[derive(Debug, Default)]
+pub struct EngineStatus {
+    running: AtomicBool,
+    standby: AtomicBool,
+    draining: AtomicBool,
+    processing: AtomicBool,
+    last_dequeue: Mutex<Option<DateTime<Utc>>>,
+    last_assignment: Mutex<Option<DateTime<Utc>>>,
+    assigned: AtomicU64,
+    requeued: AtomicU64,
+    failed: AtomicU64,
+    restarts: AtomicU64,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct EngineHealth {
+    /// `ok`, `stalled` (orders waiting but none taken for 30 s plus the
+    /// requeue delay), `standby`
+    /// (another instance holds the leader lease) or `stopped`.
+    pub status: &'static str,
+    pub running: bool,
+    pub standby: bool,
+    /// Shutting down: new orders are refused while queued ones are assigned.
+    pub draining: bool,
+    pub queue_depth: usize,
+    pub last_dequeue_at: Option<DateTime<Utc>>,
+    pub last_assignment_at: Option<DateTime<Utc>>,
+    pub assigned_total: u64,
+    /// Orders put back because no courier could take them.
+    pub requeued_total: u64,
+    pub failed_total: u64,
+    /// Times the supervisor restarted the engine after it panicked or exited.
+    pub restarts_total: u64,
+}
+
+/// Whether the instance should receive traffic; see `/ready`.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Readiness {
+    pub ready: bool,
+    /// Why it is not: any of `engine_stopped`, `engine_stalled`,
+    /// `engine_standby`, `draining` and `queue_saturated`. Empty when ready.
+    pub reasons: Vec<&'static str>,
+    pub queue_depth: usize,
+    pub queue_capacity: usize,
+}
+
+impl EngineStatus {
+    /// Marks the engine running until the returned guard is dropped, which
+    /// also happens if the engine task panics.
+    pub fn start(&self) -> RunningGuard<'_> {
+        self.running.store(true, Ordering::Relaxed);
+        RunningGuard(self)
+    }
+
+    /// Whether the engine waits for this instance to lead before taking
+    /// orders; see [`crate::engine::leadership::Leadership`].
+    pub fn set_standby(&self, standby: bool) {
+        self.standby.store(standby, Ordering::Relaxed);
+    }
+
+    /// Refuses new orders from here on; see `crate::shutdown::drain`.
+    pub fn begin_draining(&self) {
+        self.draining.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::Relaxed)
+    }
+
+    /// True once nothing is queued and the engine is not between taking an
+    /// order and either assigning or requeueing it.
+    pub fn is_idle(&self, queue_depth: usize) -> bool {
+        queue_depth == 0 && !self.processing.load(Ordering::Relaxed)
+    }
+
+    pub fn record_dequeue(&self, now: DateTime<Utc>) {
+        self.processing.store(true, Ordering::Relaxed);
+        *self.last_dequeue.lock().unwrap_or_else(|e| e.into_inner()) = Some(now);
+    }
+
+    /// The order taken by the last `record_dequeue` is done with.
+    pub fn record_processed(&self) {
+        self.processing.store(false, Ordering::Relaxed);
+    }
+
+    pub fn record_assignment(&self, now: DateTime<Utc>) {
+        self.assigned.fetch_add(1, Ordering::Relaxed);
+        *self
+            .last_assignment
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = Some(now);
+    }
+
+    pub fn record_requeue(&self) {
+        self.requeued.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_failure(&self) {
+        self.failed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The engine went down; the order it held, if any, went with it.
+    pub fn record_restart(&self) {
+        self.restarts.fetch_add(1, Ordering::Relaxed);
+        self.processing.store(false, Ordering::Relaxed);
+    }
+
+    /// How the engine is doing as of `now`, while it waits `requeue_delay`
+    /// before each requeue.
+    pub fn health(
+        &self,
+        queue_depth: usize,
+        now: DateTime<Utc>,
+        requeue_delay: Duration,
+    ) -> EngineHealth {
+        let running = self.running.load(Ordering::Relaxed);
+        let standby = self.standby.load(Ordering::Relaxed);
+        let last_dequeue_at = *self.last_dequeue.lock().unwrap_or_else(|e| e.into_inner());
+        let stall_after = STALL_AFTER.saturating_add(requeue_delay);
+        let stalled = !standby
+            && queue_depth > 0
+            && last_dequeue_at
+                .is_some_and(|at| (now - at).to_std().unwrap_or_default() > stall_after);
+
+        EngineHealth {
+            status: match (running, standby, stalled) {
+                (false, _, _) => "stopped",
+                (true, true, _) => "standby",
+                (true, false, true) => "stalled",
+                (true, false, false) => "ok",
+            },
+            running,
+            standby,
+            draining: self.is_draining(),
+            queue_depth,
+            last_dequeue_at,
+            last_assignment_at: *self
+                .last_assignment
+                .lock()
+                .unwrap_or_else(|e| e.into_inner()),
+            assigned_total: self.assigned.load(Ordering::Relaxed),
+            requeued_total: self.requeued.load(Ordering::Relaxed),
+            failed_total: self.failed.load(Ordering::Relaxed),
+            restarts_total: self.restarts.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Ready while the engine runs and keeps up, the instance leads and is
+    /// not shutting down, and the queue has room below
+    /// `SATURATED_QUEUE_PERCENT`.
+    pub fn readiness(
+        &self,
+        queue_depth: usize,
+        queue_capacity: usize,
+        now: DateTime<Utc>,
+        requeue_delay: Duration,
+    ) -> Readiness {
+        let health = self.health(queue_depth, now, requeue_delay);
+        let mut reasons = Vec::new();
+        match health.status {
+            "stopped" => reasons.push("engine_stopped"),
+            "stalled" => reasons.push("engine_stalled"),
+            "standby" => reasons.push("engine_standby"),
+            _ => {}
+        }
+        if health.draining {
+            reasons.push("draining");
+        }
+        if queue_depth * 100 >= queue_capacity * SATURATED_QUEUE_PERCENT {
+            reasons.push("queue_saturated");
+        }
+
+        Readiness {
+            ready: reasons.is_empty(),
+            reasons,
+            queue_depth,
+            queue_capacity,
+        }
+    }
+}
+
+pub struct RunningGuard<'a>(&'a EngineStatus);
+
+impl Drop for RunningGuard<'_> {
+    fn drop(&mut self) {
+        self.0.running.store(false, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::{TimeDelta, Utc};
+
+    use super::EngineStatus;
+
+    #[test]
+    fn stall_threshold_allows_for_the_requeue_delay() {
+        let status = EngineStatus::default();
+        let _running = status.start();
+        let dequeued = Utc::now();
+        status.record_dequeue(dequeued);
+        let now = dequeued + TimeDelta::seconds(31);
+
+        let health = |requeue_delay| status.health(1, now, requeue_delay).status;
+        assert_eq!(health(Duration::from_millis(250)), "stalled");
+        assert_eq!(health(Duration::from_secs(5)), "ok");
+        assert_eq!(status.health(0, now, Duration::ZERO).status, "ok");
+    }
+}
@@ -0,0 +1,180 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::{mpsc, Mutex, OwnedMutexGuard};
+use tracing::{error, info, warn};
+
+use crate::engine::assignment::{assign_queued_orders, assign_region_orders};
+use crate::engine::queue::QueuedOrder;
+use crate::state::AppState;
+
+/// Wait before the first restart; doubled for each crash that follows.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// An engine that stayed up this long restarts with `INITIAL_BACKOFF` again.
+const STABLE_AFTER: Duration = Duration::from_secs(60);
+
+type QueueGuard = OwnedMutexGuard<mpsc::Receiver<QueuedOrder>>;
+
+/// Runs the assignment engine, and one per region, and restarts each with
+/// backoff whenever it panics or returns while orders can still arrive.
+/// Each restart is counted in `engine_restarts_total` and `/health/engine`;
+/// while an engine is down `/ready` reports `engine_stopped`. Returns only
+/// once the queue is closed.
+pub async fn supervise_assignment_engine(
+    state: Arc<AppState>,
+    order_rx: mpsc::Receiver<QueuedOrder>,
+) {
+    for (index, region) in state.regions.iter().enumerate() {
+        let engine = move |state, mut order_rx: QueueGuard| async move {
+            assign_region_orders(state, Some(index), &mut order_rx).await;
+        };
+        tokio::spawn(supervise(
+            state.clone(),
+            Some(index),
+            region.order_rx.clone(),
+            engine,
+        ));
+    }
+
+    supervise(
+        state,
+        None,
+        Arc::new(Mutex::new(order_rx)),
+        |state, mut order_rx| async move {
+            assign_queued_orders(state, &mut order_rx).await;
+        },
+    )
+    .await;
+}
+
+/// Keeps the engine of `region` running. It holds the lock on `order_rx`
+/// while it runs; a panic releases it with the queue and the orders in it
+/// intact.
+async fn supervise<F, Fut>(
+    state: Arc<AppState>,
+    region: Option<usize>,
+    order_rx: Arc<Mutex<mpsc::Receiver<QueuedOrder>>>,
+    engine: F,
+) where
+    F: Fn(Arc<AppState>, QueueGuard) -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let mut backoff = INITIAL_BACKOFF;
+
+    loop {
+        let started = state.clock.now();
+        let queue = order_rx.clone().lock_owned().await;
+        let reason = match tokio::spawn(engine(state.clone(), queue)).await {
+            Ok(()) if order_rx.lock().await.is_closed() => {
+                info!("assignment engine supervisor stopped: queue closed");
+                return;
+            }
+            Ok(()) => "exited",
+            Err(err) if err.is_panic() => "panic",
+            Err(err) => {
+                error!(error = %err, "assignment engine task cancelled");
+                return;
+            }
+        };
+
+        if (state.clock.now() - started).to_std().unwrap_or_default() >= STABLE_AFTER {
+            backoff = INITIAL_BACKOFF;
+        }
+        state.engine_of(region).record_restart();
+        state.metrics.record_engine_restart(reason);
+        warn!(
+            reason,
+            backoff_ms = backoff.as_millis() as u64,
+            "assignment engine went down, restarting"
+        );
+        state.clock.sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use chrono::{Duration, Utc};
+    use tokio::sync::Mutex;
+
+    use super::supervise;
+    use crate::clock::ManualClock;
+    use crate::engine::assignment::assign_queued_orders;
+    use crate::state::AppState;
+
+    /// Lets the spawned tasks run until `done` holds.
+    async fn run_until(done: impl Fn() -> bool) {
+        for _ in 0..1000 {
+            if done() {
+                return;
+            }
+            tokio::task::yield_now().await;
+        }
+        panic!("tasks never got there");
+    }
+
+    #[tokio::test]
+    async fn restarts_a_panicked_engine_after_a_backoff() {
+        let clock = ManualClock::new(Utc::now());
+        let (mut state, rx) = AppState::new(16, 16);
+        state.clock = Arc::new(clock.clone());
+        let state = Arc::new(state);
+        let attempts = Arc::new(AtomicUsize::new(0));
+
+        let engine_attempts = attempts.clone();
+        let rx = Arc::new(Mutex::new(rx));
+        tokio::spawn(supervise(
+            state.clone(),
+            None,
+            rx,
+            move |state, mut order_rx| {
+                let attempt = engine_attempts.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    if attempt == 0 {
+                        let _running = state.engine.start();
+                        panic!("engine bug");
+                    }
+                    assign_queued_orders(state, &mut order_rx).await;
+                }
+            },
+        ));
+
+        let restarts = || {
+            state
+                .engine
+                .health(
+                    state.queue_depth(),
+                    state.clock.now(),
+                    state.tunables().requeue_delay,
+                )
+                .restarts_total
+        };
+        run_until(|| restarts() == 1).await;
+        assert!(!state.readiness().ready);
+
+        // Still backing off until the clock passes the first 100 ms.
+        clock.advance(Duration::milliseconds(99));
+        for _ in 0..100 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+        clock.advance(Duration::milliseconds(1));
+        run_until(|| state.readiness().ready).await;
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+        assert_eq!(restarts(), 1);
+        assert_eq!(
+            state
+                .metrics
+                .engine_restarts_total
+                .with_label_values(&["panic"])
+                .get(),
+            1
+        );
+    }
+}
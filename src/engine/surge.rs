@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use tracing::{info, warn};
+
+use crate::geo::zone_of;
+use crate::models::courier::{CourierStatus, GeoPoint};
+use crate::models::event::DispatchEvent;
+use crate::models::order::OrderStatus;
+use crate::models::surge::SurgeEvent;
+use crate::state::AppState;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SurgeSettings {
+    /// Pending orders per available courier above which a zone surges; 0
+    /// turns detection off.
+    pub threshold: f64,
+    /// Added to the priority score of orders picked up in a surging zone,
+    /// up to its maximum of 1.
+    pub priority_boost: f64,
+}
+
+impl Default for SurgeSettings {
+    fn default() -> Self {
+        Self {
+            threshold: 3.0,
+            priority_boost: 0.3,
+        }
+    }
+}
+
+/// Zones where orders wait on too few couriers, by pickup zone. Orders
+/// picked up in one are scored with a higher priority until it calms down,
+/// so a busy area is not left behind the rest of the city.
+#[derive(Debug, Default)]
+pub struct SurgeMonitor {
+    pub settings: SurgeSettings,
+    zones: RwLock<HashMap<String, SurgeEvent>>,
+}
+
+impl SurgeMonitor {
+    pub fn new(settings: SurgeSettings) -> Self {
+        Self {
+            settings,
+            zones: RwLock::default(),
+        }
+    }
+
+    /// What to add to the priority score of an order picked up at `pickup`.
+    pub fn priority_boost(&self, pickup: &GeoPoint) -> f64 {
+        let zones = self.zones.read().unwrap_or_else(|e| e.into_inner());
+        if !zones.is_empty() && zones.contains_key(&zone_of(pickup)) {
+            self.settings.priority_boost
+        } else {
+            0.0
+        }
+    }
+
+    /// The zones in surge as of the last check, busiest first.
+    pub fn surging(&self) -> Vec<SurgeEvent> {
+        let mut zones: Vec<SurgeEvent> = self
+            .zones
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+            .cloned()
+            .collect();
+        zones.sort_by(|a, b| {
+            b.pending_orders
+                .cmp(&a.pending_orders)
+                .then_with(|| a.zone.cmp(&b.zone))
+        });
+        zones
+    }
+}
+
+/// Counts pending orders and available couriers per zone, updates which
+/// zones are in surge and announces those that went into or out of it.
+/// Returns those announcements.
+pub fn detect_surges(state: &AppState) -> Vec<SurgeEvent> {
+    let monitor = &state.surge;
+    let mut counts: HashMap<String, (u64, u64)> = HashMap::new();
+    {
+        let _snapshot = state.snapshots.read();
+        for order in state.orders.iter() {
+            if order.status == OrderStatus::Pending {
+                counts.entry(zone_of(&order.pickup)).or_default().0 += 1;
+            }
+        }
+        for courier in state.couriers.iter() {
+            if courier.status == CourierStatus::Available && !courier.is_full() {
+                counts.entry(zone_of(&courier.location)).or_default().1 += 1;
+            }
+        }
+    }
+
+    let now = state.clock.now();
+    let threshold = monitor.settings.threshold;
+    let event = |zone: &str, surging: bool| {
+        let (pending_orders, available_couriers) = counts.get(zone).copied().unwrap_or_default();
+        SurgeEvent {
+            zone: zone.to_string(),
+            surging,
+            pending_orders,
+            available_couriers,
+            at: now,
+        }
+    };
+    let surging: HashMap<String, SurgeEvent> = counts
+        .iter()
+        .filter(|&(_, &(pending, available))| pending as f64 > threshold * available as f64)
+        .map(|(zone, _)| (zone.clone(), event(zone, true)))
+        .collect();
+
+    let mut zones = monitor.zones.write().unwrap_or_else(|e| e.into_inner());
+    let mut changes: Vec<SurgeEvent> = surging
+        .values()
+        .filter(|surge| !zones.contains_key(&surge.zone))
+        .cloned()
+        .collect();
+    changes.extend(
+        zones
+            .keys()
+            .filter(|zone| !surging.contains_key(*zone))
+            .map(|zone| event(zone, false)),
+    );
+    *zones = surging;
+    state.metrics.surge_zones.set(zones.len() as i64);
+    drop(zones);
+
+    for change in &changes {
+        if change.surging {
+            state.metrics.surges_total.inc();
+            warn!(
+                zone = %change.zone,
+                pending_orders = change.pending_orders,
+                available_couriers = change.available_couriers,
+                "zone in surge; boosting order priority"
+            );
+        } else {
+            info!(zone = %change.zone, "surge over");
+        }
+        state.events.publish(DispatchEvent::Surge(change.clone()));
+    }
+    changes
+}
+
+/// Checks for surges every `interval` while this instance leads; followers
+/// run no engine for the boost to matter to.
+pub async fn run_surge_detector(state: Arc<AppState>, interval: Duration) {
+    let mut ticks = tokio::time::interval(interval);
+    loop {
+        ticks.tick().await;
+        if state.leadership.is_leader() {
+            detect_surges(&state);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    use super::detect_surges;
+    use crate::models::courier::{Courier, CourierStatus, GeoPoint, OfferHistory, VehicleType};
+    use crate::models::order::{DeliveryOrder, OrderStatus, Priority};
+    use crate::state::AppState;
+
+    fn order(pickup: &GeoPoint) -> DeliveryOrder {
+        DeliveryOrder {
+            id: Uuid::now_v7(),
+            pickup: pickup.clone(),
+            dropoff: pickup.clone(),
+            extra_pickups: Vec::new(),
+            priority: Priority::Normal,
+            status: OrderStatus::Pending,
+            assigned_courier: None,
+            created_at: Utc::now(),
+            size: 1.0,
+            items: Vec::new(),
+            cash_on_delivery: None,
+            required_vehicle: None,
+            required_skills: Vec::new(),
+            handling: Vec::new(),
+            waiting_reason: None,
+            declined_by: Vec::new(),
+            callback_url: None,
+            customer_contact: None,
+            request_id: None,
+        }
+    }
+
+    #[test]
+    fn zones_surge_while_orders_outnumber_couriers() {
+        let (state, _rx) = AppState::new(16, 16);
+        let mitte = GeoPoint {
+            lat: 52.52,
+            lng: 13.405,
+        };
+        let courier = Courier {
+            id: Uuid::now_v7(),
+            name: "Bo".to_string(),
+            location: mitte.clone(),
+            capacity: 2.0,
+            current_load: 0.0,
+            status: CourierStatus::Available,
+            break_until: None,
+            rating: 4.5,
+            offers: OfferHistory::default(),
+            last_assigned_at: None,
+            vehicle_type: VehicleType::Bike,
+            updated_at: Utc::now(),
+            last_seen_at: Utc::now(),
+            shifts: Vec::new(),
+            skills: Vec::new(),
+            preferred_zones: Vec::new(),
+            accepts_cod: false,
+            cod_limit: None,
+            equipment: Vec::new(),
+            location_cache: Default::default(),
+        };
+        state.couriers.insert(courier.id, Arc::new(courier));
+        let orders: Vec<DeliveryOrder> = (0..4).map(|_| order(&mitte)).collect();
+        for order in &orders[..3] {
+            state.orders.insert(order.id, order.clone());
+        }
+        assert!(detect_surges(&state).is_empty());
+        assert_eq!(state.surge.priority_boost(&mitte), 0.0);
+
+        state.orders.insert(orders[3].id, orders[3].clone());
+        let started = detect_surges(&state);
+        assert_eq!(started.len(), 1);
+        assert!(started[0].surging);
+        assert_eq!(started[0].pending_orders, 4);
+        assert_eq!(state.surge.priority_boost(&mitte), 0.3);
+        assert!(detect_surges(&state).is_empty());
+
+        state.orders.get_mut(&orders[0].id).unwrap().status = OrderStatus::Assigned;
+        let ended = detect_surges(&state);
+        assert_eq!(ended.len(), 1);
+        assert!(!ended[0].surging);
+        assert!(state.surge.surging().is_empty());
+    }
+}
@@ -0,0 +1,58 @@
+use std::collections::BTreeSet;
+use std::sync::RwLock;
+
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+/// Assignments ordered by when they were made, so a time range is looked up
+/// without walking every assignment. An assignment's time never changes,
+/// so entries are only ever added.
+#[derive(Default)]
+pub struct AssignmentTimeline {
+    entries: RwLock<BTreeSet<(DateTime<Utc>, Uuid)>>,
+}
+
+impl AssignmentTimeline {
+    pub fn insert(&self, assignment_id: Uuid, assigned_at: DateTime<Utc>) {
+        self.entries
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert((assigned_at, assignment_id));
+    }
+
+    /// Assignments made at or after `from` and before `to`, oldest first;
+    /// either end may be left open.
+    pub fn between(&self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> Vec<Uuid> {
+        let entries = self.entries.read().unwrap_or_else(|e| e.into_inner());
+        let from = from.unwrap_or(DateTime::<Utc>::MIN_UTC);
+        entries
+            .range((from, Uuid::nil())..)
+            .take_while(|(at, _)| to.is_none_or(|to| *at < to))
+            .map(|&(_, id)| id)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use uuid::Uuid;
+
+    use super::AssignmentTimeline;
+
+    #[test]
+    fn ranges_include_the_start_and_exclude_the_end() {
+        let timeline = AssignmentTimeline::default();
+        let six = Utc::now() - Duration::hours(2);
+        let seven = six + Duration::hours(1);
+        let (early, on_the_hour, late) = (Uuid::now_v7(), Uuid::now_v7(), Uuid::now_v7());
+        timeline.insert(late, seven + Duration::minutes(30));
+        timeline.insert(early, six - Duration::minutes(1));
+        timeline.insert(on_the_hour, six);
+
+        assert_eq!(timeline.between(Some(six), Some(seven)), vec![on_the_hour]);
+        assert_eq!(timeline.between(None, Some(six)), vec![early]);
+        assert_eq!(timeline.between(Some(six), None), vec![on_the_hour, late]);
+        assert_eq!(timeline.between(None, None).len(), 3);
+    }
+}
@@ -0,0 +1,157 @@
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::geo::haversine_km;
+use crate::models::event::DispatchEvent;
+use crate::models::order::{OrderStatus, OrderTracking};
+use crate::state::AppState;
+
+/// Assumed door-to-door courier speed for ETAs, in km/h.
+pub const AVERAGE_COURIER_SPEED_KMH: f64 = 20.0;
+
+/// Time to cover `km` at the assumed courier speed.
+pub fn travel_seconds(km: f64) -> f64 {
+    km / AVERAGE_COURIER_SPEED_KMH * 3600.0
+}
+
+/// Where an order's courier is and how long until the dropoff: via every
+/// pickup while `Assigned`, straight to the dropoff once `InTransit`, which
+/// means all pickups are done.
+pub fn order_tracking(state: &AppState, order_id: Uuid) -> Result<OrderTracking, AppError> {
+    let order = state
+        .orders
+        .get(&order_id)
+        .map(|entry| entry.value().clone())
+        .ok_or_else(|| AppError::NotFound(format!("order {} not found", order_id)))?;
+
+    let courier_location = order
+        .assigned_courier
+        .and_then(|id| state.couriers.get(&id).map(|c| c.location.clone()));
+
+    let remaining_km = match (&order.status, &courier_location) {
+        (OrderStatus::Assigned, Some(location)) => {
+            Some(haversine_km(location, &order.pickup) + order.route_km())
+        }
+        (OrderStatus::InTransit, Some(location)) => Some(haversine_km(location, &order.dropoff)),
+        (OrderStatus::Delivered, _) => Some(0.0),
+        _ => None,
+    };
+
+    Ok(OrderTracking {
+        order_id,
+        status: order.status,
+        courier_id: order.assigned_courier,
+        courier_location,
+        eta_seconds: remaining_km.map(|km| travel_seconds(km).round() as u64),
+        updated_at: state.clock.now(),
+    })
+}
+
+/// Whether `event` can change the tracking view of `order_id`.
+pub fn affects_tracking(state: &AppState, order_id: Uuid, event: &DispatchEvent) -> bool {
+    match event {
+        DispatchEvent::Order(order) => order.order_id == order_id,
+        DispatchEvent::Assignment(assignment) => assignment.order_id == order_id,
+        DispatchEvent::CourierLocation(update) => state
+            .orders
+            .get(&order_id)
+            .is_some_and(|order| order.assigned_courier == Some(update.courier_id)),
+        DispatchEvent::Report(_) | DispatchEvent::Surge(_) | DispatchEvent::Change(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    use super::order_tracking;
+    use crate::models::courier::{Courier, CourierStatus, GeoPoint, OfferHistory, VehicleType};
+    use crate::models::order::{DeliveryOrder, OrderStatus, Priority};
+    use crate::state::AppState;
+
+    #[test]
+    fn eta_covers_pickup_leg_until_picked_up() {
+        let (state, _rx) = AppState::new(16, 16);
+        let courier = Courier {
+            id: Uuid::new_v4(),
+            name: "Max".to_string(),
+            location: GeoPoint {
+                lat: 52.52,
+                lng: 13.40,
+            },
+            capacity: 3.0,
+            current_load: 1.0,
+            status: CourierStatus::Available,
+            break_until: None,
+            rating: 4.5,
+            offers: OfferHistory::default(),
+            last_assigned_at: None,
+            vehicle_type: VehicleType::Bike,
+            updated_at: Utc::now(),
+            last_seen_at: Utc::now(),
+            shifts: Vec::new(),
+            skills: Vec::new(),
+            preferred_zones: Vec::new(),
+            accepts_cod: false,
+            cod_limit: None,
+            equipment: Vec::new(),
+            location_cache: Default::default(),
+        };
+        let order = DeliveryOrder {
+            id: Uuid::new_v4(),
+            pickup: GeoPoint {
+                lat: 52.52,
+                lng: 13.40,
+            },
+            dropoff: GeoPoint {
+                lat: 52.61,
+                lng: 13.40,
+            },
+            extra_pickups: Vec::new(),
+            priority: Priority::Normal,
+            status: OrderStatus::Assigned,
+            assigned_courier: Some(courier.id),
+            created_at: Utc::now(),
+            size: 1.0,
+            items: Vec::new(),
+            cash_on_delivery: None,
+            required_vehicle: None,
+            required_skills: Vec::new(),
+            handling: Vec::new(),
+            waiting_reason: None,
+            declined_by: Vec::new(),
+            callback_url: None,
+            customer_contact: None,
+            request_id: None,
+        };
+        state.couriers.insert(courier.id, Arc::new(courier));
+        state.orders.insert(order.id, order.clone());
+
+        // ~10 km at 20 km/h
+        let eta = order_tracking(&state, order.id)
+            .unwrap()
+            .eta_seconds
+            .unwrap();
+        assert!((1780..=1820).contains(&eta), "{eta}");
+
+        // A second pickup ~10 km south adds that leg and ~10 km back.
+        state.orders.get_mut(&order.id).unwrap().extra_pickups = vec![GeoPoint {
+            lat: 52.43,
+            lng: 13.40,
+        }];
+        let eta = order_tracking(&state, order.id)
+            .unwrap()
+            .eta_seconds
+            .unwrap();
+        assert!((5360..=5440).contains(&eta), "{eta}");
+
+        state.orders.get_mut(&order.id).unwrap().status = OrderStatus::Delivered;
+        assert_eq!(
+            order_tracking(&state, order.id).unwrap().eta_seconds,
+            Some(0)
+        );
+    }
+}
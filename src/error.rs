@@ -12,12 +12,24 @@ pub enum AppError {
     #[error("bad request: {0}")]
     BadRequest(String),
 
+    #[error("unauthorized: {0}")]
+    Unauthorized(String),
+
+    #[error("forbidden: {0}")]
+    Forbidden(String),
+
     #[error("conflict: {0}")]
     Conflict(String),
 
+    #[error("too many requests: {0}")]
+    TooManyRequests(String),
+
     #[error("no couriers available")]
     NoAvailableCouriers,
 
+    #[error("unavailable: {0}")]
+    Unavailable(String),
+
     #[error("internal error: {0}")]
     Internal(String),
 }
@@ -27,11 +39,15 @@ impl IntoResponse for AppError {
         let (status, message) = match &self {
             AppError::NotFound(msg) => (StatusCode::NOT_FOUND, msg.clone()),
             AppError::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg.clone()),
+            AppError::Unauthorized(msg) => (StatusCode::UNAUTHORIZED, msg.clone()),
+            AppError::Forbidden(msg) => (StatusCode::FORBIDDEN, msg.clone()),
             AppError::Conflict(msg) => (StatusCode::CONFLICT, msg.clone()),
+            AppError::TooManyRequests(msg) => (StatusCode::TOO_MANY_REQUESTS, msg.clone()),
             AppError::NoAvailableCouriers => (
                 StatusCode::SERVICE_UNAVAILABLE,
                 "no couriers available".to_string(),
             ),
+            AppError::Unavailable(msg) => (StatusCode::SERVICE_UNAVAILABLE, msg.clone()),
             AppError::Internal(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg.clone()),
         };
 
@@ -42,3 +58,19 @@ impl IntoResponse for AppError {
         (status, body).into_response()
     }
 }
+
+impl From<AppError> for tonic::Status {
+    fn from(err: AppError) -> Self {
+        match err {
+            AppError::NotFound(msg) => tonic::Status::not_found(msg),
+            AppError::BadRequest(msg) => tonic::Status::invalid_argument(msg),
+            AppError::Unauthorized(msg) => tonic::Status::unauthenticated(msg),
+            AppError::Forbidden(msg) => tonic::Status::permission_denied(msg),
+            AppError::Conflict(msg) => tonic::Status::failed_precondition(msg),
+            AppError::TooManyRequests(msg) => tonic::Status::resource_exhausted(msg),
+            AppError::NoAvailableCouriers => tonic::Status::unavailable("no couriers available"),
+            AppError::Unavailable(msg) => tonic::Status::unavailable(msg),
+            AppError::Internal(msg) => tonic::Status::internal(msg),
+        }
+    }
+}
@@ -0,0 +1,283 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{Stream, StreamExt};
+
+use crate::chaos::Chaos;
+use crate::models::event::DispatchEvent;
+
+/// An event as it went out on the bus. `seq` increases by one per published
+/// event and doubles as the resume cursor for stream clients.
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordedEvent {
+    pub seq: u64,
+    pub recorded_at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub event: DispatchEvent,
+    /// Published on another instance and relayed here, so integrations that
+    /// deliver each event once (webhooks, Kafka, NATS) leave it to the origin.
+    #[serde(skip)]
+    pub relayed: bool,
+}
+
+/// A subscriber fell behind and this many events were dropped for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lagged(pub u64);
+
+struct History {
+    next_seq: u64,
+    events: VecDeque<RecordedEvent>,
+}
+
+/// Broadcast channel for assignment and order events, plus a ring buffer of
+/// the most recent ones so clients that reconnect can resume from their last
+/// `seq`. Courier locations go out on their own channel
+/// (`AppState::courier_locations_tx`) so a burst of pings cannot push these
+/// out of a slow subscriber's buffer.
+pub struct EventBus {
+    tx: broadcast::Sender<RecordedEvent>,
+    replay_capacity: usize,
+    history: Mutex<History>,
+    chaos: Option<Arc<Chaos>>,
+}
+
+impl EventBus {
+    pub fn new(buffer_size: usize, replay_capacity: usize) -> Self {
+        let (tx, _unused_rx) = broadcast::channel(buffer_size);
+        Self {
+            tx,
+            replay_capacity,
+            history: Mutex::new(History {
+                next_seq: 1,
+                events: VecDeque::with_capacity(replay_capacity),
+            }),
+            chaos: None,
+        }
+    }
+
+    /// Drops events at `chaos`'s rate; see [`ChaosSettings::drop_event_rate`].
+    ///
+    /// [`ChaosSettings::drop_event_rate`]: crate::chaos::ChaosSettings::drop_event_rate
+    pub fn with_chaos(mut self, chaos: Arc<Chaos>) -> Self {
+        self.chaos = Some(chaos);
+        self
+    }
+
+    pub fn publish(&self, event: DispatchEvent) -> u64 {
+        self.record(event, false)
+    }
+
+    /// Publishes an event that originated on another instance. It gets a
+    /// local `seq` like any other.
+    pub fn publish_relayed(&self, event: DispatchEvent) -> u64 {
+        self.record(event, true)
+    }
+
+    fn record(&self, event: DispatchEvent, relayed: bool) -> u64 {
+        // Sequencing and sending under one lock keeps the live feed in `seq`
+        // order, which resuming clients rely on to drop duplicates.
+        let mut history = self.history.lock().unwrap_or_else(|e| e.into_inner());
+        let recorded = RecordedEvent {
+            seq: history.next_seq,
+            recorded_at: Utc::now(),
+            event,
+            relayed,
+        };
+        history.next_seq += 1;
+        if self.chaos.as_ref().is_some_and(|chaos| chaos.drop_event()) {
+            return recorded.seq;
+        }
+
+        if self.replay_capacity > 0 {
+            if history.events.len() == self.replay_capacity {
+                history.events.pop_front();
+            }
+            history.events.push_back(recorded.clone());
+        }
+
+        let seq = recorded.seq;
+        let _ = self.tx.send(recorded);
+        seq
+    }
+
+    /// Events published but not yet received by every subscriber.
+    pub fn undelivered(&self) -> usize {
+        self.tx.len()
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<RecordedEvent> {
+        self.tx.subscribe()
+    }
+
+    /// The `seq` of the last event published; 0 before the first.
+    pub fn last_seq(&self) -> u64 {
+        let history = self.history.lock().unwrap_or_else(|e| e.into_inner());
+        history.next_seq - 1
+    }
+
+    /// Whether a client that received everything up to `after` can resume
+    /// from it without a gap: `after` was published here and nothing since
+    /// has been evicted from the buffer.
+    pub fn can_resume(&self, after: u64) -> bool {
+        let history = self.history.lock().unwrap_or_else(|e| e.into_inner());
+        after + 1 == history.next_seq
+            || (after < history.next_seq
+                && history
+                    .events
+                    .front()
+                    .is_some_and(|first| first.seq <= after + 1))
+    }
+
+    /// Validates a client's resume cursor. A cursor past the last published
+    /// `seq` predates a restart (`seq` is not persisted), so the client is
+    /// treated as new rather than waiting for the counter to catch up.
+    pub fn resume_cursor(&self, requested: Option<u64>) -> Option<u64> {
+        let history = self.history.lock().unwrap_or_else(|e| e.into_inner());
+        requested.filter(|&cursor| cursor < history.next_seq)
+    }
+
+    /// Buffered events after `after` (none when `None`) followed by the live
+    /// feed, with anything already replayed dropped by `seq`. If the consumer
+    /// falls behind the broadcast buffer, the stream yields `Err(Lagged(n))`
+    /// in place of the `n` events it missed and then carries on.
+    pub fn stream(
+        &self,
+        after: Option<u64>,
+        since: Option<DateTime<Utc>>,
+    ) -> impl Stream<Item = Result<RecordedEvent, Lagged>> + Send + use<> {
+        let live = BroadcastStream::new(self.subscribe());
+        let cursor = self.resume_cursor(after);
+        let replay = match cursor {
+            Some(cursor) => self.replay(Some(cursor), since),
+            None => Vec::new(),
+        };
+        let replayed_up_to = replay
+            .last()
+            .map_or(cursor.unwrap_or(0), |recorded| recorded.seq);
+
+        tokio_stream::iter(replay.into_iter().map(Ok)).chain(live.filter_map(move |result| {
+            match result {
+                Ok(recorded) if recorded.seq <= replayed_up_to => None,
+                Ok(recorded) => Some(Ok(recorded)),
+                Err(BroadcastStreamRecvError::Lagged(dropped)) => Some(Err(Lagged(dropped))),
+            }
+        }))
+    }
+
+    /// Buffered events, oldest first, with `seq` above `after` and recorded
+    /// strictly after `since`. A client whose cursor has already been evicted
+    /// sees the gap as a jump in `seq`.
+    pub fn replay(&self, after: Option<u64>, since: Option<DateTime<Utc>>) -> Vec<RecordedEvent> {
+        let history = self.history.lock().unwrap_or_else(|e| e.into_inner());
+        history
+            .events
+            .iter()
+            .filter(|recorded| after.is_none_or(|after| recorded.seq > after))
+            .filter(|recorded| since.is_none_or(|since| recorded.recorded_at > since))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    use tokio_stream::StreamExt;
+
+    use super::{EventBus, Lagged};
+    use crate::models::courier::GeoPoint;
+    use crate::models::event::DispatchEvent;
+    use crate::models::order::{OrderEvent, OrderStatus};
+
+    fn order_event() -> DispatchEvent {
+        DispatchEvent::Order(OrderEvent {
+            order_id: Uuid::new_v4(),
+            status: OrderStatus::Pending,
+            courier_id: None,
+            pickup: GeoPoint {
+                lat: 52.51,
+                lng: 13.39,
+            },
+            occurred_at: Utc::now(),
+            items: Vec::new(),
+        })
+    }
+
+    #[test]
+    fn keeps_only_the_most_recent_events() {
+        let bus = EventBus::new(16, 2);
+        for _ in 0..3 {
+            bus.publish(order_event());
+        }
+
+        let seqs: Vec<u64> = bus.replay(None, None).iter().map(|e| e.seq).collect();
+        assert_eq!(seqs, [2, 3]);
+    }
+
+    #[test]
+    fn since_excludes_older_events() {
+        let bus = EventBus::new(16, 8);
+        bus.publish(order_event());
+        let cutoff = bus.replay(None, None)[0].recorded_at;
+        bus.publish(order_event());
+
+        let replay = bus.replay(None, Some(cutoff));
+        assert_eq!(replay.len(), 1);
+        assert!(replay[0].recorded_at > cutoff);
+    }
+
+    #[test]
+    fn resumes_after_cursor() {
+        let bus = EventBus::new(16, 8);
+        let mut rx = bus.subscribe();
+        let first = bus.publish(order_event());
+        let second = bus.publish(order_event());
+
+        assert_eq!(bus.resume_cursor(Some(first)), Some(first));
+        assert_eq!(bus.resume_cursor(Some(second + 1)), None);
+
+        let replay = bus.replay(Some(first), None);
+        assert_eq!(replay.len(), 1);
+        assert_eq!(replay[0].seq, second);
+
+        let live: Vec<u64> = std::iter::from_fn(|| rx.try_recv().ok())
+            .map(|e| e.seq)
+            .collect();
+        assert_eq!(live, [1, 2]);
+    }
+
+    #[test]
+    fn resumes_only_without_a_gap() {
+        let bus = EventBus::new(16, 2);
+        assert!(bus.can_resume(0));
+        for _ in 0..3 {
+            bus.publish(order_event());
+        }
+
+        assert_eq!(bus.last_seq(), 3);
+        assert!(!bus.can_resume(0));
+        assert!(bus.can_resume(1));
+        assert!(bus.can_resume(3));
+        assert!(!bus.can_resume(4));
+    }
+
+    #[tokio::test]
+    async fn reports_lag_then_continues() {
+        let bus = EventBus::new(2, 8);
+        let mut stream = Box::pin(bus.stream(None, None));
+        for _ in 0..4 {
+            bus.publish(order_event());
+        }
+
+        assert_eq!(stream.next().await.unwrap().err(), Some(Lagged(2)));
+        assert_eq!(stream.next().await.unwrap().unwrap().seq, 3);
+        assert_eq!(stream.next().await.unwrap().unwrap().seq, 4);
+    }
+}
@@ -0,0 +1,205 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::str::FromStr;
+use std::sync::RwLock;
+
+use uuid::Uuid;
+
+use crate::geo::{geohash, ZONE_PRECISION};
+use crate::models::courier::GeoPoint;
+
+/// Kilometres per degree of latitude.
+const KM_PER_DEGREE: f64 = 111.195;
+
+/// Most geohash cells a query looks up; bigger areas are looked up with
+/// coarser cells.
+const MAX_QUERY_CELLS: usize = 64;
+
+/// A lat/lng rectangle. Boxes across the antimeridian are not supported.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub min_lat: f64,
+    pub min_lng: f64,
+    pub max_lat: f64,
+    pub max_lng: f64,
+}
+
+impl BoundingBox {
+    /// The smallest box holding the circle of `radius_km` around `center`.
+    pub fn around(center: &GeoPoint, radius_km: f64) -> Self {
+        let lat_span = radius_km / KM_PER_DEGREE;
+        let cos_lat = center.lat.to_radians().cos();
+        let lng_span = if cos_lat > 1e-9 {
+            (lat_span / cos_lat).min(180.0)
+        } else {
+            180.0
+        };
+        Self {
+            min_lat: (center.lat - lat_span).max(-90.0),
+            min_lng: (center.lng - lng_span).max(-180.0),
+            max_lat: (center.lat + lat_span).min(90.0),
+            max_lng: (center.lng + lng_span).min(180.0),
+        }
+    }
+
+    pub fn contains(&self, point: &GeoPoint) -> bool {
+        (self.min_lat..=self.max_lat).contains(&point.lat)
+            && (self.min_lng..=self.max_lng).contains(&point.lng)
+    }
+
+    /// The overlap of two boxes, if they overlap.
+    pub fn intersect(&self, other: &BoundingBox) -> Option<BoundingBox> {
+        let overlap = BoundingBox {
+            min_lat: self.min_lat.max(other.min_lat),
+            min_lng: self.min_lng.max(other.min_lng),
+            max_lat: self.max_lat.min(other.max_lat),
+            max_lng: self.max_lng.min(other.max_lng),
+        };
+        (overlap.min_lat <= overlap.max_lat && overlap.min_lng <= overlap.max_lng)
+            .then_some(overlap)
+    }
+
+    /// Geohash cells of `precision` characters that together cover the box,
+    /// or `None` if there would be more than `MAX_QUERY_CELLS` of them.
+    fn covering_cells(&self, precision: usize) -> Option<BTreeSet<String>> {
+        let lng_bits = (5 * precision).div_ceil(2) as i32;
+        let lat_bits = (5 * precision / 2) as i32;
+        let cell_width = 360.0 / 2f64.powi(lng_bits);
+        let cell_height = 180.0 / 2f64.powi(lat_bits);
+        let rows = ((self.max_lat - self.min_lat) / cell_height) as usize + 2;
+        let cols = ((self.max_lng - self.min_lng) / cell_width) as usize + 2;
+        if rows.saturating_mul(cols) > MAX_QUERY_CELLS {
+            return None;
+        }
+
+        // Samples one cell apart, with the last clamped to the far edge,
+        // land in every cell the box touches.
+        let mut cells = BTreeSet::new();
+        for row in 0..rows {
+            let lat = (self.min_lat + row as f64 * cell_height).min(self.max_lat);
+            for col in 0..cols {
+                let lng = (self.min_lng + col as f64 * cell_width).min(self.max_lng);
+                cells.insert(geohash(&GeoPoint { lat, lng }, precision));
+            }
+        }
+        Some(cells)
+    }
+}
+
+/// `min_lng,min_lat,max_lng,max_lat`, the order GeoJSON uses.
+impl FromStr for BoundingBox {
+    type Err = String;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<f64> = raw
+            .split(',')
+            .map(|part| part.trim().parse::<f64>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| {
+                "bbox must be four numbers: min_lng,min_lat,max_lng,max_lat".to_string()
+            })?;
+        let [min_lng, min_lat, max_lng, max_lat] = parts[..] else {
+            return Err("bbox must be four numbers: min_lng,min_lat,max_lng,max_lat".to_string());
+        };
+        if ![min_lat, max_lat]
+            .iter()
+            .all(|lat| (-90.0..=90.0).contains(lat))
+            || ![min_lng, max_lng]
+                .iter()
+                .all(|lng| (-180.0..=180.0).contains(lng))
+        {
+            return Err("bbox latitudes must be within ±90 and longitudes within ±180".to_string());
+        }
+        if min_lat > max_lat || min_lng > max_lng {
+            return Err("bbox minimums must not exceed its maximums".to_string());
+        }
+        Ok(Self {
+            min_lat,
+            min_lng,
+            max_lat,
+            max_lng,
+        })
+    }
+}
+
+/// Orders bucketed by the geohash cell of their first pickup. A pickup never
+/// moves, so orders are only ever added.
+#[derive(Default)]
+pub struct PickupIndex {
+    cells: RwLock<BTreeMap<String, Vec<Uuid>>>,
+}
+
+impl PickupIndex {
+    pub fn insert(&self, order_id: Uuid, pickup: &GeoPoint) {
+        self.cells
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(geohash(pickup, ZONE_PRECISION))
+            .or_default()
+            .push(order_id);
+    }
+
+    /// Orders whose pickup may lie in `bbox`: everything in the cells
+    /// covering it. Callers check the exact position.
+    pub fn candidates(&self, bbox: &BoundingBox) -> Vec<Uuid> {
+        // Precision 1 always fits: there are only 32 such cells.
+        let prefixes = (1..=ZONE_PRECISION)
+            .rev()
+            .find_map(|precision| bbox.covering_cells(precision))
+            .unwrap_or_default();
+
+        let cells = self.cells.read().unwrap_or_else(|e| e.into_inner());
+        let mut ids = Vec::new();
+        for prefix in prefixes {
+            for (_, bucket) in cells
+                .range(prefix.clone()..)
+                .take_while(|(cell, _)| cell.starts_with(&prefix))
+            {
+                ids.extend_from_slice(bucket);
+            }
+        }
+        ids
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::{BoundingBox, PickupIndex};
+    use crate::models::courier::GeoPoint;
+
+    fn point(lat: f64, lng: f64) -> GeoPoint {
+        GeoPoint { lat, lng }
+    }
+
+    #[test]
+    fn finds_orders_in_small_and_large_boxes() {
+        let index = PickupIndex::default();
+        let mitte = Uuid::new_v4();
+        let kreuzberg = Uuid::new_v4();
+        let hamburg = Uuid::new_v4();
+        index.insert(mitte, &point(52.52, 13.40));
+        index.insert(kreuzberg, &point(52.49, 13.42));
+        index.insert(hamburg, &point(53.55, 9.99));
+
+        let central: BoundingBox = "13.38,52.51,13.41,52.53".parse().unwrap();
+        let found = index.candidates(&central);
+        assert!(found.contains(&mitte));
+        assert!(!found.contains(&hamburg));
+
+        let berlin = BoundingBox::around(&point(52.51, 13.41), 5.0);
+        let found = index.candidates(&berlin);
+        assert!(found.contains(&mitte) && found.contains(&kreuzberg));
+
+        let germany: BoundingBox = "5.8,47.2,15.1,55.1".parse().unwrap();
+        assert_eq!(index.candidates(&germany).len(), 3);
+    }
+
+    #[test]
+    fn rejects_malformed_boxes() {
+        assert!("13.38,52.51,13.41".parse::<BoundingBox>().is_err());
+        assert!("13.41,52.51,13.38,52.53".parse::<BoundingBox>().is_err());
+        assert!("13.38,95,13.41,96".parse::<BoundingBox>().is_err());
+        assert!("a,b,c,d".parse::<BoundingBox>().is_err());
+    }
+}
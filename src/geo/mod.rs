@@ -1,25 +1,194 @@
+pub mod index;
+pub mod region;
+
 use crate::models::courier::GeoPoint;
 
 const EARTH_RADIUS_KM: f64 = 6_371.0;
+const GEOHASH_ALPHABET: &[u8; 32] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+/// Geohash length used for zones (cells of roughly 4.9 x 4.9 km).
+pub const ZONE_PRECISION: usize = 5;
+
+/// Longest geohash accepted anywhere, e.g. for preferred zones.
+pub const MAX_GEOHASH_PRECISION: usize = 12;
+
+/// Geohash length distances are cached by (cells of roughly 153 x 153 m).
+pub const DISTANCE_CELL_PRECISION: usize = 7;
 
 pub fn haversine_km(a: &GeoPoint, b: &GeoPoint) -> f64 {
-    let lat1 = a.lat.to_radians();
-    let lat2 = b.lat.to_radians();
-    let delta_lat = (b.lat - a.lat).to_radians();
-    let delta_lng = (b.lng - a.lng).to_radians();
+    haversine_radians_km(&a.into(), &b.into())
+}
+
+/// A point in radians, with the cosine of its latitude: everything
+/// `haversine_km` works out from one end alone.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RadianPoint {
+    pub lat: f64,
+    pub lng: f64,
+    pub cos_lat: f64,
+}
 
-    let sin_lat = (delta_lat / 2.0).sin();
-    let sin_lng = (delta_lng / 2.0).sin();
+impl From<&GeoPoint> for RadianPoint {
+    fn from(point: &GeoPoint) -> Self {
+        let lat = point.lat.to_radians();
+        Self {
+            lat,
+            lng: point.lng.to_radians(),
+            cos_lat: lat.cos(),
+        }
+    }
+}
 
-    let haversine = sin_lat * sin_lat + lat1.cos() * lat2.cos() * sin_lng * sin_lng;
+/// `haversine_km` for points already converted, for hot loops that measure
+/// from the same points over and over.
+pub fn haversine_radians_km(a: &RadianPoint, b: &RadianPoint) -> f64 {
+    let sin_lat = ((b.lat - a.lat) / 2.0).sin();
+    let sin_lng = ((b.lng - a.lng) / 2.0).sin();
+
+    let haversine = sin_lat * sin_lat + a.cos_lat * b.cos_lat * sin_lng * sin_lng;
     let central_angle = 2.0 * haversine.sqrt().asin();
 
     EARTH_RADIUS_KM * central_angle
 }
 
+/// What is derived from a point for distances, remembered next to the
+/// point it was worked out from, so a stale or missing cache is noticed and
+/// recomputed rather than used.
+#[derive(Debug, Clone, Copy)]
+pub struct CachedLocation {
+    source: (f64, f64),
+    radians: RadianPoint,
+    cell: u64,
+}
+
+impl CachedLocation {
+    pub fn new(point: &GeoPoint) -> Self {
+        Self {
+            source: (point.lat, point.lng),
+            radians: point.into(),
+            cell: cell_id(point, DISTANCE_CELL_PRECISION),
+        }
+    }
+
+    fn is_for(&self, point: &GeoPoint) -> bool {
+        self.source == (point.lat, point.lng)
+    }
+
+    /// `point` in radians, from the cache when it was made for `point`.
+    pub fn radians(&self, point: &GeoPoint) -> RadianPoint {
+        if self.is_for(point) {
+            self.radians
+        } else {
+            point.into()
+        }
+    }
+
+    /// `cell_id` of `point` at `DISTANCE_CELL_PRECISION`, from the cache
+    /// when it was made for `point`.
+    pub fn cell(&self, point: &GeoPoint) -> u64 {
+        if self.is_for(point) {
+            self.cell
+        } else {
+            cell_id(point, DISTANCE_CELL_PRECISION)
+        }
+    }
+}
+
+impl Default for CachedLocation {
+    /// An empty cache: NaN never equals a coordinate, so it always misses.
+    fn default() -> Self {
+        Self {
+            source: (f64::NAN, f64::NAN),
+            radians: RadianPoint {
+                lat: f64::NAN,
+                lng: f64::NAN,
+                cos_lat: f64::NAN,
+            },
+            cell: 0,
+        }
+    }
+}
+
+/// Encodes a point as a geohash of `precision` characters. Any prefix of a
+/// geohash is the cell containing it, so zone filters are prefix matches.
+pub fn geohash(point: &GeoPoint, precision: usize) -> String {
+    let mut hash = vec![0u8; precision];
+    encode_geohash(point, &mut hash);
+    hash.into_iter().map(char::from).collect()
+}
+
+/// Fills `cell` with the geohash of `point`, one character per byte.
+fn encode_geohash(point: &GeoPoint, cell: &mut [u8]) {
+    let bits = cell_id(point, cell.len());
+    let last = cell.len().saturating_sub(1);
+    for (index, byte) in cell.iter_mut().enumerate() {
+        let chunk = (bits >> (5 * (last - index))) & 0x1f;
+        *byte = GEOHASH_ALPHABET[chunk as usize];
+    }
+}
+
+/// The geohash of `point` of `precision` characters as a number, five bits
+/// per character: a cheap map key. Precision is at most
+/// `MAX_GEOHASH_PRECISION`.
+pub fn cell_id(point: &GeoPoint, precision: usize) -> u64 {
+    debug_assert!(precision <= MAX_GEOHASH_PRECISION);
+    let mut lat_range = (-90.0, 90.0);
+    let mut lng_range = (-180.0, 180.0);
+    let mut bits = 0u64;
+    for bit in 0..5 * precision {
+        let (range, value) = if bit % 2 == 0 {
+            (&mut lng_range, point.lng)
+        } else {
+            (&mut lat_range, point.lat)
+        };
+
+        let mid = (range.0 + range.1) / 2.0;
+        bits <<= 1;
+        if value >= mid {
+            bits |= 1;
+            range.0 = mid;
+        } else {
+            range.1 = mid;
+        }
+    }
+    bits
+}
+
+/// The `cell_id` of the cell `hash` names, or `None` if it is not a
+/// geohash.
+pub fn parse_cell(hash: &str) -> Option<u64> {
+    if !is_geohash(hash) {
+        return None;
+    }
+    hash.bytes().try_fold(0u64, |bits, byte| {
+        let chunk = GEOHASH_ALPHABET.iter().position(|&c| c == byte)?;
+        Some(bits << 5 | chunk as u64)
+    })
+}
+
+pub fn zone_of(point: &GeoPoint) -> String {
+    geohash(point, ZONE_PRECISION)
+}
+
+/// Whether `zone` is a lowercase geohash of at most 12 characters.
+pub fn is_geohash(zone: &str) -> bool {
+    (1..=MAX_GEOHASH_PRECISION).contains(&zone.len())
+        && zone.bytes().all(|b| GEOHASH_ALPHABET.contains(&b))
+}
+
+/// Whether `point` lies inside `zone`, a geohash of any precision.
+pub fn in_zone(point: &GeoPoint, zone: &str) -> bool {
+    let mut cell = [0u8; MAX_GEOHASH_PRECISION];
+    let Some(cell) = cell.get_mut(..zone.len()) else {
+        return false;
+    };
+    encode_geohash(point, cell);
+    cell == zone.as_bytes()
+}
+
 #[cfg(test)]
 mod tests {
-    use super::haversine_km;
+    use super::{cell_id, geohash, haversine_km, in_zone, parse_cell, CachedLocation, RadianPoint};
     use crate::models::courier::GeoPoint;
 
     #[test]
@@ -45,4 +214,37 @@ mod tests {
         let distance = haversine_km(&london, &paris);
         assert!((distance - 343.0).abs() < 5.0);
     }
+
+    #[test]
+    fn geohash_matches_reference_encoding() {
+        let p = GeoPoint {
+            lat: 57.64911,
+            lng: 10.40744,
+        };
+        assert_eq!(geohash(&p, 11), "u4pruydqqvj");
+        assert!(in_zone(&p, "u4pru"));
+        assert!(!in_zone(&p, "u4prv"));
+        assert_eq!(parse_cell("u4pru"), Some(cell_id(&p, 5)));
+        assert_eq!(parse_cell("u4pra"), None);
+    }
+
+    #[test]
+    fn cached_locations_are_only_used_for_their_own_point() {
+        let berlin = GeoPoint {
+            lat: 52.52,
+            lng: 13.405,
+        };
+        let hamburg = GeoPoint {
+            lat: 53.5511,
+            lng: 9.9937,
+        };
+        let cache = CachedLocation::new(&berlin);
+        assert_eq!(cache.radians(&berlin), RadianPoint::from(&berlin));
+        assert_eq!(cache.radians(&hamburg), RadianPoint::from(&hamburg));
+        assert_eq!(
+            CachedLocation::default().radians(&berlin),
+            RadianPoint::from(&berlin)
+        );
+        assert_ne!(cache.cell(&berlin), cache.cell(&hamburg));
+    }
 }
@@ -0,0 +1,235 @@
+use std::sync::Arc;
+
+use dashmap::mapref::multiple::RefMulti;
+use dashmap::mapref::one::{Ref, RefMut};
+use dashmap::DashMap;
+use uuid::Uuid;
+
+use crate::geo::{cell_id, in_zone, parse_cell};
+use crate::models::courier::{Courier, GeoPoint};
+use crate::models::order::DeliveryOrder;
+
+/// Geohash length regions are cut at (cells of roughly 156 x 156 km, about
+/// a metro area).
+pub const REGION_PRECISION: usize = 3;
+
+const DEFAULT_SHARDS: usize = 64;
+
+/// Something a `RegionMap` files by where it is.
+pub trait Located {
+    fn position(&self) -> &GeoPoint;
+}
+
+impl Located for Courier {
+    fn position(&self) -> &GeoPoint {
+        &self.location
+    }
+}
+
+/// Orders are filed by their first pickup, which never moves.
+impl Located for DeliveryOrder {
+    fn position(&self) -> &GeoPoint {
+        &self.pickup
+    }
+}
+
+impl<T: Located> Located for Arc<T> {
+    fn position(&self) -> &GeoPoint {
+        (**self).position()
+    }
+}
+
+/// A map by id split into shards by region, so that everything in one
+/// region sits together and a query about one region reads one shard.
+/// Lookups by id go through an index of which shard each entry is in.
+///
+/// It offers the parts of `DashMap`'s API the state uses, with the same
+/// guards. An entry whose region changes is moved by `rehome` (or by
+/// inserting it again); a move holds the entry locked while it is copied,
+/// so no update made through `get_mut` is lost. An iteration running
+/// alongside a move may see the entry twice or not at all: readers that
+/// need a whole view hold [`crate::snapshot::Snapshots::read`], which moves
+/// wait for.
+pub struct RegionMap<V> {
+    shards: Box<[DashMap<Uuid, V>]>,
+    /// The shard each entry is in.
+    homes: DashMap<Uuid, usize>,
+}
+
+impl<V: Located> Default for RegionMap<V> {
+    fn default() -> Self {
+        Self::new(DEFAULT_SHARDS)
+    }
+}
+
+impl<V: Located> RegionMap<V> {
+    pub fn new(shards: usize) -> Self {
+        Self {
+            shards: (0..shards.max(1)).map(|_| DashMap::new()).collect(),
+            homes: DashMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.homes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.homes.is_empty()
+    }
+
+    pub fn contains_key(&self, id: &Uuid) -> bool {
+        self.homes.contains_key(id)
+    }
+
+    pub fn get(&self, id: &Uuid) -> Option<Ref<'_, Uuid, V>> {
+        loop {
+            let home = *self.homes.get(id)?;
+            let entry = self.shards[home].get(id);
+            // Gone from that shard only if a move took it elsewhere since
+            // the home was read.
+            if entry.is_some() || self.homes.get(id).is_none_or(|now| *now == home) {
+                return entry;
+            }
+        }
+    }
+
+    pub fn get_mut(&self, id: &Uuid) -> Option<RefMut<'_, Uuid, V>> {
+        self.home_mut(id).map(|(_, entry)| entry)
+    }
+
+    /// Inserts `value` in the shard of its region, moving it if an earlier
+    /// version was filed elsewhere. Returns the earlier version.
+    pub fn insert(&self, id: Uuid, value: V) -> Option<V> {
+        let to = self.shard_of(value.position());
+        match self.home_mut(&id) {
+            Some((from, current)) if from != to => {
+                self.shards[to].insert(id, value);
+                self.homes.insert(id, to);
+                drop(current);
+                self.shards[from].remove(&id).map(|(_, old)| old)
+            }
+            current => {
+                drop(current);
+                let old = self.shards[to].insert(id, value);
+                self.homes.insert(id, to);
+                old
+            }
+        }
+    }
+
+    /// Every entry, region by region.
+    pub fn iter(&self) -> impl Iterator<Item = RefMulti<'_, Uuid, V>> {
+        self.shards.iter().flat_map(DashMap::iter)
+    }
+
+    /// The entries lying in `cell`, a geohash of at least
+    /// `REGION_PRECISION` characters; only its region's shard is read.
+    /// Nothing for anything else.
+    pub fn within<'a>(&'a self, cell: &'a str) -> impl Iterator<Item = RefMulti<'a, Uuid, V>> {
+        let shard = cell
+            .get(..REGION_PRECISION)
+            .and_then(parse_cell)
+            .map(|region| self.shard_of_region(region));
+        shard
+            .into_iter()
+            .flat_map(|shard| self.shards[shard].iter())
+            .filter(move |entry| in_zone(entry.position(), cell))
+    }
+
+    /// The entry locked for writing and the shard it is in, checked to
+    /// still be its home once locked.
+    fn home_mut(&self, id: &Uuid) -> Option<(usize, RefMut<'_, Uuid, V>)> {
+        loop {
+            let home = *self.homes.get(id)?;
+            let entry = self.shards[home].get_mut(id);
+            if self.homes.get(id).is_none_or(|now| *now == home) {
+                return entry.map(|entry| (home, entry));
+            }
+        }
+    }
+
+    fn shard_of(&self, point: &GeoPoint) -> usize {
+        self.shard_of_region(cell_id(point, REGION_PRECISION))
+    }
+
+    fn shard_of_region(&self, region: u64) -> usize {
+        // Neighbouring regions differ in their low bits; spread them out.
+        (region.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 32) as usize % self.shards.len()
+    }
+}
+
+impl<V: Located + Clone> RegionMap<V> {
+    /// Moves the entry to the shard of its region after an update through
+    /// `get_mut` that may have taken it to another one.
+    pub fn rehome(&self, id: &Uuid) {
+        let Some((from, current)) = self.home_mut(id) else {
+            return;
+        };
+        let to = self.shard_of(current.position());
+        if to != from {
+            self.shards[to].insert(*id, current.clone());
+            self.homes.insert(*id, to);
+            drop(current);
+            self.shards[from].remove(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::{Located, RegionMap};
+    use crate::geo::geohash;
+    use crate::models::courier::GeoPoint;
+
+    #[derive(Clone)]
+    struct Pin(GeoPoint, u32);
+
+    impl Located for Pin {
+        fn position(&self) -> &GeoPoint {
+            &self.0
+        }
+    }
+
+    #[test]
+    fn keeps_entries_findable_across_regions() {
+        let berlin = GeoPoint {
+            lat: 52.52,
+            lng: 13.405,
+        };
+        let paris = GeoPoint {
+            lat: 48.8566,
+            lng: 2.3522,
+        };
+        let map = RegionMap::new(8);
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        map.insert(a, Pin(berlin.clone(), 0));
+        map.insert(b, Pin(paris.clone(), 0));
+        assert_eq!(map.len(), 2);
+
+        let in_berlin = geohash(&berlin, 4);
+        let ids: Vec<Uuid> = map.within(&in_berlin).map(|entry| *entry.key()).collect();
+        assert_eq!(ids, vec![a]);
+        assert_eq!(map.within("u3").count(), 0);
+
+        // Moved to Paris through get_mut, then rehomed: still found by id
+        // and now listed with Paris.
+        {
+            let mut pin = map.get_mut(&a).unwrap();
+            pin.0 = paris.clone();
+            pin.1 = 1;
+        }
+        map.rehome(&a);
+        assert_eq!(map.get(&a).unwrap().1, 1);
+        assert_eq!(map.within(&geohash(&paris, 3)).count(), 2);
+        assert_eq!(map.within(&in_berlin).count(), 0);
+        assert_eq!(map.iter().count(), 2);
+
+        let old = map.insert(a, Pin(berlin, 2)).unwrap();
+        assert_eq!(old.1, 1);
+        assert_eq!(map.within(&in_berlin).count(), 1);
+        assert_eq!(map.len(), 2);
+    }
+}
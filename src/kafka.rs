@@ -0,0 +1,166 @@
+use std::sync::Arc;
+
+use rdkafka::error::KafkaError;
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::ClientConfig;
+use tokio_stream::StreamExt;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::events::{Lagged, RecordedEvent};
+use crate::models::event::DispatchEvent;
+use crate::state::AppState;
+
+#[derive(Debug, Clone)]
+pub struct KafkaSettings {
+    /// `bootstrap.servers`, comma-separated `host:port` pairs.
+    pub brokers: String,
+    /// Topic for assignment events; empty to skip them.
+    pub assignments_topic: String,
+    /// Topic for order lifecycle events (created, in transit, delivered, ...);
+    /// empty to skip them.
+    pub orders_topic: String,
+}
+
+impl KafkaSettings {
+    /// Destination topic and partition key for an event. Keying by order ID
+    /// keeps every event for one order on one partition, and so in order.
+    fn route(&self, event: &DispatchEvent) -> Option<(&str, Uuid)> {
+        let (topic, order_id) = match event {
+            DispatchEvent::Assignment(assignment) => (&self.assignments_topic, assignment.order_id),
+            DispatchEvent::Order(order) => (&self.orders_topic, order.order_id),
+            DispatchEvent::CourierLocation(_)
+            | DispatchEvent::Report(_)
+            | DispatchEvent::Surge(_)
+            | DispatchEvent::Change(_) => return None,
+        };
+        (!topic.is_empty()).then_some((topic.as_str(), order_id))
+    }
+}
+
+/// Publishes assignment and order events to Kafka as the same JSON envelope
+/// the WebSocket sends. Delivery is at most once: events are not retried
+/// beyond librdkafka's own retries, and failures are logged and counted.
+pub async fn run_kafka_publisher(state: Arc<AppState>, settings: KafkaSettings) {
+    let producer: FutureProducer = match ClientConfig::new()
+        .set("bootstrap.servers", &settings.brokers)
+        .create()
+    {
+        Ok(producer) => producer,
+        Err(err) => {
+            warn!(error = %err, "failed to create kafka producer, kafka publishing disabled");
+            return;
+        }
+    };
+
+    info!(brokers = %settings.brokers, "kafka publisher started");
+
+    let mut events = Box::pin(state.events.stream(None, None));
+    let mut last_seq = 0;
+
+    while let Some(item) = events.next().await {
+        let batch = match item {
+            Ok(recorded) if recorded.seq <= last_seq => continue,
+            Ok(recorded) => vec![recorded],
+            Err(Lagged(dropped)) => {
+                state.metrics.record_dropped_events("kafka", dropped);
+                warn!(dropped, "kafka publisher lagged, replaying from buffer");
+                state.events.replay(Some(last_seq), None)
+            }
+        };
+
+        for recorded in batch {
+            last_seq = last_seq.max(recorded.seq);
+            publish(&state, &producer, &settings, &recorded);
+        }
+    }
+
+    info!("kafka publisher stopped");
+}
+
+fn publish(
+    state: &Arc<AppState>,
+    producer: &FutureProducer,
+    settings: &KafkaSettings,
+    recorded: &RecordedEvent,
+) {
+    if recorded.relayed {
+        return;
+    }
+    let Some((topic, order_id)) = settings.route(&recorded.event) else {
+        return;
+    };
+
+    let payload = match serde_json::to_vec(recorded) {
+        Ok(payload) => payload,
+        Err(err) => {
+            warn!(error = %err, "failed to serialize kafka payload");
+            return;
+        }
+    };
+    let key = order_id.to_string();
+
+    // Enqueue synchronously so librdkafka sees events in `seq` order, then
+    // wait for the broker acknowledgement off the hot path.
+    let record = FutureRecord::to(topic).key(&key).payload(&payload);
+    let delivery = match producer.send_result(record) {
+        Ok(delivery) => delivery,
+        Err((err, _)) => {
+            record_failure(state, topic, recorded.seq, &err);
+            return;
+        }
+    };
+
+    let state = state.clone();
+    let topic = topic.to_string();
+    let seq = recorded.seq;
+    tokio::spawn(async move {
+        match delivery.await {
+            Ok(Ok(_)) => state.metrics.record_kafka_message("published"),
+            Ok(Err((err, _))) => record_failure(&state, &topic, seq, &err),
+            Err(_) => warn!(topic, seq, "kafka producer dropped before delivery"),
+        }
+    });
+}
+
+fn record_failure(state: &AppState, topic: &str, seq: u64, err: &KafkaError) {
+    warn!(topic, seq, error = %err, "failed to publish event to kafka");
+    state.metrics.record_kafka_message("failed");
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    use super::KafkaSettings;
+    use crate::models::courier::GeoPoint;
+    use crate::models::event::DispatchEvent;
+    use crate::models::order::{OrderEvent, OrderStatus};
+
+    #[test]
+    fn routes_order_events_by_order_id_and_skips_disabled_topics() {
+        let order_id = Uuid::new_v4();
+        let event = DispatchEvent::Order(OrderEvent {
+            order_id,
+            status: OrderStatus::Delivered,
+            courier_id: None,
+            pickup: GeoPoint {
+                lat: 52.51,
+                lng: 13.39,
+            },
+            occurred_at: Utc::now(),
+            items: Vec::new(),
+        });
+
+        let mut settings = KafkaSettings {
+            brokers: "localhost:9092".to_string(),
+            assignments_topic: "dispatch.assignments".to_string(),
+            orders_topic: "dispatch.orders".to_string(),
+        };
+        assert_eq!(settings.route(&event), Some(("dispatch.orders", order_id)));
+
+        settings.orders_topic.clear();
+        assert_eq!(settings.route(&event), None);
+    }
+}
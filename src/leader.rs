@@ -0,0 +1,108 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use redis::aio::MultiplexedConnection;
+use tokio::time::{sleep, timeout};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::state::AppState;
+
+/// Takes the lease when it is free and renews it when this instance holds
+/// it, in one step so two instances can never both succeed.
+const CLAIM_SCRIPT: &str = r"
+local holder = redis.call('GET', KEYS[1])
+if holder == ARGV[1] then
+  redis.call('PEXPIRE', KEYS[1], ARGV[2])
+  return 1
+elseif not holder then
+  redis.call('SET', KEYS[1], ARGV[1], 'PX', ARGV[2])
+  return 1
+end
+return 0
+";
+
+#[derive(Debug, Clone)]
+pub struct ElectionSettings {
+    pub url: String,
+    pub key: String,
+    pub lease: Duration,
+}
+
+/// Competes with the other replicas for a lease on `key` so that only one
+/// of them runs the assignment engines. The leader renews the lease every
+/// third of its length; if it cannot, it steps down at once, and another
+/// instance takes over once the lease runs out. An instance that is not
+/// the leader retries as often, so failover takes at most about one lease.
+///
+/// Sets `state.leadership`, which the engines wait on; start it with
+/// leadership off so no engine runs before the first claim.
+pub async fn run_leader_election(state: Arc<AppState>, settings: ElectionSettings) {
+    let instance = Uuid::new_v4();
+    state.metrics.leader.set(0);
+    let client = match redis::Client::open(settings.url.as_str()) {
+        Ok(client) => client,
+        Err(err) => {
+            error!(error = %err, "invalid redis url, this instance will never lead");
+            return;
+        }
+    };
+
+    info!(key = %settings.key, %instance, "leader election started");
+
+    let lease_ms = settings.lease.as_millis() as u64;
+    let mut connection: Option<MultiplexedConnection> = None;
+    loop {
+        // A claim still pending when the next is due may be too late to
+        // keep the lease; give up on it rather than lead without one.
+        let claimed = timeout(
+            settings.lease / 3,
+            claim(&client, &mut connection, &settings.key, instance, lease_ms),
+        )
+        .await;
+        let leads = match claimed {
+            Ok(Ok(leads)) => leads,
+            Ok(Err(err)) => {
+                warn!(error = %err, "leader lease unreachable");
+                connection = None;
+                false
+            }
+            Err(_) => {
+                warn!("leader lease claim timed out");
+                connection = None;
+                false
+            }
+        };
+        if state.leadership.set(leads) {
+            state.metrics.record_leadership(leads);
+            if leads {
+                info!(key = %settings.key, "this instance is now the leader");
+            } else {
+                warn!(key = %settings.key, "this instance is no longer the leader");
+            }
+        }
+        sleep(settings.lease / 3).await;
+    }
+}
+
+async fn claim(
+    client: &redis::Client,
+    connection: &mut Option<MultiplexedConnection>,
+    key: &str,
+    instance: Uuid,
+    lease_ms: u64,
+) -> redis::RedisResult<bool> {
+    let connection = match connection {
+        Some(connection) => connection,
+        None => connection.insert(client.get_multiplexed_async_connection().await?),
+    };
+    let claimed: i64 = redis::cmd("EVAL")
+        .arg(CLAIM_SCRIPT)
+        .arg(1)
+        .arg(key)
+        .arg(instance.to_string())
+        .arg(lease_ms)
+        .query_async(connection)
+        .await?;
+    Ok(claimed == 1)
+}
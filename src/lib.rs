@@ -1,8 +1,37 @@
 pub mod api;
+pub mod audit;
+pub mod auth;
+pub mod chaos;
+pub mod clock;
 pub mod config;
+pub mod connections;
 pub mod engine;
 pub mod error;
+pub mod events;
 pub mod geo;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+#[cfg(feature = "redis")]
+pub mod leader;
+pub mod listen;
 pub mod models;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+#[cfg(feature = "nats")]
+pub mod nats;
+pub mod notifications;
 pub mod observability;
+pub mod rate_limit;
+#[cfg(feature = "redis")]
+pub mod relay;
+pub mod reload;
+pub mod replay;
+pub mod reports;
+pub mod shutdown;
+pub mod snapshot;
+pub mod standby;
 pub mod state;
+pub mod tenants;
+pub mod tls;
+pub mod validation;
+pub mod webhooks;
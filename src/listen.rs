@@ -0,0 +1,230 @@
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::str::FromStr;
+use std::task::{Context, Poll};
+
+use axum::Router;
+use futures::stream::{self, BoxStream, StreamExt};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use hyper_util::server::graceful::GracefulShutdown;
+use hyper_util::service::TowerToHyperService;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream, UnixListener, UnixStream};
+use tokio_stream::wrappers::{TcpListenerStream, UnixListenerStream};
+use tonic::transport::server::{Connected, TcpConnectInfo};
+use tracing::{debug, warn};
+
+use crate::error::AppError;
+
+/// Where a server listens: `host:port`, or `unix:/path/to.sock`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenAddr {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl FromStr for ListenAddr {
+    type Err = String;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        match raw.strip_prefix("unix:") {
+            Some("") => Err("unix: needs a socket path".to_string()),
+            Some(path) => Ok(ListenAddr::Unix(PathBuf::from(path))),
+            None => raw
+                .parse()
+                .map(ListenAddr::Tcp)
+                .map_err(|err| format!("{raw}: {err}")),
+        }
+    }
+}
+
+impl fmt::Display for ListenAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListenAddr::Tcp(addr) => write!(f, "{addr}"),
+            ListenAddr::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+pub enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+/// Binds `addr`. A socket file left behind by an earlier run is replaced;
+/// any other file at the path is an error rather than being deleted.
+pub async fn bind(addr: &ListenAddr) -> Result<Listener, AppError> {
+    let failed = |err: io::Error| AppError::Internal(format!("failed to bind {addr}: {err}"));
+    match addr {
+        ListenAddr::Tcp(socket_addr) => TcpListener::bind(socket_addr)
+            .await
+            .map(Listener::Tcp)
+            .map_err(failed),
+        ListenAddr::Unix(path) => {
+            remove_stale_socket(path).map_err(failed)?;
+            UnixListener::bind(path).map(Listener::Unix).map_err(failed)
+        }
+    }
+}
+
+fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path),
+        Ok(_) => Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "path exists and is not a socket",
+        )),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err),
+    }
+}
+
+/// An accepted connection from either kind of listener.
+pub enum Connection {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+}
+
+/// Connections from every listener as one stream, for tonic's
+/// `serve_with_incoming_shutdown`.
+pub fn incoming(listeners: Vec<Listener>) -> BoxStream<'static, io::Result<Connection>> {
+    stream::select_all(listeners.into_iter().map(|listener| {
+        match listener {
+            Listener::Tcp(listener) => TcpListenerStream::new(listener)
+                .map(|conn| {
+                    let conn = conn?;
+                    conn.set_nodelay(true)?;
+                    Ok(Connection::Tcp(conn))
+                })
+                .boxed(),
+            Listener::Unix(listener) => UnixListenerStream::new(listener)
+                .map(|conn| conn.map(Connection::Unix))
+                .boxed(),
+        }
+    }))
+    .boxed()
+}
+
+/// Unix connections have no addresses, so the access log shows no client.
+impl Connected for Connection {
+    type ConnectInfo = TcpConnectInfo;
+
+    fn connect_info(&self) -> Self::ConnectInfo {
+        match self {
+            Connection::Tcp(conn) => conn.connect_info(),
+            Connection::Unix(_) => TcpConnectInfo {
+                local_addr: None,
+                remote_addr: None,
+            },
+        }
+    }
+}
+
+impl AsyncRead for Connection {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Connection::Tcp(conn) => Pin::new(conn).poll_read(cx, buf),
+            Connection::Unix(conn) => Pin::new(conn).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for Connection {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Connection::Tcp(conn) => Pin::new(conn).poll_write(cx, buf),
+            Connection::Unix(conn) => Pin::new(conn).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Connection::Tcp(conn) => Pin::new(conn).poll_flush(cx),
+            Connection::Unix(conn) => Pin::new(conn).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Connection::Tcp(conn) => Pin::new(conn).poll_shutdown(cx),
+            Connection::Unix(conn) => Pin::new(conn).poll_shutdown(cx),
+        }
+    }
+}
+
+/// Serves `app` over plain HTTP/1 and HTTP/2 on a unix socket until
+/// `shutdown` resolves, then waits for open connections to finish. Requests
+/// carry no `ConnectInfo`, so rate limits and per-IP caps see one client, as
+/// they would behind a proxy.
+pub async fn serve_unix(
+    listener: UnixListener,
+    app: Router,
+    shutdown: impl Future<Output = ()>,
+) -> Result<(), AppError> {
+    let builder = auto::Builder::new(TokioExecutor::new());
+    let graceful = GracefulShutdown::new();
+    tokio::pin!(shutdown);
+
+    loop {
+        let conn = tokio::select! {
+            conn = listener.accept() => conn,
+            _ = &mut shutdown => break,
+        };
+        let (conn, _) = match conn {
+            Ok(conn) => conn,
+            Err(err) => {
+                warn!(error = %err, "failed to accept unix socket connection");
+                continue;
+            }
+        };
+
+        let service = TowerToHyperService::new(app.clone());
+        let conn = builder.serve_connection_with_upgrades(TokioIo::new(conn), service);
+        let conn = graceful.watch(conn.into_owned());
+        tokio::spawn(async move {
+            if let Err(err) = conn.await {
+                debug!(error = %err, "unix socket connection closed with error");
+            }
+        });
+    }
+
+    graceful.shutdown().await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::ListenAddr;
+
+    #[test]
+    fn parses_tcp_and_unix_addresses() {
+        assert_eq!(
+            "127.0.0.1:3000".parse::<ListenAddr>(),
+            Ok(ListenAddr::Tcp("127.0.0.1:3000".parse().unwrap()))
+        );
+        assert_eq!(
+            "unix:/run/dispatch/http.sock".parse::<ListenAddr>(),
+            Ok(ListenAddr::Unix(PathBuf::from("/run/dispatch/http.sock")))
+        );
+        assert!("unix:".parse::<ListenAddr>().is_err());
+        assert!("localhost".parse::<ListenAddr>().is_err());
+    }
+}
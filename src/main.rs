@@ -1,74 +1,583 @@
-mod api;
-mod config;
-mod engine;
-mod error;
-mod geo;
-mod models;
-mod observability;
-mod state;
-
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 use std::sync::Arc;
 
+use clap::{Parser, Subcommand};
+use futures::future::BoxFuture;
 use tonic::transport::Server as TonicServer;
-use tracing_subscriber::EnvFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+use utoipa::OpenApi;
+
+use dispatch_router::api::grpc::access_log::AccessLogLayer;
+use dispatch_router::api::grpc::auth::{ApiKeyInterceptor, GrpcAuth, JwtInterceptor};
+use dispatch_router::api::grpc::pb::dispatch_service_server::DispatchServiceServer;
+use dispatch_router::api::grpc::tenants::TenantServices;
+use dispatch_router::api::grpc::{health, GrpcDispatchService};
+use dispatch_router::api::rest::cors::CorsSettings;
+use dispatch_router::api::rest::docs::ApiDoc;
+use dispatch_router::api::rest::limits::RequestLimits;
+use dispatch_router::auth::run_jwks_refresh;
+use dispatch_router::engine::heartbeat::{run_courier_sweeper, HeartbeatSettings};
+use dispatch_router::engine::surge::run_surge_detector;
+use dispatch_router::notifications::customer::{
+    run_customer_notifier, CustomerSettings, Templates,
+};
+use dispatch_router::notifications::push::{run_push_notifier, PushSettings};
+use dispatch_router::rate_limit::run_rate_limit_pruner;
+use dispatch_router::reload::{run_config_reloader, ReloadSettings};
+use dispatch_router::reports::run_report_generator;
+use dispatch_router::standby::{run_standby, StandbySettings};
+use dispatch_router::tenants::{Tenants, DEFAULT_TENANT};
+use dispatch_router::tls::{self, TlsSettings};
+use dispatch_router::webhooks::{run_webhook_dispatcher, DeliverySettings};
+use dispatch_router::{api, config, engine, error, listen, shutdown};
+
+/// Real-time delivery assignment service. Configuration comes from the
+/// environment and `.env` (or `CONFIG_FILE`); see the README.
+const PROTO: &str = include_str!("../proto/dispatch.proto");
 
-use crate::api::grpc::pb::dispatch_service_server::DispatchServiceServer;
-use crate::api::grpc::GrpcDispatchService;
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run the REST, WebSocket and gRPC servers (the default).
+    Serve,
+    /// Load and validate the configuration, then exit without serving.
+    CheckConfig,
+    /// Print the OpenAPI document for the REST API.
+    ExportOpenapi {
+        /// Write to this file instead of stdout.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Print the gRPC service definition (`dispatch.proto`).
+    ExportProto {
+        /// Write to this file instead of stdout.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
 
 #[tokio::main]
-async fn main() -> Result<(), error::AppError> {
+async fn main() -> ExitCode {
+    match run(Cli::parse().command.unwrap_or(Command::Serve)).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error::AppError::Internal(message)) => {
+            eprintln!("error: {message}");
+            ExitCode::FAILURE
+        }
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(command: Command) -> Result<(), error::AppError> {
+    match command {
+        Command::Serve => serve().await,
+        Command::CheckConfig => check_config().await,
+        Command::ExportOpenapi { output } => {
+            let document = ApiDoc::openapi().to_pretty_json().map_err(|err| {
+                error::AppError::Internal(format!("failed to render openapi: {err}"))
+            })?;
+            export(&format!("{document}\n"), output.as_deref())
+        }
+        Command::ExportProto { output } => export(PROTO, output.as_deref()),
+    }
+}
+
+/// Runs every check `serve` would run before binding its ports, so a bad
+/// deployment fails here instead of at startup.
+async fn check_config() -> Result<(), error::AppError> {
     let config = config::Config::from_env()?;
+    if !config.cors_allowed_origins.is_empty() {
+        let _ = api::rest::cors::layer(&cors_settings(&config))?;
+    }
+    if !config.tls_cert_path.is_empty() {
+        tls::load(&tls_settings(&config)).await?;
+    }
 
-    tracing_subscriber::fmt()
-        .with_env_filter(EnvFilter::new(config.log_level.clone()))
-        .with_target(false)
-        .compact()
-        .init();
+    match &config.config_file {
+        Some(path) => println!("configuration OK (loaded {})", path.display()),
+        None => println!("configuration OK"),
+    }
+    Ok(())
+}
 
-    let (app_state, order_rx) =
-        state::AppState::new(config.order_queue_size, config.event_buffer_size);
-    let shared_state = Arc::new(app_state);
+fn export(contents: &str, output: Option<&Path>) -> Result<(), error::AppError> {
+    let written = match output {
+        Some(path) => std::fs::write(path, contents),
+        None => std::io::stdout().write_all(contents.as_bytes()),
+    };
+    written.map_err(|err| error::AppError::Internal(format!("failed to write export: {err}")))
+}
 
-    let app = api::rest::router(shared_state.clone());
+fn cors_settings(config: &config::Config) -> CorsSettings {
+    CorsSettings {
+        allowed_origins: config.cors_allowed_origins.clone(),
+        allowed_methods: config.cors_allowed_methods.clone(),
+        max_age: std::time::Duration::from_secs(config.cors_max_age_secs),
+    }
+}
+
+fn tls_settings(config: &config::Config) -> TlsSettings {
+    TlsSettings {
+        cert_path: config.tls_cert_path.clone().into(),
+        key_path: config.tls_key_path.clone().into(),
+        reload_interval: std::time::Duration::from_secs(config.tls_reload_interval_secs),
+    }
+}
 
-    tokio::spawn(engine::assignment::run_assignment_engine(
-        shared_state.clone(),
-        order_rx,
+async fn serve() -> Result<(), error::AppError> {
+    let config = config::Config::from_env()?;
+
+    #[cfg(feature = "otel")]
+    let tracer_provider = if config.otel_exporter_endpoint.is_empty() {
+        None
+    } else {
+        Some(dispatch_router::observability::otel::tracer_provider(
+            &config.otel_service_name,
+        )?)
+    };
+    #[cfg(feature = "otel")]
+    let otel_layer = tracer_provider
+        .as_ref()
+        .map(dispatch_router::observability::otel::layer);
+    #[cfg(not(feature = "otel"))]
+    let otel_layer: Option<tracing_subscriber::layer::Identity> = None;
+
+    #[cfg(feature = "console")]
+    let console_layer = Some(dispatch_router::observability::console::layer(
+        config.console_bind,
     ));
+    #[cfg(not(feature = "console"))]
+    let console_layer: Option<tracing_subscriber::layer::Identity> = None;
 
-    let grpc_addr = format!("0.0.0.0:{}", config.grpc_port)
-        .parse()
-        .map_err(|err| error::AppError::Internal(format!("invalid grpc address: {err}")))?;
-    let grpc_service = GrpcDispatchService::new(shared_state.clone());
+    // `LOG_LEVEL` filters logs and exported spans only; the console layer
+    // needs the runtime's trace-level task spans whatever it is set to.
+    let (log_filter, log_filter_handle) =
+        tracing_subscriber::reload::Layer::new(EnvFilter::new(&config.tunables.log_level));
+    let json_logs = config.log_json.then(|| {
+        tracing_subscriber::fmt::layer()
+            .with_target(false)
+            .json()
+            .flatten_event(true)
+    });
+    let compact_logs = (!config.log_json).then(|| {
+        tracing_subscriber::fmt::layer()
+            .with_target(false)
+            .compact()
+    });
+    let logging = Layer::and_then(json_logs, compact_logs)
+        .and_then(otel_layer)
+        .with_filter(log_filter);
+    tracing_subscriber::registry()
+        .with(logging)
+        .with(console_layer)
+        .init();
 
+    if !config.legacy_env_vars.is_empty() {
+        tracing::warn!(
+            variables = %config.legacy_env_vars.join(","),
+            "settings without the {} prefix are deprecated; rename them",
+            config::ENV_PREFIX
+        );
+    }
+
+    #[cfg(not(feature = "otel"))]
+    if !config.otel_exporter_endpoint.is_empty() {
+        tracing::warn!(
+            "OTEL_EXPORTER_OTLP_ENDPOINT is set but this build lacks the `otel` feature; ignoring it"
+        );
+    }
+
+    // Every tenant gets its own state, engine and background tasks; the
+    // default tenant's also serves the message-bus bridges below.
+    let (tenants, queues) = Tenants::from_config(&config);
+    let tenants = Arc::new(tenants);
+    let shared_state = tenants.default_tenant().clone();
+
+    let mut app = api::rest::limits::apply(
+        api::rest::tenants::tenant_router(tenants.clone()),
+        &RequestLimits {
+            max_body_bytes: config.max_request_body_bytes,
+            timeout: (config.request_timeout_secs > 0)
+                .then(|| std::time::Duration::from_secs(config.request_timeout_secs)),
+            max_concurrent: config.max_concurrent_requests,
+        },
+    );
+    if !config.cors_allowed_origins.is_empty() {
+        app = app.layer(api::rest::cors::layer(&cors_settings(&config))?);
+    }
+
+    match &shared_state.jwt {
+        Some(verifier) => {
+            tokio::spawn(run_jwks_refresh(verifier.clone()));
+        }
+        None => tracing::warn!("JWT_JWKS_URL is empty; REST and gRPC APIs accept any caller"),
+    }
+    if config.chaos.is_enabled() {
+        tracing::warn!(
+            latency_rate = config.chaos.latency_rate,
+            latency_ms = config.chaos.latency.as_millis() as u64,
+            drop_event_rate = config.chaos.drop_event_rate,
+            engine_panic_rate = config.chaos.engine_panic_rate,
+            "chaos mode is on; faults are injected on purpose"
+        );
+    }
+
+    let reload_settings = ReloadSettings {
+        config_file: config.config_file.clone(),
+        poll_interval: (config.config_reload_interval_secs > 0)
+            .then(|| std::time::Duration::from_secs(config.config_reload_interval_secs)),
+    };
+    let heartbeat_settings = HeartbeatSettings {
+        timeout: (config.courier_heartbeat_timeout_secs > 0)
+            .then(|| std::time::Duration::from_secs(config.courier_heartbeat_timeout_secs)),
+    };
+    let delivery_settings = DeliverySettings {
+        max_attempts: config.webhook_max_attempts,
+        retry_base: std::time::Duration::from_millis(config.webhook_retry_base_ms),
+        timeout: std::time::Duration::from_secs(config.webhook_timeout_secs),
+        dead_letter_limit: config.webhook_dead_letter_limit,
+    };
+    let templates = Templates::default();
+    let customer_settings = CustomerSettings {
+        statuses: config.customer_notify_statuses.clone(),
+        callback_secret: config.customer_callback_secret.clone(),
+        gateway_url: config.customer_gateway_url.clone(),
+        templates: Templates {
+            assigned: config
+                .customer_template_assigned
+                .clone()
+                .unwrap_or(templates.assigned),
+            in_transit: config
+                .customer_template_in_transit
+                .clone()
+                .unwrap_or(templates.in_transit),
+            delivered: config
+                .customer_template_delivered
+                .clone()
+                .unwrap_or(templates.delivered),
+        },
+    };
+    let push_settings = (!config.push_gateway_url.is_empty()).then(|| PushSettings {
+        gateway_url: config.push_gateway_url.clone(),
+        gateway_token: config.push_gateway_token.clone(),
+    });
+
+    if let Some(key) = &config.leader_election_key {
+        #[cfg(feature = "redis")]
+        {
+            // Engines wait on standby until the first claim settles it.
+            shared_state.leadership.set(false);
+            tokio::spawn(dispatch_router::leader::run_leader_election(
+                shared_state.clone(),
+                dispatch_router::leader::ElectionSettings {
+                    url: config.redis_url.clone(),
+                    key: key.clone(),
+                    lease: std::time::Duration::from_secs(config.leader_lease_secs),
+                },
+            ));
+        }
+        #[cfg(not(feature = "redis"))]
+        tracing::warn!(
+            key,
+            "LEADER_ELECTION_KEY is set but this build lacks the `redis` feature; ignoring it"
+        );
+    }
+    let standby_settings = (!config.tunables.standby_primary_url.is_empty()).then(|| {
+        // Engines wait until the standby takes over.
+        shared_state.leadership.set(false);
+        StandbySettings {
+            token: config.standby_token.clone(),
+            elected: cfg!(feature = "redis") && config.leader_election_key.is_some(),
+        }
+    });
+
+    let mut engine_handle = None;
+    for (tenant_state, order_rx) in queues {
+        tokio::spawn(run_rate_limit_pruner(tenant_state.clone()));
+        tokio::spawn(run_report_generator(tenant_state.clone()));
+        if let Some(standby_settings) = &standby_settings {
+            tokio::spawn(run_standby(tenant_state.clone(), standby_settings.clone()));
+        }
+        let log_filter_handle = log_filter_handle.clone();
+        tokio::spawn(run_config_reloader(
+            tenant_state.clone(),
+            reload_settings.clone(),
+            Box::new(move |level| {
+                let filter = EnvFilter::try_new(level).map_err(|err| err.to_string())?;
+                log_filter_handle
+                    .reload(filter)
+                    .map_err(|err| err.to_string())
+            }),
+        ));
+
+        let handle = tokio::spawn(engine::supervisor::supervise_assignment_engine(
+            tenant_state.clone(),
+            order_rx,
+        ));
+        if tenant_state.tenant == DEFAULT_TENANT {
+            engine_handle = Some(handle);
+        }
+
+        if config.surge.threshold > 0.0 {
+            tokio::spawn(run_surge_detector(
+                tenant_state.clone(),
+                std::time::Duration::from_secs(config.surge_check_interval_secs),
+            ));
+        }
+        tokio::spawn(run_courier_sweeper(
+            tenant_state.clone(),
+            heartbeat_settings,
+        ));
+        tokio::spawn(run_webhook_dispatcher(
+            tenant_state.clone(),
+            delivery_settings,
+        ));
+        tokio::spawn(run_customer_notifier(
+            tenant_state.clone(),
+            customer_settings.clone(),
+        ));
+        if let Some(push_settings) = &push_settings {
+            tokio::spawn(run_push_notifier(tenant_state, push_settings.clone()));
+        }
+    }
+    let engine_handle = engine_handle.expect("the default tenant has an engine");
+
+    if !config.kafka_brokers.is_empty() {
+        #[cfg(feature = "kafka")]
+        tokio::spawn(dispatch_router::kafka::run_kafka_publisher(
+            shared_state.clone(),
+            dispatch_router::kafka::KafkaSettings {
+                brokers: config.kafka_brokers.clone(),
+                assignments_topic: config.kafka_assignments_topic.clone(),
+                orders_topic: config.kafka_orders_topic.clone(),
+            },
+        ));
+        #[cfg(not(feature = "kafka"))]
+        tracing::warn!(
+            "KAFKA_BROKERS is set but this build lacks the `kafka` feature; ignoring it"
+        );
+    }
+
+    if !config.nats_url.is_empty() {
+        #[cfg(feature = "nats")]
+        tokio::spawn(dispatch_router::nats::run_nats_bridge(
+            shared_state.clone(),
+            dispatch_router::nats::NatsSettings {
+                url: config.nats_url.clone(),
+                subject_prefix: config.nats_subject_prefix.clone(),
+                jetstream: config.nats_jetstream,
+                orders_subject: config.nats_orders_subject.clone(),
+            },
+        ));
+        #[cfg(not(feature = "nats"))]
+        tracing::warn!("NATS_URL is set but this build lacks the `nats` feature; ignoring it");
+    }
+
+    if !config.mqtt_host.is_empty() {
+        #[cfg(feature = "mqtt")]
+        tokio::spawn(dispatch_router::mqtt::run_mqtt_ingestion(
+            shared_state.clone(),
+            dispatch_router::mqtt::MqttSettings {
+                host: config.mqtt_host.clone(),
+                port: config.mqtt_port,
+                client_id: config.mqtt_client_id.clone(),
+                username: config.mqtt_username.clone(),
+                password: config.mqtt_password.clone(),
+                topic: config.mqtt_topic.clone(),
+                devices: config.mqtt_device_map.clone(),
+            },
+        ));
+        #[cfg(not(feature = "mqtt"))]
+        tracing::warn!("MQTT_HOST is set but this build lacks the `mqtt` feature; ignoring it");
+    }
+
+    if !config.redis_url.is_empty() {
+        #[cfg(feature = "redis")]
+        tokio::spawn(dispatch_router::relay::run_event_relay(
+            shared_state.clone(),
+            dispatch_router::relay::RelaySettings {
+                url: config.redis_url.clone(),
+                channel: config.redis_channel.clone(),
+            },
+        ));
+        #[cfg(not(feature = "redis"))]
+        tracing::warn!("REDIS_URL is set but this build lacks the `redis` feature; ignoring it");
+    }
+
+    if config.metrics_port != 0 {
+        let metrics_addr = format!("0.0.0.0:{}", config.metrics_port);
+        let listener = tokio::net::TcpListener::bind(&metrics_addr)
+            .await
+            .map_err(|err| {
+                error::AppError::Internal(format!("failed to bind {metrics_addr}: {err}"))
+            })?;
+        let metrics_app = api::rest::metrics_router(tenants.clone());
+        tracing::info!(metrics_port = config.metrics_port, "metrics server started");
+        tokio::spawn(async move {
+            if let Err(err) = axum::serve(listener, metrics_app).await {
+                tracing::error!(error = %err, "metrics server failed");
+            }
+        });
+    }
+
+    let (mut health_reporter, health_service) = tonic_health::server::health_reporter();
+    health::report_serving(&mut health_reporter).await;
+    tokio::spawn(health::watch_engine(health_reporter, engine_handle));
+
+    // Bound up front so a taken port or bad socket path fails startup.
+    let mut grpc_listeners = Vec::new();
+    for addr in &config.grpc_listen {
+        grpc_listeners.push(listen::bind(addr).await?);
+    }
+    let grpc_auth = GrpcAuth {
+        api_keys: ApiKeyInterceptor::new(config.grpc_api_keys.clone()),
+        jwt: JwtInterceptor::new(shared_state.jwt.clone()),
+    };
+    let grpc_services = TenantServices::new(tenants.iter().map(|tenant_state| {
+        (
+            tenant_state.tenant.clone(),
+            DispatchServiceServer::with_interceptor(
+                GrpcDispatchService::new(tenant_state.clone()),
+                grpc_auth.clone(),
+            ),
+        )
+    }));
+    if !grpc_auth.api_keys.is_enabled() && shared_state.jwt.is_none() {
+        tracing::warn!("GRPC_API_KEYS is empty; gRPC API is unauthenticated");
+    }
+
+    // Draining runs while the servers still answer, so new orders get a 503
+    // rather than a refused connection; they stop once it is done.
+    let (stop_tx, stop_rx) = tokio::sync::watch::channel(false);
+    let drain_tenants = tenants.clone();
+    let drain_deadline = std::time::Duration::from_secs(config.shutdown_drain_secs);
     tokio::spawn(async move {
-        tracing::info!(grpc_port = %grpc_addr, "grpc server started");
+        shutdown::signal().await;
+        tracing::info!("shutdown requested");
+        futures::future::join_all(
+            drain_tenants
+                .iter()
+                .map(|tenant_state| shutdown::drain(tenant_state, drain_deadline)),
+        )
+        .await;
+        let _ = stop_tx.send(true);
+    });
+
+    let grpc_stop = stop_rx.clone();
+    let grpc_addrs = listed(&config.grpc_listen);
+    let grpc_server = tokio::spawn(async move {
+        tracing::info!(grpc_listen = %grpc_addrs, "grpc server started");
         if let Err(err) = TonicServer::builder()
-            .add_service(DispatchServiceServer::new(grpc_service))
-            .serve(grpc_addr)
+            .layer(AccessLogLayer::new(shared_state.metrics.clone()))
+            .trace_fn(|request| {
+                tracing::info_span!(
+                    "grpc",
+                    rpc = %request.uri().path(),
+                    request_id = request
+                        .headers()
+                        .get(api::REQUEST_ID_HEADER)
+                        .and_then(|value| value.to_str().ok()),
+                )
+            })
+            .add_service(health_service)
+            .add_service(grpc_services)
+            .serve_with_incoming_shutdown(listen::incoming(grpc_listeners), stopped(grpc_stop))
             .await
         {
             tracing::error!(error = %err, "grpc server failed");
         }
     });
 
-    let bind_addr = format!("0.0.0.0:{}", config.http_port);
-    let listener = tokio::net::TcpListener::bind(&bind_addr)
-        .await
-        .map_err(|err| error::AppError::Internal(format!("failed to bind {bind_addr}: {err}")))?;
+    // TLS covers the TCP addresses; unix sockets stay plain HTTP for the
+    // local proxy in front of them.
+    let tls_config = if config.tls_cert_path.is_empty() {
+        None
+    } else {
+        let settings = tls_settings(&config);
+        let tls_config = tls::load(&settings).await?;
+        tokio::spawn(tls::watch_certificates(tls_config.clone(), settings));
+        Some(tls_config)
+    };
+
+    let mut http_servers: Vec<BoxFuture<'static, Result<(), error::AppError>>> = Vec::new();
+    for addr in &config.http_listen {
+        let listener = listen::bind(addr).await?;
+        let stop = stopped(stop_rx.clone());
+        let app = app.clone();
+        let server_error =
+            |err: std::io::Error| error::AppError::Internal(format!("server error: {err}"));
+        match (listener, &tls_config) {
+            (listen::Listener::Tcp(listener), None) => {
+                http_servers.push(Box::pin(async move {
+                    axum::serve(
+                        listener,
+                        app.into_make_service_with_connect_info::<std::net::SocketAddr>(),
+                    )
+                    .with_graceful_shutdown(stop)
+                    .await
+                    .map_err(server_error)
+                }));
+            }
+            (listen::Listener::Tcp(listener), Some(tls_config)) => {
+                let listener = listener.into_std().map_err(server_error)?;
+                let handle = axum_server::Handle::new();
+                let shutdown = handle.clone();
+                tokio::spawn(async move {
+                    stop.await;
+                    shutdown.graceful_shutdown(None);
+                });
+                let server = axum_server::from_tcp_rustls(listener, tls_config.clone())
+                    .handle(handle)
+                    .serve(app.into_make_service_with_connect_info::<std::net::SocketAddr>());
+                http_servers.push(Box::pin(async move { server.await.map_err(server_error) }));
+            }
+            (listen::Listener::Unix(listener), _) => {
+                http_servers.push(Box::pin(listen::serve_unix(listener, app, stop)));
+            }
+        }
+    }
+    tracing::info!(
+        http_listen = %listed(&config.http_listen),
+        tls = tls_config.is_some(),
+        "http server started"
+    );
+    futures::future::try_join_all(http_servers).await?;
 
-    tracing::info!(http_port = config.http_port, "http server started");
+    if let Err(err) = grpc_server.await {
+        tracing::error!(error = %err, "grpc server task failed");
+    }
 
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
-        .await
-        .map_err(|err| error::AppError::Internal(format!("server error: {err}")))?;
+    #[cfg(feature = "otel")]
+    if let Some(provider) = tracer_provider
+        && let Err(err) = provider.shutdown()
+    {
+        tracing::warn!(error = %err, "failed to flush traces");
+    }
 
     Ok(())
 }
 
-async fn shutdown_signal() {
-    if let Err(err) = tokio::signal::ctrl_c().await {
-        tracing::error!(error = %err, "failed to listen for shutdown signal");
-    }
+fn listed(addrs: &[listen::ListenAddr]) -> String {
+    addrs
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+async fn stopped(mut stop: tokio::sync::watch::Receiver<bool>) {
+    let _ = stop.wait_for(|stopped| *stopped).await;
 }
@@ -1,16 +1,33 @@
+use std::collections::BTreeMap;
+
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use uuid::Uuid;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema)]
 pub struct ScoreBreakdown {
     pub distance_score: f64,
     pub load_score: f64,
     pub rating_score: f64,
     pub priority_score: f64,
+    /// 1 when the pickup is in one of the courier's preferred zones.
+    #[serde(default)]
+    pub zone_score: f64,
+    /// Share of offers the courier accepted; see `OfferHistory`.
+    #[serde(default)]
+    pub reliability_score: f64,
+    /// How long the courier has gone without an assignment, from 0 to 1;
+    /// favours couriers left waiting so work spreads across the fleet.
+    #[serde(default)]
+    pub idle_score: f64,
+    /// 1 for a courier that has earned nothing today, 0.5 at the fleet
+    /// median and less above it.
+    #[serde(default)]
+    pub earnings_score: f64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct Assignment {
     pub id: Uuid,
     pub order_id: Uuid,
@@ -18,4 +35,49 @@ pub struct Assignment {
     pub score: f64,
     pub score_breakdown: ScoreBreakdown,
     pub assigned_at: DateTime<Utc>,
+    /// When the order should reach its dropoff, by the ETA at assignment.
+    pub due_at: DateTime<Utc>,
+    /// Set once the courier delivers the order.
+    #[serde(default)]
+    pub delivered_at: Option<DateTime<Utc>>,
+    /// Orders the courier was already carrying or on its way to when this
+    /// one was stacked onto its route; empty for a plain assignment.
+    #[serde(default)]
+    pub stacked_with: Vec<Uuid>,
+    /// Cash the courier collects at the dropoff, copied from the order.
+    #[serde(default)]
+    pub cash_on_delivery: Option<f64>,
+}
+
+impl Assignment {
+    pub fn delivered_on_time(&self) -> bool {
+        self.delivered_at.is_some_and(|at| at <= self.due_at)
+    }
+}
+
+/// A courier the engine could give an order to, as scored for
+/// `POST /orders/preview`.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PreviewCandidate {
+    pub courier_id: Uuid,
+    pub name: String,
+    pub score: f64,
+    pub score_breakdown: ScoreBreakdown,
+    pub pickup_km: f64,
+    /// Detour the order would add to the courier's route, when it could be
+    /// stacked onto the orders the courier already has.
+    pub stacking_detour_km: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct AssignmentPreview {
+    /// The courier the engine would pick now. One that can stack the order
+    /// wins over a higher score, so it need not be the first candidate.
+    pub selected_courier_id: Option<Uuid>,
+    /// Best score first, cut to the requested limit.
+    pub candidates: Vec<PreviewCandidate>,
+    /// Couriers that could take the order, before the limit.
+    pub eligible: usize,
+    /// Couriers turned away, by the first constraint they failed.
+    pub rejected: BTreeMap<String, u64>,
 }
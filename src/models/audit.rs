@@ -0,0 +1,31 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditEntity {
+    Courier,
+    Order,
+}
+
+/// One change to a courier or order: who made it, what it was, and the
+/// entity before and after.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct AuditEntry {
+    pub seq: u64,
+    pub entity: AuditEntity,
+    pub entity_id: Uuid,
+    /// e.g. `created`, `status_changed`, `assigned`.
+    pub action: String,
+    /// The API the change came through (`rest`, `grpc`, `nats`) or `engine`
+    /// for the assignment engine's own changes.
+    pub actor: String,
+    /// Absent for creations.
+    #[schema(value_type = Option<Object>)]
+    pub old: Option<serde_json::Value>,
+    #[schema(value_type = Option<Object>)]
+    pub new: Option<serde_json::Value>,
+    pub recorded_at: DateTime<Utc>,
+}
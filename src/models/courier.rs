@@ -1,28 +1,322 @@
+use std::str::FromStr;
+
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use uuid::Uuid;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use crate::geo::{in_zone, CachedLocation, RadianPoint};
+use crate::models::order::Handling;
+use crate::models::shift::{upcoming_windows, Shift, ShiftWindow};
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct GeoPoint {
     pub lat: f64,
     pub lng: f64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
 pub enum CourierStatus {
     Available,
     Busy,
+    /// Keeps the orders it has but is offered no new ones.
+    OnBreak,
     Offline,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl CourierStatus {
+    pub const ALL: [CourierStatus; 4] = [
+        CourierStatus::Available,
+        CourierStatus::Busy,
+        CourierStatus::OnBreak,
+        CourierStatus::Offline,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CourierStatus::Available => "Available",
+            CourierStatus::Busy => "Busy",
+            CourierStatus::OnBreak => "OnBreak",
+            CourierStatus::Offline => "Offline",
+        }
+    }
+}
+
+/// What courier capacity and order sizes are measured in, one unit for the
+/// whole deployment.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CapacityUnit {
+    /// Orders counted one by one; sizes and capacities are whole numbers.
+    #[default]
+    Slots,
+    Kg,
+    Liters,
+}
+
+impl CapacityUnit {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CapacityUnit::Slots => "slots",
+            CapacityUnit::Kg => "kg",
+            CapacityUnit::Liters => "liters",
+        }
+    }
+}
+
+impl FromStr for CapacityUnit {
+    type Err = String;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        match raw.to_ascii_lowercase().as_str() {
+            "slots" => Ok(CapacityUnit::Slots),
+            "kg" => Ok(CapacityUnit::Kg),
+            "liters" | "litres" => Ok(CapacityUnit::Liters),
+            other => Err(format!(
+                "unknown unit {other}, expected slots, kg or liters"
+            )),
+        }
+    }
+}
+
+/// How couriers' preferred zones weigh on assignment, one mode for the whole
+/// deployment.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ZoneMode {
+    /// Pickups in a preferred zone score higher; any courier may still win.
+    #[default]
+    Prefer,
+    /// Couriers with preferred zones are only offered pickups inside them.
+    Strict,
+}
+
+impl FromStr for ZoneMode {
+    type Err = String;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        match raw.to_ascii_lowercase().as_str() {
+            "prefer" => Ok(ZoneMode::Prefer),
+            "strict" => Ok(ZoneMode::Strict),
+            other => Err(format!(
+                "unknown zone mode {other}, expected prefer or strict"
+            )),
+        }
+    }
+}
+
+/// Slack for sums of fractional sizes that land a hair over capacity.
+const LOAD_EPSILON: f64 = 1e-9;
+
+/// What a courier rides, from least to most cargo room.
+#[derive(
+    Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, ToSchema,
+)]
+pub enum VehicleType {
+    #[default]
+    Bike,
+    Scooter,
+    Car,
+    Van,
+}
+
+impl VehicleType {
+    /// A vehicle carries anything a smaller one could.
+    pub fn can_carry(self, required: Option<VehicleType>) -> bool {
+        required.is_none_or(|required| self >= required)
+    }
+}
+
+/// Offers a courier counts as accepted before its reliability drops below 1,
+/// so a new courier's first decline does not sink it.
+const RELIABILITY_PRIOR: f64 = 3.0;
+
+/// What became of the orders the engine handed a courier. An order counts
+/// as accepted once picked up, as declined when handed back with
+/// `POST /orders/{id}/decline`, and as timed out when the courier went
+/// silent before picking it up.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct OfferHistory {
+    pub accepted: u64,
+    pub declined: u64,
+    pub timed_out: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OfferOutcome {
+    Accepted,
+    Declined,
+    TimedOut,
+}
+
+impl OfferHistory {
+    pub fn record(&mut self, outcome: OfferOutcome) {
+        match outcome {
+            OfferOutcome::Accepted => self.accepted += 1,
+            OfferOutcome::Declined => self.declined += 1,
+            OfferOutcome::TimedOut => self.timed_out += 1,
+        }
+    }
+
+    /// Share of offers accepted, between 0 and 1, smoothed towards 1 while
+    /// there are few of them.
+    pub fn reliability(&self) -> f64 {
+        let total = (self.accepted + self.declined + self.timed_out) as f64;
+        (self.accepted as f64 + RELIABILITY_PRIOR) / (total + RELIABILITY_PRIOR)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct Courier {
     pub id: Uuid,
     pub name: String,
     pub location: GeoPoint,
-    pub capacity: u8,
-    pub current_load: u8,
+    /// In the deployment's `CAPACITY_UNIT`.
+    pub capacity: f64,
+    /// Sum of the sizes of the orders the courier is carrying.
+    pub current_load: f64,
     pub status: CourierStatus,
+    /// When an `OnBreak` courier goes back to work by itself. Without it
+    /// the break lasts until the courier changes its status.
+    #[serde(default)]
+    pub break_until: Option<DateTime<Utc>>,
+    /// Customer rating, 0 to 5.
     pub rating: f64,
+    /// How the courier handled the orders it was given; feeds the
+    /// reliability score, which is kept apart from `rating`.
+    #[serde(default)]
+    pub offers: OfferHistory,
+    /// When the engine last gave the courier an order; feeds the idle
+    /// score. `None` if it never has.
+    #[serde(default)]
+    pub last_assigned_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub vehicle_type: VehicleType,
     pub updated_at: DateTime<Utc>,
+    /// Last sign of life from the courier: a location ping, a status change
+    /// or a heartbeat.
+    #[serde(default = "Utc::now")]
+    pub last_seen_at: DateTime<Utc>,
+    /// Working hours. A courier without shifts can be assigned at any time.
+    #[serde(default)]
+    pub shifts: Vec<Shift>,
+    /// Lowercase tags such as `alcohol-certified` or `heavy-lift`; only
+    /// orders whose required skills are all here are offered to the courier.
+    #[serde(default)]
+    pub skills: Vec<String>,
+    /// Geohash zones the courier would rather work in, of any precision.
+    #[serde(default)]
+    pub preferred_zones: Vec<String>,
+    /// Whether the courier takes cash-on-delivery orders.
+    #[serde(default)]
+    pub accepts_cod: bool,
+    /// Cash float: the most cash the courier may be due to collect across
+    /// its undelivered orders. `None` for no limit.
+    #[serde(default)]
+    pub cod_limit: Option<f64>,
+    /// Handling the courier is equipped for, e.g. a cool bag for `Chilled`.
+    #[serde(default)]
+    pub equipment: Vec<Handling>,
+    /// What scoring derives from `location`, kept by `set_location` so it
+    /// is not worked out again for every order.
+    #[serde(skip)]
+    pub location_cache: CachedLocation,
+}
+
+impl Courier {
+    pub fn set_location(&mut self, location: GeoPoint) {
+        self.location_cache = CachedLocation::new(&location);
+        self.location = location;
+    }
+
+    /// `location` in radians, from the cache when it is current.
+    pub fn location_radians(&self) -> RadianPoint {
+        self.location_cache.radians(&self.location)
+    }
+
+    /// `cell_id` of `location` at `DISTANCE_CELL_PRECISION`, from the cache
+    /// when it is current.
+    pub fn location_cell(&self) -> u64 {
+        self.location_cache.cell(&self.location)
+    }
+
+    /// Whether an order of `size` fits next to what the courier carries.
+    pub fn has_room_for(&self, size: f64) -> bool {
+        self.current_load + size <= self.capacity + LOAD_EPSILON
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.current_load + LOAD_EPSILON >= self.capacity
+    }
+
+    /// Takes `size` off the load, never going below empty.
+    pub fn unload(&mut self, size: f64) {
+        self.current_load = (self.current_load - size).max(0.0);
+    }
+
+    /// Required skills the courier lacks.
+    pub fn missing_skills<'a>(&self, required: &'a [String]) -> Vec<&'a str> {
+        required
+            .iter()
+            .filter(|skill| !self.skills.contains(skill))
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Handling the order needs that the courier's equipment cannot give.
+    pub fn missing_handling(&self, required: &[Handling]) -> Vec<Handling> {
+        required
+            .iter()
+            .copied()
+            .filter(|handling| !handling.is_met_by(&self.equipment))
+            .collect()
+    }
+
+    /// Whether the courier could ever collect `amount` on delivery.
+    pub fn can_collect(&self, amount: f64) -> bool {
+        self.accepts_cod && self.cod_limit.is_none_or(|limit| amount <= limit)
+    }
+
+    /// Whether `point` lies in one of the courier's preferred zones.
+    pub fn prefers_zone_of(&self, point: &GeoPoint) -> bool {
+        self.preferred_zones.iter().any(|zone| in_zone(point, zone))
+    }
+
+    /// Whether the engine may hand the courier work at `at`.
+    pub fn is_on_shift(&self, at: DateTime<Utc>) -> bool {
+        self.shifts.is_empty()
+            || upcoming_windows(&self.shifts, at)
+                .first()
+                .is_some_and(|window| window.contains(at))
+    }
+
+    /// The shift running at `at`, if any, and the next one to start after it.
+    pub fn shift_windows(&self, at: DateTime<Utc>) -> (Option<ShiftWindow>, Option<ShiftWindow>) {
+        let mut windows = upcoming_windows(&self.shifts, at).into_iter().peekable();
+        let current = windows.next_if(|window| window.contains(at));
+        (current, windows.next())
+    }
+}
+
+/// Published whenever a courier's position changes.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CourierLocation {
+    pub courier_id: Uuid,
+    pub location: GeoPoint,
+    pub status: CourierStatus,
+    pub updated_at: DateTime<Utc>,
+    /// Received from another instance through the event relay rather than
+    /// produced here.
+    #[serde(skip)]
+    pub relayed: bool,
+}
+
+impl CourierLocation {
+    pub fn from_courier(courier: &Courier) -> Self {
+        Self {
+            courier_id: courier.id,
+            location: courier.location.clone(),
+            status: courier.status.clone(),
+            updated_at: courier.updated_at,
+            relayed: false,
+        }
+    }
 }
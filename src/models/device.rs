@@ -0,0 +1,20 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PushPlatform {
+    Fcm,
+    Apns,
+}
+
+/// A courier's phone, registered to receive assignment notifications.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CourierDevice {
+    pub courier_id: Uuid,
+    pub platform: PushPlatform,
+    pub token: String,
+    pub registered_at: DateTime<Utc>,
+}
@@ -0,0 +1,77 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::models::assignment::Assignment;
+use crate::models::courier::{Courier, CourierLocation};
+use crate::models::order::{DeliveryOrder, OrderEvent};
+use crate::models::report::DailyReport;
+use crate::models::surge::SurgeEvent;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Topic {
+    Assignments,
+    Orders,
+    CourierLocations,
+    Reports,
+    Surges,
+    Changes,
+}
+
+impl Topic {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Topic::Assignments => "assignments",
+            Topic::Orders => "orders",
+            Topic::CourierLocations => "courier_locations",
+            Topic::Reports => "reports",
+            Topic::Surges => "surges",
+            Topic::Changes => "changes",
+        }
+    }
+}
+
+/// An event as pushed to stream consumers: `{"topic": "...", "data": {...}}`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "topic", content = "data", rename_all = "snake_case")]
+pub enum DispatchEvent {
+    #[serde(rename = "assignments")]
+    Assignment(Assignment),
+    #[serde(rename = "orders")]
+    Order(OrderEvent),
+    #[serde(rename = "courier_locations")]
+    CourierLocation(CourierLocation),
+    /// A day's report, once it is generated.
+    #[serde(rename = "reports")]
+    Report(DailyReport),
+    #[serde(rename = "surges")]
+    Surge(SurgeEvent),
+    /// Only published with `PUBLISH_CHANGES` on, for standbys.
+    #[serde(rename = "changes")]
+    Change(EntityChange),
+}
+
+/// A courier or order as a whole, as it is after a change. Standbys apply
+/// these to mirror the instance; see `crate::standby`. A courier that only
+/// moves publishes a `CourierLocation` instead.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "kind", content = "entity", rename_all = "snake_case")]
+pub enum EntityChange {
+    Courier(Arc<Courier>),
+    Order(Box<DeliveryOrder>),
+}
+
+impl DispatchEvent {
+    pub fn topic(&self) -> Topic {
+        match self {
+            DispatchEvent::Assignment(_) => Topic::Assignments,
+            DispatchEvent::Order(_) => Topic::Orders,
+            DispatchEvent::CourierLocation(_) => Topic::CourierLocations,
+            DispatchEvent::Report(_) => Topic::Reports,
+            DispatchEvent::Surge(_) => Topic::Surges,
+            DispatchEvent::Change(_) => Topic::Changes,
+        }
+    }
+}
@@ -0,0 +1,18 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// The customer's verdict on a delivered order, at most one per order.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Feedback {
+    pub order_id: Uuid,
+    /// The courier who delivered the order.
+    pub courier_id: Uuid,
+    /// 1 to 5.
+    pub stars: u8,
+    pub comment: Option<String>,
+    /// The courier's rating once these stars were taken in.
+    pub rating_after: f64,
+    pub created_at: DateTime<Utc>,
+}
@@ -0,0 +1,36 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// A step in an order's life.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderHistoryEvent {
+    Created,
+    /// Put in the assignment queue, when created and whenever it is handed
+    /// back; the engine's own retries are not listed.
+    Queued,
+    /// Offered to a courier by the engine; the order is `Assigned` until the
+    /// courier picks it up or declines.
+    Assigned,
+    Declined,
+    /// Taken back from a courier who went silent before pickup.
+    Unassigned,
+    PickedUp,
+    Delivered,
+    Rejected,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct OrderHistoryEntry {
+    pub event: OrderHistoryEvent,
+    /// Who caused it: the token subject or API (`rest`, `grpc`, `nats`),
+    /// `engine`, or `heartbeat`.
+    pub actor: String,
+    /// The courier involved, if any.
+    pub courier_id: Option<Uuid>,
+    /// More on what happened, e.g. why an order was rejected.
+    pub detail: Option<String>,
+    pub at: DateTime<Utc>,
+}
@@ -1,3 +1,14 @@
 pub mod assignment;
+pub mod audit;
 pub mod courier;
+pub mod device;
+pub mod event;
+pub mod feedback;
+pub mod history;
 pub mod order;
+pub mod report;
+pub mod route;
+pub mod shift;
+pub mod stats;
+pub mod surge;
+pub mod webhook;
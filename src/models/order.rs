@@ -1,10 +1,12 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use uuid::Uuid;
 
-use crate::models::courier::GeoPoint;
+use crate::geo::haversine_km;
+use crate::models::courier::{GeoPoint, VehicleType};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub enum Priority {
     Low,
     Normal,
@@ -12,21 +14,249 @@ pub enum Priority {
     Urgent,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+impl Priority {
+    pub const ALL: [Priority; 4] = [
+        Priority::Low,
+        Priority::Normal,
+        Priority::High,
+        Priority::Urgent,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Priority::Low => "Low",
+            Priority::Normal => "Normal",
+            Priority::High => "High",
+            Priority::Urgent => "Urgent",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
 pub enum OrderStatus {
     Pending,
     Assigned,
     InTransit,
     Delivered,
+    /// Taken out of the queue for good; the reason is on its entry in the
+    /// order dead letters.
+    Rejected,
+}
+
+impl OrderStatus {
+    pub const ALL: [OrderStatus; 5] = [
+        OrderStatus::Pending,
+        OrderStatus::Assigned,
+        OrderStatus::InTransit,
+        OrderStatus::Delivered,
+        OrderStatus::Rejected,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrderStatus::Pending => "Pending",
+            OrderStatus::Assigned => "Assigned",
+            OrderStatus::InTransit => "InTransit",
+            OrderStatus::Delivered => "Delivered",
+            OrderStatus::Rejected => "Rejected",
+        }
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct DeliveryOrder {
     pub id: Uuid,
+    /// The first pickup; the courier is scored by its distance to it.
     pub pickup: GeoPoint,
     pub dropoff: GeoPoint,
+    /// Further pickups, visited in order after `pickup` and before the
+    /// dropoff, e.g. a pharmacy after a restaurant.
+    #[serde(default)]
+    pub extra_pickups: Vec<GeoPoint>,
     pub priority: Priority,
     pub status: OrderStatus,
     pub assigned_courier: Option<Uuid>,
     pub created_at: DateTime<Utc>,
+    /// Capacity the order takes up, in the deployment's `CAPACITY_UNIT`.
+    #[serde(default = "default_size")]
+    pub size: f64,
+    /// What the order contains; when given, `size` is their total weight.
+    #[serde(default)]
+    pub items: Vec<OrderItem>,
+    /// Cash the courier collects at the dropoff; `None` for prepaid orders.
+    /// Only couriers that accept cash on delivery are offered it.
+    #[serde(default)]
+    pub cash_on_delivery: Option<f64>,
+    /// Smallest vehicle that can carry the order; couriers on anything
+    /// smaller are never offered it.
+    #[serde(default)]
+    pub required_vehicle: Option<VehicleType>,
+    /// Skill tags the assigned courier must all have.
+    #[serde(default)]
+    pub required_skills: Vec<String>,
+    /// Care the parcel needs on the way; only couriers equipped for all of
+    /// it are offered the order.
+    #[serde(default)]
+    pub handling: Vec<Handling>,
+    /// Set while the engine keeps finding no courier for the order.
+    #[serde(default)]
+    pub waiting_reason: Option<WaitingReason>,
+    /// Couriers who handed the order back; it is not offered to them again.
+    #[serde(default)]
+    pub declined_by: Vec<Uuid>,
+    /// Receives a tracking snapshot on the status transitions the deployment
+    /// notifies customers about.
+    #[serde(default)]
+    pub callback_url: Option<String>,
+    /// Phone number, email or other reference the notification gateway
+    /// understands.
+    #[serde(default)]
+    pub customer_contact: Option<String>,
+    /// `x-request-id` of the request that created the order, carried into
+    /// the engine's spans so its logs can be found by that ID.
+    #[serde(default)]
+    pub request_id: Option<String>,
+}
+
+impl DeliveryOrder {
+    /// Length of the trip from the first pickup through the others to the
+    /// dropoff.
+    pub fn route_km(&self) -> f64 {
+        let mut km = 0.0;
+        let mut from = &self.pickup;
+        for stop in self.extra_pickups.iter().chain([&self.dropoff]) {
+            km += haversine_km(from, stop);
+            from = stop;
+        }
+        km
+    }
+}
+
+/// One line of an order's contents.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct OrderItem {
+    pub description: String,
+    /// Weight of one unit, in the deployment's `CAPACITY_UNIT`.
+    pub weight: f64,
+    #[serde(default = "default_quantity")]
+    pub quantity: u32,
+}
+
+impl OrderItem {
+    pub fn total_weight(&self) -> f64 {
+        self.weight * f64::from(self.quantity)
+    }
+}
+
+fn default_quantity() -> u32 {
+    1
+}
+
+/// Why the engine could not assign a pending order on its last attempt.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub enum WaitingReason {
+    /// Couriers who could take the order exist but none is free, on shift
+    /// and with room right now.
+    NoCourierAvailable,
+    /// No courier in the fleet has the vehicle, skills or capacity the order
+    /// needs; it waits until one is added or changed.
+    NoQualifiedCourier,
+}
+
+/// Care a parcel needs in transit, and the equipment a courier carries to
+/// give it.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub enum Handling {
+    /// Padded box, no stacking.
+    Fragile,
+    /// Cool bag.
+    Chilled,
+    /// Freezer box, which keeps chilled parcels cold as well.
+    Frozen,
+}
+
+impl Handling {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Handling::Fragile => "Fragile",
+            Handling::Chilled => "Chilled",
+            Handling::Frozen => "Frozen",
+        }
+    }
+
+    /// Whether a courier carrying `equipment` can give this handling.
+    pub fn is_met_by(self, equipment: &[Handling]) -> bool {
+        equipment.contains(&self)
+            || (self == Handling::Chilled && equipment.contains(&Handling::Frozen))
+    }
+}
+
+/// An order the engine gave up on, with why.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct OrderDeadLetter {
+    pub order_id: Uuid,
+    pub reason: String,
+    pub rejected_at: DateTime<Utc>,
+}
+
+/// One slot, what every order took before sizes existed.
+pub fn default_size() -> f64 {
+    1.0
+}
+
+/// An order as submitted, whichever way it came in.
+#[derive(Debug, Clone)]
+pub struct NewOrder {
+    pub pickup: GeoPoint,
+    pub dropoff: GeoPoint,
+    pub extra_pickups: Vec<GeoPoint>,
+    pub priority: Priority,
+    /// `None` for the items' total weight, or 1 without items.
+    pub size: Option<f64>,
+    pub items: Vec<OrderItem>,
+    pub cash_on_delivery: Option<f64>,
+    pub required_vehicle: Option<VehicleType>,
+    pub required_skills: Vec<String>,
+    pub handling: Vec<Handling>,
+    pub callback_url: Option<String>,
+    pub customer_contact: Option<String>,
+    pub request_id: Option<String>,
+}
+
+/// Emitted on every order lifecycle transition: `Pending` when created,
+/// then `Assigned`, `InTransit` (picked up) and `Delivered`, or `Rejected`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct OrderEvent {
+    pub order_id: Uuid,
+    pub status: OrderStatus,
+    pub courier_id: Option<Uuid>,
+    pub pickup: GeoPoint,
+    pub occurred_at: DateTime<Utc>,
+    #[serde(default)]
+    pub items: Vec<OrderItem>,
+}
+
+impl OrderEvent {
+    pub fn from_order(order: &DeliveryOrder, occurred_at: DateTime<Utc>) -> Self {
+        Self {
+            order_id: order.id,
+            status: order.status.clone(),
+            courier_id: order.assigned_courier,
+            pickup: order.pickup.clone(),
+            occurred_at,
+            items: order.items.clone(),
+        }
+    }
+}
+
+/// Live view of a single order for "track my delivery" pages.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct OrderTracking {
+    pub order_id: Uuid,
+    pub status: OrderStatus,
+    pub courier_id: Option<Uuid>,
+    pub courier_location: Option<GeoPoint>,
+    /// Estimated seconds until dropoff; unknown until a courier is assigned.
+    pub eta_seconds: Option<u64>,
+    pub updated_at: DateTime<Utc>,
 }
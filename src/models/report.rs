@@ -0,0 +1,36 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// One UTC day of dispatch, from `GET /reports/{date}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct DailyReport {
+    pub date: NaiveDate,
+    pub generated_at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub totals: ReportTotals,
+    /// The same figures per pickup zone, busiest first.
+    pub zones: Vec<ZoneReport>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ReportTotals {
+    /// Orders created that day.
+    pub orders: u64,
+    /// Assignments made that day.
+    pub assignments: u64,
+    /// Orders delivered that day.
+    pub deliveries: u64,
+    /// Of those, the ones delivered by the assignment's `due_at`.
+    pub on_time_deliveries: u64,
+    /// `on_time_deliveries / deliveries`; `None` without deliveries.
+    pub sla_hit_rate: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ZoneReport {
+    /// The 5-character geohash cell the orders were picked up in.
+    pub zone: String,
+    #[serde(flatten)]
+    pub totals: ReportTotals,
+}
@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::models::courier::GeoPoint;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum StopKind {
+    Pickup,
+    Dropoff,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct RouteStop {
+    pub order_id: Uuid,
+    pub kind: StopKind,
+    pub location: GeoPoint,
+}
+
+/// The stops a courier has left across all its orders, in the order to
+/// visit them.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CourierRoute {
+    pub courier_id: Uuid,
+    pub stops: Vec<RouteStop>,
+    /// From the courier's current location through every stop.
+    pub distance_km: f64,
+}
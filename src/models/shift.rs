@@ -0,0 +1,105 @@
+use chrono::{DateTime, Datelike, Days, NaiveDate, NaiveTime, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// A recurring block of working hours, in UTC. A shift whose `end` is not
+/// after its `start` runs overnight and ends the next day.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Shift {
+    /// Days the shift starts on.
+    #[schema(value_type = Vec<String>, example = json!(["Mon", "Tue", "Wed"]))]
+    pub weekdays: Vec<Weekday>,
+    #[schema(value_type = String, example = "08:00:00")]
+    pub start: NaiveTime,
+    #[schema(value_type = String, example = "16:00:00")]
+    pub end: NaiveTime,
+}
+
+/// One occurrence of a shift.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ShiftWindow {
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+}
+
+impl ShiftWindow {
+    pub fn contains(&self, at: DateTime<Utc>) -> bool {
+        self.starts_at <= at && at < self.ends_at
+    }
+}
+
+impl Shift {
+    /// The occurrence starting on `date`, if the shift runs that weekday.
+    pub fn window_on(&self, date: NaiveDate) -> Option<ShiftWindow> {
+        if !self.weekdays.contains(&date.weekday()) {
+            return None;
+        }
+        let end_date = if self.end > self.start {
+            date
+        } else {
+            date.checked_add_days(Days::new(1))?
+        };
+        Some(ShiftWindow {
+            starts_at: date.and_time(self.start).and_utc(),
+            ends_at: end_date.and_time(self.end).and_utc(),
+        })
+    }
+}
+
+/// Occurrences of `shifts` that have not ended by `after`, earliest first,
+/// looking a week ahead.
+pub fn upcoming_windows(shifts: &[Shift], after: DateTime<Utc>) -> Vec<ShiftWindow> {
+    // Starting a day early catches an overnight shift that is still running.
+    let first = after.date_naive().pred_opt().unwrap_or(NaiveDate::MIN);
+    let mut windows: Vec<ShiftWindow> = first
+        .iter_days()
+        .take(9)
+        .flat_map(|date| shifts.iter().filter_map(move |shift| shift.window_on(date)))
+        .filter(|window| window.ends_at > after)
+        .collect();
+    windows.sort_by_key(|window| window.starts_at);
+    windows
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, NaiveTime, Utc, Weekday};
+
+    use super::{upcoming_windows, Shift};
+
+    fn at(raw: &str) -> DateTime<Utc> {
+        raw.parse().unwrap()
+    }
+
+    fn shift(weekdays: &[Weekday], start: u32, end: u32) -> Shift {
+        Shift {
+            weekdays: weekdays.to_vec(),
+            start: NaiveTime::from_hms_opt(start, 0, 0).unwrap(),
+            end: NaiveTime::from_hms_opt(end, 0, 0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn finds_the_running_and_the_next_occurrence() {
+        // 2026-10-12 is a Monday.
+        let shifts = [shift(&[Weekday::Mon, Weekday::Wed], 8, 16)];
+
+        let windows = upcoming_windows(&shifts, at("2026-10-12T09:00:00Z"));
+        assert!(windows[0].contains(at("2026-10-12T09:00:00Z")));
+        assert_eq!(windows[1].starts_at, at("2026-10-14T08:00:00Z"));
+
+        let windows = upcoming_windows(&shifts, at("2026-10-12T16:00:00Z"));
+        assert!(!windows[0].contains(at("2026-10-12T16:00:00Z")));
+        assert_eq!(windows[0].starts_at, at("2026-10-14T08:00:00Z"));
+    }
+
+    #[test]
+    fn overnight_shifts_end_the_next_day() {
+        let shifts = [shift(&[Weekday::Fri], 22, 6)];
+
+        let windows = upcoming_windows(&shifts, at("2026-10-17T05:30:00Z"));
+        assert_eq!(windows[0].starts_at, at("2026-10-16T22:00:00Z"));
+        assert_eq!(windows[0].ends_at, at("2026-10-17T06:00:00Z"));
+        assert!(windows[0].contains(at("2026-10-17T05:30:00Z")));
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Running totals of the deliveries a courier has completed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct DeliveryStats {
+    pub deliveries: u64,
+    /// Route distance of the delivered orders, from the first pickup through
+    /// any others to the dropoff.
+    pub distance_km: f64,
+    /// Sum of the payouts for those deliveries, in whatever currency the
+    /// `PAYOUT_*` rates are set in.
+    pub earnings: f64,
+}
+
+impl DeliveryStats {
+    pub fn record(&mut self, distance_km: f64, payout: f64) {
+        self.deliveries += 1;
+        self.distance_km += distance_km;
+        self.earnings += payout;
+    }
+
+    pub fn add(&mut self, other: &DeliveryStats) {
+        self.deliveries += other.deliveries;
+        self.distance_km += other.distance_km;
+        self.earnings += other.earnings;
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CourierStats {
+    pub courier_id: Uuid,
+    #[serde(flatten)]
+    pub totals: DeliveryStats,
+    /// Orders assigned to the courier and not yet delivered.
+    pub active_orders: usize,
+    /// Earnings since midnight UTC, which the earnings score goes by.
+    pub earnings_today: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct FleetStats {
+    pub couriers: usize,
+    /// Couriers with at least one delivery.
+    pub active_couriers: usize,
+    #[serde(flatten)]
+    pub totals: DeliveryStats,
+    /// Median earnings since midnight UTC over the couriers paid anything
+    /// today.
+    pub median_earnings_today: Option<f64>,
+}
+
+/// How dispatch is going, from `GET /stats`. Order counts are as of now;
+/// the rest covers the last `window_secs`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DispatchSummary {
+    /// Orders in each status, e.g. `{"Pending": 3, "Assigned": 12, ...}`.
+    pub orders_by_status: BTreeMap<String, u64>,
+    pub window_secs: u64,
+    /// Orders the engine took from its queues; one requeued for lack of a
+    /// courier is taken again on every retry.
+    pub attempts: u64,
+    pub assignments: u64,
+    /// `assignments / attempts`; `None` without attempts.
+    pub success_rate: Option<f64>,
+    /// From an order's creation to its assignment; `None` without
+    /// assignments.
+    pub mean_assignment_latency_seconds: Option<f64>,
+    /// Estimated from buckets, so accurate to within one bucket.
+    pub p95_assignment_latency_seconds: Option<f64>,
+    pub mean_pickup_km: Option<f64>,
+    /// Courier utilization after each change in load, in tenths: entry `i`
+    /// counts changes that left a courier between `i / 10` and
+    /// `(i + 1) / 10` of its capacity, the last one including full.
+    pub utilization: Vec<u64>,
+}
+
+/// Recent dispatch minute by minute, from `GET /stats/timeseries`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TimeSeries {
+    /// Length of each point, 60.
+    pub interval_secs: u64,
+    /// Oldest first, the last one being the minute under way.
+    pub points: Vec<TimeSeriesPoint>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TimeSeriesPoint {
+    /// When the minute started.
+    pub at: DateTime<Utc>,
+    /// Orders created in it.
+    pub orders: u64,
+    /// Assignments made in it.
+    pub assignments: u64,
+    /// Mean score of those assignments; `None` without any.
+    pub mean_score: Option<f64>,
+}
+
+/// What couriers are ranked by on the leaderboard.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LeaderboardMetric {
+    /// Orders delivered in the period.
+    #[default]
+    Deliveries,
+    /// Customer rating, among couriers assigned or delivering in the period.
+    Rating,
+    /// Share of the period's deliveries that arrived by their `due_at`.
+    OnTimeRate,
+}
+
+/// How far back the leaderboard looks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LeaderboardPeriod {
+    /// The last 24 hours.
+    #[default]
+    Day,
+    /// The last 7 days.
+    Week,
+}
+
+impl LeaderboardPeriod {
+    pub fn duration(&self) -> chrono::Duration {
+        match self {
+            LeaderboardPeriod::Day => chrono::Duration::days(1),
+            LeaderboardPeriod::Week => chrono::Duration::weeks(1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct LeaderboardEntry {
+    /// 1 for the top courier; couriers with the same value share a rank.
+    pub rank: usize,
+    pub courier_id: Uuid,
+    pub name: String,
+    /// The ranked metric.
+    pub value: f64,
+    /// Orders delivered in the period, whatever the metric.
+    pub deliveries: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Leaderboard {
+    pub metric: LeaderboardMetric,
+    pub period: LeaderboardPeriod,
+    /// Start of the period; it ends now.
+    pub since: DateTime<Utc>,
+    pub entries: Vec<LeaderboardEntry>,
+}
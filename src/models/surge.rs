@@ -0,0 +1,17 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// A zone going into or out of surge, published on the `surges` topic.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct SurgeEvent {
+    /// The 5-character geohash cell of the pickups.
+    pub zone: String,
+    /// Whether the zone is now in surge.
+    pub surging: bool,
+    /// Orders waiting for a courier with their pickup in the zone.
+    pub pending_orders: u64,
+    /// Couriers in the zone that are available and have room.
+    pub available_couriers: u64,
+    pub at: DateTime<Utc>,
+}
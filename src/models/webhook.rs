@@ -0,0 +1,32 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::models::event::Topic;
+
+/// An external endpoint that receives assignment and order events by POST.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Webhook {
+    pub id: Uuid,
+    pub url: String,
+    /// HMAC-SHA256 key for the `X-Dispatch-Signature` header; never echoed back.
+    #[serde(skip_serializing)]
+    pub secret: String,
+    pub event_types: Vec<Topic>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A delivery that still failed after the last retry.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DeadLetter {
+    pub id: Uuid,
+    pub webhook_id: Uuid,
+    pub event_seq: u64,
+    pub topic: Topic,
+    #[schema(value_type = Object)]
+    pub payload: serde_json::Value,
+    pub attempts: u32,
+    pub last_error: String,
+    pub failed_at: DateTime<Utc>,
+}
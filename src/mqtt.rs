@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rumqttc::{AsyncClient, Event, EventLoop, Incoming, MqttOptions, QoS};
+use serde::Deserialize;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::engine::location::move_courier;
+use crate::models::courier::GeoPoint;
+use crate::state::AppState;
+
+/// Pause before polling again after the connection drops; rumqttc reconnects
+/// on the next poll.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone)]
+pub struct MqttSettings {
+    pub host: String,
+    pub port: u16,
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Topic filter with a single `+` standing for the device ID, e.g.
+    /// `couriers/+/location`.
+    pub topic: String,
+    /// Device ID to courier ID. Devices not listed here must publish under
+    /// their courier's ID.
+    pub devices: HashMap<String, Uuid>,
+}
+
+impl MqttSettings {
+    /// The segment of `topic` that the filter's `+` matched.
+    fn device_id<'a>(&self, topic: &'a str) -> Option<&'a str> {
+        let mut filter = self.topic.split('/');
+        let mut segments = topic.split('/');
+        let mut device_id = None;
+        loop {
+            match (filter.next(), segments.next()) {
+                (Some("+"), Some(segment)) => device_id = Some(segment),
+                (Some(expected), Some(segment)) if expected == segment => {}
+                (None, None) => return device_id,
+                _ => return None,
+            }
+        }
+    }
+
+    fn courier_id(&self, device_id: &str) -> Option<Uuid> {
+        self.devices
+            .get(device_id)
+            .copied()
+            .or_else(|| Uuid::parse_str(device_id).ok())
+    }
+}
+
+/// Position as tracker firmware commonly reports it; `lon` is accepted for
+/// `lng`.
+#[derive(Debug, Deserialize)]
+struct DevicePosition {
+    lat: f64,
+    #[serde(alias = "lon")]
+    lng: f64,
+}
+
+/// Subscribes to the device topic and applies each position to its courier.
+/// Runs until the process exits, reconnecting whenever the broker drops us.
+pub async fn run_mqtt_ingestion(state: Arc<AppState>, settings: MqttSettings) {
+    let mut options = MqttOptions::new(&settings.client_id, &settings.host, settings.port);
+    options.set_keep_alive(Duration::from_secs(30));
+    if let Some(username) = &settings.username {
+        options.set_credentials(username, settings.password.clone().unwrap_or_default());
+    }
+
+    let (client, mut eventloop) = AsyncClient::new(options, 64);
+    info!(host = %settings.host, port = settings.port, topic = %settings.topic, "mqtt ingestion started");
+
+    loop {
+        poll(&state, &settings, &client, &mut eventloop).await;
+    }
+}
+
+async fn poll(
+    state: &AppState,
+    settings: &MqttSettings,
+    client: &AsyncClient,
+    eventloop: &mut EventLoop,
+) {
+    match eventloop.poll().await {
+        // Subscribe on every (re)connect; the broker forgets clean sessions.
+        Ok(Event::Incoming(Incoming::ConnAck(_))) => {
+            if let Err(err) = client.try_subscribe(&settings.topic, QoS::AtMostOnce) {
+                warn!(error = %err, topic = %settings.topic, "failed to subscribe to mqtt topic");
+            }
+        }
+        Ok(Event::Incoming(Incoming::Publish(publish))) => {
+            match apply_position(state, settings, &publish.topic, &publish.payload) {
+                Ok(()) => state.metrics.record_mqtt_message("accepted"),
+                Err(error) => {
+                    state.metrics.record_mqtt_message("rejected");
+                    warn!(topic = %publish.topic, error, "rejected mqtt location");
+                }
+            }
+        }
+        Ok(_) => {}
+        Err(err) => {
+            warn!(error = %err, "mqtt connection error, reconnecting");
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    }
+}
+
+fn apply_position(
+    state: &AppState,
+    settings: &MqttSettings,
+    topic: &str,
+    payload: &[u8],
+) -> Result<(), String> {
+    let device_id = settings
+        .device_id(topic)
+        .ok_or_else(|| "topic does not match the device filter".to_string())?;
+    let courier_id = settings
+        .courier_id(device_id)
+        .ok_or_else(|| format!("device {device_id} is not mapped to a courier"))?;
+    let position: DevicePosition =
+        serde_json::from_slice(payload).map_err(|err| format!("invalid position: {err}"))?;
+
+    move_courier(
+        state,
+        courier_id,
+        GeoPoint {
+            lat: position.lat,
+            lng: position.lng,
+        },
+    )
+    .map(|_| ())
+    .map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use uuid::Uuid;
+
+    use super::MqttSettings;
+
+    #[test]
+    fn resolves_couriers_from_device_topics() {
+        let mapped = Uuid::new_v4();
+        let direct = Uuid::new_v4();
+        let settings = MqttSettings {
+            host: "localhost".to_string(),
+            port: 1883,
+            client_id: "dispatch-router".to_string(),
+            username: None,
+            password: None,
+            topic: "couriers/+/location".to_string(),
+            devices: HashMap::from([("tracker-17".to_string(), mapped)]),
+        };
+
+        assert_eq!(
+            settings.device_id("couriers/tracker-17/location"),
+            Some("tracker-17")
+        );
+        assert_eq!(settings.device_id("couriers/tracker-17/battery"), None);
+        assert_eq!(settings.courier_id("tracker-17"), Some(mapped));
+        assert_eq!(settings.courier_id(&direct.to_string()), Some(direct));
+        assert_eq!(settings.courier_id("tracker-99"), None);
+    }
+}
@@ -0,0 +1,215 @@
+use std::sync::Arc;
+
+use async_nats::jetstream;
+use serde_json::json;
+use tokio_stream::StreamExt;
+use tracing::{info, warn};
+
+use crate::api::rest::orders::CreateOrderRequest;
+use crate::api::REQUEST_ID_HEADER;
+use crate::engine::queue::submit_order;
+use crate::events::{Lagged, RecordedEvent};
+use crate::models::event::Topic;
+use crate::models::order::NewOrder;
+use crate::state::AppState;
+
+/// Queue group for order ingestion, so replicas share the subject instead of
+/// each creating every order.
+const ORDERS_QUEUE_GROUP: &str = "dispatch-router";
+
+#[derive(Debug, Clone)]
+pub struct NatsSettings {
+    pub url: String,
+    /// Events are published to `<prefix>.assignments` and `<prefix>.orders`.
+    pub subject_prefix: String,
+    /// Publish through JetStream and wait for the stream's ack. A stream
+    /// covering the subjects must already exist.
+    pub jetstream: bool,
+    /// Subject to take `CreateOrderRequest` JSON from; empty disables ingestion.
+    pub orders_subject: String,
+}
+
+impl NatsSettings {
+    fn event_subject(&self, topic: Topic) -> String {
+        format!("{}.{}", self.subject_prefix, topic.as_str())
+    }
+}
+
+/// Connects to NATS, then publishes events and, when configured, ingests
+/// orders until the connection is closed.
+pub async fn run_nats_bridge(state: Arc<AppState>, settings: NatsSettings) {
+    let client = match async_nats::connect(&settings.url).await {
+        Ok(client) => client,
+        Err(err) => {
+            warn!(error = %err, url = %settings.url, "failed to connect to nats, nats bridge disabled");
+            return;
+        }
+    };
+
+    info!(url = %settings.url, jetstream = settings.jetstream, "nats bridge started");
+
+    if !settings.orders_subject.is_empty() {
+        tokio::spawn(consume_orders(
+            state.clone(),
+            client.clone(),
+            settings.orders_subject.clone(),
+        ));
+    }
+
+    publish_events(state, client, settings).await;
+}
+
+async fn publish_events(state: Arc<AppState>, client: async_nats::Client, settings: NatsSettings) {
+    let jetstream = settings.jetstream.then(|| jetstream::new(client.clone()));
+    let mut events = Box::pin(state.events.stream(None, None));
+    let mut last_seq = 0;
+
+    while let Some(item) = events.next().await {
+        let batch = match item {
+            Ok(recorded) if recorded.seq <= last_seq => continue,
+            Ok(recorded) => vec![recorded],
+            Err(Lagged(dropped)) => {
+                state.metrics.record_dropped_events("nats", dropped);
+                warn!(dropped, "nats publisher lagged, replaying from buffer");
+                state.events.replay(Some(last_seq), None)
+            }
+        };
+
+        for recorded in batch {
+            last_seq = last_seq.max(recorded.seq);
+            publish(&state, &client, jetstream.as_ref(), &settings, &recorded).await;
+        }
+    }
+
+    info!("nats publisher stopped");
+}
+
+async fn publish(
+    state: &Arc<AppState>,
+    client: &async_nats::Client,
+    jetstream: Option<&jetstream::Context>,
+    settings: &NatsSettings,
+    recorded: &RecordedEvent,
+) {
+    if recorded.relayed || recorded.event.topic() == Topic::Changes {
+        return;
+    }
+    let subject = settings.event_subject(recorded.event.topic());
+    let payload = match serde_json::to_vec(recorded) {
+        Ok(payload) => payload,
+        Err(err) => {
+            warn!(error = %err, "failed to serialize nats payload");
+            return;
+        }
+    };
+
+    let Some(jetstream) = jetstream else {
+        match client.publish(subject.clone(), payload.into()).await {
+            Ok(()) => state.metrics.record_nats_message("published"),
+            Err(err) => record_failure(state, &subject, recorded.seq, &err),
+        }
+        return;
+    };
+
+    // Sending here keeps events in `seq` order; the stream's ack is awaited
+    // off the hot path.
+    let ack = match jetstream.publish(subject.clone(), payload.into()).await {
+        Ok(ack) => ack,
+        Err(err) => {
+            record_failure(state, &subject, recorded.seq, &err);
+            return;
+        }
+    };
+
+    let state = state.clone();
+    let seq = recorded.seq;
+    tokio::spawn(async move {
+        match ack.await {
+            Ok(_) => state.metrics.record_nats_message("published"),
+            Err(err) => record_failure(&state, &subject, seq, &err),
+        }
+    });
+}
+
+fn record_failure(state: &AppState, subject: &str, seq: u64, err: &dyn std::fmt::Display) {
+    warn!(subject, seq, error = %err, "failed to publish event to nats");
+    state.metrics.record_nats_message("failed");
+}
+
+/// Creates an order for every `CreateOrderRequest` received on `subject`.
+/// Requests that carry a reply subject get the created order back, or
+/// `{"error": ...}` if it was rejected.
+async fn consume_orders(state: Arc<AppState>, client: async_nats::Client, subject: String) {
+    let mut subscriber = match client
+        .queue_subscribe(subject.clone(), ORDERS_QUEUE_GROUP.to_string())
+        .await
+    {
+        Ok(subscriber) => subscriber,
+        Err(err) => {
+            warn!(error = %err, subject, "failed to subscribe to nats orders subject");
+            return;
+        }
+    };
+
+    info!(subject, "nats order ingestion started");
+
+    while let Some(message) = subscriber.next().await {
+        let request_id = message
+            .headers
+            .as_ref()
+            .and_then(|headers| headers.get(REQUEST_ID_HEADER))
+            .map(|value| value.to_string());
+        let outcome = match serde_json::from_slice::<CreateOrderRequest>(&message.payload) {
+            Ok(request) => {
+                let mut new = NewOrder::from(request);
+                new.request_id = request_id;
+                submit_order(&state, new, "nats")
+                    .await
+                    .map_err(|err| err.to_string())
+            }
+            Err(err) => Err(format!("invalid order: {err}")),
+        };
+
+        let reply = match outcome {
+            Ok(order) => {
+                state.metrics.record_nats_message("order_created");
+                serde_json::to_value(order).unwrap_or_default()
+            }
+            Err(error) => {
+                warn!(subject, error, "rejected order from nats");
+                state.metrics.record_nats_message("order_rejected");
+                json!({ "error": error })
+            }
+        };
+
+        if let Some(reply_to) = message.reply
+            && let Err(err) = client.publish(reply_to, reply.to_string().into()).await
+        {
+            warn!(error = %err, "failed to reply to nats order request");
+        }
+    }
+
+    info!(subject, "nats order ingestion stopped");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NatsSettings;
+    use crate::models::event::Topic;
+
+    #[test]
+    fn event_subjects_use_prefix_and_topic() {
+        let settings = NatsSettings {
+            url: "nats://localhost:4222".to_string(),
+            subject_prefix: "dispatch".to_string(),
+            jetstream: false,
+            orders_subject: String::new(),
+        };
+
+        assert_eq!(
+            settings.event_subject(Topic::Assignments),
+            "dispatch.assignments"
+        );
+        assert_eq!(settings.event_subject(Topic::Orders), "dispatch.orders");
+    }
+}
@@ -0,0 +1,257 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio_stream::StreamExt;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::engine::tracking::order_tracking;
+use crate::events::{Lagged, RecordedEvent};
+use crate::models::event::DispatchEvent;
+use crate::models::order::{OrderStatus, OrderTracking};
+use crate::state::AppState;
+use crate::webhooks::{sign, EVENT_HEADER, SIGNATURE_HEADER};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Message templates per transition. `{order_id}` and `{eta_minutes}` are
+/// substituted; `{eta_minutes}` is empty while the ETA is unknown.
+#[derive(Debug, Clone)]
+pub struct Templates {
+    pub assigned: String,
+    pub in_transit: String,
+    pub delivered: String,
+}
+
+impl Default for Templates {
+    fn default() -> Self {
+        Self {
+            assigned: "A courier is on the way to pick up your order.".to_string(),
+            in_transit: "Your order is on its way, arriving in about {eta_minutes} min."
+                .to_string(),
+            delivered: "Your order has been delivered.".to_string(),
+        }
+    }
+}
+
+impl Templates {
+    fn render(&self, tracking: &OrderTracking) -> Option<String> {
+        let template = match tracking.status {
+            OrderStatus::Assigned => &self.assigned,
+            OrderStatus::InTransit => &self.in_transit,
+            OrderStatus::Delivered => &self.delivered,
+            OrderStatus::Pending | OrderStatus::Rejected => return None,
+        };
+        let eta_minutes = tracking
+            .eta_seconds
+            .map(|seconds| seconds.div_ceil(60).to_string())
+            .unwrap_or_default();
+
+        Some(
+            template
+                .replace("{order_id}", &tracking.order_id.to_string())
+                .replace("{eta_minutes}", &eta_minutes),
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CustomerSettings {
+    /// Transitions customers hear about.
+    pub statuses: Vec<OrderStatus>,
+    /// Signs callback bodies like webhook deliveries when set.
+    pub callback_secret: Option<String>,
+    /// Delivers templated messages to `customer_contact`; empty disables.
+    pub gateway_url: String,
+    pub templates: Templates,
+}
+
+/// The body POSTed to the notification gateway.
+#[derive(Debug, Serialize)]
+pub struct CustomerMessage {
+    pub contact: String,
+    pub order_id: Uuid,
+    pub status: OrderStatus,
+    pub message: String,
+}
+
+/// Notifies customers of orders that carry a `callback_url` or
+/// `customer_contact` when the order reaches one of the configured statuses.
+/// Each notification is attempted once.
+pub async fn run_customer_notifier(state: Arc<AppState>, settings: CustomerSettings) {
+    let client = match reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(err) => {
+            warn!(error = %err, "failed to build customer notification client, notifications disabled");
+            return;
+        }
+    };
+
+    info!("customer notifier started");
+
+    let settings = Arc::new(settings);
+    let mut events = Box::pin(state.events.stream(None, None));
+    let mut last_seq = 0;
+
+    while let Some(item) = events.next().await {
+        let batch = match item {
+            Ok(recorded) if recorded.seq <= last_seq => continue,
+            Ok(recorded) => vec![recorded],
+            Err(Lagged(dropped)) => {
+                state.metrics.record_dropped_events("customer", dropped);
+                warn!(dropped, "customer notifier lagged, replaying from buffer");
+                state.events.replay(Some(last_seq), None)
+            }
+        };
+
+        for recorded in batch {
+            last_seq = last_seq.max(recorded.seq);
+            notify(&state, &client, &settings, &recorded);
+        }
+    }
+
+    info!("customer notifier stopped");
+}
+
+fn notify(
+    state: &Arc<AppState>,
+    client: &reqwest::Client,
+    settings: &Arc<CustomerSettings>,
+    recorded: &RecordedEvent,
+) {
+    let DispatchEvent::Order(event) = &recorded.event else {
+        return;
+    };
+    if recorded.relayed || !settings.statuses.contains(&event.status) {
+        return;
+    }
+    let Some((callback_url, contact)) = state
+        .orders
+        .get(&event.order_id)
+        .map(|order| (order.callback_url.clone(), order.customer_contact.clone()))
+    else {
+        return;
+    };
+    if callback_url.is_none() && contact.is_none() {
+        return;
+    }
+
+    let Ok(mut tracking) = order_tracking(state, event.order_id) else {
+        return;
+    };
+    // The order may have moved on since this event; report the transition
+    // being notified, not whatever the order is at now.
+    tracking.status = event.status.clone();
+
+    if let Some(url) = callback_url {
+        tokio::spawn(send_callback(
+            state.clone(),
+            client.clone(),
+            settings.clone(),
+            url,
+            tracking.clone(),
+        ));
+    }
+
+    if let Some(contact) = contact
+        && !settings.gateway_url.is_empty()
+        && let Some(message) = settings.templates.render(&tracking)
+    {
+        tokio::spawn(send_message(
+            state.clone(),
+            client.clone(),
+            settings.clone(),
+            CustomerMessage {
+                contact,
+                order_id: tracking.order_id,
+                status: tracking.status,
+                message,
+            },
+        ));
+    }
+}
+
+async fn send_callback(
+    state: Arc<AppState>,
+    client: reqwest::Client,
+    settings: Arc<CustomerSettings>,
+    url: String,
+    tracking: OrderTracking,
+) {
+    let body = match serde_json::to_vec(&tracking) {
+        Ok(body) => body,
+        Err(err) => {
+            warn!(error = %err, "failed to serialize customer callback");
+            return;
+        }
+    };
+
+    let mut request = client
+        .post(&url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(EVENT_HEADER, "order_status");
+    if let Some(secret) = &settings.callback_secret {
+        request = request.header(SIGNATURE_HEADER, sign(secret, &body));
+    }
+
+    let result = request
+        .body(body)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status());
+    record(&state, "callback", tracking.order_id, result.err());
+}
+
+async fn send_message(
+    state: Arc<AppState>,
+    client: reqwest::Client,
+    settings: Arc<CustomerSettings>,
+    message: CustomerMessage,
+) {
+    let result = client
+        .post(&settings.gateway_url)
+        .json(&message)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status());
+    record(&state, "message", message.order_id, result.err());
+}
+
+fn record(state: &AppState, channel: &str, order_id: Uuid, error: Option<reqwest::Error>) {
+    match error {
+        None => state.metrics.record_customer_notification(channel, "sent"),
+        Some(err) => {
+            state
+                .metrics
+                .record_customer_notification(channel, "failed");
+            warn!(%order_id, channel, error = %err, "customer notification failed");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    use super::Templates;
+    use crate::models::order::{OrderStatus, OrderTracking};
+
+    #[test]
+    fn renders_eta_in_whole_minutes() {
+        let tracking = OrderTracking {
+            order_id: Uuid::new_v4(),
+            status: OrderStatus::InTransit,
+            courier_id: None,
+            courier_location: None,
+            eta_seconds: Some(61),
+            updated_at: Utc::now(),
+        };
+
+        assert_eq!(
+            Templates::default().render(&tracking).unwrap(),
+            "Your order is on its way, arriving in about 2 min."
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use serde::Serialize;
+use serde_json::json;
+use tokio_stream::StreamExt;
+use tracing::{info, warn};
+
+use crate::events::{Lagged, RecordedEvent};
+use crate::models::assignment::Assignment;
+use crate::models::device::{CourierDevice, PushPlatform};
+use crate::models::event::DispatchEvent;
+use crate::state::AppState;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone)]
+pub struct PushSettings {
+    /// Push gateway that forwards notifications to FCM or APNs.
+    pub gateway_url: String,
+    /// Sent as `Authorization: Bearer <token>` when set.
+    pub gateway_token: Option<String>,
+}
+
+/// The body POSTed to the gateway for one device.
+#[derive(Debug, Serialize)]
+pub struct PushNotification {
+    pub platform: PushPlatform,
+    pub token: String,
+    pub title: String,
+    pub body: String,
+    pub data: serde_json::Value,
+}
+
+/// Notifies couriers on their registered devices when the engine assigns
+/// them an order. Best effort: each notification is sent once, and tokens
+/// the gateway reports as gone (404 or 410) are unregistered.
+pub async fn run_push_notifier(state: Arc<AppState>, settings: PushSettings) {
+    let client = match reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(err) => {
+            warn!(error = %err, "failed to build push client, push notifications disabled");
+            return;
+        }
+    };
+
+    info!(gateway = %settings.gateway_url, "push notifier started");
+
+    let mut events = Box::pin(state.events.stream(None, None));
+    let mut last_seq = 0;
+
+    while let Some(item) = events.next().await {
+        let batch = match item {
+            Ok(recorded) if recorded.seq <= last_seq => continue,
+            Ok(recorded) => vec![recorded],
+            Err(Lagged(dropped)) => {
+                state.metrics.record_dropped_events("push", dropped);
+                warn!(dropped, "push notifier lagged, replaying from buffer");
+                state.events.replay(Some(last_seq), None)
+            }
+        };
+
+        for recorded in batch {
+            last_seq = last_seq.max(recorded.seq);
+            notify(&state, &client, &settings, &recorded);
+        }
+    }
+
+    info!("push notifier stopped");
+}
+
+fn notify(
+    state: &Arc<AppState>,
+    client: &reqwest::Client,
+    settings: &PushSettings,
+    recorded: &RecordedEvent,
+) {
+    let DispatchEvent::Assignment(assignment) = &recorded.event else {
+        return;
+    };
+    if recorded.relayed {
+        return;
+    }
+
+    let devices = state
+        .courier_devices
+        .get(&assignment.courier_id)
+        .map(|devices| devices.clone())
+        .unwrap_or_default();
+
+    for device in devices {
+        let notification = assignment_notification(state, assignment, &device);
+        tokio::spawn(send(
+            state.clone(),
+            client.clone(),
+            settings.clone(),
+            device,
+            notification,
+        ));
+    }
+}
+
+fn assignment_notification(
+    state: &AppState,
+    assignment: &Assignment,
+    device: &CourierDevice,
+) -> PushNotification {
+    let body = match state.orders.get(&assignment.order_id) {
+        Some(order) => format!(
+            "Pick up at {:.5}, {:.5}",
+            order.pickup.lat, order.pickup.lng
+        ),
+        None => "Open the app for details".to_string(),
+    };
+
+    PushNotification {
+        platform: device.platform,
+        token: device.token.clone(),
+        title: "New delivery assigned".to_string(),
+        body,
+        data: json!({
+            "type": "assignment",
+            "assignment_id": assignment.id,
+            "order_id": assignment.order_id,
+        }),
+    }
+}
+
+async fn send(
+    state: Arc<AppState>,
+    client: reqwest::Client,
+    settings: PushSettings,
+    device: CourierDevice,
+    notification: PushNotification,
+) {
+    let mut request = client.post(&settings.gateway_url).json(&notification);
+    if let Some(token) = &settings.gateway_token {
+        request = request.bearer_auth(token);
+    }
+
+    match request.send().await {
+        Ok(response) if response.status().is_success() => {
+            state.metrics.record_push_notification("sent");
+        }
+        Ok(response) if matches!(response.status(), StatusCode::NOT_FOUND | StatusCode::GONE) => {
+            state.remove_courier_device(device.courier_id, &device.token);
+            state.metrics.record_push_notification("token_removed");
+            info!(courier_id = %device.courier_id, "push token rejected by gateway, unregistered");
+        }
+        Ok(response) => {
+            state.metrics.record_push_notification("failed");
+            warn!(courier_id = %device.courier_id, status = %response.status(), "push gateway rejected notification");
+        }
+        Err(err) => {
+            state.metrics.record_push_notification("failed");
+            warn!(courier_id = %device.courier_id, error = %err, "failed to send push notification");
+        }
+    }
+}
@@ -0,0 +1,19 @@
+use std::net::SocketAddr;
+
+use console_subscriber::ConsoleLayer;
+use tracing::Subscriber;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+#[cfg(not(tokio_unstable))]
+compile_error!("the `console` feature needs RUSTFLAGS=\"--cfg tokio_unstable\"");
+
+/// A `tracing` layer recording task spans and poll times for `tokio-console`,
+/// which connects to `bind`. It filters for itself, regardless of
+/// `LOG_LEVEL`, and serves from its own thread.
+pub fn layer<S>(bind: SocketAddr) -> impl Layer<S>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    ConsoleLayer::builder().server_addr(bind).spawn()
+}
@@ -1,28 +1,117 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use base64::Engine;
+use prometheus::proto::MetricFamily;
 use prometheus::{
-    Encoder, GaugeVec, HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+    Encoder, Gauge, GaugeVec, Histogram, HistogramVec, IntCounter, IntCounterVec, IntGauge,
+    IntGaugeVec, Opts, Registry, TextEncoder,
 };
+use tokio::runtime::RuntimeMetrics;
+
+use crate::auth::constant_time_eq;
+use crate::geo::region::RegionMap;
+use crate::models::courier::{Courier, CourierStatus};
+use crate::models::order::Priority;
 
 #[derive(Clone)]
 pub struct Metrics {
     registry: Registry,
     pub assignments_total: IntCounterVec,
-    pub orders_in_queue: IntGauge,
+    pub orders_in_queue: IntGaugeVec,
+    pub order_queue_wait_seconds: HistogramVec,
     pub assignment_latency_seconds: HistogramVec,
-    pub courier_utilization: GaugeVec,
+    pub assignment_pickup_distance_km: Histogram,
+    pub assignment_eta_seconds: Histogram,
+    pub courier_utilization: Histogram,
+    pub fleet_capacity: Gauge,
+    pub fleet_load: Gauge,
+    pub fleet_utilization: Gauge,
+    pub couriers: IntGaugeVec,
+    pub ws_connections_active: IntGauge,
+    pub ws_connections_rejected_total: IntCounterVec,
+    pub ws_messages_sent_total: IntCounterVec,
+    pub stream_events_dropped_total: IntCounterVec,
+    pub webhook_deliveries_total: IntCounterVec,
+    pub webhook_dead_letters_evicted_total: IntCounter,
+    pub kafka_messages_total: IntCounterVec,
+    pub nats_messages_total: IntCounterVec,
+    pub mqtt_messages_total: IntCounterVec,
+    pub relay_messages_total: IntCounterVec,
+    pub standby_changes_applied_total: IntCounter,
+    pub push_notifications_total: IntCounterVec,
+    pub customer_notifications_total: IntCounterVec,
+    pub grpc_requests_total: IntCounterVec,
+    pub grpc_request_duration_seconds: HistogramVec,
+    pub rate_limited_requests_total: IntCounterVec,
+    pub config_reloads_total: IntCounterVec,
+    pub engine_restarts_total: IntCounterVec,
+    pub chaos_injections_total: IntCounterVec,
+    pub leader: IntGauge,
+    pub leadership_changes_total: IntCounterVec,
+    pub surge_zones: IntGauge,
+    pub surges_total: IntCounter,
+    pub courier_timeouts_total: IntCounter,
+    pub orders_without_qualified_courier_total: IntCounter,
+    pub orders_stacked_total: IntCounter,
+    pub orders_rejected_total: IntCounter,
+    pub candidates_rejected_total: IntCounterVec,
+    pub distance_cache_lookups_total: IntCounterVec,
+    pub tokio_workers: IntGauge,
+    pub tokio_alive_tasks: IntGauge,
+    pub tokio_global_queue_depth: IntGauge,
+    pub tokio_worker_busy_seconds: GaugeVec,
+    #[cfg(tokio_unstable)]
+    pub tokio_worker_polls: IntGaugeVec,
+    #[cfg(tokio_unstable)]
+    pub tokio_worker_mean_poll_seconds: GaugeVec,
 }
 
 impl Metrics {
     pub fn new() -> Self {
-        let registry = Registry::new();
+        Self::with_registry(Registry::new())
+    }
 
+    /// Metrics whose every series carries `tenant="<tenant>"`.
+    pub fn for_tenant(tenant: &str) -> Self {
+        let labels = HashMap::from([("tenant".to_string(), tenant.to_string())]);
+        Self::with_registry(
+            Registry::new_custom(None, Some(labels)).expect("valid tenant metrics label"),
+        )
+    }
+
+    fn with_registry(registry: Registry) -> Self {
         let assignments_total = IntCounterVec::new(
             Opts::new("assignments_total", "Total assignments by outcome"),
             &["outcome"],
         )
         .expect("valid assignments_total metric");
 
-        let orders_in_queue = IntGauge::new("orders_in_queue", "Current number of orders in queue")
-            .expect("valid orders_in_queue metric");
+        let orders_in_queue = IntGaugeVec::new(
+            Opts::new(
+                "orders_in_queue",
+                "Current number of orders in queue by priority",
+            ),
+            &["priority"],
+        )
+        .expect("valid orders_in_queue metric");
+        // Start every priority at zero so an empty Urgent queue reads 0
+        // rather than having no series.
+        for priority in Priority::ALL {
+            orders_in_queue.with_label_values(&[priority.as_str()]);
+        }
+
+        let order_queue_wait_seconds = HistogramVec::new(
+            prometheus::HistogramOpts::new(
+                "order_queue_wait_seconds",
+                "Time from order creation to the start of its assignment in seconds",
+            )
+            .buckets(vec![
+                0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0,
+            ]),
+            &["priority"],
+        )
+        .expect("valid order_queue_wait_seconds metric");
 
         let assignment_latency_seconds = HistogramVec::new(
             prometheus::HistogramOpts::new(
@@ -33,36 +122,551 @@ impl Metrics {
         )
         .expect("valid assignment_latency_seconds metric");
 
-        let courier_utilization = GaugeVec::new(
-            Opts::new("courier_utilization", "Courier utilization ratio [0..1]"),
-            &["courier_id"],
+        let assignment_pickup_distance_km = Histogram::with_opts(
+            prometheus::HistogramOpts::new(
+                "assignment_pickup_distance_km",
+                "Distance from the assigned courier to the pickup in km",
+            )
+            .buckets(vec![
+                0.25, 0.5, 1.0, 2.0, 3.0, 5.0, 7.5, 10.0, 15.0, 20.0, 30.0, 50.0,
+            ]),
+        )
+        .expect("valid assignment_pickup_distance_km metric");
+
+        let assignment_eta_seconds = Histogram::with_opts(
+            prometheus::HistogramOpts::new(
+                "assignment_eta_seconds",
+                "Estimated time to dropoff at assignment in seconds",
+            )
+            .buckets(vec![
+                60.0, 120.0, 300.0, 600.0, 900.0, 1200.0, 1800.0, 2700.0, 3600.0, 5400.0, 7200.0,
+            ]),
+        )
+        .expect("valid assignment_eta_seconds metric");
+
+        let courier_utilization = Histogram::with_opts(
+            prometheus::HistogramOpts::new(
+                "courier_utilization",
+                "Courier utilization ratio [0..1] after each change in load",
+            )
+            .buckets(vec![0.0, 0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8, 0.9, 1.0]),
         )
         .expect("valid courier_utilization metric");
 
+        let fleet_capacity = Gauge::new(
+            "fleet_capacity",
+            "Total capacity, in CAPACITY_UNIT, of couriers that are not offline",
+        )
+        .expect("valid fleet_capacity metric");
+
+        let fleet_load = Gauge::new(
+            "fleet_load",
+            "Sum of the sizes of orders carried by couriers that are not offline",
+        )
+        .expect("valid fleet_load metric");
+
+        let fleet_utilization = Gauge::new(
+            "fleet_utilization",
+            "fleet_load / fleet_capacity, 0 with no capacity",
+        )
+        .expect("valid fleet_utilization metric");
+
+        let couriers = IntGaugeVec::new(Opts::new("couriers", "Couriers by status"), &["status"])
+            .expect("valid couriers metric");
+        for status in CourierStatus::ALL {
+            couriers.with_label_values(&[status.as_str()]);
+        }
+
+        let ws_connections_active = IntGauge::new(
+            "ws_connections_active",
+            "Currently connected WebSocket clients",
+        )
+        .expect("valid ws_connections_active metric");
+
+        let ws_connections_rejected_total = IntCounterVec::new(
+            Opts::new(
+                "ws_connections_rejected_total",
+                "WebSocket connections refused by connection limits",
+            ),
+            &["reason"],
+        )
+        .expect("valid ws_connections_rejected_total metric");
+
+        let ws_messages_sent_total = IntCounterVec::new(
+            Opts::new(
+                "ws_messages_sent_total",
+                "Events pushed to WebSocket clients",
+            ),
+            &["topic"],
+        )
+        .expect("valid ws_messages_sent_total metric");
+
+        let stream_events_dropped_total = IntCounterVec::new(
+            Opts::new(
+                "stream_events_dropped_total",
+                "Events skipped because a stream consumer fell behind the broadcast buffer",
+            ),
+            &["transport"],
+        )
+        .expect("valid stream_events_dropped_total metric");
+
+        let webhook_deliveries_total = IntCounterVec::new(
+            Opts::new(
+                "webhook_deliveries_total",
+                "Webhook delivery attempts by outcome",
+            ),
+            &["outcome"],
+        )
+        .expect("valid webhook_deliveries_total metric");
+
+        let webhook_dead_letters_evicted_total = IntCounter::new(
+            "webhook_dead_letters_evicted_total",
+            "Webhook dead letters dropped, oldest first, to stay within WEBHOOK_DEAD_LETTER_LIMIT",
+        )
+        .expect("valid webhook_dead_letters_evicted_total metric");
+
+        let kafka_messages_total = IntCounterVec::new(
+            Opts::new(
+                "kafka_messages_total",
+                "Events published to Kafka by outcome",
+            ),
+            &["outcome"],
+        )
+        .expect("valid kafka_messages_total metric");
+
+        let nats_messages_total = IntCounterVec::new(
+            Opts::new(
+                "nats_messages_total",
+                "Events published to and orders received from NATS by outcome",
+            ),
+            &["outcome"],
+        )
+        .expect("valid nats_messages_total metric");
+
+        let mqtt_messages_total = IntCounterVec::new(
+            Opts::new(
+                "mqtt_messages_total",
+                "Device location messages received over MQTT by outcome",
+            ),
+            &["outcome"],
+        )
+        .expect("valid mqtt_messages_total metric");
+
+        let relay_messages_total = IntCounterVec::new(
+            Opts::new(
+                "relay_messages_total",
+                "Events exchanged with other instances over Redis by direction",
+            ),
+            &["direction"],
+        )
+        .expect("valid relay_messages_total metric");
+
+        let standby_changes_applied_total = IntCounter::new(
+            "standby_changes_applied_total",
+            "Courier and order changes a standby mirrored from its primary",
+        )
+        .expect("valid standby_changes_applied_total metric");
+
+        let push_notifications_total = IntCounterVec::new(
+            Opts::new(
+                "push_notifications_total",
+                "Courier push notifications by outcome",
+            ),
+            &["outcome"],
+        )
+        .expect("valid push_notifications_total metric");
+
+        let customer_notifications_total = IntCounterVec::new(
+            Opts::new(
+                "customer_notifications_total",
+                "Customer order notifications by channel and outcome",
+            ),
+            &["channel", "outcome"],
+        )
+        .expect("valid customer_notifications_total metric");
+
+        let grpc_requests_total = IntCounterVec::new(
+            Opts::new(
+                "grpc_requests_total",
+                "gRPC requests by RPC and status code",
+            ),
+            &["rpc", "code"],
+        )
+        .expect("valid grpc_requests_total metric");
+
+        let grpc_request_duration_seconds = HistogramVec::new(
+            prometheus::HistogramOpts::new(
+                "grpc_request_duration_seconds",
+                "Time to the start of the gRPC response in seconds",
+            ),
+            &["rpc"],
+        )
+        .expect("valid grpc_request_duration_seconds metric");
+
+        let rate_limited_requests_total = IntCounterVec::new(
+            Opts::new(
+                "rate_limited_requests_total",
+                "REST requests rejected with 429 by route group",
+            ),
+            &["group"],
+        )
+        .expect("valid rate_limited_requests_total metric");
+
+        let config_reloads_total = IntCounterVec::new(
+            Opts::new(
+                "config_reloads_total",
+                "Configuration reloads by outcome (applied, unchanged, failed)",
+            ),
+            &["outcome"],
+        )
+        .expect("valid config_reloads_total metric");
+
+        let engine_restarts_total = IntCounterVec::new(
+            Opts::new(
+                "engine_restarts_total",
+                "Assignment engine restarts by the supervisor, by reason (panic, exited)",
+            ),
+            &["reason"],
+        )
+        .expect("valid engine_restarts_total metric");
+
+        let chaos_injections_total = IntCounterVec::new(
+            Opts::new(
+                "chaos_injections_total",
+                "Faults injected by chaos mode, by kind (latency, dropped_event, engine_panic)",
+            ),
+            &["kind"],
+        )
+        .expect("valid chaos_injections_total metric");
+
+        let leader = IntGauge::new(
+            "leader",
+            "1 while this instance holds the leader lease and runs the engine, else 0",
+        )
+        .expect("valid leader metric");
+        // Every instance leads unless leader election is on.
+        leader.set(1);
+
+        let leadership_changes_total = IntCounterVec::new(
+            Opts::new(
+                "leadership_changes_total",
+                "Times this instance took or lost the leader lease, by role now held (leader, follower)",
+            ),
+            &["role"],
+        )
+        .expect("valid leadership_changes_total metric");
+
+        let surge_zones = IntGauge::new(
+            "surge_zones",
+            "Zones where pending orders outnumber available couriers by more than SURGE_THRESHOLD",
+        )
+        .expect("valid surge_zones metric");
+
+        let surges_total = IntCounter::new(
+            "surges_total",
+            "Times a zone went into surge, boosting its orders' priority",
+        )
+        .expect("valid surges_total metric");
+
+        let courier_timeouts_total = IntCounter::new(
+            "courier_timeouts_total",
+            "Couriers taken offline for missing heartbeats",
+        )
+        .expect("valid courier_timeouts_total metric");
+
+        let orders_without_qualified_courier_total = IntCounter::new(
+            "orders_without_qualified_courier_total",
+            "Orders no courier had the vehicle, skills or capacity for",
+        )
+        .expect("valid orders_without_qualified_courier_total metric");
+
+        let orders_rejected_total = IntCounter::new(
+            "orders_rejected_total",
+            "Orders taken out of the queue because no courier could ever take them",
+        )
+        .expect("valid orders_rejected_total metric");
+
+        let candidates_rejected_total = IntCounterVec::new(
+            Opts::new(
+                "candidates_rejected_total",
+                "Couriers passed over for an order, by the first constraint they failed",
+            ),
+            &["constraint"],
+        )
+        .expect("valid candidates_rejected_total metric");
+
+        let distance_cache_lookups_total = IntCounterVec::new(
+            Opts::new(
+                "distance_cache_lookups_total",
+                "Courier-to-pickup distances looked up while scoring, by hit or miss",
+            ),
+            &["result"],
+        )
+        .expect("valid distance_cache_lookups_total metric");
+
+        let orders_stacked_total = IntCounter::new(
+            "orders_stacked_total",
+            "Orders added to the route of a courier already carrying others",
+        )
+        .expect("valid orders_stacked_total metric");
+
+        let tokio_workers = IntGauge::new("tokio_workers", "Tokio runtime worker threads")
+            .expect("valid tokio_workers metric");
+        let tokio_alive_tasks = IntGauge::new(
+            "tokio_alive_tasks",
+            "Tasks spawned on the Tokio runtime that have not finished",
+        )
+        .expect("valid tokio_alive_tasks metric");
+        let tokio_global_queue_depth = IntGauge::new(
+            "tokio_global_queue_depth",
+            "Tasks waiting in the Tokio runtime's shared queue for a worker",
+        )
+        .expect("valid tokio_global_queue_depth metric");
+        let tokio_worker_busy_seconds = GaugeVec::new(
+            Opts::new(
+                "tokio_worker_busy_seconds",
+                "Time each Tokio worker has spent running tasks since startup",
+            ),
+            &["worker"],
+        )
+        .expect("valid tokio_worker_busy_seconds metric");
+        #[cfg(tokio_unstable)]
+        let tokio_worker_polls = IntGaugeVec::new(
+            Opts::new(
+                "tokio_worker_polls",
+                "Task polls by each Tokio worker since startup",
+            ),
+            &["worker"],
+        )
+        .expect("valid tokio_worker_polls metric");
+        #[cfg(tokio_unstable)]
+        let tokio_worker_mean_poll_seconds = GaugeVec::new(
+            Opts::new(
+                "tokio_worker_mean_poll_seconds",
+                "Moving average of how long one task poll takes on each Tokio worker",
+            ),
+            &["worker"],
+        )
+        .expect("valid tokio_worker_mean_poll_seconds metric");
+
         registry
             .register(Box::new(assignments_total.clone()))
             .expect("register assignments_total");
         registry
             .register(Box::new(orders_in_queue.clone()))
             .expect("register orders_in_queue");
+        registry
+            .register(Box::new(order_queue_wait_seconds.clone()))
+            .expect("register order_queue_wait_seconds");
         registry
             .register(Box::new(assignment_latency_seconds.clone()))
             .expect("register assignment_latency_seconds");
+        registry
+            .register(Box::new(assignment_pickup_distance_km.clone()))
+            .expect("register assignment_pickup_distance_km");
+        registry
+            .register(Box::new(assignment_eta_seconds.clone()))
+            .expect("register assignment_eta_seconds");
         registry
             .register(Box::new(courier_utilization.clone()))
             .expect("register courier_utilization");
+        registry
+            .register(Box::new(fleet_capacity.clone()))
+            .expect("register fleet_capacity");
+        registry
+            .register(Box::new(fleet_load.clone()))
+            .expect("register fleet_load");
+        registry
+            .register(Box::new(fleet_utilization.clone()))
+            .expect("register fleet_utilization");
+        registry
+            .register(Box::new(couriers.clone()))
+            .expect("register couriers");
+        registry
+            .register(Box::new(ws_connections_active.clone()))
+            .expect("register ws_connections_active");
+        registry
+            .register(Box::new(ws_connections_rejected_total.clone()))
+            .expect("register ws_connections_rejected_total");
+        registry
+            .register(Box::new(ws_messages_sent_total.clone()))
+            .expect("register ws_messages_sent_total");
+        registry
+            .register(Box::new(stream_events_dropped_total.clone()))
+            .expect("register stream_events_dropped_total");
+        registry
+            .register(Box::new(webhook_deliveries_total.clone()))
+            .expect("register webhook_deliveries_total");
+        registry
+            .register(Box::new(webhook_dead_letters_evicted_total.clone()))
+            .expect("register webhook_dead_letters_evicted_total");
+        registry
+            .register(Box::new(kafka_messages_total.clone()))
+            .expect("register kafka_messages_total");
+        registry
+            .register(Box::new(nats_messages_total.clone()))
+            .expect("register nats_messages_total");
+        registry
+            .register(Box::new(mqtt_messages_total.clone()))
+            .expect("register mqtt_messages_total");
+        registry
+            .register(Box::new(relay_messages_total.clone()))
+            .expect("register relay_messages_total");
+        registry
+            .register(Box::new(standby_changes_applied_total.clone()))
+            .expect("register standby_changes_applied_total");
+        registry
+            .register(Box::new(push_notifications_total.clone()))
+            .expect("register push_notifications_total");
+        registry
+            .register(Box::new(customer_notifications_total.clone()))
+            .expect("register customer_notifications_total");
+        registry
+            .register(Box::new(grpc_requests_total.clone()))
+            .expect("register grpc_requests_total");
+        registry
+            .register(Box::new(grpc_request_duration_seconds.clone()))
+            .expect("register grpc_request_duration_seconds");
+        registry
+            .register(Box::new(rate_limited_requests_total.clone()))
+            .expect("register rate_limited_requests_total");
+        registry
+            .register(Box::new(config_reloads_total.clone()))
+            .expect("register config_reloads_total");
+        registry
+            .register(Box::new(engine_restarts_total.clone()))
+            .expect("register engine_restarts_total");
+        registry
+            .register(Box::new(chaos_injections_total.clone()))
+            .expect("register chaos_injections_total");
+        registry
+            .register(Box::new(leader.clone()))
+            .expect("register leader");
+        registry
+            .register(Box::new(leadership_changes_total.clone()))
+            .expect("register leadership_changes_total");
+        registry
+            .register(Box::new(surge_zones.clone()))
+            .expect("register surge_zones");
+        registry
+            .register(Box::new(surges_total.clone()))
+            .expect("register surges_total");
+        registry
+            .register(Box::new(courier_timeouts_total.clone()))
+            .expect("register courier_timeouts_total");
+        registry
+            .register(Box::new(orders_without_qualified_courier_total.clone()))
+            .expect("register orders_without_qualified_courier_total");
+        registry
+            .register(Box::new(orders_stacked_total.clone()))
+            .expect("register orders_stacked_total");
+        registry
+            .register(Box::new(orders_rejected_total.clone()))
+            .expect("register orders_rejected_total");
+        registry
+            .register(Box::new(candidates_rejected_total.clone()))
+            .expect("register candidates_rejected_total");
+        registry
+            .register(Box::new(distance_cache_lookups_total.clone()))
+            .expect("register distance_cache_lookups_total");
+        registry
+            .register(Box::new(tokio_workers.clone()))
+            .expect("register tokio_workers");
+        registry
+            .register(Box::new(tokio_alive_tasks.clone()))
+            .expect("register tokio_alive_tasks");
+        registry
+            .register(Box::new(tokio_global_queue_depth.clone()))
+            .expect("register tokio_global_queue_depth");
+        registry
+            .register(Box::new(tokio_worker_busy_seconds.clone()))
+            .expect("register tokio_worker_busy_seconds");
+        #[cfg(tokio_unstable)]
+        registry
+            .register(Box::new(tokio_worker_polls.clone()))
+            .expect("register tokio_worker_polls");
+        #[cfg(tokio_unstable)]
+        registry
+            .register(Box::new(tokio_worker_mean_poll_seconds.clone()))
+            .expect("register tokio_worker_mean_poll_seconds");
 
         Self {
             registry,
             assignments_total,
             orders_in_queue,
+            order_queue_wait_seconds,
             assignment_latency_seconds,
+            assignment_pickup_distance_km,
+            assignment_eta_seconds,
             courier_utilization,
+            fleet_capacity,
+            fleet_load,
+            fleet_utilization,
+            couriers,
+            ws_connections_active,
+            ws_connections_rejected_total,
+            ws_messages_sent_total,
+            stream_events_dropped_total,
+            webhook_deliveries_total,
+            webhook_dead_letters_evicted_total,
+            kafka_messages_total,
+            nats_messages_total,
+            mqtt_messages_total,
+            relay_messages_total,
+            standby_changes_applied_total,
+            push_notifications_total,
+            customer_notifications_total,
+            grpc_requests_total,
+            grpc_request_duration_seconds,
+            rate_limited_requests_total,
+            config_reloads_total,
+            engine_restarts_total,
+            chaos_injections_total,
+            leader,
+            leadership_changes_total,
+            surge_zones,
+            surges_total,
+            courier_timeouts_total,
+            orders_without_qualified_courier_total,
+            orders_stacked_total,
+            orders_rejected_total,
+            candidates_rejected_total,
+            distance_cache_lookups_total,
+            tokio_workers,
+            tokio_alive_tasks,
+            tokio_global_queue_depth,
+            tokio_worker_busy_seconds,
+            #[cfg(tokio_unstable)]
+            tokio_worker_polls,
+            #[cfg(tokio_unstable)]
+            tokio_worker_mean_poll_seconds,
         }
     }
 
     pub fn encode(&self) -> Result<String, String> {
-        let metric_families = self.registry.gather();
+        Self::encode_all([self])
+    }
+
+    /// Encodes several tenants' metrics as one exposition, each family
+    /// holding every tenant's series.
+    pub fn encode_all<'a>(all: impl IntoIterator<Item = &'a Metrics>) -> Result<String, String> {
+        let mut metric_families: Vec<MetricFamily> = Vec::new();
+        for metrics in all {
+            for mut family in metrics.registry.gather() {
+                match metric_families
+                    .iter_mut()
+                    .find(|merged| merged.get_name() == family.get_name())
+                {
+                    Some(merged) => {
+                        for metric in family.take_metric() {
+                            merged.mut_metric().push(metric);
+                        }
+                    }
+                    None => metric_families.push(family),
+                }
+            }
+        }
+        metric_families.sort_by(|a, b| a.get_name().cmp(b.get_name()));
         let mut buffer = Vec::new();
 
         TextEncoder::new()
@@ -71,4 +675,187 @@ impl Metrics {
 
         String::from_utf8(buffer).map_err(|err| format!("metrics are not valid utf8: {err}"))
     }
+
+    /// Samples a courier's utilization after its load changed.
+    pub fn record_courier_load(&self, current_load: f64, capacity: f64) {
+        if capacity > 0.0 {
+            self.courier_utilization.observe(current_load / capacity);
+        }
+    }
+
+    /// Recomputes the fleet gauges. Done on scrape, so the engine never walks
+    /// the whole fleet and removed couriers leave nothing behind.
+    pub fn refresh_fleet(&self, couriers: &RegionMap<Arc<Courier>>) {
+        let mut by_status = [0i64; CourierStatus::ALL.len()];
+        let (mut capacity, mut load) = (0.0, 0.0);
+        for entry in couriers.iter() {
+            let courier = entry.value();
+            by_status[courier.status.clone() as usize] += 1;
+            if courier.status != CourierStatus::Offline {
+                capacity += courier.capacity;
+                load += courier.current_load;
+            }
+        }
+
+        for (status, count) in CourierStatus::ALL.iter().zip(by_status) {
+            self.couriers
+                .with_label_values(&[status.as_str()])
+                .set(count);
+        }
+        self.fleet_capacity.set(capacity);
+        self.fleet_load.set(load);
+        self.fleet_utilization
+            .set(if capacity > 0.0 { load / capacity } else { 0.0 });
+    }
+
+    /// Copies the runtime's own counters into the registry, on scrape like
+    /// the fleet gauges. Per-poll figures need a `tokio_unstable` build.
+    pub fn refresh_runtime(&self, runtime: &RuntimeMetrics) {
+        let workers = runtime.num_workers();
+        self.tokio_workers.set(workers as i64);
+        self.tokio_alive_tasks.set(runtime.num_alive_tasks() as i64);
+        self.tokio_global_queue_depth
+            .set(runtime.global_queue_depth() as i64);
+        for worker in 0..workers {
+            let label = worker.to_string();
+            self.tokio_worker_busy_seconds
+                .with_label_values(&[&label])
+                .set(runtime.worker_total_busy_duration(worker).as_secs_f64());
+            #[cfg(tokio_unstable)]
+            {
+                self.tokio_worker_polls
+                    .with_label_values(&[&label])
+                    .set(runtime.worker_poll_count(worker) as i64);
+                self.tokio_worker_mean_poll_seconds
+                    .with_label_values(&[&label])
+                    .set(runtime.worker_mean_poll_time(worker).as_secs_f64());
+            }
+        }
+    }
+
+    pub fn record_leadership(&self, leader: bool) {
+        self.leader.set(i64::from(leader));
+        self.leadership_changes_total
+            .with_label_values(&[if leader { "leader" } else { "follower" }])
+            .inc();
+    }
+
+    pub fn record_dropped_events(&self, transport: &str, dropped: u64) {
+        self.stream_events_dropped_total
+            .with_label_values(&[transport])
+            .inc_by(dropped);
+    }
+
+    pub fn record_webhook_delivery(&self, outcome: &str) {
+        self.webhook_deliveries_total
+            .with_label_values(&[outcome])
+            .inc();
+    }
+
+    pub fn record_kafka_message(&self, outcome: &str) {
+        self.kafka_messages_total
+            .with_label_values(&[outcome])
+            .inc();
+    }
+
+    pub fn record_nats_message(&self, outcome: &str) {
+        self.nats_messages_total.with_label_values(&[outcome]).inc();
+    }
+
+    pub fn record_mqtt_message(&self, outcome: &str) {
+        self.mqtt_messages_total.with_label_values(&[outcome]).inc();
+    }
+
+    pub fn record_relay_message(&self, direction: &str) {
+        self.relay_messages_total
+            .with_label_values(&[direction])
+            .inc();
+    }
+
+    pub fn record_push_notification(&self, outcome: &str) {
+        self.push_notifications_total
+            .with_label_values(&[outcome])
+            .inc();
+    }
+
+    pub fn record_customer_notification(&self, channel: &str, outcome: &str) {
+        self.customer_notifications_total
+            .with_label_values(&[channel, outcome])
+            .inc();
+    }
+
+    pub fn record_grpc_request(&self, rpc: &str, code: &str, seconds: f64) {
+        self.grpc_requests_total
+            .with_label_values(&[rpc, code])
+            .inc();
+        self.grpc_request_duration_seconds
+            .with_label_values(&[rpc])
+            .observe(seconds);
+    }
+
+    pub fn record_rate_limited(&self, group: &str) {
+        self.rate_limited_requests_total
+            .with_label_values(&[group])
+            .inc();
+    }
+
+    pub fn record_config_reload(&self, outcome: &str) {
+        self.config_reloads_total
+            .with_label_values(&[outcome])
+            .inc();
+    }
+
+    pub fn record_engine_restart(&self, reason: &str) {
+        self.engine_restarts_total
+            .with_label_values(&[reason])
+            .inc();
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Who may scrape `/metrics`. With no credentials set it is open.
+#[derive(Debug, Clone, Default)]
+pub struct MetricsAccess {
+    /// Accepted as `authorization: Bearer <token>`.
+    pub bearer_token: Option<String>,
+    /// `user:password`, accepted as HTTP basic auth.
+    pub basic_auth: Option<String>,
+    /// Metrics are served on their own port instead of alongside the API.
+    pub separate_port: bool,
+}
+
+impl MetricsAccess {
+    pub fn is_protected(&self) -> bool {
+        self.bearer_token.is_some() || self.basic_auth.is_some()
+    }
+
+    /// Whether an `authorization` header value matches either credential.
+    pub fn allows(&self, authorization: Option<&str>) -> bool {
+        if !self.is_protected() {
+            return true;
+        }
+        let Some(authorization) = authorization else {
+            return false;
+        };
+
+        let bearer = self
+            .bearer_token
+            .as_deref()
+            .zip(authorization.strip_prefix("Bearer "))
+            .is_some_and(|(token, presented)| constant_time_eq(token, presented.trim()));
+        let basic = self
+            .basic_auth
+            .as_deref()
+            .zip(authorization.strip_prefix("Basic "))
+            .is_some_and(|(credentials, presented)| {
+                let expected = base64::engine::general_purpose::STANDARD.encode(credentials);
+                constant_time_eq(&expected, presented.trim())
+            });
+        bearer | basic
+    }
 }
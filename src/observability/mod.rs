@@ -1 +1,7 @@
+#[cfg(feature = "console")]
+pub mod console;
 pub mod metrics;
+#[cfg(feature = "otel")]
+pub mod otel;
+pub mod stats;
+pub mod timeseries;
@@ -0,0 +1,36 @@
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::SpanExporter;
+use opentelemetry_sdk::trace::{Tracer, TracerProvider};
+use opentelemetry_sdk::{runtime, Resource};
+use tracing::Subscriber;
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::error::AppError;
+
+/// Batches spans to the OTLP/HTTP collector named by
+/// `OTEL_EXPORTER_OTLP_ENDPOINT` (`/v1/traces` is appended). Shut the
+/// provider down on exit to flush the last batch.
+pub fn tracer_provider(service_name: &str) -> Result<TracerProvider, AppError> {
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .build()
+        .map_err(|err| AppError::Internal(format!("failed to build otlp exporter: {err}")))?;
+
+    Ok(TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new([KeyValue::new(
+            "service.name",
+            service_name.to_string(),
+        )]))
+        .build())
+}
+
+/// A `tracing` layer exporting every span it sees through `provider`.
+pub fn layer<S>(provider: &TracerProvider) -> OpenTelemetryLayer<S, Tracer>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    tracing_opentelemetry::layer().with_tracer(provider.tracer("dispatch-router"))
+}
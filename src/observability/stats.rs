@@ -0,0 +1,271 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use crate::models::order::OrderStatus;
+use crate::models::stats::{DispatchSummary, TimeSeries};
+use crate::observability::timeseries::{MinuteSeries, DEFAULT_TIMESERIES_MINUTES};
+
+pub const DEFAULT_STATS_WINDOW: Duration = Duration::from_secs(900);
+
+/// Slots the window is cut into; each ages out as a whole, so the window
+/// slides in steps of a sixtieth of its length.
+const SLOTS: usize = 60;
+
+/// Upper bounds, in seconds, of the buckets assignment latencies are
+/// counted in; one more bucket takes anything longer.
+const LATENCY_BOUNDS: [f64; 12] = [
+    0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0, 600.0,
+];
+
+const UTILIZATION_BUCKETS: usize = 10;
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Slot {
+    /// Which stretch of time, counted in slots from the epoch, the figures
+    /// are for.
+    epoch: i64,
+    attempts: u64,
+    assignments: u64,
+    latency_sum: f64,
+    latency_buckets: [u64; LATENCY_BOUNDS.len() + 1],
+    pickup_km_sum: f64,
+    utilization: [u64; UTILIZATION_BUCKETS],
+}
+
+impl Slot {
+    fn add(&mut self, other: &Slot) {
+        self.attempts += other.attempts;
+        self.assignments += other.assignments;
+        self.latency_sum += other.latency_sum;
+        self.pickup_km_sum += other.pickup_km_sum;
+        for (total, count) in self.latency_buckets.iter_mut().zip(other.latency_buckets) {
+            *total += count;
+        }
+        for (total, count) in self.utilization.iter_mut().zip(other.utilization) {
+            *total += count;
+        }
+    }
+}
+
+/// The figures behind `GET /stats`, kept up to date as orders move and the
+/// engines work, so reading them never walks the orders or the fleet.
+/// Windowed figures are added to the slot for the current time; a slot is
+/// cleared when its turn comes round again. The per-minute series behind
+/// `GET /stats/timeseries` is fed alongside.
+#[derive(Debug)]
+pub struct DispatchStats {
+    orders_by_status: [AtomicI64; OrderStatus::ALL.len()],
+    slot_millis: i64,
+    slots: Mutex<[Slot; SLOTS]>,
+    series: MinuteSeries,
+}
+
+impl Default for DispatchStats {
+    fn default() -> Self {
+        Self::new(DEFAULT_STATS_WINDOW, DEFAULT_TIMESERIES_MINUTES)
+    }
+}
+
+impl DispatchStats {
+    /// Sums up the last `window`, and keeps the last `series_minutes`
+    /// minutes as a series.
+    pub fn new(window: Duration, series_minutes: usize) -> Self {
+        Self {
+            orders_by_status: Default::default(),
+            slot_millis: (window.as_millis() as i64 / SLOTS as i64).max(1),
+            slots: Mutex::new([Slot::default(); SLOTS]),
+            series: MinuteSeries::new(series_minutes),
+        }
+    }
+
+    /// An order moved from `from`, or was created for `None`, to `to`, at
+    /// `now`.
+    pub fn record_order_status(
+        &self,
+        from: Option<&OrderStatus>,
+        to: &OrderStatus,
+        now: DateTime<Utc>,
+    ) {
+        match from {
+            Some(from) => {
+                self.orders_by_status[status_index(from)].fetch_sub(1, Ordering::Relaxed);
+            }
+            None => self.series.record_order(now),
+        }
+        self.orders_by_status[status_index(to)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// An engine took an order from its queue.
+    pub fn record_attempt(&self, now: DateTime<Utc>) {
+        self.update(now, |slot| slot.attempts += 1);
+    }
+
+    /// An order was assigned `latency` after it was created, to a courier
+    /// `pickup_km` from its pickup that scored `score`.
+    pub fn record_assignment(
+        &self,
+        latency: Duration,
+        pickup_km: f64,
+        score: f64,
+        now: DateTime<Utc>,
+    ) {
+        self.series.record_assignment(score, now);
+        let seconds = latency.as_secs_f64();
+        let bucket = LATENCY_BOUNDS
+            .iter()
+            .position(|&bound| seconds <= bound)
+            .unwrap_or(LATENCY_BOUNDS.len());
+        self.update(now, |slot| {
+            slot.assignments += 1;
+            slot.latency_sum += seconds;
+            slot.latency_buckets[bucket] += 1;
+            slot.pickup_km_sum += pickup_km;
+        });
+    }
+
+    /// A courier's load changed, leaving it at `current_load` of
+    /// `capacity`.
+    pub fn record_courier_load(&self, current_load: f64, capacity: f64, now: DateTime<Utc>) {
+        if capacity <= 0.0 {
+            return;
+        }
+        let tenth = (current_load / capacity * UTILIZATION_BUCKETS as f64).max(0.0) as usize;
+        self.update(now, |slot| {
+            slot.utilization[tenth.min(UTILIZATION_BUCKETS - 1)] += 1
+        });
+    }
+
+    pub fn summary(&self, now: DateTime<Utc>) -> DispatchSummary {
+        let current = self.epoch(now);
+        let mut total = Slot::default();
+        for slot in self.slots.lock().unwrap_or_else(|e| e.into_inner()).iter() {
+            if (0..SLOTS as i64).contains(&(current - slot.epoch)) {
+                total.add(slot);
+            }
+        }
+
+        let per_assignment =
+            |sum: f64| (total.assignments > 0).then(|| sum / total.assignments as f64);
+        DispatchSummary {
+            orders_by_status: OrderStatus::ALL
+                .iter()
+                .map(|status| {
+                    let count = self.orders_by_status[status_index(status)].load(Ordering::Relaxed);
+                    (status.as_str().to_string(), count.max(0) as u64)
+                })
+                .collect::<BTreeMap<_, _>>(),
+            window_secs: (self.slot_millis * SLOTS as i64 / 1000) as u64,
+            attempts: total.attempts,
+            assignments: total.assignments,
+            success_rate: (total.attempts > 0)
+                .then(|| total.assignments as f64 / total.attempts as f64),
+            mean_assignment_latency_seconds: per_assignment(total.latency_sum),
+            p95_assignment_latency_seconds: (total.assignments > 0)
+                .then(|| percentile(&total.latency_buckets, total.assignments, 0.95)),
+            mean_pickup_km: per_assignment(total.pickup_km_sum),
+            utilization: total.utilization.to_vec(),
+        }
+    }
+
+    pub fn timeseries(&self, now: DateTime<Utc>) -> TimeSeries {
+        self.series.points(now)
+    }
+
+    fn epoch(&self, at: DateTime<Utc>) -> i64 {
+        at.timestamp_millis().div_euclid(self.slot_millis)
+    }
+
+    fn update(&self, now: DateTime<Utc>, record: impl FnOnce(&mut Slot)) {
+        let epoch = self.epoch(now);
+        let mut slots = self.slots.lock().unwrap_or_else(|e| e.into_inner());
+        let slot = &mut slots[epoch.rem_euclid(SLOTS as i64) as usize];
+        if slot.epoch != epoch {
+            *slot = Slot {
+                epoch,
+                ..Slot::default()
+            };
+        }
+        record(slot);
+    }
+}
+
+fn status_index(status: &OrderStatus) -> usize {
+    OrderStatus::ALL
+        .iter()
+        .position(|candidate| candidate == status)
+        .expect("ALL lists every status")
+}
+
+/// The `q` quantile of `count` latencies counted in `buckets`, assuming
+/// they are spread evenly within each bucket. Past the last bound, that
+/// bound is the best estimate there is.
+fn percentile(buckets: &[u64], count: u64, q: f64) -> f64 {
+    let rank = q * count as f64;
+    let mut below = 0;
+    for (index, &in_bucket) in buckets.iter().enumerate() {
+        if in_bucket > 0 && (below + in_bucket) as f64 >= rank {
+            let lower = index
+                .checked_sub(1)
+                .map_or(0.0, |prev| LATENCY_BOUNDS[prev]);
+            let Some(&upper) = LATENCY_BOUNDS.get(index) else {
+                return lower;
+            };
+            return lower + (upper - lower) * (rank - below as f64) / in_bucket as f64;
+        }
+        below += in_bucket;
+    }
+    LATENCY_BOUNDS[LATENCY_BOUNDS.len() - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::Utc;
+
+    use super::DispatchStats;
+    use crate::models::order::OrderStatus;
+
+    #[test]
+    fn sums_up_the_window_and_forgets_what_left_it() {
+        let stats = DispatchStats::new(Duration::from_secs(60), 5);
+        let now = Utc::now();
+        stats.record_order_status(None, &OrderStatus::Pending, now);
+        stats.record_order_status(None, &OrderStatus::Pending, now);
+        stats.record_order_status(Some(&OrderStatus::Pending), &OrderStatus::Assigned, now);
+        for _ in 0..4 {
+            stats.record_attempt(now);
+        }
+        for (millis, km) in [(200, 1.0), (400, 2.0), (3000, 3.0), (3000, 2.0)] {
+            stats.record_assignment(Duration::from_millis(millis), km, 0.5, now);
+        }
+        stats.record_courier_load(1.0, 4.0, now);
+        stats.record_courier_load(4.0, 4.0, now);
+
+        let summary = stats.summary(now);
+        assert_eq!(summary.window_secs, 60);
+        assert_eq!(summary.orders_by_status["Pending"], 1);
+        assert_eq!(summary.orders_by_status["Assigned"], 1);
+        assert_eq!(summary.orders_by_status["Delivered"], 0);
+        assert_eq!(summary.success_rate, Some(1.0));
+        assert_eq!(summary.mean_assignment_latency_seconds, Some(1.65));
+        let p95 = summary.p95_assignment_latency_seconds.unwrap();
+        assert!((2.5..=5.0).contains(&p95), "{p95}");
+        assert_eq!(summary.mean_pickup_km, Some(2.0));
+        assert_eq!(summary.utilization[2], 1);
+        assert_eq!(summary.utilization[9], 1);
+
+        let later = stats.summary(now + chrono::Duration::seconds(61));
+        assert_eq!(later.attempts, 0);
+        assert_eq!(later.success_rate, None);
+        assert_eq!(later.orders_by_status["Pending"], 1);
+
+        let minute = stats.timeseries(now).points.pop().unwrap();
+        assert_eq!((minute.orders, minute.assignments), (2, 4));
+        assert_eq!(minute.mean_score, Some(0.5));
+    }
+}
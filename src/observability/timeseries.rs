@@ -0,0 +1,128 @@
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+
+use crate::models::stats::{TimeSeries, TimeSeriesPoint};
+
+pub const DEFAULT_TIMESERIES_MINUTES: usize = 60;
+
+const MINUTE_MILLIS: i64 = 60_000;
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Minute {
+    /// Minutes since the epoch the counts are for.
+    minute: i64,
+    orders: u64,
+    assignments: u64,
+    score_sum: f64,
+}
+
+/// Per-minute counts for `GET /stats/timeseries`, held in a ring of one
+/// entry per minute: recording into a minute whose entry still holds the
+/// minute one lap earlier clears it first, so nothing ever needs pruning.
+#[derive(Debug)]
+pub struct MinuteSeries {
+    minutes: Mutex<Vec<Minute>>,
+}
+
+impl Default for MinuteSeries {
+    fn default() -> Self {
+        Self::new(DEFAULT_TIMESERIES_MINUTES)
+    }
+}
+
+impl MinuteSeries {
+    pub fn new(minutes: usize) -> Self {
+        Self {
+            minutes: Mutex::new(vec![Minute::default(); minutes.max(1)]),
+        }
+    }
+
+    pub fn record_order(&self, now: DateTime<Utc>) {
+        self.update(now, |minute| minute.orders += 1);
+    }
+
+    pub fn record_assignment(&self, score: f64, now: DateTime<Utc>) {
+        self.update(now, |minute| {
+            minute.assignments += 1;
+            minute.score_sum += score;
+        });
+    }
+
+    /// One point per minute up to the one `now` is in, oldest first; minutes
+    /// without activity are there with zero counts.
+    pub fn points(&self, now: DateTime<Utc>) -> TimeSeries {
+        let current = minute_of(now);
+        let minutes = self.minutes.lock().unwrap_or_else(|e| e.into_inner());
+        let len = minutes.len() as i64;
+        let points = (current - len + 1..=current)
+            .map(|at| {
+                let slot = minutes[at.rem_euclid(len) as usize];
+                let slot = if slot.minute == at {
+                    slot
+                } else {
+                    Minute::default()
+                };
+                TimeSeriesPoint {
+                    at: DateTime::from_timestamp_millis(at * MINUTE_MILLIS).unwrap_or_default(),
+                    orders: slot.orders,
+                    assignments: slot.assignments,
+                    mean_score: (slot.assignments > 0)
+                        .then(|| slot.score_sum / slot.assignments as f64),
+                }
+            })
+            .collect();
+        TimeSeries {
+            interval_secs: (MINUTE_MILLIS / 1000) as u64,
+            points,
+        }
+    }
+
+    fn update(&self, at: DateTime<Utc>, record: impl FnOnce(&mut Minute)) {
+        let minute = minute_of(at);
+        let mut minutes = self.minutes.lock().unwrap_or_else(|e| e.into_inner());
+        let len = minutes.len() as i64;
+        let slot = &mut minutes[minute.rem_euclid(len) as usize];
+        if slot.minute != minute {
+            *slot = Minute {
+                minute,
+                ..Minute::default()
+            };
+        }
+        record(slot);
+    }
+}
+
+fn minute_of(at: DateTime<Utc>) -> i64 {
+    at.timestamp_millis().div_euclid(MINUTE_MILLIS)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use super::MinuteSeries;
+
+    #[test]
+    fn keeps_a_point_per_minute_and_overwrites_the_oldest() {
+        let series = MinuteSeries::new(5);
+        let now = Utc::now();
+        series.update(now - Duration::minutes(7), |minute| minute.orders += 9);
+        series.update(now - Duration::minutes(2), |minute| minute.orders += 1);
+        series.update(now, |minute| {
+            minute.assignments += 2;
+            minute.score_sum += 1.5;
+        });
+
+        let series = series.points(now);
+        assert_eq!(series.interval_secs, 60);
+        assert_eq!(series.points.len(), 5);
+        let orders: Vec<u64> = series.points.iter().map(|point| point.orders).collect();
+        assert_eq!(orders, [0, 0, 1, 0, 0]);
+        let last = &series.points[4];
+        assert_eq!(last.assignments, 2);
+        assert_eq!(last.mean_score, Some(0.75));
+        assert!(last.at <= now && now - last.at < Duration::minutes(1));
+        assert_eq!(series.points[3].mean_score, None);
+    }
+}
@@ -0,0 +1,197 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use axum::http::Method;
+use dashmap::DashMap;
+
+use crate::state::AppState;
+
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Requests that share a budget. Order creation is the expensive path, so it
+/// gets its own budget apart from other changes and from reads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RouteGroup {
+    OrderCreate,
+    Write,
+    Read,
+}
+
+impl RouteGroup {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RouteGroup::OrderCreate => "order_create",
+            RouteGroup::Write => "write",
+            RouteGroup::Read => "read",
+        }
+    }
+
+    /// The group a request counts against; `None` for health checks and
+    /// metrics, which probes and scrapers hit on a schedule.
+    pub fn of(method: &Method, path: &str) -> Option<Self> {
+        if path.starts_with("/health") || matches!(path, "/live" | "/ready" | "/metrics") {
+            return None;
+        }
+        Some(match *method {
+            Method::POST if path == "/orders" => RouteGroup::OrderCreate,
+            Method::GET | Method::HEAD | Method::OPTIONS => RouteGroup::Read,
+            _ => RouteGroup::Write,
+        })
+    }
+}
+
+/// Requests per minute allowed to each client, per group; zero means
+/// unlimited. A client can spend a full minute's budget in a burst.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RateLimits {
+    pub order_create_per_min: u32,
+    pub write_per_min: u32,
+    pub read_per_min: u32,
+}
+
+impl RateLimits {
+    fn per_min(&self, group: RouteGroup) -> u32 {
+        match group {
+            RouteGroup::OrderCreate => self.order_create_per_min,
+            RouteGroup::Write => self.write_per_min,
+            RouteGroup::Read => self.read_per_min,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token buckets per client and route group. A client is whoever its API key
+/// says it is, falling back to its IP address.
+pub struct RateLimiter {
+    limits: RateLimits,
+    buckets: DashMap<(RouteGroup, String), Bucket>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: RateLimits) -> Self {
+        Self {
+            limits,
+            buckets: DashMap::new(),
+        }
+    }
+
+    /// Takes one request from the client's budget, or says how long until it
+    /// can make one.
+    pub fn check(&self, group: RouteGroup, client: &str) -> Result<(), Duration> {
+        self.check_at(group, client, Instant::now())
+    }
+
+    fn check_at(&self, group: RouteGroup, client: &str, now: Instant) -> Result<(), Duration> {
+        let per_min = self.limits.per_min(group);
+        if per_min == 0 {
+            return Ok(());
+        }
+        let capacity = f64::from(per_min);
+        let per_sec = capacity / 60.0;
+
+        let mut bucket = self
+            .buckets
+            .entry((group, client.to_string()))
+            .or_insert(Bucket {
+                tokens: capacity,
+                updated: now,
+            });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * per_sec).min(capacity);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / per_sec))
+        }
+    }
+
+    /// Drops buckets that have refilled completely; they are recreated full
+    /// on the client's next request, so nothing is lost.
+    pub fn prune(&self) {
+        let now = Instant::now();
+        self.buckets.retain(|(group, _), bucket| {
+            let per_min = f64::from(self.limits.per_min(*group));
+            let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+            bucket.tokens + elapsed * per_min / 60.0 < per_min
+        });
+    }
+}
+
+/// Keeps the bucket map from growing with every client ever seen.
+pub async fn run_rate_limit_pruner(state: Arc<AppState>) {
+    let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+    loop {
+        interval.tick().await;
+        state.rate_limiter.prune();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use axum::http::Method;
+
+    use super::{RateLimiter, RateLimits, RouteGroup};
+
+    #[test]
+    fn groups_order_creation_apart_from_other_requests() {
+        assert_eq!(
+            RouteGroup::of(&Method::POST, "/orders"),
+            Some(RouteGroup::OrderCreate)
+        );
+        assert_eq!(
+            RouteGroup::of(&Method::PATCH, "/orders/1/status"),
+            Some(RouteGroup::Write)
+        );
+        assert_eq!(
+            RouteGroup::of(&Method::GET, "/orders/1"),
+            Some(RouteGroup::Read)
+        );
+        assert_eq!(RouteGroup::of(&Method::GET, "/health/engine"), None);
+        assert_eq!(RouteGroup::of(&Method::GET, "/ready"), None);
+    }
+
+    #[test]
+    fn budgets_are_per_client_and_refill_over_time() {
+        let limiter = RateLimiter::new(RateLimits {
+            order_create_per_min: 2,
+            write_per_min: 0,
+            read_per_min: 60,
+        });
+        let start = Instant::now();
+
+        assert!(limiter
+            .check_at(RouteGroup::OrderCreate, "a", start)
+            .is_ok());
+        assert!(limiter
+            .check_at(RouteGroup::OrderCreate, "a", start)
+            .is_ok());
+        let retry_after = limiter
+            .check_at(RouteGroup::OrderCreate, "a", start)
+            .unwrap_err();
+        assert_eq!(retry_after, Duration::from_secs(30));
+
+        assert!(limiter
+            .check_at(RouteGroup::OrderCreate, "b", start)
+            .is_ok());
+        assert!(limiter.check_at(RouteGroup::Read, "a", start).is_ok());
+        assert!(limiter.check_at(RouteGroup::Write, "a", start).is_ok());
+
+        let later = start + Duration::from_secs(30);
+        assert!(limiter
+            .check_at(RouteGroup::OrderCreate, "a", later)
+            .is_ok());
+        assert!(limiter
+            .check_at(RouteGroup::OrderCreate, "a", later)
+            .is_err());
+    }
+}
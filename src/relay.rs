@@ -0,0 +1,201 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+use tokio_stream::StreamExt;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::events::Lagged;
+use crate::models::event::DispatchEvent;
+use crate::state::AppState;
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone)]
+pub struct RelaySettings {
+    pub url: String,
+    pub channel: String,
+}
+
+/// What goes over the Redis channel. `instance` lets each replica ignore its
+/// own messages.
+#[derive(Debug, Serialize, Deserialize)]
+struct RelayMessage {
+    instance: Uuid,
+    event: DispatchEvent,
+}
+
+/// Bridges this instance's events through Redis pub/sub so that stream
+/// clients on every replica see every event. Events received from other
+/// replicas are marked relayed and never sent back out, which also keeps
+/// webhooks, Kafka and NATS to one delivery per event.
+pub async fn run_event_relay(state: Arc<AppState>, settings: RelaySettings) {
+    let instance = Uuid::new_v4();
+    let client = match redis::Client::open(settings.url.as_str()) {
+        Ok(client) => client,
+        Err(err) => {
+            warn!(error = %err, "invalid redis url, event relay disabled");
+            return;
+        }
+    };
+
+    info!(channel = %settings.channel, %instance, "event relay started");
+
+    // Carried across reconnects so events published while Redis was
+    // unreachable are sent from the replay buffer once it is back.
+    let mut last_seq = 0;
+    loop {
+        if let Err(err) = relay(&state, &client, &settings.channel, instance, &mut last_seq).await {
+            warn!(error = %err, "event relay disconnected, reconnecting");
+        }
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+async fn relay(
+    state: &AppState,
+    client: &redis::Client,
+    channel: &str,
+    instance: Uuid,
+    last_seq: &mut u64,
+) -> redis::RedisResult<()> {
+    let mut publisher = client.get_multiplexed_async_connection().await?;
+    let mut pubsub = client.get_async_pubsub().await?;
+    pubsub.subscribe(channel).await?;
+    let mut incoming = pubsub.into_on_message();
+
+    let mut events = Box::pin(
+        state
+            .events
+            .stream((*last_seq > 0).then_some(*last_seq), None),
+    );
+    let mut locations = state.courier_locations_tx.subscribe();
+
+    loop {
+        let outgoing = tokio::select! {
+            message = incoming.next() => {
+                let Some(message) = message else {
+                    return Ok(());
+                };
+                receive(state, instance, message.get_payload_bytes());
+                continue;
+            }
+            item = events.next() => match item {
+                Some(Ok(recorded)) if recorded.seq <= *last_seq => continue,
+                Some(Ok(recorded)) => {
+                    *last_seq = recorded.seq;
+                    if recorded.relayed {
+                        continue;
+                    }
+                    vec![recorded.event]
+                }
+                Some(Err(Lagged(dropped))) => {
+                    state.metrics.record_dropped_events("relay", dropped);
+                    let missed = state.events.replay(Some(*last_seq), None);
+                    if let Some(last) = missed.last() {
+                        *last_seq = last.seq;
+                    }
+                    missed
+                        .into_iter()
+                        .filter(|recorded| !recorded.relayed)
+                        .map(|recorded| recorded.event)
+                        .collect()
+                }
+                None => return Ok(()),
+            },
+            location = locations.recv() => match location {
+                Ok(location) if location.relayed => continue,
+                Ok(location) => vec![DispatchEvent::CourierLocation(location)],
+                // Locations are superseded by the next ping; nothing to catch up.
+                Err(RecvError::Lagged(dropped)) => {
+                    state.metrics.record_dropped_events("relay", dropped);
+                    continue;
+                }
+                Err(RecvError::Closed) => return Ok(()),
+            },
+        };
+
+        for event in outgoing {
+            let payload = match serde_json::to_string(&RelayMessage { instance, event }) {
+                Ok(payload) => payload,
+                Err(err) => {
+                    warn!(error = %err, "failed to serialize relay message");
+                    continue;
+                }
+            };
+            publisher.publish::<_, _, ()>(channel, payload).await?;
+            state.metrics.record_relay_message("sent");
+        }
+    }
+}
+
+fn receive(state: &AppState, instance: Uuid, payload: &[u8]) {
+    let message: RelayMessage = match serde_json::from_slice(payload) {
+        Ok(message) => message,
+        Err(err) => {
+            warn!(error = %err, "ignoring malformed relay message");
+            return;
+        }
+    };
+    if message.instance == instance {
+        return;
+    }
+
+    state.metrics.record_relay_message("received");
+    match message.event {
+        DispatchEvent::CourierLocation(mut location) => {
+            location.relayed = true;
+            let _ = state.courier_locations_tx.send(location);
+        }
+        event => {
+            state.events.publish_relayed(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    use super::{receive, RelayMessage};
+    use crate::models::courier::GeoPoint;
+    use crate::models::event::DispatchEvent;
+    use crate::models::order::{OrderEvent, OrderStatus};
+    use crate::state::AppState;
+
+    fn message(instance: Uuid) -> Vec<u8> {
+        serde_json::to_vec(&RelayMessage {
+            instance,
+            event: DispatchEvent::Order(OrderEvent {
+                order_id: Uuid::new_v4(),
+                status: OrderStatus::Pending,
+                courier_id: None,
+                pickup: GeoPoint {
+                    lat: 52.51,
+                    lng: 13.39,
+                },
+                occurred_at: Utc::now(),
+                items: Vec::new(),
+            }),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn republishes_foreign_events_as_relayed_and_ignores_own() {
+        let (state, _rx) = AppState::new(16, 16);
+        let instance = Uuid::new_v4();
+
+        receive(&state, instance, &message(instance));
+        assert!(state.events.replay(None, None).is_empty());
+
+        receive(&state, instance, &message(Uuid::new_v4()));
+        let replay = state.events.replay(None, None);
+        assert_eq!(replay.len(), 1);
+        assert!(replay[0].relayed);
+    }
+}
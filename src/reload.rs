@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{info, warn};
+
+use crate::config::Tunables;
+use crate::error::AppError;
+use crate::state::AppState;
+
+/// Applies a new `LOG_LEVEL` to the running subscriber, whose reload handle
+/// only `main` can name.
+pub type LogLevelHook = Box<dyn Fn(&str) -> Result<(), String> + Send + Sync>;
+
+#[derive(Debug, Clone)]
+pub struct ReloadSettings {
+    /// The dotenv file to re-read; without one only the process environment
+    /// is consulted, which cannot change, so reloads find nothing new.
+    pub config_file: Option<PathBuf>,
+    /// How often the file is checked for changes; `None` reloads on SIGHUP only.
+    pub poll_interval: Option<Duration>,
+}
+
+/// Reads the tunables again. Values in the config file win over the process
+/// environment, which still holds whatever the file said at startup, so a
+/// setting removed from the file keeps its old value rather than resetting.
+pub fn read_tunables(config_file: Option<&Path>) -> Result<Tunables, AppError> {
+    let from_file: HashMap<String, String> = match config_file {
+        Some(path) => dotenvy::from_path_iter(path)
+            .and_then(|entries| entries.collect())
+            .map_err(|err| {
+                AppError::Internal(format!("failed to read {}: {err}", path.display()))
+            })?,
+        None => HashMap::new(),
+    };
+    Tunables::from_lookup(|key| from_file.get(key).cloned().or_else(|| env::var(key).ok()))
+}
+
+/// Re-reads the tunables on SIGHUP and whenever the config file changes,
+/// and swaps them into `state` in one step. Fleet, orders and connections
+/// are untouched. A file that fails to parse or validate is logged and the
+/// running settings stay as they are.
+pub async fn run_config_reloader(
+    state: Arc<AppState>,
+    settings: ReloadSettings,
+    apply_log_level: LogLevelHook,
+) {
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => Some(hangup),
+        Err(err) => {
+            warn!(error = %err, "failed to listen for SIGHUP, reloading on file changes only");
+            None
+        }
+    };
+    let mut poll = settings.poll_interval.map(tokio::time::interval);
+    let mut loaded = modified(settings.config_file.as_deref()).await;
+
+    loop {
+        tokio::select! {
+            Some(()) = async { hangup.as_mut()?.recv().await } => {
+                info!("SIGHUP received, reloading configuration");
+            }
+            _ = async {
+                match poll.as_mut() {
+                    Some(poll) => poll.tick().await,
+                    None => std::future::pending().await,
+                }
+            } => {
+                let current = modified(settings.config_file.as_deref()).await;
+                if current == loaded {
+                    continue;
+                }
+                loaded = current;
+            }
+        }
+
+        reload(&state, settings.config_file.as_deref(), &apply_log_level);
+    }
+}
+
+fn reload(state: &AppState, config_file: Option<&Path>, apply_log_level: &LogLevelHook) {
+    let tunables = match read_tunables(config_file) {
+        Ok(tunables) => tunables,
+        Err(err) => {
+            state.metrics.record_config_reload("failed");
+            warn!(error = %err, "configuration reload failed, keeping the running settings");
+            return;
+        }
+    };
+
+    let current = state.tunables();
+    if *current == tunables {
+        state.metrics.record_config_reload("unchanged");
+        return;
+    }
+    if current.log_level != tunables.log_level
+        && let Err(err) = apply_log_level(&tunables.log_level)
+    {
+        state.metrics.record_config_reload("failed");
+        warn!(error = %err, "failed to apply LOG_LEVEL, keeping the running settings");
+        return;
+    }
+
+    info!(
+        log_level = %tunables.log_level,
+        scoring = ?tunables.scoring,
+        requeue_delay_ms = tunables.requeue_delay.as_millis() as u64,
+        "configuration reloaded"
+    );
+    state.set_tunables(tunables);
+    state.metrics.record_config_reload("applied");
+}
+
+async fn modified(path: Option<&Path>) -> Option<SystemTime> {
+    tokio::fs::metadata(path?)
+        .await
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_tunables, reload, LogLevelHook};
+    use crate::state::AppState;
+
+    #[test]
+    fn reload_swaps_in_file_values_and_keeps_them_on_errors() {
+        let dir = std::env::temp_dir().join(format!("dispatch-reload-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join(".env");
+        let (state, _rx) = AppState::new(16, 16);
+        let hook: LogLevelHook = Box::new(|_| Ok(()));
+
+        std::fs::write(
+            &file,
+            "SCORING_DISTANCE_WEIGHT=0.9\nENGINE_REQUEUE_DELAY_MS=1000\nLOG_LEVEL=debug\n",
+        )
+        .unwrap();
+        let tunables = read_tunables(Some(&file)).unwrap();
+        assert_eq!(tunables.scoring.distance, 0.9);
+        assert_eq!(tunables.requeue_delay.as_millis(), 1000);
+
+        reload(&state, Some(&file), &hook);
+        assert_eq!(state.tunables().scoring.distance, 0.9);
+        assert_eq!(state.tunables().log_level, "debug");
+
+        std::fs::write(&file, "SCORING_DISTANCE_WEIGHT=-1\n").unwrap();
+        reload(&state, Some(&file), &hook);
+        assert_eq!(state.tunables().scoring.distance, 0.9);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,280 @@
+use std::collections::{HashMap, HashSet};
+use std::io::BufRead;
+use std::time::Duration;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::api::rest::couriers::{build_courier, CreateCourierRequest};
+use crate::api::rest::orders::CreateOrderRequest;
+use crate::clock::{Clock, ManualClock};
+use crate::engine::fleet::register_courier;
+use crate::engine::lifecycle::transition_order;
+use crate::engine::location::move_courier;
+use crate::engine::queue::submit_order;
+use crate::error::AppError;
+use crate::models::courier::GeoPoint;
+use crate::models::order::{NewOrder, OrderStatus};
+use crate::state::AppState;
+
+/// Audit actor for everything a replay does.
+const ACTOR: &str = "replay";
+
+/// How often the replay clock moves on while waiting for the next record.
+const TICK: Duration = Duration::from_millis(10);
+
+/// One line of a replay log: something that happened at `at`.
+#[derive(Deserialize)]
+pub struct ReplayRecord {
+    pub at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub event: ReplayEvent,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ReplayEvent {
+    /// A courier came on; the rest is a `POST /couriers` body. The courier
+    /// keeps `id`, so later records can refer to it.
+    Courier {
+        id: Uuid,
+        #[serde(flatten)]
+        courier: CreateCourierRequest,
+    },
+    /// An order came in; the rest is a `POST /orders` body. The replayed
+    /// order gets a new ID; `id` only ties later status records to it.
+    Order {
+        #[serde(default)]
+        id: Option<Uuid>,
+        #[serde(flatten)]
+        order: CreateOrderRequest,
+    },
+    Location {
+        courier_id: Uuid,
+        location: GeoPoint,
+    },
+    /// The order was picked up (`InTransit`) or `Delivered`, freeing its
+    /// courier.
+    Status { order_id: Uuid, status: OrderStatus },
+}
+
+/// Reads a JSON-lines log, skipping blank lines, and sorts it by time;
+/// records with the same time keep their order.
+pub fn parse_log(reader: impl BufRead) -> Result<Vec<ReplayRecord>, AppError> {
+    let mut records = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line.map_err(|err| AppError::Internal(format!("failed to read log: {err}")))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record = serde_json::from_str(&line)
+            .map_err(|err| AppError::BadRequest(format!("line {}: {err}", index + 1)))?;
+        records.push(record);
+    }
+    records.sort_by_key(|record: &ReplayRecord| record.at);
+    Ok(records)
+}
+
+/// What a replay fed the engine and, once `tally` has run, what the engine
+/// made of it.
+#[derive(Debug, Default, Serialize)]
+pub struct ReplaySummary {
+    pub records: u64,
+    /// Records that could not be applied, e.g. a move for an unknown courier
+    /// or a delivery for an order the replay never assigned.
+    pub skipped: u64,
+    pub couriers: u64,
+    pub orders: u64,
+    pub assigned: u64,
+    pub unassigned: u64,
+    pub mean_score: Option<f64>,
+    pub mean_pickup_km: Option<f64>,
+    /// From an order coming in to its assignment, in the log's own time.
+    pub mean_wait_seconds: Option<f64>,
+    /// IDs the replayed orders were given.
+    #[serde(skip)]
+    replayed: HashSet<Uuid>,
+}
+
+impl ReplaySummary {
+    /// Waits until none of the replayed orders is left waiting for a
+    /// courier, or `timeout` runs out, moving `clock` on at `speed`
+    /// meanwhile.
+    pub async fn drain(
+        &self,
+        state: &AppState,
+        clock: &ManualClock,
+        speed: f64,
+        timeout: Duration,
+    ) {
+        const POLL: Duration = Duration::from_millis(50);
+        let deadline = tokio::time::Instant::now() + timeout;
+        while tokio::time::Instant::now() < deadline {
+            let waiting = self.replayed.iter().any(|id| {
+                state
+                    .orders
+                    .get(id)
+                    .is_some_and(|order| order.status == OrderStatus::Pending)
+            });
+            if !waiting {
+                return;
+            }
+            tokio::time::sleep(POLL).await;
+            clock.set(later(clock.now(), POLL, speed));
+        }
+    }
+
+    /// Fills in how the engine did on the replayed orders.
+    pub fn tally(&mut self, state: &AppState) {
+        let (mut assigned, mut score_sum, mut wait_sum) = (0, 0.0, 0.0);
+        for order_id in &self.replayed {
+            let Some(assignment) = state.latest_assignment_for_order(*order_id) else {
+                continue;
+            };
+            assigned += 1;
+            score_sum += assignment.score;
+            if let Some(order) = state.orders.get(order_id) {
+                let wait = (assignment.assigned_at - order.created_at)
+                    .to_std()
+                    .unwrap_or_default();
+                wait_sum += wait.as_secs_f64();
+            }
+        }
+        self.assigned = assigned;
+        self.unassigned = self.replayed.len() as u64 - assigned;
+        let per_assignment = |sum: f64| (assigned > 0).then(|| sum / assigned as f64);
+        self.mean_score = per_assignment(score_sum);
+        self.mean_wait_seconds = per_assignment(wait_sum);
+
+        let pickup = &state.metrics.assignment_pickup_distance_km;
+        self.mean_pickup_km = (pickup.get_sample_count() > 0)
+            .then(|| pickup.get_sample_sum() / pickup.get_sample_count() as f64);
+    }
+}
+
+/// Feeds `records` to the engine behind `state` with the gaps between them
+/// divided by `speed`, so 60 plays an hour of traffic in a minute.
+///
+/// `clock` must be the state's clock. It follows the log: it reads each
+/// record's `at` as that record is fed, and runs at `speed` in between, so
+/// the engine stamps what it does in the log's own time.
+pub async fn replay(
+    state: &AppState,
+    clock: &ManualClock,
+    records: Vec<ReplayRecord>,
+    speed: f64,
+) -> ReplaySummary {
+    let mut summary = ReplaySummary::default();
+    let mut orders: HashMap<Uuid, Uuid> = HashMap::new();
+    if let Some(first) = records.first() {
+        clock.set(first.at);
+    }
+    for record in records {
+        pace(clock, record.at, speed).await;
+        summary.records += 1;
+
+        let applied = match record.event {
+            ReplayEvent::Courier { id, courier } => build_courier(courier, state.capacity_unit)
+                .map(|mut courier| {
+                    courier.id = id;
+                    register_courier(state, courier, ACTOR);
+                    summary.couriers += 1;
+                }),
+            ReplayEvent::Order { id, order } => submit_order(state, NewOrder::from(order), ACTOR)
+                .await
+                .map(|submitted| {
+                    if let Some(id) = id {
+                        orders.insert(id, submitted.id);
+                    }
+                    summary.replayed.insert(submitted.id);
+                    summary.orders += 1;
+                }),
+            ReplayEvent::Location {
+                courier_id,
+                location,
+            } => move_courier(state, courier_id, location).map(drop),
+            ReplayEvent::Status { order_id, status } => match orders.get(&order_id) {
+                Some(&order_id) => advance(state, order_id, status),
+                None => Err(AppError::NotFound(format!(
+                    "order {order_id} not in the log"
+                ))),
+            },
+        };
+        if let Err(err) = applied {
+            debug!(at = %record.at, error = %err, "skipping replay record");
+            summary.skipped += 1;
+        }
+    }
+    summary
+}
+
+/// Runs `clock` on at `speed` until it reads `until`.
+async fn pace(clock: &ManualClock, until: DateTime<Utc>, speed: f64) {
+    loop {
+        let now = clock.now();
+        let left = (until - now).to_std().unwrap_or_default();
+        let wall = Duration::try_from_secs_f64(left.as_secs_f64() / speed).unwrap_or(Duration::MAX);
+        if wall <= TICK {
+            if !wall.is_zero() {
+                tokio::time::sleep(wall).await;
+            }
+            clock.set(until.max(now));
+            return;
+        }
+        tokio::time::sleep(TICK).await;
+        clock.set(later(now, TICK, speed).min(until));
+    }
+}
+
+/// `now` moved on by `wall` real time played at `speed`.
+fn later(now: DateTime<Utc>, wall: Duration, speed: f64) -> DateTime<Utc> {
+    Duration::try_from_secs_f64(wall.as_secs_f64() * speed)
+        .ok()
+        .and_then(|elapsed| TimeDelta::from_std(elapsed).ok())
+        .and_then(|elapsed| now.checked_add_signed(elapsed))
+        .unwrap_or(DateTime::<Utc>::MAX_UTC)
+}
+
+/// Moves an order on to `status`, through `InTransit` if it goes straight
+/// from `Assigned` to `Delivered` in the log.
+fn advance(state: &AppState, order_id: Uuid, status: OrderStatus) -> Result<(), AppError> {
+    let current = state
+        .orders
+        .get(&order_id)
+        .map(|order| order.status.clone());
+    if status == OrderStatus::Delivered && current == Some(OrderStatus::Assigned) {
+        transition_order(state, order_id, OrderStatus::InTransit, ACTOR)?;
+    }
+    transition_order(state, order_id, status, ACTOR).map(drop)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_log, ReplayEvent};
+
+    #[test]
+    fn parses_records_in_time_order() {
+        let log = r#"
+{"at":"2026-10-16T18:00:05Z","type":"location","courier_id":"0192f0c4-0000-7000-8000-000000000001","location":{"lat":52.52,"lng":13.41}}
+{"at":"2026-10-16T18:00:00Z","type":"courier","id":"0192f0c4-0000-7000-8000-000000000001","name":"Bo","location":{"lat":52.52,"lng":13.4},"capacity":2,"rating":4.5}
+
+{"at":"2026-10-16T18:00:05Z","type":"order","pickup":{"lat":52.52,"lng":13.4},"dropoff":{"lat":52.53,"lng":13.41},"priority":"High"}
+"#;
+        let records = parse_log(log.as_bytes()).unwrap();
+        assert_eq!(records.len(), 3);
+        assert!(matches!(records[0].event, ReplayEvent::Courier { .. }));
+        assert!(matches!(records[1].event, ReplayEvent::Location { .. }));
+        assert!(matches!(
+            records[2].event,
+            ReplayEvent::Order { id: None, .. }
+        ));
+
+        let Err(err) = parse_log(r#"{"at":"2026-10-16T18:00:00Z","type":"teleport"}"#.as_bytes())
+        else {
+            panic!("unknown record type accepted");
+        };
+        assert!(err.to_string().contains("line 1"), "{err}");
+    }
+}
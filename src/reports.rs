@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Days, NaiveDate, NaiveTime, Utc};
+use dashmap::DashMap;
+use tracing::{info, warn};
+
+use crate::geo::zone_of;
+use crate::models::event::DispatchEvent;
+use crate::models::report::{DailyReport, ReportTotals, ZoneReport};
+use crate::state::AppState;
+
+/// How long after midnight UTC the day before is reported on, so events
+/// from its last moments have landed.
+const REPORT_DELAY: Duration = Duration::from_secs(60);
+
+/// Daily reports by date. With `REPORTS_DIR` set, each is also written
+/// there as `<tenant>/<date>.json` and read back from there after a
+/// restart.
+#[derive(Default)]
+pub struct ReportStore {
+    reports: DashMap<NaiveDate, DailyReport>,
+    dir: Option<PathBuf>,
+}
+
+impl ReportStore {
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        Self {
+            reports: DashMap::new(),
+            dir,
+        }
+    }
+
+    pub fn get(&self, date: NaiveDate) -> Option<DailyReport> {
+        if let Some(report) = self.reports.get(&date) {
+            return Some(report.clone());
+        }
+        let path = self.dir.as_ref()?.join(format!("{date}.json"));
+        let report: DailyReport = match std::fs::read(&path) {
+            Ok(contents) => serde_json::from_slice(&contents)
+                .inspect_err(|err| warn!(path = %path.display(), error = %err, "unreadable report"))
+                .ok()?,
+            Err(_) => return None,
+        };
+        self.reports.insert(date, report.clone());
+        Some(report)
+    }
+
+    /// Keeps `report`, replacing any earlier one for its date. It is kept in
+    /// memory even if writing it to disk fails.
+    pub fn insert(&self, report: DailyReport) -> std::io::Result<()> {
+        let date = report.date;
+        let written = match &self.dir {
+            Some(dir) => std::fs::create_dir_all(dir).and_then(|()| {
+                std::fs::write(
+                    dir.join(format!("{date}.json")),
+                    serde_json::to_vec_pretty(&report)?,
+                )
+            }),
+            None => Ok(()),
+        };
+        self.reports.insert(date, report);
+        written
+    }
+}
+
+/// Orders, assignments, deliveries and the on-time rate over the UTC day
+/// `date`, in total and per pickup zone.
+pub fn daily_report(state: &AppState, date: NaiveDate, now: DateTime<Utc>) -> DailyReport {
+    let start = date.and_time(NaiveTime::MIN).and_utc();
+    let end = start + Days::new(1);
+    let on_the_day = |at: DateTime<Utc>| (start..end).contains(&at);
+
+    let _snapshot = state.snapshots.read();
+    let mut zones: HashMap<String, ReportTotals> = HashMap::new();
+    for order in state.orders.iter() {
+        if on_the_day(order.created_at) {
+            zones.entry(zone_of(&order.pickup)).or_default().orders += 1;
+        }
+    }
+    for assignment in state.assignments.iter() {
+        let assigned = on_the_day(assignment.assigned_at);
+        let delivered = assignment.delivered_at.is_some_and(on_the_day);
+        if !assigned && !delivered {
+            continue;
+        }
+        let Some(zone) = state
+            .orders
+            .get(&assignment.order_id)
+            .map(|order| zone_of(&order.pickup))
+        else {
+            continue;
+        };
+        let totals = zones.entry(zone).or_default();
+        if assigned {
+            totals.assignments += 1;
+        }
+        if delivered {
+            totals.deliveries += 1;
+            if assignment.delivered_on_time() {
+                totals.on_time_deliveries += 1;
+            }
+        }
+    }
+
+    let mut total = ReportTotals::default();
+    let mut zones: Vec<ZoneReport> = zones
+        .into_iter()
+        .map(|(zone, mut totals)| {
+            totals.sla_hit_rate = hit_rate(&totals);
+            total.orders += totals.orders;
+            total.assignments += totals.assignments;
+            total.deliveries += totals.deliveries;
+            total.on_time_deliveries += totals.on_time_deliveries;
+            ZoneReport { zone, totals }
+        })
+        .collect();
+    total.sla_hit_rate = hit_rate(&total);
+    zones.sort_by(|a, b| {
+        b.totals
+            .orders
+            .cmp(&a.totals.orders)
+            .then_with(|| a.zone.cmp(&b.zone))
+    });
+
+    DailyReport {
+        date,
+        generated_at: now,
+        totals: total,
+        zones,
+    }
+}
+
+fn hit_rate(totals: &ReportTotals) -> Option<f64> {
+    (totals.deliveries > 0).then(|| totals.on_time_deliveries as f64 / totals.deliveries as f64)
+}
+
+/// Reports on `date`, keeps the report and, on the leader, publishes it
+/// to webhooks subscribed to `reports`.
+pub fn generate_report(state: &AppState, date: NaiveDate) -> DailyReport {
+    let report = daily_report(state, date, state.clock.now());
+    if let Err(err) = state.reports.insert(report.clone()) {
+        warn!(%date, error = %err, "failed to write report, keeping it in memory only");
+    }
+    if state.leadership.is_leader() {
+        state.events.publish(DispatchEvent::Report(report.clone()));
+    }
+    info!(%date, orders = report.totals.orders, "daily report generated");
+    report
+}
+
+/// Reports on each day shortly after it ends, starting with yesterday if
+/// there is no report on it yet.
+pub async fn run_report_generator(state: Arc<AppState>) {
+    loop {
+        let now = state.clock.now();
+        let today = now.date_naive();
+        if let Some(yesterday) = today.pred_opt()
+            && state.reports.get(yesterday).is_none()
+        {
+            generate_report(&state, yesterday);
+        }
+
+        let next = (today + Days::new(1)).and_time(NaiveTime::MIN).and_utc();
+        let wait = (next - now).to_std().unwrap_or_default() + REPORT_DELAY;
+        tokio::time::sleep(wait).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, Utc};
+
+    use super::ReportStore;
+    use crate::models::report::{DailyReport, ReportTotals};
+
+    #[test]
+    fn reports_are_read_back_from_disk() {
+        let dir = std::env::temp_dir().join(format!("dispatch-reports-{}", uuid::Uuid::new_v4()));
+        let date = NaiveDate::from_ymd_opt(2026, 10, 16).unwrap();
+        let report = DailyReport {
+            date,
+            generated_at: Utc::now(),
+            totals: ReportTotals {
+                orders: 3,
+                ..ReportTotals::default()
+            },
+            zones: Vec::new(),
+        };
+
+        ReportStore::new(Some(dir.clone()))
+            .insert(report.clone())
+            .unwrap();
+        let restarted = ReportStore::new(Some(dir.clone()));
+        assert_eq!(restarted.get(date), Some(report));
+        assert_eq!(restarted.get(date.succ_opt().unwrap()), None);
+        assert_eq!(ReportStore::default().get(date), None);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
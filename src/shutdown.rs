@@ -0,0 +1,149 @@
+use std::time::Duration;
+
+use tokio::time::{sleep, Instant};
+use tracing::{info, warn};
+
+use crate::state::AppState;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Resolves on SIGTERM or ctrl-c.
+pub async fn signal() {
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(err) => {
+                warn!(error = %err, "failed to listen for SIGTERM, stopping on ctrl-c only");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => {
+            if let Err(err) = result {
+                warn!(error = %err, "failed to listen for ctrl-c");
+                std::future::pending::<()>().await;
+            }
+        }
+        _ = terminate => {}
+    }
+}
+
+/// Stops taking new orders, then waits up to `deadline` for the engines to
+/// work through their queues and for event consumers (webhooks, Kafka, NATS,
+/// stream clients) to receive what it published. Returns the orders still
+/// waiting when it gave up; with no courier free they keep being requeued,
+/// so those wait out the whole deadline.
+pub async fn drain(state: &AppState, deadline: Duration) -> usize {
+    state.engine.begin_draining();
+    let deadline = Instant::now() + deadline;
+    info!(
+        queue_depth = state.queued_orders(),
+        "draining the assignment queue"
+    );
+
+    while !state.is_idle() && Instant::now() < deadline {
+        sleep(POLL_INTERVAL).await;
+    }
+    while state.events.undelivered() > 0 && Instant::now() < deadline {
+        sleep(POLL_INTERVAL).await;
+    }
+
+    let remaining = state.queued_orders();
+    if remaining > 0 {
+        warn!(
+            remaining,
+            "shutdown deadline reached with orders still queued"
+        );
+    } else {
+        info!("assignment queue drained");
+    }
+    remaining
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    use super::drain;
+    use crate::engine::assignment::run_assignment_engine;
+    use crate::engine::queue::submit_order;
+    use crate::error::AppError;
+    use crate::models::courier::{Courier, CourierStatus, GeoPoint, OfferHistory, VehicleType};
+    use crate::models::order::{NewOrder, Priority};
+    use crate::state::AppState;
+
+    fn new_order() -> NewOrder {
+        NewOrder {
+            pickup: GeoPoint {
+                lat: 52.52,
+                lng: 13.40,
+            },
+            dropoff: GeoPoint {
+                lat: 52.53,
+                lng: 13.41,
+            },
+            extra_pickups: Vec::new(),
+            priority: Priority::Normal,
+            size: None,
+            items: Vec::new(),
+            cash_on_delivery: None,
+            required_vehicle: None,
+            required_skills: Vec::new(),
+            handling: Vec::new(),
+            callback_url: None,
+            customer_contact: None,
+            request_id: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn drain_refuses_new_orders_and_reports_what_is_left() {
+        let (state, rx) = AppState::new(16, 16);
+        let state = Arc::new(state);
+        submit_order(&state, new_order(), "test").await.unwrap();
+
+        // Nothing reads the queue yet, so the order outlives the deadline.
+        assert_eq!(drain(&state, Duration::from_millis(100)).await, 1);
+        assert!(matches!(
+            submit_order(&state, new_order(), "test").await,
+            Err(AppError::Unavailable(_))
+        ));
+
+        let courier = Courier {
+            id: Uuid::new_v4(),
+            name: "Max".to_string(),
+            location: GeoPoint {
+                lat: 52.52,
+                lng: 13.40,
+            },
+            capacity: 3.0,
+            current_load: 0.0,
+            status: CourierStatus::Available,
+            break_until: None,
+            rating: 4.5,
+            offers: OfferHistory::default(),
+            last_assigned_at: None,
+            vehicle_type: VehicleType::Bike,
+            updated_at: Utc::now(),
+            last_seen_at: Utc::now(),
+            shifts: Vec::new(),
+            skills: Vec::new(),
+            preferred_zones: Vec::new(),
+            accepts_cod: false,
+            cod_limit: None,
+            equipment: Vec::new(),
+            location_cache: Default::default(),
+        };
+        state.couriers.insert(courier.id, Arc::new(courier));
+        tokio::spawn(run_assignment_engine(state.clone(), rx));
+        assert_eq!(drain(&state, Duration::from_secs(5)).await, 0);
+    }
+}
@@ -0,0 +1,60 @@
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// Keeps readers from seeing a change to several maps half applied, such
+/// as an order already `Assigned` to a courier whose load has not been
+/// bumped yet. Such changes are made under [`Snapshots::change`]; readers
+/// that return a view of the orders, couriers or assignments copy it out
+/// under [`Snapshots::read`], so they see each change whole or not at all.
+///
+/// Take the guard before touching any of the maps, and drop it before any
+/// `.await`: waiting for it while holding a map shard can deadlock against
+/// a holder that needs that shard.
+#[derive(Default)]
+pub struct Snapshots {
+    lock: RwLock<()>,
+}
+
+impl Snapshots {
+    /// Held while one change is applied to several maps.
+    pub fn change(&self) -> RwLockWriteGuard<'_, ()> {
+        self.lock.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Held while a consistent view is copied out; changes wait until it
+    /// is dropped.
+    pub fn read(&self) -> RwLockReadGuard<'_, ()> {
+        self.lock.read().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::Snapshots;
+
+    #[test]
+    fn readers_wait_out_a_change_in_progress() {
+        let snapshots = Snapshots::default();
+        let (order, load) = (AtomicU32::new(0), AtomicU32::new(0));
+        let (started_tx, started_rx) = mpsc::channel();
+
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                let _change = snapshots.change();
+                started_tx.send(()).unwrap();
+                order.store(1, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(50));
+                load.store(1, Ordering::SeqCst);
+            });
+
+            started_rx.recv().unwrap();
+            let _snapshot = snapshots.read();
+            assert_eq!(order.load(Ordering::SeqCst), 1);
+            assert_eq!(load.load(Ordering::SeqCst), 1);
+        });
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tonic::metadata::MetadataValue;
+use tonic::{Request, Status};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::api::grpc::convert::{change_from_proto, courier_location_from_proto};
+use crate::api::grpc::pb::dispatch_service_client::DispatchServiceClient;
+use crate::api::grpc::pb::{WatchChangesRequest, WatchCourierLocationsRequest};
+use crate::engine::queue::enqueue_order;
+use crate::geo::cell_id;
+use crate::geo::region::REGION_PRECISION;
+use crate::models::courier::CourierLocation;
+use crate::models::event::EntityChange;
+use crate::models::order::{DeliveryOrder, OrderStatus};
+use crate::state::AppState;
+use crate::tenants::{DEFAULT_TENANT, TENANT_HEADER};
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+const PROMOTION_POLL: Duration = Duration::from_secs(1);
+const ACTOR: &str = "standby";
+
+#[derive(Debug, Clone)]
+pub struct StandbySettings {
+    /// Sent to the primary as `authorization: Bearer <token>`.
+    pub token: Option<String>,
+    /// Whether leader election decides when this instance takes over.
+    /// Without it, it takes over once `STANDBY_PRIMARY_URL` is cleared.
+    pub elected: bool,
+}
+
+/// Mirrors the couriers and orders of the instance at `STANDBY_PRIMARY_URL`
+/// into `state`, over its gRPC `WatchChanges` and `WatchCourierLocations`
+/// streams, until this instance is to take over: when it wins the leader
+/// lease or, without leader election, when a reload clears
+/// `STANDBY_PRIMARY_URL`. It then leads and queues the mirrored orders
+/// still `Pending` for its own engines. Assignments, order history and
+/// stats are not mirrored.
+///
+/// Start it with leadership off, so the engines wait.
+pub async fn run_standby(state: Arc<AppState>, settings: StandbySettings) {
+    info!(primary = %state.tunables().standby_primary_url, "standing by");
+
+    let mirroring = async {
+        // Carried across reconnects so the primary only sends what was
+        // missed, unless it cannot, or the primary is another one.
+        let mut primary = String::new();
+        let mut last_seq = 0;
+        loop {
+            let url = state.tunables().standby_primary_url.clone();
+            if url != primary {
+                primary = url;
+                last_seq = 0;
+            }
+            if let Err(err) = mirror(&state, &settings, &primary, &mut last_seq).await {
+                warn!(primary, error = %err, "lost the primary, reconnecting");
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    };
+    tokio::select! {
+        _ = mirroring => {}
+        () = promoted(&state) => {}
+    }
+
+    take_over(&state, &settings).await;
+}
+
+async fn mirror(
+    state: &AppState,
+    settings: &StandbySettings,
+    url: &str,
+    last_seq: &mut u64,
+) -> Result<(), Status> {
+    let mut client = DispatchServiceClient::connect(url.to_string())
+        .await
+        .map_err(|err| Status::unavailable(err.to_string()))?;
+    // Subscribed first, so that no move is missed between the copy the
+    // primary sends and the start of this stream.
+    let mut locations = client
+        .watch_courier_locations(request(
+            state,
+            settings,
+            WatchCourierLocationsRequest::default(),
+        )?)
+        .await?
+        .into_inner();
+    let mut changes = client
+        .watch_changes(request(
+            state,
+            settings,
+            WatchChangesRequest {
+                resume_from: *last_seq,
+            },
+        )?)
+        .await?
+        .into_inner();
+    info!(
+        primary = url,
+        resume_from = *last_seq,
+        "mirroring the primary"
+    );
+
+    // The two streams are not ordered with each other: moves of couriers
+    // whose registration has not arrived yet wait here for it.
+    let mut early_moves = HashMap::new();
+    loop {
+        tokio::select! {
+            change = changes.message() => {
+                let Some(change) = change? else {
+                    return Ok(());
+                };
+                let seq = change.seq;
+                apply_change(state, change_from_proto(change)?, &mut early_moves);
+                *last_seq = seq;
+                state.metrics.standby_changes_applied_total.inc();
+            }
+            location = locations.message() => {
+                let Some(location) = location? else {
+                    return Ok(());
+                };
+                let update = courier_location_from_proto(location)?;
+                if !state.couriers.contains_key(&update.courier_id) {
+                    early_moves.insert(update.courier_id, update);
+                    continue;
+                }
+                apply_location(state, update);
+            }
+        }
+    }
+}
+
+#[allow(clippy::result_large_err)]
+fn request<T>(
+    state: &AppState,
+    settings: &StandbySettings,
+    message: T,
+) -> Result<Request<T>, Status> {
+    let mut request = Request::new(message);
+    let metadata = request.metadata_mut();
+    if state.tenant != DEFAULT_TENANT {
+        let tenant = MetadataValue::try_from(state.tenant.as_str())
+            .map_err(|_| Status::invalid_argument("tenant is not valid metadata"))?;
+        metadata.insert(TENANT_HEADER, tenant);
+    }
+    if let Some(token) = &settings.token {
+        let bearer = MetadataValue::try_from(format!("Bearer {token}"))
+            .map_err(|_| Status::invalid_argument("STANDBY_TOKEN is not valid metadata"))?;
+        metadata.insert("authorization", bearer);
+    }
+    Ok(request)
+}
+
+/// Stores a courier or order as the primary published it. Nothing is
+/// published here: the primary's own consumers already saw it.
+fn apply_change(
+    state: &AppState,
+    change: EntityChange,
+    early_moves: &mut HashMap<Uuid, CourierLocation>,
+) {
+    match change {
+        EntityChange::Courier(courier) => {
+            let mut courier = Arc::unwrap_or_clone(courier);
+            // A move can overtake the change before it; keep the newer
+            // position.
+            if let Some(current) = state.couriers.get(&courier.id)
+                && current.updated_at > courier.updated_at
+            {
+                courier.location = current.location.clone();
+                courier.updated_at = current.updated_at;
+                courier.last_seen_at = current.last_seen_at;
+            }
+            courier.set_location(courier.location.clone());
+            let courier_id = courier.id;
+            {
+                // Held in case the courier moves to another region's shard.
+                let _change = state.snapshots.change();
+                state.couriers.insert(courier_id, Arc::new(courier));
+            }
+            if let Some(update) = early_moves.remove(&courier_id) {
+                apply_location(state, update);
+            }
+        }
+        EntityChange::Order(order) => {
+            let _change = state.snapshots.change();
+            let (order_id, pickup) = (order.id, order.pickup.clone());
+            if state.orders.insert(order_id, *order).is_none() {
+                state.pickup_index.insert(order_id, &pickup);
+            }
+        }
+    }
+}
+
+/// Moves a mirrored courier, unless a change made since already did.
+fn apply_location(state: &AppState, update: CourierLocation) {
+    let crossed = state
+        .couriers
+        .get_mut(&update.courier_id)
+        .is_some_and(|mut entry| {
+            if entry.updated_at > update.updated_at {
+                return false;
+            }
+            let from = cell_id(&entry.location, REGION_PRECISION);
+            let courier = Arc::make_mut(&mut entry);
+            courier.set_location(update.location);
+            courier.updated_at = update.updated_at;
+            courier.last_seen_at = update.updated_at;
+            cell_id(&courier.location, REGION_PRECISION) != from
+        });
+    if crossed {
+        let _change = state.snapshots.change();
+        state.couriers.rehome(&update.courier_id);
+    }
+}
+
+/// Resolves once this instance is to take over.
+async fn promoted(state: &AppState) {
+    let cleared = async {
+        let mut poll = tokio::time::interval(PROMOTION_POLL);
+        loop {
+            poll.tick().await;
+            if state.tunables().standby_primary_url.is_empty() {
+                return;
+            }
+        }
+    };
+    tokio::select! {
+        () = state.leadership.acquired() => {}
+        () = cleared => {}
+    }
+}
+
+async fn take_over(state: &AppState, settings: &StandbySettings) {
+    if !settings.elected && state.leadership.set(true) {
+        state.metrics.record_leadership(true);
+    }
+    let mut pending: Vec<DeliveryOrder> = state
+        .orders
+        .iter()
+        .filter(|entry| entry.status == OrderStatus::Pending)
+        .map(|entry| entry.value().clone())
+        .collect();
+    pending.sort_by_key(|order| order.created_at);
+    info!(
+        couriers = state.couriers.len(),
+        pending_orders = pending.len(),
+        "standby taking over"
+    );
+
+    for order in pending {
+        if let Err(err) = enqueue_order(state, order.clone(), ACTOR).await {
+            error!(order_id = %order.id, error = %err, "failed to queue mirrored order");
+        }
+    }
+}
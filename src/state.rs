@@ -1,39 +1,553 @@
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
 use dashmap::DashMap;
 use tokio::sync::{broadcast, mpsc};
 use uuid::Uuid;
 
+use crate::audit::AuditLog;
+use crate::auth::{JwtSettings, JwtVerifier};
+use crate::chaos::{Chaos, ChaosSettings};
+use crate::clock::{Clock, SystemClock};
+use crate::config::{Config, Tunables};
+use crate::connections::{ConnectionLimits, ConnectionTracker};
+use crate::engine::constraints::{default_constraints, Constraint};
+use crate::engine::distance::DistanceCache;
+use crate::engine::earnings::EarningsLedger;
+use crate::engine::leadership::Leadership;
+use crate::engine::lifecycle::PayoutRates;
+use crate::engine::queue::QueuedOrder;
+use crate::engine::regions::{region_of, shard_of_cell, DispatchRegion};
+use crate::engine::status::{EngineStatus, Readiness};
+use crate::engine::surge::SurgeMonitor;
+use crate::engine::timeline::AssignmentTimeline;
+use crate::events::EventBus;
+use crate::geo::cell_id;
+use crate::geo::index::PickupIndex;
+use crate::geo::region::{RegionMap, REGION_PRECISION};
 use crate::models::assignment::Assignment;
-use crate::models::courier::Courier;
-use crate::models::order::DeliveryOrder;
-use crate::observability::metrics::Metrics;
+use crate::models::courier::{CapacityUnit, Courier, CourierLocation, GeoPoint, ZoneMode};
+use crate::models::device::CourierDevice;
+use crate::models::event::{DispatchEvent, EntityChange};
+use crate::models::feedback::Feedback;
+use crate::models::history::{OrderHistoryEntry, OrderHistoryEvent};
+use crate::models::order::OrderStatus;
+use crate::models::order::{DeliveryOrder, OrderDeadLetter, OrderEvent};
+use crate::models::stats::DeliveryStats;
+use crate::models::webhook::{DeadLetter, Webhook};
+use crate::observability::metrics::{Metrics, MetricsAccess};
+use crate::observability::stats::DispatchStats;
+use crate::rate_limit::{RateLimiter, RateLimits};
+use crate::reports::ReportStore;
+use crate::snapshot::Snapshots;
+use crate::tenants::DEFAULT_TENANT;
+
+const DEFAULT_EVENT_REPLAY_SIZE: usize = 256;
+const DEFAULT_AUDIT_LOG_SIZE: usize = 10_000;
+const DEFAULT_DISTANCE_CACHE_SIZE: usize = 4096;
+const DEFAULT_PARALLEL_SCORING_THRESHOLD: usize = 10_000;
+
+/// How long-lived stream connections are kept honest: the server pings every
+/// `ping_interval` and drops clients it has not heard from in `idle_timeout`.
+#[derive(Debug, Clone, Copy)]
+pub struct Keepalive {
+    pub ping_interval: Duration,
+    pub idle_timeout: Duration,
+}
+
+impl Default for Keepalive {
+    fn default() -> Self {
+        Self {
+            ping_interval: Duration::from_secs(30),
+            idle_timeout: Duration::from_secs(90),
+        }
+    }
+}
+
+/// Which assignments [`AppState::find_assignments`] returns; the default
+/// is all of them.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct AssignmentFilter {
+    /// Only those made after the one with this id.
+    pub after: Option<Uuid>,
+    /// Only those made at or after this time.
+    pub from: Option<DateTime<Utc>>,
+    /// Only those made before this time.
+    pub to: Option<DateTime<Utc>>,
+    pub courier_id: Option<Uuid>,
+}
 
 pub struct AppState {
-    pub couriers: DashMap<Uuid, Courier>,
-    pub orders: DashMap<Uuid, DeliveryOrder>,
+    /// The delivery business this state belongs to; see
+    /// [`crate::tenants::Tenants`].
+    pub tenant: String,
+    /// Each courier as an immutable snapshot. Updates go through
+    /// `Arc::make_mut`, which stores a new version and leaves readers still
+    /// holding the old one undisturbed, so reads share rather than copy.
+    /// Filed by the region each courier is in; see [`RegionMap`].
+    pub couriers: RegionMap<Arc<Courier>>,
+    /// Filed by the region of each order's pickup.
+    pub orders: RegionMap<DeliveryOrder>,
     pub assignments: DashMap<Uuid, Assignment>,
-    pub order_tx: mpsc::Sender<DeliveryOrder>,
-    pub assignment_events_tx: broadcast::Sender<Assignment>,
+    /// Finds assignments by when they were made; add to it alongside
+    /// `assignments`.
+    pub assignment_timeline: AssignmentTimeline,
+    /// Finds orders by where they are picked up.
+    pub pickup_index: PickupIndex,
+    /// Orders the engine rejected, keyed by order ID.
+    pub order_dead_letters: DashMap<Uuid, OrderDeadLetter>,
+    /// Every order's timeline, oldest step first.
+    pub order_history: DashMap<Uuid, Vec<OrderHistoryEntry>>,
+    /// Customer feedback, keyed by order ID.
+    pub feedback: DashMap<Uuid, Feedback>,
+    pub courier_devices: DashMap<Uuid, Vec<CourierDevice>>,
+    /// Kept apart from `couriers` so earnings only show where the stats
+    /// endpoints allow.
+    pub courier_stats: DashMap<Uuid, DeliveryStats>,
+    /// Couriers' earnings today, for the earnings score.
+    pub earnings: EarningsLedger,
+    pub webhooks: DashMap<Uuid, Webhook>,
+    pub webhook_dead_letters: DashMap<Uuid, DeadLetter>,
+    /// The queue and engine of orders picked up outside every region.
+    pub order_tx: mpsc::Sender<QueuedOrder>,
+    pub engine: EngineStatus,
+    /// Areas whose orders go to engines of their own; see `REGIONS`.
+    pub regions: Vec<DispatchRegion>,
+    /// Engines orders outside every region are spread over by pickup
+    /// area: the instance-wide one plus the last `engine_shards - 1` of
+    /// `regions`.
+    pub engine_shards: usize,
+    /// Zones whose orders are boosted for lack of couriers.
+    pub surge: SurgeMonitor,
+    /// Whether this instance may run its engines; shared by every tenant.
+    pub leadership: Arc<Leadership>,
+    pub events: EventBus,
+    /// Whether every change to a courier or order is published whole, for
+    /// standbys; see `PUBLISH_CHANGES`.
+    pub publish_changes: bool,
+    pub audit: AuditLog,
+    /// Courier-to-pickup distances the engine already worked out.
+    pub distances: DistanceCache,
+    /// Guards changes spanning the orders, couriers and assignments so
+    /// list endpoints read them whole.
+    pub snapshots: Snapshots,
+    /// Fleet size from which the engine scores couriers on several threads;
+    /// 0 keeps scoring on one.
+    pub parallel_scoring_threshold: usize,
+    pub courier_locations_tx: broadcast::Sender<CourierLocation>,
+    pub keepalive: Keepalive,
+    pub ws_connections: ConnectionTracker,
+    /// Validates bearer tokens on the REST and gRPC APIs; `None` leaves them
+    /// open.
+    pub jwt: Option<Arc<JwtVerifier>>,
+    pub rate_limiter: RateLimiter,
     pub metrics: Metrics,
+    pub stats: DispatchStats,
+    /// Daily reports, generated after each day ends.
+    pub reports: ReportStore,
+    pub metrics_access: MetricsAccess,
+    /// What courier capacities and order sizes are counted in.
+    pub capacity_unit: CapacityUnit,
+    /// Whether couriers' preferred zones only weigh on scoring or also limit
+    /// which orders they are offered.
+    pub zone_mode: ZoneMode,
+    pub payouts: PayoutRates,
+    /// Rules couriers must pass to be offered an order, checked in order.
+    /// Push to it before the engine starts to add business rules.
+    pub constraints: Vec<Arc<dyn Constraint>>,
+    /// What the engine takes the time from; replace it before the engine
+    /// starts to run on a [`crate::clock::ManualClock`].
+    pub clock: Arc<dyn Clock>,
+    /// Faults injected on purpose; see `CHAOS_*`.
+    pub chaos: Arc<Chaos>,
+    /// Swapped as a whole on reload, so readers never see half an update.
+    tunables: RwLock<Arc<Tunables>>,
 }
 
 impl AppState {
     pub fn new(
         order_queue_size: usize,
         event_buffer_size: usize,
-    ) -> (Self, mpsc::Receiver<DeliveryOrder>) {
+    ) -> (Self, mpsc::Receiver<QueuedOrder>) {
+        Self::with_metrics(
+            DEFAULT_TENANT,
+            Metrics::new(),
+            order_queue_size,
+            event_buffer_size,
+        )
+    }
+
+    /// Like `new`, for one of several tenants: its metrics carry a `tenant`
+    /// label.
+    pub fn new_tenant(
+        tenant: &str,
+        order_queue_size: usize,
+        event_buffer_size: usize,
+    ) -> (Self, mpsc::Receiver<QueuedOrder>) {
+        Self::with_metrics(
+            tenant,
+            Metrics::for_tenant(tenant),
+            order_queue_size,
+            event_buffer_size,
+        )
+    }
+
+    fn with_metrics(
+        tenant: &str,
+        metrics: Metrics,
+        order_queue_size: usize,
+        event_buffer_size: usize,
+    ) -> (Self, mpsc::Receiver<QueuedOrder>) {
         let (order_tx, order_rx) = mpsc::channel(order_queue_size);
-        let (assignment_events_tx, _unused_rx) = broadcast::channel(event_buffer_size);
+        let (courier_locations_tx, _unused_rx) = broadcast::channel(event_buffer_size);
+        let ws_connections = ConnectionTracker::new(
+            ConnectionLimits::default(),
+            metrics.ws_connections_active.clone(),
+        );
+        let chaos = Arc::new(Chaos::new(
+            ChaosSettings::default(),
+            metrics.chaos_injections_total.clone(),
+        ));
 
         (
             Self {
-                couriers: DashMap::new(),
-                orders: DashMap::new(),
+                tenant: tenant.to_string(),
+                couriers: RegionMap::default(),
+                orders: RegionMap::default(),
                 assignments: DashMap::new(),
+                assignment_timeline: AssignmentTimeline::default(),
+                pickup_index: PickupIndex::default(),
+                order_dead_letters: DashMap::new(),
+                order_history: DashMap::new(),
+                feedback: DashMap::new(),
+                courier_devices: DashMap::new(),
+                courier_stats: DashMap::new(),
+                earnings: EarningsLedger::default(),
+                webhooks: DashMap::new(),
+                webhook_dead_letters: DashMap::new(),
                 order_tx,
-                assignment_events_tx,
-                metrics: Metrics::new(),
+                engine: EngineStatus::default(),
+                regions: Vec::new(),
+                engine_shards: 1,
+                surge: SurgeMonitor::default(),
+                leadership: Arc::default(),
+                events: EventBus::new(event_buffer_size, DEFAULT_EVENT_REPLAY_SIZE),
+                publish_changes: false,
+                audit: AuditLog::new(DEFAULT_AUDIT_LOG_SIZE),
+                distances: DistanceCache::new(DEFAULT_DISTANCE_CACHE_SIZE),
+                snapshots: Snapshots::default(),
+                parallel_scoring_threshold: DEFAULT_PARALLEL_SCORING_THRESHOLD,
+                courier_locations_tx,
+                keepalive: Keepalive::default(),
+                ws_connections,
+                jwt: None,
+                rate_limiter: RateLimiter::new(RateLimits::default()),
+                metrics,
+                stats: DispatchStats::default(),
+                reports: ReportStore::default(),
+                metrics_access: MetricsAccess::default(),
+                capacity_unit: CapacityUnit::default(),
+                zone_mode: ZoneMode::default(),
+                payouts: PayoutRates::default(),
+                constraints: default_constraints(),
+                clock: Arc::new(SystemClock),
+                chaos,
+                tunables: RwLock::new(Arc::new(Tunables::default())),
             },
             order_rx,
         )
     }
+
+    /// Builds the state with every tunable taken from `config`; `new` uses
+    /// defaults for anything beyond the channel sizes.
+    pub fn from_config(config: &Config) -> (Self, mpsc::Receiver<QueuedOrder>) {
+        Self::configure(
+            Self::new(config.order_queue_size, config.event_buffer_size),
+            config,
+        )
+    }
+
+    /// Like `from_config`, for one of several tenants.
+    pub fn tenant_from_config(
+        config: &Config,
+        tenant: &str,
+    ) -> (Self, mpsc::Receiver<QueuedOrder>) {
+        Self::configure(
+            Self::new_tenant(tenant, config.order_queue_size, config.event_buffer_size),
+            config,
+        )
+    }
+
+    fn configure(
+        (mut state, order_rx): (Self, mpsc::Receiver<QueuedOrder>),
+        config: &Config,
+    ) -> (Self, mpsc::Receiver<QueuedOrder>) {
+        state.chaos = Arc::new(Chaos::new(
+            config.chaos,
+            state.metrics.chaos_injections_total.clone(),
+        ));
+        state.events = EventBus::new(config.event_buffer_size, config.event_replay_size)
+            .with_chaos(state.chaos.clone());
+        state.audit = AuditLog::new(config.audit_log_size);
+        state.distances = DistanceCache::new(config.distance_cache_size);
+        state.stats = DispatchStats::new(
+            Duration::from_secs(config.stats_window_secs),
+            config.stats_timeseries_minutes,
+        );
+        state.reports = ReportStore::new(
+            config
+                .reports_dir
+                .as_ref()
+                .map(|dir| dir.join(&state.tenant)),
+        );
+        state.parallel_scoring_threshold = config.parallel_scoring_threshold;
+        state.regions = config
+            .regions
+            .iter()
+            .map(|settings| DispatchRegion::new(settings.clone(), config.order_queue_size))
+            .chain(
+                (1..config.engine_shards)
+                    .map(|index| DispatchRegion::shard(index, config.order_queue_size)),
+            )
+            .collect();
+        state.engine_shards = config.engine_shards;
+        state.surge = SurgeMonitor::new(config.surge);
+        state.keepalive = Keepalive {
+            ping_interval: Duration::from_secs(config.ws_ping_interval_secs),
+            idle_timeout: Duration::from_secs(config.ws_idle_timeout_secs),
+        };
+        state.ws_connections = ConnectionTracker::new(
+            ConnectionLimits {
+                max_total: config.ws_max_connections,
+                max_per_ip: config.ws_max_connections_per_ip,
+            },
+            state.metrics.ws_connections_active.clone(),
+        );
+        state.metrics_access = MetricsAccess {
+            bearer_token: config.metrics_bearer_token.clone(),
+            basic_auth: config.metrics_basic_auth.clone(),
+            separate_port: config.metrics_port != 0,
+        };
+        state.capacity_unit = config.capacity_unit;
+        state.zone_mode = config.zone_mode;
+        state.payouts = config.payouts;
+        state.publish_changes = config.publish_changes;
+        state.set_tunables(config.tunables.clone());
+        state.rate_limiter = RateLimiter::new(RateLimits {
+            order_create_per_min: config.rate_limit_order_create_per_min,
+            write_per_min: config.rate_limit_write_per_min,
+            read_per_min: config.rate_limit_read_per_min,
+        });
+        if !config.jwt_jwks_url.is_empty() {
+            state.jwt = Some(Arc::new(JwtVerifier::new(JwtSettings {
+                jwks_url: config.jwt_jwks_url.clone(),
+                issuer: config.jwt_issuer.clone(),
+                audience: config.jwt_audience.clone(),
+                refresh_interval: Duration::from_secs(config.jwt_jwks_refresh_secs),
+                courier_token_secret: config.courier_token_secret.clone(),
+                courier_token_ttl: Duration::from_secs(config.courier_token_ttl_secs),
+            })));
+        }
+        (state, order_rx)
+    }
+
+    /// Orders waiting in the queue, whether or not the engine is reading it.
+    pub fn queue_depth(&self) -> usize {
+        self.order_tx.max_capacity() - self.order_tx.capacity()
+    }
+
+    /// Ready only while every region's engine is as well; the queue
+    /// figures add up all the queues.
+    pub fn readiness(&self) -> Readiness {
+        let (now, requeue_delay) = (self.clock.now(), self.tunables().requeue_delay);
+        let mut readiness = self.engine.readiness(
+            self.queue_depth(),
+            self.order_tx.max_capacity(),
+            now,
+            requeue_delay,
+        );
+        for region in &self.regions {
+            let regional = region.engine.readiness(
+                region.queue_depth(),
+                region.order_tx.max_capacity(),
+                now,
+                requeue_delay,
+            );
+            readiness.ready &= regional.ready;
+            for reason in regional.reasons {
+                if !readiness.reasons.contains(&reason) {
+                    readiness.reasons.push(reason);
+                }
+            }
+            readiness.queue_depth += regional.queue_depth;
+            readiness.queue_capacity += regional.queue_capacity;
+        }
+        readiness
+    }
+
+    /// Orders waiting in the queues of every engine.
+    pub fn queued_orders(&self) -> usize {
+        self.queue_depth()
+            + self
+                .regions
+                .iter()
+                .map(DispatchRegion::queue_depth)
+                .sum::<usize>()
+    }
+
+    /// Whether every engine has worked through its queue.
+    pub fn is_idle(&self) -> bool {
+        self.engine.is_idle(self.queue_depth())
+            && self
+                .regions
+                .iter()
+                .all(|region| region.engine.is_idle(region.queue_depth()))
+    }
+
+    /// Index into `regions` of the region, or engine shard, whose engine
+    /// takes orders picked up at `point`; `None` for the instance-wide
+    /// engine.
+    pub fn region_of(&self, point: &GeoPoint) -> Option<usize> {
+        region_of(&self.regions, point).or_else(|| {
+            let shard = shard_of_cell(cell_id(point, REGION_PRECISION), self.engine_shards);
+            (shard > 0).then(|| self.regions.len() + shard - self.engine_shards)
+        })
+    }
+
+    /// The status of the engine for `region`, as from `region_of`.
+    pub fn engine_of(&self, region: Option<usize>) -> &EngineStatus {
+        region.map_or(&self.engine, |index| &self.regions[index].engine)
+    }
+
+    pub fn publish_assignment(&self, assignment: &Assignment) {
+        self.events
+            .publish(DispatchEvent::Assignment(assignment.clone()));
+    }
+
+    /// The runtime-tunable settings as of now.
+    pub fn tunables(&self) -> Arc<Tunables> {
+        self.tunables
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    pub fn set_tunables(&self, tunables: Tunables) {
+        *self.tunables.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(tunables);
+    }
+
+    /// Announces `order` as it is after moving from status `from`, or
+    /// being created for `None`.
+    pub fn publish_order_event(&self, from: Option<&OrderStatus>, order: &DeliveryOrder) {
+        self.stats
+            .record_order_status(from, &order.status, self.clock.now());
+        self.events
+            .publish(DispatchEvent::Order(OrderEvent::from_order(
+                order,
+                self.clock.now(),
+            )));
+    }
+
+    /// Publishes `courier` whole if `publish_changes` is on. Call it with
+    /// the courier's entry still locked, so that changes to one courier go
+    /// out in the order they were made.
+    pub fn publish_courier_change(&self, courier: &Arc<Courier>) {
+        if self.publish_changes {
+            self.events
+                .publish(DispatchEvent::Change(EntityChange::Courier(Arc::clone(
+                    courier,
+                ))));
+        }
+    }
+
+    /// Like `publish_courier_change`, for an order.
+    pub fn publish_order_change(&self, order: &DeliveryOrder) {
+        if self.publish_changes {
+            self.events
+                .publish(DispatchEvent::Change(EntityChange::Order(Box::new(
+                    order.clone(),
+                ))));
+        }
+    }
+
+    pub fn publish_courier_location(&self, courier: &Courier) {
+        let _ = self
+            .courier_locations_tx
+            .send(CourierLocation::from_courier(courier));
+    }
+
+    /// Adds a step to the order's timeline.
+    pub fn record_order_history(
+        &self,
+        order_id: Uuid,
+        event: OrderHistoryEvent,
+        actor: &str,
+        courier_id: Option<Uuid>,
+        detail: Option<String>,
+    ) {
+        self.order_history
+            .entry(order_id)
+            .or_default()
+            .push(OrderHistoryEntry {
+                event,
+                actor: actor.to_string(),
+                courier_id,
+                detail,
+                at: self.clock.now(),
+            });
+    }
+
+    /// Drops a courier's device token. Returns whether it was registered.
+    pub fn remove_courier_device(&self, courier_id: Uuid, token: &str) -> bool {
+        let Some(mut devices) = self.courier_devices.get_mut(&courier_id) else {
+            return false;
+        };
+        let before = devices.len();
+        devices.retain(|device| device.token != token);
+        devices.len() < before
+    }
+
+    /// Assignments matching `filter` in the order they were made; at most
+    /// `limit` of them. A time range is looked up in the timeline, so it
+    /// only reads the assignments inside it.
+    pub fn find_assignments(&self, filter: &AssignmentFilter, limit: usize) -> Vec<Assignment> {
+        let matches = |assignment: &Assignment| {
+            filter.after.is_none_or(|after| assignment.id > after)
+                && filter
+                    .courier_id
+                    .is_none_or(|courier_id| assignment.courier_id == courier_id)
+        };
+        let mut assignments: Vec<Assignment> = {
+            let _snapshot = self.snapshots.read();
+            if filter.from.is_some() || filter.to.is_some() {
+                self.assignment_timeline
+                    .between(filter.from, filter.to)
+                    .iter()
+                    .filter_map(|id| self.assignments.get(id))
+                    .filter(|entry| matches(entry.value()))
+                    .map(|entry| entry.value().clone())
+                    .collect()
+            } else {
+                self.assignments
+                    .iter()
+                    .filter(|entry| matches(entry.value()))
+                    .map(|entry| entry.value().clone())
+                    .collect()
+            }
+        };
+        assignments.sort_unstable_by_key(|assignment| assignment.id);
+        assignments.truncate(limit);
+        assignments
+    }
+
+    /// Most recent assignment for an order; an order can be assigned more
+    /// than once if it is ever re-dispatched.
+    pub fn latest_assignment_for_order(&self, order_id: Uuid) -> Option<Assignment> {
+        self.assignments
+            .iter()
+            .filter(|entry| entry.value().order_id == order_id)
+            .max_by_key(|entry| entry.value().assigned_at)
+            .map(|entry| entry.value().clone())
+    }
 }
@@ -0,0 +1,113 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use tokio::sync::mpsc;
+
+use crate::config::Config;
+use crate::engine::queue::QueuedOrder;
+use crate::error::AppError;
+use crate::state::AppState;
+
+/// Header, and gRPC metadata key, naming the tenant a request is for.
+pub const TENANT_HEADER: &str = "x-tenant-id";
+
+/// The tenant of requests that name none; the only one unless `TENANTS`
+/// lists more.
+pub const DEFAULT_TENANT: &str = "default";
+
+const MAX_TENANT_ID_LEN: usize = 64;
+
+/// A tenant's state and the queue its engine reads.
+pub type TenantQueue = (Arc<AppState>, mpsc::Receiver<QueuedOrder>);
+
+/// Whether `id` can name a tenant: 1 to 64 lowercase letters, digits, `-`
+/// or `_`.
+pub fn is_tenant_id(id: &str) -> bool {
+    (1..=MAX_TENANT_ID_LEN).contains(&id.len())
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-' || b == b'_')
+}
+
+/// The delivery businesses one deployment serves. Each tenant has an
+/// `AppState` of its own, so its fleet, orders, assignments, queue and
+/// engine, webhooks and metrics are never seen by another.
+pub struct Tenants {
+    states: BTreeMap<String, Arc<AppState>>,
+}
+
+impl Tenants {
+    /// Files each state under its `tenant`. One of them must be the
+    /// default tenant.
+    pub fn new(states: impl IntoIterator<Item = Arc<AppState>>) -> Self {
+        let states: BTreeMap<_, _> = states
+            .into_iter()
+            .map(|state| (state.tenant.clone(), state))
+            .collect();
+        assert!(
+            states.contains_key(DEFAULT_TENANT),
+            "the default tenant is missing"
+        );
+        Self { states }
+    }
+
+    /// The default tenant plus one per entry in `TENANTS`, all checking
+    /// tokens with the same verifier and leading or following together.
+    /// Each comes with the queue its engine reads.
+    pub fn from_config(config: &Config) -> (Self, Vec<TenantQueue>) {
+        let (default, order_rx) = if config.tenants.is_empty() {
+            AppState::from_config(config)
+        } else {
+            AppState::tenant_from_config(config, DEFAULT_TENANT)
+        };
+        let jwt = default.jwt.clone();
+        let leadership = default.leadership.clone();
+        let mut queues = vec![(Arc::new(default), order_rx)];
+        for tenant in &config.tenants {
+            let (mut state, order_rx) = AppState::tenant_from_config(config, tenant);
+            state.jwt = jwt.clone();
+            state.leadership = leadership.clone();
+            queues.push((Arc::new(state), order_rx));
+        }
+
+        let tenants = Self::new(queues.iter().map(|(state, _)| Arc::clone(state)));
+        (tenants, queues)
+    }
+
+    pub fn default_tenant(&self) -> &Arc<AppState> {
+        &self.states[DEFAULT_TENANT]
+    }
+
+    /// The state of `tenant`, or of the default tenant for `None`.
+    pub fn get(&self, tenant: Option<&str>) -> Result<&Arc<AppState>, AppError> {
+        let tenant = tenant.unwrap_or(DEFAULT_TENANT);
+        self.states
+            .get(tenant)
+            .ok_or_else(|| AppError::BadRequest(format!("unknown tenant {tenant:?}")))
+    }
+
+    /// Every tenant's state, the default tenant's among them.
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<AppState>> {
+        self.states.values()
+    }
+
+    /// Whether tenants beyond the default one are configured.
+    pub fn is_multi_tenant(&self) -> bool {
+        self.states.len() > 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_tenant_id;
+
+    #[test]
+    fn tenant_ids_are_short_lowercase_slugs() {
+        assert!(is_tenant_id("acme"));
+        assert!(is_tenant_id("acme-eu_2"));
+        assert!(!is_tenant_id(""));
+        assert!(!is_tenant_id("Acme"));
+        assert!(!is_tenant_id("acme eu"));
+        assert!(!is_tenant_id(&"a".repeat(65)));
+    }
+}
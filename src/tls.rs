@@ -0,0 +1,126 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use axum_server::tls_rustls::RustlsConfig;
+use tracing::{info, warn};
+
+use crate::error::AppError;
+
+#[derive(Debug, Clone)]
+pub struct TlsSettings {
+    /// PEM certificate chain, leaf first.
+    pub cert_path: PathBuf,
+    /// PEM private key (PKCS#8, PKCS#1 or SEC1).
+    pub key_path: PathBuf,
+    /// How often the files are checked for a rotated certificate.
+    pub reload_interval: Duration,
+}
+
+/// Modification times of the certificate and key. Either changing means the
+/// pair was rotated; `metadata` follows symlinks, so this also catches a
+/// mounted secret being swapped underneath a stable path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Fingerprint {
+    cert: Option<SystemTime>,
+    key: Option<SystemTime>,
+}
+
+impl Fingerprint {
+    async fn of(settings: &TlsSettings) -> Self {
+        Self {
+            cert: modified(&settings.cert_path).await,
+            key: modified(&settings.key_path).await,
+        }
+    }
+}
+
+async fn modified(path: &Path) -> Option<SystemTime> {
+    tokio::fs::metadata(path)
+        .await
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Loads the certificate and key for the HTTPS listener.
+pub async fn load(settings: &TlsSettings) -> Result<RustlsConfig, AppError> {
+    // Only the first install wins; later calls find the same provider in place.
+    let _ = rustls::crypto::ring::default_provider().install_default();
+
+    RustlsConfig::from_pem_file(&settings.cert_path, &settings.key_path)
+        .await
+        .map_err(|err| {
+            AppError::Internal(format!(
+                "failed to load tls certificate {} / key {}: {err}",
+                settings.cert_path.display(),
+                settings.key_path.display()
+            ))
+        })
+}
+
+/// Reloads `config` whenever the certificate or key file changes, so a
+/// renewed certificate is served to new connections without a restart.
+/// Existing connections keep the certificate they were set up with. A pair
+/// that fails to load (e.g. caught mid-rotation) leaves the old one in place
+/// and is retried on the next change.
+pub async fn watch_certificates(config: RustlsConfig, settings: TlsSettings) {
+    let mut loaded = Fingerprint::of(&settings).await;
+    let mut interval = tokio::time::interval(settings.reload_interval);
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+        let current = Fingerprint::of(&settings).await;
+        if current == loaded {
+            continue;
+        }
+        loaded = current;
+
+        match config
+            .reload_from_pem_file(&settings.cert_path, &settings.key_path)
+            .await
+        {
+            Ok(()) => info!(cert = %settings.cert_path.display(), "tls certificate reloaded"),
+            Err(err) => {
+                warn!(error = %err, cert = %settings.cert_path.display(), "failed to reload tls certificate, keeping the previous one")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::{Fingerprint, TlsSettings};
+
+    #[tokio::test]
+    async fn fingerprint_changes_when_a_file_is_rewritten() {
+        let dir = std::env::temp_dir().join(format!("dispatch-tls-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let settings = TlsSettings {
+            cert_path: dir.join("cert.pem"),
+            key_path: dir.join("key.pem"),
+            reload_interval: Duration::from_secs(60),
+        };
+
+        let missing = Fingerprint::of(&settings).await;
+        assert_eq!(missing.cert, None);
+
+        std::fs::write(&settings.cert_path, "cert").unwrap();
+        std::fs::write(&settings.key_path, "key").unwrap();
+        let written = Fingerprint::of(&settings).await;
+        assert_ne!(written, missing);
+        assert_eq!(Fingerprint::of(&settings).await, written);
+
+        let later = SystemTime::now() + Duration::from_secs(5);
+        std::fs::File::options()
+            .write(true)
+            .open(&settings.cert_path)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        assert_ne!(Fingerprint::of(&settings).await, written);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
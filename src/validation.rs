@@ -0,0 +1,264 @@
+use crate::error::AppError;
+use crate::geo::{haversine_km, is_geohash};
+use crate::models::courier::{CapacityUnit, GeoPoint};
+use crate::models::order::OrderItem;
+use crate::models::shift::Shift;
+
+/// Pickup and dropoff closer than this are treated as the same place.
+const MIN_ROUTE_KM: f64 = 0.01;
+/// Most stops one order may pick up from, counting the first.
+pub const MAX_PICKUPS: usize = 10;
+
+/// Rejects coordinates that are not finite or fall outside WGS84 bounds. A
+/// single NaN stored on a courier would make every distance score NaN.
+pub fn validate_point(field: &str, point: &GeoPoint) -> Result<(), AppError> {
+    if !point.lat.is_finite() || !point.lng.is_finite() {
+        return Err(AppError::BadRequest(format!(
+            "{field} must have finite coordinates"
+        )));
+    }
+    if !(-90.0..=90.0).contains(&point.lat) {
+        return Err(AppError::BadRequest(format!(
+            "{field}.lat must be between -90 and 90"
+        )));
+    }
+    if !(-180.0..=180.0).contains(&point.lng) {
+        return Err(AppError::BadRequest(format!(
+            "{field}.lng must be between -180 and 180"
+        )));
+    }
+    Ok(())
+}
+
+/// Both ends must be valid points and not the same place.
+pub fn validate_route(pickup: &GeoPoint, dropoff: &GeoPoint) -> Result<(), AppError> {
+    validate_point("pickup", pickup)?;
+    validate_point("dropoff", dropoff)?;
+    if haversine_km(pickup, dropoff) < MIN_ROUTE_KM {
+        return Err(AppError::BadRequest(
+            "pickup and dropoff must be different places".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Extra pickups must be valid points, and an order may have at most
+/// `MAX_PICKUPS` pickups in all.
+pub fn validate_extra_pickups(stops: &[GeoPoint]) -> Result<(), AppError> {
+    if stops.len() >= MAX_PICKUPS {
+        return Err(AppError::BadRequest(format!(
+            "an order can have at most {MAX_PICKUPS} pickups"
+        )));
+    }
+    for (index, stop) in stops.iter().enumerate() {
+        validate_point(&format!("extra_pickups[{index}]"), stop)?;
+    }
+    Ok(())
+}
+
+/// Ratings above 5 are clamped by the caller; negative or non-finite ones
+/// are rejected.
+pub fn validate_rating(rating: f64) -> Result<(), AppError> {
+    if !rating.is_finite() || rating < 0.0 {
+        return Err(AppError::BadRequest(
+            "rating must be a non-negative number".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Capacities and order sizes must be positive, and whole numbers when
+/// counted in slots.
+pub fn validate_amount(field: &str, amount: f64, unit: CapacityUnit) -> Result<(), AppError> {
+    if !amount.is_finite() || amount <= 0.0 {
+        return Err(AppError::BadRequest(format!("{field} must be > 0")));
+    }
+    if unit == CapacityUnit::Slots && amount.fract() != 0.0 {
+        return Err(AppError::BadRequest(format!(
+            "{field} must be a whole number of slots"
+        )));
+    }
+    Ok(())
+}
+
+/// Cash amounts must be positive numbers.
+pub fn validate_cash(field: &str, amount: f64) -> Result<(), AppError> {
+    if !amount.is_finite() || amount <= 0.0 {
+        return Err(AppError::BadRequest(format!("{field} must be > 0")));
+    }
+    Ok(())
+}
+
+/// Every shift needs at least one weekday and must not start and end at the
+/// same time, which would be ambiguous between no hours and a full day.
+pub fn validate_shifts(shifts: &[Shift]) -> Result<(), AppError> {
+    for (index, shift) in shifts.iter().enumerate() {
+        if shift.weekdays.is_empty() {
+            return Err(AppError::BadRequest(format!(
+                "shifts[{index}].weekdays cannot be empty"
+            )));
+        }
+        if shift.start == shift.end {
+            return Err(AppError::BadRequest(format!(
+                "shifts[{index}] must not start and end at the same time"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Trims and lowercases skill tags and drops duplicates, so `Heavy-Lift`
+/// and `heavy-lift ` match. Empty tags are rejected.
+pub fn normalize_skills(field: &str, skills: Vec<String>) -> Result<Vec<String>, AppError> {
+    let mut normalized: Vec<String> = Vec::with_capacity(skills.len());
+    for skill in skills {
+        let skill = skill.trim().to_lowercase();
+        if skill.is_empty() {
+            return Err(AppError::BadRequest(format!(
+                "{field} cannot contain empty tags"
+            )));
+        }
+        if !normalized.contains(&skill) {
+            normalized.push(skill);
+        }
+    }
+    Ok(normalized)
+}
+
+/// Trims item descriptions, which must not be empty. Weights follow the
+/// same rules as sizes, and every item needs a quantity of at least 1.
+pub fn normalize_items(
+    items: Vec<OrderItem>,
+    unit: CapacityUnit,
+) -> Result<Vec<OrderItem>, AppError> {
+    items
+        .into_iter()
+        .enumerate()
+        .map(|(index, item)| {
+            let description = item.description.trim().to_string();
+            if description.is_empty() {
+                return Err(AppError::BadRequest(format!(
+                    "items[{index}].description cannot be empty"
+                )));
+            }
+            validate_amount(&format!("items[{index}].weight"), item.weight, unit)?;
+            if item.quantity == 0 {
+                return Err(AppError::BadRequest(format!(
+                    "items[{index}].quantity must be at least 1"
+                )));
+            }
+            Ok(OrderItem {
+                description,
+                ..item
+            })
+        })
+        .collect()
+}
+
+/// Trims and lowercases preferred zones and drops duplicates. Each must be a
+/// geohash of 1 to 12 characters.
+pub fn normalize_zones(field: &str, zones: Vec<String>) -> Result<Vec<String>, AppError> {
+    let mut normalized: Vec<String> = Vec::with_capacity(zones.len());
+    for zone in zones {
+        let zone = zone.trim().to_lowercase();
+        if !is_geohash(&zone) {
+            return Err(AppError::BadRequest(format!(
+                "{field} must be geohashes of 1 to 12 characters, got {zone:?}"
+            )));
+        }
+        if !normalized.contains(&zone) {
+            normalized.push(zone);
+        }
+    }
+    Ok(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        normalize_items, normalize_skills, normalize_zones, validate_amount,
+        validate_extra_pickups, validate_point, validate_rating, validate_route, MAX_PICKUPS,
+    };
+    use crate::models::courier::{CapacityUnit, GeoPoint};
+    use crate::models::order::OrderItem;
+
+    fn point(lat: f64, lng: f64) -> GeoPoint {
+        GeoPoint { lat, lng }
+    }
+
+    #[test]
+    fn rejects_non_finite_and_out_of_range_points() {
+        assert!(validate_point("location", &point(52.52, 13.405)).is_ok());
+        assert!(validate_point("location", &point(f64::NAN, 13.405)).is_err());
+        assert!(validate_point("location", &point(52.52, f64::INFINITY)).is_err());
+        assert!(validate_point("location", &point(90.5, 13.405)).is_err());
+        assert!(validate_point("location", &point(52.52, -180.5)).is_err());
+    }
+
+    #[test]
+    fn rejects_same_place_routes_and_negative_ratings() {
+        let pickup = point(52.52, 13.405);
+        assert!(validate_route(&pickup, &point(52.50, 13.42)).is_ok());
+        assert!(validate_route(&pickup, &pickup).is_err());
+        assert!(validate_extra_pickups(&[point(52.51, 13.41)]).is_ok());
+        assert!(validate_extra_pickups(&[point(152.51, 13.41)]).is_err());
+        assert!(validate_extra_pickups(&vec![pickup.clone(); MAX_PICKUPS]).is_err());
+
+        assert!(validate_rating(9.9).is_ok());
+        assert!(validate_rating(-0.1).is_err());
+        assert!(validate_rating(f64::NAN).is_err());
+    }
+
+    #[test]
+    fn slots_must_be_whole_but_kilograms_need_not_be() {
+        assert!(validate_amount("size", 2.0, CapacityUnit::Slots).is_ok());
+        assert!(validate_amount("size", 2.5, CapacityUnit::Slots).is_err());
+        assert!(validate_amount("size", 2.5, CapacityUnit::Kg).is_ok());
+        assert!(validate_amount("size", 0.0, CapacityUnit::Kg).is_err());
+        assert!(validate_amount("size", f64::NAN, CapacityUnit::Liters).is_err());
+    }
+
+    #[test]
+    fn items_need_a_description_weight_and_quantity() {
+        let item = |description: &str, weight: f64, quantity: u32| OrderItem {
+            description: description.to_string(),
+            weight,
+            quantity,
+        };
+
+        let items = normalize_items(vec![item(" Rice 5kg ", 5.0, 2)], CapacityUnit::Kg).unwrap();
+        assert_eq!(items[0].description, "Rice 5kg");
+        assert_eq!(items[0].total_weight(), 10.0);
+
+        assert!(normalize_items(vec![item(" ", 1.0, 1)], CapacityUnit::Kg).is_err());
+        assert!(normalize_items(vec![item("Milk", 0.0, 1)], CapacityUnit::Kg).is_err());
+        assert!(normalize_items(vec![item("Milk", 1.0, 0)], CapacityUnit::Kg).is_err());
+        assert!(normalize_items(vec![item("Milk", 0.5, 1)], CapacityUnit::Slots).is_err());
+    }
+
+    #[test]
+    fn skills_are_normalized_and_deduplicated() {
+        let skills = vec![
+            " Heavy-Lift".to_string(),
+            "pharmacy".to_string(),
+            "heavy-lift".to_string(),
+        ];
+        assert_eq!(
+            normalize_skills("skills", skills).unwrap(),
+            vec!["heavy-lift", "pharmacy"]
+        );
+        assert!(normalize_skills("skills", vec!["  ".to_string()]).is_err());
+    }
+
+    #[test]
+    fn zones_must_be_geohashes() {
+        let zones = vec!["U33D ".to_string(), "u33d".to_string(), "u1".to_string()];
+        assert_eq!(
+            normalize_zones("preferred_zones", zones).unwrap(),
+            vec!["u33d", "u1"]
+        );
+        // `a` is not in the geohash alphabet.
+        assert!(normalize_zones("preferred_zones", vec!["u3a".to_string()]).is_err());
+        assert!(normalize_zones("preferred_zones", vec![String::new()]).is_err());
+    }
+}
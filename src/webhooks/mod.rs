@@ -0,0 +1,209 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use tokio_stream::StreamExt;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::events::{Lagged, RecordedEvent};
+use crate::models::webhook::{DeadLetter, Webhook};
+use crate::state::AppState;
+
+pub const SIGNATURE_HEADER: &str = "x-dispatch-signature";
+pub const EVENT_HEADER: &str = "x-dispatch-event";
+pub const DELIVERY_HEADER: &str = "x-dispatch-delivery";
+
+#[derive(Debug, Clone, Copy)]
+pub struct DeliverySettings {
+    pub max_attempts: u32,
+    /// Delay before the first retry; doubled after every further failure.
+    pub retry_base: Duration,
+    pub timeout: Duration,
+    /// Most dead letters kept; the oldest go first beyond it.
+    pub dead_letter_limit: usize,
+}
+
+/// `sha256=<hex HMAC-SHA256 of body keyed by secret>`, the value of the
+/// signature header receivers should recompute and compare.
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac accepts keys of any size");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Fans assignment and order events out to registered webhooks. Each
+/// delivery runs in its own task, so a slow endpoint delays only itself and
+/// deliveries to one webhook may arrive out of order; `seq` gives the order.
+pub async fn run_webhook_dispatcher(state: Arc<AppState>, settings: DeliverySettings) {
+    let client = match reqwest::Client::builder().timeout(settings.timeout).build() {
+        Ok(client) => client,
+        Err(err) => {
+            warn!(error = %err, "failed to build webhook client, webhooks disabled");
+            return;
+        }
+    };
+
+    info!("webhook dispatcher started");
+
+    let mut events = Box::pin(state.events.stream(None, None));
+    let mut last_seq = 0;
+
+    while let Some(item) = events.next().await {
+        let batch = match item {
+            Ok(recorded) if recorded.seq <= last_seq => continue,
+            Ok(recorded) => vec![recorded],
+            // Fill the gap from the replay buffer; anything already evicted
+            // from it is lost to webhooks.
+            Err(Lagged(dropped)) => {
+                state.metrics.record_dropped_events("webhook", dropped);
+                warn!(dropped, "webhook dispatcher lagged, replaying from buffer");
+                state.events.replay(Some(last_seq), None)
+            }
+        };
+
+        for recorded in batch {
+            last_seq = last_seq.max(recorded.seq);
+            dispatch(&state, &client, settings, recorded);
+        }
+    }
+
+    info!("webhook dispatcher stopped");
+}
+
+fn dispatch(
+    state: &Arc<AppState>,
+    client: &reqwest::Client,
+    settings: DeliverySettings,
+    recorded: RecordedEvent,
+) {
+    if recorded.relayed {
+        return;
+    }
+
+    let topic = recorded.event.topic();
+    let targets: Vec<Webhook> = state
+        .webhooks
+        .iter()
+        .filter(|entry| entry.value().event_types.contains(&topic))
+        .map(|entry| entry.value().clone())
+        .collect();
+
+    if targets.is_empty() {
+        return;
+    }
+
+    let recorded = Arc::new(recorded);
+    for webhook in targets {
+        tokio::spawn(deliver(
+            state.clone(),
+            client.clone(),
+            settings,
+            webhook,
+            recorded.clone(),
+        ));
+    }
+}
+
+async fn deliver(
+    state: Arc<AppState>,
+    client: reqwest::Client,
+    settings: DeliverySettings,
+    webhook: Webhook,
+    recorded: Arc<RecordedEvent>,
+) {
+    let body = match serde_json::to_vec(recorded.as_ref()) {
+        Ok(body) => body,
+        Err(err) => {
+            warn!(error = %err, "failed to serialize webhook payload");
+            return;
+        }
+    };
+    let signature = sign(&webhook.secret, &body);
+    let topic = recorded.event.topic();
+
+    let mut last_error = String::new();
+    for attempt in 1..=settings.max_attempts {
+        let result = client
+            .post(&webhook.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(SIGNATURE_HEADER, &signature)
+            .header(EVENT_HEADER, topic.as_str())
+            .header(DELIVERY_HEADER, recorded.seq.to_string())
+            .body(body.clone())
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+
+        match result {
+            Ok(_) => {
+                state.metrics.record_webhook_delivery("delivered");
+                return;
+            }
+            Err(err) => last_error = err.to_string(),
+        }
+
+        if attempt < settings.max_attempts {
+            state.metrics.record_webhook_delivery("retried");
+            tokio::time::sleep(settings.retry_base * 2u32.saturating_pow(attempt - 1)).await;
+        }
+    }
+
+    warn!(
+        webhook_id = %webhook.id,
+        seq = recorded.seq,
+        error = %last_error,
+        "webhook delivery failed, moved to dead letters"
+    );
+    state.metrics.record_webhook_delivery("dead_lettered");
+
+    let dead_letter = DeadLetter {
+        id: Uuid::new_v4(),
+        webhook_id: webhook.id,
+        event_seq: recorded.seq,
+        topic,
+        payload: serde_json::from_slice(&body).unwrap_or_default(),
+        attempts: settings.max_attempts,
+        last_error,
+        failed_at: Utc::now(),
+    };
+    store_dead_letter(&state, dead_letter, settings.dead_letter_limit);
+}
+
+/// Keeps `dead_letter`, evicting the oldest ones beyond `limit` so an
+/// endpoint that stays down cannot grow the list without bound.
+pub fn store_dead_letter(state: &AppState, dead_letter: DeadLetter, limit: usize) {
+    state
+        .webhook_dead_letters
+        .insert(dead_letter.id, dead_letter);
+    while state.webhook_dead_letters.len() > limit {
+        let oldest = state
+            .webhook_dead_letters
+            .iter()
+            .min_by_key(|entry| (entry.failed_at, entry.id))
+            .map(|entry| entry.id);
+        let Some(oldest) = oldest else {
+            break;
+        };
+        if state.webhook_dead_letters.remove(&oldest).is_some() {
+            state.metrics.webhook_dead_letters_evicted_total.inc();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::sign;
+
+    #[test]
+    fn signature_matches_reference_hmac() {
+        // RFC 4231 test case 2.
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+}
@@ -0,0 +1,521 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use dispatch_router::api::grpc::pb::dispatch_service_server::{
+    DispatchService, DispatchServiceServer,
+};
+use dispatch_router::api::grpc::pb::get_assignment_request::Lookup;
+use dispatch_router::api::grpc::pb::{
+    CourierStatus, CreateCourierRequest, CreateOrderRequest, GeoPoint, GetAssignmentRequest,
+    GetOrderRequest, OrderStatus, Priority, UpdateCourierLocationRequest,
+    UpdateCourierStatusRequest, WatchCourierLocationsRequest, WatchOrdersRequest,
+};
+use dispatch_router::api::grpc::GrpcDispatchService;
+use dispatch_router::config::Tunables;
+use dispatch_router::engine::assignment::run_assignment_engine;
+use dispatch_router::engine::queue::QueuedOrder;
+use dispatch_router::standby::{run_standby, StandbySettings};
+use dispatch_router::state::AppState;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::TcpListenerStream;
+use tokio_stream::StreamExt;
+use tonic::transport::Server;
+use tonic::{Code, Request};
+use uuid::Uuid;
+
+fn setup() -> (GrpcDispatchService, mpsc::Receiver<QueuedOrder>) {
+    let (state, rx) = AppState::new(1024, 1024);
+    (GrpcDispatchService::new(Arc::new(state)), rx)
+}
+
+async fn create_courier(service: &GrpcDispatchService, name: &str) -> String {
+    service
+        .create_courier(Request::new(CreateCourierRequest {
+            name: name.to_string(),
+            location: Some(GeoPoint {
+                lat: 52.52,
+                lng: 13.405,
+            }),
+            capacity_units: 3.0,
+            rating: 4.5,
+            ..Default::default()
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .id
+}
+
+#[tokio::test]
+async fn update_courier_location_moves_courier() {
+    let (service, _rx) = setup();
+    let id = create_courier(&service, "Greta").await;
+
+    let courier = service
+        .update_courier_location(Request::new(UpdateCourierLocationRequest {
+            courier_id: id,
+            location: Some(GeoPoint {
+                lat: 48.85,
+                lng: 2.35,
+            }),
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+
+    let location = courier.location.unwrap();
+    assert_eq!(location.lat, 48.85);
+    assert_eq!(location.lng, 2.35);
+}
+
+#[tokio::test]
+async fn rejects_nan_locations_and_same_place_orders() {
+    let (service, _rx) = setup();
+    let id = create_courier(&service, "Nina").await;
+
+    let err = service
+        .update_courier_location(Request::new(UpdateCourierLocationRequest {
+            courier_id: id,
+            location: Some(GeoPoint {
+                lat: f64::NAN,
+                lng: 13.405,
+            }),
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Code::InvalidArgument);
+
+    let spot = GeoPoint {
+        lat: 52.52,
+        lng: 13.405,
+    };
+    let err = service
+        .create_order(Request::new(CreateOrderRequest {
+            pickup: Some(spot.clone()),
+            dropoff: Some(spot),
+            priority: Priority::Normal as i32,
+            ..Default::default()
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Code::InvalidArgument);
+}
+
+#[tokio::test]
+async fn update_courier_status_validates_input() {
+    let (service, _rx) = setup();
+    let id = create_courier(&service, "Hans").await;
+
+    let courier = service
+        .update_courier_status(Request::new(UpdateCourierStatusRequest {
+            courier_id: id.clone(),
+            status: CourierStatus::Offline as i32,
+            ..Default::default()
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(courier.status(), CourierStatus::Offline);
+
+    let err = service
+        .update_courier_status(Request::new(UpdateCourierStatusRequest {
+            courier_id: id,
+            status: 42,
+            ..Default::default()
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Code::InvalidArgument);
+
+    let err = service
+        .update_courier_status(Request::new(UpdateCourierStatusRequest {
+            courier_id: "00000000-0000-0000-0000-000000000000".to_string(),
+            status: CourierStatus::Offline as i32,
+            ..Default::default()
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Code::NotFound);
+}
+
+#[tokio::test]
+async fn watch_orders_filters_by_zone() {
+    let (service, _rx) = setup();
+
+    let mut stream = service
+        .watch_orders(Request::new(WatchOrdersRequest {
+            statuses: vec![OrderStatus::Pending as i32],
+            zone: "u33d".to_string(),
+            ..Default::default()
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+
+    for (lat, lng) in [(48.85, 2.35), (52.51, 13.39)] {
+        service
+            .create_order(Request::new(CreateOrderRequest {
+                pickup: Some(GeoPoint { lat, lng }),
+                dropoff: Some(GeoPoint {
+                    lat: lat + 0.01,
+                    lng: lng + 0.01,
+                }),
+                priority: Priority::Normal as i32,
+                ..Default::default()
+            }))
+            .await
+            .unwrap();
+    }
+
+    let event = stream.next().await.unwrap().unwrap();
+    assert_eq!(event.status(), OrderStatus::Pending);
+    assert!(event.zone.starts_with("u33d"));
+    assert_eq!(event.pickup.unwrap().lat, 52.51);
+}
+
+#[tokio::test]
+async fn watch_orders_resumes_after_cursor() {
+    let (service, _rx) = setup();
+
+    let mut order_ids = Vec::new();
+    for _ in 0..3 {
+        let order = service
+            .create_order(Request::new(CreateOrderRequest {
+                pickup: Some(GeoPoint {
+                    lat: 52.51,
+                    lng: 13.39,
+                }),
+                dropoff: Some(GeoPoint {
+                    lat: 52.54,
+                    lng: 13.42,
+                }),
+                priority: Priority::Normal as i32,
+                ..Default::default()
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        order_ids.push(order.id);
+    }
+
+    let mut stream = service
+        .watch_orders(Request::new(WatchOrdersRequest {
+            resume_from: 1,
+            ..Default::default()
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+
+    let second = stream.next().await.unwrap().unwrap();
+    let third = stream.next().await.unwrap().unwrap();
+    assert_eq!((second.seq, second.order_id), (2, order_ids[1].clone()));
+    assert_eq!((third.seq, third.order_id), (3, order_ids[2].clone()));
+}
+
+#[tokio::test]
+async fn watch_courier_locations_filters_by_courier() {
+    let (service, _rx) = setup();
+    let tracked = create_courier(&service, "Tracked").await;
+    let other = create_courier(&service, "Other").await;
+
+    let mut stream = service
+        .watch_courier_locations(Request::new(WatchCourierLocationsRequest {
+            courier_ids: vec![tracked.clone()],
+            ..Default::default()
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+
+    for courier_id in [&other, &tracked] {
+        service
+            .update_courier_location(Request::new(UpdateCourierLocationRequest {
+                courier_id: courier_id.clone(),
+                location: Some(GeoPoint {
+                    lat: 52.53,
+                    lng: 13.41,
+                }),
+            }))
+            .await
+            .unwrap();
+    }
+
+    let event = stream.next().await.unwrap().unwrap();
+    assert_eq!(event.courier_id, tracked);
+    assert_eq!(event.status(), CourierStatus::Available);
+    assert_eq!(event.location.unwrap().lat, 52.53);
+}
+
+#[tokio::test]
+async fn watch_orders_ends_with_data_loss_when_lagging() {
+    let (state, _rx) = AppState::new(1024, 2);
+    let service = GrpcDispatchService::new(Arc::new(state));
+
+    let mut stream = service
+        .watch_orders(Request::new(WatchOrdersRequest::default()))
+        .await
+        .unwrap()
+        .into_inner();
+
+    for _ in 0..4 {
+        service
+            .create_order(Request::new(CreateOrderRequest {
+                pickup: Some(GeoPoint {
+                    lat: 52.51,
+                    lng: 13.39,
+                }),
+                dropoff: Some(GeoPoint {
+                    lat: 52.54,
+                    lng: 13.42,
+                }),
+                priority: Priority::Normal as i32,
+                ..Default::default()
+            }))
+            .await
+            .unwrap();
+    }
+
+    let err = stream.next().await.unwrap().unwrap_err();
+    assert_eq!(err.code(), Code::DataLoss);
+    assert!(err.message().starts_with("events_dropped: 2"));
+}
+
+#[tokio::test]
+async fn watch_orders_rejects_unknown_status_filter() {
+    let (service, _rx) = setup();
+
+    let err = service
+        .watch_orders(Request::new(WatchOrdersRequest {
+            statuses: vec![OrderStatus::Pending as i32, 99],
+            ..Default::default()
+        }))
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(err.code(), Code::InvalidArgument);
+}
+
+#[tokio::test]
+async fn get_order_and_assignment_after_dispatch() {
+    let (state, rx) = AppState::new(1024, 1024);
+    let shared = Arc::new(state);
+    tokio::spawn(run_assignment_engine(shared.clone(), rx));
+    let service = GrpcDispatchService::new(shared);
+
+    let courier_id = create_courier(&service, "Ines").await;
+    let order = service
+        .create_order(Request::new(CreateOrderRequest {
+            pickup: Some(GeoPoint {
+                lat: 52.51,
+                lng: 13.39,
+            }),
+            dropoff: Some(GeoPoint {
+                lat: 52.54,
+                lng: 13.42,
+            }),
+            priority: Priority::Urgent as i32,
+            ..Default::default()
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(order.assigned_courier.is_empty());
+
+    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+
+    let fetched = service
+        .get_order(Request::new(GetOrderRequest {
+            order_id: order.id.clone(),
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(fetched.status(), OrderStatus::Assigned);
+    assert_eq!(fetched.assigned_courier, courier_id);
+
+    let by_order = service
+        .get_assignment(Request::new(GetAssignmentRequest {
+            lookup: Some(Lookup::OrderId(order.id.clone())),
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(by_order.courier_id, courier_id);
+
+    let by_id = service
+        .get_assignment(Request::new(GetAssignmentRequest {
+            lookup: Some(Lookup::AssignmentId(by_order.id.clone())),
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(by_id.order_id, order.id);
+
+    let err = service
+        .get_assignment(Request::new(GetAssignmentRequest { lookup: None }))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Code::InvalidArgument);
+}
+
+#[tokio::test]
+#[allow(deprecated)]
+async fn legacy_string_fields_are_still_accepted() {
+    let (service, _rx) = setup();
+
+    let order = service
+        .create_order(Request::new(CreateOrderRequest {
+            pickup: Some(GeoPoint {
+                lat: 52.51,
+                lng: 13.39,
+            }),
+            dropoff: Some(GeoPoint {
+                lat: 52.54,
+                lng: 13.42,
+            }),
+            priority_name: "High".to_string(),
+            ..Default::default()
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(order.priority(), Priority::High);
+    assert_eq!(order.priority_name, "High");
+    assert_eq!(order.status_name, "Pending");
+
+    let err = service
+        .create_order(Request::new(CreateOrderRequest {
+            pickup: Some(GeoPoint {
+                lat: 52.51,
+                lng: 13.39,
+            }),
+            dropoff: Some(GeoPoint {
+                lat: 52.54,
+                lng: 13.42,
+            }),
+            ..Default::default()
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Code::InvalidArgument);
+}
+
+async fn create_order(service: &GrpcDispatchService) -> Uuid {
+    let order = service
+        .create_order(Request::new(CreateOrderRequest {
+            pickup: Some(GeoPoint {
+                lat: 52.51,
+                lng: 13.39,
+            }),
+            dropoff: Some(GeoPoint {
+                lat: 52.54,
+                lng: 13.42,
+            }),
+            priority: Priority::Normal as i32,
+            ..Default::default()
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    order.id.parse().unwrap()
+}
+
+/// Polls until `mirrored` holds, failing after a few seconds.
+async fn eventually(mirrored: impl Fn() -> bool) {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while !mirrored() {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("the standby did not catch up");
+}
+
+#[tokio::test]
+async fn standby_mirrors_couriers_and_pending_orders() {
+    let (mut primary, _primary_rx) = AppState::new(1024, 1024);
+    primary.publish_changes = true;
+    let primary = Arc::new(primary);
+    let service = GrpcDispatchService::new(primary.clone());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        Server::builder()
+            .add_service(DispatchServiceServer::new(GrpcDispatchService::new(
+                primary.clone(),
+            )))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+
+    // Created before the standby connects, so it gets them in its copy.
+    let first_courier: Uuid = create_courier(&service, "Ada").await.parse().unwrap();
+    let first_order = create_order(&service).await;
+
+    let (standby, mut standby_rx) = AppState::new(1024, 1024);
+    let standby = Arc::new(standby);
+    standby.leadership.set(false);
+    standby.set_tunables(Tunables {
+        standby_primary_url: format!("http://{addr}"),
+        ..Tunables::default()
+    });
+    tokio::spawn(run_standby(
+        standby.clone(),
+        StandbySettings {
+            token: None,
+            elected: false,
+        },
+    ));
+    eventually(|| {
+        standby.couriers.contains_key(&first_courier) && standby.orders.contains_key(&first_order)
+    })
+    .await;
+
+    let second_courier: Uuid = create_courier(&service, "Bo").await.parse().unwrap();
+    let second_order = create_order(&service).await;
+    service
+        .update_courier_status(Request::new(UpdateCourierStatusRequest {
+            courier_id: first_courier.to_string(),
+            status: CourierStatus::Offline as i32,
+            ..Default::default()
+        }))
+        .await
+        .unwrap();
+    service
+        .update_courier_location(Request::new(UpdateCourierLocationRequest {
+            courier_id: second_courier.to_string(),
+            location: Some(GeoPoint {
+                lat: 48.85,
+                lng: 2.35,
+            }),
+        }))
+        .await
+        .unwrap();
+    eventually(|| {
+        let offline = standby.couriers.get(&first_courier).is_some_and(|courier| {
+            courier.status == dispatch_router::models::courier::CourierStatus::Offline
+        });
+        let moved = standby
+            .couriers
+            .get(&second_courier)
+            .is_some_and(|courier| courier.location.lat == 48.85);
+        offline && moved && standby.orders.contains_key(&second_order)
+    })
+    .await;
+    assert!(!standby.leadership.is_leader());
+    assert!(standby.metrics.standby_changes_applied_total.get() >= 4);
+
+    // Clearing the primary promotes the standby, which queues what is
+    // still pending, oldest first.
+    standby.set_tunables(Tunables::default());
+    let queued = tokio::time::timeout(Duration::from_secs(5), async {
+        let first = standby_rx.recv().await.unwrap().order.id;
+        let second = standby_rx.recv().await.unwrap().order.id;
+        (first, second)
+    })
+    .await
+    .expect("the standby did not take over");
+    assert_eq!(queued, (first_order, second_order));
+    assert!(standby.leadership.is_leader());
+}
@@ -1,17 +1,29 @@
+use std::collections::HashSet;
 use std::sync::Arc;
 
 use axum::body::Body;
 use axum::http::{Request, StatusCode};
 use dispatch_router::api::rest::router;
+use dispatch_router::api::rest::ws::Subscription;
+use dispatch_router::chaos::{Chaos, ChaosSettings};
+use dispatch_router::clock::ManualClock;
+use dispatch_router::config::Tunables;
 use dispatch_router::engine::assignment::run_assignment_engine;
+use dispatch_router::engine::constraints::{Constraint, ConstraintContext, ConstraintKind};
+use dispatch_router::engine::lifecycle::PayoutRates;
+use dispatch_router::events::EventBus;
+use dispatch_router::geo::{geohash, zone_of};
+use dispatch_router::models::courier::{Courier, GeoPoint, ZoneMode};
+use dispatch_router::models::event::{DispatchEvent, Topic};
+use dispatch_router::models::order::{DeliveryOrder, Priority};
 use dispatch_router::state::AppState;
 use serde_json::{json, Value};
 use tokio::sync::mpsc;
 use tower::ServiceExt;
 
-use dispatch_router::models::order::DeliveryOrder;
+use dispatch_router::engine::queue::QueuedOrder;
 
-fn setup() -> (axum::Router, mpsc::Receiver<DeliveryOrder>) {
+fn setup() -> (axum::Router, mpsc::Receiver<QueuedOrder>) {
     let (state, rx) = AppState::new(1024, 1024);
     (router(Arc::new(state)), rx)
 }
@@ -68,6 +80,7 @@ async fn health_returns_ok() {
     assert_eq!(body["couriers"], 0);
     assert_eq!(body["orders"], 0);
     assert_eq!(body["assignments"], 0);
+    assert_eq!(body["ws_connections"], 0);
 }
 
 #[tokio::test]
@@ -111,11 +124,11 @@ async fn create_courier_returns_courier() {
 
     let body = body_json(response).await;
     assert_eq!(body["name"], "Alice");
-    assert_eq!(body["capacity"], 5);
-    assert_eq!(body["current_load"], 0);
+    assert_eq!(body["capacity"], 5.0);
+    assert_eq!(body["current_load"], 0.0);
     assert_eq!(body["status"], "Available");
     assert_eq!(body["rating"], 4.5);
-    assert!(body["id"].as_str().unwrap().len() > 0);
+    assert!(!body["id"].as_str().unwrap().is_empty());
 }
 
 #[tokio::test]
@@ -181,6 +194,32 @@ async fn create_courier_rating_clamped_to_5() {
     assert_eq!(body["rating"], 5.0);
 }
 
+#[tokio::test]
+async fn create_courier_rejects_negative_rating_and_bad_coordinates() {
+    let (app, _rx) = setup();
+    for (location, rating) in [
+        (json!({ "lat": 52.52, "lng": 13.405 }), -1.0),
+        (json!({ "lat": 91.0, "lng": 13.405 }), 4.0),
+        (json!({ "lat": 52.52, "lng": 181.0 }), 4.0),
+    ] {
+        let response = app
+            .clone()
+            .oneshot(json_request(
+                "POST",
+                "/couriers",
+                json!({
+                    "name": "Val",
+                    "location": location,
+                    "capacity": 3,
+                    "rating": rating
+                }),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
+
 #[tokio::test]
 async fn list_couriers_initially_empty() {
     let (app, _rx) = setup();
@@ -228,6 +267,86 @@ async fn update_courier_status() {
     assert_eq!(body["status"], "Offline");
 }
 
+#[tokio::test]
+async fn couriers_on_break_get_no_new_orders() {
+    let (state, rx) = AppState::new(1024, 1024);
+    let shared = Arc::new(state);
+    tokio::spawn(run_assignment_engine(shared.clone(), rx));
+    let app = router(shared.clone());
+
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/couriers",
+            json!({
+                "name": "Bo",
+                "location": { "lat": 52.52, "lng": 13.405 },
+                "capacity": 3,
+                "rating": 4.5
+            }),
+        ))
+        .await
+        .unwrap();
+    let id = body_json(res).await["id"].as_str().unwrap().to_string();
+    let status_uri = format!("/couriers/{id}/status");
+
+    let res = app
+        .clone()
+        .oneshot(patch_request(
+            &status_uri,
+            json!({ "status": "Available", "break_until": "2099-01-01T00:00:00Z" }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    let res = app
+        .clone()
+        .oneshot(patch_request(
+            &status_uri,
+            json!({ "status": "OnBreak", "break_until": "2000-01-01T00:00:00Z" }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let res = app
+        .clone()
+        .oneshot(patch_request(
+            &status_uri,
+            json!({ "status": "OnBreak", "break_until": "2099-01-01T00:00:00Z" }),
+        ))
+        .await
+        .unwrap();
+    let courier = body_json(res).await;
+    assert_eq!(courier["status"], "OnBreak");
+    assert_eq!(courier["break_until"], "2099-01-01T00:00:00Z");
+
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/orders",
+            json!({
+                "pickup": { "lat": 52.52, "lng": 13.405 },
+                "dropoff": { "lat": 52.50, "lng": 13.42 },
+                "priority": "Urgent"
+            }),
+        ))
+        .await
+        .unwrap();
+    let order_id = body_json(res).await["id"].as_str().unwrap().to_string();
+    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+
+    let res = app
+        .oneshot(get_request(&format!("/orders/{order_id}")))
+        .await
+        .unwrap();
+    let order = body_json(res).await;
+    assert_eq!(order["status"], "Pending");
+    assert_eq!(order["waiting_reason"], "NoCourierAvailable");
+}
+
 #[tokio::test]
 async fn update_courier_location() {
     let (state, _rx) = AppState::new(1024, 1024);
@@ -266,82 +385,170 @@ async fn update_courier_location() {
 }
 
 #[tokio::test]
-async fn get_nonexistent_order_returns_404() {
+async fn couriers_are_listed_by_region() {
     let (app, _rx) = setup();
-    let fake_id = "00000000-0000-0000-0000-000000000000";
-    let response = app
-        .oneshot(get_request(&format!("/orders/{fake_id}")))
+    let berlin = GeoPoint {
+        lat: 52.52,
+        lng: 13.405,
+    };
+    let paris = GeoPoint {
+        lat: 48.85,
+        lng: 2.35,
+    };
+    let mut ids = Vec::new();
+    for (name, location) in [("Berta", &berlin), ("Pierre", &paris)] {
+        let res = app
+            .clone()
+            .oneshot(json_request(
+                "POST",
+                "/couriers",
+                json!({
+                    "name": name,
+                    "location": location,
+                    "capacity": 2,
+                    "rating": 4.0
+                }),
+            ))
+            .await
+            .unwrap();
+        ids.push(body_json(res).await["id"].as_str().unwrap().to_string());
+    }
+
+    let list = |region: String| {
+        let app = app.clone();
+        async move {
+            let res = app
+                .oneshot(get_request(&format!("/couriers?region={region}")))
+                .await
+                .unwrap();
+            let couriers = body_json(res).await;
+            couriers
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|courier| courier["id"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        }
+    };
+    assert_eq!(list(geohash(&berlin, 3)).await, vec![ids[0].clone()]);
+    assert_eq!(list(geohash(&paris, 5)).await, vec![ids[1].clone()]);
+
+    // Berta moves to Paris and is filed there.
+    app.clone()
+        .oneshot(patch_request(
+            &format!("/couriers/{}/location", ids[0]),
+            json!({ "location": paris }),
+        ))
+        .await
+        .unwrap();
+    assert!(list(geohash(&berlin, 3)).await.is_empty());
+    assert_eq!(list(geohash(&paris, 3)).await.len(), 2);
+    let res = app
+        .clone()
+        .oneshot(get_request(&format!("/couriers/{}/shifts", ids[0])))
         .await
         .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
 
-    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let res = app.oneshot(get_request("/couriers?region=u3")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
 }
 
 #[tokio::test]
-async fn create_order_returns_pending() {
+async fn courier_heartbeat_updates_last_seen_at() {
     let (app, _rx) = setup();
-    let response = app
+
+    let res = app
+        .clone()
         .oneshot(json_request(
             "POST",
-            "/orders",
+            "/couriers",
             json!({
-                "pickup": { "lat": 52.51, "lng": 13.39 },
-                "dropoff": { "lat": 52.54, "lng": 13.42 },
-                "priority": "Normal"
+                "name": "Hedy",
+                "location": { "lat": 52.0, "lng": 13.0 },
+                "capacity": 2,
+                "rating": 4.0
             }),
         ))
         .await
         .unwrap();
+    let courier = body_json(res).await;
+    let id = courier["id"].as_str().unwrap();
+    let registered_at = courier["last_seen_at"].as_str().unwrap().to_string();
 
-    assert_eq!(response.status(), StatusCode::OK);
+    let res = app
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri(format!("/couriers/{id}/heartbeat"))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
 
-    let body = body_json(response).await;
-    assert_eq!(body["status"], "Pending");
-    assert!(body["assigned_courier"].is_null());
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = body_json(res).await;
+    assert!(body["last_seen_at"].as_str().unwrap() >= registered_at.as_str());
 }
 
 #[tokio::test]
-async fn full_assignment_flow() {
+async fn engine_skips_couriers_outside_their_shifts() {
     let (state, rx) = AppState::new(1024, 1024);
     let shared = Arc::new(state);
     tokio::spawn(run_assignment_engine(shared.clone(), rx));
     let app = router(shared.clone());
 
+    // Two days out, so the shift cannot be running now.
+    let weekday = (chrono::Utc::now() + chrono::Duration::days(2))
+        .format("%a")
+        .to_string();
     let res = app
         .clone()
         .oneshot(json_request(
             "POST",
             "/couriers",
             json!({
-                "name": "Dispatch Dan",
-                "location": { "lat": 52.52, "lng": 13.405 },
+                "name": "Off Duty Olga",
+                "location": { "lat": 52.51, "lng": 13.39 },
                 "capacity": 5,
-                "rating": 4.8
+                "rating": 5.0,
+                "shifts": [{ "weekdays": [weekday], "start": "09:00:00", "end": "17:00:00" }]
             }),
         ))
         .await
         .unwrap();
     assert_eq!(res.status(), StatusCode::OK);
-    let courier = body_json(res).await;
-    let courier_id = courier["id"].as_str().unwrap().to_string();
+    let off_shift = body_json(res).await["id"].as_str().unwrap().to_string();
 
     let res = app
         .clone()
+        .oneshot(json_request(
+            "POST",
+            "/couriers",
+            json!({
+                "name": "Far Away Finn",
+                "location": { "lat": 52.60, "lng": 13.50 },
+                "capacity": 5,
+                "rating": 3.0
+            }),
+        ))
+        .await
+        .unwrap();
+    let on_duty = body_json(res).await["id"].as_str().unwrap().to_string();
+
+    app.clone()
         .oneshot(json_request(
             "POST",
             "/orders",
             json!({
                 "pickup": { "lat": 52.51, "lng": 13.39 },
                 "dropoff": { "lat": 52.54, "lng": 13.42 },
-                "priority": "Urgent"
+                "priority": "Normal"
             }),
         ))
         .await
         .unwrap();
-    assert_eq!(res.status(), StatusCode::OK);
-    let order = body_json(res).await;
-    let order_id = order["id"].as_str().unwrap().to_string();
-
     tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
 
     let res = app
@@ -349,31 +556,3672 @@ async fn full_assignment_flow() {
         .oneshot(get_request("/assignments"))
         .await
         .unwrap();
+    let assignments = body_json(res).await;
+    assert_eq!(assignments[0]["courier_id"], on_duty);
+
+    let res = app
+        .oneshot(get_request(&format!("/couriers/{off_shift}/shifts")))
+        .await
+        .unwrap();
     assert_eq!(res.status(), StatusCode::OK);
+    let body = body_json(res).await;
+    assert_eq!(body["on_shift"], false);
+    assert!(body["current"].is_null());
+    assert!(body["next"]["starts_at"].is_string());
+}
+
+#[tokio::test]
+async fn orders_needing_a_van_skip_smaller_vehicles() {
+    let (state, rx) = AppState::new(1024, 1024);
+    let shared = Arc::new(state);
+    tokio::spawn(run_assignment_engine(shared.clone(), rx));
+    let app = router(shared.clone());
+
+    let mut ids = Vec::new();
+    for (name, lat, vehicle_type) in [
+        ("Bike Bea", 52.51, "Bike"),
+        ("Car Carl", 52.52, "Car"),
+        ("Van Vera", 52.60, "Van"),
+    ] {
+        let res = app
+            .clone()
+            .oneshot(json_request(
+                "POST",
+                "/couriers",
+                json!({
+                    "name": name,
+                    "location": { "lat": lat, "lng": 13.39 },
+                    "capacity": 5,
+                    "rating": 4.5,
+                    "vehicle_type": vehicle_type
+                }),
+            ))
+            .await
+            .unwrap();
+        let courier = body_json(res).await;
+        assert_eq!(courier["vehicle_type"], vehicle_type);
+        ids.push(courier["id"].as_str().unwrap().to_string());
+    }
+
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/orders",
+            json!({
+                "pickup": { "lat": 52.51, "lng": 13.39 },
+                "dropoff": { "lat": 52.54, "lng": 13.42 },
+                "priority": "Normal",
+                "required_vehicle": "Van"
+            }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(body_json(res).await["required_vehicle"], "Van");
+    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+
+    let res = app.oneshot(get_request("/assignments")).await.unwrap();
     let assignments = body_json(res).await;
-    let list = assignments.as_array().unwrap();
-    assert_eq!(list.len(), 1);
+    assert_eq!(assignments[0]["courier_id"], ids[2]);
+}
 
-    let assignment = &list[0];
-    assert_eq!(assignment["courier_id"], courier_id);
-    assert_eq!(assignment["order_id"], order_id);
-    assert!(assignment["score"].as_f64().unwrap() > 0.0);
-    assert!(assignment["score_breakdown"]["distance_score"].as_f64().unwrap() > 0.0);
-    assert!(assignment["score_breakdown"]["load_score"].as_f64().unwrap() > 0.0);
-    assert!(assignment["score_breakdown"]["rating_score"].as_f64().unwrap() > 0.0);
-    assert!(assignment["score_breakdown"]["priority_score"].as_f64().unwrap() > 0.0);
+#[tokio::test]
+async fn one_big_order_fills_a_courier() {
+    let (state, rx) = AppState::new(1024, 1024);
+    let shared = Arc::new(state);
+    tokio::spawn(run_assignment_engine(shared.clone(), rx));
+    let app = router(shared.clone());
 
     let res = app
         .clone()
-        .oneshot(get_request(&format!("/orders/{order_id}")))
+        .oneshot(json_request(
+            "POST",
+            "/couriers",
+            json!({
+                "name": "Greta",
+                "location": { "lat": 52.52, "lng": 13.405 },
+                "capacity": 4,
+                "rating": 4.5
+            }),
+        ))
         .await
         .unwrap();
-    let updated_order = body_json(res).await;
-    assert_eq!(updated_order["status"], "Assigned");
-    assert_eq!(updated_order["assigned_courier"], courier_id);
+    let id = body_json(res).await["id"].as_str().unwrap().to_string();
 
-    let res = app.oneshot(get_request("/couriers")).await.unwrap();
+    let order = |size: f64| {
+        json_request(
+            "POST",
+            "/orders",
+            json!({
+                "pickup": { "lat": 52.51, "lng": 13.39 },
+                "dropoff": { "lat": 52.54, "lng": 13.42 },
+                "priority": "Normal",
+                "size": size
+            }),
+        )
+    };
+    let res = app.clone().oneshot(order(1.5)).await.unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let res = app.clone().oneshot(order(4.0)).await.unwrap();
+    assert_eq!(body_json(res).await["size"], 4.0);
+    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+
+    let res = app.clone().oneshot(order(1.0)).await.unwrap();
+    let small = body_json(res).await;
+    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+
+    let res = app.clone().oneshot(get_request("/couriers")).await.unwrap();
     let couriers = body_json(res).await;
-    let updated_courier = &couriers.as_array().unwrap()[0];
-    assert_eq!(updated_courier["current_load"], 1);
+    assert_eq!(couriers[0]["id"], id.as_str());
+    assert_eq!(couriers[0]["current_load"], 4.0);
+    assert_eq!(couriers[0]["status"], "Busy");
+
+    let res = app
+        .oneshot(get_request(&format!(
+            "/orders/{}",
+            small["id"].as_str().unwrap()
+        )))
+        .await
+        .unwrap();
+    let small = body_json(res).await;
+    assert_eq!(small["status"], "Pending");
+    assert_eq!(small["waiting_reason"], "NoCourierAvailable");
+}
+
+#[tokio::test]
+async fn order_items_add_up_to_the_order_size() {
+    let (state, _rx) = AppState::new(1024, 1024);
+    let app = router(Arc::new(state));
+
+    let order = |extra: Value| {
+        let mut body = json!({
+            "pickup": { "lat": 52.51, "lng": 13.39 },
+            "dropoff": { "lat": 52.54, "lng": 13.42 },
+            "priority": "Normal",
+            "items": [
+                { "description": "Water 6-pack", "weight": 1, "quantity": 2 },
+                { "description": " Flowers ", "weight": 1 }
+            ]
+        });
+        body.as_object_mut()
+            .unwrap()
+            .extend(extra.as_object().unwrap().clone());
+        json_request("POST", "/orders", body)
+    };
+
+    let res = app.clone().oneshot(order(json!({}))).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let created = body_json(res).await;
+    assert_eq!(created["size"], 3.0);
+    assert_eq!(created["items"][0]["quantity"], 2);
+    assert_eq!(created["items"][1]["description"], "Flowers");
+    assert_eq!(created["items"][1]["quantity"], 1);
+
+    let res = app
+        .clone()
+        .oneshot(order(json!({ "size": 3 })))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let res = app
+        .oneshot(order(json!({
+            "items": [{ "description": "Sofa", "weight": 1, "quantity": 0 }]
+        })))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn orders_can_pick_up_from_several_stops() {
+    let (state, rx) = AppState::new(1024, 1024);
+    let shared = Arc::new(state);
+    tokio::spawn(run_assignment_engine(shared.clone(), rx));
+    let app = router(shared.clone());
+
+    // Close to the second pickup, but the first one decides.
+    let mut courier_ids = Vec::new();
+    for (name, lat) in [("Near first", 52.511), ("Near second", 52.531)] {
+        let res = app
+            .clone()
+            .oneshot(json_request(
+                "POST",
+                "/couriers",
+                json!({
+                    "name": name,
+                    "location": { "lat": lat, "lng": 13.39 },
+                    "capacity": 2,
+                    "rating": 4.5
+                }),
+            ))
+            .await
+            .unwrap();
+        courier_ids.push(body_json(res).await["id"].as_str().unwrap().to_string());
+    }
+
+    let order = |extra_pickups: Value| {
+        json_request(
+            "POST",
+            "/orders",
+            json!({
+                "pickup": { "lat": 52.51, "lng": 13.39 },
+                "extra_pickups": extra_pickups,
+                "dropoff": { "lat": 52.54, "lng": 13.42 },
+                "priority": "Normal"
+            }),
+        )
+    };
+    let res = app
+        .clone()
+        .oneshot(order(json!([{ "lat": 52.53, "lng": 13.39 }])))
+        .await
+        .unwrap();
+    let created = body_json(res).await;
+    assert_eq!(created["extra_pickups"][0]["lat"], 52.53);
+    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+
+    let res = app
+        .clone()
+        .oneshot(get_request(&format!(
+            "/orders/{}",
+            created["id"].as_str().unwrap()
+        )))
+        .await
+        .unwrap();
+    assert_eq!(
+        body_json(res).await["assigned_courier"],
+        courier_ids[0].as_str()
+    );
+
+    let too_many = vec![json!({ "lat": 52.53, "lng": 13.39 }); 10];
+    let res = app.oneshot(order(json!(too_many))).await.unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn orders_on_the_way_are_stacked_onto_an_en_route_courier() {
+    let (state, rx) = AppState::new(1024, 1024);
+    state.set_tunables(Tunables {
+        stacking_max_detour_km: 2.0,
+        ..Tunables::default()
+    });
+    let shared = Arc::new(state);
+    tokio::spawn(run_assignment_engine(shared.clone(), rx));
+    let app = router(shared.clone());
+
+    let courier = |name: &str, lng: f64| {
+        json_request(
+            "POST",
+            "/couriers",
+            json!({
+                "name": name,
+                "location": { "lat": 52.50, "lng": lng },
+                "capacity": 2,
+                "rating": 4.5
+            }),
+        )
+    };
+    let order = |pickup: f64, dropoff: f64| {
+        json_request(
+            "POST",
+            "/orders",
+            json!({
+                "pickup": { "lat": 52.50, "lng": pickup },
+                "dropoff": { "lat": 52.50, "lng": dropoff },
+                "priority": "Normal"
+            }),
+        )
+    };
+
+    let res = app.clone().oneshot(courier("Ron", 13.40)).await.unwrap();
+    let en_route = body_json(res).await["id"].as_str().unwrap().to_string();
+    let res = app.clone().oneshot(order(13.40, 13.50)).await.unwrap();
+    let first = body_json(res).await["id"].as_str().unwrap().to_string();
+    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+    app.clone()
+        .oneshot(patch_request(
+            &format!("/orders/{first}/status"),
+            json!({ "status": "InTransit" }),
+        ))
+        .await
+        .unwrap();
+
+    // Standing right at the next pickup, but Ron passes it anyway.
+    app.clone().oneshot(courier("Ida", 13.45)).await.unwrap();
+    let res = app.clone().oneshot(order(13.45, 13.51)).await.unwrap();
+    let second = body_json(res).await["id"].as_str().unwrap().to_string();
+    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+
+    let res = app
+        .clone()
+        .oneshot(get_request(&format!("/orders/{second}/assignment")))
+        .await
+        .unwrap();
+    let assignment = body_json(res).await;
+    assert_eq!(assignment["courier_id"], en_route.as_str());
+    assert_eq!(assignment["stacked_with"], json!([first]));
+    assert_eq!(shared.metrics.orders_stacked_total.get(), 1);
+
+    let res = app
+        .oneshot(get_request(&format!("/couriers/{en_route}/route")))
+        .await
+        .unwrap();
+    let route = body_json(res).await;
+    let stops: Vec<(&str, &str)> = route["stops"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|stop| {
+            (
+                stop["kind"].as_str().unwrap(),
+                stop["order_id"].as_str().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        stops,
+        [
+            ("Pickup", second.as_str()),
+            ("Dropoff", first.as_str()),
+            ("Dropoff", second.as_str())
+        ]
+    );
+    assert!(route["distance_km"].as_f64().unwrap() < 8.0);
+}
+
+#[tokio::test]
+async fn cash_on_delivery_goes_to_couriers_with_room_in_their_cash_float() {
+    let (state, rx) = AppState::new(1024, 1024);
+    let shared = Arc::new(state);
+    tokio::spawn(run_assignment_engine(shared.clone(), rx));
+    let app = router(shared.clone());
+
+    let courier = |name: &str, lng: f64, cod: Value| {
+        let mut body = json!({
+            "name": name,
+            "location": { "lat": 52.51, "lng": lng },
+            "capacity": 3,
+            "rating": 4.5
+        });
+        body.as_object_mut()
+            .unwrap()
+            .extend(cod.as_object().unwrap().clone());
+        json_request("POST", "/couriers", body)
+    };
+    // The card-only courier is closest, so only COD keeps it from winning.
+    app.clone()
+        .oneshot(courier("Card only", 13.39, json!({})))
+        .await
+        .unwrap();
+    let res = app
+        .clone()
+        .oneshot(courier(
+            "Cash",
+            13.40,
+            json!({ "accepts_cod": true, "cod_limit": 50.0 }),
+        ))
+        .await
+        .unwrap();
+    let cash_courier = body_json(res).await["id"].as_str().unwrap().to_string();
+
+    let order = |amount: f64| {
+        json_request(
+            "POST",
+            "/orders",
+            json!({
+                "pickup": { "lat": 52.51, "lng": 13.39 },
+                "dropoff": { "lat": 52.54, "lng": 13.42 },
+                "priority": "Normal",
+                "cash_on_delivery": amount
+            }),
+        )
+    };
+    let order_id = |res| async { body_json(res).await["id"].as_str().unwrap().to_string() };
+
+    let first = order_id(app.clone().oneshot(order(30.0)).await.unwrap()).await;
+    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+    let res = app
+        .clone()
+        .oneshot(get_request(&format!("/orders/{first}/assignment")))
+        .await
+        .unwrap();
+    let assignment = body_json(res).await;
+    assert_eq!(assignment["courier_id"], cash_courier.as_str());
+    assert_eq!(assignment["cash_on_delivery"], 30.0);
+
+    // 30 + 25 is over the float until the first order is delivered.
+    let second = order_id(app.clone().oneshot(order(25.0)).await.unwrap()).await;
+    let too_much = order_id(app.clone().oneshot(order(80.0)).await.unwrap()).await;
+    // The engine sits out a requeue delay after each order it cannot place.
+    tokio::time::sleep(tokio::time::Duration::from_millis(600)).await;
+    let order_state = |id: String| {
+        let app = app.clone();
+        async move {
+            let res = app
+                .oneshot(get_request(&format!("/orders/{id}")))
+                .await
+                .unwrap();
+            body_json(res).await
+        }
+    };
+    let waiting = order_state(second.clone()).await;
+    assert_eq!(waiting["status"], "Pending");
+    assert_eq!(waiting["waiting_reason"], "NoCourierAvailable");
+    assert_eq!(
+        order_state(too_much).await["waiting_reason"],
+        "NoQualifiedCourier"
+    );
+
+    for status in ["InTransit", "Delivered"] {
+        app.clone()
+            .oneshot(patch_request(
+                &format!("/orders/{first}/status"),
+                json!({ "status": status }),
+            ))
+            .await
+            .unwrap();
+    }
+    tokio::time::sleep(tokio::time::Duration::from_millis(800)).await;
+    let assigned = order_state(second).await;
+    assert_eq!(assigned["assigned_courier"], cash_courier.as_str());
+}
+
+#[tokio::test]
+async fn orders_needing_equipment_no_courier_has_are_dead_lettered() {
+    let (state, rx) = AppState::new(1024, 1024);
+    let shared = Arc::new(state);
+    tokio::spawn(run_assignment_engine(shared.clone(), rx));
+    let app = router(shared.clone());
+
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/couriers",
+            json!({
+                "name": "Freezer van",
+                "location": { "lat": 52.51, "lng": 13.40 },
+                "capacity": 3,
+                "rating": 4.5,
+                "equipment": ["Frozen"]
+            }),
+        ))
+        .await
+        .unwrap();
+    let courier_id = body_json(res).await["id"].as_str().unwrap().to_string();
+
+    let order = |handling: Value| {
+        json_request(
+            "POST",
+            "/orders",
+            json!({
+                "pickup": { "lat": 52.51, "lng": 13.39 },
+                "dropoff": { "lat": 52.54, "lng": 13.42 },
+                "priority": "Normal",
+                "handling": handling
+            }),
+        )
+    };
+    let order_id = |res| async { body_json(res).await["id"].as_str().unwrap().to_string() };
+
+    // A freezer box keeps chilled parcels cold too.
+    let chilled = order_id(
+        app.clone()
+            .oneshot(order(json!(["Chilled"])))
+            .await
+            .unwrap(),
+    )
+    .await;
+    let fragile = order_id(
+        app.clone()
+            .oneshot(order(json!(["Fragile", "Frozen"])))
+            .await
+            .unwrap(),
+    )
+    .await;
+    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+
+    let res = app
+        .clone()
+        .oneshot(get_request(&format!("/orders/{chilled}")))
+        .await
+        .unwrap();
+    assert_eq!(
+        body_json(res).await["assigned_courier"],
+        courier_id.as_str()
+    );
+
+    let res = app
+        .clone()
+        .oneshot(get_request(&format!("/orders/{fragile}")))
+        .await
+        .unwrap();
+    let rejected = body_json(res).await;
+    assert_eq!(rejected["status"], "Rejected");
+    assert_eq!(rejected["assigned_courier"], Value::Null);
+
+    let res = app
+        .clone()
+        .oneshot(get_request("/orders/dead-letters"))
+        .await
+        .unwrap();
+    let dead_letters = body_json(res).await;
+    assert_eq!(dead_letters.as_array().unwrap().len(), 1);
+    assert_eq!(dead_letters[0]["order_id"], fragile.as_str());
+    assert_eq!(
+        dead_letters[0]["reason"],
+        "no courier is equipped for Fragile handling"
+    );
+    assert_eq!(shared.metrics.orders_rejected_total.get(), 1);
+}
+
+/// Keeps urgent orders for couriers rated 4.5 and up.
+struct UrgentNeedsTopRating;
+
+impl Constraint for UrgentNeedsTopRating {
+    fn name(&self) -> &'static str {
+        "urgent_rating"
+    }
+
+    fn kind(&self) -> ConstraintKind {
+        ConstraintKind::Qualification
+    }
+
+    fn allows(&self, _ctx: &ConstraintContext, courier: &Courier, order: &DeliveryOrder) -> bool {
+        !matches!(order.priority, Priority::Urgent) || courier.rating >= 4.5
+    }
+}
+
+#[tokio::test]
+async fn custom_constraints_filter_candidates() {
+    let (mut state, rx) = AppState::new(1024, 1024);
+    state.constraints.push(Arc::new(UrgentNeedsTopRating));
+    let shared = Arc::new(state);
+    tokio::spawn(run_assignment_engine(shared.clone(), rx));
+    let app = router(shared.clone());
+
+    app.clone()
+        .oneshot(json_request(
+            "POST",
+            "/couriers",
+            json!({
+                "name": "New",
+                "location": { "lat": 52.51, "lng": 13.40 },
+                "capacity": 3,
+                "rating": 4.0
+            }),
+        ))
+        .await
+        .unwrap();
+
+    let order = |priority: &str| {
+        json_request(
+            "POST",
+            "/orders",
+            json!({
+                "pickup": { "lat": 52.51, "lng": 13.39 },
+                "dropoff": { "lat": 52.54, "lng": 13.42 },
+                "priority": priority
+            }),
+        )
+    };
+    let order_id = |res| async { body_json(res).await["id"].as_str().unwrap().to_string() };
+    let urgent = order_id(app.clone().oneshot(order("Urgent")).await.unwrap()).await;
+    let normal = order_id(app.clone().oneshot(order("Normal")).await.unwrap()).await;
+    // The engine sits out a requeue delay after the urgent order.
+    tokio::time::sleep(tokio::time::Duration::from_millis(600)).await;
+
+    let res = app
+        .clone()
+        .oneshot(get_request(&format!("/orders/{urgent}")))
+        .await
+        .unwrap();
+    let waiting = body_json(res).await;
+    assert_eq!(waiting["status"], "Pending");
+    assert_eq!(waiting["waiting_reason"], "NoQualifiedCourier");
+
+    let res = app
+        .clone()
+        .oneshot(get_request(&format!("/orders/{normal}")))
+        .await
+        .unwrap();
+    assert_eq!(body_json(res).await["status"], "Assigned");
+    assert!(
+        shared
+            .metrics
+            .candidates_rejected_total
+            .with_label_values(&["urgent_rating"])
+            .get()
+            >= 1
+    );
+}
+
+#[tokio::test]
+async fn orders_wait_for_a_courier_with_the_required_skills() {
+    let (state, rx) = AppState::new(1024, 1024);
+    let shared = Arc::new(state);
+    tokio::spawn(run_assignment_engine(shared.clone(), rx));
+    let app = router(shared.clone());
+
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/couriers",
+            json!({
+                "name": "Ada",
+                "location": { "lat": 52.52, "lng": 13.405 },
+                "capacity": 3,
+                "rating": 4.5,
+                "skills": ["Pharmacy"]
+            }),
+        ))
+        .await
+        .unwrap();
+    let courier = body_json(res).await;
+    assert_eq!(courier["skills"], json!(["pharmacy"]));
+    let courier_id = courier["id"].as_str().unwrap().to_string();
+
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/orders",
+            json!({
+                "pickup": { "lat": 52.52, "lng": 13.405 },
+                "dropoff": { "lat": 52.50, "lng": 13.42 },
+                "priority": "Normal",
+                "required_skills": ["pharmacy", "alcohol-certified"]
+            }),
+        ))
+        .await
+        .unwrap();
+    let order_id = body_json(res).await["id"].as_str().unwrap().to_string();
+    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+
+    let res = app
+        .clone()
+        .oneshot(get_request(&format!("/orders/{order_id}")))
+        .await
+        .unwrap();
+    let order = body_json(res).await;
+    assert_eq!(order["status"], "Pending");
+    assert_eq!(order["waiting_reason"], "NoQualifiedCourier");
+    assert_eq!(
+        shared.metrics.orders_without_qualified_courier_total.get(),
+        1
+    );
+
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "PUT",
+            &format!("/couriers/{courier_id}/skills"),
+            json!({ "skills": ["pharmacy", "Alcohol-Certified "] }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+
+    let res = app
+        .oneshot(get_request(&format!("/orders/{order_id}")))
+        .await
+        .unwrap();
+    let order = body_json(res).await;
+    assert_eq!(order["status"], "Assigned");
+    assert_eq!(order["assigned_courier"], courier_id.as_str());
+    assert_eq!(order["waiting_reason"], Value::Null);
+}
+
+#[tokio::test]
+async fn strict_zone_mode_keeps_couriers_in_their_zones() {
+    let (mut state, rx) = AppState::new(1024, 1024);
+    state.zone_mode = ZoneMode::Strict;
+    let shared = Arc::new(state);
+    tokio::spawn(run_assignment_engine(shared.clone(), rx));
+    let app = router(shared.clone());
+
+    let pickup = GeoPoint {
+        lat: 52.52,
+        lng: 13.405,
+    };
+    let mut ids = Vec::new();
+    // The nearer courier only wants work far to the south.
+    for (name, lat, zone) in [
+        ("Near Nia", 52.52, "s".to_string()),
+        ("Local Lou", 52.53, zone_of(&pickup)),
+    ] {
+        let res = app
+            .clone()
+            .oneshot(json_request(
+                "POST",
+                "/couriers",
+                json!({
+                    "name": name,
+                    "location": { "lat": lat, "lng": 13.405 },
+                    "capacity": 3,
+                    "rating": 4.5,
+                    "preferred_zones": [zone]
+                }),
+            ))
+            .await
+            .unwrap();
+        ids.push(body_json(res).await["id"].as_str().unwrap().to_string());
+    }
+
+    app.clone()
+        .oneshot(json_request(
+            "POST",
+            "/orders",
+            json!({
+                "pickup": pickup,
+                "dropoff": { "lat": 52.50, "lng": 13.42 },
+                "priority": "Normal"
+            }),
+        ))
+        .await
+        .unwrap();
+    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+
+    let res = app.oneshot(get_request("/assignments")).await.unwrap();
+    let assignments = body_json(res).await;
+    assert_eq!(assignments[0]["courier_id"], ids[1]);
+    assert_eq!(assignments[0]["score_breakdown"]["zone_score"], 1.0);
+}
+
+#[tokio::test]
+async fn parallel_scoring_picks_the_same_courier() {
+    let (mut state, rx) = AppState::new(1024, 1024);
+    state.parallel_scoring_threshold = 1;
+    let shared = Arc::new(state);
+    tokio::spawn(run_assignment_engine(shared.clone(), rx));
+    let app = router(shared.clone());
+
+    // Offsets are shuffled so the nearest courier is not created first.
+    let mut nearest = String::new();
+    for step in 0..40 {
+        let offset = ((step * 7) % 40) as f64 * 0.01;
+        let res = app
+            .clone()
+            .oneshot(json_request(
+                "POST",
+                "/couriers",
+                json!({
+                    "name": format!("Courier {step}"),
+                    "location": { "lat": 52.52 + offset, "lng": 13.405 },
+                    "capacity": 3,
+                    "rating": 4.5
+                }),
+            ))
+            .await
+            .unwrap();
+        let id = body_json(res).await["id"].as_str().unwrap().to_string();
+        if offset == 0.0 {
+            nearest = id;
+        }
+    }
+
+    app.clone()
+        .oneshot(json_request(
+            "POST",
+            "/orders",
+            json!({
+                "pickup": { "lat": 52.52, "lng": 13.405 },
+                "dropoff": { "lat": 52.50, "lng": 13.42 },
+                "priority": "Normal"
+            }),
+        ))
+        .await
+        .unwrap();
+    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+
+    let res = app.oneshot(get_request("/assignments")).await.unwrap();
+    let assignments = body_json(res).await;
+    assert_eq!(assignments.as_array().unwrap().len(), 1);
+    assert_eq!(assignments[0]["courier_id"], nearest);
+}
+
+#[tokio::test]
+async fn get_nonexistent_order_returns_404() {
+    let (app, _rx) = setup();
+    let fake_id = "00000000-0000-0000-0000-000000000000";
+    let response = app
+        .oneshot(get_request(&format!("/orders/{fake_id}")))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn create_order_returns_pending() {
+    let (app, _rx) = setup();
+    let response = app
+        .oneshot(json_request(
+            "POST",
+            "/orders",
+            json!({
+                "pickup": { "lat": 52.51, "lng": 13.39 },
+                "dropoff": { "lat": 52.54, "lng": 13.42 },
+                "priority": "Normal"
+            }),
+        ))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = body_json(response).await;
+    assert_eq!(body["status"], "Pending");
+    assert!(body["assigned_courier"].is_null());
+}
+
+#[tokio::test]
+async fn full_assignment_flow() {
+    let (state, rx) = AppState::new(1024, 1024);
+    let shared = Arc::new(state);
+    tokio::spawn(run_assignment_engine(shared.clone(), rx));
+    let app = router(shared.clone());
+
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/couriers",
+            json!({
+                "name": "Dispatch Dan",
+                "location": { "lat": 52.52, "lng": 13.405 },
+                "capacity": 5,
+                "rating": 4.8
+            }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let courier = body_json(res).await;
+    let courier_id = courier["id"].as_str().unwrap().to_string();
+
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/orders",
+            json!({
+                "pickup": { "lat": 52.51, "lng": 13.39 },
+                "dropoff": { "lat": 52.54, "lng": 13.42 },
+                "priority": "Urgent"
+            }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let order = body_json(res).await;
+    let order_id = order["id"].as_str().unwrap().to_string();
+
+    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+
+    let res = app
+        .clone()
+        .oneshot(get_request("/assignments"))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let assignments = body_json(res).await;
+    let list = assignments.as_array().unwrap();
+    assert_eq!(list.len(), 1);
+
+    let assignment = &list[0];
+    assert_eq!(assignment["courier_id"], courier_id);
+    assert_eq!(assignment["order_id"], order_id);
+    assert!(assignment["score"].as_f64().unwrap() > 0.0);
+    assert!(assignment["score_breakdown"]["distance_score"].as_f64().unwrap() > 0.0);
+    assert!(assignment["score_breakdown"]["load_score"].as_f64().unwrap() > 0.0);
+    assert!(assignment["score_breakdown"]["rating_score"].as_f64().unwrap() > 0.0);
+    assert!(assignment["score_breakdown"]["priority_score"].as_f64().unwrap() > 0.0);
+
+    let urgent = ["Urgent"];
+    assert_eq!(
+        shared
+            .metrics
+            .orders_in_queue
+            .with_label_values(&urgent)
+            .get(),
+        0
+    );
+    assert_eq!(
+        shared
+            .metrics
+            .order_queue_wait_seconds
+            .with_label_values(&urgent)
+            .get_sample_count(),
+        1
+    );
+    assert_eq!(
+        shared
+            .metrics
+            .assignment_pickup_distance_km
+            .get_sample_count(),
+        1
+    );
+    assert!(shared.metrics.assignment_eta_seconds.get_sample_sum() > 0.0);
+
+    let res = app
+        .clone()
+        .oneshot(get_request(&format!("/orders/{order_id}")))
+        .await
+        .unwrap();
+    let updated_order = body_json(res).await;
+    assert_eq!(updated_order["status"], "Assigned");
+    assert_eq!(updated_order["assigned_courier"], courier_id);
+
+    let res = app.oneshot(get_request("/couriers")).await.unwrap();
+    let couriers = body_json(res).await;
+    let updated_courier = &couriers.as_array().unwrap()[0];
+    assert_eq!(updated_courier["current_load"], 1.0);
+}
+
+#[tokio::test]
+async fn assignment_lookup_by_order_and_courier() {
+    let (state, rx) = AppState::new(1024, 1024);
+    let shared = Arc::new(state);
+    tokio::spawn(run_assignment_engine(shared.clone(), rx));
+    let app = router(shared.clone());
+
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/couriers",
+            json!({
+                "name": "Lookup Lou",
+                "location": { "lat": 52.52, "lng": 13.405 },
+                "capacity": 5,
+                "rating": 4.2
+            }),
+        ))
+        .await
+        .unwrap();
+    let courier = body_json(res).await;
+    let courier_id = courier["id"].as_str().unwrap().to_string();
+
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/orders",
+            json!({
+                "pickup": { "lat": 52.51, "lng": 13.39 },
+                "dropoff": { "lat": 52.54, "lng": 13.42 },
+                "priority": "High"
+            }),
+        ))
+        .await
+        .unwrap();
+    let order = body_json(res).await;
+    let order_id = order["id"].as_str().unwrap().to_string();
+
+    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+
+    let res = app
+        .clone()
+        .oneshot(get_request(&format!("/orders/{order_id}/assignment")))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let assignment = body_json(res).await;
+    assert_eq!(assignment["order_id"], order_id);
+    assert_eq!(assignment["courier_id"], courier_id);
+
+    let res = app
+        .clone()
+        .oneshot(get_request(&format!(
+            "/couriers/{courier_id}/assignments?active=true"
+        )))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let active = body_json(res).await;
+    assert_eq!(active.as_array().unwrap().len(), 1);
+    assert_eq!(active[0]["order_id"], order_id);
+
+    let res = app
+        .oneshot(get_request(&format!(
+            "/couriers/{courier_id}/assignments?active=false"
+        )))
+        .await
+        .unwrap();
+    let historical = body_json(res).await;
+    assert_eq!(historical.as_array().unwrap().len(), 0);
+}
+
+#[tokio::test]
+async fn unassigned_order_assignment_returns_404() {
+    let (app, _rx) = setup();
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/orders",
+            json!({
+                "pickup": { "lat": 52.51, "lng": 13.39 },
+                "dropoff": { "lat": 52.54, "lng": 13.42 },
+                "priority": "Low"
+            }),
+        ))
+        .await
+        .unwrap();
+    let order = body_json(res).await;
+    let order_id = order["id"].as_str().unwrap();
+
+    let res = app
+        .oneshot(get_request(&format!("/orders/{order_id}/assignment")))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn openapi_document_lists_rest_paths() {
+    let (app, _rx) = setup();
+    let response = app.oneshot(get_request("/openapi.json")).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = body_json(response).await;
+    assert!(body["openapi"].as_str().unwrap().starts_with("3."));
+    assert!(body["paths"]["/couriers"]["post"].is_object());
+    assert!(body["paths"]["/orders/{id}/assignment"]["get"].is_object());
+    assert!(body["components"]["schemas"]["DeliveryOrder"].is_object());
+}
+
+#[tokio::test]
+async fn bulk_import_reports_per_item_results() {
+    let (state, _rx) = AppState::new(1024, 1024);
+    let shared = Arc::new(state);
+    let app = router(shared.clone());
+
+    let res = app
+        .oneshot(json_request(
+            "POST",
+            "/couriers/bulk",
+            json!([
+                {
+                    "name": "Ada",
+                    "location": { "lat": 52.52, "lng": 13.405 },
+                    "capacity": 3,
+                    "rating": 4.7
+                },
+                {
+                    "name": "",
+                    "location": { "lat": 52.52, "lng": 13.405 },
+                    "capacity": 3,
+                    "rating": 4.7
+                },
+                { "name": "Missing fields" }
+            ]),
+        ))
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = body_json(res).await;
+    assert_eq!(body["created"], 1);
+    assert_eq!(body["failed"], 2);
+    assert_eq!(body["results"][0]["courier"]["name"], "Ada");
+    assert!(body["results"][1]["error"].is_string());
+    assert!(body["results"][2]["error"].is_string());
+    assert_eq!(shared.couriers.len(), 1);
+}
+
+#[tokio::test]
+async fn bulk_import_accepts_csv() {
+    let (state, _rx) = AppState::new(1024, 1024);
+    let shared = Arc::new(state);
+    let app = router(shared.clone());
+
+    let csv = "name,lat,lng,capacity,rating\nAda,52.52,13.405,3,4.7\nBen,52.50,13.39,0,4.1\n";
+    let res = app
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/couriers/bulk")
+                .header("content-type", "text/csv")
+                .body(Body::from(csv))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = body_json(res).await;
+    assert_eq!(body["created"], 1);
+    assert_eq!(body["failed"], 1);
+    assert_eq!(body["results"][1]["index"], 1);
+    assert_eq!(shared.couriers.len(), 1);
+}
+
+#[tokio::test]
+async fn order_status_transitions_release_courier() {
+    let (state, rx) = AppState::new(1024, 1024);
+    let shared = Arc::new(state);
+    tokio::spawn(run_assignment_engine(shared.clone(), rx));
+    let app = router(shared.clone());
+    let mut events = shared.events.subscribe();
+
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/couriers",
+            json!({
+                "name": "Solo",
+                "location": { "lat": 52.52, "lng": 13.405 },
+                "capacity": 1,
+                "rating": 4.0
+            }),
+        ))
+        .await
+        .unwrap();
+    let courier = body_json(res).await;
+    let courier_id = courier["id"].as_str().unwrap().to_string();
+
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/orders",
+            json!({
+                "pickup": { "lat": 52.51, "lng": 13.39 },
+                "dropoff": { "lat": 52.54, "lng": 13.42 },
+                "priority": "Normal"
+            }),
+        ))
+        .await
+        .unwrap();
+    let order = body_json(res).await;
+    let order_id = order["id"].as_str().unwrap().to_string();
+
+    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+
+    let res = app
+        .clone()
+        .oneshot(patch_request(
+            &format!("/orders/{order_id}/status"),
+            json!({ "status": "Delivered" }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::CONFLICT);
+
+    for status in ["InTransit", "Delivered"] {
+        let res = app
+            .clone()
+            .oneshot(patch_request(
+                &format!("/orders/{order_id}/status"),
+                json!({ "status": status }),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(body_json(res).await["status"], status);
+    }
+
+    let res = app.oneshot(get_request("/couriers")).await.unwrap();
+    let couriers = body_json(res).await;
+    assert_eq!(couriers[0]["id"], courier_id);
+    assert_eq!(couriers[0]["current_load"], 0.0);
+    assert_eq!(couriers[0]["status"], "Available");
+
+    let mut seen = Vec::new();
+    while let Ok(recorded) = events.try_recv() {
+        if let DispatchEvent::Order(event) = recorded.event {
+            seen.push(format!("{:?}", event.status));
+        }
+    }
+    assert_eq!(seen, ["Pending", "Assigned", "InTransit", "Delivered"]);
+}
+
+#[tokio::test]
+async fn declined_orders_go_to_another_courier_and_count_against_reliability() {
+    let (state, rx) = AppState::new(1024, 1024);
+    let shared = Arc::new(state);
+    tokio::spawn(run_assignment_engine(shared.clone(), rx));
+    let app = router(shared.clone());
+
+    let mut ids = Vec::new();
+    for (name, lat) in [("Near Ned", 52.51), ("Far Fay", 52.60)] {
+        let res = app
+            .clone()
+            .oneshot(json_request(
+                "POST",
+                "/couriers",
+                json!({
+                    "name": name,
+                    "location": { "lat": lat, "lng": 13.39 },
+                    "capacity": 2,
+                    "rating": 4.5
+                }),
+            ))
+            .await
+            .unwrap();
+        ids.push(body_json(res).await["id"].as_str().unwrap().to_string());
+    }
+
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/orders",
+            json!({
+                "pickup": { "lat": 52.51, "lng": 13.39 },
+                "dropoff": { "lat": 52.54, "lng": 13.42 },
+                "priority": "Normal"
+            }),
+        ))
+        .await
+        .unwrap();
+    let order_id = body_json(res).await["id"].as_str().unwrap().to_string();
+    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+
+    let decline = || json_request("POST", &format!("/orders/{order_id}/decline"), json!({}));
+    let res = app.clone().oneshot(decline()).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let order = body_json(res).await;
+    assert_eq!(order["status"], "Pending");
+    assert_eq!(order["declined_by"], json!([ids[0]]));
+    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+
+    let res = app
+        .clone()
+        .oneshot(get_request(&format!("/orders/{order_id}")))
+        .await
+        .unwrap();
+    assert_eq!(body_json(res).await["assigned_courier"], ids[1]);
+
+    let res = app
+        .clone()
+        .oneshot(patch_request(
+            &format!("/orders/{order_id}/status"),
+            json!({ "status": "InTransit" }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let res = app.clone().oneshot(decline()).await.unwrap();
+    assert_eq!(res.status(), StatusCode::CONFLICT);
+
+    let courier = |id: &str| shared.couriers.get(&id.parse().unwrap()).unwrap().clone();
+    let near = courier(&ids[0]);
+    assert_eq!(near.offers.declined, 1);
+    assert_eq!(near.current_load, 0.0);
+    assert!(near.offers.reliability() < 1.0);
+    let far = courier(&ids[1]);
+    assert_eq!(far.offers.accepted, 1);
+    assert_eq!(far.offers.reliability(), 1.0);
+}
+
+#[tokio::test]
+async fn declines_are_refused_while_the_queue_is_full() {
+    use dispatch_router::models::order::OrderStatus;
+
+    let (state, mut rx) = AppState::new(1, 1024);
+    let shared = Arc::new(state);
+    let app = router(shared.clone());
+
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/couriers",
+            json!({
+                "name": "Queued Quinn",
+                "location": { "lat": 52.51, "lng": 13.39 },
+                "capacity": 2,
+                "rating": 4.5
+            }),
+        ))
+        .await
+        .unwrap();
+    let courier_id: uuid::Uuid = body_json(res).await["id"]
+        .as_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    let order = || {
+        json_request(
+            "POST",
+            "/orders",
+            json!({
+                "pickup": { "lat": 52.51, "lng": 13.39 },
+                "dropoff": { "lat": 52.54, "lng": 13.42 },
+                "priority": "Normal"
+            }),
+        )
+    };
+    let res = app.clone().oneshot(order()).await.unwrap();
+    let order_id: uuid::Uuid = body_json(res).await["id"]
+        .as_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+
+    // Stand in for the engine: take the order off the queue and assign it.
+    rx.recv().await.unwrap();
+    {
+        let mut stored = shared.orders.get_mut(&order_id).unwrap();
+        stored.status = OrderStatus::Assigned;
+        stored.assigned_courier = Some(courier_id);
+    }
+    Arc::make_mut(&mut shared.couriers.get_mut(&courier_id).unwrap()).current_load = 1.0;
+    // Another order fills the queue.
+    app.clone().oneshot(order()).await.unwrap();
+
+    let decline = || json_request("POST", &format!("/orders/{order_id}/decline"), json!({}));
+    let res = app.clone().oneshot(decline()).await.unwrap();
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    {
+        let stored = shared.orders.get(&order_id).unwrap();
+        assert_eq!(stored.status, OrderStatus::Assigned);
+        assert_eq!(stored.assigned_courier, Some(courier_id));
+        assert!(stored.declined_by.is_empty());
+    }
+    let courier = shared.couriers.get(&courier_id).unwrap().clone();
+    assert_eq!(courier.current_load, 1.0);
+    assert_eq!(courier.offers.declined, 0);
+
+    rx.recv().await.unwrap();
+    let res = app.clone().oneshot(decline()).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let queued = rx.recv().await.unwrap();
+    assert_eq!(queued.order.id, order_id);
+    assert_eq!(queued.order.status, OrderStatus::Pending);
+    assert_eq!(queued.order.declined_by, vec![courier_id]);
+}
+
+#[tokio::test]
+async fn deliveries_add_up_in_courier_and_fleet_stats() {
+    let (mut state, rx) = AppState::new(1024, 1024);
+    state.payouts = PayoutRates {
+        per_delivery: 3.0,
+        per_km: 0.5,
+    };
+    let shared = Arc::new(state);
+    tokio::spawn(run_assignment_engine(shared.clone(), rx));
+    let app = router(shared.clone());
+
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/couriers",
+            json!({
+                "name": "Pia",
+                "location": { "lat": 52.52, "lng": 13.405 },
+                "capacity": 2,
+                "rating": 4.0
+            }),
+        ))
+        .await
+        .unwrap();
+    let courier_id = body_json(res).await["id"].as_str().unwrap().to_string();
+
+    let mut order_ids = Vec::new();
+    for _ in 0..2 {
+        let res = app
+            .clone()
+            .oneshot(json_request(
+                "POST",
+                "/orders",
+                json!({
+                    "pickup": { "lat": 52.51, "lng": 13.39 },
+                    "dropoff": { "lat": 52.54, "lng": 13.42 },
+                    "priority": "Normal"
+                }),
+            ))
+            .await
+            .unwrap();
+        order_ids.push(body_json(res).await["id"].as_str().unwrap().to_string());
+    }
+    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+
+    for status in ["InTransit", "Delivered"] {
+        app.clone()
+            .oneshot(patch_request(
+                &format!("/orders/{}/status", order_ids[0]),
+                json!({ "status": status }),
+            ))
+            .await
+            .unwrap();
+    }
+
+    let res = app
+        .clone()
+        .oneshot(get_request(&format!("/couriers/{courier_id}/stats")))
+        .await
+        .unwrap();
+    let stats = body_json(res).await;
+    let distance_km = stats["distance_km"].as_f64().unwrap();
+    assert_eq!(stats["deliveries"], 1);
+    assert!((3.0..4.5).contains(&distance_km));
+    assert!((stats["earnings"].as_f64().unwrap() - (3.0 + 0.5 * distance_km)).abs() < 1e-9);
+    assert_eq!(stats["active_orders"], 1);
+    assert_eq!(stats["earnings_today"], stats["earnings"]);
+
+    let res = app.oneshot(get_request("/fleet/stats")).await.unwrap();
+    let fleet = body_json(res).await;
+    assert_eq!(fleet["couriers"], 1);
+    assert_eq!(fleet["active_couriers"], 1);
+    assert_eq!(fleet["deliveries"], 1);
+    assert_eq!(fleet["earnings"], stats["earnings"]);
+    assert_eq!(fleet["median_earnings_today"], stats["earnings"]);
+}
+
+#[tokio::test]
+async fn feedback_on_delivered_orders_updates_the_courier_rating() {
+    let (state, rx) = AppState::new(1024, 1024);
+    let shared = Arc::new(state);
+    tokio::spawn(run_assignment_engine(shared.clone(), rx));
+    let app = router(shared.clone());
+
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/couriers",
+            json!({
+                "name": "Ida",
+                "location": { "lat": 52.51, "lng": 13.39 },
+                "capacity": 1,
+                "rating": 4.0
+            }),
+        ))
+        .await
+        .unwrap();
+    let courier_id = body_json(res).await["id"].as_str().unwrap().to_string();
+
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/orders",
+            json!({
+                "pickup": { "lat": 52.51, "lng": 13.39 },
+                "dropoff": { "lat": 52.52, "lng": 13.40 },
+                "priority": "Normal"
+            }),
+        ))
+        .await
+        .unwrap();
+    let order_id = body_json(res).await["id"].as_str().unwrap().to_string();
+    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+
+    let feedback = json!({ "stars": 2, "comment": "Arrived cold" });
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            &format!("/orders/{order_id}/feedback"),
+            feedback.clone(),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::CONFLICT);
+
+    for status in ["InTransit", "Delivered"] {
+        app.clone()
+            .oneshot(patch_request(
+                &format!("/orders/{order_id}/status"),
+                json!({ "status": status }),
+            ))
+            .await
+            .unwrap();
+    }
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            &format!("/orders/{order_id}/feedback"),
+            feedback.clone(),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    // 4.0 + 0.1 * (2 - 4.0)
+    let rating = body_json(res).await["rating_after"].as_f64().unwrap();
+    assert!((rating - 3.8).abs() < 1e-9, "{rating}");
+
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            &format!("/orders/{order_id}/feedback"),
+            feedback,
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::CONFLICT);
+
+    let res = app
+        .clone()
+        .oneshot(get_request(&format!("/couriers/{courier_id}/feedback")))
+        .await
+        .unwrap();
+    let history = body_json(res).await;
+    assert_eq!(history.as_array().unwrap().len(), 1);
+    assert_eq!(history[0]["order_id"], order_id.as_str());
+    assert_eq!(history[0]["comment"], "Arrived cold");
+}
+
+#[tokio::test]
+async fn order_history_lists_every_step_with_its_actor() {
+    let (state, rx) = AppState::new(1024, 1024);
+    let shared = Arc::new(state);
+    tokio::spawn(run_assignment_engine(shared.clone(), rx));
+    let app = router(shared.clone());
+
+    let courier = |name: &str| {
+        json_request(
+            "POST",
+            "/couriers",
+            json!({
+                "name": name,
+                "location": { "lat": 52.51, "lng": 13.39 },
+                "capacity": 1,
+                "rating": 4.0
+            }),
+        )
+    };
+    let res = app.clone().oneshot(courier("Ida")).await.unwrap();
+    let first = body_json(res).await["id"].as_str().unwrap().to_string();
+
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/orders",
+            json!({
+                "pickup": { "lat": 52.51, "lng": 13.39 },
+                "dropoff": { "lat": 52.52, "lng": 13.40 },
+                "priority": "Normal"
+            }),
+        ))
+        .await
+        .unwrap();
+    let order_id = body_json(res).await["id"].as_str().unwrap().to_string();
+    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+
+    app.clone()
+        .oneshot(json_request(
+            "POST",
+            &format!("/orders/{order_id}/decline"),
+            json!({}),
+        ))
+        .await
+        .unwrap();
+    let res = app.clone().oneshot(courier("Ole")).await.unwrap();
+    let second = body_json(res).await["id"].as_str().unwrap().to_string();
+    tokio::time::sleep(tokio::time::Duration::from_millis(600)).await;
+    for status in ["InTransit", "Delivered"] {
+        app.clone()
+            .oneshot(patch_request(
+                &format!("/orders/{order_id}/status"),
+                json!({ "status": status }),
+            ))
+            .await
+            .unwrap();
+    }
+
+    let res = app
+        .clone()
+        .oneshot(get_request(&format!("/orders/{order_id}/history")))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let history = body_json(res).await;
+    let steps: Vec<(&str, &str, &Value)> = history
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| {
+            (
+                entry["event"].as_str().unwrap(),
+                entry["actor"].as_str().unwrap(),
+                &entry["courier_id"],
+            )
+        })
+        .collect();
+    let (first, second) = (json!(first), json!(second));
+    assert_eq!(
+        steps,
+        [
+            ("created", "rest", &Value::Null),
+            ("queued", "rest", &Value::Null),
+            ("assigned", "engine", &first),
+            ("declined", "rest", &first),
+            ("queued", "rest", &Value::Null),
+            ("assigned", "engine", &second),
+            ("picked_up", "rest", &second),
+            ("delivered", "rest", &second),
+        ]
+    );
+
+    let res = app
+        .oneshot(get_request(&format!(
+            "/orders/{}/history",
+            uuid::Uuid::new_v4()
+        )))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn leaderboard_ranks_couriers_by_recent_deliveries() {
+    let (state, rx) = AppState::new(1024, 1024);
+    let shared = Arc::new(state);
+    tokio::spawn(run_assignment_engine(shared.clone(), rx));
+    let app = router(shared.clone());
+
+    let mut courier_ids = Vec::new();
+    for (name, lng) in [("Ida", 13.39), ("Ole", 13.45)] {
+        let res = app
+            .clone()
+            .oneshot(json_request(
+                "POST",
+                "/couriers",
+                json!({
+                    "name": name,
+                    "location": { "lat": 52.51, "lng": lng },
+                    "capacity": 1,
+                    "rating": 4.0
+                }),
+            ))
+            .await
+            .unwrap();
+        courier_ids.push(body_json(res).await["id"].as_str().unwrap().to_string());
+    }
+
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/orders",
+            json!({
+                "pickup": { "lat": 52.51, "lng": 13.39 },
+                "dropoff": { "lat": 52.52, "lng": 13.40 },
+                "priority": "Normal"
+            }),
+        ))
+        .await
+        .unwrap();
+    let order_id = body_json(res).await["id"].as_str().unwrap().to_string();
+    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+
+    for status in ["InTransit", "Delivered"] {
+        app.clone()
+            .oneshot(patch_request(
+                &format!("/orders/{order_id}/status"),
+                json!({ "status": status }),
+            ))
+            .await
+            .unwrap();
+    }
+
+    let res = app
+        .clone()
+        .oneshot(get_request(
+            "/couriers/leaderboard?metric=on_time_rate&period=week",
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let board = body_json(res).await;
+    assert_eq!(board["metric"], "on_time_rate");
+    let entries = board["entries"].as_array().unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0]["courier_id"], courier_ids[0].as_str());
+    assert_eq!(entries[0]["name"], "Ida");
+    assert_eq!(entries[0]["rank"], 1);
+    assert_eq!(entries[0]["value"], 1.0);
+    assert_eq!(entries[0]["deliveries"], 1);
+
+    let res = app
+        .oneshot(get_request("/couriers/leaderboard?metric=speed"))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn event_stream_replays_after_last_event_id() {
+    let (state, _rx) = AppState::new(1024, 1024);
+    let shared = Arc::new(state);
+    let app = router(shared.clone());
+
+    for _ in 0..2 {
+        let res = app
+            .clone()
+            .oneshot(json_request(
+                "POST",
+                "/orders",
+                json!({
+                    "pickup": { "lat": 52.51, "lng": 13.39 },
+                    "dropoff": { "lat": 52.54, "lng": 13.42 },
+                    "priority": "Normal"
+                }),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    let res = app
+        .oneshot(
+            Request::builder()
+                .uri("/events/stream")
+                .header("last-event-id", "1")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-type"], "text/event-stream");
+
+    let mut body = res.into_body().into_data_stream();
+    let frame = futures::StreamExt::next(&mut body).await.unwrap().unwrap();
+    let frame = String::from_utf8(frame.to_vec()).unwrap();
+    assert!(frame.contains("id: 2\n"), "{frame}");
+    assert!(frame.contains("event: orders\n"), "{frame}");
+    assert!(frame.contains(r#""topic":"orders""#), "{frame}");
+}
+
+#[tokio::test]
+async fn track_order_streams_initial_snapshot() {
+    let (app, _rx) = setup();
+
+    let missing = uuid::Uuid::new_v4();
+    let res = app
+        .clone()
+        .oneshot(get_request(&format!("/orders/{missing}/track")))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/orders",
+            json!({
+                "pickup": { "lat": 52.51, "lng": 13.39 },
+                "dropoff": { "lat": 52.54, "lng": 13.42 },
+                "priority": "Normal"
+            }),
+        ))
+        .await
+        .unwrap();
+    let order_id = body_json(res).await["id"].as_str().unwrap().to_string();
+
+    let res = app
+        .oneshot(get_request(&format!("/orders/{order_id}/track")))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let mut body = res.into_body().into_data_stream();
+    let frame = futures::StreamExt::next(&mut body).await.unwrap().unwrap();
+    let frame = String::from_utf8(frame.to_vec()).unwrap();
+    assert!(frame.starts_with("event: tracking\n"), "{frame}");
+    assert!(frame.contains(r#""status":"Pending""#), "{frame}");
+    assert!(frame.contains(r#""eta_seconds":null"#), "{frame}");
+}
+
+#[tokio::test]
+async fn webhook_registration_validates_and_hides_secret() {
+    let (app, _rx) = setup();
+
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/webhooks",
+            json!({ "url": "ftp://example.com", "secret": "x", "event_types": ["orders"] }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/webhooks",
+            json!({
+                "url": "https://example.com/dispatch",
+                "secret": "x",
+                "event_types": ["assignments", "orders"]
+            }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let webhook = body_json(res).await;
+    assert!(webhook.get("secret").is_none());
+    let id = webhook["id"].as_str().unwrap().to_string();
+
+    let res = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("DELETE")
+                .uri(format!("/webhooks/{id}"))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+    let res = app.oneshot(get_request("/webhooks")).await.unwrap();
+    assert_eq!(body_json(res).await, json!([]));
+}
+
+#[tokio::test]
+async fn courier_devices_register_once_per_token() {
+    let (app, _rx) = setup();
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/couriers",
+            json!({
+                "name": "Alice",
+                "location": { "lat": 52.52, "lng": 13.405 },
+                "capacity": 5,
+                "rating": 4.5
+            }),
+        ))
+        .await
+        .unwrap();
+    let courier_id = body_json(res).await["id"].as_str().unwrap().to_string();
+    let devices_uri = format!("/couriers/{courier_id}/devices");
+
+    for _ in 0..2 {
+        let res = app
+            .clone()
+            .oneshot(json_request(
+                "POST",
+                &devices_uri,
+                json!({ "platform": "fcm", "token": "tok-1" }),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    let res = app
+        .clone()
+        .oneshot(get_request(&devices_uri))
+        .await
+        .unwrap();
+    let devices = body_json(res).await;
+    assert_eq!(devices.as_array().unwrap().len(), 1);
+    assert_eq!(devices[0]["platform"], "fcm");
+
+    let res = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("DELETE")
+                .uri(format!("{devices_uri}/tok-1"))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+    let res = app
+        .oneshot(json_request(
+            "POST",
+            &format!("/couriers/{}/devices", uuid::Uuid::new_v4()),
+            json!({ "platform": "apns", "token": "tok-2" }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn create_order_rejects_non_http_callback_url() {
+    let (app, _rx) = setup();
+    let res = app
+        .oneshot(json_request(
+            "POST",
+            "/orders",
+            json!({
+                "pickup": { "lat": 52.51, "lng": 13.39 },
+                "dropoff": { "lat": 52.54, "lng": 13.42 },
+                "priority": "Normal",
+                "callback_url": "mailto:someone@example.com"
+            }),
+        ))
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn create_order_records_request_id() {
+    let (app, _rx) = setup();
+    let order = json!({
+        "pickup": { "lat": 52.51, "lng": 13.39 },
+        "dropoff": { "lat": 52.54, "lng": 13.42 },
+        "priority": "Normal"
+    });
+
+    let mut req = json_request("POST", "/orders", order.clone());
+    req.headers_mut()
+        .insert("x-request-id", "checkout-42".parse().unwrap());
+    let res = app.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.headers()["x-request-id"], "checkout-42");
+    assert_eq!(body_json(res).await["request_id"], "checkout-42");
+
+    let res = app
+        .oneshot(json_request("POST", "/orders", order))
+        .await
+        .unwrap();
+    let generated = res.headers()["x-request-id"].to_str().unwrap().to_string();
+    assert!(!generated.is_empty());
+    assert_eq!(body_json(res).await["request_id"], generated.as_str());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn metrics_include_tokio_runtime_gauges() {
+    let (app, _rx) = setup();
+    let res = app.oneshot(get_request("/metrics")).await.unwrap();
+    let body = body_string(res).await;
+
+    assert!(body.contains("tokio_workers 2"), "{body}");
+    assert!(body.contains("tokio_alive_tasks "), "{body}");
+    assert!(body.contains("tokio_worker_busy_seconds{worker=\"1\"}"), "{body}");
+}
+
+#[tokio::test]
+async fn metrics_report_fleet_aggregates_without_per_courier_labels() {
+    let (app, _rx) = setup();
+    for capacity in [3, 5] {
+        let res = app
+            .clone()
+            .oneshot(json_request(
+                "POST",
+                "/couriers",
+                json!({
+                    "name": "Fleet Fay",
+                    "location": { "lat": 52.52, "lng": 13.405 },
+                    "capacity": capacity,
+                    "rating": 4.5
+                }),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    let res = app.oneshot(get_request("/metrics")).await.unwrap();
+    let body = body_string(res).await;
+
+    assert!(body.contains("fleet_capacity 8"));
+    assert!(body.contains("fleet_load 0"));
+    assert!(body.contains("couriers{status=\"Available\"} 2"));
+    assert!(!body.contains("courier_id"));
+}
+
+#[tokio::test]
+async fn audit_log_records_courier_changes() {
+    let (app, _rx) = setup();
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/couriers",
+            json!({
+                "name": "Audited Ann",
+                "location": { "lat": 52.52, "lng": 13.405 },
+                "capacity": 2,
+                "rating": 4.1
+            }),
+        ))
+        .await
+        .unwrap();
+    let courier_id = body_json(res).await["id"].as_str().unwrap().to_string();
+
+    let res = app
+        .clone()
+        .oneshot(patch_request(
+            &format!("/couriers/{courier_id}/status"),
+            json!({ "status": "Offline" }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = app
+        .clone()
+        .oneshot(get_request(&format!(
+            "/audit?entity=courier&entity_id={courier_id}"
+        )))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let entries = body_json(res).await;
+    let entries = entries.as_array().unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0]["action"], "created");
+    assert!(entries[0]["old"].is_null());
+    assert_eq!(entries[1]["action"], "status_changed");
+    assert_eq!(entries[1]["actor"], "rest");
+    assert_eq!(entries[1]["old"]["status"], "Available");
+    assert_eq!(entries[1]["new"]["status"], "Offline");
+
+    let res = app
+        .oneshot(get_request("/audit?entity=order"))
+        .await
+        .unwrap();
+    assert!(body_json(res).await.as_array().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn engine_health_reports_liveness_and_requeues() {
+    let (state, rx) = AppState::new(1024, 1024);
+    let shared = Arc::new(state);
+    let app = router(shared.clone());
+
+    let res = app
+        .clone()
+        .oneshot(get_request("/health/engine"))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body_json(res).await["status"], "stopped");
+
+    tokio::spawn(run_assignment_engine(shared.clone(), rx));
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/orders",
+            json!({
+                "pickup": { "lat": 52.51, "lng": 13.39 },
+                "dropoff": { "lat": 52.54, "lng": 13.42 },
+                "priority": "Normal"
+            }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    tokio::time::sleep(tokio::time::Duration::from_millis(300)).await;
+
+    let res = app.oneshot(get_request("/health/engine")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let health = body_json(res).await;
+    assert_eq!(health["status"], "ok");
+    assert!(health["requeued_total"].as_u64().unwrap() >= 1);
+    assert!(health["last_assignment_at"].is_null());
+}
+
+#[tokio::test]
+async fn live_and_ready_probes_differ_while_the_engine_is_down() {
+    let (state, rx) = AppState::new(10, 1024);
+    let shared = Arc::new(state);
+    let app = router(shared.clone());
+
+    let res = app.clone().oneshot(get_request("/ready")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body_json(res).await["reasons"], json!(["engine_stopped"]));
+
+    // Nothing reads the queue yet, so it fills up.
+    for _ in 0..9 {
+        let res = app
+            .clone()
+            .oneshot(json_request(
+                "POST",
+                "/orders",
+                json!({
+                    "pickup": { "lat": 52.51, "lng": 13.39 },
+                    "dropoff": { "lat": 52.54, "lng": 13.42 },
+                    "priority": "Normal"
+                }),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+    let res = app.clone().oneshot(get_request("/ready")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(
+        body_json(res).await["reasons"],
+        json!(["engine_stopped", "queue_saturated"])
+    );
+    let res = app.clone().oneshot(get_request("/live")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/couriers",
+            json!({
+                "name": "Alice",
+                "location": { "lat": 52.52, "lng": 13.405 },
+                "capacity": 10,
+                "rating": 4.5
+            }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    tokio::spawn(run_assignment_engine(shared.clone(), rx));
+    tokio::time::sleep(tokio::time::Duration::from_millis(300)).await;
+    let res = app.oneshot(get_request("/ready")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(body_json(res).await["ready"], true);
+}
+
+fn token(claims: Value) -> String {
+    let header = jsonwebtoken::Header {
+        kid: Some("test".to_string()),
+        ..Default::default()
+    };
+    let key = jsonwebtoken::EncodingKey::from_secret(b"secret");
+    let token = jsonwebtoken::encode(&header, &claims, &key).unwrap();
+    format!("Bearer {token}")
+}
+
+fn with_token(mut request: Request<Body>, token: &str) -> Request<Body> {
+    request
+        .headers_mut()
+        .insert("authorization", token.parse().unwrap());
+    request
+}
+
+/// Turns JWT auth on, trusting tokens made by `token`.
+fn require_jwt(state: &mut AppState) {
+    use dispatch_router::auth::{JwtSettings, JwtVerifier};
+
+    let verifier = JwtVerifier::new(JwtSettings {
+        jwks_url: "http://idp.test/jwks".to_string(),
+        issuer: None,
+        audience: None,
+        refresh_interval: std::time::Duration::from_secs(300),
+        courier_token_secret: None,
+        courier_token_ttl: std::time::Duration::from_secs(3600),
+    });
+    verifier.set_keys(
+        &serde_json::from_value(json!({
+            "keys": [{ "kty": "oct", "kid": "test", "alg": "HS256", "k": "c2VjcmV0" }]
+        }))
+        .unwrap(),
+    );
+    state.jwt = Some(Arc::new(verifier));
+}
+
+#[tokio::test]
+async fn jwt_roles_gate_courier_and_order_changes() {
+    let (mut state, _rx) = AppState::new(1024, 1024);
+    require_jwt(&mut state);
+    let app = router(Arc::new(state));
+
+    let exp = 4_102_444_800u64;
+    let admin = token(json!({ "sub": "ops-admin", "exp": exp, "roles": ["admin"] }));
+    let dispatcher = token(json!({ "sub": "desk", "exp": exp, "roles": ["dispatcher"] }));
+    let new_courier = json!({
+        "name": "Gated Gus",
+        "location": { "lat": 52.52, "lng": 13.405 },
+        "capacity": 2,
+        "rating": 4.5
+    });
+
+    let res = app
+        .clone()
+        .oneshot(json_request("POST", "/couriers", new_courier.clone()))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let res = app
+        .clone()
+        .oneshot(with_token(
+            json_request("POST", "/couriers", new_courier.clone()),
+            &dispatcher,
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let res = app
+        .clone()
+        .oneshot(with_token(
+            json_request("POST", "/couriers", new_courier),
+            &admin,
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let courier_id = body_json(res).await["id"].as_str().unwrap().to_string();
+
+    let own = token(json!({
+        "sub": "gus",
+        "exp": exp,
+        "roles": ["courier"],
+        "courier_id": courier_id,
+    }));
+    let other = token(json!({
+        "sub": "someone-else",
+        "exp": exp,
+        "roles": ["courier"],
+        "courier_id": uuid::Uuid::new_v4(),
+    }));
+    let status_uri = format!("/couriers/{courier_id}/status");
+
+    let res = app
+        .clone()
+        .oneshot(with_token(
+            patch_request(&status_uri, json!({ "status": "Offline" })),
+            &other,
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let res = app
+        .clone()
+        .oneshot(with_token(
+            patch_request(&status_uri, json!({ "status": "Offline" })),
+            &own,
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = app
+        .clone()
+        .oneshot(with_token(
+            json_request(
+                "POST",
+                "/orders",
+                json!({
+                    "pickup": { "lat": 52.52, "lng": 13.405 },
+                    "dropoff": { "lat": 52.50, "lng": 13.42 },
+                    "priority": "Normal"
+                }),
+            ),
+            &own,
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let res = app
+        .clone()
+        .oneshot(with_token(
+            get_request(&format!("/audit?entity=courier&entity_id={courier_id}")),
+            &admin,
+        ))
+        .await
+        .unwrap();
+    let entries = body_json(res).await;
+    let actors: Vec<&str> = entries
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| entry["actor"].as_str().unwrap())
+        .collect();
+    assert_eq!(actors, vec!["ops-admin", "gus"]);
+}
+
+#[tokio::test]
+async fn courier_tokens_only_read_their_own_orders_and_events() {
+    let (mut state, _rx) = AppState::new(1024, 1024);
+    require_jwt(&mut state);
+    let state = Arc::new(state);
+    let app = router(state.clone());
+
+    let exp = 4_102_444_800u64;
+    let dispatcher = token(json!({ "sub": "desk", "exp": exp, "roles": ["dispatcher"] }));
+    let courier_id = uuid::Uuid::new_v4();
+    let courier = token(json!({
+        "sub": "gus",
+        "exp": exp,
+        "roles": ["courier"],
+        "courier_id": courier_id,
+    }));
+
+    let res = app
+        .clone()
+        .oneshot(with_token(
+            json_request(
+                "POST",
+                "/orders",
+                json!({
+                    "pickup": { "lat": 52.52, "lng": 13.405 },
+                    "dropoff": { "lat": 52.50, "lng": 13.42 },
+                    "priority": "Normal",
+                    "customer_contact": "+49 30 1234567"
+                }),
+            ),
+            &dispatcher,
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let order_uri = format!("/orders/{}", body_json(res).await["id"].as_str().unwrap());
+
+    for (uri, token, expected) in [
+        (order_uri.as_str(), &courier, StatusCode::FORBIDDEN),
+        (order_uri.as_str(), &dispatcher, StatusCode::OK),
+        ("/couriers", &courier, StatusCode::FORBIDDEN),
+        ("/couriers", &dispatcher, StatusCode::OK),
+        ("/assignments", &courier, StatusCode::OK),
+        (
+            format!("/assignments?courier_id={}", uuid::Uuid::new_v4()).as_str(),
+            &courier,
+            StatusCode::FORBIDDEN,
+        ),
+        (
+            format!("/events/stream?courier_id={}", uuid::Uuid::new_v4()).as_str(),
+            &courier,
+            StatusCode::FORBIDDEN,
+        ),
+        (
+            format!("/events/stream?courier_id={courier_id}").as_str(),
+            &courier,
+            StatusCode::OK,
+        ),
+    ] {
+        let res = app
+            .clone()
+            .oneshot(with_token(get_request(uri), token))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), expected, "GET {uri}");
+    }
+
+    let res = app
+        .clone()
+        .oneshot(get_request("/events/stream"))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let mut subscription = Subscription {
+        topics: HashSet::from([Topic::Assignments, Topic::Reports]),
+        ..Default::default()
+    };
+    let principal = state
+        .jwt
+        .as_ref()
+        .unwrap()
+        .verify_bearer(Some(&courier))
+        .unwrap();
+    subscription.restrict_to(&principal).unwrap();
+    assert_eq!(subscription.courier_id, Some(courier_id));
+    assert_eq!(subscription.topics, HashSet::from([Topic::Assignments]));
+}
+
+#[tokio::test]
+async fn order_creation_is_rate_limited_per_client() {
+    use dispatch_router::rate_limit::{RateLimiter, RateLimits};
+
+    let (mut state, _rx) = AppState::new(1024, 1024);
+    state.rate_limiter = RateLimiter::new(RateLimits {
+        order_create_per_min: 1,
+        write_per_min: 0,
+        read_per_min: 0,
+    });
+    let app = router(Arc::new(state));
+    let order = |api_key: &str| {
+        let mut request = json_request(
+            "POST",
+            "/orders",
+            json!({
+                "pickup": { "lat": 52.52, "lng": 13.405 },
+                "dropoff": { "lat": 52.50, "lng": 13.42 },
+                "priority": "Normal"
+            }),
+        );
+        request
+            .headers_mut()
+            .insert("x-api-key", api_key.parse().unwrap());
+        request
+    };
+
+    let res = app.clone().oneshot(order("first")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = app.clone().oneshot(order("first")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    let retry_after: u64 = res.headers()["retry-after"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!((1..=60).contains(&retry_after));
+
+    let res = app.clone().oneshot(order("second")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = app.oneshot(get_request("/assignments")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn cors_allows_configured_origins_only() {
+    use dispatch_router::api::rest::cors::{layer, CorsSettings};
+
+    let (app, _rx) = setup();
+    let app = app.layer(
+        layer(&CorsSettings {
+            allowed_origins: vec!["https://ops.example.com".to_string()],
+            allowed_methods: vec!["GET".to_string(), "POST".to_string()],
+            max_age: std::time::Duration::from_secs(600),
+        })
+        .unwrap(),
+    );
+    let preflight = |origin: &str| {
+        Request::builder()
+            .method("OPTIONS")
+            .uri("/orders")
+            .header("origin", origin)
+            .header("access-control-request-method", "POST")
+            .header(
+                "access-control-request-headers",
+                "content-type,authorization",
+            )
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let res = app
+        .clone()
+        .oneshot(preflight("https://ops.example.com"))
+        .await
+        .unwrap();
+    assert_eq!(
+        res.headers()["access-control-allow-origin"],
+        "https://ops.example.com"
+    );
+    assert!(res.headers()["access-control-allow-methods"]
+        .to_str()
+        .unwrap()
+        .contains("POST"));
+
+    let res = app
+        .oneshot(preflight("https://evil.example.com"))
+        .await
+        .unwrap();
+    assert!(res.headers().get("access-control-allow-origin").is_none());
+}
+
+#[tokio::test]
+async fn metrics_require_credentials_when_configured() {
+    use dispatch_router::observability::metrics::MetricsAccess;
+
+    let (mut state, _rx) = AppState::new(1024, 1024);
+    state.metrics_access = MetricsAccess {
+        bearer_token: Some("scrape-token".to_string()),
+        basic_auth: Some("prom:secret".to_string()),
+        separate_port: false,
+    };
+    let state = Arc::new(state);
+    let app = router(state.clone());
+    let scrape = |authorization: Option<&str>| {
+        let mut request = Request::builder().method("GET").uri("/metrics");
+        if let Some(value) = authorization {
+            request = request.header("authorization", value);
+        }
+        request.body(Body::empty()).unwrap()
+    };
+
+    let res = app.clone().oneshot(scrape(None)).await.unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    assert!(res.headers().contains_key("www-authenticate"));
+    let res = app
+        .clone()
+        .oneshot(scrape(Some("Bearer wrong")))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    let res = app
+        .clone()
+        .oneshot(scrape(Some("Bearer scrape-token")))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let res = app
+        .oneshot(scrape(Some("Basic cHJvbTpzZWNyZXQ=")))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let (mut state, _rx) = AppState::new(1024, 1024);
+    state.metrics_access.separate_port = true;
+    let state = Arc::new(state);
+    let res = router(state.clone())
+        .oneshot(get_request("/metrics"))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    let tenants = dispatch_router::tenants::Tenants::new([state]);
+    let res = dispatch_router::api::rest::metrics_router(Arc::new(tenants))
+        .oneshot(get_request("/metrics"))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn issued_courier_tokens_cannot_act_on_other_couriers() {
+    use dispatch_router::auth::{JwtSettings, JwtVerifier};
+
+    let (mut state, _rx) = AppState::new(1024, 1024);
+    let verifier = JwtVerifier::new(JwtSettings {
+        jwks_url: "http://idp.test/jwks".to_string(),
+        issuer: None,
+        audience: None,
+        refresh_interval: std::time::Duration::from_secs(300),
+        courier_token_secret: Some("courier-token-secret-for-tests-only".to_string()),
+        courier_token_ttl: std::time::Duration::from_secs(3600),
+    });
+    verifier.set_keys(
+        &serde_json::from_value(json!({
+            "keys": [{ "kty": "oct", "kid": "test", "alg": "HS256", "k": "c2VjcmV0" }]
+        }))
+        .unwrap(),
+    );
+    state.jwt = Some(Arc::new(verifier));
+    let app = router(Arc::new(state));
+    let admin = token(json!({ "sub": "ops-admin", "exp": 4_102_444_800u64, "roles": ["admin"] }));
+
+    let mut couriers = Vec::new();
+    for name in ["Ada", "Bea"] {
+        let res = app
+            .clone()
+            .oneshot(with_token(
+                json_request(
+                    "POST",
+                    "/couriers",
+                    json!({
+                        "name": name,
+                        "location": { "lat": 52.52, "lng": 13.405 },
+                        "capacity": 2,
+                        "rating": 4.5
+                    }),
+                ),
+                &admin,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = body_json(res).await;
+        let id = body["id"].as_str().unwrap().to_string();
+        let bearer = format!("Bearer {}", body["token"]["token"].as_str().unwrap());
+        couriers.push((id, bearer));
+    }
+    let (ada, ada_token) = &couriers[0];
+    let (bea, _) = &couriers[1];
+    let move_to = json!({ "location": { "lat": 52.53, "lng": 13.41 } });
+
+    let res = app
+        .clone()
+        .oneshot(with_token(
+            patch_request(&format!("/couriers/{ada}/location"), move_to.clone()),
+            ada_token,
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = app
+        .clone()
+        .oneshot(with_token(
+            patch_request(&format!("/couriers/{bea}/location"), move_to),
+            ada_token,
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let res = app
+        .clone()
+        .oneshot(with_token(
+            patch_request(
+                &format!("/couriers/{bea}/status"),
+                json!({ "status": "Offline" }),
+            ),
+            ada_token,
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let res = app
+        .oneshot(with_token(
+            json_request("POST", &format!("/couriers/{ada}/token"), json!({})),
+            ada_token,
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn oversized_and_stalled_bodies_are_cut_off() {
+    use dispatch_router::api::rest::limits::{apply, RequestLimits};
+
+    let (app, _rx) = setup();
+    let app = apply(
+        app,
+        &RequestLimits {
+            max_body_bytes: 1024,
+            timeout: Some(std::time::Duration::from_millis(200)),
+            max_concurrent: 8,
+        },
+    );
+
+    let couriers: Vec<Value> = (0..100)
+        .map(|i| {
+            json!({
+                "name": format!("Courier {i}"),
+                "location": { "lat": 52.52, "lng": 13.405 },
+                "capacity": 2,
+                "rating": 4.5
+            })
+        })
+        .collect();
+    let res = app
+        .clone()
+        .oneshot(json_request("POST", "/couriers/bulk", json!(couriers)))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    // A client that sends headers and then never finishes the body.
+    let stalled = Body::from_stream(futures::stream::pending::<Result<Vec<u8>, std::io::Error>>());
+    let res = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/couriers")
+                .header("content-type", "application/json")
+                .body(stalled)
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::REQUEST_TIMEOUT);
+
+    let res = app.oneshot(get_request("/couriers")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn order_search_finds_pending_orders_in_an_area() {
+    let (state, _rx) = AppState::new(1024, 1024);
+    let app = router(Arc::new(state));
+
+    let mut ids = Vec::new();
+    for (lat, lng) in [(52.52, 13.40), (52.49, 13.42), (53.55, 9.99)] {
+        let res = app
+            .clone()
+            .oneshot(json_request(
+                "POST",
+                "/orders",
+                json!({
+                    "pickup": { "lat": lat, "lng": lng },
+                    "dropoff": { "lat": 52.53, "lng": 13.41 },
+                    "priority": "Normal"
+                }),
+            ))
+            .await
+            .unwrap();
+        ids.push(body_json(res).await["id"].as_str().unwrap().to_string());
+    }
+    let found = |body: Value| -> Vec<String> {
+        body.as_array()
+            .unwrap()
+            .iter()
+            .map(|order| order["id"].as_str().unwrap().to_string())
+            .collect()
+    };
+
+    let res = app
+        .clone()
+        .oneshot(get_request("/orders/search?bbox=13.38,52.51,13.41,52.53"))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(found(body_json(res).await), vec![ids[0].clone()]);
+
+    let res = app
+        .clone()
+        .oneshot(get_request(
+            "/orders/search?lat=52.51&lng=13.41&radius_km=5",
+        ))
+        .await
+        .unwrap();
+    assert_eq!(
+        found(body_json(res).await),
+        vec![ids[0].clone(), ids[1].clone()]
+    );
+
+    let res = app
+        .clone()
+        .oneshot(get_request(
+            "/orders/search?bbox=13.30,52.45,13.50,52.60&lat=52.49&lng=13.42&radius_km=1",
+        ))
+        .await
+        .unwrap();
+    assert_eq!(found(body_json(res).await), vec![ids[1].clone()]);
+
+    for uri in [
+        "/orders/search",
+        "/orders/search?bbox=13.41,52.51,13.38",
+        "/orders/search?lat=52.51&lng=13.41",
+    ] {
+        let res = app.clone().oneshot(get_request(uri)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{uri}");
+    }
+}
+
+#[tokio::test]
+async fn tenants_do_not_see_each_others_couriers() {
+    use dispatch_router::api::rest::tenants::tenant_router;
+    use dispatch_router::tenants::{Tenants, DEFAULT_TENANT, TENANT_HEADER};
+
+    let (default, _default_rx) = AppState::new_tenant(DEFAULT_TENANT, 1024, 1024);
+    let (acme, _acme_rx) = AppState::new_tenant("acme", 1024, 1024);
+    let tenants = Arc::new(Tenants::new([Arc::new(default), Arc::new(acme)]));
+    let app = tenant_router(tenants.clone());
+
+    let mut create = json_request(
+        "POST",
+        "/couriers",
+        json!({
+            "name": "Alice",
+            "location": { "lat": 52.52, "lng": 13.405 },
+            "capacity": 5,
+            "rating": 4.5
+        }),
+    );
+    create
+        .headers_mut()
+        .insert(TENANT_HEADER, "acme".parse().unwrap());
+    let res = app.clone().oneshot(create).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let mut list = get_request("/couriers");
+    list.headers_mut()
+        .insert(TENANT_HEADER, "acme".parse().unwrap());
+    let res = app.clone().oneshot(list).await.unwrap();
+    assert_eq!(body_json(res).await.as_array().unwrap().len(), 1);
+
+    // No header is the default tenant, which has no couriers.
+    let res = app.clone().oneshot(get_request("/couriers")).await.unwrap();
+    assert_eq!(body_json(res).await.as_array().unwrap().len(), 0);
+    assert_eq!(tenants.get(Some("acme")).unwrap().couriers.len(), 1);
+    assert!(tenants.default_tenant().couriers.is_empty());
+
+    let mut unknown = get_request("/couriers");
+    unknown
+        .headers_mut()
+        .insert(TENANT_HEADER, "globex".parse().unwrap());
+    let res = app.clone().oneshot(unknown).await.unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    // One scrape covers both, each series labelled with its tenant.
+    let res = app.oneshot(get_request("/metrics")).await.unwrap();
+    let body = body_string(res).await;
+    assert!(body.contains("couriers{status=\"Available\",tenant=\"acme\"} 1"));
+    assert!(body.contains("couriers{status=\"Available\",tenant=\"default\"} 0"));
+}
+
+#[tokio::test]
+async fn regions_dispatch_their_orders_with_their_own_settings() {
+    use dispatch_router::engine::regions::{DispatchRegion, RegionSettings};
+    use dispatch_router::engine::supervisor::supervise_assignment_engine;
+
+    let mitte = GeoPoint {
+        lat: 52.52,
+        lng: 13.405,
+    };
+    let (mut state, rx) = AppState::new(1024, 1024);
+    state.regions.push(DispatchRegion::new(
+        RegionSettings {
+            name: "mitte".to_string(),
+            cells: vec![geohash(&mitte, 6)],
+            scoring: None,
+            max_pickup_km: Some(1.0),
+            stacking_max_detour_km: None,
+        },
+        1024,
+    ));
+    let shared = Arc::new(state);
+    tokio::spawn(supervise_assignment_engine(shared.clone(), rx));
+    let app = router(shared.clone());
+
+    // About 3 km east of the region's pickups.
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/couriers",
+            json!({
+                "name": "Bo",
+                "location": { "lat": 52.52, "lng": 13.45 },
+                "capacity": 3,
+                "rating": 4.5
+            }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let mut order_ids = Vec::new();
+    for pickup in [
+        json!({ "lat": 52.52, "lng": 13.405 }),
+        json!({ "lat": 52.52, "lng": 13.47 }),
+    ] {
+        let res = app
+            .clone()
+            .oneshot(json_request(
+                "POST",
+                "/orders",
+                json!({
+                    "pickup": pickup,
+                    "dropoff": { "lat": 52.50, "lng": 13.42 },
+                    "priority": "Normal"
+                }),
+            ))
+            .await
+            .unwrap();
+        order_ids.push(body_json(res).await["id"].as_str().unwrap().to_string());
+    }
+    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+
+    // The region's 1 km limit keeps the courier off its order; the one
+    // outside it has no limit.
+    let status = |order: Value| order["status"].as_str().unwrap().to_string();
+    let res = app
+        .clone()
+        .oneshot(get_request(&format!("/orders/{}", order_ids[0])))
+        .await
+        .unwrap();
+    assert_eq!(status(body_json(res).await), "Pending");
+    let res = app
+        .oneshot(get_request(&format!("/orders/{}", order_ids[1])))
+        .await
+        .unwrap();
+    assert_eq!(status(body_json(res).await), "Assigned");
+
+    let region = &shared.regions[0];
+    let requeue_delay = shared.tunables().requeue_delay;
+    let health = region
+        .engine
+        .health(region.queue_depth(), shared.clock.now(), requeue_delay);
+    assert!(health.running);
+    assert!(health.last_dequeue_at.is_some());
+    assert_eq!(health.assigned_total, 0);
+    assert_eq!(
+        shared
+            .engine
+            .health(shared.queue_depth(), shared.clock.now(), requeue_delay)
+            .assigned_total,
+        1
+    );
+}
+
+#[tokio::test]
+async fn followers_hold_orders_until_they_lead() {
+    use dispatch_router::engine::supervisor::supervise_assignment_engine;
+
+    let (state, rx) = AppState::new(1024, 1024);
+    let shared = Arc::new(state);
+    shared.leadership.set(false);
+    tokio::spawn(supervise_assignment_engine(shared.clone(), rx));
+    let app = router(shared.clone());
+
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/couriers",
+            json!({
+                "name": "Bo",
+                "location": { "lat": 52.52, "lng": 13.405 },
+                "capacity": 3,
+                "rating": 4.5
+            }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/orders",
+            json!({
+                "pickup": { "lat": 52.52, "lng": 13.41 },
+                "dropoff": { "lat": 52.50, "lng": 13.42 },
+                "priority": "Normal"
+            }),
+        ))
+        .await
+        .unwrap();
+    let order_id = body_json(res).await["id"].as_str().unwrap().to_string();
+    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+    let res = app.clone().oneshot(get_request("/ready")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body_json(res).await["reasons"], json!(["engine_standby"]));
+    assert_eq!(shared.queued_orders(), 1);
+
+    shared.leadership.set(true);
+    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+    let res = app
+        .clone()
+        .oneshot(get_request(&format!("/orders/{order_id}")))
+        .await
+        .unwrap();
+    assert_eq!(body_json(res).await["status"], "Assigned");
+    let res = app.oneshot(get_request("/ready")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn assignments_page_in_the_order_they_were_made() {
+    let (state, rx) = AppState::new(1024, 1024);
+    let shared = Arc::new(state);
+    tokio::spawn(run_assignment_engine(shared.clone(), rx));
+    let app = router(shared);
+
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/couriers",
+            json!({
+                "name": "Bo",
+                "location": { "lat": 52.52, "lng": 13.405 },
+                "capacity": 3,
+                "rating": 4.5
+            }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let mut order_ids = Vec::new();
+    for _ in 0..3 {
+        let res = app
+            .clone()
+            .oneshot(json_request(
+                "POST",
+                "/orders",
+                json!({
+                    "pickup": { "lat": 52.52, "lng": 13.41 },
+                    "dropoff": { "lat": 52.50, "lng": 13.42 },
+                    "priority": "Normal"
+                }),
+            ))
+            .await
+            .unwrap();
+        order_ids.push(body_json(res).await["id"].as_str().unwrap().to_string());
+        tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+    }
+
+    let page = |uri: String| {
+        let app = app.clone();
+        async move {
+            let res = app.oneshot(get_request(&uri)).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            body_json(res).await.as_array().unwrap().clone()
+        }
+    };
+    let first = page("/assignments?limit=2".to_string()).await;
+    let second = page(format!(
+        "/assignments?after={}&limit=2",
+        first[1]["id"].as_str().unwrap()
+    ))
+    .await;
+
+    let paged: Vec<&str> = first
+        .iter()
+        .chain(&second)
+        .map(|assignment| assignment["order_id"].as_str().unwrap())
+        .collect();
+    assert_eq!(paged, order_ids);
+
+    let at = |assignment: &Value| assignment["assigned_at"].as_str().unwrap().to_string();
+    let window = page(format!(
+        "/assignments?from={}&to={}",
+        at(&first[1]),
+        at(&second[0])
+    ))
+    .await;
+    assert_eq!(window.len(), 1);
+    assert_eq!(window[0]["order_id"], order_ids[1].as_str());
+    let courier_id = first[0]["courier_id"].as_str().unwrap();
+    let since = page(format!(
+        "/assignments?from={}&courier_id={courier_id}",
+        at(&first[1])
+    ))
+    .await;
+    assert_eq!(since.len(), 2);
+    let elsewhere = page(format!("/assignments?courier_id={}", uuid::Uuid::now_v7())).await;
+    assert!(elsewhere.is_empty());
+
+    let res = app
+        .clone()
+        .oneshot(get_request(&format!(
+            "/assignments?from={}&to={}",
+            at(&second[0]),
+            at(&first[0])
+        )))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn engine_shards_split_orders_by_pickup_area() {
+    use dispatch_router::engine::regions::DispatchRegion;
+    use dispatch_router::engine::supervisor::supervise_assignment_engine;
+
+    let (mut state, rx) = AppState::new(1024, 1024);
+    state.engine_shards = 8;
+    state
+        .regions
+        .extend((1..8).map(|index| DispatchRegion::shard(index, 1024)));
+    let shared = Arc::new(state);
+    tokio::spawn(supervise_assignment_engine(shared.clone(), rx));
+    let app = router(shared.clone());
+
+    let cities = [
+        GeoPoint {
+            lat: 52.52,
+            lng: 13.405,
+        },
+        GeoPoint {
+            lat: 48.8566,
+            lng: 2.3522,
+        },
+        GeoPoint {
+            lat: 40.4168,
+            lng: -3.7038,
+        },
+    ];
+    for city in &cities {
+        let res = app
+            .clone()
+            .oneshot(json_request(
+                "POST",
+                "/couriers",
+                json!({
+                    "name": "Bo",
+                    "location": { "lat": city.lat, "lng": city.lng },
+                    "capacity": 3,
+                    "rating": 4.5
+                }),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = app
+            .clone()
+            .oneshot(json_request(
+                "POST",
+                "/orders",
+                json!({
+                    "pickup": { "lat": city.lat, "lng": city.lng + 0.005 },
+                    "dropoff": { "lat": city.lat - 0.02, "lng": city.lng },
+                    "priority": "Normal"
+                }),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+
+    // Each city's order went to the engine its area hashes to.
+    let engines: Vec<Option<usize>> = cities.iter().map(|city| shared.region_of(city)).collect();
+    for (city, engine) in cities.iter().zip(&engines) {
+        let same_engine = engines.iter().filter(|other| *other == engine).count() as u64;
+        let health = shared.engine_of(*engine).health(
+            0,
+            shared.clock.now(),
+            shared.tunables().requeue_delay,
+        );
+        assert_eq!(health.assigned_total, same_engine, "{city:?}");
+    }
+    assert!(engines.iter().any(Option::is_some), "{engines:?}");
+}
+
+#[tokio::test]
+async fn stats_sum_up_dispatch_as_it_happens() {
+    let (state, rx) = AppState::new(1024, 1024);
+    let shared = Arc::new(state);
+    tokio::spawn(run_assignment_engine(shared.clone(), rx));
+    let app = router(shared);
+
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/couriers",
+            json!({
+                "name": "Bo",
+                "location": { "lat": 52.52, "lng": 13.405 },
+                "capacity": 2,
+                "rating": 4.5
+            }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let mut order_ids = Vec::new();
+    for _ in 0..2 {
+        let res = app
+            .clone()
+            .oneshot(json_request(
+                "POST",
+                "/orders",
+                json!({
+                    "pickup": { "lat": 52.52, "lng": 13.42 },
+                    "dropoff": { "lat": 52.50, "lng": 13.42 },
+                    "priority": "Normal"
+                }),
+            ))
+            .await
+            .unwrap();
+        order_ids.push(body_json(res).await["id"].as_str().unwrap().to_string());
+    }
+    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+    for status in ["InTransit", "Delivered"] {
+        let res = app
+            .clone()
+            .oneshot(json_request(
+                "PATCH",
+                &format!("/orders/{}/status", order_ids[0]),
+                json!({ "status": status }),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    let res = app.clone().oneshot(get_request("/stats")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let stats = body_json(res).await;
+    assert_eq!(stats["orders_by_status"]["Delivered"], 1);
+    assert_eq!(stats["orders_by_status"]["Assigned"], 1);
+    assert_eq!(stats["orders_by_status"]["Pending"], 0);
+    assert_eq!(stats["assignments"], 2);
+    assert_eq!(stats["success_rate"], 1.0);
+    let pickup_km = stats["mean_pickup_km"].as_f64().unwrap();
+    assert!((0.9..1.1).contains(&pickup_km), "{pickup_km}");
+    assert!(stats["p95_assignment_latency_seconds"].as_f64().is_some());
+    // Loaded to a half, then full, then back to a half on delivery.
+    assert_eq!(stats["utilization"][5], 2);
+    assert_eq!(stats["utilization"][9], 1);
+
+    let res = app.oneshot(get_request("/stats/timeseries")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let series = body_json(res).await;
+    let points = series["points"].as_array().unwrap();
+    assert_eq!(points.len(), 60);
+    let total = |field: &str| -> u64 {
+        points
+            .iter()
+            .map(|point| point[field].as_u64().unwrap())
+            .sum()
+    };
+    assert_eq!((total("orders"), total("assignments")), (2, 2));
+    assert!(points
+        .iter()
+        .any(|point| point["mean_score"].as_f64().is_some()));
+}
+
+#[tokio::test]
+async fn daily_reports_sum_up_the_day_by_zone() {
+    let (state, rx) = AppState::new(1024, 1024);
+    let shared = Arc::new(state);
+    tokio::spawn(run_assignment_engine(shared.clone(), rx));
+    let app = router(shared.clone());
+
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/couriers",
+            json!({
+                "name": "Bo",
+                "location": { "lat": 52.52, "lng": 13.405 },
+                "capacity": 2,
+                "rating": 4.5
+            }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let pickup = GeoPoint {
+        lat: 52.52,
+        lng: 13.42,
+    };
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/orders",
+            json!({
+                "pickup": pickup,
+                "dropoff": { "lat": 52.50, "lng": 13.42 },
+                "priority": "Normal"
+            }),
+        ))
+        .await
+        .unwrap();
+    let order_id = body_json(res).await["id"].as_str().unwrap().to_string();
+    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+    for status in ["InTransit", "Delivered"] {
+        let res = app
+            .clone()
+            .oneshot(json_request(
+                "PATCH",
+                &format!("/orders/{order_id}/status"),
+                json!({ "status": status }),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    let today = chrono::Utc::now().date_naive();
+    let res = app
+        .clone()
+        .oneshot(get_request(&format!("/reports/{today}")))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    dispatch_router::reports::generate_report(&shared, today);
+    let res = app
+        .clone()
+        .oneshot(get_request(&format!("/reports/{today}")))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let report = body_json(res).await;
+    assert_eq!(report["orders"], 1);
+    assert_eq!(report["assignments"], 1);
+    assert_eq!(report["deliveries"], 1);
+    assert_eq!(report["sla_hit_rate"], 1.0);
+    assert_eq!(report["zones"][0]["zone"], zone_of(&pickup).as_str());
+    assert_eq!(report["zones"][0]["orders"], 1);
+
+    let published = shared.events.replay(None, None);
+    assert!(published
+        .iter()
+        .any(|recorded| matches!(recorded.event, DispatchEvent::Report(_))));
+}
+
+#[tokio::test]
+async fn surging_zones_boost_their_orders_priority() {
+    let (state, rx) = AppState::new(1024, 1024);
+    let shared = Arc::new(state);
+    tokio::spawn(run_assignment_engine(shared.clone(), rx));
+    let app = router(shared.clone());
+
+    let pickup = GeoPoint {
+        lat: 52.52,
+        lng: 13.41,
+    };
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/orders",
+            json!({
+                "pickup": pickup,
+                "dropoff": { "lat": 52.50, "lng": 13.42 },
+                "priority": "Normal"
+            }),
+        ))
+        .await
+        .unwrap();
+    let order_id = body_json(res).await["id"].as_str().unwrap().to_string();
+
+    let started = dispatch_router::engine::surge::detect_surges(&shared);
+    assert_eq!(started.len(), 1);
+    let res = app.clone().oneshot(get_request("/surges")).await.unwrap();
+    let surges = body_json(res).await;
+    assert_eq!(surges[0]["zone"], zone_of(&pickup).as_str());
+    assert_eq!(surges[0]["pending_orders"], 1);
+    assert!(shared
+        .events
+        .replay(None, None)
+        .iter()
+        .any(|recorded| matches!(recorded.event, DispatchEvent::Surge(_))));
+
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/couriers",
+            json!({
+                "name": "Bo",
+                "location": { "lat": 52.52, "lng": 13.405 },
+                "capacity": 2,
+                "rating": 4.5
+            }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    tokio::time::sleep(tokio::time::Duration::from_millis(600)).await;
+
+    let res = app
+        .clone()
+        .oneshot(get_request(&format!("/orders/{order_id}/assignment")))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let assignment = body_json(res).await;
+    // Normal priority scores 0.7; the surge adds 0.3.
+    assert_eq!(assignment["score_breakdown"]["priority_score"], 1.0);
+
+    let ended = dispatch_router::engine::surge::detect_surges(&shared);
+    assert!(ended.len() == 1 && !ended[0].surging);
+}
+
+#[tokio::test]
+async fn replay_feeds_a_recorded_log_through_the_engine() {
+    let clock = ManualClock::new(chrono::Utc::now());
+    let (mut state, rx) = AppState::new(1024, 1024);
+    state.clock = Arc::new(clock.clone());
+    let shared = Arc::new(state);
+    tokio::spawn(run_assignment_engine(shared.clone(), rx));
+
+    let (near, far, order) = (
+        uuid::Uuid::now_v7(),
+        uuid::Uuid::now_v7(),
+        uuid::Uuid::now_v7(),
+    );
+    let courier = |id: uuid::Uuid, lng: f64| {
+        json!({
+            "at": "2026-10-16T18:00:00Z",
+            "type": "courier",
+            "id": id,
+            "name": "Replayed",
+            "location": { "lat": 52.52, "lng": lng },
+            "capacity": 1,
+            "rating": 4.5
+        })
+    };
+    let log = [
+        courier(near, 13.405),
+        courier(far, 13.6),
+        json!({
+            "at": "2026-10-16T18:00:02Z",
+            "type": "order",
+            "id": order,
+            "pickup": { "lat": 52.521, "lng": 13.406 },
+            "dropoff": { "lat": 52.53, "lng": 13.42 },
+            "priority": "Normal"
+        }),
+        json!({
+            "at": "2026-10-16T18:00:04Z",
+            "type": "location",
+            "courier_id": uuid::Uuid::now_v7(),
+            "location": { "lat": 52.52, "lng": 13.405 }
+        }),
+        json!({
+            "at": "2026-10-16T18:00:08Z",
+            "type": "status",
+            "order_id": order,
+            "status": "Delivered"
+        }),
+        json!({
+            "at": "2026-10-16T18:00:09Z",
+            "type": "location",
+            "courier_id": near,
+            "location": { "lat": 52.53, "lng": 13.42 }
+        }),
+        json!({
+            "at": "2026-10-16T18:00:10Z",
+            "type": "order",
+            "pickup": { "lat": 52.53, "lng": 13.421 },
+            "dropoff": { "lat": 52.52, "lng": 13.405 },
+            "priority": "High"
+        }),
+    ]
+    .map(|record| record.to_string())
+    .join("\n");
+
+    let records = dispatch_router::replay::parse_log(log.as_bytes()).unwrap();
+    let mut summary = dispatch_router::replay::replay(&shared, &clock, records, 20.0).await;
+    summary
+        .drain(&shared, &clock, 20.0, std::time::Duration::from_secs(2))
+        .await;
+    summary.tally(&shared);
+
+    assert_eq!(summary.records, 7);
+    assert_eq!(summary.skipped, 1);
+    assert_eq!((summary.couriers, summary.orders), (2, 2));
+    assert_eq!((summary.assigned, summary.unassigned), (2, 0));
+    assert!(summary.mean_score.is_some());
+    assert!(summary.mean_pickup_km.unwrap() < 1.0);
+    assert!(summary.mean_wait_seconds.is_some());
+    assert!(shared
+        .assignments
+        .iter()
+        .all(|assignment| assignment.courier_id == near));
+    // Stamped in the log's time, not when the test ran.
+    let at = |time: &str| time.parse::<chrono::DateTime<chrono::Utc>>().unwrap();
+    let log_span = at("2026-10-16T18:00:02Z")..at("2026-10-16T18:01:00Z");
+    assert!(shared
+        .assignments
+        .iter()
+        .all(|assignment| log_span.contains(&assignment.assigned_at)));
+    assert!(summary.mean_wait_seconds.unwrap() < 60.0);
+    assert_eq!(shared.couriers.get(&far).unwrap().current_load, 0.0);
+}
+
+#[tokio::test]
+async fn chaos_mode_drops_events_but_keeps_their_seq() {
+    let (mut state, _rx) = AppState::new(1024, 1024);
+    state.chaos = Arc::new(Chaos::new(
+        ChaosSettings {
+            drop_event_rate: 1.0,
+            ..ChaosSettings::default()
+        },
+        state.metrics.chaos_injections_total.clone(),
+    ));
+    state.events = EventBus::new(1024, 16).with_chaos(state.chaos.clone());
+    let shared = Arc::new(state);
+    let app = router(shared.clone());
+
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/orders",
+            json!({
+                "pickup": { "lat": 52.52, "lng": 13.405 },
+                "dropoff": { "lat": 52.53, "lng": 13.42 },
+                "priority": "Normal"
+            }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    assert!(shared.events.replay(None, None).is_empty());
+    assert_eq!(shared.events.resume_cursor(Some(1)), Some(1));
+    assert_eq!(
+        shared
+            .metrics
+            .chaos_injections_total
+            .with_label_values(&["dropped_event"])
+            .get(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn order_preview_ranks_couriers_without_creating_the_order() {
+    let (state, _rx) = AppState::new(1024, 1024);
+    let shared = Arc::new(state);
+    let app = router(shared.clone());
+
+    let mut ids = Vec::new();
+    for (name, lng, capacity) in [("Far", 13.45, 3), ("Near", 13.406, 3), ("Tiny", 13.405, 1)] {
+        let res = app
+            .clone()
+            .oneshot(json_request(
+                "POST",
+                "/couriers",
+                json!({
+                    "name": name,
+                    "location": { "lat": 52.52, "lng": lng },
+                    "capacity": capacity,
+                    "rating": 4.5
+                }),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        ids.push(body_json(res).await["id"].as_str().unwrap().to_string());
+    }
+
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/orders/preview?limit=1",
+            json!({
+                "pickup": { "lat": 52.52, "lng": 13.405 },
+                "dropoff": { "lat": 52.53, "lng": 13.42 },
+                "priority": "High",
+                "size": 2
+            }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let preview = body_json(res).await;
+    assert_eq!(preview["selected_courier_id"], ids[1].as_str());
+    assert_eq!(preview["eligible"], 2);
+    assert_eq!(preview["rejected"]["capacity"], 1);
+    let candidates = preview["candidates"].as_array().unwrap();
+    assert_eq!(candidates.len(), 1);
+    assert_eq!(candidates[0]["name"], "Near");
+    assert_eq!(candidates[0]["score_breakdown"]["priority_score"], 0.85);
+    assert!(candidates[0]["pickup_km"].as_f64().unwrap() < 0.1);
+
+    assert_eq!(shared.orders.len(), 0);
+    assert_eq!(shared.queue_depth(), 0);
+    assert!(shared.events.replay(None, None).is_empty());
+
+    let res = app
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/orders/preview",
+            json!({
+                "pickup": { "lat": 52.52, "lng": 13.405 },
+                "dropoff": { "lat": 52.53, "lng": 13.42 },
+                "priority": "High",
+                "size": -1
+            }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+}
+
+async fn next_ws_json<S>(socket: &mut S) -> Value
+where
+    S: futures::Stream<
+            Item = Result<
+                tokio_tungstenite::tungstenite::Message,
+                tokio_tungstenite::tungstenite::Error,
+            >,
+        > + Unpin,
+{
+    use futures::StreamExt;
+    use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+    loop {
+        let frame = tokio::time::timeout(std::time::Duration::from_secs(2), socket.next())
+            .await
+            .expect("no frame within 2s")
+            .unwrap()
+            .unwrap();
+        if let WsMessage::Text(text) = frame {
+            return serde_json::from_str(&text).unwrap();
+        }
+    }
+}
+
+#[tokio::test]
+async fn websocket_clients_are_told_when_they_lag_behind_either_stream() {
+    use chrono::Utc;
+    use dispatch_router::models::courier::{CourierLocation, CourierStatus};
+    use dispatch_router::models::order::{OrderEvent, OrderStatus};
+    use futures::SinkExt;
+    use tokio_tungstenite::tungstenite::Message as WsMessage;
+    use uuid::Uuid;
+
+    let (state, _rx) = AppState::new(1024, 4);
+    let shared = Arc::new(state);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let app = router(shared.clone());
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{addr}/ws"))
+        .await
+        .unwrap();
+    socket
+        .send(WsMessage::Text(
+            json!({ "type": "subscribe", "topics": ["orders", "courier_locations"] }).to_string(),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(next_ws_json(&mut socket).await["topic"], "subscribed");
+
+    // Nothing else runs on this thread until the next await, so the
+    // socket's receivers overflow.
+    let courier_id = Uuid::now_v7();
+    for _ in 0..10 {
+        shared
+            .courier_locations_tx
+            .send(CourierLocation {
+                courier_id,
+                location: GeoPoint {
+                    lat: 52.52,
+                    lng: 13.405,
+                },
+                status: CourierStatus::Available,
+                updated_at: Utc::now(),
+                relayed: false,
+            })
+            .unwrap();
+    }
+    let notice = loop {
+        let message = next_ws_json(&mut socket).await;
+        if message["topic"] == "events_dropped" {
+            break message;
+        }
+        assert_eq!(message["topic"], "courier_locations");
+    };
+    assert_eq!(notice["data"]["count"], 6);
+
+    for _ in 0..10 {
+        shared.events.publish(DispatchEvent::Order(OrderEvent {
+            order_id: Uuid::now_v7(),
+            status: OrderStatus::Pending,
+            courier_id: None,
+            pickup: GeoPoint {
+                lat: 52.52,
+                lng: 13.405,
+            },
+            occurred_at: Utc::now(),
+            items: Vec::new(),
+        }));
+    }
+    let notice = loop {
+        let message = next_ws_json(&mut socket).await;
+        if message["topic"] == "events_dropped" {
+            break message;
+        }
+        assert_ne!(message["topic"], "orders", "orders sent before the notice");
+    };
+    assert_eq!(notice["data"]["count"], 6);
+    assert_eq!(
+        shared
+            .metrics
+            .stream_events_dropped_total
+            .with_label_values(&["ws"])
+            .get(),
+        12
+    );
 }
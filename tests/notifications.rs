@@ -0,0 +1,188 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::http::{StatusCode, Uri};
+use axum::routing::post;
+use axum::{Json, Router};
+use chrono::Utc;
+use dispatch_router::models::assignment::{Assignment, ScoreBreakdown};
+use dispatch_router::models::courier::GeoPoint;
+use dispatch_router::models::device::{CourierDevice, PushPlatform};
+use dispatch_router::models::order::{DeliveryOrder, OrderStatus, Priority};
+use dispatch_router::notifications::customer::{
+    run_customer_notifier, CustomerSettings, Templates,
+};
+use dispatch_router::notifications::push::{run_push_notifier, PushSettings};
+use dispatch_router::state::AppState;
+use serde_json::Value;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+/// Starts a gateway that answers every POST with `status`, forwarding the
+/// request path and JSON body.
+async fn gateway(status: StatusCode) -> (String, mpsc::UnboundedReceiver<(String, Value)>) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let app = Router::new().fallback(post(move |uri: Uri, Json(body): Json<Value>| {
+        let tx = tx.clone();
+        async move {
+            let _ = tx.send((uri.path().to_string(), body));
+            status
+        }
+    }));
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    (format!("http://{addr}"), rx)
+}
+
+async fn next_request(requests: &mut mpsc::UnboundedReceiver<(String, Value)>) -> (String, Value) {
+    tokio::time::timeout(Duration::from_secs(2), requests.recv())
+        .await
+        .unwrap()
+        .unwrap()
+}
+
+async fn setup(gateway_url: String, courier_id: Uuid) -> Arc<AppState> {
+    let (state, _rx) = AppState::new(1024, 1024);
+    let state = Arc::new(state);
+    state.courier_devices.insert(
+        courier_id,
+        vec![CourierDevice {
+            courier_id,
+            platform: PushPlatform::Fcm,
+            token: "tok-1".to_string(),
+            registered_at: Utc::now(),
+        }],
+    );
+
+    tokio::spawn(run_push_notifier(
+        state.clone(),
+        PushSettings {
+            gateway_url: format!("{gateway_url}/push"),
+            gateway_token: None,
+        },
+    ));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    state
+}
+
+fn assignment(courier_id: Uuid) -> Assignment {
+    Assignment {
+        id: Uuid::new_v4(),
+        order_id: Uuid::new_v4(),
+        courier_id,
+        score: 0.9,
+        score_breakdown: ScoreBreakdown {
+            distance_score: 0.9,
+            load_score: 1.0,
+            rating_score: 0.9,
+            priority_score: 0.7,
+            zone_score: 0.0,
+            reliability_score: 1.0,
+            idle_score: 1.0,
+            earnings_score: 1.0,
+        },
+        assigned_at: Utc::now(),
+        due_at: Utc::now() + chrono::Duration::minutes(20),
+        delivered_at: None,
+        stacked_with: Vec::new(),
+        cash_on_delivery: None,
+    }
+}
+
+#[tokio::test]
+async fn assignment_notifies_courier_devices() {
+    let courier_id = Uuid::new_v4();
+    let (url, mut requests) = gateway(StatusCode::OK).await;
+    let state = setup(url, courier_id).await;
+
+    let assignment = assignment(courier_id);
+    state.publish_assignment(&assignment);
+
+    let (_, body) = next_request(&mut requests).await;
+    assert_eq!(body["platform"], "fcm");
+    assert_eq!(body["token"], "tok-1");
+    assert_eq!(body["data"]["order_id"], assignment.order_id.to_string());
+}
+
+#[tokio::test]
+async fn gone_tokens_are_unregistered() {
+    let courier_id = Uuid::new_v4();
+    let (url, mut requests) = gateway(StatusCode::GONE).await;
+    let state = setup(url, courier_id).await;
+
+    state.publish_assignment(&assignment(courier_id));
+    next_request(&mut requests).await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    assert!(state.courier_devices.get(&courier_id).unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn order_transitions_notify_customer_callback_and_contact() {
+    let (url, mut requests) = gateway(StatusCode::OK).await;
+    let (state, _rx) = AppState::new(1024, 1024);
+    let state = Arc::new(state);
+
+    tokio::spawn(run_customer_notifier(
+        state.clone(),
+        CustomerSettings {
+            statuses: vec![OrderStatus::Delivered],
+            callback_secret: None,
+            gateway_url: format!("{url}/messages"),
+            templates: Templates::default(),
+        },
+    ));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let mut order = DeliveryOrder {
+        id: Uuid::new_v4(),
+        pickup: GeoPoint {
+            lat: 52.51,
+            lng: 13.39,
+        },
+        dropoff: GeoPoint {
+            lat: 52.54,
+            lng: 13.42,
+        },
+        extra_pickups: Vec::new(),
+        priority: Priority::Normal,
+        status: OrderStatus::InTransit,
+        assigned_courier: None,
+        created_at: Utc::now(),
+        size: 1.0,
+        items: Vec::new(),
+        cash_on_delivery: None,
+        required_vehicle: None,
+        required_skills: Vec::new(),
+        handling: Vec::new(),
+        waiting_reason: None,
+        declined_by: Vec::new(),
+        callback_url: Some(format!("{url}/callback")),
+        customer_contact: Some("+4915112345678".to_string()),
+        request_id: None,
+    };
+    state.orders.insert(order.id, order.clone());
+    // Not a configured status, so nothing is sent for it.
+    state.publish_order_event(Some(&OrderStatus::Assigned), &order);
+
+    order.status = OrderStatus::Delivered;
+    state.orders.insert(order.id, order.clone());
+    state.publish_order_event(Some(&OrderStatus::InTransit), &order);
+
+    let mut received = [
+        next_request(&mut requests).await,
+        next_request(&mut requests).await,
+    ];
+    received.sort_by(|a, b| a.0.cmp(&b.0));
+
+    assert_eq!(received[0].0, "/callback");
+    assert_eq!(received[0].1["status"], "Delivered");
+    assert_eq!(received[1].0, "/messages");
+    assert_eq!(received[1].1["contact"], "+4915112345678");
+    assert_eq!(received[1].1["message"], "Your order has been delivered.");
+    assert!(requests.try_recv().is_err());
+}
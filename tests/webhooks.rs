@@ -0,0 +1,181 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::body::Bytes;
+use axum::http::{HeaderMap, StatusCode};
+use axum::routing::post;
+use axum::Router;
+use chrono::Utc;
+use dispatch_router::models::courier::GeoPoint;
+use dispatch_router::models::event::Topic;
+use dispatch_router::models::order::{DeliveryOrder, OrderStatus, Priority};
+use dispatch_router::models::webhook::{DeadLetter, Webhook};
+use dispatch_router::state::AppState;
+use dispatch_router::webhooks::{
+    run_webhook_dispatcher, sign, store_dead_letter, DeliverySettings, EVENT_HEADER,
+    SIGNATURE_HEADER,
+};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+const SECRET: &str = "s3cret";
+
+/// Starts a receiver that answers 500 to the first `failures` requests and
+/// 200 afterwards, forwarding every request it sees.
+async fn receiver(failures: usize) -> (String, mpsc::UnboundedReceiver<(HeaderMap, Bytes)>) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let seen = Arc::new(AtomicUsize::new(0));
+
+    let app = Router::new().route(
+        "/hook",
+        post(move |headers: HeaderMap, body: Bytes| {
+            let tx = tx.clone();
+            let seen = seen.clone();
+            async move {
+                let _ = tx.send((headers, body));
+                if seen.fetch_add(1, Ordering::SeqCst) < failures {
+                    StatusCode::INTERNAL_SERVER_ERROR
+                } else {
+                    StatusCode::OK
+                }
+            }
+        }),
+    );
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    (format!("http://{addr}/hook"), rx)
+}
+
+async fn setup(url: String, max_attempts: u32) -> Arc<AppState> {
+    let (state, _rx) = AppState::new(1024, 1024);
+    let state = Arc::new(state);
+    let webhook = Webhook {
+        id: Uuid::new_v4(),
+        url,
+        secret: SECRET.to_string(),
+        event_types: vec![Topic::Orders],
+        created_at: Utc::now(),
+    };
+    state.webhooks.insert(webhook.id, webhook);
+
+    tokio::spawn(run_webhook_dispatcher(
+        state.clone(),
+        DeliverySettings {
+            max_attempts,
+            retry_base: Duration::from_millis(10),
+            timeout: Duration::from_secs(2),
+            dead_letter_limit: 100,
+        },
+    ));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    state
+}
+
+fn publish_order(state: &AppState) {
+    state.publish_order_event(
+        None,
+        &DeliveryOrder {
+            id: Uuid::new_v4(),
+            pickup: GeoPoint {
+                lat: 52.51,
+                lng: 13.39,
+            },
+            dropoff: GeoPoint {
+                lat: 52.54,
+                lng: 13.42,
+            },
+            extra_pickups: Vec::new(),
+            priority: Priority::Normal,
+            status: OrderStatus::Pending,
+            assigned_courier: None,
+            created_at: Utc::now(),
+            size: 1.0,
+            items: Vec::new(),
+            cash_on_delivery: None,
+            required_vehicle: None,
+            required_skills: Vec::new(),
+            handling: Vec::new(),
+            waiting_reason: None,
+            declined_by: Vec::new(),
+            callback_url: None,
+            customer_contact: None,
+            request_id: None,
+        },
+    );
+}
+
+#[tokio::test]
+async fn delivers_signed_event_after_retry() {
+    let (url, mut requests) = receiver(1).await;
+    let state = setup(url, 3).await;
+
+    publish_order(&state);
+
+    for _ in 0..2 {
+        let (headers, body) = tokio::time::timeout(Duration::from_secs(2), requests.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(headers[SIGNATURE_HEADER], sign(SECRET, &body));
+        assert_eq!(headers[EVENT_HEADER], "orders");
+
+        let payload: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(payload["topic"], "orders");
+        assert_eq!(payload["seq"], 1);
+    }
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(state.webhook_dead_letters.is_empty());
+}
+
+#[tokio::test]
+async fn exhausted_retries_are_dead_lettered() {
+    let (url, _requests) = receiver(usize::MAX).await;
+    let state = setup(url, 2).await;
+
+    publish_order(&state);
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    let dead_letter = state.webhook_dead_letters.iter().next().unwrap().clone();
+    assert_eq!(dead_letter.attempts, 2);
+    assert_eq!(dead_letter.event_seq, 1);
+    assert_eq!(dead_letter.payload["topic"], "orders");
+    assert!(dead_letter.last_error.contains("500"));
+}
+
+#[tokio::test]
+async fn dead_letters_beyond_the_limit_evict_the_oldest() {
+    let (state, _rx) = AppState::new(16, 16);
+    let webhook_id = Uuid::new_v4();
+    let started = Utc::now();
+    for seq in 1..=5 {
+        store_dead_letter(
+            &state,
+            DeadLetter {
+                id: Uuid::new_v4(),
+                webhook_id,
+                event_seq: seq,
+                topic: Topic::Orders,
+                payload: serde_json::Value::Null,
+                attempts: 3,
+                last_error: "HTTP 500".to_string(),
+                failed_at: started + chrono::Duration::seconds(seq as i64),
+            },
+            3,
+        );
+    }
+
+    let mut kept: Vec<u64> = state
+        .webhook_dead_letters
+        .iter()
+        .map(|entry| entry.event_seq)
+        .collect();
+    kept.sort();
+    assert_eq!(kept, vec![3, 4, 5]);
+    assert_eq!(state.metrics.webhook_dead_letters_evicted_total.get(), 2);
+}